@@ -0,0 +1,133 @@
+//! Sprites of all peers in a session moving in sync, built with [`WasmPeersPlugin`] in many-to-many topology.
+//!
+//! Each peer moves its own sprite around a circle and sends its position to others every few frames,
+//! positions of their sprites are updated from received messages. Rendering is left out to keep
+//! dependencies small, an app built on `bevy` would copy `Position` into `Transform` of a `SpriteBundle`,
+//! here sprite positions are logged to the console instead.
+//!
+//! Requires a signaling server from accompanying crate listening on port 9001, build with e.g.
+//! `trunk` or `wasm-pack` and open the page in two browser tabs.
+
+use std::time::Duration;
+
+use bevy_app::{App, ScheduleRunnerPlugin, Update};
+use bevy_ecs::prelude::*;
+use serde::{Deserialize, Serialize};
+use wasm_peers::bevy::{
+    PeerConnected, PeerDisconnected, PeerMessage, PeerSender, Topology, WasmPeersPlugin,
+};
+use wasm_peers::{ConnectionType, SessionId, UserId};
+
+const SIGNALING_SERVER_URL: &str = "ws://0.0.0.0:9001/many-to-many";
+/// Frames between sending position of the local sprite
+const SEND_INTERVAL: u32 = 6;
+
+#[derive(Component, Debug, Clone, Copy, Default, Serialize, Deserialize)]
+struct Position {
+    x: f32,
+    y: f32,
+}
+
+/// Sprite moved by this peer.
+#[derive(Component)]
+struct LocalSprite;
+
+/// Sprite moved by another peer.
+#[derive(Component)]
+struct RemoteSprite(UserId);
+
+#[derive(Resource, Default)]
+struct Frame(u32);
+
+fn spawn_local_sprite(mut commands: Commands) {
+    commands.spawn((LocalSprite, Position::default()));
+}
+
+fn move_local_sprite(
+    mut frame: ResMut<Frame>,
+    mut sprites: Query<&mut Position, With<LocalSprite>>,
+    sender: PeerSender<Position>,
+) {
+    frame.0 = frame.0.saturating_add(1);
+    #[allow(clippy::cast_precision_loss)]
+    let angle = frame.0 as f32 / 60.0;
+    for mut position in &mut sprites {
+        *position = Position {
+            x: angle.cos() * 100.0,
+            y: angle.sin() * 100.0,
+        };
+        if frame.0 % SEND_INTERVAL == 0 {
+            if let Err(err) = sender.send_to_all(&*position) {
+                log::error!("failed to send position: {:?}", err);
+            }
+        }
+    }
+}
+
+fn spawn_remote_sprites(mut commands: Commands, mut connected: EventReader<PeerConnected>) {
+    for &PeerConnected(user_id) in connected.read() {
+        commands.spawn((RemoteSprite(user_id), Position::default()));
+    }
+}
+
+fn despawn_remote_sprites(
+    mut commands: Commands,
+    mut disconnected: EventReader<PeerDisconnected>,
+    sprites: Query<(Entity, &RemoteSprite)>,
+) {
+    for &PeerDisconnected(user_id) in disconnected.read() {
+        for (entity, &RemoteSprite(owner)) in &sprites {
+            if owner == user_id {
+                commands.entity(entity).despawn();
+            }
+        }
+    }
+}
+
+fn move_remote_sprites(
+    mut messages: EventReader<PeerMessage<Position>>,
+    mut sprites: Query<(&RemoteSprite, &mut Position)>,
+) {
+    for &PeerMessage(user_id, received) in messages.read() {
+        for (&RemoteSprite(owner), mut position) in &mut sprites {
+            if owner == user_id {
+                *position = received;
+            }
+        }
+    }
+}
+
+fn log_remote_sprites(frame: Res<Frame>, sprites: Query<(&RemoteSprite, &Position)>) {
+    if frame.0 % 60 != 0 {
+        return;
+    }
+    for (&RemoteSprite(owner), position) in &sprites {
+        log::info!("sprite of {} is at {:?}", owner, position);
+    }
+}
+
+fn main() {
+    App::new()
+        .add_plugins(ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(
+            1.0 / 60.0,
+        )))
+        .add_plugins(WasmPeersPlugin::<Position>::new(
+            SIGNALING_SERVER_URL,
+            SessionId::new(1),
+            ConnectionType::Local,
+            Topology::ManyToMany,
+        ))
+        .init_resource::<Frame>()
+        .add_systems(bevy_app::Startup, spawn_local_sprite)
+        .add_systems(
+            Update,
+            (
+                move_local_sprite,
+                spawn_remote_sprites,
+                despawn_remote_sprites,
+                move_remote_sprites,
+                log_remote_sprites,
+            ),
+        )
+        .run();
+}
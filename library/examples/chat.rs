@@ -0,0 +1,170 @@
+//! Minimal chat between all peers in a session, built with [`many_to_many::NetworkManager`] and plain DOM.
+//!
+//! First peer to open the page generates a session id and puts it in the URL with [`SessionBootstrap`],
+//! others join the same session by opening the shared URL. Nickname of each user is kept in local storage.
+//!
+//! Requires a signaling server from accompanying crate listening on port 9001, build with e.g.
+//! `trunk` or `wasm-pack` and open the page in a few browser tabs.
+
+use js_sys::Date;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_peers::many_to_many::NetworkManager;
+use wasm_peers::session_bootstrap::SessionBootstrap;
+use wasm_peers::{ConnectionType, UserId};
+use web_sys::{Document, HtmlInputElement, Window};
+
+const SIGNALING_SERVER_URL: &str = "ws://0.0.0.0:9001/many-to-many";
+const STUN_SERVER_URL: &str = "stun:openrelay.metered.ca:80";
+const NICKNAME_KEY: &str = "wasm-peers-chat-nickname";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ChatMessage {
+    author: String,
+    text: String,
+    /// Milliseconds since the Unix epoch, as returned by `Date.now()`
+    timestamp: f64,
+}
+
+/// Everything peers send to each other.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum ChatEvent {
+    /// Sent to each peer once connection with it opens
+    Joined {
+        nickname: String,
+    },
+    Message(ChatMessage),
+}
+
+/// Nickname from local storage, asked for and saved there the first time.
+fn nickname(window: &Window) -> Result<String, JsValue> {
+    let storage = window
+        .local_storage()?
+        .ok_or_else(|| JsValue::from_str("no local storage"))?;
+    if let Some(nickname) = storage.get_item(NICKNAME_KEY)? {
+        return Ok(nickname);
+    }
+    let nickname = window
+        .prompt_with_message("Your nickname")?
+        .filter(|nickname| !nickname.trim().is_empty())
+        .unwrap_or_else(|| "anonymous".to_owned());
+    storage.set_item(NICKNAME_KEY, &nickname)?;
+    Ok(nickname)
+}
+
+fn show(document: &Document, line: &str) -> Result<(), JsValue> {
+    let messages = document
+        .get_element_by_id("messages")
+        .ok_or_else(|| JsValue::from_str("no messages list"))?;
+    let item = document.create_element("li")?;
+    item.set_text_content(Some(line));
+    messages.append_child(&item)?;
+    Ok(())
+}
+
+fn show_event(document: &Document, user_id: UserId, event: ChatEvent) {
+    let line = match event {
+        ChatEvent::Joined { nickname } => format!("{} ({}) joined", nickname, user_id),
+        ChatEvent::Message(message) => {
+            let time = Date::new(&message.timestamp.into()).to_locale_time_string("default");
+            format!(
+                "[{}] {}: {}",
+                String::from(time),
+                message.author,
+                message.text
+            )
+        }
+    };
+    if let Err(err) = show(document, &line) {
+        log::error!("failed to show message: {:?}", err);
+    }
+}
+
+fn build_page(document: &Document) -> Result<HtmlInputElement, JsValue> {
+    let body = document
+        .body()
+        .ok_or_else(|| JsValue::from_str("no document body"))?;
+    let share = document.create_element("p")?;
+    share.set_text_content(Some("Share the URL of this page to invite others."));
+    body.append_child(&share)?;
+    let messages = document.create_element("ul")?;
+    messages.set_id("messages");
+    body.append_child(&messages)?;
+    let input: HtmlInputElement = document.create_element("input")?.dyn_into()?;
+    input.set_placeholder("Type a message and press Enter");
+    body.append_child(&input)?;
+    Ok(input)
+}
+
+fn start() -> Result<(), JsValue> {
+    let window = web_sys::window().ok_or_else(|| JsValue::from_str("no global window object"))?;
+    let document = window
+        .document()
+        .ok_or_else(|| JsValue::from_str("no document"))?;
+    let session_id = SessionBootstrap::from_location()
+        .map_err(|err| JsValue::from_str(&err.to_string()))?
+        .session_id();
+    let nickname = nickname(&window)?;
+    let input = build_page(&document)?;
+
+    let mut network_manager = NetworkManager::new(
+        SIGNALING_SERVER_URL,
+        session_id,
+        ConnectionType::Stun {
+            urls: STUN_SERVER_URL.to_owned(),
+        },
+    )
+    .map_err(|err| JsValue::from_str(&err.to_string()))?;
+
+    let on_open = {
+        let network_manager = network_manager.clone();
+        let nickname = nickname.clone();
+        move |user_id| {
+            let joined = ChatEvent::Joined {
+                nickname: nickname.clone(),
+            };
+            if let Err(err) = network_manager.send_message(user_id, &joined) {
+                log::error!("failed to introduce to {}: {:?}", user_id, err);
+            }
+        }
+    };
+    let on_message = {
+        let document = document.clone();
+        move |user_id, event: ChatEvent| show_event(&document, user_id, event)
+    };
+    network_manager
+        .start(on_open, on_message)
+        .map_err(|err| JsValue::from_str(&err.to_string()))?;
+
+    let on_keydown = Closure::<dyn FnMut(web_sys::KeyboardEvent)>::new({
+        let input = input.clone();
+        move |event: web_sys::KeyboardEvent| {
+            if event.key() != "Enter" || input.value().is_empty() {
+                return;
+            }
+            let message = ChatMessage {
+                author: nickname.clone(),
+                text: input.value(),
+                timestamp: Date::now(),
+            };
+            if let Err(err) = show(&document, &format!("you: {}", message.text)) {
+                log::error!("failed to show message: {:?}", err);
+            }
+            if let Err(err) = network_manager.send_message_to_all(&ChatEvent::Message(message)) {
+                log::error!("failed to send message: {:?}", err);
+            }
+            input.set_value("");
+        }
+    });
+    input.add_event_listener_with_callback("keydown", on_keydown.as_ref().unchecked_ref())?;
+    // handler lives as long as the page
+    on_keydown.forget();
+    Ok(())
+}
+
+fn main() {
+    if let Err(err) = start() {
+        log::error!("failed to start chat: {:?}", err);
+    }
+}
@@ -0,0 +1,31 @@
+//! Native peer that answers every message with `pong!`,
+//! browser tests use it to check interoperability with native peers.
+//!
+//! Requires a signaling server running on 0.0.0.0:9001.
+
+use wasm_peers::one_to_one::native::NetworkManager;
+use wasm_peers::{ConnectionType, SessionId};
+
+const SIGNALING_SERVER_URL: &str = "ws://0.0.0.0:9001/one-to-one";
+/// Session joined by browser peer in `tests/one_to_one.rs`
+const SESSION_ID: SessionId = SessionId::new(5678);
+
+#[tokio::main]
+async fn main() -> wasm_peers::Result<()> {
+    let mut peer =
+        NetworkManager::new_async(SIGNALING_SERVER_URL, SESSION_ID, ConnectionType::Local).await?;
+    let peer_clone = peer.clone();
+    peer.start(
+        || println!("browser peer connected"),
+        move |message: String| {
+            println!("received message: {}", message);
+            if let Err(err) = peer_clone.send_message("pong!") {
+                eprintln!("failed to send pong: {}", err);
+            }
+        },
+    )
+    .await?;
+    tokio::signal::ctrl_c().await?;
+    peer.close().await;
+    Ok(())
+}
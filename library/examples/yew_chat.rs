@@ -0,0 +1,62 @@
+//! Minimal chat between all peers in a session, built with [`use_many_to_many`] hook.
+//!
+//! Requires a signaling server from accompanying crate listening on port 9001, build with e.g.
+//! `trunk` or `wasm-pack` with `yew` feature enabled and open the page in a few browser tabs.
+
+use wasm_peers::hooks::use_many_to_many;
+use wasm_peers::{ConnectionType, SessionId};
+use web_sys::HtmlInputElement;
+use yew::{function_component, html, use_node_ref, Callback, Html, SubmitEvent};
+
+const SIGNALING_SERVER_URL: &str = "ws://0.0.0.0:9001/one-to-many";
+const STUN_SERVER_URL: &str = "stun:openrelay.metered.ca:80";
+
+#[function_component(Chat)]
+fn chat() -> Html {
+    let peers = use_many_to_many::<String>(
+        SIGNALING_SERVER_URL,
+        SessionId::new(1),
+        ConnectionType::Stun {
+            urls: STUN_SERVER_URL.to_owned(),
+        },
+    );
+    let input_ref = use_node_ref();
+
+    let on_submit = {
+        let peers = peers.clone();
+        let input_ref = input_ref.clone();
+        Callback::from(move |event: SubmitEvent| {
+            event.prevent_default();
+            let Some(input) = input_ref.cast::<HtmlInputElement>() else {
+                return;
+            };
+            if let Err(err) = peers.send_to_all(&input.value()) {
+                log::error!("failed to send message: {:?}", err);
+            }
+            input.set_value("");
+        })
+    };
+
+    let state = peers.state();
+    html! {
+        <main>
+            <p>{ format!("connected peers: {}", state.open_peers.len()) }</p>
+            if let Some(ref error) = state.signaling_error {
+                <p>{ format!("signaling error: {}", error) }</p>
+            }
+            <ul>
+                { for state.messages.iter().map(|(user_id, message)| html! {
+                    <li>{ format!("{user_id}: {message}") }</li>
+                }) }
+            </ul>
+            <form onsubmit={on_submit}>
+                <input ref={input_ref} />
+                <button type="submit">{ "send" }</button>
+            </form>
+        </main>
+    }
+}
+
+fn main() {
+    yew::Renderer::<Chat>::new().render();
+}
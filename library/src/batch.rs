@@ -0,0 +1,276 @@
+/*!
+Batching of messages sent to a peer in quick succession into a single data channel message,
+enabled with [`DataChannelConfig::batching`](crate::DataChannelConfig::batching).
+
+Batches are binary frames starting with `0xc1`, like relayed messages, followed by `0xfe`
+and serialized messages, each preceded by its length as 4 big-endian bytes.
+Received batches are unpacked whether batching is enabled or not, so only sending peers have to enable it.
+*/
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fmt::Debug;
+use std::future::Future;
+use std::hash::Hash;
+use std::rc::Rc;
+
+use anyhow::anyhow;
+use js_sys::{Promise, Uint8Array};
+use log::{debug, error};
+use wasm_bindgen::JsValue;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::RtcDataChannel;
+
+use crate::utils::{has_frame_marker, send_serialized_message, serialized_message_to_js, sleep};
+use crate::{BatchConfig, DataChannelConfig};
+
+/// Never starts a `MessagePack` message, second byte tells it apart from relay frames
+const BATCH_MARKER: [u8; 2] = [0xc1, 0xfe];
+/// Length preceding each message in a batch
+const LENGTH_LEN: usize = 4;
+
+fn to_frame(messages: &[Vec<u8>]) -> Vec<u8> {
+    let mut frame = BATCH_MARKER.to_vec();
+    for message in messages {
+        let len = u32::try_from(message.len()).unwrap_or(u32::MAX);
+        frame.extend_from_slice(&len.to_be_bytes());
+        frame.extend_from_slice(message);
+    }
+    frame
+}
+
+/// Messages carried by a batch frame, `None` if it's malformed.
+fn parse_frame(bytes: &[u8]) -> Option<Vec<&[u8]>> {
+    let mut rest = bytes.strip_prefix(&BATCH_MARKER[..])?;
+    let mut messages = Vec::new();
+    while !rest.is_empty() {
+        let len = u32::from_be_bytes(rest.get(..LENGTH_LEN)?.try_into().ok()?);
+        let end = LENGTH_LEN.checked_add(usize::try_from(len).ok()?)?;
+        messages.push(rest.get(LENGTH_LEN..end)?);
+        rest = rest.get(end..)?;
+    }
+    Some(messages)
+}
+
+/// Data of each message received on a data channel, in the form user messages are received in.
+/// It's just `data` unless it's a batch.
+pub fn unpack(data: JsValue) -> Vec<JsValue> {
+    if !has_frame_marker(&data, BATCH_MARKER) {
+        return vec![data];
+    }
+    let bytes = Uint8Array::new(&data).to_vec();
+    parse_frame(&bytes).map_or_else(
+        || {
+            error!("dropping malformed batch of messages");
+            Vec::new()
+        },
+        |messages| messages.into_iter().map(serialized_message_to_js).collect(),
+    )
+}
+
+/// Serialized messages waiting to be sent to a single peer.
+#[derive(Debug)]
+struct PendingBatch {
+    data_channel: RtcDataChannel,
+    messages: Vec<Vec<u8>>,
+    /// Size of the batch frame with all of the messages
+    size: usize,
+}
+
+impl PendingBatch {
+    fn new(data_channel: RtcDataChannel) -> Self {
+        Self {
+            data_channel,
+            messages: Vec::new(),
+            size: BATCH_MARKER.len(),
+        }
+    }
+
+    fn size_with(&self, message: &[u8]) -> usize {
+        self.size
+            .saturating_add(LENGTH_LEN)
+            .saturating_add(message.len())
+    }
+
+    fn push(&mut self, message: &[u8]) {
+        self.size = self.size_with(message);
+        self.messages.push(message.to_vec());
+    }
+
+    /// Sends a single message on its own, as it would be sent without batching.
+    fn send(self) -> crate::Result<()> {
+        match *self.messages.as_slice() {
+            [] => Ok(()),
+            [ref message] => send_serialized_message(&self.data_channel, message),
+            _ => self
+                .data_channel
+                .send_with_u8_array(&to_frame(&self.messages))
+                .map_err(|err| anyhow!("failed to send batch of messages: {:?}", err)),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct BatcherInner<Id> {
+    batches: HashMap<Id, PendingBatch>,
+    /// Set while a flush is scheduled, to a value that's never reused, so that a flush
+    /// scheduled earlier doesn't send batches that were started after it
+    scheduled_flush: Option<u64>,
+    next_flush: u64,
+}
+
+/// Batches of messages waiting to be sent, shared by all data channels of a network manager.
+#[derive(Debug, Clone)]
+pub struct Batcher<Id> {
+    inner: Rc<RefCell<BatcherInner<Id>>>,
+}
+
+impl<Id> Default for Batcher<Id> {
+    fn default() -> Self {
+        Self {
+            inner: Rc::new(RefCell::new(BatcherInner {
+                batches: HashMap::new(),
+                scheduled_flush: None,
+                next_flush: 0,
+            })),
+        }
+    }
+}
+
+impl<Id: Clone + Eq + Hash + Debug + 'static> Batcher<Id> {
+    /// Adds serialized `message` to the batch for `peer`, sending the batch first if the message doesn't fit.
+    /// Sends the message right away if batching isn't enabled in `config`.
+    ///
+    /// # Errors
+    /// This function errs if sending the previous batch or the message failed.
+    pub fn push(
+        &self,
+        peer: Id,
+        data_channel: &RtcDataChannel,
+        message: &[u8],
+        config: &DataChannelConfig,
+    ) -> crate::Result<()> {
+        let Some(ref batching) = config.batching else {
+            return send_serialized_message(data_channel, message);
+        };
+        let max_bytes = config.max_batch_bytes(batching);
+        let sent_first = {
+            let inner = self.inner.borrow();
+            inner.batches.get(&peer).map_or(false, |batch| {
+                batch.data_channel != *data_channel || batch.size_with(message) > max_bytes
+            })
+        };
+        if sent_first {
+            self.flush_peer(&peer)?;
+        }
+        if PendingBatch::new(data_channel.clone()).size_with(message) > max_bytes {
+            return send_serialized_message(data_channel, message);
+        }
+        self.inner
+            .borrow_mut()
+            .batches
+            .entry(peer)
+            .or_insert_with(|| PendingBatch::new(data_channel.clone()))
+            .push(message);
+        self.schedule_flush(batching);
+        Ok(())
+    }
+
+    fn flush_peer(&self, peer: &Id) -> crate::Result<()> {
+        let batch = self.inner.borrow_mut().batches.remove(peer);
+        batch.map_or(Ok(()), PendingBatch::send)
+    }
+
+    /// Sends batches of all peers right away, returning those whose batch couldn't be sent.
+    pub fn flush(&self) -> Vec<(Id, crate::Error)> {
+        let batches: Vec<(Id, PendingBatch)> = {
+            let mut inner = self.inner.borrow_mut();
+            inner.scheduled_flush = None;
+            inner.batches.drain().collect()
+        };
+        batches
+            .into_iter()
+            .filter_map(|(peer, batch)| batch.send().err().map(|err| (peer, err)))
+            .collect()
+    }
+
+    /// Drops batch waiting for `peer`, once it left.
+    #[cfg(feature = "one-to-many")]
+    pub fn forget(&self, peer: &Id) {
+        self.inner.borrow_mut().batches.remove(peer);
+    }
+
+    /// Flushes batches on the next animation frame, or after `max_latency` if it comes sooner.
+    fn schedule_flush(&self, config: &BatchConfig) {
+        let flush = {
+            let mut inner = self.inner.borrow_mut();
+            if inner.scheduled_flush.is_some() {
+                return;
+            }
+            let flush = inner.next_flush;
+            inner.next_flush = flush.wrapping_add(1);
+            inner.scheduled_flush = Some(flush);
+            flush
+        };
+        self.flush_after(flush, next_animation_frame());
+        self.flush_after(flush, sleep(config.max_latency));
+    }
+
+    fn flush_after(&self, flush: u64, wait: impl Future<Output = crate::Result<()>> + 'static) {
+        let batcher = self.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            if let Err(err) = wait.await {
+                debug!("flushing batches right away: {}", err);
+            }
+            if batcher.inner.borrow().scheduled_flush != Some(flush) {
+                return;
+            }
+            for (peer, err) in batcher.flush() {
+                error!("failed to send batch of messages to {:?}: {}", peer, err);
+            }
+        });
+    }
+}
+
+/// Resolves on the next animation frame, errs outside of a browser window, e.g. in Node.js.
+async fn next_animation_frame() -> crate::Result<()> {
+    let window = web_sys::window().ok_or_else(|| anyhow!("no global window object"))?;
+    let mut result = Ok(0);
+    let frame = Promise::new(&mut |resolve, _reject| {
+        result = window.request_animation_frame(&resolve);
+    });
+    result.map_err(|err| anyhow!("failed to request animation frame: {:?}", err))?;
+    JsFuture::from(frame)
+        .await
+        .map(|_| ())
+        .map_err(|err| anyhow!("animation frame failed: {:?}", err))
+}
+
+#[cfg(test)]
+mod test {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+
+    #[wasm_bindgen_test]
+    fn test_batch_frames_round_trip() {
+        let messages = vec![vec![0x92, 0x01, 0x02], Vec::new(), vec![0xa1, 0x61]];
+        let frame = to_frame(&messages);
+        assert_eq!(
+            parse_frame(&frame),
+            Some(messages.iter().map(Vec::as_slice).collect())
+        );
+        assert_eq!(parse_frame(&to_frame(&[])), Some(Vec::new()));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_malformed_batches_are_rejected() {
+        let frame = to_frame(&[vec![0x92, 0x01, 0x02]]);
+        let truncated = frame
+            .get(..frame.len().saturating_sub(1))
+            .unwrap_or_default();
+        assert_eq!(parse_frame(truncated), None);
+        assert_eq!(parse_frame(&[0xc1, 0x00, 0x00]), None);
+    }
+}
@@ -0,0 +1,380 @@
+/*!
+Plugin bridging network managers into [Bevy](https://bevyengine.org) ECS, available with `bevy` feature.
+
+[`WasmPeersPlugin`] connects to signaling server when it's added to the app and buffers [`PeerEvent`]s
+received from then on. Each frame, before `Update`, the buffer is drained into Bevy events:
+[`PeerConnected`], [`PeerDisconnected`], [`PeerMessage`] and [`PeerSignalingError`],
+which systems read with `EventReader`. Messages are sent with the [`PeerSender`] system param,
+and open connections are kept in the [`PeerConnections`] non-send resource.
+
+Network managers aren't `Send`, so systems using them run on the main thread,
+which is the only one in the browser anyway.
+
+# Example
+
+```
+use bevy_app::{App, Update};
+use bevy_ecs::prelude::*;
+use wasm_peers::bevy::{PeerMessage, PeerSender, Topology, WasmPeersPlugin};
+use wasm_peers::{ConnectionType, SessionId};
+
+fn echo(mut messages: EventReader<PeerMessage<String>>, sender: PeerSender<String>) {
+    for &PeerMessage(user_id, ref message) in messages.read() {
+        let _result = sender.send_to(user_id, message);
+    }
+}
+
+App::new()
+    .add_plugins(WasmPeersPlugin::<String>::new(
+        "ws://0.0.0.0:9001/many-to-many",
+        SessionId::new(1),
+        ConnectionType::Local,
+        Topology::ManyToMany,
+    ))
+    .add_systems(Update, echo);
+```
+*/
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::marker::PhantomData;
+use std::rc::Rc;
+
+use bevy_app::{App, Plugin, PreUpdate};
+use bevy_ecs::event::{Event, EventWriter};
+use bevy_ecs::system::{NonSend, NonSendMut, SystemParam};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use wasm_peers_protocol::{SessionId, UserId};
+
+#[cfg(feature = "many-to-many")]
+use crate::many_to_many;
+use crate::one_to_many::{MiniClient, MiniServer};
+use crate::{ConnectionType, PeerEvent, PeerManager};
+
+/// Network manager that [`WasmPeersPlugin`] creates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Topology {
+    /// Hosts a session in [one-to-many](crate::one_to_many) topology
+    MiniServer,
+    /// Joins a session as a client in [one-to-many](crate::one_to_many) topology
+    MiniClient,
+    /// Connects to all peers in session in [many-to-many](crate::many_to_many) topology
+    #[cfg(feature = "many-to-many")]
+    ManyToMany,
+}
+
+/// Data channel with a peer opened, messages can be sent to it from now on.
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeerConnected(pub UserId);
+
+/// Connection with a peer was closed.
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeerDisconnected(pub UserId);
+
+/// Message received from a peer.
+#[derive(Event, Debug, Clone, PartialEq, Eq)]
+pub struct PeerMessage<T>(pub UserId, pub T);
+
+/// Signaling server returned an error, or network manager couldn't be created.
+#[derive(Event, Debug, Clone, PartialEq, Eq)]
+pub struct PeerSignalingError(pub String);
+
+#[derive(Clone)]
+enum AnyNetworkManager {
+    MiniServer(MiniServer),
+    MiniClient(MiniClient),
+    #[cfg(feature = "many-to-many")]
+    ManyToMany(many_to_many::NetworkManager),
+}
+
+impl AnyNetworkManager {
+    fn send_to<T: Serialize>(&self, user_id: UserId, message: &T) -> crate::Result<()> {
+        match *self {
+            Self::MiniServer(ref network_manager) => network_manager.send_to(user_id, message),
+            Self::MiniClient(ref network_manager) => network_manager.send_to(user_id, message),
+            #[cfg(feature = "many-to-many")]
+            Self::ManyToMany(ref network_manager) => network_manager.send_to(user_id, message),
+        }
+    }
+
+    fn send_to_all<T: Serialize>(&self, message: &T) -> crate::Result<()> {
+        match *self {
+            Self::MiniServer(ref network_manager) => network_manager.send_to_all(message),
+            Self::MiniClient(ref network_manager) => network_manager.send_to_all(message),
+            #[cfg(feature = "many-to-many")]
+            Self::ManyToMany(ref network_manager) => network_manager.send_to_all(message),
+        }
+    }
+
+    fn disconnect(&self) {
+        match *self {
+            Self::MiniServer(ref network_manager) => network_manager.disconnect(),
+            Self::MiniClient(ref network_manager) => network_manager.disconnect(),
+            #[cfg(feature = "many-to-many")]
+            Self::ManyToMany(ref network_manager) => network_manager.disconnect(),
+        }
+    }
+}
+
+/// Non-send resource owning the network manager, read it with `NonSend<PeerConnections>`.
+pub struct PeerConnections {
+    network_manager: Option<AnyNetworkManager>,
+    open_peers: Vec<UserId>,
+}
+
+impl PeerConnections {
+    /// Peers with an open data channel, as of the last drained [`PeerConnected`] and [`PeerDisconnected`].
+    #[must_use]
+    pub fn open_peers(&self) -> &[UserId] {
+        &self.open_peers
+    }
+
+    /// Closes all connections, after which sending messages errs.
+    pub fn close(&mut self) {
+        if let Some(network_manager) = self.network_manager.take() {
+            network_manager.disconnect();
+        }
+        self.open_peers.clear();
+    }
+
+    fn network_manager(&self) -> crate::Result<&AnyNetworkManager> {
+        self.network_manager
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("network manager is not running"))
+    }
+}
+
+type Buffer<T> = Rc<RefCell<VecDeque<PeerEvent<T>>>>;
+
+/// Events received since the last frame.
+struct PeerEventBuffer<T>(Buffer<T>);
+
+/// System param sending messages to peers.
+#[derive(SystemParam)]
+pub struct PeerSender<'w, T: Serialize + 'static> {
+    connections: NonSend<'w, PeerConnections>,
+    message: PhantomData<fn(T)>,
+}
+
+impl<T: Serialize + 'static> PeerSender<'_, T> {
+    /// Sends message to a single peer, mini clients always send it to the host.
+    ///
+    /// # Errors
+    /// This function errs if network manager couldn't be created
+    /// or if sending the message fails, e.g. because the connection isn't open yet.
+    pub fn send_to(&self, user_id: UserId, message: &T) -> crate::Result<()> {
+        self.connections
+            .network_manager()?
+            .send_to(user_id, message)
+    }
+
+    /// Sends message to all connected peers.
+    ///
+    /// # Errors
+    /// This function errs if network manager couldn't be created
+    /// or if sending the message fails, e.g. because the connection isn't open yet.
+    pub fn send_to_all(&self, message: &T) -> crate::Result<()> {
+        self.connections.network_manager()?.send_to_all(message)
+    }
+
+    /// Peers with an open data channel.
+    #[must_use]
+    pub fn open_peers(&self) -> &[UserId] {
+        self.connections.open_peers()
+    }
+}
+
+/// Connects to signaling server once added to the app and turns [`PeerEvent`]s into Bevy events.
+/// See [module documentation](self) for details.
+///
+/// Only one can be added to an app, with `T` being the type of all messages exchanged with peers.
+pub struct WasmPeersPlugin<T> {
+    signaling_server_url: String,
+    session_id: SessionId,
+    connection_type: ConnectionType,
+    topology: Topology,
+    message: PhantomData<fn() -> T>,
+}
+
+impl<T: Serialize + DeserializeOwned + Send + Sync + 'static> WasmPeersPlugin<T> {
+    /// Configures connection to be made once the plugin is added to the app.
+    #[must_use]
+    pub fn new(
+        signaling_server_url: &str,
+        session_id: SessionId,
+        connection_type: ConnectionType,
+        topology: Topology,
+    ) -> Self {
+        Self {
+            signaling_server_url: signaling_server_url.to_owned(),
+            session_id,
+            connection_type,
+            topology,
+            message: PhantomData,
+        }
+    }
+
+    fn connect(&self, buffer: &Buffer<T>) -> crate::Result<AnyNetworkManager> {
+        let url = &*self.signaling_server_url;
+        let connection_type = self.connection_type.clone();
+        Ok(match self.topology {
+            Topology::MiniServer => AnyNetworkManager::MiniServer(start(
+                MiniServer::new(url, self.session_id, connection_type)?,
+                buffer,
+            )?),
+            Topology::MiniClient => AnyNetworkManager::MiniClient(start(
+                MiniClient::new(url, self.session_id, connection_type)?,
+                buffer,
+            )?),
+            #[cfg(feature = "many-to-many")]
+            Topology::ManyToMany => AnyNetworkManager::ManyToMany(start(
+                many_to_many::NetworkManager::new(url, self.session_id, connection_type)?,
+                buffer,
+            )?),
+        })
+    }
+}
+
+impl<T: Serialize + DeserializeOwned + Send + Sync + 'static> Plugin for WasmPeersPlugin<T> {
+    fn build(&self, app: &mut App) {
+        let buffer: Buffer<T> = Rc::default();
+        let network_manager = match self.connect(&buffer) {
+            Ok(network_manager) => Some(network_manager),
+            Err(err) => {
+                buffer
+                    .borrow_mut()
+                    .push_back(PeerEvent::SignalingError(err.to_string()));
+                None
+            }
+        };
+        app.add_event::<PeerConnected>()
+            .add_event::<PeerDisconnected>()
+            .add_event::<PeerMessage<T>>()
+            .add_event::<PeerSignalingError>()
+            .insert_non_send_resource(PeerConnections {
+                network_manager,
+                open_peers: Vec::new(),
+            })
+            .insert_non_send_resource(PeerEventBuffer(buffer))
+            .add_systems(PreUpdate, drain_peer_events::<T>);
+    }
+}
+
+/// Starts `network_manager`, buffering its events.
+fn start<M, T>(mut network_manager: M, buffer: &Buffer<T>) -> crate::Result<M>
+where
+    M: PeerManager<Id = UserId>,
+    T: DeserializeOwned + 'static,
+{
+    let buffer = Rc::clone(buffer);
+    network_manager.start_handling(move |event| buffer.borrow_mut().push_back(event))?;
+    Ok(network_manager)
+}
+
+/// Sends Bevy events for all [`PeerEvent`]s buffered since the last frame.
+#[allow(clippy::needless_pass_by_value)] // systems take their params by value
+fn drain_peer_events<T: Send + Sync + 'static>(
+    buffer: NonSend<PeerEventBuffer<T>>,
+    mut connections: NonSendMut<PeerConnections>,
+    mut connected: EventWriter<PeerConnected>,
+    mut disconnected: EventWriter<PeerDisconnected>,
+    mut messages: EventWriter<PeerMessage<T>>,
+    mut signaling_errors: EventWriter<PeerSignalingError>,
+) {
+    let events: Vec<_> = buffer.0.borrow_mut().drain(..).collect();
+    for event in events {
+        match event {
+            PeerEvent::Opened(user_id) => {
+                if !connections.open_peers.contains(&user_id) {
+                    connections.open_peers.push(user_id);
+                }
+                connected.send(PeerConnected(user_id));
+            }
+            PeerEvent::Message(user_id, message) => messages.send(PeerMessage(user_id, message)),
+            PeerEvent::Closed(user_id, _reason) => {
+                connections.open_peers.retain(|open_id| *open_id != user_id);
+                disconnected.send(PeerDisconnected(user_id));
+            }
+            PeerEvent::SignalingError(error) => signaling_errors.send(PeerSignalingError(error)),
+            PeerEvent::StateChanged(_, _) | PeerEvent::ChannelStateChanged(_, _) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bevy_ecs::event::Events;
+    use bevy_ecs::system::RunSystemOnce;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+    use crate::CloseReason;
+
+    fn app_with_buffer() -> (App, Buffer<u32>) {
+        let buffer: Buffer<u32> = Rc::default();
+        let mut app = App::new();
+        app.add_event::<PeerConnected>()
+            .add_event::<PeerDisconnected>()
+            .add_event::<PeerMessage<u32>>()
+            .add_event::<PeerSignalingError>()
+            .insert_non_send_resource(PeerConnections {
+                network_manager: None,
+                open_peers: Vec::new(),
+            })
+            .insert_non_send_resource(PeerEventBuffer(Rc::clone(&buffer)))
+            .add_systems(PreUpdate, drain_peer_events::<u32>);
+        (app, buffer)
+    }
+
+    fn sent<E: Event + Clone>(app: &App) -> Vec<E> {
+        let events = app.world.resource::<Events<E>>();
+        events.iter_current_update_events().cloned().collect()
+    }
+
+    #[wasm_bindgen_test]
+    fn test_buffered_events_are_drained_into_bevy_events_each_frame() {
+        let (mut app, buffer) = app_with_buffer();
+        let user_id = UserId::new(1);
+        buffer.borrow_mut().extend([
+            PeerEvent::Opened(user_id),
+            PeerEvent::Message(user_id, 7),
+            PeerEvent::SignalingError("session is full".to_owned()),
+        ]);
+        app.update();
+        assert!(buffer.borrow().is_empty());
+        assert_eq!(sent::<PeerConnected>(&app), [PeerConnected(user_id)]);
+        assert_eq!(sent::<PeerMessage<u32>>(&app), [PeerMessage(user_id, 7)]);
+        assert_eq!(
+            sent::<PeerSignalingError>(&app),
+            [PeerSignalingError("session is full".to_owned())]
+        );
+        assert_eq!(
+            app.world
+                .non_send_resource::<PeerConnections>()
+                .open_peers(),
+            [user_id]
+        );
+
+        buffer
+            .borrow_mut()
+            .push_back(PeerEvent::Closed(user_id, CloseReason::PeerLeft));
+        app.update();
+        assert_eq!(sent::<PeerDisconnected>(&app), [PeerDisconnected(user_id)]);
+        assert!(sent::<PeerMessage<u32>>(&app).is_empty());
+        assert!(app
+            .world
+            .non_send_resource::<PeerConnections>()
+            .open_peers()
+            .is_empty());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_sending_errs_without_network_manager() {
+        let (mut app, _buffer) = app_with_buffer();
+        let result = app
+            .world
+            .run_system_once(|sender: PeerSender<u32>| sender.send_to_all(&1));
+        assert!(result.is_err());
+    }
+}
@@ -1 +1,29 @@
+use std::time::Duration;
+
 pub(crate) const DEFAULT_MAX_RETRANSMITS: u16 = 10;
+pub(crate) const DEFAULT_ENVELOPE_WINDOW_SIZE: usize = 64;
+/// Largest message that can be sent over a data channel by default, see [`crate::DataChannelConfig::max_message_size`].
+pub(crate) const DEFAULT_MAX_MESSAGE_SIZE: usize = 64 * 1024;
+/// Largest batch of messages by default, see [`crate::BatchConfig::max_batch_bytes`].
+pub(crate) const DEFAULT_MAX_BATCH_BYTES: usize = 16 * 1024;
+pub(crate) const DEFAULT_BATCH_MAX_LATENCY: Duration = Duration::from_millis(50);
+#[cfg(feature = "one-to-one")]
+pub(crate) const DEFAULT_SIGNALING_RECONNECT_ATTEMPTS: u32 = 5;
+/// Delay before the first attempt to reconnect with signaling server, doubled for each next one.
+#[cfg(feature = "one-to-one")]
+pub(crate) const SIGNALING_RECONNECT_BASE_DELAY: Duration = Duration::from_millis(500);
+/// How long to wait for acknowledgement of a reliable message before the first resend.
+#[cfg(any(feature = "one-to-one", feature = "one-to-many"))]
+pub(crate) const DEFAULT_ACK_TIMEOUT: Duration = Duration::from_millis(250);
+#[cfg(any(feature = "one-to-one", feature = "one-to-many"))]
+pub(crate) const DEFAULT_MAX_SEND_ATTEMPTS: u32 = 5;
+/// How long to wait for signaling server to respond with the list of open sessions.
+#[cfg(any(feature = "one-to-one", feature = "one-to-many"))]
+pub(crate) const SESSION_LIST_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long connection with a peer whose every candidate was refused is given to connect
+/// before it's reported to the application.
+#[cfg(any(feature = "one-to-one", feature = "one-to-many"))]
+pub(crate) const REMOTE_CANDIDATES_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long to wait for a pong before peer is considered unresponsive.
+#[cfg(feature = "one-to-many")]
+pub(crate) const DEFAULT_PING_TIMEOUT: Duration = Duration::from_secs(2);
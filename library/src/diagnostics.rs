@@ -0,0 +1,134 @@
+use serde::Serialize;
+use wasm_bindgen::JsValue;
+use wasm_peers_protocol::{SessionId, UserId};
+use web_sys::{RtcDataChannel, RtcPeerConnection};
+
+use crate::transport::SignalingConnection;
+use crate::utils::data_channel_protocol;
+use crate::ConnectionType;
+
+/// Placeholder for TURN username and credential.
+const REDACTED: &str = "[redacted]";
+
+/// Snapshot of a network manager's state, meant to be serialized to JSON
+/// and attached to bug reports about peers that fail to connect.
+///
+/// States are reported with their JavaScript names, e.g. `checking` or `have-local-offer`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DiagnosticsReport {
+    /// Session ids don't fit into JSON numbers, so it's written as a string
+    pub session_id: String,
+    /// Address of the signaling server, `None` with loopback signaling
+    pub signaling_url: Option<String>,
+    /// Ready state of the connection with signaling server:
+    /// `connecting`, `open`, `closing` or `closed`, `loopback` with loopback signaling
+    pub signaling_state: String,
+    /// `None` in one-to-one topology until the other peer joins
+    pub is_host: Option<bool>,
+    /// Configured connection type, with TURN username and credential redacted
+    pub connection_type: String,
+    /// `navigator.userAgent`, `None` outside of a browser window
+    pub user_agent: Option<String>,
+    pub peers: Vec<PeerDiagnostics>,
+}
+
+/// State of the connection with a single peer.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PeerDiagnostics {
+    /// `None` in one-to-one topology
+    pub user_id: Option<UserId>,
+    pub ice_connection_state: String,
+    pub ice_gathering_state: String,
+    pub signaling_state: String,
+    pub has_local_description: bool,
+    pub has_remote_description: bool,
+    /// Candidates received from the peer that the browser refused to add, e.g. mDNS `.local` ones
+    pub refused_remote_candidates: usize,
+    /// Local candidates kept from the peer by [`CandidateFilter`](crate::CandidateFilter),
+    /// the peers may fail to connect if it suppressed too many
+    pub suppressed_local_candidates: usize,
+    /// `None` until data channel is created or received from the other peer
+    pub data_channel: Option<DataChannelDiagnostics>,
+}
+
+/// State of the data channel with a single peer.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DataChannelDiagnostics {
+    pub label: String,
+    /// Application protocol, see [`DataChannelConfig::protocol`](crate::DataChannelConfig::protocol)
+    pub protocol: String,
+    pub ready_state: String,
+    /// Bytes queued to be sent
+    pub buffered_amount: u32,
+}
+
+impl DiagnosticsReport {
+    pub(crate) fn new(
+        session_id: SessionId,
+        signaling: &SignalingConnection,
+        signaling_url: Option<String>,
+        is_host: Option<bool>,
+        connection_type: &ConnectionType,
+        peers: Vec<PeerDiagnostics>,
+    ) -> Self {
+        Self {
+            session_id: session_id.inner().to_string(),
+            signaling_url,
+            signaling_state: signaling.ready_state_name().to_owned(),
+            is_host,
+            connection_type: redacted(connection_type),
+            user_agent: web_sys::window().and_then(|window| window.navigator().user_agent().ok()),
+            peers,
+        }
+    }
+}
+
+impl PeerDiagnostics {
+    pub(crate) fn new(
+        user_id: Option<UserId>,
+        peer_connection: &RtcPeerConnection,
+        data_channel: Option<&RtcDataChannel>,
+        refused_remote_candidates: usize,
+        suppressed_local_candidates: usize,
+    ) -> Self {
+        Self {
+            user_id,
+            ice_connection_state: js_enum_name(peer_connection.ice_connection_state()),
+            ice_gathering_state: js_enum_name(peer_connection.ice_gathering_state()),
+            signaling_state: js_enum_name(peer_connection.signaling_state()),
+            has_local_description: peer_connection.local_description().is_some(),
+            has_remote_description: peer_connection.remote_description().is_some(),
+            refused_remote_candidates,
+            suppressed_local_candidates,
+            data_channel: data_channel.map(|data_channel| DataChannelDiagnostics {
+                label: data_channel.label(),
+                protocol: data_channel_protocol(data_channel),
+                ready_state: js_enum_name(data_channel.ready_state()),
+                buffered_amount: data_channel.buffered_amount(),
+            }),
+        }
+    }
+}
+
+fn js_enum_name(value: impl Into<JsValue>) -> String {
+    value.into().as_string().unwrap_or_default()
+}
+
+fn redacted(connection_type: &ConnectionType) -> String {
+    match *connection_type {
+        ConnectionType::StunAndTurn {
+            ref stun_urls,
+            ref turn_urls,
+            ..
+        } => format!(
+            "{:?}",
+            ConnectionType::StunAndTurn {
+                stun_urls: stun_urls.clone(),
+                turn_urls: turn_urls.clone(),
+                username: REDACTED.to_owned(),
+                credential: REDACTED.to_owned(),
+            }
+        ),
+        ref connection_type => format!("{:?}", connection_type),
+    }
+}
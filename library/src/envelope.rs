@@ -0,0 +1,517 @@
+/*!
+Ordered delivery of messages over unordered data channels.
+
+[`EnvelopeNetworkManager`] wraps a network manager of any topology and sends each message
+in an [`Envelope`] with a sequence number counted separately for each peer.
+Received envelopes are buffered until all preceding ones arrive,
+so the event handler gets messages in the same order in which they were sent,
+without head-of-line blocking of ordered data channels on the sending side.
+
+Messages can still be lost on the way. Once more than `window_size` envelopes are waiting
+for a missing one, or it doesn't arrive within `gap_timeout`, it's considered lost
+and buffered messages are delivered without it.
+With [`Delivery::Immediate`] messages aren't held back at all, they're only numbered,
+so that gaps and reordering can be read with [`EnvelopeNetworkManager::stats_for`].
+
+Both peers have to use [`EnvelopeNetworkManager`], as envelopes are sent as regular messages.
+
+# Example
+
+```no_run
+use wasm_peers::envelope::EnvelopeNetworkManager;
+use wasm_peers::many_to_many::NetworkManager;
+use wasm_peers::{ConnectionType, PeerEvent, SessionId};
+use web_sys::console;
+
+let network_manager = NetworkManager::new(
+    "ws://0.0.0.0:9001/many-to-many",
+    SessionId::new(1),
+    ConnectionType::Local,
+)
+.expect("failed to connect to signaling server");
+let mut network_manager = EnvelopeNetworkManager::new(network_manager);
+let sender = network_manager.clone();
+network_manager.start_with_event_handler(move |event: PeerEvent<u32>| match event {
+    PeerEvent::Opened(user_id) => {
+        for count in 0..10 {
+            let _result = sender.send_message(user_id, &count);
+        }
+    }
+    // always arrive as 0, 1, 2...
+    PeerEvent::Message(user_id, count) => {
+        console::log_1(&format!("{} counted to {}", user_id, count).into());
+    }
+    _ => {}
+})
+.unwrap();
+```
+*/
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::rc::Rc;
+use std::time::Duration;
+
+use log::{debug, error};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::constants::DEFAULT_ENVELOPE_WINDOW_SIZE;
+use crate::utils::sleep;
+use crate::{PeerEvent, PeerManager};
+
+/// Message together with its position in the stream of messages sent to a single peer.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Envelope<T> {
+    pub seq: u64,
+    pub payload: T,
+}
+
+/// How messages arriving before some of the preceding ones are delivered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Delivery {
+    /// Held back until all preceding messages arrive or are considered lost
+    #[default]
+    InOrder,
+    /// Delivered right away, gaps are only reported by [`EnvelopeNetworkManager::stats_for`]
+    Immediate,
+}
+
+/// Configuration of [`EnvelopeNetworkManager`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnvelopeConfig {
+    pub delivery: Delivery,
+    /// How many messages received after a missing one are kept track of before it's considered lost,
+    /// 64 by default
+    pub window_size: usize,
+    /// How long a missing message is waited for before it's considered lost,
+    /// by default only `window_size` limits it
+    pub gap_timeout: Option<Duration>,
+}
+
+impl Default for EnvelopeConfig {
+    fn default() -> Self {
+        Self {
+            delivery: Delivery::default(),
+            window_size: DEFAULT_ENVELOPE_WINDOW_SIZE,
+            gap_timeout: None,
+        }
+    }
+}
+
+/// Counters of messages received from a single peer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SequenceStats {
+    /// Messages that arrived after a message sent later than them
+    pub messages_reordered: u64,
+    /// Messages considered lost as they didn't arrive within [`EnvelopeConfig::gap_timeout`]
+    pub messages_dropped_by_gap_timeout: u64,
+    /// Messages considered lost as more than [`EnvelopeConfig::window_size`] messages arrived after them
+    pub messages_dropped_by_window: u64,
+    /// Messages still missing before the latest received one
+    pub missing: u64,
+}
+
+/// Buffers envelopes received from a single peer until they can be delivered in order.
+#[derive(Debug)]
+struct ReorderBuffer<T> {
+    delivery: Delivery,
+    next_seq: u64,
+    /// Messages received after a missing one, `None` if already delivered with [`Delivery::Immediate`]
+    buffered: BTreeMap<u64, Option<T>>,
+    window_size: usize,
+    stats: SequenceStats,
+    /// Missing message whose gap timeout is running
+    gap_timer: Option<u64>,
+}
+
+impl<T> ReorderBuffer<T> {
+    fn new(delivery: Delivery, window_size: usize) -> Self {
+        Self {
+            delivery,
+            next_seq: 0,
+            buffered: BTreeMap::new(),
+            window_size,
+            stats: SequenceStats::default(),
+            gap_timer: None,
+        }
+    }
+
+    /// Returns payloads that can be delivered after receiving `envelope`, in order.
+    fn push(&mut self, envelope: Envelope<T>) -> Vec<T> {
+        let seq = envelope.seq;
+        if seq < self.next_seq || self.buffered.contains_key(&seq) {
+            debug!("dropping duplicated or skipped message {}", seq);
+            return Vec::new();
+        }
+        if self
+            .buffered
+            .keys()
+            .next_back()
+            .map_or(false, |&latest_seq| latest_seq > seq)
+        {
+            self.stats.messages_reordered = self.stats.messages_reordered.saturating_add(1);
+        }
+        let mut ready = Vec::new();
+        match self.delivery {
+            Delivery::InOrder => {
+                self.buffered.insert(seq, Some(envelope.payload));
+            }
+            Delivery::Immediate => {
+                ready.push(envelope.payload);
+                self.buffered.insert(seq, None);
+            }
+        }
+        if self.buffered.len() > self.window_size {
+            let skipped = self.skip_missing();
+            self.stats.messages_dropped_by_window = self
+                .stats
+                .messages_dropped_by_window
+                .saturating_add(skipped);
+        }
+        ready.extend(self.advance());
+        ready
+    }
+
+    /// Starts gap timeout of the first missing message, unless it's already running.
+    fn start_gap_timer(&mut self) -> Option<u64> {
+        if self.buffered.is_empty() || self.gap_timer == Some(self.next_seq) {
+            return None;
+        }
+        self.gap_timer = Some(self.next_seq);
+        self.gap_timer
+    }
+
+    /// Considers message `seq` lost, if it's still the first missing one,
+    /// returning payloads that can be delivered now.
+    fn skip_gap(&mut self, seq: u64) -> Vec<T> {
+        if self.gap_timer == Some(seq) {
+            self.gap_timer = None;
+        }
+        if self.next_seq != seq || self.buffered.is_empty() {
+            return Vec::new();
+        }
+        let skipped = self.skip_missing();
+        self.stats.messages_dropped_by_gap_timeout = self
+            .stats
+            .messages_dropped_by_gap_timeout
+            .saturating_add(skipped);
+        self.advance()
+    }
+
+    /// Skips messages missing before the oldest buffered one, returning how many were skipped.
+    fn skip_missing(&mut self) -> u64 {
+        let Some(oldest_seq) = self.buffered.keys().next().copied() else {
+            return 0;
+        };
+        debug!(
+            "messages {}..{} considered lost, skipping them",
+            self.next_seq, oldest_seq
+        );
+        let skipped = oldest_seq.saturating_sub(self.next_seq);
+        self.next_seq = oldest_seq;
+        skipped
+    }
+
+    fn advance(&mut self) -> Vec<T> {
+        let mut ready = Vec::new();
+        while let Some(payload) = self.buffered.remove(&self.next_seq) {
+            ready.extend(payload);
+            self.next_seq = self.next_seq.wrapping_add(1);
+        }
+        self.stats.missing = self.buffered.keys().next_back().map_or(0, |&latest_seq| {
+            let buffered = u64::try_from(self.buffered.len()).unwrap_or(u64::MAX);
+            latest_seq
+                .saturating_sub(self.next_seq)
+                .saturating_add(1)
+                .saturating_sub(buffered)
+        });
+        ready
+    }
+}
+
+type Handler<T, Id> = Box<dyn FnMut(PeerEvent<T, Id>)>;
+
+/// Receiving side of [`EnvelopeNetworkManager`], shared with running gap timeouts.
+struct Receiver<T, Id> {
+    config: EnvelopeConfig,
+    buffers: RefCell<HashMap<Id, ReorderBuffer<T>>>,
+    stats: Rc<RefCell<HashMap<Id, SequenceStats>>>,
+    handler: RefCell<Handler<T, Id>>,
+}
+
+impl<T: 'static, Id: Clone + Eq + Hash + 'static> Receiver<T, Id> {
+    fn notify(&self, event: PeerEvent<T, Id>) {
+        (self.handler.borrow_mut())(event);
+    }
+
+    fn receive(self: &Rc<Self>, id: &Id, envelope: Envelope<T>) {
+        let ready = self
+            .buffers
+            .borrow_mut()
+            .entry(id.clone())
+            .or_insert_with(|| ReorderBuffer::new(self.config.delivery, self.config.window_size))
+            .push(envelope);
+        self.deliver(id, ready);
+    }
+
+    /// Passes `ready` payloads to the handler, then waits for the first missing message if there is one.
+    fn deliver(self: &Rc<Self>, id: &Id, ready: Vec<T>) {
+        let gap = {
+            let mut buffers = self.buffers.borrow_mut();
+            let Some(buffer) = buffers.get_mut(id) else {
+                return;
+            };
+            self.stats.borrow_mut().insert(id.clone(), buffer.stats);
+            self.config
+                .gap_timeout
+                .and_then(|timeout| buffer.start_gap_timer().map(|seq| (seq, timeout)))
+        };
+        for payload in ready {
+            self.notify(PeerEvent::Message(id.clone(), payload));
+        }
+        if let Some((seq, timeout)) = gap {
+            let receiver = Rc::clone(self);
+            let id = id.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                if let Err(err) = sleep(timeout).await {
+                    error!("failed to wait for missing message: {}", err);
+                    return;
+                }
+                let skipped_to = receiver
+                    .buffers
+                    .borrow_mut()
+                    .get_mut(&id)
+                    .map(|buffer| buffer.skip_gap(seq))
+                    .unwrap_or_default();
+                receiver.deliver(&id, skipped_to);
+            });
+        }
+    }
+
+    fn forget(&self, id: &Id) {
+        self.buffers.borrow_mut().remove(id);
+        self.stats.borrow_mut().remove(id);
+    }
+}
+
+/// Wrapper over network manager of any topology, delivering messages in the order they were sent.
+/// See [module documentation](self) for details.
+pub struct EnvelopeNetworkManager<M: PeerManager> {
+    inner: M,
+    /// Sequence number of the next message sent to each connected peer
+    next_seqs: Rc<RefCell<HashMap<M::Id, u64>>>,
+    stats: Rc<RefCell<HashMap<M::Id, SequenceStats>>>,
+    config: EnvelopeConfig,
+}
+
+impl<M: PeerManager> Clone for EnvelopeNetworkManager<M> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            next_seqs: Rc::clone(&self.next_seqs),
+            stats: Rc::clone(&self.stats),
+            config: self.config.clone(),
+        }
+    }
+}
+
+impl<M: PeerManager> EnvelopeNetworkManager<M>
+where
+    M::Id: Eq + Hash,
+{
+    /// Wraps `inner`, which shouldn't be started yet.
+    pub fn new(inner: M) -> Self {
+        Self::with_config(inner, EnvelopeConfig::default())
+    }
+
+    /// Same as [`EnvelopeNetworkManager::new`], but at most `window_size` out of order messages
+    /// are buffered for each peer before the missing ones are skipped.
+    pub fn with_window_size(inner: M, window_size: usize) -> Self {
+        Self::with_config(
+            inner,
+            EnvelopeConfig {
+                window_size,
+                ..EnvelopeConfig::default()
+            },
+        )
+    }
+
+    /// Same as [`EnvelopeNetworkManager::new`], but with custom delivery of out of order messages.
+    pub fn with_config(inner: M, config: EnvelopeConfig) -> Self {
+        Self {
+            inner,
+            next_seqs: Rc::new(RefCell::new(HashMap::new())),
+            stats: Rc::new(RefCell::new(HashMap::new())),
+            config,
+        }
+    }
+
+    /// Counters of messages received from peer `id`, `None` if none were received since it connected.
+    #[must_use]
+    pub fn stats_for(&self, id: &M::Id) -> Option<SequenceStats> {
+        self.stats.borrow().get(id).copied()
+    }
+
+    /// Starts the underlying network manager, calling `handler` with received messages
+    /// in the order they were sent by each peer.
+    ///
+    /// # Errors
+    /// Errs with [`AlreadyStartedError`](crate::AlreadyStartedError) if it was already started.
+    pub fn start_with_event_handler<T: DeserializeOwned + 'static>(
+        &mut self,
+        handler: impl FnMut(PeerEvent<T, M::Id>) + 'static,
+    ) -> crate::Result<()> {
+        let next_seqs = Rc::clone(&self.next_seqs);
+        let receiver = Rc::new(Receiver {
+            config: self.config.clone(),
+            buffers: RefCell::new(HashMap::new()),
+            stats: Rc::clone(&self.stats),
+            handler: RefCell::new(Box::new(handler)),
+        });
+        self.inner
+            .start_handling(move |event: PeerEvent<Envelope<T>, M::Id>| match event {
+                PeerEvent::Opened(id) => {
+                    next_seqs.borrow_mut().entry(id.clone()).or_insert(0);
+                    receiver.notify(PeerEvent::Opened(id));
+                }
+                PeerEvent::Message(id, envelope) => receiver.receive(&id, envelope),
+                PeerEvent::Closed(id, reason) => {
+                    next_seqs.borrow_mut().remove(&id);
+                    receiver.forget(&id);
+                    receiver.notify(PeerEvent::Closed(id, reason));
+                }
+                PeerEvent::SignalingError(err) => receiver.notify(PeerEvent::SignalingError(err)),
+                PeerEvent::StateChanged(id, state) => {
+                    receiver.notify(PeerEvent::StateChanged(id, state));
+                }
+                PeerEvent::ChannelStateChanged(id, state) => {
+                    receiver.notify(PeerEvent::ChannelStateChanged(id, state));
+                }
+            })
+    }
+
+    /// Sends message to a single peer as the next one in order.
+    ///
+    /// # Errors
+    /// Same as `send_message` of the underlying network manager.
+    pub fn send_message<T: Serialize + ?Sized>(&self, id: M::Id, message: &T) -> crate::Result<()> {
+        let seq = self.next_seqs.borrow().get(&id).copied().unwrap_or(0);
+        self.inner.send_to(
+            id.clone(),
+            &Envelope {
+                seq,
+                payload: message,
+            },
+        )?;
+        // sequence number is only used up once the message is sent, so that receiver won't wait for it
+        self.next_seqs.borrow_mut().insert(id, seq.wrapping_add(1));
+        Ok(())
+    }
+
+    /// Sends message to all connected peers, each with its own sequence number.
+    ///
+    /// # Errors
+    /// This function tries to send message to all peers and returns the first error that occurred.
+    pub fn send_message_to_all<T: Serialize + ?Sized>(&self, message: &T) -> crate::Result<()> {
+        let ids: Vec<_> = self.next_seqs.borrow().keys().cloned().collect();
+        ids.into_iter()
+            .map(|id| self.send_message(id, message))
+            .fold(Ok(()), Result::and)
+    }
+
+    /// Closes the underlying network manager.
+    pub fn close(&self) {
+        self.inner.disconnect();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+
+    fn push_all(buffer: &mut ReorderBuffer<&'static str>, seqs: &[u64]) -> Vec<&'static str> {
+        const PAYLOADS: [&str; 6] = ["a", "b", "c", "d", "e", "f"];
+        seqs.iter()
+            .flat_map(|&seq| {
+                let payload = PAYLOADS
+                    .get(usize::try_from(seq).expect("seq out of range"))
+                    .expect("no payload for seq");
+                buffer.push(Envelope {
+                    seq,
+                    payload: *payload,
+                })
+            })
+            .collect()
+    }
+
+    #[wasm_bindgen_test]
+    fn test_messages_in_order_are_delivered_immediately() {
+        let mut buffer = ReorderBuffer::new(Delivery::InOrder, DEFAULT_ENVELOPE_WINDOW_SIZE);
+        assert_eq!(push_all(&mut buffer, &[0, 1, 2]), vec!["a", "b", "c"]);
+        assert!(buffer.buffered.is_empty());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_messages_out_of_order_wait_for_missing_one() {
+        let mut buffer = ReorderBuffer::new(Delivery::InOrder, DEFAULT_ENVELOPE_WINDOW_SIZE);
+        assert!(push_all(&mut buffer, &[2, 1]).is_empty());
+        assert_eq!(push_all(&mut buffer, &[0]), vec!["a", "b", "c"]);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_duplicated_messages_are_dropped() {
+        let mut buffer = ReorderBuffer::new(Delivery::InOrder, DEFAULT_ENVELOPE_WINDOW_SIZE);
+        assert_eq!(push_all(&mut buffer, &[0, 0, 1, 0]), vec!["a", "b"]);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_lost_message_is_skipped_once_window_is_full() {
+        let mut buffer = ReorderBuffer::new(Delivery::InOrder, 2);
+        assert!(push_all(&mut buffer, &[2, 3]).is_empty());
+        assert_eq!(push_all(&mut buffer, &[5]), vec!["c", "d"]);
+        assert_eq!(push_all(&mut buffer, &[4, 0]), vec!["e", "f"]);
+        assert_eq!(buffer.stats.messages_dropped_by_window, 2);
+        assert_eq!(buffer.stats.messages_reordered, 1);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_immediate_delivery_reports_gaps() {
+        let mut buffer = ReorderBuffer::new(Delivery::Immediate, DEFAULT_ENVELOPE_WINDOW_SIZE);
+        assert_eq!(push_all(&mut buffer, &[0, 2, 4]), vec!["a", "c", "e"]);
+        assert_eq!(buffer.stats.missing, 2);
+        assert_eq!(push_all(&mut buffer, &[1, 1]), vec!["b"]);
+        assert_eq!(buffer.stats.missing, 1);
+        assert_eq!(buffer.stats.messages_reordered, 1);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_missing_message_is_skipped_after_gap_timeout() {
+        let mut buffer = ReorderBuffer::new(Delivery::InOrder, DEFAULT_ENVELOPE_WINDOW_SIZE);
+        assert_eq!(push_all(&mut buffer, &[0, 2, 3]), vec!["a"]);
+        assert_eq!(buffer.start_gap_timer(), Some(1));
+        assert_eq!(buffer.start_gap_timer(), None);
+        assert_eq!(buffer.skip_gap(1), vec!["c", "d"]);
+        assert!(push_all(&mut buffer, &[1]).is_empty());
+        assert_eq!(buffer.stats.messages_dropped_by_gap_timeout, 1);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_gap_timeout_of_message_that_arrived_is_ignored() {
+        let mut buffer = ReorderBuffer::new(Delivery::InOrder, DEFAULT_ENVELOPE_WINDOW_SIZE);
+        assert!(push_all(&mut buffer, &[1]).is_empty());
+        assert_eq!(buffer.start_gap_timer(), Some(0));
+        assert_eq!(push_all(&mut buffer, &[0]), vec!["a", "b"]);
+        assert!(buffer.skip_gap(0).is_empty());
+        assert_eq!(
+            buffer.stats,
+            SequenceStats {
+                messages_reordered: 1,
+                ..SequenceStats::default()
+            }
+        );
+    }
+}
@@ -1,2 +1,133 @@
+use std::fmt::{Display, Formatter};
+
+use wasm_peers_protocol::{UserId, PROTOCOL_MAJOR_VERSION, PROTOCOL_MINOR_VERSION};
+
 pub type Result<T> = anyhow::Result<T>;
 pub type Error = anyhow::Error;
+
+/// Error returned when sending a message to a group of peers failed for some of them.
+/// Message was still sent to all peers not listed in `failures`.
+#[derive(Debug)]
+pub struct MultiSendError {
+    pub failures: Vec<(UserId, Error)>,
+}
+
+impl Display for MultiSendError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "failed to send message to {} peer(s):",
+            self.failures.len()
+        )?;
+        for failure in &self.failures {
+            write!(f, " {}: {};", failure.0, failure.1)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for MultiSendError {}
+
+/// Error returned when `start` is called on a network manager that was already started.
+/// Starting it again would install a second set of handlers and corrupt the connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AlreadyStartedError;
+
+impl Display for AlreadyStartedError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "network manager was already started")
+    }
+}
+
+impl std::error::Error for AlreadyStartedError {}
+
+/// Error returned when serialized message is larger than [`crate::DataChannelConfig::max_message_size`].
+/// It's returned before the message reaches the data channel, which is left intact.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MessageTooLargeError {
+    /// Size of the serialized message in bytes
+    pub size: usize,
+    /// Maximum message size in bytes
+    pub limit: usize,
+}
+
+impl Display for MessageTooLargeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "message of {} bytes exceeds maximum message size of {} bytes",
+            self.size, self.limit
+        )
+    }
+}
+
+impl std::error::Error for MessageTooLargeError {}
+
+/// Error returned when a message sent reliably wasn't acknowledged by the other peer after all attempts.
+/// It might still have been delivered, if it was acknowledgements that got lost.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NotAcknowledgedError {
+    /// How many times the message was sent
+    pub attempts: u32,
+}
+
+impl Display for NotAcknowledgedError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "message wasn't acknowledged after {} attempt(s)",
+            self.attempts
+        )
+    }
+}
+
+impl std::error::Error for NotAcknowledgedError {}
+
+/// Error returned when a message is sent in another mode than the data channel carries: plain text
+/// on a channel started with `start_string`, or serialized messages otherwise. Modes can't be mixed,
+/// as the other peer couldn't tell them apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelModeError {
+    /// Whether the data channel carries plain text
+    pub text_mode: bool,
+}
+
+impl Display for ChannelModeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.text_mode {
+            write!(
+                f,
+                "data channel carries plain text, serialized messages can't be sent over it"
+            )
+        } else {
+            write!(
+                f,
+                "data channel carries serialized messages, plain text can't be sent over it"
+            )
+        }
+    }
+}
+
+impl std::error::Error for ChannelModeError {}
+
+/// Error reported once signaling server refuses the connection, as it speaks an incompatible major version
+/// of the protocol. Either the library or the signaling server has to be updated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProtocolVersionMismatchError {
+    /// Major version of the protocol spoken by signaling server
+    pub server_major: u8,
+    /// Minor version of the protocol spoken by signaling server
+    pub server_minor: u8,
+}
+
+impl Display for ProtocolVersionMismatchError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "signaling server speaks protocol v{}.{}, incompatible with v{}.{} of this library",
+            self.server_major, self.server_minor, PROTOCOL_MAJOR_VERSION, PROTOCOL_MINOR_VERSION
+        )
+    }
+}
+
+impl std::error::Error for ProtocolVersionMismatchError {}
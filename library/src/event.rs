@@ -0,0 +1,79 @@
+use std::cell::RefCell;
+use std::convert::Infallible;
+use std::fmt::Debug;
+use std::rc::Rc;
+
+use wasm_peers_protocol::UserId;
+
+use crate::{ChannelState, ConnectionState};
+
+/// Reason why connection with a peer was closed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloseReason {
+    /// Data channel was closed, either by the other peer or by the browser
+    ChannelClosed,
+    /// Connection couldn't be established or re-established
+    ConnectionFailed,
+    /// Signaling server reported that the peer left the session
+    PeerLeft,
+}
+
+/// Everything that can happen to a network manager, delivered to a single event handler
+/// passed to one of the `start_with_event_handler` methods.
+///
+/// Peers are identified by `Id`, which is [`UserId`] in topologies with multiple peers
+/// and `()` in [one-to-one](crate::one_to_one) topology.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PeerEvent<T, Id = UserId> {
+    /// Data channel with a peer opened, messages can be sent from now on
+    Opened(Id),
+    /// Message received from a peer
+    Message(Id, T),
+    /// Connection with a peer was closed
+    Closed(Id, CloseReason),
+    /// Signaling server returned an error or its message couldn't be handled
+    SignalingError(String),
+    /// Connection with a peer changed its state
+    StateChanged(Id, ConnectionState),
+    /// Data channel with a peer started closing or closed
+    ChannelStateChanged(Id, ChannelState),
+}
+
+/// Events that don't carry a message, so they can be produced without knowing message type.
+pub(crate) type LifecycleEvent<Id = UserId> = PeerEvent<Infallible, Id>;
+
+impl<Id> LifecycleEvent<Id> {
+    pub(crate) fn with_message_type<T>(self) -> PeerEvent<T, Id> {
+        match self {
+            Self::Opened(id) => PeerEvent::Opened(id),
+            Self::Message(_, never) => match never {},
+            Self::Closed(id, reason) => PeerEvent::Closed(id, reason),
+            Self::SignalingError(error) => PeerEvent::SignalingError(error),
+            Self::StateChanged(id, state) => PeerEvent::StateChanged(id, state),
+            Self::ChannelStateChanged(id, state) => PeerEvent::ChannelStateChanged(id, state),
+        }
+    }
+}
+
+pub(crate) type EventHandler<T, Id = UserId> = Rc<RefCell<dyn FnMut(PeerEvent<T, Id>)>>;
+
+pub(crate) type LifecycleEventHandler<Id = UserId> = Rc<RefCell<dyn FnMut(LifecycleEvent<Id>)>>;
+
+pub(crate) fn event_handler<T, Id>(
+    handler: impl FnMut(PeerEvent<T, Id>) + 'static,
+) -> EventHandler<T, Id> {
+    Rc::new(RefCell::new(handler))
+}
+
+/// Handler for events without a message, that forwards them to the full event handler.
+/// With `tracing` feature each of them is also emitted as a `tracing` event.
+pub(crate) fn lifecycle_event_handler<T: 'static, Id: Debug + 'static>(
+    event_handler: &EventHandler<T, Id>,
+) -> LifecycleEventHandler<Id> {
+    let event_handler = Rc::clone(event_handler);
+    Rc::new(RefCell::new(move |event: LifecycleEvent<Id>| {
+        #[cfg(feature = "tracing")]
+        tracing::info!(?event, "connection lifecycle event");
+        (event_handler.borrow_mut())(event.with_message_type());
+    }))
+}
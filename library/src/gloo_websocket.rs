@@ -0,0 +1,206 @@
+/*!
+Signaling through [`gloo_net::websocket::futures::WebSocket`] instead of raw browser `WebSocket`.
+
+Connection is driven by a single task, sending queued messages and passing received ones to callbacks,
+so no closure is leaked with `Closure::forget`, they're all dropped together with the connection
+once it's closed or the last network manager using it is dropped.
+Messages sent before connection opens are queued instead of failing.
+*/
+
+use std::cell::RefCell;
+use std::fmt::{Debug, Formatter};
+use std::pin::Pin;
+use std::rc::Rc;
+
+use anyhow::anyhow;
+use futures_channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use futures_channel::oneshot;
+use futures_util::future::{poll_fn, ready, select, Either, LocalBoxFuture};
+use futures_util::{FutureExt, Sink, SinkExt, StreamExt};
+use gloo_net::websocket::futures::WebSocket;
+use gloo_net::websocket::{Message, State};
+use log::{debug, error};
+use wasm_bindgen::JsValue;
+
+use crate::transport::{OnMessage, WebSocketLike};
+
+enum Command {
+    Send(Vec<u8>),
+    Close,
+}
+
+type Callback = Box<dyn FnMut()>;
+
+struct Callbacks {
+    ready_state: &'static str,
+    on_open: Option<Callback>,
+    on_message: Option<OnMessage>,
+    on_close: Option<Callback>,
+    /// Notified whether connection opened, once it's known
+    open_waiters: Vec<oneshot::Sender<bool>>,
+}
+
+/// `WebSocket` connection with signaling server, driven by a task spawned on opening it.
+pub struct GlooWebSocket {
+    commands: UnboundedSender<Command>,
+    callbacks: Rc<RefCell<Callbacks>>,
+}
+
+impl Debug for GlooWebSocket {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GlooWebSocket")
+            .field("ready_state", &self.callbacks.borrow().ready_state)
+            .finish_non_exhaustive()
+    }
+}
+
+impl GlooWebSocket {
+    /// Opens connection, spawning the task that drives it.
+    pub fn open(url: &str) -> Result<Self, JsValue> {
+        let websocket = WebSocket::open(url).map_err(|err| JsValue::from_str(&err.to_string()))?;
+        let (commands, receiver) = unbounded();
+        let callbacks = Rc::new(RefCell::new(Callbacks {
+            ready_state: "connecting",
+            on_open: None,
+            on_message: None,
+            on_close: None,
+            open_waiters: Vec::new(),
+        }));
+        wasm_bindgen_futures::spawn_local(run(websocket, receiver, Rc::clone(&callbacks)));
+        Ok(Self {
+            commands,
+            callbacks,
+        })
+    }
+}
+
+impl WebSocketLike for GlooWebSocket {
+    fn send(&self, message: &[u8]) -> crate::Result<()> {
+        self.commands
+            .unbounded_send(Command::Send(message.to_vec()))
+            .map_err(|err| anyhow!("failed to send message across the websocket: {}", err))
+    }
+
+    fn wait_for_open(&self) -> LocalBoxFuture<'_, crate::Result<()>> {
+        let opened = {
+            let mut callbacks = self.callbacks.borrow_mut();
+            if callbacks.ready_state == "connecting" {
+                let (sender, receiver) = oneshot::channel();
+                callbacks.open_waiters.push(sender);
+                receiver.map(|opened| opened.unwrap_or(false)).boxed_local()
+            } else {
+                ready(callbacks.ready_state == "open").boxed_local()
+            }
+        };
+        async move {
+            if opened.await {
+                Ok(())
+            } else {
+                Err(anyhow!("failed to connect to signaling server"))
+            }
+        }
+        .boxed_local()
+    }
+
+    fn set_on_open(&self, mut on_open: Box<dyn FnMut()>) {
+        // websocket might have been opened already by `NetworkManager::new_async`
+        if self.callbacks.borrow().ready_state == "open" {
+            on_open();
+            return;
+        }
+        self.callbacks.borrow_mut().on_open = Some(on_open);
+    }
+
+    fn set_on_message(&self, on_message: OnMessage) {
+        self.callbacks.borrow_mut().on_message = Some(on_message);
+    }
+
+    fn set_on_close(&self, on_close: Box<dyn FnMut()>) {
+        self.callbacks.borrow_mut().on_close = Some(on_close);
+    }
+
+    fn ready_state_name(&self) -> &'static str {
+        self.callbacks.borrow().ready_state
+    }
+
+    fn close(&self) {
+        {
+            let mut callbacks = self.callbacks.borrow_mut();
+            callbacks.on_message = None;
+            callbacks.on_close = None;
+        }
+        // task is already over if connection was closed
+        let _result = self.commands.unbounded_send(Command::Close);
+    }
+}
+
+/// Waits for `websocket` to open, calling `on_close` if it fails to, then sends `commands` through it and passes received messages
+/// to `callbacks` until it's closed by either side, dropping it afterwards.
+async fn run(
+    mut websocket: WebSocket,
+    mut commands: UnboundedReceiver<Command>,
+    callbacks: Rc<RefCell<Callbacks>>,
+) {
+    // sink is ready once connection is no longer connecting
+    let result = poll_fn(|cx| Sink::<Message>::poll_ready(Pin::new(&mut websocket), cx)).await;
+    let opened = result.is_ok() && matches!(websocket.state(), State::Open);
+    let (waiters, callback) = {
+        let mut callbacks = callbacks.borrow_mut();
+        callbacks.ready_state = if opened { "open" } else { "closed" };
+        let callback = if opened {
+            callbacks.on_open.take()
+        } else {
+            callbacks.on_close.take()
+        };
+        (std::mem::take(&mut callbacks.open_waiters), callback)
+    };
+    for waiter in waiters {
+        let _result = waiter.send(opened);
+    }
+    if let Some(mut callback) = callback {
+        callback();
+    }
+    if !opened {
+        return;
+    }
+
+    loop {
+        match select(websocket.next(), commands.next()).await {
+            Either::Left((Some(Ok(Message::Bytes(message))), _)) => {
+                // take the callback out, so that it can freely use the connection
+                let on_message = callbacks.borrow_mut().on_message.take();
+                if let Some(mut on_message) = on_message {
+                    on_message(&message);
+                    callbacks.borrow_mut().on_message.get_or_insert(on_message);
+                }
+            }
+            Either::Left((Some(Ok(Message::Text(_))), _)) => {
+                error!("ignoring text message from signaling server");
+            }
+            Either::Left((Some(Err(err)), _)) => {
+                debug!("signaling server connection: {}", err);
+            }
+            Either::Left((None, _)) => {
+                let on_close = {
+                    let mut callbacks = callbacks.borrow_mut();
+                    callbacks.ready_state = "closed";
+                    callbacks.on_close.take()
+                };
+                if let Some(mut on_close) = on_close {
+                    on_close();
+                }
+                return;
+            }
+            Either::Right((Some(Command::Send(message)), _)) => {
+                if let Err(err) = websocket.send(Message::Bytes(message)).await {
+                    error!("failed to send message across the websocket: {}", err);
+                }
+            }
+            // every handle was dropped or connection was closed with `WebSocketLike::close`
+            Either::Right((Some(Command::Close) | None, _)) => {
+                callbacks.borrow_mut().ready_state = "closed";
+                return;
+            }
+        }
+    }
+}
@@ -0,0 +1,280 @@
+/*!
+Hooks for using network managers from [yew](https://yew.rs) function components,
+available with `yew` feature.
+
+Each hook owns a network manager for the lifetime of the component and closes it on unmount.
+Received messages and connection events are stored in [`PeerState`], re-rendering the component on each change,
+and messages can be sent with a [`UsePeerHandle`], which can be cloned freely into yew callbacks.
+
+# Example
+
+```
+use wasm_peers::hooks::use_many_to_many;
+use wasm_peers::{ConnectionType, SessionId};
+use yew::{function_component, html, Callback, Html};
+
+#[function_component(Chat)]
+fn chat() -> Html {
+    let peers = use_many_to_many::<String>(
+        "ws://0.0.0.0:9001/one-to-many",
+        SessionId::new(1),
+        ConnectionType::Local,
+    );
+    let on_click = {
+        let peers = peers.clone();
+        Callback::from(move |_| {
+            let _result = peers.send_to_all(&"ping!".to_owned());
+        })
+    };
+    html! {
+        <div>
+            <p>{ format!("connected peers: {}", peers.state().open_peers.len()) }</p>
+            { for peers.state().messages.iter().map(|&(ref user_id, ref message)| html! {
+                <p>{ format!("{}: {}", user_id, message) }</p>
+            }) }
+            <button onclick={on_click}>{ "ping" }</button>
+        </div>
+    }
+}
+```
+*/
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use wasm_peers_protocol::{SessionId, UserId};
+use yew::{hook, use_effect_with, use_mut_ref, use_reducer, Reducible, UseReducerHandle};
+
+#[cfg(feature = "many-to-many")]
+use crate::many_to_many;
+#[cfg(feature = "one-to-many")]
+use crate::one_to_many::{MiniClient, MiniServer};
+#[cfg(feature = "one-to-one")]
+use crate::one_to_one;
+#[cfg(any(feature = "one-to-one", feature = "one-to-many"))]
+use crate::ConnectionType;
+use crate::PeerEvent;
+pub use crate::PeerManager;
+
+/// Connections and messages gathered from [`PeerEvent`]s received so far.
+#[derive(Debug, Clone)]
+pub struct PeerState<T, Id = UserId> {
+    /// Peers with an open data channel
+    pub open_peers: Vec<Id>,
+    /// Received messages, oldest first
+    pub messages: Vec<(Id, T)>,
+    /// Last error reported by signaling server
+    pub signaling_error: Option<String>,
+}
+
+impl<T, Id> Default for PeerState<T, Id> {
+    fn default() -> Self {
+        Self {
+            open_peers: Vec::new(),
+            messages: Vec::new(),
+            signaling_error: None,
+        }
+    }
+}
+
+impl<T: Clone + 'static, Id: Clone + PartialEq + 'static> Reducible for PeerState<T, Id> {
+    type Action = PeerEvent<T, Id>;
+
+    fn reduce(self: Rc<Self>, event: Self::Action) -> Rc<Self> {
+        let mut state = (*self).clone();
+        match event {
+            PeerEvent::Opened(id) => {
+                if !state.open_peers.contains(&id) {
+                    state.open_peers.push(id);
+                }
+            }
+            PeerEvent::Message(id, message) => state.messages.push((id, message)),
+            PeerEvent::Closed(id, _reason) => state.open_peers.retain(|open_id| *open_id != id),
+            PeerEvent::SignalingError(error) => state.signaling_error = Some(error),
+            // nothing to re-render
+            PeerEvent::StateChanged(_, _) | PeerEvent::ChannelStateChanged(_, _) => return self,
+        }
+        Rc::new(state)
+    }
+}
+
+/// Handle returned by the hooks, giving access to [`PeerState`] and sending messages.
+/// It can be cloned freely and captured by `'static` callbacks.
+pub struct UsePeerHandle<T: Clone + 'static, M: PeerManager> {
+    state: UseReducerHandle<PeerState<T, M::Id>>,
+    network_manager: Rc<RefCell<Option<M>>>,
+}
+
+impl<T: Clone + 'static, M: PeerManager> Clone for UsePeerHandle<T, M> {
+    fn clone(&self) -> Self {
+        Self {
+            state: self.state.clone(),
+            network_manager: Rc::clone(&self.network_manager),
+        }
+    }
+}
+
+impl<T: Serialize + Clone + 'static, M: PeerManager> UsePeerHandle<T, M> {
+    /// Connections and messages as of the current render.
+    #[must_use]
+    pub fn state(&self) -> &PeerState<T, M::Id> {
+        &self.state
+    }
+
+    /// Sends message to a single peer.
+    ///
+    /// # Errors
+    /// This function errs if network manager couldn't be created
+    /// or if sending the message fails, e.g. because the connection isn't open yet.
+    pub fn send_to(&self, id: M::Id, message: &T) -> crate::Result<()> {
+        self.network_manager()?.send_to(id, message)
+    }
+
+    /// Sends message to all connected peers.
+    ///
+    /// # Errors
+    /// This function errs if network manager couldn't be created
+    /// or if sending the message fails, e.g. because the connection isn't open yet.
+    pub fn send_to_all(&self, message: &T) -> crate::Result<()> {
+        self.network_manager()?.send_to_all(message)
+    }
+
+    fn network_manager(&self) -> crate::Result<M> {
+        self.network_manager
+            .borrow()
+            .clone()
+            .ok_or_else(|| anyhow::anyhow!("network manager is not running"))
+    }
+}
+
+/// Creates network manager with `create` once per URL and session id
+/// and closes it when component is unmounted or either of them changes.
+#[hook]
+fn use_peer_manager<T, M, F>(
+    signaling_server_url: &str,
+    session_id: SessionId,
+    create: F,
+) -> UsePeerHandle<T, M>
+where
+    T: Serialize + DeserializeOwned + Clone + 'static,
+    M: PeerManager,
+    F: FnOnce(&str, SessionId) -> crate::Result<M> + 'static,
+{
+    let state = use_reducer(PeerState::default);
+    let network_manager = use_mut_ref(|| None);
+    {
+        let dispatcher = state.dispatcher();
+        let network_manager = Rc::clone(&network_manager);
+        use_effect_with(
+            (signaling_server_url.to_owned(), session_id),
+            move |&(ref signaling_server_url, session_id)| {
+                match create(signaling_server_url, session_id) {
+                    Ok(mut new_network_manager) => {
+                        let event_dispatcher = dispatcher.clone();
+                        match new_network_manager
+                            .start_handling(move |event| event_dispatcher.dispatch(event))
+                        {
+                            Ok(()) => *network_manager.borrow_mut() = Some(new_network_manager),
+                            Err(err) => {
+                                dispatcher.dispatch(PeerEvent::SignalingError(err.to_string()));
+                            }
+                        }
+                    }
+                    Err(err) => dispatcher.dispatch(PeerEvent::SignalingError(err.to_string())),
+                }
+                move || {
+                    let network_manager = network_manager.borrow_mut().take();
+                    if let Some(network_manager) = network_manager {
+                        network_manager.disconnect();
+                    }
+                }
+            },
+        );
+    }
+    UsePeerHandle {
+        state,
+        network_manager,
+    }
+}
+
+/// Connects to the other peer in [one-to-one](crate::one_to_one) topology.
+#[cfg(feature = "one-to-one")]
+#[hook]
+pub fn use_one_to_one<T>(
+    signaling_server_url: &str,
+    session_id: SessionId,
+    connection_type: ConnectionType,
+) -> UsePeerHandle<T, one_to_one::NetworkManager>
+where
+    T: Serialize + DeserializeOwned + Clone + 'static,
+{
+    use_peer_manager(
+        signaling_server_url,
+        session_id,
+        move |signaling_server_url, session_id| {
+            one_to_one::NetworkManager::new(signaling_server_url, session_id, connection_type)
+        },
+    )
+}
+
+/// Hosts a session in [one-to-many](crate::one_to_many) topology.
+#[cfg(feature = "one-to-many")]
+#[hook]
+pub fn use_mini_server<T>(
+    signaling_server_url: &str,
+    session_id: SessionId,
+    connection_type: ConnectionType,
+) -> UsePeerHandle<T, MiniServer>
+where
+    T: Serialize + DeserializeOwned + Clone + 'static,
+{
+    use_peer_manager(
+        signaling_server_url,
+        session_id,
+        move |signaling_server_url, session_id| {
+            MiniServer::new(signaling_server_url, session_id, connection_type)
+        },
+    )
+}
+
+/// Joins a session as a client in [one-to-many](crate::one_to_many) topology.
+#[cfg(feature = "one-to-many")]
+#[hook]
+pub fn use_mini_client<T>(
+    signaling_server_url: &str,
+    session_id: SessionId,
+    connection_type: ConnectionType,
+) -> UsePeerHandle<T, MiniClient>
+where
+    T: Serialize + DeserializeOwned + Clone + 'static,
+{
+    use_peer_manager(
+        signaling_server_url,
+        session_id,
+        move |signaling_server_url, session_id| {
+            MiniClient::new(signaling_server_url, session_id, connection_type)
+        },
+    )
+}
+
+/// Connects to all peers in session in [many-to-many](crate::many_to_many) topology.
+#[cfg(feature = "many-to-many")]
+#[hook]
+pub fn use_many_to_many<T>(
+    signaling_server_url: &str,
+    session_id: SessionId,
+    connection_type: ConnectionType,
+) -> UsePeerHandle<T, many_to_many::NetworkManager>
+where
+    T: Serialize + DeserializeOwned + Clone + 'static,
+{
+    use_peer_manager(
+        signaling_server_url,
+        session_id,
+        move |signaling_server_url, session_id| {
+            many_to_many::NetworkManager::new(signaling_server_url, session_id, connection_type)
+        },
+    )
+}
@@ -0,0 +1,488 @@
+/*!
+Local ICE candidates gathered by peer connections, reported to the application to debug NAT traversal,
+e.g. to warn that no relay candidates were gathered because TURN server isn't configured right.
+Remote candidates the browser refused to add are counted as well, e.g. mDNS `.local` candidates
+that some browsers emit and others can't resolve.
+
+Local candidates can be kept from the other peer with a [`CandidateFilter`], e.g. to not expose
+local network details, in which case those suppressed are counted too.
+*/
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::hash::Hash;
+use std::net::Ipv4Addr;
+use std::rc::Rc;
+
+use log::warn;
+use serde::Serialize;
+use wasm_peers_protocol::IceCandidate;
+use web_sys::{RtcIceConnectionState, RtcPeerConnection};
+
+use crate::constants::REMOTE_CANDIDATES_TIMEOUT;
+use crate::utils::{add_ice_candidate, sleep};
+
+/// Type of an ICE candidate, the way the other peer can reach this one through it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CandidateType {
+    /// Address of a local network interface
+    Host,
+    /// Public address discovered with a STUN server
+    ServerReflexive,
+    /// Address the other peer was seen from during connectivity checks
+    PeerReflexive,
+    /// Address on a TURN server relaying the traffic
+    Relay,
+}
+
+impl CandidateType {
+    fn parse(typ: &str) -> Option<Self> {
+        match typ {
+            "host" => Some(Self::Host),
+            "srflx" => Some(Self::ServerReflexive),
+            "prflx" => Some(Self::PeerReflexive),
+            "relay" => Some(Self::Relay),
+            _ => None,
+        }
+    }
+}
+
+/// Details of a local ICE candidate, parsed from its SDP attribute.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CandidateInfo {
+    pub typ: CandidateType,
+    /// Transport protocol, `udp` or `tcp`
+    pub protocol: String,
+    /// Address with the last two octets of IPv4 addresses and all but the first group of IPv6 ones hidden,
+    /// so that it can be shown or logged. `mDNS` host names, which browsers use to hide local addresses, are kept.
+    pub address_redacted: String,
+    pub port: u16,
+}
+
+impl CandidateInfo {
+    /// Parses `candidate` attribute, e.g. `candidate:842163049 1 udp 1677729535 203.0.113.7 46154 typ srflx ...`,
+    /// `None` if it's malformed or empty, as the one ending gathering is.
+    #[must_use]
+    pub fn parse(candidate: &str) -> Option<Self> {
+        let candidate = candidate.strip_prefix("a=").unwrap_or(candidate);
+        let candidate = candidate.strip_prefix("candidate:").unwrap_or(candidate);
+        // foundation, component, protocol, priority, address, port, "typ", type
+        let fields: Vec<&str> = candidate.split_whitespace().collect();
+        let [_foundation, _component, protocol, _priority, address, port, "typ", typ, ..] =
+            *fields.as_slice()
+        else {
+            return None;
+        };
+        Some(Self {
+            typ: CandidateType::parse(typ)?,
+            protocol: protocol.to_ascii_lowercase(),
+            address_redacted: redact_address(address),
+            port: port.parse().ok()?,
+        })
+    }
+}
+
+fn redact_address(address: &str) -> String {
+    if let Ok(ip) = address.parse::<Ipv4Addr>() {
+        let [first, second, _, _] = ip.octets();
+        return format!("{}.{}.x.x", first, second);
+    }
+    match address.split_once(':') {
+        Some((first_group, _)) => format!("{}:x", first_group),
+        None => address.to_owned(),
+    }
+}
+
+/// Decides which local ICE candidates are signaled to the other peer, set with `set_candidate_filter`
+/// of network managers. Candidates it rejects are still gathered, but the other peer never learns them,
+/// so filtering out too many may keep peers from connecting, e.g. [`CandidateFilter::relay_only`]
+/// without a TURN server configured.
+///
+/// Any `Fn(&CandidateInfo) -> bool` closure can be used as a filter as well.
+#[derive(Clone)]
+pub struct CandidateFilter {
+    allows: Rc<dyn Fn(&CandidateInfo) -> bool>,
+}
+
+impl CandidateFilter {
+    /// Signals only candidates for which `allows` returns `true`.
+    pub fn new(allows: impl Fn(&CandidateInfo) -> bool + 'static) -> Self {
+        Self {
+            allows: Rc::new(allows),
+        }
+    }
+
+    /// Signals only relay candidates, so that the other peer only learns address of the TURN server.
+    #[must_use]
+    pub fn relay_only() -> Self {
+        Self::new(|candidate| candidate.typ == CandidateType::Relay)
+    }
+
+    /// Signals all but host candidates, including mDNS `.local` ones, so that local addresses aren't exposed.
+    #[must_use]
+    pub fn no_host() -> Self {
+        Self::new(|candidate| candidate.typ != CandidateType::Host)
+    }
+
+    /// Whether `candidate` should be signaled to the other peer.
+    #[must_use]
+    pub fn allows(&self, candidate: &CandidateInfo) -> bool {
+        (self.allows)(candidate)
+    }
+}
+
+impl Debug for CandidateFilter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CandidateFilter").finish_non_exhaustive()
+    }
+}
+
+impl<F: Fn(&CandidateInfo) -> bool + 'static> From<F> for CandidateFilter {
+    fn from(allows: F) -> Self {
+        Self::new(allows)
+    }
+}
+
+/// Number of local ICE candidates of each type gathered so far,
+/// read with `gathered_candidate_summary` of network managers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct CandidateSummary {
+    pub host: usize,
+    pub server_reflexive: usize,
+    pub peer_reflexive: usize,
+    pub relay: usize,
+}
+
+impl CandidateSummary {
+    fn record(&mut self, typ: CandidateType) {
+        let count = match typ {
+            CandidateType::Host => &mut self.host,
+            CandidateType::ServerReflexive => &mut self.server_reflexive,
+            CandidateType::PeerReflexive => &mut self.peer_reflexive,
+            CandidateType::Relay => &mut self.relay,
+        };
+        *count = count.saturating_add(1);
+    }
+
+    /// Total number of candidates gathered.
+    #[must_use]
+    pub const fn total(&self) -> usize {
+        self.host
+            .saturating_add(self.server_reflexive)
+            .saturating_add(self.peer_reflexive)
+            .saturating_add(self.relay)
+    }
+}
+
+/// Remote candidates received from a single peer.
+#[derive(Debug, Clone, Copy, Default)]
+struct RemoteCandidates {
+    added: usize,
+    refused: usize,
+}
+
+type LocalCandidateCallback<Id> = Rc<RefCell<dyn FnMut(Id, CandidateInfo)>>;
+type GatheringCompleteCallback<Id> = Rc<RefCell<dyn FnMut(Id)>>;
+
+struct IceGatheringInner<Id> {
+    summary: CandidateSummary,
+    remote: HashMap<Id, RemoteCandidates>,
+    filter: Option<CandidateFilter>,
+    /// Local candidates for connection with each peer that the filter kept from it
+    suppressed: HashMap<Id, usize>,
+    on_local_candidate: Option<LocalCandidateCallback<Id>>,
+    on_gathering_complete: Option<GatheringCompleteCallback<Id>>,
+}
+
+/// Candidates gathered by all peer connections of a network manager, along with callbacks notified of them.
+#[derive(Clone)]
+pub struct IceGathering<Id> {
+    inner: Rc<RefCell<IceGatheringInner<Id>>>,
+}
+
+impl<Id> Debug for IceGathering<Id> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("IceGathering")
+            .field("summary", &self.inner.borrow().summary)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<Id> Default for IceGathering<Id> {
+    fn default() -> Self {
+        Self {
+            inner: Rc::new(RefCell::new(IceGatheringInner {
+                summary: CandidateSummary::default(),
+                remote: HashMap::new(),
+                filter: None,
+                suppressed: HashMap::new(),
+                on_local_candidate: None,
+                on_gathering_complete: None,
+            })),
+        }
+    }
+}
+
+impl<Id> IceGathering<Id> {
+    pub fn set_on_local_candidate(
+        &self,
+        on_local_candidate: impl FnMut(Id, CandidateInfo) + 'static,
+    ) {
+        self.inner.borrow_mut().on_local_candidate =
+            Some(Rc::new(RefCell::new(on_local_candidate)));
+    }
+
+    pub fn set_on_gathering_complete(&self, on_gathering_complete: impl FnMut(Id) + 'static) {
+        self.inner.borrow_mut().on_gathering_complete =
+            Some(Rc::new(RefCell::new(on_gathering_complete)));
+    }
+
+    /// Filter consulted for candidates gathered from now on, `None` signals all of them.
+    pub fn set_filter(&self, filter: Option<CandidateFilter>) {
+        self.inner.borrow_mut().filter = filter;
+    }
+
+    pub fn summary(&self) -> CandidateSummary {
+        self.inner.borrow().summary
+    }
+
+    /// Records `candidate` gathered by connection with `peer`, unless it can't be parsed,
+    /// returns whether it should be signaled to `peer`. Candidates that can't be parsed,
+    /// like the empty one ending gathering, are always signaled.
+    pub fn local_candidate(&self, peer: Id, candidate: &str) -> bool
+    where
+        Id: Copy + Eq + Hash,
+    {
+        let Some(info) = CandidateInfo::parse(candidate) else {
+            return true;
+        };
+        let filter = self.inner.borrow().filter.clone();
+        // filter is called without borrowing, so that it can freely use the network manager
+        let allowed = filter.map_or(true, |filter| filter.allows(&info));
+        // clone the callback out, so that it can freely use the network manager
+        let callback = {
+            let mut inner = self.inner.borrow_mut();
+            inner.summary.record(info.typ);
+            if !allowed {
+                let suppressed = inner.suppressed.entry(peer).or_default();
+                *suppressed = suppressed.saturating_add(1);
+            }
+            inner.on_local_candidate.clone()
+        };
+        if let Some(callback) = callback {
+            (callback.borrow_mut())(peer, info);
+        }
+        allowed
+    }
+
+    /// Number of local candidates for connection with `peer` that the filter kept from it.
+    pub fn suppressed_local_candidates(&self, peer: &Id) -> usize
+    where
+        Id: Eq + Hash,
+    {
+        self.inner
+            .borrow()
+            .suppressed
+            .get(peer)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn gathering_complete(&self, peer: Id) {
+        let callback = self.inner.borrow().on_gathering_complete.clone();
+        if let Some(callback) = callback {
+            (callback.borrow_mut())(peer);
+        }
+    }
+
+    /// Records candidate received from `peer` that was added to the connection with it.
+    pub fn remote_candidate_added(&self, peer: Id)
+    where
+        Id: Eq + Hash,
+    {
+        let mut inner = self.inner.borrow_mut();
+        let remote = inner.remote.entry(peer).or_default();
+        remote.added = remote.added.saturating_add(1);
+    }
+
+    /// Records candidate received from `peer` that the browser refused to add,
+    /// returns whether it's the first one refused and none were added before it.
+    pub fn remote_candidate_refused(&self, peer: Id) -> bool
+    where
+        Id: Eq + Hash,
+    {
+        let mut inner = self.inner.borrow_mut();
+        let remote = inner.remote.entry(peer).or_default();
+        remote.refused = remote.refused.saturating_add(1);
+        remote.refused == 1 && remote.added == 0
+    }
+
+    /// Number of candidates received from `peer` that the browser refused to add.
+    pub fn refused_remote_candidates(&self, peer: &Id) -> usize
+    where
+        Id: Eq + Hash,
+    {
+        self.inner
+            .borrow()
+            .remote
+            .get(peer)
+            .map_or(0, |remote| remote.refused)
+    }
+
+    /// Whether the browser refused every candidate received from `peer` so far, if there were any.
+    pub fn all_remote_candidates_refused(&self, peer: &Id) -> bool
+    where
+        Id: Eq + Hash,
+    {
+        self.inner
+            .borrow()
+            .remote
+            .get(peer)
+            .map_or(false, |remote| remote.refused > 0 && remote.added == 0)
+    }
+}
+
+/// Adds `ice_candidate` received from `peer` to `peer_connection`. Candidate that the browser refuses
+/// is logged and counted instead of failing, as the remaining ones may still connect the peers.
+/// If the browser refused every candidate of `peer` and the connection still isn't connected
+/// after [`REMOTE_CANDIDATES_TIMEOUT`], `on_all_refused` is called.
+pub async fn add_remote_candidate<Id: Copy + Eq + Hash + 'static>(
+    peer_connection: &RtcPeerConnection,
+    ice_candidate: &IceCandidate,
+    peer: Id,
+    ice_gathering: &IceGathering<Id>,
+    on_all_refused: impl FnOnce() + 'static,
+) {
+    let result = add_ice_candidate(peer_connection, ice_candidate).await;
+    if ice_candidate.is_end_of_candidates() {
+        if let Err(err) = result {
+            warn!("failed to add end of ICE candidates: {}", err);
+        }
+        return;
+    }
+    let Err(err) = result else {
+        ice_gathering.remote_candidate_added(peer);
+        return;
+    };
+    warn!(
+        "failed to add ICE candidate {:?}, skipping it: {}",
+        ice_candidate.candidate, err
+    );
+    if !ice_gathering.remote_candidate_refused(peer) {
+        return;
+    }
+    let peer_connection = peer_connection.clone();
+    let ice_gathering = ice_gathering.clone();
+    wasm_bindgen_futures::spawn_local(async move {
+        if let Err(sleep_err) = sleep(REMOTE_CANDIDATES_TIMEOUT).await {
+            warn!("failed to wait for connection to connect: {}", sleep_err);
+            return;
+        }
+        let connected = matches!(
+            peer_connection.ice_connection_state(),
+            RtcIceConnectionState::Connected | RtcIceConnectionState::Completed
+        );
+        if !connected && ice_gathering.all_remote_candidates_refused(&peer) {
+            on_all_refused();
+        }
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+
+    #[wasm_bindgen_test]
+    fn test_candidates_are_parsed_and_redacted() {
+        let srflx = CandidateInfo::parse(
+            "candidate:842163049 1 udp 1677729535 203.0.113.7 46154 typ srflx raddr 0.0.0.0 rport 0 generation 0",
+        );
+        assert_eq!(
+            srflx,
+            Some(CandidateInfo {
+                typ: CandidateType::ServerReflexive,
+                protocol: "udp".to_owned(),
+                address_redacted: "203.0.x.x".to_owned(),
+                port: 46154,
+            })
+        );
+        let relay = CandidateInfo::parse("candidate:1 1 TCP 41885439 2001:db8::1 3478 typ relay")
+            .expect("failed to parse relay candidate");
+        assert_eq!(relay.typ, CandidateType::Relay);
+        assert_eq!(relay.protocol, "tcp");
+        assert_eq!(relay.address_redacted, "2001:x");
+        let mdns = CandidateInfo::parse("candidate:2 1 udp 2113937151 0b7c.local 54400 typ host")
+            .expect("failed to parse host candidate");
+        assert_eq!(mdns.address_redacted, "0b7c.local");
+    }
+
+    #[wasm_bindgen_test]
+    fn test_malformed_candidates_are_rejected() {
+        assert_eq!(CandidateInfo::parse(""), None);
+        assert_eq!(
+            CandidateInfo::parse("candidate:1 1 udp 1 10.0.0.1 notaport typ host"),
+            None
+        );
+        assert_eq!(
+            CandidateInfo::parse("candidate:1 1 udp 1 10.0.0.1 5000 typ unknown"),
+            None
+        );
+    }
+
+    #[wasm_bindgen_test]
+    fn test_summary_counts_candidates_by_type() {
+        let gathering = IceGathering::default();
+        gathering.local_candidate((), "candidate:1 1 udp 1 10.0.0.1 5000 typ host");
+        gathering.local_candidate((), "candidate:2 1 udp 1 203.0.113.7 5001 typ srflx");
+        gathering.local_candidate((), "");
+        let summary = gathering.summary();
+        assert_eq!(summary.host, 1);
+        assert_eq!(summary.server_reflexive, 1);
+        assert_eq!(summary.relay, 0);
+        assert_eq!(summary.total(), 2);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_filtered_candidates_are_gathered_but_not_signaled() {
+        let gathering = IceGathering::default();
+        gathering.set_filter(Some(CandidateFilter::no_host()));
+        assert!(!gathering.local_candidate(1, "candidate:1 1 udp 1 0b7c.local 5000 typ host"));
+        assert!(gathering.local_candidate(1, "candidate:2 1 udp 1 203.0.113.7 5001 typ srflx"));
+        assert!(gathering.local_candidate(1, ""));
+        assert_eq!(gathering.summary().total(), 2);
+        assert_eq!(gathering.suppressed_local_candidates(&1), 1);
+
+        gathering.set_filter(Some(CandidateFilter::relay_only()));
+        assert!(!gathering.local_candidate(2, "candidate:2 1 udp 1 203.0.113.7 5001 typ srflx"));
+        assert!(gathering.local_candidate(2, "candidate:3 1 udp 1 198.51.100.1 3478 typ relay"));
+        assert_eq!(gathering.suppressed_local_candidates(&2), 1);
+
+        gathering.set_filter(Some(CandidateFilter::from(|candidate: &CandidateInfo| {
+            candidate.protocol == "tcp"
+        })));
+        assert!(!gathering.local_candidate(3, "candidate:3 1 udp 1 198.51.100.1 3478 typ relay"));
+        gathering.set_filter(None);
+        assert!(gathering.local_candidate(3, "candidate:1 1 udp 1 10.0.0.1 5000 typ host"));
+        assert_eq!(gathering.suppressed_local_candidates(&3), 1);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_all_refused_remote_candidates_are_reported_once() {
+        let gathering = IceGathering::default();
+        assert!(!gathering.all_remote_candidates_refused(&1));
+        assert!(gathering.remote_candidate_refused(1));
+        assert!(!gathering.remote_candidate_refused(1));
+        assert!(gathering.all_remote_candidates_refused(&1));
+        gathering.remote_candidate_added(1);
+        assert!(!gathering.all_remote_candidates_refused(&1));
+        assert_eq!(gathering.refused_remote_candidates(&1), 2);
+
+        gathering.remote_candidate_added(2);
+        assert!(!gathering.remote_candidate_refused(2));
+        assert_eq!(gathering.refused_remote_candidates(&3), 0);
+    }
+}
@@ -0,0 +1,377 @@
+/*!
+Wrappers over network managers exported with `wasm-bindgen`, for use from plain JavaScript.
+
+Each wrapper owns its network manager, so the connection stays alive for as long as
+the JavaScript object isn't freed. Messages are strings or `Uint8Array`s and can only be exchanged
+with peers that also use these wrappers.
+
+# Example
+
+```js
+import init, { JsManyToMany } from "./wasm_peers.js";
+
+await init();
+const peer = new JsManyToMany("ws://0.0.0.0:9001/many-to-many", "1234", { type: "local" });
+peer.start(
+    (userId) => peer.sendTo(userId, "hello!"),
+    (userId, message) => console.log(`${userId} sent:`, message),
+    (userId) => console.log(`${userId} left`),
+);
+```
+*/
+
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use js_sys::{Array, Function, Uint8Array};
+use log::error;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::wasm_bindgen;
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_peers_protocol::{SessionId, UserId};
+
+use crate::{many_to_many, one_to_many, one_to_one, ConnectionType, PeerEvent};
+
+/// Message sent between JavaScript peers.
+#[derive(Debug, Serialize, Deserialize)]
+enum JsMessage {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+impl JsMessage {
+    fn from_js(message: &JsValue) -> Result<Self, JsValue> {
+        if let Some(text) = message.as_string() {
+            Ok(Self::Text(text))
+        } else if let Some(bytes) = message.dyn_ref::<Uint8Array>() {
+            Ok(Self::Binary(bytes.to_vec()))
+        } else {
+            Err(JsValue::from_str("message must be a string or Uint8Array"))
+        }
+    }
+
+    fn into_js(self) -> JsValue {
+        match self {
+            Self::Text(text) => JsValue::from_str(&text),
+            Self::Binary(bytes) => Uint8Array::from(bytes.as_slice()).into(),
+        }
+    }
+}
+
+#[allow(clippy::needless_pass_by_value)]
+fn to_js_error(err: crate::Error) -> JsValue {
+    JsValue::from_str(&err.to_string())
+}
+
+fn parse_arguments(
+    session_id: &str,
+    connection_type: &JsValue,
+) -> crate::Result<(SessionId, ConnectionType)> {
+    let session_id = SessionId::from_str(session_id)
+        .map_err(|err| anyhow!("invalid session id '{}': {}", session_id, err))?;
+    Ok((session_id, ConnectionType::from_js_object(connection_type)?))
+}
+
+#[allow(clippy::unnecessary_wraps)] // passed as `peer_to_js` to `js_event_handler`
+fn user_id_to_js(user_id: UserId) -> Option<JsValue> {
+    Some(JsValue::from(user_id.into_inner()))
+}
+
+/// Calls one of JavaScript callbacks for each event,
+/// with peer's id as the first argument if `peer_to_js` returns one.
+fn js_event_handler<Id>(
+    on_open: Function,
+    on_message: Function,
+    on_close: Function,
+    peer_to_js: impl Fn(Id) -> Option<JsValue> + 'static,
+) -> impl FnMut(PeerEvent<JsMessage, Id>) + 'static {
+    move |event| {
+        let (callback, peer, message) = match event {
+            PeerEvent::Opened(peer) => (&on_open, peer, None),
+            PeerEvent::Message(peer, message) => (&on_message, peer, Some(message.into_js())),
+            PeerEvent::Closed(peer, _) => (&on_close, peer, None),
+            PeerEvent::SignalingError(err) => {
+                error!("signaling error: {}", err);
+                return;
+            }
+            PeerEvent::StateChanged(..) | PeerEvent::ChannelStateChanged(..) => return,
+        };
+        let args = peer_to_js(peer)
+            .into_iter()
+            .chain(message)
+            .collect::<Array>();
+        if let Err(err) = callback.apply(&JsValue::NULL, &args) {
+            error!("JavaScript callback threw an error: {:?}", err);
+        }
+    }
+}
+
+/// JavaScript wrapper over [`one_to_one::NetworkManager`].
+#[wasm_bindgen]
+#[derive(Debug)]
+pub struct JsOneToOne {
+    inner: one_to_one::NetworkManager,
+}
+
+#[wasm_bindgen]
+impl JsOneToOne {
+    /// Same as [`one_to_one::NetworkManager::new`],
+    /// with connection type given as in [`ConnectionType::from_js_object`].
+    ///
+    /// # Errors
+    /// This function errs if any of the arguments is invalid or connecting to signaling server fails.
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        signaling_server_url: &str,
+        session_id: &str,
+        connection_type: &JsValue,
+    ) -> Result<JsOneToOne, JsValue> {
+        let (session_id, connection_type) =
+            parse_arguments(session_id, connection_type).map_err(to_js_error)?;
+        let inner =
+            one_to_one::NetworkManager::new(signaling_server_url, session_id, connection_type)
+                .map_err(to_js_error)?;
+        Ok(Self { inner })
+    }
+
+    /// Begins the connection, calls `on_open()`, `on_message(message)` and `on_close()`.
+    ///
+    /// # Errors
+    /// This function errs if it was already started.
+    pub fn start(
+        &mut self,
+        on_open: Function,
+        on_message: Function,
+        on_close: Function,
+    ) -> Result<(), JsValue> {
+        self.inner
+            .start_with_event_handler(js_event_handler(on_open, on_message, on_close, |()| None))
+            .map_err(to_js_error)
+    }
+
+    /// Sends a string or `Uint8Array` to the other peer.
+    ///
+    /// # Errors
+    /// This function errs if message is of another type or sending it fails.
+    pub fn send(&self, message: &JsValue) -> Result<(), JsValue> {
+        self.inner
+            .send_message(&JsMessage::from_js(message)?)
+            .map_err(to_js_error)
+    }
+
+    /// Same as [`one_to_one::NetworkManager::close`]
+    pub fn close(&self) {
+        self.inner.close();
+    }
+}
+
+/// JavaScript wrapper over [`one_to_many::MiniServer`].
+#[wasm_bindgen]
+#[derive(Debug)]
+pub struct JsMiniServer {
+    inner: one_to_many::MiniServer,
+}
+
+#[wasm_bindgen]
+impl JsMiniServer {
+    /// Same as [`one_to_many::MiniServer::new`],
+    /// with connection type given as in [`ConnectionType::from_js_object`].
+    ///
+    /// # Errors
+    /// This function errs if any of the arguments is invalid or connecting to signaling server fails.
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        signaling_server_url: &str,
+        session_id: &str,
+        connection_type: &JsValue,
+    ) -> Result<JsMiniServer, JsValue> {
+        let (session_id, connection_type) =
+            parse_arguments(session_id, connection_type).map_err(to_js_error)?;
+        let inner = one_to_many::MiniServer::new(signaling_server_url, session_id, connection_type)
+            .map_err(to_js_error)?;
+        Ok(Self { inner })
+    }
+
+    /// Begins accepting clients, calls `on_open(userId)`, `on_message(userId, message)`
+    /// and `on_close(userId)`, where `userId` is a `BigInt`.
+    ///
+    /// # Errors
+    /// This function errs if it was already started.
+    pub fn start(
+        &mut self,
+        on_open: Function,
+        on_message: Function,
+        on_close: Function,
+    ) -> Result<(), JsValue> {
+        self.inner
+            .start_with_event_handler(js_event_handler(
+                on_open,
+                on_message,
+                on_close,
+                user_id_to_js,
+            ))
+            .map_err(to_js_error)
+    }
+
+    /// Sends a string or `Uint8Array` to the client with `user_id`.
+    ///
+    /// # Errors
+    /// This function errs if message is of another type or sending it fails.
+    #[wasm_bindgen(js_name = sendTo)]
+    pub fn send_to(&self, user_id: u64, message: &JsValue) -> Result<(), JsValue> {
+        self.inner
+            .send_message(UserId::new(user_id), &JsMessage::from_js(message)?)
+            .map_err(to_js_error)
+    }
+
+    /// Sends a string or `Uint8Array` to all connected clients.
+    ///
+    /// # Errors
+    /// This function errs if message is of another type or sending it fails.
+    #[wasm_bindgen(js_name = sendToAll)]
+    pub fn send_to_all(&self, message: &JsValue) -> Result<(), JsValue> {
+        self.inner
+            .send_message_to_all(&JsMessage::from_js(message)?)
+            .map_err(to_js_error)
+    }
+
+    /// Same as [`one_to_many::MiniServer::close`]
+    pub fn close(&self) {
+        self.inner.close();
+    }
+}
+
+/// JavaScript wrapper over [`one_to_many::MiniClient`].
+#[wasm_bindgen]
+#[derive(Debug)]
+pub struct JsMiniClient {
+    inner: one_to_many::MiniClient,
+}
+
+#[wasm_bindgen]
+impl JsMiniClient {
+    /// Same as [`one_to_many::MiniClient::new`],
+    /// with connection type given as in [`ConnectionType::from_js_object`].
+    ///
+    /// # Errors
+    /// This function errs if any of the arguments is invalid or connecting to signaling server fails.
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        signaling_server_url: &str,
+        session_id: &str,
+        connection_type: &JsValue,
+    ) -> Result<JsMiniClient, JsValue> {
+        let (session_id, connection_type) =
+            parse_arguments(session_id, connection_type).map_err(to_js_error)?;
+        let inner = one_to_many::MiniClient::new(signaling_server_url, session_id, connection_type)
+            .map_err(to_js_error)?;
+        Ok(Self { inner })
+    }
+
+    /// Begins connecting to the host, calls `on_open()`, `on_message(message)` and `on_close()`.
+    ///
+    /// # Errors
+    /// This function errs if it was already started.
+    pub fn start(
+        &mut self,
+        on_open: Function,
+        on_message: Function,
+        on_close: Function,
+    ) -> Result<(), JsValue> {
+        self.inner
+            .start_with_event_handler(js_event_handler(on_open, on_message, on_close, |_| None))
+            .map_err(to_js_error)
+    }
+
+    /// Sends a string or `Uint8Array` to the host.
+    ///
+    /// # Errors
+    /// This function errs if message is of another type or sending it fails.
+    pub fn send(&self, message: &JsValue) -> Result<(), JsValue> {
+        self.inner
+            .send_message_to_host(&JsMessage::from_js(message)?)
+            .map_err(to_js_error)
+    }
+
+    /// Same as [`one_to_many::MiniClient::close`]
+    pub fn close(&self) {
+        self.inner.close();
+    }
+}
+
+/// JavaScript wrapper over [`many_to_many::NetworkManager`].
+#[wasm_bindgen]
+#[derive(Debug)]
+pub struct JsManyToMany {
+    inner: many_to_many::NetworkManager,
+}
+
+#[wasm_bindgen]
+impl JsManyToMany {
+    /// Same as [`many_to_many::NetworkManager::new`],
+    /// with connection type given as in [`ConnectionType::from_js_object`].
+    ///
+    /// # Errors
+    /// This function errs if any of the arguments is invalid or connecting to signaling server fails.
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        signaling_server_url: &str,
+        session_id: &str,
+        connection_type: &JsValue,
+    ) -> Result<JsManyToMany, JsValue> {
+        let (session_id, connection_type) =
+            parse_arguments(session_id, connection_type).map_err(to_js_error)?;
+        let inner =
+            many_to_many::NetworkManager::new(signaling_server_url, session_id, connection_type)
+                .map_err(to_js_error)?;
+        Ok(Self { inner })
+    }
+
+    /// Begins connecting to other peers, calls `on_open(userId)`, `on_message(userId, message)`
+    /// and `on_close(userId)`, where `userId` is a `BigInt`.
+    ///
+    /// # Errors
+    /// This function errs if it was already started.
+    pub fn start(
+        &mut self,
+        on_open: Function,
+        on_message: Function,
+        on_close: Function,
+    ) -> Result<(), JsValue> {
+        self.inner
+            .start_with_event_handler(js_event_handler(
+                on_open,
+                on_message,
+                on_close,
+                user_id_to_js,
+            ))
+            .map_err(to_js_error)
+    }
+
+    /// Sends a string or `Uint8Array` to the peer with `user_id`.
+    ///
+    /// # Errors
+    /// This function errs if message is of another type or sending it fails.
+    #[wasm_bindgen(js_name = sendTo)]
+    pub fn send_to(&self, user_id: u64, message: &JsValue) -> Result<(), JsValue> {
+        self.inner
+            .send_message(UserId::new(user_id), &JsMessage::from_js(message)?)
+            .map_err(to_js_error)
+    }
+
+    /// Sends a string or `Uint8Array` to all connected peers.
+    ///
+    /// # Errors
+    /// This function errs if message is of another type or sending it fails.
+    #[wasm_bindgen(js_name = sendToAll)]
+    pub fn send_to_all(&self, message: &JsValue) -> Result<(), JsValue> {
+        self.inner
+            .send_message_to_all(&JsMessage::from_js(message)?)
+            .map_err(to_js_error)
+    }
+
+    /// Same as [`many_to_many::NetworkManager::close`]
+    pub fn close(&self) {
+        self.inner.close();
+    }
+}
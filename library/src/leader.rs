@@ -0,0 +1,133 @@
+/*!
+Deterministic leader of a many-to-many session, so that peers agree on which of them coordinates the rest
+without exchanging any messages: it's the member with the lowest [`UserId`].
+
+Members are the ones reported by signaling server on joining the session,
+followed by peers whose data channel opened since and without the ones whose connection closed.
+*/
+
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::fmt::{Debug, Formatter};
+use std::rc::Rc;
+
+use wasm_peers_protocol::UserId;
+
+type LeaderChangedCallback = Rc<RefCell<dyn FnMut(UserId)>>;
+
+#[derive(Default)]
+struct LeaderElectionInner {
+    /// Set once signaling server reported members of the session, there's no leader before that
+    own_id: Option<UserId>,
+    members: BTreeSet<UserId>,
+    on_leader_changed: Option<LeaderChangedCallback>,
+}
+
+impl LeaderElectionInner {
+    fn leader(&self) -> Option<UserId> {
+        self.own_id?;
+        self.members.iter().next().copied()
+    }
+}
+
+/// Members of a session along with the callback notified once their leader changes.
+#[derive(Clone, Default)]
+pub struct LeaderElection {
+    inner: Rc<RefCell<LeaderElectionInner>>,
+}
+
+impl Debug for LeaderElection {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let inner = self.inner.borrow();
+        f.debug_struct("LeaderElection")
+            .field("own_id", &inner.own_id)
+            .field("members", &inner.members)
+            .finish_non_exhaustive()
+    }
+}
+
+impl LeaderElection {
+    #[cfg(feature = "many-to-many")]
+    pub fn set_on_leader_changed(&self, on_leader_changed: impl FnMut(UserId) + 'static) {
+        self.inner.borrow_mut().on_leader_changed = Some(Rc::new(RefCell::new(on_leader_changed)));
+    }
+
+    #[cfg(feature = "many-to-many")]
+    pub fn leader(&self) -> Option<UserId> {
+        self.inner.borrow().leader()
+    }
+
+    #[cfg(feature = "many-to-many")]
+    pub fn is_leader(&self) -> bool {
+        let inner = self.inner.borrow();
+        inner.own_id.is_some() && inner.own_id == inner.leader()
+    }
+
+    /// Records members of the session joined as `own_id`, reported by signaling server.
+    pub fn joined(&self, own_id: UserId, members: impl IntoIterator<Item = UserId>) {
+        self.update(|inner| {
+            inner.own_id = Some(own_id);
+            inner.members = members.into_iter().collect();
+            inner.members.insert(own_id);
+        });
+    }
+
+    /// Records `peer` joining the session, ignored until members of the session are known.
+    pub fn peer_joined(&self, peer: UserId) {
+        self.update(|inner| {
+            if inner.own_id.is_some() {
+                inner.members.insert(peer);
+            }
+        });
+    }
+
+    /// Records `peer` leaving the session, choosing the next leader if it was the current one.
+    pub fn peer_left(&self, peer: UserId) {
+        self.update(|inner| {
+            inner.members.remove(&peer);
+        });
+    }
+
+    fn update(&self, change: impl FnOnce(&mut LeaderElectionInner)) {
+        // clone the callback out, so that it can freely use the network manager
+        let (leader, callback) = {
+            let mut inner = self.inner.borrow_mut();
+            let previous = inner.leader();
+            change(&mut inner);
+            let leader = inner.leader();
+            if leader == previous {
+                return;
+            }
+            (leader, inner.on_leader_changed.clone())
+        };
+        if let (Some(leader), Some(callback)) = (leader, callback) {
+            (callback.borrow_mut())(leader);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "many-to-many"))]
+mod test {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+
+    #[wasm_bindgen_test]
+    fn test_lowest_member_leads_until_it_leaves() {
+        let election = LeaderElection::default();
+        let changes = Rc::new(RefCell::new(Vec::new()));
+        let recorded = Rc::clone(&changes);
+        election.set_on_leader_changed(move |leader| recorded.borrow_mut().push(leader));
+        election.peer_joined(UserId::new(1));
+        assert_eq!(election.leader(), None);
+
+        election.joined(UserId::new(5), [UserId::new(3), UserId::new(7)]);
+        assert_eq!(election.leader(), Some(UserId::new(3)));
+        assert!(!election.is_leader());
+        election.peer_joined(UserId::new(9));
+        election.peer_left(UserId::new(7));
+        election.peer_left(UserId::new(3));
+        assert!(election.is_leader());
+        assert_eq!(*changes.borrow(), vec![UserId::new(3), UserId::new(5)]);
+    }
+}
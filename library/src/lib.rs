@@ -15,8 +15,128 @@ After that you can send messages back and forth without worrying about the imple
 Library contains three network , [one-to-one](one_to_one), which creates an equal connection between two peers,
 [one-to-many](one_to_many), which specifies a host and arbitrary number of clients
 and [many-to-many](many_to_many) that creates connection for each pair of peers and allows sending messages to any of them.
-*/
 
+Instead of separate callbacks, all topologies can also be started with a single event handler
+via `start_with_event_handler`, which receives every [`PeerEvent`] - opened and closed connections,
+messages, connection state changes and signaling errors.
+
+# Message encoding
+
+By default messages are serialized with `MessagePack` and sent as binary data over unordered data channels.
+Enabling `json-messages` feature switches to JSON sent as text over ordered data channels,
+which makes the traffic readable in browser `DevTools` and by non-Rust peers.
+Both peers must use the same encoding.
+
+# Ordered delivery
+
+Messages sent over unordered data channels may arrive out of order.
+[`EnvelopeNetworkManager`](envelope::EnvelopeNetworkManager) wraps a network manager of any topology,
+numbering outgoing messages and delivering incoming ones in the order they were sent.
+
+# Topics
+
+[`PubSubNetworkManager`](pubsub::PubSubNetworkManager) wraps a network manager of any topology,
+routing messages of independent streams, each with its own type, to subscribers of their topics.
+
+# Message types
+
+[`TypedNetworkManager`](typed::TypedNetworkManager) wraps a network manager of any topology,
+passing each received message to the handler registered for its type.
+
+# Sharing sessions
+
+With `session-bootstrap` feature, [`SessionBootstrap`](session_bootstrap::SessionBootstrap)
+reads session id from the page URL, or generates one and puts it there,
+so that others join the session by opening the same link.
+
+# Synced values
+
+[`SyncedValue`](synced::SyncedValue) keeps a value, e.g. a shared document, in sync between all peers
+of a many-to-many session, merging concurrent writes with the last writer winning by default.
+
+# Native peers
+
+Enabling `native` feature provides [one-to-one network manager](one_to_one::native) for native (non-WASM) peers,
+built on `webrtc-rs` and `tokio`, that can connect with browser peers.
+
+# Node.js
+
+Enabling `node` feature provides `WebSocketKind::Node`, which connects to signaling server with `WebSocket`
+of the `ws` package, for WASM peers running in Node.js, which has no global `WebSocket` before version 22.
+It's passed to [`SignalingClient::with_websocket`] or `one_to_one::NetworkManager::with_websocket`.
+The package must be installed next to the generated bindings, and peer connections still need
+a global `RTCPeerConnection`, e.g. from a polyfill.
+
+# gloo-net
+
+Enabling `gloo-websocket` feature provides `WebSocketKind::Gloo`, which connects to signaling server with `WebSocket`
+of the `gloo-net` crate, driven by a single task that is dropped together with the connection instead of leaking closures.
+Messages sent while it's connecting are queued. Both features can be enabled together, constructors that don't take
+a [`WebSocketKind`] always open browser's `WebSocket`.
+
+# Web Workers
+
+Enabling `worker` feature keeps [connections with signaling server in a dedicated worker](worker),
+while peer connections stay on the main thread.
+
+# Yew
+
+Enabling `yew` feature provides [hooks] that own a network manager
+and re-render function components on incoming messages and connection events.
+
+# Bevy
+
+Enabling `bevy` feature provides [a plugin](bevy::WasmPeersPlugin) that inserts
+connections as an ECS resource and turns connection events and received messages into Bevy events.
+
+# Diagnostics
+
+When peers fail to connect, `diagnostics` method of every network manager gathers
+a [`DiagnosticsReport`] with the state of signaling and of each peer connection,
+which can be serialized to JSON and attached to a bug report.
+
+# Tracing
+
+Enabling `tracing` feature instruments network managers with `tracing` spans carrying `session_id`
+and `user_id` of the peer, so that log lines of several network managers alive in one page can be told apart.
+Connection lifecycle events are emitted as `tracing` events.
+Library still logs with `log`, a subscriber collecting `log` records (e.g. with `tracing-log`)
+records them within the current span.
+
+# Logging
+
+SDP and ICE candidates are logged with ICE credentials dropped and IP addresses masked,
+and Debug output of [`ConnectionType`] hides TURN credential, so that debug logs of released applications
+don't leak them. Enabling `log-full-sdp` feature logs SDP and candidates verbatim, for local debugging.
+
+# Network simulation
+
+Enabling `netem` feature adds `set_network_conditions` to network managers of all topologies,
+which delays, drops and reorders their messages according to [`NetworkConditions`],
+so that issues of real networks can be reproduced and tested with peers connected over localhost.
+It's meant for development builds only and is disabled by default.
+
+# Testing
+
+Enabling `test-utils` feature provides [in-memory signaling server](test_utils::LoopbackSignaling),
+so peers can be connected in browser tests without running the signaling server.
+
+# JavaScript
+
+Enabling `js-bindings` feature exports [wrapper classes](js) that let plain JavaScript apps
+use all topologies without writing any Rust.
+
+# Binary size
+
+Each topology, and the `web-sys` features only it needs, is compiled in just with its feature,
+so applications using one of them should disable default features, e.g.
+`wasm-peers = { version = "0.4", default-features = false, features = ["one-to-one"] }`.
+`get_random_session_id` and `get_random_stable_id` need `uuid` feature, which `session-bootstrap` enables,
+neither of them is enabled by default.
+Enabling `quiet` feature compiles out all log statements, by setting `max_level_off` feature of `log` crate,
+which applies to every crate of the application.
+*/
+#![doc = include_str!("sizes.md")]
 #![allow(
     clippy::module_name_repetitions,
     clippy::future_not_send, // false positive in WASM (single threaded) context
@@ -82,17 +202,83 @@ and [many-to-many](many_to_many) that creates connection for each pair of peers
     clippy::unseparated_literal_suffix,
     clippy::verbose_file_reads
 )]
+// `no-std` feature of the protocol crate is reported for every workspace member
+#![allow(clippy::negative_feature_names)]
 
+#[cfg(any(feature = "one-to-one", feature = "one-to-many"))]
+mod batch;
+#[cfg(feature = "bevy")]
+pub mod bevy;
 pub(crate) mod constants;
+mod diagnostics;
+pub mod envelope;
 mod error;
+mod event;
+#[cfg(feature = "gloo-websocket")]
+mod gloo_websocket;
+#[cfg(feature = "yew")]
+pub mod hooks;
+#[cfg(any(feature = "one-to-one", feature = "one-to-many"))]
+mod ice;
+#[cfg(feature = "js-bindings")]
+pub mod js;
+#[cfg(feature = "one-to-many")]
+mod leader;
 #[cfg(feature = "many-to-many")]
 pub mod many_to_many;
+#[cfg(any(feature = "one-to-one", feature = "one-to-many"))]
+mod netem;
+#[cfg(feature = "node")]
+mod node;
 #[cfg(feature = "one-to-many")]
 pub mod one_to_many;
 #[cfg(feature = "one-to-one")]
 pub mod one_to_one;
+mod peer_manager;
+#[cfg(feature = "one-to-many")]
+mod ping;
+pub mod pubsub;
+#[cfg(any(feature = "one-to-one", feature = "one-to-many"))]
+mod reliable;
+#[cfg(feature = "session-bootstrap")]
+pub mod session_bootstrap;
+#[cfg(any(feature = "one-to-one", feature = "one-to-many"))]
+mod session_list;
+mod signaling_client;
+mod span;
+#[cfg(feature = "many-to-many")]
+pub mod synced;
+#[cfg(feature = "test-utils")]
+pub mod test_utils;
+mod transport;
+pub mod typed;
 mod utils;
+#[cfg(feature = "worker")]
+pub mod worker;
 
-pub use error::{Error, Result};
-pub use utils::{get_random_session_id, ConnectionType};
-pub use wasm_peers_protocol::{SessionId, UserId};
+pub use diagnostics::{DataChannelDiagnostics, DiagnosticsReport, PeerDiagnostics};
+pub use error::{
+    AlreadyStartedError, ChannelModeError, Error, MessageTooLargeError, MultiSendError,
+    NotAcknowledgedError, ProtocolVersionMismatchError, Result,
+};
+pub use event::{CloseReason, PeerEvent};
+#[cfg(any(feature = "one-to-one", feature = "one-to-many"))]
+pub use ice::{CandidateFilter, CandidateInfo, CandidateSummary, CandidateType};
+#[cfg(all(
+    feature = "netem",
+    any(feature = "one-to-one", feature = "one-to-many")
+))]
+pub use netem::NetworkConditions;
+pub use peer_manager::PeerManager;
+pub use signaling_client::SignalingClient;
+pub use transport::WebSocketKind;
+#[cfg(feature = "uuid")]
+pub use utils::{get_random_session_id, get_random_stable_id};
+pub use utils::{
+    ice_candidate_from_js_value, ice_candidate_to_js_value, BatchConfig, ChannelState,
+    ConnectionState, ConnectionType, DataChannelConfig, JsConnectionType, NegotiationNeededHandler,
+    SdpMunger,
+};
+pub use wasm_peers_protocol::{
+    IceCandidate, JoinError, JoinIntent, SessionId, SessionInfo, StableId, TopologyType, UserId,
+};
@@ -13,7 +13,7 @@ In this example we create 3 peers that all establish connection with each other.
 Each of the peers will send a `ping` message to each new connection.
 Also each peer will respond with a `pong` response.
 Overall we will expect 6 `ping` and 6 `pong` messages (3 connections, both peers in each).
-```
+```no_run
 use wasm_peers::many_to_many::NetworkManager;
 use wasm_peers::{ConnectionType, SessionId};
 use std::cell::RefCell;
@@ -57,7 +57,7 @@ let peer_generator = || {
             }
         }
     };
-    peer.start(peer_on_open, peer_on_message);
+    peer.start(peer_on_open, peer_on_message).unwrap();
 };
 peer_generator();
 peer_generator();
@@ -65,12 +65,25 @@ peer_generator();
 ```
  */
 
+use std::fmt::{Debug, Display, Formatter};
+use std::future::Future;
+use std::time::Duration;
+
 use serde::de::DeserializeOwned;
 use serde::Serialize;
-use wasm_peers_protocol::{SessionId, UserId};
+use wasm_peers_protocol::{JoinIntent, SessionId, SessionInfo, UserId};
+use web_sys::{RtcDataChannelState, RtcPeerConnection, RtcSignalingState};
 
-use crate::one_to_many::NetworkManager as OneToManyNetworkManager;
-use crate::ConnectionType;
+use crate::one_to_many::{NetworkManager as OneToManyNetworkManager, RetryConfig};
+#[cfg(feature = "test-utils")]
+use crate::test_utils::{LoopbackSignaling, Topology};
+use crate::transport::SignalingConnection;
+#[cfg(feature = "netem")]
+use crate::NetworkConditions;
+use crate::{
+    CandidateFilter, CandidateInfo, CandidateSummary, ConnectionState, ConnectionType,
+    DataChannelConfig, DiagnosticsReport, PeerEvent,
+};
 
 /// Abstraction over `WebRTC` peer-to-peer connection.
 /// Structure representing equal peer in many-to-many topology.
@@ -87,11 +100,30 @@ use crate::ConnectionType;
 /// to allow possibility of referring to network manger itself from the callbacks.
 ///
 /// This class is a  pointer to the underlying resource and can be cloned freely.
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct NetworkManager {
     inner: OneToManyNetworkManager,
 }
 
+impl Debug for NetworkManager {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NetworkManager")
+            .field("session_id", &self.session_id())
+            .field("signaling_url", &self.signaling_url())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Display for NetworkManager {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "many-to-many peer in {}", self.session_id())?;
+        if let Some(signaling_url) = self.signaling_url() {
+            write!(f, " via {}", signaling_url)?;
+        }
+        Ok(())
+    }
+}
+
 impl NetworkManager {
     /// Creates an instance with all resources required to create a connections to other peers.
     /// Requires an  address of an signaling server instance,
@@ -114,26 +146,218 @@ impl NetworkManager {
         })
     }
 
+    /// Same as [`NetworkManager::new`], but creates a new session, reporting [`PeerEvent::SignalingError`]
+    /// with [`JoinError::AlreadyExists`](crate::JoinError::AlreadyExists) if it exists already.
+    ///
+    /// # Errors
+    /// This function errs if opening a `WebSocket` connection to URL provided by `signaling_server_url` fails.
+    pub fn new_create(
+        signaling_server_url: &str,
+        session_id: SessionId,
+        connection_type: ConnectionType,
+    ) -> crate::Result<Self> {
+        let network_manager = Self::new(signaling_server_url, session_id, connection_type)?;
+        network_manager.set_join_intent(JoinIntent::Create);
+        Ok(network_manager)
+    }
+
+    /// Same as [`NetworkManager::new`], but joins an existing session, e.g. with a code typed by the user,
+    /// reporting [`PeerEvent::SignalingError`] with [`JoinError::NoSuchSession`](crate::JoinError::NoSuchSession)
+    /// if it doesn't exist.
+    ///
+    /// # Errors
+    /// This function errs if opening a `WebSocket` connection to URL provided by `signaling_server_url` fails.
+    pub fn new_join(
+        signaling_server_url: &str,
+        session_id: SessionId,
+        connection_type: ConnectionType,
+    ) -> crate::Result<Self> {
+        let network_manager = Self::new(signaling_server_url, session_id, connection_type)?;
+        network_manager.set_join_intent(JoinIntent::Join);
+        Ok(network_manager)
+    }
+
+    /// Same as [`NetworkManager::new`], but signal messages are exchanged through `signaling`
+    /// instead of a signaling server.
+    #[cfg(feature = "test-utils")]
+    #[must_use]
+    pub fn with_loopback(
+        signaling: &LoopbackSignaling,
+        session_id: SessionId,
+        connection_type: ConnectionType,
+    ) -> Self {
+        Self::with_signaling(
+            SignalingConnection::Loopback(signaling.connect(Topology::ManyToMany)),
+            None,
+            session_id,
+            connection_type,
+        )
+    }
+
+    pub(crate) fn with_signaling(
+        signaling: SignalingConnection,
+        signaling_server_url: Option<String>,
+        session_id: SessionId,
+        connection_type: ConnectionType,
+    ) -> Self {
+        Self {
+            inner: OneToManyNetworkManager::with_signaling(
+                signaling,
+                signaling_server_url,
+                session_id,
+                connection_type,
+                true,
+            ),
+        }
+    }
+
+    /// Same as [`NetworkManager::new`], but resolves only once connection with signaling server is open.
+    ///
+    /// # Errors
+    /// This function errs if opening a `WebSocket` connection to URL provided by `signaling_server_url` fails.
+    pub async fn new_async(
+        signaling_server_url: &str,
+        session_id: SessionId,
+        connection_type: ConnectionType,
+    ) -> crate::Result<Self> {
+        Ok(Self {
+            inner: OneToManyNetworkManager::new_async(
+                signaling_server_url,
+                session_id,
+                connection_type,
+                true,
+            )
+            .await?,
+        })
+    }
+
     /// Second part of the setup that begins the actual connection.
     /// Requires specifying a callbacks that are guaranteed to run
     /// when a new connection opens and on each message received.
     /// It takes [`UserId`] as an argument which helps identify sending peer.
-    pub fn start<T: DeserializeOwned>(
+    ///
+    /// # Errors
+    /// Errs with [`AlreadyStartedError`](crate::AlreadyStartedError) if it was already started.
+    pub fn start<T: DeserializeOwned + 'static>(
         &mut self,
-        on_open_callback: impl FnMut(UserId) + Clone + 'static,
-        on_message_callback: impl FnMut(UserId, T) + Clone + 'static,
-    ) {
-        self.inner.start(on_open_callback, on_message_callback);
+        on_open_callback: impl FnMut(UserId) + 'static,
+        on_message_callback: impl FnMut(UserId, T) + 'static,
+    ) -> crate::Result<()> {
+        self.inner.start(on_open_callback, on_message_callback)
+    }
+
+    /// # Errors
+    /// Errs with [`AlreadyStartedError`](crate::AlreadyStartedError) if it was already started.
+    pub fn start_with_retransmits<T: DeserializeOwned + 'static>(
+        &mut self,
+        max_retransmits: u16,
+        on_open_callback: impl FnMut(UserId) + 'static,
+        on_message_callback: impl FnMut(UserId, T) + 'static,
+    ) -> crate::Result<()> {
+        self.inner
+            .start_with_retransmits(max_retransmits, on_open_callback, on_message_callback)
     }
 
-    pub fn start_with_retransmits<T: DeserializeOwned>(
+    /// Alternative to [`NetworkManager::start`] with a single handler receiving every [`PeerEvent`],
+    /// including peers leaving the session and signaling errors.
+    ///
+    /// # Errors
+    /// Errs with [`AlreadyStartedError`](crate::AlreadyStartedError) if it was already started.
+    pub fn start_with_event_handler<T: DeserializeOwned + 'static>(
+        &mut self,
+        handler: impl FnMut(PeerEvent<T>) + 'static,
+    ) -> crate::Result<()> {
+        self.inner.start_with_event_handler(handler)
+    }
+
+    /// # Errors
+    /// Errs with [`AlreadyStartedError`](crate::AlreadyStartedError) if it was already started.
+    pub fn start_with_event_handler_and_retransmits<T: DeserializeOwned + 'static>(
         &mut self,
         max_retransmits: u16,
-        on_open_callback: impl FnMut(UserId) + Clone + 'static,
-        on_message_callback: impl FnMut(UserId, T) + Clone + 'static,
+        handler: impl FnMut(PeerEvent<T>) + 'static,
+    ) -> crate::Result<()> {
+        self.inner
+            .start_with_event_handler_and_retransmits(max_retransmits, handler)
+    }
+
+    /// Sets how connections with peers are re-created after their ICE fails.
+    /// Only the peer which initiated the connection retries it.
+    pub fn set_retry_config(&self, retry_config: RetryConfig) {
+        self.inner.set_retry_config(retry_config);
+    }
+
+    /// Same as [`one_to_many::NetworkManager::set_join_intent`](OneToManyNetworkManager::set_join_intent)
+    pub fn set_join_intent(&self, intent: JoinIntent) {
+        self.inner.set_join_intent(intent);
+    }
+
+    /// Same as [`one_to_many::NetworkManager::set_data_channel_config`](OneToManyNetworkManager::set_data_channel_config)
+    pub fn set_data_channel_config(&self, data_channel_config: DataChannelConfig) {
+        self.inner.set_data_channel_config(data_channel_config);
+    }
+
+    /// Same as [`one_to_many::NetworkManager::set_network_conditions`](OneToManyNetworkManager::set_network_conditions)
+    #[cfg(feature = "netem")]
+    pub fn set_network_conditions(&self, conditions: NetworkConditions) {
+        self.inner.set_network_conditions(conditions);
+    }
+
+    /// Same as [`one_to_many::NetworkManager::set_ping_timeout`](OneToManyNetworkManager::set_ping_timeout)
+    pub fn set_ping_timeout(&self, ping_timeout: Duration) {
+        self.inner.set_ping_timeout(ping_timeout);
+    }
+
+    /// Registers a callback notified whenever connection with one of the peers changes its state,
+    /// including each retry attempt and the final outcome.
+    pub fn set_on_connection_state_change(
+        &self,
+        on_connection_state_change: impl FnMut(UserId, ConnectionState) + 'static,
     ) {
         self.inner
-            .start_with_retransmits(max_retransmits, on_open_callback, on_message_callback);
+            .set_on_connection_state_change(on_connection_state_change);
+    }
+
+    /// Same as [`one_to_many::NetworkManager::set_on_local_candidate`](OneToManyNetworkManager::set_on_local_candidate)
+    pub fn set_on_local_candidate(
+        &self,
+        on_local_candidate: impl FnMut(UserId, CandidateInfo) + 'static,
+    ) {
+        self.inner.set_on_local_candidate(on_local_candidate);
+    }
+
+    /// Same as [`one_to_many::NetworkManager::set_on_gathering_complete`](OneToManyNetworkManager::set_on_gathering_complete)
+    pub fn set_on_gathering_complete(&self, on_gathering_complete: impl FnMut(UserId) + 'static) {
+        self.inner.set_on_gathering_complete(on_gathering_complete);
+    }
+
+    /// Same as [`one_to_many::NetworkManager::gathered_candidate_summary`](OneToManyNetworkManager::gathered_candidate_summary)
+    #[must_use]
+    pub fn gathered_candidate_summary(&self) -> CandidateSummary {
+        self.inner.gathered_candidate_summary()
+    }
+
+    /// Same as [`one_to_many::NetworkManager::set_candidate_filter`](OneToManyNetworkManager::set_candidate_filter)
+    pub fn set_candidate_filter(&self, filter: impl Into<CandidateFilter>) {
+        self.inner.set_candidate_filter(filter);
+    }
+
+    /// Enables relaying, disabled by default. Once direct connection with a peer fails for good,
+    /// messages to it are sent through another peer connected to both, which forwards them,
+    /// and connection state changes to [`ConnectionState::Relayed`].
+    /// A relayed message is forwarded only once, never through a chain of peers.
+    ///
+    /// Only peers with relaying enabled forward messages and accept relayed ones,
+    /// so all peers of the session should enable it. Disabling it stops relaying right away.
+    pub fn set_relay_enabled(&self, enabled: bool) {
+        self.inner.set_relay_enabled(enabled);
+    }
+
+    /// Peer through which messages to `user_id` are relayed, `None` if they're sent directly
+    /// or can't be sent at all.
+    #[must_use]
+    pub fn relayed_via(&self, user_id: UserId) -> Option<UserId> {
+        self.inner.relayed_via(user_id)
     }
 
     /// Sends message over established data channel to a single peer represented by
@@ -153,6 +377,29 @@ impl NetworkManager {
         self.inner.send_message(user_id, message)
     }
 
+    /// Same as [`one_to_many::NetworkManager::send_message_reliably`](OneToManyNetworkManager::send_message_reliably),
+    /// e.g. for a "game over" message that mustn't get lost.
+    pub fn send_message_reliably<T: Serialize + ?Sized>(
+        &self,
+        user_id: UserId,
+        message: &T,
+    ) -> impl Future<Output = crate::Result<()>> {
+        self.inner.send_message_reliably(user_id, message)
+    }
+
+    /// Sends the same message to a group of peers, serializing it only once.
+    ///
+    /// # Errors
+    /// This function errs if serialization fails or with [`MultiSendError`](crate::MultiSendError)
+    /// listing each peer to whom the message couldn't be sent.
+    pub fn send_message_to_many<T: Serialize + ?Sized>(
+        &self,
+        user_ids: &[UserId],
+        message: &T,
+    ) -> crate::Result<()> {
+        self.inner.send_message_to_many(user_ids, message)
+    }
+
     /// Send message to a all connected client-users.
     ///
     /// # Errors
@@ -164,4 +411,151 @@ impl NetworkManager {
     pub fn send_message_to_all<T: Serialize + ?Sized>(&self, message: &T) -> crate::Result<()> {
         self.inner.send_message_to_all(message)
     }
+
+    /// Same as [`one_to_many::NetworkManager::flush`](OneToManyNetworkManager::flush)
+    ///
+    /// # Errors
+    /// This function errs with [`MultiSendError`](crate::MultiSendError) listing each peer
+    /// whose batch couldn't be sent.
+    pub fn flush(&self) -> crate::Result<()> {
+        self.inner.flush()
+    }
+
+    /// Session given to [`NetworkManager::new`].
+    #[must_use]
+    pub fn session_id(&self) -> SessionId {
+        self.inner.session_id()
+    }
+
+    /// Address of the signaling server, `None` with loopback signaling.
+    #[must_use]
+    pub fn signaling_url(&self) -> Option<String> {
+        self.inner.signaling_url()
+    }
+
+    /// Same as [`one_to_many::NetworkManager::connection_type`](OneToManyNetworkManager::connection_type)
+    #[must_use]
+    pub fn connection_type(&self) -> ConnectionType {
+        self.inner.connection_type()
+    }
+
+    /// Same as [`one_to_many::NetworkManager::update_connection_type`](OneToManyNetworkManager::update_connection_type)
+    pub fn update_connection_type(&self, connection_type: ConnectionType) {
+        self.inner.update_connection_type(connection_type);
+    }
+
+    /// Same as [`one_to_many::NetworkManager::set_ice_config_provider`](OneToManyNetworkManager::set_ice_config_provider)
+    pub fn set_ice_config_provider<F>(&self, provider: impl Fn() -> F + 'static)
+    where
+        F: Future<Output = ConnectionType> + 'static,
+    {
+        self.inner.set_ice_config_provider(provider);
+    }
+
+    /// Signaling state of the peer connection with `user_id`, `None` if there is no connection with it.
+    #[must_use]
+    pub fn signaling_state_for(&self, user_id: UserId) -> Option<RtcSignalingState> {
+        self.inner.signaling_state_for(user_id)
+    }
+
+    /// Same as [`one_to_many::NetworkManager::channel_ready_state_for`](OneToManyNetworkManager::channel_ready_state_for)
+    #[must_use]
+    pub fn channel_ready_state_for(&self, user_id: UserId) -> Option<RtcDataChannelState> {
+        self.inner.channel_ready_state_for(user_id)
+    }
+
+    /// Same as [`one_to_many::NetworkManager::negotiated_max_message_size_for`](OneToManyNetworkManager::negotiated_max_message_size_for)
+    #[must_use]
+    pub fn negotiated_max_message_size_for(&self, user_id: UserId) -> Option<usize> {
+        self.inner.negotiated_max_message_size_for(user_id)
+    }
+
+    /// Same as [`one_to_many::NetworkManager::peer_connection_for`](OneToManyNetworkManager::peer_connection_for)
+    #[must_use]
+    pub fn peer_connection_for(&self, user_id: UserId) -> Option<RtcPeerConnection> {
+        self.inner.peer_connection_for(user_id)
+    }
+
+    /// Same as [`one_to_many::NetworkManager::pending_peers`](OneToManyNetworkManager::pending_peers)
+    #[must_use]
+    pub fn pending_peers(&self) -> Vec<UserId> {
+        self.inner.pending_peers()
+    }
+
+    /// Same as [`one_to_many::NetworkManager::connected_peers`](OneToManyNetworkManager::connected_peers)
+    #[must_use]
+    pub fn connected_peers(&self) -> Vec<UserId> {
+        self.inner.connected_peers()
+    }
+
+    /// Number of peers with open data channel, pending connections aren't counted.
+    /// Once everyone in a session of `n` peers is connected, it's `n - 1` for each of them.
+    #[must_use]
+    pub fn mesh_size(&self) -> usize {
+        self.inner.connected_peers().len()
+    }
+
+    /// Whether data channels with all other peers are open, `expected_peers` including this one,
+    /// e.g. to wait until everyone in a game lobby is connected.
+    #[must_use]
+    pub fn is_fully_meshed(&self, expected_peers: usize) -> bool {
+        self.mesh_size() == expected_peers.saturating_sub(1)
+    }
+
+    /// Leader of the session, the peer with the lowest [`UserId`] among this one and peers it's connected to,
+    /// so that all peers agree on it without exchanging any messages.
+    /// `None` until signaling server reports members of the session right after joining it.
+    #[must_use]
+    pub fn current_leader(&self) -> Option<UserId> {
+        self.inner.leader_election().leader()
+    }
+
+    /// Whether this peer is the leader of the session, see [`NetworkManager::current_leader`].
+    #[must_use]
+    pub fn is_leader(&self) -> bool {
+        self.inner.leader_election().is_leader()
+    }
+
+    /// Registers a callback called with the new leader of the session once it's first known
+    /// and every time the leader leaves, see [`NetworkManager::current_leader`].
+    pub fn set_on_leader_changed(&self, on_leader_changed: impl FnMut(UserId) + 'static) {
+        self.inner
+            .leader_election()
+            .set_on_leader_changed(on_leader_changed);
+    }
+
+    /// Same as [`one_to_many::NetworkManager::ping_latency`](OneToManyNetworkManager::ping_latency)
+    ///
+    /// # Errors
+    /// This function errs if there is no data channel with `user_id` yet or sending the ping fails.
+    pub fn ping_latency(
+        &self,
+        user_id: UserId,
+    ) -> impl Future<Output = crate::Result<Option<Duration>>> {
+        self.inner.ping_latency(user_id)
+    }
+
+    /// Pings all connected peers at once, e.g. for a game host to find the one with the highest latency
+    /// and adjust its tick rate. Same as
+    /// [`one_to_many::NetworkManager::ping_latency_all`](OneToManyNetworkManager::ping_latency_all)
+    pub fn ping_latency_all(&self) -> impl Future<Output = Vec<(UserId, Option<Duration>)>> {
+        self.inner.ping_latency_all()
+    }
+
+    /// Same as [`one_to_many::NetworkManager::diagnostics`](OneToManyNetworkManager::diagnostics)
+    pub fn diagnostics(&self) -> impl Future<Output = DiagnosticsReport> {
+        self.inner.diagnostics()
+    }
+
+    /// Same as [`one_to_many::NetworkManager::list_sessions`](OneToManyNetworkManager::list_sessions),
+    /// but server lists sessions of its many-to-many endpoint.
+    pub fn list_sessions(&self) -> impl Future<Output = Vec<SessionInfo>> {
+        self.inner.list_sessions()
+    }
+
+    /// Closes connections with all peers and the connection with signaling server.
+    /// Network manager shouldn't be used afterwards.
+    pub fn close(&self) {
+        self.inner.close();
+    }
 }
@@ -0,0 +1,179 @@
+/*!
+Artificial latency, loss and reordering of data channel messages, enabled with `netem` feature,
+so that desyncs reported by users can be reproduced with peers connected over localhost.
+
+Messages sent with `send_message` methods and everything received on data channels are passed through
+the [`NetworkConditions`] set with `set_network_conditions` of a network manager.
+Delayed messages wait in `setTimeout` before they are sent or handed to callbacks,
+and errors of delayed sends are only logged, as the send call has already returned.
+
+Without `netem` feature messages are sent and received right away and none of it is compiled in.
+*/
+
+#[cfg(feature = "netem")]
+use std::cell::Cell;
+#[cfg(feature = "netem")]
+use std::rc::Rc;
+#[cfg(feature = "netem")]
+use std::time::Duration;
+
+#[cfg(feature = "netem")]
+use log::{debug, error};
+#[cfg(feature = "netem")]
+use wasm_bindgen_futures::spawn_local;
+
+#[cfg(feature = "netem")]
+use crate::utils::{random_u128, sleep};
+
+/// How much longer than any other message a reordered one is held back, so that messages sent after it overtake it
+#[cfg(feature = "netem")]
+const REORDER_DELAY_MS: u32 = 20;
+
+/// Conditions of a simulated network, the default one being perfect.
+///
+/// ```no_run
+/// use wasm_peers::one_to_one::NetworkManager;
+/// use wasm_peers::{ConnectionType, NetworkConditions, SessionId};
+///
+/// let network_manager = NetworkManager::new(
+///     "ws://0.0.0.0:9001/one-to-one",
+///     SessionId::new(1),
+///     ConnectionType::Local,
+/// )
+/// .unwrap();
+/// network_manager.set_network_conditions(NetworkConditions {
+///     latency_ms: 100,
+///     jitter_ms: 30,
+///     loss_pct: 5,
+///     reorder_pct: 10,
+/// });
+/// ```
+#[cfg(feature = "netem")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NetworkConditions {
+    /// Delay of every message, in milliseconds
+    pub latency_ms: u32,
+    /// Maximum random delay added on top of `latency_ms`, in milliseconds
+    pub jitter_ms: u32,
+    /// Percentage of messages that are dropped
+    pub loss_pct: u8,
+    /// Percentage of messages held back until messages sent after them are delivered
+    pub reorder_pct: u8,
+}
+
+/// Applies [`NetworkConditions`] to messages of a network manager, shared by its clones.
+#[derive(Debug, Clone, Default)]
+pub struct NetworkEmulator {
+    #[cfg(feature = "netem")]
+    conditions: Rc<Cell<NetworkConditions>>,
+}
+
+/// When a message is delivered on a simulated network.
+#[cfg(feature = "netem")]
+enum Delivery {
+    Now,
+    After(Duration),
+    Never,
+}
+
+impl NetworkEmulator {
+    /// Applies `conditions` to messages sent and received from now on.
+    #[cfg(feature = "netem")]
+    pub fn set_conditions(&self, conditions: NetworkConditions) {
+        self.conditions.set(conditions);
+    }
+
+    /// Calls `send` right away.
+    #[cfg(not(feature = "netem"))]
+    #[allow(clippy::unused_self)]
+    pub fn send(&self, send: impl FnOnce() -> crate::Result<()> + 'static) -> crate::Result<()> {
+        send()
+    }
+
+    /// Calls `receive` right away.
+    #[cfg(not(feature = "netem"))]
+    #[allow(clippy::unused_self)]
+    pub fn receive(&self, receive: impl FnOnce() + 'static) {
+        receive();
+    }
+
+    /// Calls `send` right away on a perfect network, returning its result,
+    /// otherwise after a simulated delay, or never if the message is lost.
+    #[cfg(feature = "netem")]
+    pub fn send(&self, send: impl FnOnce() -> crate::Result<()> + 'static) -> crate::Result<()> {
+        match self.delivery() {
+            Delivery::Now => send(),
+            Delivery::After(delay) => {
+                deliver_after(delay, move || {
+                    if let Err(err) = send() {
+                        error!("failed to send delayed message: {}", err);
+                    }
+                });
+                Ok(())
+            }
+            Delivery::Never => Ok(()),
+        }
+    }
+
+    /// Calls `receive` right away on a perfect network,
+    /// otherwise after a simulated delay, or never if the message is lost.
+    #[cfg(feature = "netem")]
+    pub fn receive(&self, receive: impl FnOnce() + 'static) {
+        match self.delivery() {
+            Delivery::Now => receive(),
+            Delivery::After(delay) => deliver_after(delay, receive),
+            Delivery::Never => {}
+        }
+    }
+
+    #[cfg(feature = "netem")]
+    fn delivery(&self) -> Delivery {
+        let conditions = self.conditions.get();
+        if chance(conditions.loss_pct) {
+            debug!("simulated network dropped a message");
+            return Delivery::Never;
+        }
+        let delay_ms = if chance(conditions.reorder_pct) {
+            conditions
+                .latency_ms
+                .saturating_add(conditions.jitter_ms)
+                .saturating_add(REORDER_DELAY_MS)
+        } else {
+            conditions
+                .latency_ms
+                .saturating_add(random_below(conditions.jitter_ms.saturating_add(1)))
+        };
+        if delay_ms == 0 {
+            Delivery::Now
+        } else {
+            Delivery::After(Duration::from_millis(u64::from(delay_ms)))
+        }
+    }
+}
+
+/// Calls `deliver` once `delay` passes, or right away if it can't wait, e.g. outside of a window.
+#[cfg(feature = "netem")]
+fn deliver_after(delay: Duration, deliver: impl FnOnce() + 'static) {
+    spawn_local(async move {
+        if let Err(err) = sleep(delay).await {
+            error!("failed to delay message: {}", err);
+        }
+        deliver();
+    });
+}
+
+/// Whether an event happening `pct` percent of times happens.
+#[cfg(feature = "netem")]
+fn chance(pct: u8) -> bool {
+    random_below(100) < u32::from(pct)
+}
+
+/// Random number in `0..bound`, zero if `bound` is zero.
+#[cfg(feature = "netem")]
+fn random_below(bound: u32) -> u32 {
+    let random = random_u128();
+    random
+        .checked_rem(u128::from(bound))
+        .and_then(|random| u32::try_from(random).ok())
+        .unwrap_or(0)
+}
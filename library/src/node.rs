@@ -0,0 +1,71 @@
+/*!
+Signaling in Node.js, which has no global `WebSocket` before version 22, through the [`ws`](https://www.npmjs.com/package/ws)
+package instead, which has to be installed next to the generated bindings.
+
+`ws` implements the browser API, so calls are made through [`WebSocket`] bindings of `web-sys`,
+which look methods up on the object itself.
+Peer connections still require a global `RTCPeerConnection`, e.g. from a polyfill.
+*/
+
+use futures_util::future::LocalBoxFuture;
+use wasm_bindgen::prelude::wasm_bindgen;
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::WebSocket;
+
+use crate::transport::{OnMessage, WebSocketLike};
+
+#[wasm_bindgen(module = "ws")]
+extern "C" {
+    /// `WebSocket` class of the `ws` package.
+    #[wasm_bindgen(js_name = WebSocket)]
+    #[derive(Debug, Clone)]
+    pub type NodeWebSocket;
+
+    #[wasm_bindgen(constructor, js_class = "WebSocket", catch)]
+    fn new(url: &str) -> Result<NodeWebSocket, JsValue>;
+}
+
+impl NodeWebSocket {
+    /// Opens connection receiving binary messages as `ArrayBuffer`s.
+    pub fn open(url: &str) -> Result<Self, JsValue> {
+        let websocket = Self::new(url)?;
+        websocket
+            .as_websocket()
+            .set_binary_type(web_sys::BinaryType::Arraybuffer);
+        Ok(websocket)
+    }
+
+    fn as_websocket(&self) -> &WebSocket {
+        self.unchecked_ref()
+    }
+}
+
+impl WebSocketLike for NodeWebSocket {
+    fn send(&self, message: &[u8]) -> crate::Result<()> {
+        WebSocketLike::send(self.as_websocket(), message)
+    }
+
+    fn wait_for_open(&self) -> LocalBoxFuture<'_, crate::Result<()>> {
+        self.as_websocket().wait_for_open()
+    }
+
+    fn set_on_open(&self, on_open: Box<dyn FnMut()>) {
+        self.as_websocket().set_on_open(on_open);
+    }
+
+    fn set_on_message(&self, on_message: OnMessage) {
+        self.as_websocket().set_on_message(on_message);
+    }
+
+    fn set_on_close(&self, on_close: Box<dyn FnMut()>) {
+        self.as_websocket().set_on_close(on_close);
+    }
+
+    fn ready_state_name(&self) -> &'static str {
+        self.as_websocket().ready_state_name()
+    }
+
+    fn close(&self) {
+        WebSocketLike::close(self.as_websocket());
+    }
+}
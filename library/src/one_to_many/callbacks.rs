@@ -1,42 +1,53 @@
-use js_sys::Uint8Array;
+use std::rc::Rc;
+
 use log::{debug, error, info};
 use serde::de::DeserializeOwned;
 use wasm_bindgen::closure::Closure;
 use wasm_bindgen::{JsCast, JsValue};
 use wasm_peers_protocol::one_to_many::SignalMessage;
-use wasm_peers_protocol::{IceCandidate, SessionId, UserId};
+use wasm_peers_protocol::{
+    IceCandidate, JoinIntent, SessionId, StableId, UserId, PROTOCOL_MAJOR_VERSION,
+    PROTOCOL_MINOR_VERSION,
+};
 use web_sys::{
-    MessageEvent, RtcDataChannel, RtcDataChannelEvent, RtcPeerConnection,
-    RtcPeerConnectionIceEvent, WebSocket,
+    MessageEvent, RtcDataChannel, RtcDataChannelEvent, RtcIceConnectionState, RtcPeerConnection,
+    RtcPeerConnectionIceEvent,
 };
 
+use crate::batch;
+use crate::event::{EventHandler, LifecycleEvent};
+use crate::ice::IceGathering;
 use crate::one_to_many::{websocket_handler, NetworkManager};
+use crate::span::in_current_span;
+use crate::transport::{send_signal_message, SignalingConnection, SignalingTransport};
+use crate::utils::deserialize_message;
+use crate::{ChannelState, CloseReason, ConnectionState, PeerEvent};
 
 /// Also calls:
 /// * `set_data_channel_on_open`
 /// * `set_data_channel_on_message`
 /// * `set_data_channel_on_error`
-pub fn set_peer_connection_on_data_channel<T: DeserializeOwned>(
+/// * `set_data_channel_on_close`
+pub fn set_peer_connection_on_data_channel<T: DeserializeOwned + 'static>(
     peer_connection: &RtcPeerConnection,
     client_id: UserId,
     network_manager: NetworkManager,
-    on_open_callback: impl FnMut(UserId) + Clone + 'static,
-    on_message_callback: impl FnMut(UserId, T) + Clone + 'static,
+    event_handler: EventHandler<T>,
 ) {
-    let on_open_callback_clone = on_open_callback;
-    let on_message_callback_clone = on_message_callback;
-    let on_datachannel: Box<dyn FnMut(RtcDataChannelEvent)> =
-        Box::new(move |data_channel_event: RtcDataChannelEvent| {
+    let on_datachannel: Box<dyn FnMut(RtcDataChannelEvent)> = Box::new(in_current_span(
+        move |data_channel_event: RtcDataChannelEvent| {
             info!("received data channel");
             let data_channel = data_channel_event.channel();
 
-            set_data_channel_on_open(&data_channel, client_id, on_open_callback_clone.clone());
+            set_data_channel_on_open(&data_channel, client_id, network_manager.clone());
             set_data_channel_on_error(&data_channel);
             set_data_channel_on_message(
                 &data_channel,
                 client_id,
-                on_message_callback_clone.clone(),
+                network_manager.clone(),
+                Rc::clone(&event_handler),
             );
+            set_data_channel_on_close(&data_channel, client_id, network_manager.clone());
 
             if let Some(connection) = network_manager
                 .inner
@@ -48,98 +59,119 @@ pub fn set_peer_connection_on_data_channel<T: DeserializeOwned>(
             } else {
                 error!("no connection for given id: {}", client_id);
             }
-        });
+        },
+    ));
     let on_datachannel = Closure::wrap(on_datachannel);
     peer_connection.set_ondatachannel(Some(on_datachannel.as_ref().unchecked_ref()));
     on_datachannel.forget();
 }
 
 /// handle message sent by signaling server
-pub fn set_websocket_on_message<T: DeserializeOwned>(
-    websocket: &WebSocket,
+pub fn set_websocket_on_message<T: DeserializeOwned + 'static>(
+    signaling: &SignalingConnection,
     network_manager: NetworkManager,
     max_retransmits: u16,
-    on_open_callback: impl FnMut(UserId) + Clone + 'static,
-    on_message_callback: impl FnMut(UserId, T) + Clone + 'static,
+    event_handler: EventHandler<T>,
     is_host: bool,
 ) {
-    let on_message_callback = {
-        let websocket = websocket.clone();
-        let on_message_callback: Box<dyn FnMut(MessageEvent)> =
-            Box::new(move |ev: MessageEvent| {
-                let Ok(message) = ev.data().dyn_into::<Uint8Array>().map(|v| v.to_vec()) else {
-                    error!("failed to convert message to Uint8Array");
-                    return;
-                };
-                let Ok(message) = rmp_serde::from_slice(message.as_slice()) else {
-                    error!("failed to deserialize message");
-                    return;
-                };
-                let network_manager = network_manager.clone();
-                let websocket = websocket.clone();
-                let on_open_callback_clone = on_open_callback.clone();
-                let on_message_callback_clone = on_message_callback.clone();
-                wasm_bindgen_futures::spawn_local(async move {
-                    if let Err(err) = websocket_handler::handle_websocket_message(
-                        network_manager,
-                        message,
-                        websocket,
-                        max_retransmits,
-                        on_open_callback_clone,
-                        on_message_callback_clone,
-                        is_host,
-                    )
-                    .await
-                    {
-                        error!("failed to handle websocket message: {}", err);
-                    }
-                });
-            });
-        Closure::wrap(on_message_callback)
-    };
-    websocket.set_onmessage(Some(on_message_callback.as_ref().unchecked_ref()));
-    on_message_callback.forget();
+    let signaling_clone = signaling.clone();
+    signaling.set_on_message(move |message: &[u8]| {
+        let Ok(message) = rmp_serde::from_slice(message) else {
+            error!("failed to deserialize message");
+            return;
+        };
+        let network_manager = network_manager.clone();
+        let signaling_clone = signaling_clone.clone();
+        let event_handler = Rc::clone(&event_handler);
+        wasm_bindgen_futures::spawn_local(async move {
+            if let Err(err) = websocket_handler::handle_websocket_message(
+                network_manager.clone(),
+                message,
+                signaling_clone,
+                max_retransmits,
+                event_handler,
+                is_host,
+            )
+            .await
+            {
+                error!("failed to handle websocket message: {}", err);
+                network_manager.notify(LifecycleEvent::SignalingError(err.to_string()));
+            }
+        });
+    });
 }
 
 /// once web socket is open, send a request to start or join a session
-pub fn set_websocket_on_open(websocket: &WebSocket, session_id: SessionId, is_host: bool) {
-    let on_open_callback = {
-        let websocket = websocket.clone();
-        let on_open_callback: Box<dyn FnMut(JsValue)> = Box::new(move |_| {
-            let signal_message = SignalMessage::SessionJoin(session_id, is_host);
-            let signal_message =
-                rmp_serde::to_vec(&signal_message).expect("failed serializing SignalMessage");
-            if let Err(err) = websocket.send_with_u8_array(&signal_message) {
-                error!("failed to send signal message: {:?}", err);
-            }
-        });
-        Closure::wrap(on_open_callback)
-    };
-    websocket.set_onopen(Some(on_open_callback.as_ref().unchecked_ref()));
-    on_open_callback.forget();
+pub fn set_websocket_on_open(
+    signaling: &SignalingConnection,
+    session_id: SessionId,
+    is_host: bool,
+    stable_id: Option<StableId>,
+    join_intent: JoinIntent,
+) {
+    let signaling_clone = signaling.clone();
+    signaling.set_on_open(move || {
+        // announced first, so that signaling server speaking an incompatible version refuses the connection
+        let protocol_version = SignalMessage::ProtocolVersion {
+            major: PROTOCOL_MAJOR_VERSION,
+            minor: PROTOCOL_MINOR_VERSION,
+        };
+        if let Err(err) = send_signal_message(&signaling_clone, &protocol_version) {
+            error!("failed to send protocol version: {}", err);
+        }
+        // plain join is understood by signaling servers not knowing about intents
+        let signal_message = match (stable_id, join_intent) {
+            (Some(stable_id), _) => SignalMessage::SessionJoinAs(session_id, stable_id),
+            (None, JoinIntent::CreateOrJoin) => SignalMessage::SessionJoin(session_id, is_host),
+            (None, intent) => SignalMessage::SessionJoinWithIntent(session_id, is_host, intent),
+        };
+        if let Err(err) = send_signal_message(&signaling_clone, &signal_message) {
+            error!("failed to send signal message: {}", err);
+        }
+    });
 }
 
-pub fn set_data_channel_on_message<T: DeserializeOwned>(
+pub fn set_data_channel_on_message<T: DeserializeOwned + 'static>(
     data_channel: &RtcDataChannel,
     client_id: UserId,
-    mut on_message_callback: impl FnMut(UserId, T) + 'static,
+    network_manager: NetworkManager,
+    event_handler: EventHandler<T>,
 ) {
-    let on_message_callback: Box<dyn FnMut(MessageEvent)> = Box::new(move |ev: MessageEvent| {
-        let message = ev.data().dyn_into::<Uint8Array>().ok();
-        if let Some(message) = message.and_then(|t| rmp_serde::from_slice(&t.to_vec()).ok()) {
-            debug!("message from datachannel (will call on_message)");
-            on_message_callback(client_id, message);
+    let data_channel_clone = data_channel.clone();
+    let pending_pings = network_manager.inner.borrow().pending_pings.clone();
+    let reliable_messages = network_manager.inner.borrow().reliable_messages.clone();
+    let network_emulator = network_manager.inner.borrow().network_emulator.clone();
+    let handle_data = Rc::new(move |data: JsValue| {
+        if pending_pings.handle_frame(&data_channel_clone, &data)
+            || network_manager.handle_relay_frame(client_id, &data, &event_handler)
+        {
+            return;
+        }
+        let Some(data) = reliable_messages.receive(client_id, &data_channel_clone, data) else {
+            return;
+        };
+        for message_data in batch::unpack(data) {
+            if let Some(message) = deserialize_message(&message_data) {
+                debug!("message from datachannel (will call on_message)");
+                (event_handler.borrow_mut())(PeerEvent::Message(client_id, message));
+            }
         }
     });
+    let on_message_callback: Box<dyn FnMut(MessageEvent)> =
+        Box::new(in_current_span(move |ev: MessageEvent| {
+            let handle_data = Rc::clone(&handle_data);
+            let data = ev.data();
+            network_emulator.receive(move || handle_data(data));
+        }));
     let on_message_callback = Closure::wrap(on_message_callback);
     data_channel.set_onmessage(Some(on_message_callback.as_ref().unchecked_ref()));
     on_message_callback.forget();
 }
 
 pub fn set_data_channel_on_error(data_channel: &RtcDataChannel) {
-    let on_error: Box<dyn FnMut(JsValue)> = Box::new(move |data_channel_error| {
+    let on_error: Box<dyn FnMut(JsValue)> = Box::new(in_current_span(move |data_channel_error| {
         error!("data channel error: {:?}", data_channel_error);
-    });
+    }));
     let on_error = Closure::wrap(on_error);
     data_channel.set_onerror(Some(on_error.as_ref().unchecked_ref()));
     on_error.forget();
@@ -148,25 +180,118 @@ pub fn set_data_channel_on_error(data_channel: &RtcDataChannel) {
 pub fn set_data_channel_on_open(
     data_channel: &RtcDataChannel,
     client_id: UserId,
-    mut on_open_callback: impl FnMut(UserId) + 'static,
+    network_manager: NetworkManager,
 ) {
-    let on_open_callback: Box<dyn FnMut(JsValue)> = Box::new(move |_| {
+    let on_open_callback: Box<dyn FnMut(JsValue)> = Box::new(in_current_span(move |_| {
         debug!("data channel is now open, calling on_open!");
-        on_open_callback(client_id);
-    });
+        {
+            let mut inner = network_manager.inner.borrow_mut();
+            if !inner.is_host {
+                inner.host_id = Some(client_id);
+            }
+        }
+        network_manager.notify(LifecycleEvent::Opened(client_id));
+        network_manager.relay_channel_opened(client_id);
+    }));
     let on_open_callback = Closure::wrap(on_open_callback);
     data_channel.set_onopen(Some(on_open_callback.as_ref().unchecked_ref()));
     on_open_callback.forget();
 }
 
-pub fn set_peer_connection_on_ice_connection_state_change(peer_connection: &RtcPeerConnection) {
+/// Reports the data channel closing and then closed, unless its connection was already replaced
+/// (e.g. torn down for a retry) and it's no longer the channel used for `client_id`.
+pub fn set_data_channel_on_close(
+    data_channel: &RtcDataChannel,
+    client_id: UserId,
+    network_manager: NetworkManager,
+) {
+    let is_current = {
+        let network_manager = network_manager.clone();
+        let data_channel = data_channel.clone();
+        move || {
+            network_manager
+                .inner
+                .borrow()
+                .connections
+                .get(&client_id)
+                .and_then(|connection| connection.data_channel.as_ref())
+                == Some(&data_channel)
+        }
+    };
+
+    let on_closing: Box<dyn FnMut(JsValue)> = Box::new(in_current_span({
+        let is_current = is_current.clone();
+        let network_manager = network_manager.clone();
+        move |_| {
+            debug!("data channel with {:?} is closing", client_id);
+            if is_current() {
+                network_manager.notify(LifecycleEvent::ChannelStateChanged(
+                    client_id,
+                    ChannelState::Closing,
+                ));
+            }
+        }
+    }));
+    let on_closing = Closure::wrap(on_closing);
+    // web-sys has no setter for `onclosing`
+    if let Err(err) = data_channel
+        .add_event_listener_with_callback("closing", on_closing.as_ref().unchecked_ref())
+    {
+        error!("failed to listen for data channel closing: {:?}", err);
+    }
+    on_closing.forget();
+
+    let on_close: Box<dyn FnMut(JsValue)> = Box::new(in_current_span(move |_| {
+        debug!("data channel with {:?} closed", client_id);
+        if is_current() {
+            network_manager.notify(LifecycleEvent::ChannelStateChanged(
+                client_id,
+                ChannelState::Closed,
+            ));
+            network_manager.notify(LifecycleEvent::Closed(
+                client_id,
+                CloseReason::ChannelClosed,
+            ));
+            network_manager.relay_channel_closed(client_id, false);
+        }
+    }));
+    let on_close = Closure::wrap(on_close);
+    data_channel.set_onclose(Some(on_close.as_ref().unchecked_ref()));
+    on_close.forget();
+}
+
+/// Reports ICE connection state changes of connection with `peer_id`
+/// and calls `on_failed` once ICE fails.
+pub fn set_peer_connection_on_ice_connection_state_change(
+    peer_connection: &RtcPeerConnection,
+    peer_id: UserId,
+    network_manager: NetworkManager,
+    mut on_failed: impl FnMut() + 'static,
+) {
     let peer_connection_clone = peer_connection.clone();
-    let on_ice_connection_state_change: Box<dyn FnMut()> = Box::new(move || {
-        debug!(
-            "connection state change: {:?}",
-            peer_connection_clone.ice_connection_state()
-        );
-    });
+    let on_ice_connection_state_change: Box<dyn FnMut(JsValue)> =
+        Box::new(in_current_span(move |_| {
+            let ice_connection_state = peer_connection_clone.ice_connection_state();
+            debug!(
+                "connection state change with {:?}: {:?}",
+                peer_id, ice_connection_state
+            );
+            match ice_connection_state {
+                RtcIceConnectionState::Connected | RtcIceConnectionState::Completed => {
+                    network_manager
+                        .inner
+                        .borrow_mut()
+                        .retry_attempts
+                        .remove(&peer_id);
+                    network_manager.notify_connection_state(peer_id, ConnectionState::Connected);
+                }
+                RtcIceConnectionState::Disconnected => {
+                    network_manager.notify_connection_state(peer_id, ConnectionState::Disconnected);
+                }
+                RtcIceConnectionState::Failed => on_failed(),
+                _ => {}
+            }
+        }));
     let on_ice_connection_state_change = Closure::wrap(on_ice_connection_state_change);
     peer_connection.set_oniceconnectionstatechange(Some(
         on_ice_connection_state_change.as_ref().unchecked_ref(),
@@ -174,32 +299,37 @@ pub fn set_peer_connection_on_ice_connection_state_change(peer_connection: &RtcP
     on_ice_connection_state_change.forget();
 }
 
+/// Also records each candidate in `ice_gathering`, candidates rejected by its filter aren't sent.
 pub fn set_peer_connection_on_ice_candidate(
     peer_connection: &RtcPeerConnection,
     client_id: UserId,
-    websocket_clone: WebSocket,
+    transport: impl SignalingTransport + 'static,
     session_id_clone: SessionId,
+    ice_gathering: IceGathering<UserId>,
 ) {
     let on_ice_candidate: Box<dyn FnMut(RtcPeerConnectionIceEvent)> =
-        Box::new(move |ev: RtcPeerConnectionIceEvent| {
-            if let Some(candidate) = ev.candidate() {
-                let signaled_candidate = IceCandidate {
-                    candidate: candidate.candidate(),
-                    sdp_mid: candidate.sdp_mid(),
-                    sdp_m_line_index: candidate.sdp_m_line_index(),
-                };
-                debug!("signaled candidate: {:#?}", signaled_candidate);
-
-                let signal_message =
-                    SignalMessage::IceCandidate(session_id_clone, client_id, signaled_candidate);
-                let signal_message =
-                    rmp_serde::to_vec(&signal_message).expect("failed to serialize SignalMessage");
-
-                if let Err(err) = websocket_clone.send_with_u8_array(&signal_message) {
-                    error!("failed to send one of the ICE candidates: {:?}", err);
-                }
+        Box::new(in_current_span(move |ev: RtcPeerConnectionIceEvent| {
+            // gathering is complete once there is no candidate, which the other peer is told
+            // with an empty one
+            let signaled_candidate =
+                ev.candidate()
+                    .map_or_else(IceCandidate::end_of_candidates, |candidate| IceCandidate {
+                        candidate: candidate.candidate(),
+                        sdp_mid: candidate.sdp_mid(),
+                        sdp_m_line_index: candidate.sdp_m_line_index(),
+                    });
+            debug!("signaled candidate: {:#?}", signaled_candidate);
+            if !ice_gathering.local_candidate(client_id, &signaled_candidate.candidate) {
+                debug!("candidate filtered out, not signaling it");
+                return;
             }
-        });
+
+            let signal_message =
+                SignalMessage::IceCandidate(session_id_clone, client_id, signaled_candidate);
+            if let Err(err) = send_signal_message(&transport, &signal_message) {
+                error!("failed to send one of the ICE candidates: {}", err);
+            }
+        }));
     let on_ice_candidate = Closure::wrap(on_ice_candidate);
     peer_connection.set_onicecandidate(Some(on_ice_candidate.as_ref().unchecked_ref()));
     on_ice_candidate.forget();
@@ -17,7 +17,7 @@ This example shows three peers connecting, with one being a dedicated host.
 Host waits for both peers to connect and only then sends `ping` messages to both
 and clients independently respond with `pong` messages.
 
-```
+```no_run
 use wasm_peers::one_to_many::{MiniClient, MiniServer};
 use wasm_peers::ConnectionType;
 use std::cell::RefCell;
@@ -48,7 +48,7 @@ let server_on_open = {
     }
 };
 let server_on_message = {
-    move |user_id, message: &str| {
+    move |user_id, message: String| {
         console::log_1(
             &format!(
                 "server received message from client {:?}: {}",
@@ -58,7 +58,7 @@ let server_on_message = {
         );
     }
 };
-server.start(server_on_open, server_on_message);
+server.start(server_on_open, server_on_message).unwrap();
 
 let client_generator = || {
     let mut client = MiniClient::new(
@@ -70,12 +70,12 @@ let client_generator = || {
     let client_on_open = || { /* do nothing */ };
     let client_clone = client.clone();
     let client_on_message = {
-        move |message| {
+        move |message: String| {
             console::log_1(&format!("client received message: {}", message).into());
             client_clone.send_message_to_host("pong!").unwrap();
         }
     };
-    client.start(client_on_open, client_on_message);
+    client.start(client_on_open, client_on_message).unwrap();
 };
 client_generator();
 client_generator();
@@ -83,51 +83,203 @@ client_generator();
 */
 
 mod callbacks;
+mod relay;
+mod snapshot;
 mod websocket_handler;
 
+pub use snapshot::SessionSnapshot;
+
 use std::cell::RefCell;
 use std::collections::HashMap;
+use std::fmt::{Debug, Display, Formatter};
+use std::future::{ready, Future};
 use std::rc::Rc;
+use std::time::Duration;
 
 use anyhow::anyhow;
+use futures_util::future::{join_all, LocalBoxFuture};
+use futures_util::FutureExt;
+use log::{debug, error};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
-use wasm_peers_protocol::{SessionId, UserId};
-use web_sys::{RtcDataChannel, RtcPeerConnection, WebSocket};
+use wasm_bindgen::JsValue;
+use wasm_peers_protocol::one_to_many::SignalMessage;
+use wasm_peers_protocol::{JoinIntent, SessionId, SessionInfo, StableId, UserId};
+use web_sys::{
+    RtcDataChannel, RtcDataChannelState, RtcIceConnectionState, RtcPeerConnection,
+    RtcSignalingState,
+};
 
-use crate::constants::DEFAULT_MAX_RETRANSMITS;
+use crate::batch::Batcher;
+use crate::constants::{DEFAULT_MAX_RETRANSMITS, DEFAULT_PING_TIMEOUT, SESSION_LIST_TIMEOUT};
+use crate::event::{
+    event_handler, lifecycle_event_handler, EventHandler, LifecycleEvent, LifecycleEventHandler,
+};
+use crate::ice::IceGathering;
+use crate::leader::LeaderElection;
+use crate::netem::NetworkEmulator;
 use crate::one_to_many::callbacks::{set_websocket_on_message, set_websocket_on_open};
-use crate::ConnectionType;
+use crate::one_to_many::relay::{
+    advertise_peers, parse_advertised_peers, RelayFrame, RelayState, RelayedMessage,
+};
+use crate::ping::PendingPings;
+use crate::reliable::ReliableMessages;
+use crate::session_list::PendingSessionLists;
+#[cfg(feature = "test-utils")]
+use crate::test_utils::{LoopbackSignaling, Topology};
+use crate::transport::SignalingConnection;
+use crate::utils::{deserialize_serialized_message, negotiated_max_message_size, Negotiation};
+#[cfg(feature = "netem")]
+use crate::NetworkConditions;
+use crate::{
+    AlreadyStartedError, CandidateFilter, CandidateInfo, CandidateSummary, ConnectionState,
+    ConnectionType, DataChannelConfig, DiagnosticsReport, MultiSendError, PeerDiagnostics,
+    PeerEvent, SdpMunger,
+};
+
+/// Configuration of automatic re-creation of a connection with a single peer after its ICE fails.
+///
+/// Only the peer which sent the initial offer (the host in client-server topology) retries,
+/// by tearing down the failed connection and sending a fresh offer.
+#[derive(Debug, Clone)]
+pub struct RetryConfig {
+    /// How many times the connection is re-created, `0` disables retrying
+    pub max_attempts: u32,
+    /// Connection type used for the final attempt instead of the one provided in constructor,
+    /// e.g. [`ConnectionType::StunAndTurn`] if TURN relay should only be used as the last resort
+    pub final_attempt_connection_type: Option<ConnectionType>,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            final_attempt_connection_type: None,
+        }
+    }
+}
+
+type ConnectionStateCallback = Rc<RefCell<dyn FnMut(UserId, ConnectionState)>>;
+type RelayPolicy = Rc<RefCell<dyn FnMut(UserId, UserId) -> bool>>;
+type IceConfigProvider = Rc<dyn Fn() -> LocalBoxFuture<'static, ConnectionType>>;
 
 #[derive(Debug, Clone)]
 struct Connection {
     peer_connection: RtcPeerConnection,
+    negotiation: Rc<Negotiation>,
     data_channel: Option<RtcDataChannel>,
 }
 
 impl Connection {
-    const fn new(peer_connection: RtcPeerConnection, data_channel: Option<RtcDataChannel>) -> Self {
+    const fn new(
+        peer_connection: RtcPeerConnection,
+        negotiation: Rc<Negotiation>,
+        data_channel: Option<RtcDataChannel>,
+    ) -> Self {
         Self {
             peer_connection,
+            negotiation,
             data_channel,
         }
     }
 }
 
-#[derive(Debug)]
 struct NetworkManagerInner {
     session_id: SessionId,
-    websocket: WebSocket,
+    signaling: SignalingConnection,
+    /// `None` with loopback signaling
+    signaling_server_url: Option<String>,
     connection_type: ConnectionType,
+    /// Called for fresh `connection_type` before each peer connection is created
+    ice_config_provider: Option<IceConfigProvider>,
     is_host: bool,
+    /// Set on client once data channel with host opens
+    host_id: Option<UserId>,
+    /// Set on client joining the session under a stable id
+    stable_id: Option<StableId>,
+    join_intent: JoinIntent,
+    /// Stable ids of clients reported to the host by signaling server
+    stable_ids: HashMap<UserId, StableId>,
     connections: HashMap<UserId, Connection>,
+    retry_config: RetryConfig,
+    retry_attempts: HashMap<UserId, u32>,
+    data_channel_config: DataChannelConfig,
+    pending_pings: PendingPings,
+    ping_timeout: Duration,
+    reliable_messages: ReliableMessages<UserId>,
+    batcher: Batcher<UserId>,
+    network_emulator: NetworkEmulator,
+    ice_gathering: IceGathering<UserId>,
+    leader_election: LeaderElection,
+    session_lists: PendingSessionLists,
+    relay: RelayState,
+    /// Decides whether a message from the first peer is relayed to the second one, all are by default
+    relay_policy: Option<RelayPolicy>,
+    /// Host turns away clients over it, see [`MiniServer::set_max_clients`]
+    max_clients: Option<usize>,
+    on_connection_state_change: Option<ConnectionStateCallback>,
+    on_lifecycle_event: Option<LifecycleEventHandler>,
+    /// Set by the first call to one of `start` methods, which can't be repeated
+    started: bool,
 }
 
-#[derive(Debug, Clone)]
+impl Debug for NetworkManagerInner {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NetworkManagerInner")
+            .field("session_id", &self.session_id)
+            .field("signaling", &self.signaling)
+            .field("signaling_server_url", &self.signaling_server_url)
+            .field("connection_type", &self.connection_type)
+            .field("is_host", &self.is_host)
+            .field("host_id", &self.host_id)
+            .field("stable_id", &self.stable_id)
+            .field("join_intent", &self.join_intent)
+            .field("stable_ids", &self.stable_ids)
+            .field("connections", &self.connections)
+            .field("retry_config", &self.retry_config)
+            .field("retry_attempts", &self.retry_attempts)
+            .field("data_channel_config", &self.data_channel_config)
+            .field("pending_pings", &self.pending_pings)
+            .field("ping_timeout", &self.ping_timeout)
+            .field("reliable_messages", &self.reliable_messages)
+            .field("batcher", &self.batcher)
+            .field("network_emulator", &self.network_emulator)
+            .field("ice_gathering", &self.ice_gathering)
+            .field("leader_election", &self.leader_election)
+            .field("session_lists", &self.session_lists)
+            .field("relay", &self.relay)
+            .field("max_clients", &self.max_clients)
+            .field("started", &self.started)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Clone)]
 pub struct NetworkManager {
     inner: Rc<RefCell<NetworkManagerInner>>,
 }
 
+impl Debug for NetworkManager {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NetworkManager")
+            .field("session_id", &self.session_id())
+            .field("is_host", &self.is_host())
+            .field("signaling_url", &self.signaling_url())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Display for NetworkManager {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let role = if self.is_host() { "host" } else { "client" };
+        write!(f, "one-to-many {} in {}", role, self.session_id())?;
+        if let Some(signaling_url) = self.signaling_url() {
+            write!(f, " via {}", signaling_url)?;
+        }
+        Ok(())
+    }
+}
+
 impl NetworkManager {
     /// Creates an instance with all resources required to create a connection.
     /// Requires an  address of an signaling server instance,
@@ -141,81 +293,585 @@ impl NetworkManager {
         connection_type: ConnectionType,
         is_host: bool,
     ) -> crate::Result<Self> {
-        let websocket = WebSocket::new(signaling_server_url).map_err(|err| {
-            anyhow!(
-                "failed to create connection with signaling server on {}: {:?}",
-                signaling_server_url,
-                err
-            )
-        })?;
-        websocket.set_binary_type(web_sys::BinaryType::Arraybuffer);
+        Ok(Self::with_signaling(
+            SignalingConnection::open(signaling_server_url)?,
+            Some(signaling_server_url.to_owned()),
+            session_id,
+            connection_type,
+            is_host,
+        ))
+    }
 
-        Ok(Self {
+    pub(crate) fn with_signaling(
+        signaling: SignalingConnection,
+        signaling_server_url: Option<String>,
+        session_id: SessionId,
+        connection_type: ConnectionType,
+        is_host: bool,
+    ) -> Self {
+        Self {
             inner: Rc::new(RefCell::new(NetworkManagerInner {
                 session_id,
-                websocket,
+                signaling,
+                signaling_server_url,
                 connection_type,
+                ice_config_provider: None,
                 is_host,
+                host_id: None,
+                stable_id: None,
+                join_intent: JoinIntent::default(),
+                stable_ids: HashMap::new(),
                 connections: HashMap::new(),
+                retry_config: RetryConfig::default(),
+                data_channel_config: DataChannelConfig::default(),
+                retry_attempts: HashMap::new(),
+                pending_pings: PendingPings::default(),
+                ping_timeout: DEFAULT_PING_TIMEOUT,
+                reliable_messages: ReliableMessages::default(),
+                batcher: Batcher::default(),
+                network_emulator: NetworkEmulator::default(),
+                ice_gathering: IceGathering::default(),
+                leader_election: LeaderElection::default(),
+                session_lists: PendingSessionLists::default(),
+                relay: RelayState::default(),
+                relay_policy: None,
+                max_clients: None,
+                on_connection_state_change: None,
+                on_lifecycle_event: None,
+                started: false,
             })),
-        })
+        }
     }
 
-    pub fn start<T: DeserializeOwned>(
-        &mut self,
-        on_open_callback: impl FnMut(UserId) + Clone + 'static,
-        on_message_callback: impl FnMut(UserId, T) + Clone + 'static,
+    /// Same as [`NetworkManager::new`], but resolves only once connection with signaling server is open.
+    ///
+    /// # Errors
+    /// This function errs if opening a `WebSocket` connection to URL provided by `signaling_server_url` fails.
+    pub async fn new_async(
+        signaling_server_url: &str,
+        session_id: SessionId,
+        connection_type: ConnectionType,
+        is_host: bool,
+    ) -> crate::Result<Self> {
+        let network_manager =
+            Self::new(signaling_server_url, session_id, connection_type, is_host)?;
+        let signaling = network_manager.inner.borrow().signaling.clone();
+        signaling.wait_for_open().await?;
+        Ok(network_manager)
+    }
+
+    /// Sets how connections with peers are re-created after their ICE fails.
+    pub fn set_retry_config(&self, retry_config: RetryConfig) {
+        self.inner.borrow_mut().retry_config = retry_config;
+    }
+
+    /// Joins the session with `intent`, refused with [`PeerEvent::SignalingError`] if the session doesn't match it.
+    /// Ignored by clients joining under a stable id, see [`MiniClient::set_stable_id`].
+    ///
+    /// Has to be called before the network manager is started.
+    pub fn set_join_intent(&self, intent: JoinIntent) {
+        self.inner.borrow_mut().join_intent = intent;
+    }
+
+    /// Sets how data channels with peers are created, applies to connections created afterwards.
+    /// [`DataChannelConfig::max_message_size`] applies to messages sent from now on.
+    pub fn set_data_channel_config(&self, data_channel_config: DataChannelConfig) {
+        self.inner.borrow_mut().data_channel_config = data_channel_config;
+    }
+
+    /// Delays, drops and reorders messages sent and received from now on as configured,
+    /// [`NetworkConditions::default`] turns it off.
+    #[cfg(feature = "netem")]
+    pub fn set_network_conditions(&self, conditions: NetworkConditions) {
+        self.inner
+            .borrow()
+            .network_emulator
+            .set_conditions(conditions);
+    }
+
+    /// Sets how long [`NetworkManager::ping_latency`] waits for a peer to answer, 2 seconds by default.
+    pub fn set_ping_timeout(&self, ping_timeout: Duration) {
+        self.inner.borrow_mut().ping_timeout = ping_timeout;
+    }
+
+    /// Registers a callback notified whenever connection with one of the peers changes its state,
+    /// including each retry attempt and the final outcome.
+    pub fn set_on_connection_state_change(
+        &self,
+        on_connection_state_change: impl FnMut(UserId, ConnectionState) + 'static,
+    ) {
+        self.inner.borrow_mut().on_connection_state_change =
+            Some(Rc::new(RefCell::new(on_connection_state_change)));
+    }
+
+    pub(crate) fn notify_negotiation_needed(&self) {
+        // clone the handler out, so that it can freely use the network manager
+        let handler = self
+            .inner
+            .borrow()
+            .data_channel_config
+            .on_negotiation_needed
+            .clone();
+        if let Some(handler) = handler {
+            handler.call();
+        }
+    }
+
+    /// Registers a callback called with details of each local ICE candidate gathered for connection
+    /// with one of the peers, e.g. to debug NAT traversal.
+    pub fn set_on_local_candidate(
+        &self,
+        on_local_candidate: impl FnMut(UserId, CandidateInfo) + 'static,
     ) {
+        self.inner
+            .borrow()
+            .ice_gathering
+            .set_on_local_candidate(on_local_candidate);
+    }
+
+    /// Registers a callback called once connection with one of the peers gathered all of its local ICE candidates.
+    pub fn set_on_gathering_complete(&self, on_gathering_complete: impl FnMut(UserId) + 'static) {
+        self.inner
+            .borrow()
+            .ice_gathering
+            .set_on_gathering_complete(on_gathering_complete);
+    }
+
+    /// Number of local ICE candidates of each type gathered so far for connections with all peers,
+    /// e.g. to warn that there are no relay ones because TURN server isn't configured right,
+    /// before connections fail.
+    #[must_use]
+    pub fn gathered_candidate_summary(&self) -> CandidateSummary {
+        self.inner.borrow().ice_gathering.summary()
+    }
+
+    /// Signals to peers only local ICE candidates that `filter` allows from now on,
+    /// e.g. [`CandidateFilter::relay_only`] to only expose address of the TURN server.
+    /// Number of candidates it suppressed for each peer is included in [`NetworkManager::diagnostics`].
+    pub fn set_candidate_filter(&self, filter: impl Into<CandidateFilter>) {
+        self.inner
+            .borrow()
+            .ice_gathering
+            .set_filter(Some(filter.into()));
+    }
+
+    /// Function modifying SDP, see [`DataChannelConfig::on_sdp`].
+    pub(crate) fn sdp_munger(&self) -> Option<SdpMunger> {
+        self.inner.borrow().data_channel_config.on_sdp.clone()
+    }
+
+    pub(crate) fn ice_gathering(&self) -> IceGathering<UserId> {
+        self.inner.borrow().ice_gathering.clone()
+    }
+
+    #[cfg(feature = "many-to-many")]
+    pub(crate) fn leader_election(&self) -> LeaderElection {
+        self.inner.borrow().leader_election.clone()
+    }
+
+    fn notify_connection_state(&self, user_id: UserId, state: ConnectionState) {
+        // clone the callback out, so that it can freely use the network manager
+        let callback = self.inner.borrow().on_connection_state_change.clone();
+        if let Some(callback) = callback {
+            (callback.borrow_mut())(user_id, state);
+        }
+        self.notify(LifecycleEvent::StateChanged(user_id, state));
+    }
+
+    /// Passes event to the handler given to [`NetworkManager::start_with_event_handler`].
+    fn notify(&self, event: LifecycleEvent) {
+        // members of many-to-many sessions are tracked to elect their leader
+        let leader_election = self.inner.borrow().leader_election.clone();
+        match event {
+            LifecycleEvent::Opened(peer_id) => leader_election.peer_joined(peer_id),
+            LifecycleEvent::Closed(peer_id, _) => leader_election.peer_left(peer_id),
+            _ => {}
+        }
+        // clone the handler out, so that it can freely use the network manager
+        let handler = self.inner.borrow().on_lifecycle_event.clone();
+        if let Some(handler) = handler {
+            (handler.borrow_mut())(event);
+        }
+    }
+
+    /// Enables relaying messages to peers whose direct connection failed through a peer connected to both,
+    /// and relaying messages for other peers, disabled by default.
+    #[cfg(feature = "many-to-many")]
+    pub(crate) fn set_relay_enabled(&self, enabled: bool) {
+        self.inner.borrow_mut().relay.set_enabled(enabled);
+        self.advertise_relay_peers();
+        self.update_relay_routes();
+    }
+
+    /// Peer through which messages to `user_id` are relayed, `None` if they aren't.
+    #[cfg(feature = "many-to-many")]
+    pub(crate) fn relayed_via(&self, user_id: UserId) -> Option<UserId> {
+        self.inner.borrow().relay.route_for(user_id)
+    }
+
+    /// Tells every neighbour which peers it can relay to through this one, none if relaying is disabled.
+    fn advertise_relay_peers(&self) {
+        let neighbours = self.connected_peers();
+        let peers = if self.inner.borrow().relay.is_enabled() {
+            neighbours.clone()
+        } else {
+            Vec::new()
+        };
+        for user_id in neighbours {
+            if let Some(data_channel) = self.open_data_channel(user_id) {
+                if let Err(err) = advertise_peers(&data_channel, &peers) {
+                    error!("failed to advertise peers to {}: {}", user_id, err);
+                }
+            }
+        }
+    }
+
+    fn update_relay_routes(&self) {
+        let changes = self.inner.borrow_mut().relay.update_routes();
+        for (user_id, route) in changes {
+            let state = route.map_or(ConnectionState::Failed, |via| ConnectionState::Relayed {
+                via,
+            });
+            self.notify_connection_state(user_id, state);
+        }
+    }
+
+    /// Data channel with `user_id` opened, so messages are sent to it directly
+    /// and it can relay messages to peers it's connected with.
+    fn relay_channel_opened(&self, user_id: UserId) {
+        self.inner.borrow_mut().relay.set_reachable(user_id);
+        if self.inner.borrow().relay.is_enabled() {
+            self.advertise_relay_peers();
+        }
+    }
+
+    /// Data channel with `user_id` closed or it left the session, so it can no longer relay messages.
+    fn relay_channel_closed(&self, user_id: UserId, peer_left: bool) {
+        {
+            let mut inner = self.inner.borrow_mut();
+            if peer_left {
+                inner.relay.forget(user_id);
+            } else {
+                inner.relay.remove_neighbour(user_id);
+            }
+        }
+        if self.inner.borrow().relay.is_enabled() {
+            self.advertise_relay_peers();
+        }
+        self.update_relay_routes();
+    }
+
+    /// Direct connection with `user_id` failed for good, messages to it are relayed from now on if possible.
+    fn relay_connection_failed(&self, user_id: UserId) {
+        self.inner.borrow_mut().relay.set_unreachable(user_id);
+        self.update_relay_routes();
+    }
+
+    /// Handles peers advertised by a neighbour and messages relayed through or by it.
+    /// Returns `false` if `data` is not a relay frame, but a user message.
+    fn handle_relay_frame<T: DeserializeOwned>(
+        &self,
+        from: UserId,
+        data: &JsValue,
+        event_handler: &EventHandler<T>,
+    ) -> bool {
+        if let Some(peers) = parse_advertised_peers(data) {
+            self.inner
+                .borrow_mut()
+                .relay
+                .set_neighbour_peers(from, peers);
+            self.update_relay_routes();
+            return true;
+        }
+        let Some(frame) = RelayFrame::parse(data) else {
+            return false;
+        };
+        match frame {
+            RelayFrame::Relay(message) => self.forward_relayed_message(from, message),
+            RelayFrame::Relayed { source, payload } => {
+                let accepted = {
+                    let inner = self.inner.borrow();
+                    // client accepts messages relayed by its host
+                    inner.relay.is_enabled() || inner.host_id == Some(from)
+                };
+                if !accepted {
+                    debug!("relaying is disabled, dropping message relayed by {}", from);
+                } else if let Some(message) = deserialize_serialized_message(&payload) {
+                    (event_handler.borrow_mut())(PeerEvent::Message(source, message));
+                }
+            }
+        }
+        true
+    }
+
+    fn forward_relayed_message(&self, from: UserId, message: RelayedMessage) {
+        let RelayedMessage {
+            final_recipient,
+            payload,
+        } = message;
+        if !self.inner.borrow().relay.forwards() {
+            debug!("relaying is disabled, dropping message from {}", from);
+            return;
+        }
+        // clone the policy out, so that it can freely use the network manager
+        let relay_policy = self.inner.borrow().relay_policy.clone();
+        if let Some(relay_policy) = relay_policy {
+            if !(relay_policy.borrow_mut())(from, final_recipient) {
+                debug!(
+                    "relaying from {} to {} is not allowed, dropping message",
+                    from, final_recipient
+                );
+                return;
+            }
+        }
+        let Some(data_channel) = self.open_data_channel(final_recipient) else {
+            debug!(
+                "no open data channel with {}, dropping message relayed by {}",
+                final_recipient, from
+            );
+            return;
+        };
+        let forwarded = RelayFrame::Relayed {
+            source: from,
+            payload,
+        };
+        if let Err(err) = forwarded.send(&data_channel) {
+            error!("failed to relay message from {}: {}", from, err);
+        }
+    }
+
+    /// Connection type for the next connection with given peer, taking retry attempts into account.
+    fn connection_type_for(&self, user_id: UserId) -> ConnectionType {
+        let inner = self.inner.borrow();
+        let attempt = inner.retry_attempts.get(&user_id).copied().unwrap_or(0);
+        match inner.retry_config.final_attempt_connection_type {
+            Some(ref connection_type)
+                if attempt > 0 && attempt == inner.retry_config.max_attempts =>
+            {
+                connection_type.clone()
+            }
+            _ => inner.connection_type.clone(),
+        }
+    }
+
+    /// Second part of the setup that begins the actual connections.
+    /// Requires specifying a callbacks that are guaranteed to run
+    /// when a connection with a peer opens and on each message received from it.
+    ///
+    /// # Errors
+    /// Errs with [`AlreadyStartedError`] if network manager or any of its clones was already started.
+    pub fn start<T: DeserializeOwned + 'static>(
+        &mut self,
+        on_open_callback: impl FnMut(UserId) + 'static,
+        on_message_callback: impl FnMut(UserId, T) + 'static,
+    ) -> crate::Result<()> {
         self.start_with_retransmits(
             DEFAULT_MAX_RETRANSMITS,
             on_open_callback,
             on_message_callback,
-        );
+        )
     }
 
-    pub fn start_with_retransmits<T: DeserializeOwned>(
+    /// Same as [`NetworkManager::start`], but data channels give up on a message
+    /// after `max_retransmits` retransmissions.
+    ///
+    /// # Errors
+    /// Errs with [`AlreadyStartedError`] if network manager or any of its clones was already started.
+    pub fn start_with_retransmits<T: DeserializeOwned + 'static>(
         &mut self,
         max_retransmits: u16,
-        on_open_callback: impl FnMut(UserId) + Clone + 'static,
-        on_message_callback: impl FnMut(UserId, T) + Clone + 'static,
-    ) {
-        let websocket = self.inner.borrow().websocket.clone();
+        mut on_open_callback: impl FnMut(UserId) + 'static,
+        mut on_message_callback: impl FnMut(UserId, T) + 'static,
+    ) -> crate::Result<()> {
+        self.start_with_event_handler_and_retransmits(max_retransmits, move |event| match event {
+            PeerEvent::Opened(user_id) => on_open_callback(user_id),
+            PeerEvent::Message(user_id, message) => on_message_callback(user_id, message),
+            _ => {}
+        })
+    }
+
+    /// Alternative to [`NetworkManager::start`] with a single handler receiving every [`PeerEvent`].
+    ///
+    /// # Errors
+    /// Errs with [`AlreadyStartedError`] if network manager or any of its clones was already started.
+    pub fn start_with_event_handler<T: DeserializeOwned + 'static>(
+        &mut self,
+        handler: impl FnMut(PeerEvent<T>) + 'static,
+    ) -> crate::Result<()> {
+        self.start_with_event_handler_and_retransmits(DEFAULT_MAX_RETRANSMITS, handler)
+    }
+
+    /// Combination of [`NetworkManager::start_with_event_handler`]
+    /// and [`NetworkManager::start_with_retransmits`].
+    ///
+    /// # Errors
+    /// Errs with [`AlreadyStartedError`] if network manager or any of its clones was already started.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "start",
+            skip_all,
+            fields(session_id = %self.session_id().inner(), is_host = self.is_host())
+        )
+    )]
+    pub fn start_with_event_handler_and_retransmits<T: DeserializeOwned + 'static>(
+        &mut self,
+        max_retransmits: u16,
+        handler: impl FnMut(PeerEvent<T>) + 'static,
+    ) -> crate::Result<()> {
+        if std::mem::replace(&mut self.inner.borrow_mut().started, true) {
+            return Err(AlreadyStartedError.into());
+        }
+        let handler = event_handler(handler);
+        self.inner.borrow_mut().on_lifecycle_event = Some(lifecycle_event_handler(&handler));
+
+        let signaling = self.inner.borrow().signaling.clone();
         let session_id = self.inner.borrow().session_id;
         let is_host = self.inner.borrow().is_host;
+        let stable_id = self.inner.borrow().stable_id;
+        let join_intent = self.inner.borrow().join_intent;
 
-        set_websocket_on_open(&websocket, session_id, is_host);
-        set_websocket_on_message(
-            &websocket,
-            self.clone(),
-            max_retransmits,
-            on_open_callback,
-            on_message_callback,
-            is_host,
-        );
+        set_websocket_on_open(&signaling, session_id, is_host, stable_id, join_intent);
+        set_websocket_on_message(&signaling, self.clone(), max_retransmits, handler, is_host);
+        Ok(())
     }
 
     /// Send message to a connected client-user identified by unique [`UserId`]
     ///
     /// # Errors
     /// This function can err if:
-    /// - sending of the message was tried before data channel was established or,
+    /// - sending of the message was tried before data channel was established,
+    /// - serialized message is larger than [`DataChannelConfig::max_message_size`],
+    ///   with [`MessageTooLargeError`](crate::MessageTooLargeError) or,
     /// - sending of the message failed.
     pub fn send_message<T: Serialize + ?Sized>(
         &self,
         user_id: UserId,
         message: &T,
     ) -> crate::Result<()> {
-        let message = rmp_serde::to_vec(message)?;
-        self.inner
+        let message = self
+            .inner
+            .borrow()
+            .data_channel_config
+            .serialize_message(message)?;
+        self.send_serialized_message(user_id, &message)
+    }
+
+    /// Sends message to `user_id` and resolves once the peer acknowledges it, resending it
+    /// when acknowledgement doesn't arrive in time, as configured with [`DataChannelConfig::ack_timeout`]
+    /// and [`DataChannelConfig::max_send_attempts`]. Event handler of the peer receives the message once,
+    /// no matter how many times it arrived, acknowledgements never reach it.
+    ///
+    /// # Errors
+    /// This function errs if there is no data channel with `user_id` yet, serialized message is larger than
+    /// [`DataChannelConfig::max_message_size`] with [`MessageTooLargeError`](crate::MessageTooLargeError),
+    /// sending of the message failed, or with [`NotAcknowledgedError`](crate::NotAcknowledgedError)
+    /// if no attempt was acknowledged.
+    pub fn send_message_reliably<T: Serialize + ?Sized>(
+        &self,
+        user_id: UserId,
+        message: &T,
+    ) -> impl Future<Output = crate::Result<()>> {
+        let message = self
+            .inner
             .borrow()
+            .data_channel_config
+            .serialize_message(message);
+        let network_manager = self.clone();
+        async move {
+            let message = message?;
+            let (data_channel, reliable_messages, data_channel_config) = {
+                let inner = network_manager.inner.borrow();
+                let data_channel = inner
+                    .connections
+                    .get(&user_id)
+                    .ok_or_else(|| anyhow!("no connection for user {}", user_id))?
+                    .data_channel
+                    .clone()
+                    .ok_or_else(|| anyhow!("no data channel setup yet for user {}", user_id))?;
+                (
+                    data_channel,
+                    inner.reliable_messages.clone(),
+                    inner.data_channel_config.clone(),
+                )
+            };
+            reliable_messages
+                .send(&data_channel, &message, &data_channel_config)
+                .await
+        }
+    }
+
+    /// Send message to each of the connected client-users identified by `user_ids`.
+    /// Message is serialized only once, no matter the number of recipients.
+    ///
+    /// # Errors
+    /// This function errs if serialization fails, with [`MessageTooLargeError`](crate::MessageTooLargeError)
+    /// before sending anything if message is too large, or with [`MultiSendError`]
+    /// listing each user to whom the message couldn't be sent.
+    pub fn send_message_to_many<T: Serialize + ?Sized>(
+        &self,
+        user_ids: &[UserId],
+        message: &T,
+    ) -> crate::Result<()> {
+        let message = self
+            .inner
+            .borrow()
+            .data_channel_config
+            .serialize_message(message)?;
+        let failures: Vec<_> = user_ids
+            .iter()
+            .filter_map(|&user_id| {
+                self.send_serialized_message(user_id, &message)
+                    .err()
+                    .map(|err| (user_id, err))
+            })
+            .collect();
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(MultiSendError { failures }.into())
+        }
+    }
+
+    fn send_serialized_message(&self, user_id: UserId, message: &[u8]) -> crate::Result<()> {
+        let network_emulator = self.inner.borrow().network_emulator.clone();
+        let network_manager = self.clone();
+        let message = message.to_vec();
+        network_emulator
+            .send(move || network_manager.send_serialized_message_now(user_id, &message))
+    }
+
+    fn send_serialized_message_now(&self, user_id: UserId, message: &[u8]) -> crate::Result<()> {
+        let relayed_via = self.inner.borrow().relay.route_for(user_id);
+        if let Some(via) = relayed_via {
+            return self.send_serialized_message_via(via, user_id, message);
+        }
+        let inner = self.inner.borrow();
+        let data_channel = inner
             .connections
             .get(&user_id)
             .ok_or_else(|| anyhow!("no connection for user {}", user_id))?
             .data_channel
             .as_ref()
-            .ok_or_else(|| anyhow!("no data channel setup yet for user {}", user_id))?
-            .send_with_u8_array(&message)
-            .map_err(|err| anyhow!("failed to send string: {:?}", err))
+            .ok_or_else(|| anyhow!("no data channel setup yet for user {}", user_id))?;
+        inner
+            .batcher
+            .push(user_id, data_channel, message, &inner.data_channel_config)
+    }
+
+    /// Sends messages waiting in batches right away, instead of on the next animation frame,
+    /// e.g. before closing connections. Does nothing unless [`DataChannelConfig::batching`] is enabled.
+    ///
+    /// # Errors
+    /// This function errs with [`MultiSendError`] listing each peer whose batch couldn't be sent.
+    pub fn flush(&self) -> crate::Result<()> {
+        let batcher = self.inner.borrow().batcher.clone();
+        let failures = batcher.flush();
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(MultiSendError { failures }.into())
+        }
     }
 
     /// Send message to a all connected client-users.
@@ -224,81 +880,537 @@ impl NetworkManager {
     /// It might fail if the connection is not yet set up
     /// and thus should only be called after `on_open_callback` triggers.
     /// Otherwise it will result in an error:
-    /// - if sending of the message was tried before data channel was established or,
+    /// - if sending of the message was tried before data channel was established,
+    /// - with [`MessageTooLargeError`](crate::MessageTooLargeError) if serialized message is larger than
+    ///   [`DataChannelConfig::max_message_size`] or,
     /// - if sending of the message failed.
     pub fn send_message_to_all<T: Serialize + ?Sized>(&self, message: &T) -> crate::Result<()> {
-        let message = rmp_serde::to_vec(message)?;
-        for data_channel in self
+        self.send_message_to_all_except(None, message)
+    }
+
+    /// Same as [`NetworkManager::send_message_to_all`], but skipping `excluded` user.
+    fn send_message_to_all_except<T: Serialize + ?Sized>(
+        &self,
+        excluded: Option<UserId>,
+        message: &T,
+    ) -> crate::Result<()> {
+        let message = self
+            .inner
+            .borrow()
+            .data_channel_config
+            .serialize_message(message)?;
+        let user_ids: Vec<UserId> = self
             .inner
             .borrow()
             .connections
-            .values()
-            .filter_map(|connection| connection.data_channel.as_ref())
-        {
+            .keys()
+            .copied()
+            .filter(|&user_id| Some(user_id) != excluded)
+            .collect();
+        for user_id in user_ids {
             // TODO(tkarwowski): some may fail, should we return a list results?
-            let _result = data_channel.send_with_u8_array(&message);
+            let _result = self.send_serialized_message(user_id, &message);
         }
         Ok(())
     }
-}
-
-/// Abstraction over `WebRTC` peer-to-peer connection.
-/// Structure representing server in client-server topology.
-///
-/// `WebRTC` data channel communication abstracted to a single class.
-/// All setup is handled internally, you must only provide callbacks
-/// for when the connection opens and for handling incoming messages.
-/// It also provides a method of sending data to the other end of the connection.
-///
-/// Only works with [wasm-peers-signaling-server](https://docs.rs/wasm-peers-signaling-server/latest/wasm_peers_signaling_server/) instance,
-/// whose full  address must be provided.
-///
-/// Start-up flow is divided into two methods [`MiniServer::new`] and [`MiniServer::start`]
-/// to allow possibility of referring to network manger itself from the callbacks.
-///
-/// This class is a  pointer to the underlying resource and can be cloned freely.
-#[derive(Debug, Clone)]
-pub struct MiniServer {
-    inner: NetworkManager,
-}
 
-impl MiniServer {
-    /// Creates an instance with all resources required to create a connections to client-peers.
-    /// Requires an  address of an signaling server instance,
-    /// session id by which it will identify connecting pair of peers and type of connection.
-    ///
-    /// # Errors
-    /// This function errs if opening a `WebSocket` connection to URL provided by `signaling_server_url` fails.
-    pub fn new(
-        signaling_server_url: &str,
-        session_id: SessionId,
-        connection_type: ConnectionType,
-    ) -> crate::Result<Self> {
-        Ok(Self {
-            inner: NetworkManager::new(signaling_server_url, session_id, connection_type, true)?,
+    /// Sends message to `via`, which forwards it to `user_id`.
+    fn send_serialized_message_via(
+        &self,
+        via: UserId,
+        user_id: UserId,
+        message: &[u8],
+    ) -> crate::Result<()> {
+        let data_channel = self
+            .open_data_channel(via)
+            .ok_or_else(|| anyhow!("no open data channel with {} relaying to {}", via, user_id))?;
+        RelayFrame::Relay(RelayedMessage {
+            final_recipient: user_id,
+            payload: message.to_vec(),
         })
+        .send(&data_channel)
     }
 
-    /// Second part of the setup that begins the actual connection.
-    /// Requires specifying a callbacks that are guaranteed to run
-    /// when the connection opens and on each message received.
-    /// It takes [`UserId`] as an argument which helps identify which client-peer.
-    pub fn start<T: DeserializeOwned>(
-        &mut self,
-        on_open_callback: impl FnMut(UserId) + Clone + 'static,
-        on_message_callback: impl FnMut(UserId, T) + Clone + 'static,
-    ) {
-        self.inner.start(on_open_callback, on_message_callback);
+    fn open_data_channel(&self, user_id: UserId) -> Option<RtcDataChannel> {
+        self.inner
+            .borrow()
+            .connections
+            .get(&user_id)
+            .and_then(|connection| connection.data_channel.clone())
+            .filter(|data_channel| data_channel.ready_state() == RtcDataChannelState::Open)
     }
 
-    pub fn start_with_retransmits<T: DeserializeOwned>(
-        &mut self,
+    /// Session given to [`NetworkManager::new`].
+    #[must_use]
+    pub fn session_id(&self) -> SessionId {
+        self.inner.borrow().session_id
+    }
+
+    /// Whether this peer accepts connections from clients or connects to the host.
+    #[must_use]
+    pub fn is_host(&self) -> bool {
+        self.inner.borrow().is_host
+    }
+
+    /// Address of the signaling server, `None` with loopback signaling.
+    #[must_use]
+    pub fn signaling_url(&self) -> Option<String> {
+        self.inner.borrow().signaling_server_url.clone()
+    }
+
+    /// Type of connection with which peer connections are created, unless overridden by [`RetryConfig`].
+    #[must_use]
+    pub fn connection_type(&self) -> ConnectionType {
+        self.inner.borrow().connection_type.clone()
+    }
+
+    /// Replaces type of connection with which peer connections are created from now on,
+    /// e.g. with TURN credentials that replace expiring ones.
+    /// Already established connections are unaffected and keep using the previous one.
+    pub fn update_connection_type(&self, connection_type: ConnectionType) {
+        self.inner.borrow_mut().connection_type = connection_type;
+    }
+
+    /// Registers a function called before each peer connection is created, whose result replaces
+    /// the connection type as with [`NetworkManager::update_connection_type`], e.g. to fetch
+    /// short-lived TURN credentials. It delays connecting with each peer until it resolves,
+    /// so it should return cached credentials unless they are about to expire.
+    /// Already established connections are unaffected.
+    pub fn set_ice_config_provider<F>(&self, provider: impl Fn() -> F + 'static)
+    where
+        F: Future<Output = ConnectionType> + 'static,
+    {
+        self.inner.borrow_mut().ice_config_provider =
+            Some(Rc::new(move || provider().boxed_local()));
+    }
+
+    /// Replaces connection type with one from the provider registered with
+    /// [`NetworkManager::set_ice_config_provider`], if any.
+    pub(crate) async fn refresh_connection_type(&self) {
+        // clone the provider out, so that the network manager isn't borrowed while waiting for it
+        let provider = self.inner.borrow().ice_config_provider.clone();
+        if let Some(provider) = provider {
+            let connection_type = provider().await;
+            self.update_connection_type(connection_type);
+        }
+    }
+
+    /// Signaling state of the peer connection with `user_id`, `None` if there is no connection with it.
+    #[must_use]
+    pub fn signaling_state_for(&self, user_id: UserId) -> Option<RtcSignalingState> {
+        self.inner
+            .borrow()
+            .connections
+            .get(&user_id)
+            .map(|connection| connection.peer_connection.signaling_state())
+    }
+
+    /// Ready state of the data channel with `user_id`, `None` if there is no channel with it.
+    /// Channel can close while ICE stays connected, e.g. when the other application closed just the channel.
+    #[must_use]
+    pub fn channel_ready_state_for(&self, user_id: UserId) -> Option<RtcDataChannelState> {
+        self.inner
+            .borrow()
+            .connections
+            .get(&user_id)
+            .and_then(|connection| connection.data_channel.as_ref())
+            .map(RtcDataChannel::ready_state)
+    }
+
+    /// Largest message `user_id` accepts, negotiated once the connection with it is established,
+    /// `None` before that or if browser doesn't expose it.
+    /// It can be used to raise [`DataChannelConfig::max_message_size`] for connected peers.
+    #[must_use]
+    pub fn negotiated_max_message_size_for(&self, user_id: UserId) -> Option<usize> {
+        self.inner
+            .borrow()
+            .connections
+            .get(&user_id)
+            .and_then(|connection| negotiated_max_message_size(&connection.peer_connection))
+    }
+
+    /// Underlying peer connection with `user_id`, e.g. to add media tracks or read its statistics,
+    /// `None` if there is no connection with it.
+    /// Connection re-created after its ICE fails is a different object, see [`RetryConfig`].
+    ///
+    /// # Warning
+    /// Network manager relies on the handlers it set on the peer connection, and on being the only one
+    /// negotiating it. Replacing those handlers, closing the connection or changing its configuration
+    /// may break messaging in ways that the library can't report.
+    #[must_use]
+    pub fn peer_connection_for(&self, user_id: UserId) -> Option<RtcPeerConnection> {
+        self.inner
+            .borrow()
+            .connections
+            .get(&user_id)
+            .map(|connection| connection.peer_connection.clone())
+    }
+
+    /// Peers whose ICE connection is still being established, i.e. in `new` or `checking` state, sorted by id.
+    #[must_use]
+    pub fn pending_peers(&self) -> Vec<UserId> {
+        let mut pending_peers: Vec<UserId> = self
+            .inner
+            .borrow()
+            .connections
+            .iter()
+            .filter(|&(_, connection)| {
+                matches!(
+                    connection.peer_connection.ice_connection_state(),
+                    RtcIceConnectionState::New | RtcIceConnectionState::Checking
+                )
+            })
+            .map(|(&user_id, _)| user_id)
+            .collect();
+        pending_peers.sort_unstable();
+        pending_peers
+    }
+
+    /// Peers with an open data channel, which messages can be sent to, sorted by id.
+    #[must_use]
+    pub fn connected_peers(&self) -> Vec<UserId> {
+        let mut connected_peers: Vec<UserId> = self
+            .inner
+            .borrow()
+            .connections
+            .iter()
+            .filter(|&(_, connection)| {
+                connection
+                    .data_channel
+                    .as_ref()
+                    .map(RtcDataChannel::ready_state)
+                    == Some(RtcDataChannelState::Open)
+            })
+            .map(|(&user_id, _)| user_id)
+            .collect();
+        connected_peers.sort_unstable();
+        connected_peers
+    }
+
+    /// Measures round trip time to `user_id` by sending it a ping over the data channel.
+    /// Resolves with `None` if the peer doesn't answer within the timeout set with
+    /// [`NetworkManager::set_ping_timeout`].
+    ///
+    /// Pings are answered by the library on its own, they never reach the event handler.
+    ///
+    /// # Errors
+    /// This function errs if there is no data channel with `user_id` yet or sending the ping fails.
+    pub fn ping_latency(
+        &self,
+        user_id: UserId,
+    ) -> impl Future<Output = crate::Result<Option<Duration>>> {
+        let network_manager = self.clone();
+        async move {
+            let (data_channel, pending_pings, ping_timeout) = {
+                let inner = network_manager.inner.borrow();
+                let data_channel = inner
+                    .connections
+                    .get(&user_id)
+                    .ok_or_else(|| anyhow!("no connection for user {}", user_id))?
+                    .data_channel
+                    .clone()
+                    .ok_or_else(|| anyhow!("no data channel setup yet for user {}", user_id))?;
+                (
+                    data_channel,
+                    inner.pending_pings.clone(),
+                    inner.ping_timeout,
+                )
+            };
+            pending_pings.ping(&data_channel, ping_timeout).await
+        }
+    }
+
+    /// Pings all [connected peers](NetworkManager::connected_peers) at once,
+    /// resolving with round trip time to each of them, sorted by id.
+    /// Latency is `None` for peers that didn't answer in time or couldn't be pinged.
+    pub fn ping_latency_all(&self) -> impl Future<Output = Vec<(UserId, Option<Duration>)>> {
+        let pings: Vec<_> = self
+            .connected_peers()
+            .into_iter()
+            .map(|user_id| {
+                let ping = self.ping_latency(user_id);
+                async move {
+                    let latency = ping.await.unwrap_or_else(|err| {
+                        error!("failed to ping user {}: {}", user_id, err);
+                        None
+                    });
+                    (user_id, latency)
+                }
+            })
+            .collect();
+        join_all(pings)
+    }
+
+    /// Gathers state of the connection with signaling server and with each peer,
+    /// to be attached to bug reports about peers that fail to connect.
+    ///
+    /// Returned future resolves right away, it's asynchronous so that statistics
+    /// which browsers only provide asynchronously can be included later on.
+    pub fn diagnostics(&self) -> impl Future<Output = DiagnosticsReport> {
+        let inner = self.inner.borrow();
+        let mut peers: Vec<_> = inner
+            .connections
+            .iter()
+            .map(|(&user_id, connection)| {
+                PeerDiagnostics::new(
+                    Some(user_id),
+                    &connection.peer_connection,
+                    connection.data_channel.as_ref(),
+                    inner.ice_gathering.refused_remote_candidates(&user_id),
+                    inner.ice_gathering.suppressed_local_candidates(&user_id),
+                )
+            })
+            .collect();
+        peers.sort_by_key(|peer| peer.user_id);
+        ready(DiagnosticsReport::new(
+            inner.session_id,
+            &inner.signaling,
+            inner.signaling_server_url.clone(),
+            Some(inner.is_host),
+            &inner.connection_type,
+            peers,
+        ))
+    }
+
+    /// Asks signaling server for sessions open on its one-to-many endpoint, e.g. to show a lobby of games.
+    /// Resolves with an empty list if the server doesn't respond within 5 seconds.
+    ///
+    /// Response is only handled once network manager is started, so it has to be called after
+    /// one of `start` methods.
+    pub fn list_sessions(&self) -> impl Future<Output = Vec<SessionInfo>> {
+        let (signaling, session_lists) = {
+            let inner = self.inner.borrow();
+            (inner.signaling.clone(), inner.session_lists.clone())
+        };
+        async move {
+            session_lists
+                .request(
+                    &signaling,
+                    &SignalMessage::SessionListRequest,
+                    SESSION_LIST_TIMEOUT,
+                )
+                .await
+        }
+    }
+
+    /// Closes connections with all peers and the connection with signaling server.
+    pub fn close(&self) {
+        let (connections, signaling) = {
+            let mut inner = self.inner.borrow_mut();
+            let connections: Vec<_> = inner.connections.drain().map(|(_, c)| c).collect();
+            (connections, inner.signaling.clone())
+        };
+        for connection in connections {
+            if let Some(data_channel) = connection.data_channel {
+                data_channel.close();
+            }
+            connection.peer_connection.close();
+        }
+        signaling.close();
+    }
+}
+
+/// Abstraction over `WebRTC` peer-to-peer connection.
+/// Structure representing server in client-server topology.
+///
+/// `WebRTC` data channel communication abstracted to a single class.
+/// All setup is handled internally, you must only provide callbacks
+/// for when the connection opens and for handling incoming messages.
+/// It also provides a method of sending data to the other end of the connection.
+///
+/// Only works with [wasm-peers-signaling-server](https://docs.rs/wasm-peers-signaling-server/latest/wasm_peers_signaling_server/) instance,
+/// whose full  address must be provided.
+///
+/// Start-up flow is divided into two methods [`MiniServer::new`] and [`MiniServer::start`]
+/// to allow possibility of referring to network manger itself from the callbacks.
+///
+/// This class is a  pointer to the underlying resource and can be cloned freely.
+#[derive(Clone)]
+pub struct MiniServer {
+    inner: NetworkManager,
+}
+
+impl Debug for MiniServer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MiniServer")
+            .field("session_id", &self.session_id())
+            .field("signaling_url", &self.signaling_url())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Display for MiniServer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&self.inner, f)
+    }
+}
+
+impl MiniServer {
+    /// Creates an instance with all resources required to create a connections to client-peers.
+    /// Requires an  address of an signaling server instance,
+    /// session id by which it will identify connecting pair of peers and type of connection.
+    ///
+    /// # Errors
+    /// This function errs if opening a `WebSocket` connection to URL provided by `signaling_server_url` fails.
+    pub fn new(
+        signaling_server_url: &str,
+        session_id: SessionId,
+        connection_type: ConnectionType,
+    ) -> crate::Result<Self> {
+        Ok(Self {
+            inner: NetworkManager::new(signaling_server_url, session_id, connection_type, true)?,
+        })
+    }
+
+    /// Same as [`MiniServer::new`], but creates a new session, reporting [`PeerEvent::SignalingError`]
+    /// with [`JoinError::AlreadyExists`](crate::JoinError::AlreadyExists) if it exists already.
+    ///
+    /// # Errors
+    /// This function errs if opening a `WebSocket` connection to URL provided by `signaling_server_url` fails.
+    pub fn new_create(
+        signaling_server_url: &str,
+        session_id: SessionId,
+        connection_type: ConnectionType,
+    ) -> crate::Result<Self> {
+        let server = Self::new(signaling_server_url, session_id, connection_type)?;
+        server.set_join_intent(JoinIntent::Create);
+        Ok(server)
+    }
+
+    /// Same as [`MiniServer::new`], but signal messages are exchanged through `signaling`
+    /// instead of a signaling server.
+    #[cfg(feature = "test-utils")]
+    #[must_use]
+    pub fn with_loopback(
+        signaling: &LoopbackSignaling,
+        session_id: SessionId,
+        connection_type: ConnectionType,
+    ) -> Self {
+        Self::with_signaling(
+            SignalingConnection::Loopback(signaling.connect(Topology::OneToMany)),
+            None,
+            session_id,
+            connection_type,
+        )
+    }
+
+    pub(crate) fn with_signaling(
+        signaling: SignalingConnection,
+        signaling_server_url: Option<String>,
+        session_id: SessionId,
+        connection_type: ConnectionType,
+    ) -> Self {
+        Self {
+            inner: NetworkManager::with_signaling(
+                signaling,
+                signaling_server_url,
+                session_id,
+                connection_type,
+                true,
+            ),
+        }
+    }
+
+    /// Same as [`MiniServer::new`], but resolves only once connection with signaling server is open.
+    ///
+    /// # Errors
+    /// This function errs if opening a `WebSocket` connection to URL provided by `signaling_server_url` fails.
+    pub async fn new_async(
+        signaling_server_url: &str,
+        session_id: SessionId,
+        connection_type: ConnectionType,
+    ) -> crate::Result<Self> {
+        Ok(Self {
+            inner: NetworkManager::new_async(
+                signaling_server_url,
+                session_id,
+                connection_type,
+                true,
+            )
+            .await?,
+        })
+    }
+
+    /// Second part of the setup that begins the actual connection.
+    /// Requires specifying a callbacks that are guaranteed to run
+    /// when the connection opens and on each message received.
+    /// It takes [`UserId`] as an argument which helps identify which client-peer.
+    ///
+    /// # Errors
+    /// Errs with [`AlreadyStartedError`] if it was already started.
+    pub fn start<T: DeserializeOwned + 'static>(
+        &mut self,
+        on_open_callback: impl FnMut(UserId) + 'static,
+        on_message_callback: impl FnMut(UserId, T) + 'static,
+    ) -> crate::Result<()> {
+        self.inner.start(on_open_callback, on_message_callback)
+    }
+
+    /// # Errors
+    /// Errs with [`AlreadyStartedError`] if it was already started.
+    pub fn start_with_retransmits<T: DeserializeOwned + 'static>(
+        &mut self,
         max_retransmits: u16,
-        on_open_callback: impl FnMut(UserId) + Clone + 'static,
-        on_message_callback: impl FnMut(UserId, T) + Clone + 'static,
+        on_open_callback: impl FnMut(UserId) + 'static,
+        on_message_callback: impl FnMut(UserId, T) + 'static,
+    ) -> crate::Result<()> {
+        self.inner
+            .start_with_retransmits(max_retransmits, on_open_callback, on_message_callback)
+    }
+
+    /// Alternative to [`MiniServer::start`] with a single handler receiving every [`PeerEvent`],
+    /// including client-peers disconnecting and signaling errors.
+    ///
+    /// # Errors
+    /// Errs with [`AlreadyStartedError`] if it was already started.
+    pub fn start_with_event_handler<T: DeserializeOwned + 'static>(
+        &mut self,
+        handler: impl FnMut(PeerEvent<T>) + 'static,
+    ) -> crate::Result<()> {
+        self.inner.start_with_event_handler(handler)
+    }
+
+    /// # Errors
+    /// Errs with [`AlreadyStartedError`] if it was already started.
+    pub fn start_with_event_handler_and_retransmits<T: DeserializeOwned + 'static>(
+        &mut self,
+        max_retransmits: u16,
+        handler: impl FnMut(PeerEvent<T>) + 'static,
+    ) -> crate::Result<()> {
+        self.inner
+            .start_with_event_handler_and_retransmits(max_retransmits, handler)
+    }
+
+    /// Sets how connections with clients are re-created after their ICE fails.
+    /// By default each connection is retried up to 3 times.
+    pub fn set_retry_config(&self, retry_config: RetryConfig) {
+        self.inner.set_retry_config(retry_config);
+    }
+
+    /// Same as [`NetworkManager::set_join_intent`]
+    pub fn set_join_intent(&self, intent: JoinIntent) {
+        self.inner.set_join_intent(intent);
+    }
+
+    /// Same as [`NetworkManager::set_data_channel_config`]
+    pub fn set_data_channel_config(&self, data_channel_config: DataChannelConfig) {
+        self.inner.set_data_channel_config(data_channel_config);
+    }
+
+    /// Same as [`NetworkManager::set_network_conditions`]
+    #[cfg(feature = "netem")]
+    pub fn set_network_conditions(&self, conditions: NetworkConditions) {
+        self.inner.set_network_conditions(conditions);
+    }
+
+    /// Registers a callback notified whenever connection with one of the clients changes its state,
+    /// including each retry attempt (e.g. to show "retrying (2/3)…") and the final outcome.
+    pub fn set_on_connection_state_change(
+        &self,
+        on_connection_state_change: impl FnMut(UserId, ConnectionState) + 'static,
     ) {
         self.inner
-            .start_with_retransmits(max_retransmits, on_open_callback, on_message_callback);
+            .set_on_connection_state_change(on_connection_state_change);
     }
 
     /// Sends message over established data channel with a single client-peer represented by
@@ -318,6 +1430,30 @@ impl MiniServer {
         self.inner.send_message(user_id, message)
     }
 
+    /// Same as [`NetworkManager::send_message_reliably`]
+    pub fn send_message_reliably<T: Serialize + ?Sized>(
+        &self,
+        user_id: UserId,
+        message: &T,
+    ) -> impl Future<Output = crate::Result<()>> {
+        self.inner.send_message_reliably(user_id, message)
+    }
+
+    /// Sends the same message to a group of client-peers, e.g. a single team in a multiplayer game.
+    /// Message is serialized only once and then sent to each of them.
+    ///
+    /// # Errors
+    /// This function errs if serialization fails or with [`MultiSendError`]
+    /// listing each client-peer to whom the message couldn't be sent,
+    /// which can be accessed with [`crate::Error::downcast_ref`].
+    pub fn send_message_to_many<T: Serialize + ?Sized>(
+        &self,
+        user_ids: &[UserId],
+        message: &T,
+    ) -> crate::Result<()> {
+        self.inner.send_message_to_many(user_ids, message)
+    }
+
     /// Convenience function that sends the same message to all connected client-peers.
     ///
     /// # Errors
@@ -329,15 +1465,230 @@ impl MiniServer {
     pub fn send_message_to_all<T: Serialize + ?Sized>(&self, message: &T) -> crate::Result<()> {
         self.inner.send_message_to_all(message)
     }
+
+    /// Sends message to all connected client-peers but `sender_id`, e.g. to pass on what it sent
+    /// in `on_message_callback` to everyone else:
+    ///
+    /// ```no_run
+    /// # use wasm_peers::one_to_many::MiniServer;
+    /// # use wasm_peers::ConnectionType;
+    /// # use wasm_peers_protocol::SessionId;
+    /// # let url = "ws://0.0.0.0:9001/one-to-many";
+    /// let mut server = MiniServer::new(url, SessionId::new(1), ConnectionType::Local).unwrap();
+    /// let relay = server.clone();
+    /// server
+    ///     .start(|_| {}, move |sender_id, message: String| {
+    ///         relay.broadcast_except_sender(sender_id, &message).unwrap();
+    ///     })
+    ///     .unwrap();
+    /// ```
+    ///
+    /// # Errors
+    /// Same as [`MiniServer::send_message_to_all`].
+    pub fn broadcast_except_sender<T: Serialize + ?Sized>(
+        &self,
+        sender_id: UserId,
+        message: &T,
+    ) -> crate::Result<()> {
+        self.inner
+            .send_message_to_all_except(Some(sender_id), message)
+    }
+
+    /// Same as [`NetworkManager::flush`]
+    ///
+    /// # Errors
+    /// This function errs with [`MultiSendError`] listing each client-peer whose batch couldn't be sent.
+    pub fn flush(&self) -> crate::Result<()> {
+        self.inner.flush()
+    }
+
+    /// Forwards messages sent by clients with [`MiniClient::send_message_to_peer`] to other clients,
+    /// disabled by default. Forwarded messages never reach host's callbacks.
+    pub fn enable_relay(&self, enabled: bool) {
+        self.inner.inner.borrow_mut().relay.set_forwarding(enabled);
+    }
+
+    /// Registers a callback deciding whether a message from the first client is forwarded
+    /// to the second one, e.g. to stop a client from flooding others. All are forwarded by default.
+    pub fn set_relay_policy(&self, allow_relay: impl FnMut(UserId, UserId) -> bool + 'static) {
+        self.inner.inner.borrow_mut().relay_policy = Some(Rc::new(RefCell::new(allow_relay)));
+    }
+
+    /// Limits number of clients connected at once, e.g. to the number of player slots of a game.
+    /// Clients over it are turned away and signaling server answers further joins with an error
+    /// until one of the clients leaves. Clients still connecting count towards the limit.
+    pub fn set_max_clients(&self, max_clients: usize) {
+        self.inner.inner.borrow_mut().max_clients = Some(max_clients);
+    }
+
+    /// Limit set with [`MiniServer::set_max_clients`], `None` if clients aren't limited.
+    #[must_use]
+    pub fn max_clients(&self) -> Option<usize> {
+        self.inner.inner.borrow().max_clients
+    }
+
+    /// Number of clients with open data channel, e.g. to show "3/8 players connected"
+    /// together with [`MiniServer::max_clients`].
+    #[must_use]
+    pub fn connected_count(&self) -> usize {
+        self.client_ids().len()
+    }
+
+    /// Ids of clients with open data channel, in ascending order. Clients are removed
+    /// once they disconnect, so unlike counting `on_open` calls it never drifts.
+    #[must_use]
+    pub fn client_ids(&self) -> Vec<UserId> {
+        self.inner.connected_peers()
+    }
+
+    /// Same as [`NetworkManager::session_id`]
+    #[must_use]
+    pub fn session_id(&self) -> SessionId {
+        self.inner.session_id()
+    }
+
+    /// Same as [`NetworkManager::signaling_url`]
+    #[must_use]
+    pub fn signaling_url(&self) -> Option<String> {
+        self.inner.signaling_url()
+    }
+
+    /// Same as [`NetworkManager::connection_type`]
+    #[must_use]
+    pub fn connection_type(&self) -> ConnectionType {
+        self.inner.connection_type()
+    }
+
+    /// Same as [`NetworkManager::update_connection_type`]
+    pub fn update_connection_type(&self, connection_type: ConnectionType) {
+        self.inner.update_connection_type(connection_type);
+    }
+
+    /// Same as [`NetworkManager::set_ice_config_provider`]
+    pub fn set_ice_config_provider<F>(&self, provider: impl Fn() -> F + 'static)
+    where
+        F: Future<Output = ConnectionType> + 'static,
+    {
+        self.inner.set_ice_config_provider(provider);
+    }
+
+    /// Signaling state of the peer connection with client `user_id`,
+    /// `None` if there is no connection with it.
+    #[must_use]
+    pub fn signaling_state_for(&self, user_id: UserId) -> Option<RtcSignalingState> {
+        self.inner.signaling_state_for(user_id)
+    }
+
+    /// Same as [`NetworkManager::channel_ready_state_for`]
+    #[must_use]
+    pub fn channel_ready_state_for(&self, user_id: UserId) -> Option<RtcDataChannelState> {
+        self.inner.channel_ready_state_for(user_id)
+    }
+
+    /// Same as [`NetworkManager::negotiated_max_message_size_for`]
+    #[must_use]
+    pub fn negotiated_max_message_size_for(&self, user_id: UserId) -> Option<usize> {
+        self.inner.negotiated_max_message_size_for(user_id)
+    }
+
+    /// Same as [`NetworkManager::peer_connection_for`]
+    #[must_use]
+    pub fn peer_connection_for(&self, user_id: UserId) -> Option<RtcPeerConnection> {
+        self.inner.peer_connection_for(user_id)
+    }
+
+    /// Same as [`NetworkManager::pending_peers`]
+    #[must_use]
+    pub fn pending_peers(&self) -> Vec<UserId> {
+        self.inner.pending_peers()
+    }
+
+    /// Same as [`NetworkManager::connected_peers`]
+    #[must_use]
+    pub fn connected_peers(&self) -> Vec<UserId> {
+        self.inner.connected_peers()
+    }
+
+    /// Same as [`NetworkManager::set_on_local_candidate`]
+    pub fn set_on_local_candidate(
+        &self,
+        on_local_candidate: impl FnMut(UserId, CandidateInfo) + 'static,
+    ) {
+        self.inner.set_on_local_candidate(on_local_candidate);
+    }
+
+    /// Same as [`NetworkManager::set_on_gathering_complete`]
+    pub fn set_on_gathering_complete(&self, on_gathering_complete: impl FnMut(UserId) + 'static) {
+        self.inner.set_on_gathering_complete(on_gathering_complete);
+    }
+
+    /// Same as [`NetworkManager::gathered_candidate_summary`]
+    #[must_use]
+    pub fn gathered_candidate_summary(&self) -> CandidateSummary {
+        self.inner.gathered_candidate_summary()
+    }
+
+    /// Same as [`NetworkManager::set_candidate_filter`]
+    pub fn set_candidate_filter(&self, filter: impl Into<CandidateFilter>) {
+        self.inner.set_candidate_filter(filter);
+    }
+
+    /// Same as [`NetworkManager::diagnostics`]
+    pub fn diagnostics(&self) -> impl Future<Output = DiagnosticsReport> {
+        self.inner.diagnostics()
+    }
+
+    /// Same as [`NetworkManager::list_sessions`]
+    pub fn list_sessions(&self) -> impl Future<Output = Vec<SessionInfo>> {
+        self.inner.list_sessions()
+    }
+
+    /// Stable id that the client joined with, see [`MiniClient::set_stable_id`].
+    /// It's known by the time `on_open_callback` is called for the client.
+    #[must_use]
+    pub fn stable_id_for(&self, user_id: UserId) -> Option<StableId> {
+        self.inner.inner.borrow().stable_ids.get(&user_id).copied()
+    }
+
+    /// Captures session id and clients connected at the moment, timestamped with `Date.now()`.
+    #[must_use]
+    pub fn snapshot(&self) -> SessionSnapshot {
+        SessionSnapshot {
+            session_id: self.session_id(),
+            connected_clients: self.connected_peers(),
+            timestamp_ms: js_sys::Date::now(),
+        }
+    }
+
+    /// Closes connections with all client-peers and the connection with signaling server.
+    /// Server shouldn't be used afterwards.
+    pub fn close(&self) {
+        self.inner.close();
+    }
 }
 
 /// Abstraction over `WebRTC` peer-to-peer connection.
 /// Same as [`MiniServer`], but representing clients in client-server topology.
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct MiniClient {
     inner: NetworkManager,
 }
 
+impl Debug for MiniClient {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MiniClient")
+            .field("session_id", &self.session_id())
+            .field("signaling_url", &self.signaling_url())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Display for MiniClient {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&self.inner, f)
+    }
+}
+
 impl MiniClient {
     /// Same as [`MiniServer::new`]
     ///
@@ -353,27 +1704,165 @@ impl MiniClient {
         })
     }
 
-    pub fn start<T: DeserializeOwned>(
+    /// Same as [`MiniClient::new`], but joins an existing session, e.g. with a code typed by the user,
+    /// reporting [`PeerEvent::SignalingError`] with [`JoinError::NoSuchSession`](crate::JoinError::NoSuchSession)
+    /// if nobody joined it yet.
+    ///
+    /// # Errors
+    /// This function errs if opening a `WebSocket` connection to URL provided by `signaling_server_url` fails.
+    pub fn new_join(
+        signaling_server_url: &str,
+        session_id: SessionId,
+        connection_type: ConnectionType,
+    ) -> crate::Result<Self> {
+        let client = Self::new(signaling_server_url, session_id, connection_type)?;
+        client.set_join_intent(JoinIntent::Join);
+        Ok(client)
+    }
+
+    /// Same as [`MiniServer::with_loopback`]
+    #[cfg(feature = "test-utils")]
+    #[must_use]
+    pub fn with_loopback(
+        signaling: &LoopbackSignaling,
+        session_id: SessionId,
+        connection_type: ConnectionType,
+    ) -> Self {
+        Self::with_signaling(
+            SignalingConnection::Loopback(signaling.connect(Topology::OneToMany)),
+            None,
+            session_id,
+            connection_type,
+        )
+    }
+
+    pub(crate) fn with_signaling(
+        signaling: SignalingConnection,
+        signaling_server_url: Option<String>,
+        session_id: SessionId,
+        connection_type: ConnectionType,
+    ) -> Self {
+        Self {
+            inner: NetworkManager::with_signaling(
+                signaling,
+                signaling_server_url,
+                session_id,
+                connection_type,
+                false,
+            ),
+        }
+    }
+
+    /// Same as [`MiniServer::new_async`]
+    ///
+    /// # Errors
+    /// This function errs if opening a `WebSocket` connection to URL provided by `signaling_server_url` fails.
+    pub async fn new_async(
+        signaling_server_url: &str,
+        session_id: SessionId,
+        connection_type: ConnectionType,
+    ) -> crate::Result<Self> {
+        Ok(Self {
+            inner: NetworkManager::new_async(
+                signaling_server_url,
+                session_id,
+                connection_type,
+                false,
+            )
+            .await?,
+        })
+    }
+
+    /// Joins the session under `stable_id`, e.g. one from [`get_random_stable_id`](crate::get_random_stable_id)
+    /// kept in local storage, so that after page reload the client takes over its previous place in session
+    /// and the host can recognize it with [`MiniServer::stable_id_for`].
+    /// Signaling server rejects it while another client with the same stable id is connected.
+    ///
+    /// Has to be called before the client is started.
+    pub fn set_stable_id(&self, stable_id: StableId) {
+        self.inner.inner.borrow_mut().stable_id = Some(stable_id);
+    }
+
+    /// Same as [`NetworkManager::set_join_intent`]
+    pub fn set_join_intent(&self, intent: JoinIntent) {
+        self.inner.set_join_intent(intent);
+    }
+
+    /// Same as [`NetworkManager::set_data_channel_config`]
+    pub fn set_data_channel_config(&self, data_channel_config: DataChannelConfig) {
+        self.inner.set_data_channel_config(data_channel_config);
+    }
+
+    /// Same as [`NetworkManager::set_network_conditions`]
+    #[cfg(feature = "netem")]
+    pub fn set_network_conditions(&self, conditions: NetworkConditions) {
+        self.inner.set_network_conditions(conditions);
+    }
+
+    /// Second part of the setup that begins the actual connection with host.
+    ///
+    /// # Errors
+    /// Errs with [`AlreadyStartedError`] if it was already started.
+    pub fn start<T: DeserializeOwned + 'static>(
         &mut self,
-        mut on_open_callback: impl FnMut() + Clone + 'static,
-        mut on_message_callback: impl FnMut(T) + Clone + 'static,
-    ) {
+        mut on_open_callback: impl FnMut() + 'static,
+        mut on_message_callback: impl FnMut(T) + 'static,
+    ) -> crate::Result<()> {
         let on_open_callback = move |_| on_open_callback();
         let on_message_callback = move |_, message| on_message_callback(message);
-        self.inner.start(on_open_callback, on_message_callback);
+        self.inner.start(on_open_callback, on_message_callback)
     }
 
     /// Same as [`MiniServer::start`], but callbacks don't take `UserId` argument, as it will always be host.
-    pub fn start_with_retransmits<T: DeserializeOwned>(
+    ///
+    /// # Errors
+    /// Errs with [`AlreadyStartedError`] if it was already started.
+    pub fn start_with_retransmits<T: DeserializeOwned + 'static>(
         &mut self,
         max_retransmits: u16,
-        mut on_open_callback: impl FnMut() + Clone + 'static,
-        mut on_message_callback: impl FnMut(T) + Clone + 'static,
-    ) {
+        mut on_open_callback: impl FnMut() + 'static,
+        mut on_message_callback: impl FnMut(T) + 'static,
+    ) -> crate::Result<()> {
         let on_open_callback = move |_| on_open_callback();
         let on_message_callback = move |_, message| on_message_callback(message);
         self.inner
-            .start_with_retransmits(max_retransmits, on_open_callback, on_message_callback);
+            .start_with_retransmits(max_retransmits, on_open_callback, on_message_callback)
+    }
+
+    /// Same as [`MiniClient::start`], but message callback takes [`UserId`] of the sender,
+    /// which is the host or the client that sent it with [`MiniClient::send_message_to_peer`].
+    ///
+    /// # Errors
+    /// Errs with [`AlreadyStartedError`] if it was already started.
+    pub fn start_with_sender<T: DeserializeOwned + 'static>(
+        &mut self,
+        mut on_open_callback: impl FnMut() + 'static,
+        on_message_callback: impl FnMut(UserId, T) + 'static,
+    ) -> crate::Result<()> {
+        let on_open_callback = move |_| on_open_callback();
+        self.inner.start(on_open_callback, on_message_callback)
+    }
+
+    /// Same as [`MiniServer::start_with_event_handler`], events carry host's [`UserId`].
+    ///
+    /// # Errors
+    /// Errs with [`AlreadyStartedError`] if it was already started.
+    pub fn start_with_event_handler<T: DeserializeOwned + 'static>(
+        &mut self,
+        handler: impl FnMut(PeerEvent<T>) + 'static,
+    ) -> crate::Result<()> {
+        self.inner.start_with_event_handler(handler)
+    }
+
+    /// # Errors
+    /// Errs with [`AlreadyStartedError`] if it was already started.
+    pub fn start_with_event_handler_and_retransmits<T: DeserializeOwned + 'static>(
+        &mut self,
+        max_retransmits: u16,
+        handler: impl FnMut(PeerEvent<T>) + 'static,
+    ) -> crate::Result<()> {
+        self.inner
+            .start_with_event_handler_and_retransmits(max_retransmits, handler)
     }
 
     /// Way of communicating with peer-server
@@ -391,4 +1880,188 @@ impl MiniClient {
     pub fn send_message_to_host<T: Serialize + ?Sized>(&self, message: &T) -> crate::Result<()> {
         self.inner.send_message_to_all(message)
     }
+
+    /// Sends message to host and resolves once it's acknowledged,
+    /// same as [`NetworkManager::send_message_reliably`].
+    ///
+    /// # Errors
+    /// This function errs if connection with host isn't open yet,
+    /// or for the same reasons as [`NetworkManager::send_message_reliably`].
+    pub fn send_message_to_host_reliably<T: Serialize + ?Sized>(
+        &self,
+        message: &T,
+    ) -> impl Future<Output = crate::Result<()>> {
+        let sent = self
+            .get_server_id()
+            .map(|host_id| self.inner.send_message_reliably(host_id, message));
+        async move {
+            sent.ok_or_else(|| anyhow!("connection with host isn't open yet"))?
+                .await
+        }
+    }
+
+    /// Sends messages waiting in a batch for the host right away, same as [`NetworkManager::flush`].
+    ///
+    /// # Errors
+    /// This function errs if sending the batch failed.
+    pub fn flush(&self) -> crate::Result<()> {
+        self.inner.flush()
+    }
+
+    /// Sends message to another client of the session through the host, which has to
+    /// [enable relaying](MiniServer::enable_relay), otherwise the message is dropped.
+    /// Receiving client gets it with [`UserId`] of this one, e.g. in [`MiniClient::start_with_sender`].
+    ///
+    /// # Errors
+    /// This function errs if connection with host isn't open yet, serialized message is larger than
+    /// [`DataChannelConfig::max_message_size`] with [`MessageTooLargeError`](crate::MessageTooLargeError),
+    /// or if sending of the message failed.
+    pub fn send_message_to_peer<T: Serialize + ?Sized>(
+        &self,
+        target: UserId,
+        message: &T,
+    ) -> crate::Result<()> {
+        let host_id = self
+            .get_server_id()
+            .ok_or_else(|| anyhow!("connection with host isn't open yet"))?;
+        let message = self
+            .inner
+            .inner
+            .borrow()
+            .data_channel_config
+            .serialize_message(message)?;
+        self.inner
+            .send_serialized_message_via(host_id, target, &message)
+    }
+
+    /// [`UserId`] of the host, the same one events passed to
+    /// [`MiniClient::start_with_event_handler`] carry, e.g. for protocols addressing peers explicitly.
+    /// `None` until connection with host opens.
+    #[must_use]
+    pub fn get_server_id(&self) -> Option<UserId> {
+        self.inner.inner.borrow().host_id
+    }
+
+    /// Same as [`NetworkManager::session_id`]
+    #[must_use]
+    pub fn session_id(&self) -> SessionId {
+        self.inner.session_id()
+    }
+
+    /// Same as [`NetworkManager::signaling_url`]
+    #[must_use]
+    pub fn signaling_url(&self) -> Option<String> {
+        self.inner.signaling_url()
+    }
+
+    /// Same as [`NetworkManager::connection_type`]
+    #[must_use]
+    pub fn connection_type(&self) -> ConnectionType {
+        self.inner.connection_type()
+    }
+
+    /// Same as [`NetworkManager::update_connection_type`]
+    pub fn update_connection_type(&self, connection_type: ConnectionType) {
+        self.inner.update_connection_type(connection_type);
+    }
+
+    /// Same as [`NetworkManager::set_ice_config_provider`]
+    pub fn set_ice_config_provider<F>(&self, provider: impl Fn() -> F + 'static)
+    where
+        F: Future<Output = ConnectionType> + 'static,
+    {
+        self.inner.set_ice_config_provider(provider);
+    }
+
+    /// Signaling state of the peer connection with host, `None` if there is no connection with it yet.
+    #[must_use]
+    pub fn signaling_state(&self) -> Option<RtcSignalingState> {
+        self.inner
+            .inner
+            .borrow()
+            .connections
+            .values()
+            .next()
+            .map(|connection| connection.peer_connection.signaling_state())
+    }
+
+    /// Ready state of the data channel with host, `None` if there is no channel with it yet,
+    /// see [`NetworkManager::channel_ready_state_for`].
+    #[must_use]
+    pub fn channel_ready_state(&self) -> Option<RtcDataChannelState> {
+        self.inner
+            .inner
+            .borrow()
+            .connections
+            .values()
+            .next()
+            .and_then(|connection| connection.data_channel.as_ref())
+            .map(RtcDataChannel::ready_state)
+    }
+
+    /// Largest message host accepts, `None` if there is no connection with it yet,
+    /// see [`NetworkManager::negotiated_max_message_size_for`].
+    #[must_use]
+    pub fn negotiated_max_message_size(&self) -> Option<usize> {
+        self.inner
+            .inner
+            .borrow()
+            .connections
+            .values()
+            .next()
+            .and_then(|connection| negotiated_max_message_size(&connection.peer_connection))
+    }
+
+    /// Underlying peer connection with host, `None` if there is no connection with it yet,
+    /// see [`NetworkManager::peer_connection_for`].
+    #[must_use]
+    pub fn peer_connection(&self) -> Option<RtcPeerConnection> {
+        self.inner
+            .inner
+            .borrow()
+            .connections
+            .values()
+            .next()
+            .map(|connection| connection.peer_connection.clone())
+    }
+
+    /// Same as [`NetworkManager::set_on_local_candidate`]
+    pub fn set_on_local_candidate(
+        &self,
+        on_local_candidate: impl FnMut(UserId, CandidateInfo) + 'static,
+    ) {
+        self.inner.set_on_local_candidate(on_local_candidate);
+    }
+
+    /// Same as [`NetworkManager::set_on_gathering_complete`]
+    pub fn set_on_gathering_complete(&self, on_gathering_complete: impl FnMut(UserId) + 'static) {
+        self.inner.set_on_gathering_complete(on_gathering_complete);
+    }
+
+    /// Same as [`NetworkManager::gathered_candidate_summary`]
+    #[must_use]
+    pub fn gathered_candidate_summary(&self) -> CandidateSummary {
+        self.inner.gathered_candidate_summary()
+    }
+
+    /// Same as [`NetworkManager::set_candidate_filter`]
+    pub fn set_candidate_filter(&self, filter: impl Into<CandidateFilter>) {
+        self.inner.set_candidate_filter(filter);
+    }
+
+    /// Same as [`NetworkManager::diagnostics`]
+    pub fn diagnostics(&self) -> impl Future<Output = DiagnosticsReport> {
+        self.inner.diagnostics()
+    }
+
+    /// Same as [`NetworkManager::list_sessions`]
+    pub fn list_sessions(&self) -> impl Future<Output = Vec<SessionInfo>> {
+        self.inner.list_sessions()
+    }
+
+    /// Closes connection with the host and the connection with signaling server.
+    /// Client shouldn't be used afterwards.
+    pub fn close(&self) {
+        self.inner.close();
+    }
 }
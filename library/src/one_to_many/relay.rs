@@ -0,0 +1,301 @@
+/*!
+Relaying of messages through a peer connected to both sides, for many-to-many peers
+whose direct connection failed, e.g. with both of them behind symmetric NATs and no TURN server,
+and for one-to-many clients messaging each other through the host.
+
+Peers with relaying enabled advertise to each other which peers they have open data channels with,
+in text frames starting with a NUL character, like pings.
+Relayed messages are binary frames starting with `0xc1`, a byte which `MessagePack` never uses,
+followed by the number of hops left, id of a peer and the serialized message.
+Sender addresses the frame to the final recipient with a single hop left, intermediary replaces
+the recipient with the sender and forwards it with no hops left, so it's never forwarded again.
+*/
+
+use std::collections::{HashMap, HashSet};
+use std::convert::TryInto;
+
+use anyhow::anyhow;
+use js_sys::{ArrayBuffer, Uint8Array};
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_peers_protocol::UserId;
+use web_sys::RtcDataChannel;
+
+const RELAY_MARKER: u8 = 0xc1;
+const PEERS_PREFIX: &str = "\0peers:";
+/// Marker, hops left and peer id preceding the payload
+const HEADER_LEN: usize = 10;
+/// Relayed messages make at most a single hop through an intermediary
+const MAX_HOPS: u8 = 1;
+
+/// Serialized message sent to an intermediary, to be forwarded to `final_recipient`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RelayedMessage {
+    pub final_recipient: UserId,
+    pub payload: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RelayFrame {
+    /// Message to be forwarded by the receiving peer
+    Relay(RelayedMessage),
+    /// Message forwarded by the receiving peer on behalf of `source`
+    Relayed { source: UserId, payload: Vec<u8> },
+}
+
+impl RelayFrame {
+    /// Reads relay frame received on a data channel, `None` if `data` is a different kind of message.
+    pub fn parse(data: &JsValue) -> Option<Self> {
+        if !(data.is_instance_of::<ArrayBuffer>() || data.is_instance_of::<Uint8Array>()) {
+            return None;
+        }
+        let bytes = Uint8Array::new(data);
+        // avoid copying user messages
+        if bytes.length() == 0 || bytes.get_index(0) != RELAY_MARKER {
+            return None;
+        }
+        Self::from_bytes(&bytes.to_vec())
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let (&marker, rest) = bytes.split_first()?;
+        let (&hops_left, rest) = rest.split_first()?;
+        if marker != RELAY_MARKER {
+            return None;
+        }
+        let peer = UserId::new(u64::from_be_bytes(rest.get(..8)?.try_into().ok()?));
+        let payload = rest.get(8..)?.to_vec();
+        match hops_left {
+            0 => Some(Self::Relayed {
+                source: peer,
+                payload,
+            }),
+            MAX_HOPS => Some(Self::Relay(RelayedMessage {
+                final_recipient: peer,
+                payload,
+            })),
+            _ => None,
+        }
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let (hops_left, peer, payload) = match *self {
+            Self::Relay(ref message) => (MAX_HOPS, message.final_recipient, &message.payload),
+            Self::Relayed {
+                source,
+                ref payload,
+            } => (0, source, payload),
+        };
+        let mut bytes = Vec::with_capacity(HEADER_LEN.saturating_add(payload.len()));
+        bytes.push(RELAY_MARKER);
+        bytes.push(hops_left);
+        bytes.extend_from_slice(&peer.into_inner().to_be_bytes());
+        bytes.extend_from_slice(payload);
+        bytes
+    }
+
+    pub fn send(&self, data_channel: &RtcDataChannel) -> crate::Result<()> {
+        data_channel
+            .send_with_u8_array(&self.to_bytes())
+            .map_err(|err| anyhow!("failed to send relay frame: {:?}", err))
+    }
+}
+
+/// Reads peers advertised by a neighbour, `None` if `data` is a different kind of message.
+pub fn parse_advertised_peers(data: &JsValue) -> Option<Vec<UserId>> {
+    parse_peers(&data.as_string()?)
+}
+
+fn parse_peers(text: &str) -> Option<Vec<UserId>> {
+    let peers = text.strip_prefix(PEERS_PREFIX)?;
+    if peers.is_empty() {
+        return Some(Vec::new());
+    }
+    peers
+        .split(',')
+        .map(|peer| peer.parse().ok().map(UserId::new))
+        .collect()
+}
+
+fn peers_to_text(peers: &[UserId]) -> String {
+    let peers: Vec<String> = peers.iter().map(ToString::to_string).collect();
+    format!("{}{}", PEERS_PREFIX, peers.join(","))
+}
+
+/// Tells a neighbour which peers messages can be relayed to through this one.
+pub fn advertise_peers(data_channel: &RtcDataChannel, peers: &[UserId]) -> crate::Result<()> {
+    data_channel
+        .send_with_str(&peers_to_text(peers))
+        .map_err(|err| anyhow!("failed to advertise peers: {:?}", err))
+}
+
+/// Which peers can be reached through which neighbours.
+#[derive(Debug, Default)]
+pub struct RelayState {
+    enabled: bool,
+    /// Set on one-to-many host forwarding messages between clients, without advertising peers
+    forwarding: bool,
+    /// Peers each neighbour has an open data channel with, as last advertised by it
+    neighbours: HashMap<UserId, HashSet<UserId>>,
+    /// Peers whose direct connection failed for good
+    unreachable: HashSet<UserId>,
+    /// Intermediary through which messages to an unreachable peer are relayed
+    routes: HashMap<UserId, UserId>,
+}
+
+impl RelayState {
+    pub const fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    #[cfg(any(test, feature = "many-to-many"))]
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// Whether messages relayed through this peer are forwarded.
+    pub const fn forwards(&self) -> bool {
+        self.enabled || self.forwarding
+    }
+
+    pub fn set_forwarding(&mut self, forwarding: bool) {
+        self.forwarding = forwarding;
+    }
+
+    pub fn route_for(&self, user_id: UserId) -> Option<UserId> {
+        self.routes.get(&user_id).copied()
+    }
+
+    pub fn set_neighbour_peers(&mut self, neighbour: UserId, peers: Vec<UserId>) {
+        self.neighbours
+            .insert(neighbour, peers.into_iter().collect());
+    }
+
+    pub fn remove_neighbour(&mut self, neighbour: UserId) {
+        self.neighbours.remove(&neighbour);
+    }
+
+    pub fn set_unreachable(&mut self, user_id: UserId) {
+        self.unreachable.insert(user_id);
+    }
+
+    /// Direct connection with `user_id` is open again, so its messages are no longer relayed.
+    pub fn set_reachable(&mut self, user_id: UserId) {
+        self.unreachable.remove(&user_id);
+        self.routes.remove(&user_id);
+    }
+
+    pub fn forget(&mut self, user_id: UserId) {
+        self.set_reachable(user_id);
+        self.remove_neighbour(user_id);
+    }
+
+    /// Picks an intermediary for each unreachable peer, keeping the current one while it's still usable.
+    /// Returns peers whose route changed, with `None` if they can no longer be reached.
+    pub fn update_routes(&mut self) -> Vec<(UserId, Option<UserId>)> {
+        let mut unreachable: Vec<UserId> = self.unreachable.iter().copied().collect();
+        unreachable.sort_unstable();
+        let mut neighbours: Vec<UserId> = self.neighbours.keys().copied().collect();
+        neighbours.sort_unstable();
+
+        let mut changes = Vec::new();
+        for user_id in unreachable {
+            let can_relay = |neighbour: &UserId| {
+                self.enabled
+                    && *neighbour != user_id
+                    && self
+                        .neighbours
+                        .get(neighbour)
+                        .map_or(false, |peers| peers.contains(&user_id))
+            };
+            let current = self.routes.get(&user_id).copied();
+            let route = current
+                .filter(can_relay)
+                .or_else(|| neighbours.iter().copied().find(can_relay));
+            if route != current {
+                match route {
+                    Some(via) => self.routes.insert(user_id, via),
+                    None => self.routes.remove(&user_id),
+                };
+                changes.push((user_id, route));
+            }
+        }
+        changes
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+
+    #[wasm_bindgen_test]
+    fn test_relay_frames_round_trip() {
+        let frames = [
+            RelayFrame::Relay(RelayedMessage {
+                final_recipient: UserId::new(u64::MAX),
+                payload: vec![0x92, 0x01, 0x02],
+            }),
+            RelayFrame::Relayed {
+                source: UserId::new(1),
+                payload: Vec::new(),
+            },
+        ];
+        for frame in frames {
+            assert_eq!(RelayFrame::from_bytes(&frame.to_bytes()), Some(frame));
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn test_frames_with_more_hops_are_rejected() {
+        let bytes = [
+            &[RELAY_MARKER, MAX_HOPS.saturating_add(1)][..],
+            &UserId::new(1).into_inner().to_be_bytes(),
+            &[0xc0],
+        ]
+        .concat();
+        assert_eq!(RelayFrame::from_bytes(&bytes), None);
+        assert_eq!(RelayFrame::from_bytes(&[RELAY_MARKER, 0, 1]), None);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_advertised_peers_round_trip() {
+        for peers in [vec![], vec![UserId::new(1), UserId::new(u64::MAX)]] {
+            assert_eq!(parse_peers(&peers_to_text(&peers)), Some(peers));
+        }
+        assert_eq!(parse_peers("\0ping:1"), None);
+        assert_eq!(parse_peers("\0peers:1,x"), None);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_route_goes_through_neighbour_connected_to_unreachable_peer() {
+        let (unreachable, neighbour, other) = (UserId::new(1), UserId::new(2), UserId::new(3));
+        let mut relay = RelayState::default();
+        relay.set_enabled(true);
+        relay.set_neighbour_peers(other, vec![]);
+        relay.set_neighbour_peers(neighbour, vec![unreachable, other]);
+        relay.set_unreachable(unreachable);
+
+        assert_eq!(relay.update_routes(), vec![(unreachable, Some(neighbour))]);
+        assert_eq!(relay.route_for(unreachable), Some(neighbour));
+        assert_eq!(relay.update_routes(), vec![]);
+
+        relay.remove_neighbour(neighbour);
+        assert_eq!(relay.update_routes(), vec![(unreachable, None)]);
+        assert_eq!(relay.route_for(unreachable), None);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_disabled_relay_has_no_routes() {
+        let (unreachable, neighbour) = (UserId::new(1), UserId::new(2));
+        let mut relay = RelayState::default();
+        relay.set_neighbour_peers(neighbour, vec![unreachable]);
+        relay.set_unreachable(unreachable);
+        assert_eq!(relay.update_routes(), vec![]);
+
+        relay.set_enabled(true);
+        assert_eq!(relay.update_routes(), vec![(unreachable, Some(neighbour))]);
+        relay.set_enabled(false);
+        assert_eq!(relay.update_routes(), vec![(unreachable, None)]);
+    }
+}
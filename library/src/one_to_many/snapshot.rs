@@ -0,0 +1,64 @@
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use wasm_peers_protocol::{SessionId, UserId};
+
+/// State of a [`MiniServer`](super::MiniServer)'s session at some point in time,
+/// e.g. to be stored and restored, or sent to a newly joined client in its `on_open_callback`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionSnapshot {
+    /// Session ids don't fit into JSON numbers, so it's written as a string
+    #[serde(serialize_with = "serialize_session_id")]
+    #[serde(deserialize_with = "deserialize_session_id")]
+    pub session_id: SessionId,
+    /// Clients with open data channels, in ascending order
+    pub connected_clients: Vec<UserId>,
+    /// Milliseconds since Unix epoch, from `Date.now()`
+    pub timestamp_ms: f64,
+}
+
+impl From<&SessionSnapshot> for String {
+    /// Serializes snapshot to JSON.
+    fn from(snapshot: &SessionSnapshot) -> Self {
+        serde_json::to_string(snapshot).expect("session snapshot is always valid JSON")
+    }
+}
+
+fn serialize_session_id<S: Serializer>(
+    session_id: &SessionId,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(&session_id.inner())
+}
+
+fn deserialize_session_id<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<SessionId, D::Error> {
+    String::deserialize(deserializer)?
+        .parse()
+        .map_err(D::Error::custom)
+}
+
+#[cfg(test)]
+mod test {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+
+    #[wasm_bindgen_test]
+    fn test_snapshot_json_round_trips() {
+        let snapshot = SessionSnapshot {
+            session_id: SessionId::new(u128::MAX),
+            connected_clients: vec![UserId::new(2), UserId::new(5)],
+            timestamp_ms: 1_700_000_000_000.0,
+        };
+        let json = String::from(&snapshot);
+        assert_eq!(
+            json,
+            r#"{"session_id":"340282366920938463463374607431768211455","connected_clients":[2,5],"timestamp_ms":1700000000000.0}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<SessionSnapshot>(&json).ok(),
+            Some(snapshot)
+        );
+    }
+}
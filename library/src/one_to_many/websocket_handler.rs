@@ -1,47 +1,65 @@
+use std::rc::Rc;
+
 use anyhow::anyhow;
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 use serde::de::DeserializeOwned;
 use wasm_bindgen_futures::JsFuture;
 use wasm_peers_protocol::one_to_many::SignalMessage;
-use wasm_peers_protocol::{SessionId, UserId};
+use wasm_peers_protocol::{IceCandidate, SessionId, UserId};
 use web_sys::{
-    RtcDataChannelInit, RtcIceCandidate, RtcIceCandidateInit, RtcSdpType,
-    RtcSessionDescriptionInit, WebSocket,
+    RtcDataChannelState, RtcIceConnectionState, RtcPeerConnection, RtcSdpType,
+    RtcSessionDescriptionInit, RtcSignalingState,
 };
 
+use crate::event::{EventHandler, LifecycleEvent};
+use crate::ice::add_remote_candidate;
 use crate::one_to_many::callbacks::{
-    set_data_channel_on_error, set_data_channel_on_message, set_data_channel_on_open,
-    set_peer_connection_on_data_channel, set_peer_connection_on_ice_candidate,
-    set_peer_connection_on_ice_connection_state_change,
+    set_data_channel_on_close, set_data_channel_on_error, set_data_channel_on_message,
+    set_data_channel_on_open, set_peer_connection_on_data_channel,
+    set_peer_connection_on_ice_candidate, set_peer_connection_on_ice_connection_state_change,
 };
 use crate::one_to_many::{Connection, NetworkManager};
+use crate::transport::{send_signal_message, SignalingTransport};
 use crate::utils::{
-    create_peer_connection, create_sdp_answer, create_sdp_offer,
+    create_data_channel_init, create_peer_connection, create_sdp_answer, create_sdp_offer,
     set_peer_connection_on_ice_gathering_state_change, set_peer_connection_on_negotiation_needed,
+    Negotiation, SdpMunger,
 };
+use crate::{CloseReason, ConnectionState, ProtocolVersionMismatchError};
 
 /// Basically a finite state machine spread across host, client and signaling server
 /// handling each step in session and then `WebRTC` setup.
-pub async fn handle_websocket_message<T: DeserializeOwned>(
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        skip_all,
+        fields(session_id = %network_manager.session_id().inner(), is_host = is_host)
+    )
+)]
+pub async fn handle_websocket_message<T: DeserializeOwned + 'static>(
     network_manager: NetworkManager,
     message: SignalMessage,
-    websocket: WebSocket,
+    transport: impl SignalingTransport + Clone + 'static,
     max_retransmits: u16,
-    on_open_callback: impl FnMut(UserId) + Clone + 'static,
-    on_message_callback: impl FnMut(UserId, T) + Clone + 'static,
+    event_handler: EventHandler<T>,
     is_host: bool,
 ) -> crate::Result<()> {
     match message {
-        SignalMessage::SessionJoin(_session_id, _peer_id) => {
+        SignalMessage::SessionJoin(..)
+        | SignalMessage::SessionJoinAs(..)
+        | SignalMessage::SessionJoinWithIntent(..)
+        | SignalMessage::SessionJoinHost(..) => {
             error!("error, SessionStartOrJoin should only be sent by peers to signaling server");
         }
+        message @ (SignalMessage::SessionListRequest | SignalMessage::SessionFullNotice(..)) => {
+            error!("error, {message:?} should only be sent by peers to signaling server");
+        }
         SignalMessage::SessionReady(session_id, peer_id) => {
             session_ready(
                 network_manager,
-                websocket,
+                transport,
                 max_retransmits,
-                on_open_callback,
-                on_message_callback,
+                event_handler,
                 is_host,
                 session_id,
                 peer_id,
@@ -51,9 +69,8 @@ pub async fn handle_websocket_message<T: DeserializeOwned>(
         SignalMessage::SdpOffer(session_id, peer_id, offer) => {
             sdp_offer(
                 network_manager,
-                websocket,
-                on_open_callback,
-                on_message_callback,
+                transport,
+                event_handler,
                 is_host,
                 session_id,
                 peer_id,
@@ -62,83 +79,74 @@ pub async fn handle_websocket_message<T: DeserializeOwned>(
             .await?;
         }
         SignalMessage::SdpAnswer(session_id, user_id, answer) => {
-            let peer_connection = network_manager
-                .inner
-                .borrow()
-                .connections
-                .get(&user_id)
-                .map(Clone::clone)
-                .map(|connection| connection.peer_connection)
-                .ok_or_else(|| {
-                    anyhow!(
-                        "(is_host: {}) no connection to send answer for given user_id: {:?}",
-                        is_host,
-                        &user_id
-                    )
-                })?;
-            let mut remote_session_description = RtcSessionDescriptionInit::new(RtcSdpType::Answer);
-            remote_session_description.sdp(&answer);
-            JsFuture::from(peer_connection.set_remote_description(&remote_session_description))
-                .await
-                .expect("failed to set remote description");
-            debug!(
-                "received answer from peer and set remote description: {}, {:?}",
-                answer, session_id
-            );
+            sdp_answer(&network_manager, is_host, session_id, user_id, &answer).await?;
         }
-        SignalMessage::IceCandidate(_session_id, user_id, ice_candidate) => {
-            let peer_connection = network_manager
-                .inner
-                .borrow()
-                .connections
-                .get(&user_id)
-                .map(Clone::clone)
-                .map(|connection| connection.peer_connection)
-                .ok_or_else(|| {
-                    anyhow!(
-                        "no connection to send ice candidate to for given user_id: {:?}",
-                        &user_id
-                    )
-                })?;
-            debug!("peer received ice candidate: {:?}", &ice_candidate);
-
-            let mut rtc_candidate = RtcIceCandidateInit::new("");
-            rtc_candidate.candidate(&ice_candidate.candidate);
-            rtc_candidate.sdp_m_line_index(ice_candidate.sdp_m_line_index);
-            rtc_candidate.sdp_mid(ice_candidate.sdp_mid.as_deref());
-
-            let rtc_candidate = RtcIceCandidate::new(&rtc_candidate)
-                .map_err(|err| anyhow!("failed to create RTC ICE candidate: {:?}", err))?;
-            JsFuture::from(
-                peer_connection.add_ice_candidate_with_opt_rtc_ice_candidate(Some(&rtc_candidate)),
-            )
-            .await
-            .map_err(|err| {
-                anyhow!(
-                    "failed to add ice candidate with optional RTC ICE candidate: {:?}",
-                    err
-                )
-            })?;
-            debug!("added ice candidate {:?}", ice_candidate);
+        SignalMessage::IceCandidate(_session_id, user_id, candidate) => {
+            ice_candidate(&network_manager, user_id, &candidate).await?;
         }
         SignalMessage::Error(session_id, user_id, error) => {
             error!(
                 "signaling server returned error: session id: {session_id:?}, user_id: \
                  {user_id:?}, error: {error}",
             );
+            network_manager.notify(LifecycleEvent::SignalingError(error));
+        }
+        SignalMessage::SessionJoinError(session_id, error) => {
+            error!(
+                "signaling server refused joining session: session id: {session_id:?}, error: {error}",
+            );
+            network_manager.notify(LifecycleEvent::SignalingError(error.to_string()));
+        }
+        SignalMessage::SessionLeave(session_id, peer_id) => {
+            session_leave(&network_manager, session_id, peer_id);
+        }
+        SignalMessage::ClientIdentity(_session_id, client_id, stable_id) => {
+            debug!("client {:?} joined as {}", client_id, stable_id);
+            let mut inner = network_manager.inner.borrow_mut();
+            inner.stable_ids.insert(client_id, stable_id);
+        }
+        SignalMessage::SessionListResponse(sessions) => {
+            let session_lists = network_manager.inner.borrow().session_lists.clone();
+            session_lists.received(sessions);
+        }
+        SignalMessage::SessionMembers(_session_id, own_id, members) => {
+            session_members(&network_manager, own_id, members);
+        }
+        SignalMessage::ProtocolVersion { major, minor } => {
+            info!("signaling server speaks protocol v{}.{}", major, minor);
+        }
+        SignalMessage::ProtocolVersionMismatch {
+            server_major,
+            server_minor,
+        } => {
+            return Err(ProtocolVersionMismatchError {
+                server_major,
+                server_minor,
+            }
+            .into());
         }
     }
 
     Ok(())
 }
 
+/// Starts tracking members of many-to-many session to elect its leader.
+fn session_members(network_manager: &NetworkManager, own_id: UserId, members: Vec<UserId>) {
+    debug!("joined as {:?} along with {:?}", own_id, members);
+    let leader_election = network_manager.inner.borrow().leader_election.clone();
+    leader_election.joined(own_id, members);
+}
+
 #[allow(clippy::too_many_arguments)]
-async fn session_ready<T: DeserializeOwned>(
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(skip_all, fields(user_id = peer_id.into_inner()))
+)]
+async fn session_ready<T: DeserializeOwned + 'static>(
     network_manager: NetworkManager,
-    websocket: WebSocket,
+    transport: impl SignalingTransport + Clone + 'static,
     max_retransmits: u16,
-    on_open_callback: impl FnMut(UserId) + Clone + 'static,
-    on_message_callback: impl FnMut(UserId, T) + Clone + 'static,
+    event_handler: EventHandler<T>,
     is_host: bool,
     session_id: SessionId,
     peer_id: UserId,
@@ -147,39 +155,101 @@ async fn session_ready<T: DeserializeOwned>(
         "peer received info that session with {:?} is ready {:?}",
         peer_id, session_id
     );
-    let peer_connection = create_peer_connection(&network_manager.inner.borrow().connection_type)?;
+    if !is_host {
+        // host starts the connection, client only waits for its offer
+        debug!("client is waiting for offer from host {:?}", peer_id);
+        return Ok(());
+    }
+    if live_connection(&network_manager, peer_id).is_some() {
+        warn!(
+            "(is_host: {}) connection with {:?} already exists, ignoring repeated SessionReady",
+            is_host, peer_id
+        );
+        return Ok(());
+    }
+    if turn_away_if_full(&network_manager, &transport, session_id, peer_id)? {
+        return Ok(());
+    }
+    network_manager.refresh_connection_type().await;
+    let peer_connection = create_peer_connection(&network_manager.connection_type_for(peer_id))?;
     set_peer_connection_on_data_channel(
         &peer_connection,
         peer_id,
         network_manager.clone(),
-        on_open_callback.clone(),
-        on_message_callback.clone(),
+        Rc::clone(&event_handler),
+    );
+    set_peer_connection_on_ice_candidate(
+        &peer_connection,
+        peer_id,
+        transport.clone(),
+        session_id,
+        network_manager.ice_gathering(),
+    );
+    set_peer_connection_on_ice_connection_state_change(
+        &peer_connection,
+        peer_id,
+        network_manager.clone(),
+        {
+            let network_manager = network_manager.clone();
+            let transport = transport.clone();
+            let event_handler = Rc::clone(&event_handler);
+            move || {
+                retry_connection(
+                    network_manager.clone(),
+                    transport.clone(),
+                    max_retransmits,
+                    Rc::clone(&event_handler),
+                    is_host,
+                    session_id,
+                    peer_id,
+                );
+            }
+        },
+    );
+    set_peer_connection_on_ice_gathering_state_change(&peer_connection, {
+        let ice_gathering = network_manager.ice_gathering();
+        move || ice_gathering.gathering_complete(peer_id)
+    });
+    // peer sending the initial offer is the impolite one
+    let negotiation = renegotiate_on_demand(
+        &peer_connection,
+        &network_manager,
+        transport.clone(),
+        session_id,
+        peer_id,
+        false,
     );
-    set_peer_connection_on_ice_candidate(&peer_connection, peer_id, websocket.clone(), session_id);
-    set_peer_connection_on_ice_connection_state_change(&peer_connection);
-    set_peer_connection_on_ice_gathering_state_change(&peer_connection);
-    set_peer_connection_on_negotiation_needed(&peer_connection);
-
-    let mut init = RtcDataChannelInit::new();
-    init.max_retransmits(max_retransmits);
-    init.ordered(false);
+
+    let (label, init) = {
+        let inner = network_manager.inner.borrow();
+        (
+            inner.data_channel_config.label_or_session_id(session_id),
+            create_data_channel_init(max_retransmits, &inner.data_channel_config),
+        )
+    };
     let data_channel = peer_connection
-        .create_data_channel_with_data_channel_dict(&format!("{}-{}", session_id, peer_id), &init);
+        .create_data_channel_with_data_channel_dict(&format!("{}-{}", label, peer_id), &init);
 
-    set_data_channel_on_open(&data_channel, peer_id, on_open_callback.clone());
+    set_data_channel_on_open(&data_channel, peer_id, network_manager.clone());
     set_data_channel_on_error(&data_channel);
-    set_data_channel_on_message(&data_channel, peer_id, on_message_callback.clone());
-
-    let offer = create_sdp_offer(&peer_connection).await?;
-    let signal_message = SignalMessage::SdpOffer(session_id, peer_id, offer);
-    let signal_message = rmp_serde::to_vec(&signal_message)?;
-    websocket
-        .send_with_u8_array(&signal_message)
-        .map_err(|err| anyhow!("failed to send message across the websocket: {:?}", err))?;
+    set_data_channel_on_message(
+        &data_channel,
+        peer_id,
+        network_manager.clone(),
+        event_handler,
+    );
+    set_data_channel_on_close(&data_channel, peer_id, network_manager.clone());
+
     network_manager.inner.borrow_mut().connections.insert(
         peer_id,
-        Connection::new(peer_connection.clone(), Some(data_channel.clone())),
+        Connection::new(peer_connection.clone(), negotiation, Some(data_channel)),
     );
+
+    let offer = create_sdp_offer(&peer_connection, network_manager.sdp_munger().as_ref()).await?;
+    send_signal_message(
+        &transport,
+        &SignalMessage::SdpOffer(session_id, peer_id, offer),
+    )?;
     debug!(
         "(is_host: {}) sent an offer to {:?} successfully",
         is_host, peer_id
@@ -188,87 +258,666 @@ async fn session_ready<T: DeserializeOwned>(
 }
 
 #[allow(clippy::too_many_arguments)]
-async fn sdp_offer<T: DeserializeOwned>(
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(skip_all, fields(user_id = peer_id.into_inner()))
+)]
+async fn sdp_offer<T: DeserializeOwned + 'static>(
     network_manager: NetworkManager,
-    websocket: WebSocket,
-    on_open_callback: impl FnMut(UserId) + Clone + 'static,
-    on_message_callback: impl FnMut(UserId, T) + Clone + 'static,
+    transport: impl SignalingTransport + Clone + 'static,
+    event_handler: EventHandler<T>,
     is_host: bool,
     session_id: SessionId,
     peer_id: UserId,
     offer: String,
 ) -> crate::Result<()> {
+    // never replace a live connection, it might already have an open data channel
+    if let Some(connection) = live_connection(&network_manager, peer_id) {
+        let on_sdp = network_manager.sdp_munger();
+        return renegotiate(transport, session_id, peer_id, &connection, offer, on_sdp).await;
+    }
+
     // non-host peer received an offer
-    let peer_connection = create_peer_connection(&network_manager.inner.borrow().connection_type)?;
+    network_manager.refresh_connection_type().await;
+    let peer_connection = create_peer_connection(&network_manager.connection_type())?;
     set_peer_connection_on_data_channel(
         &peer_connection,
         peer_id,
         network_manager.clone(),
-        on_open_callback.clone(),
-        on_message_callback.clone(),
+        Rc::clone(&event_handler),
+    );
+    set_peer_connection_on_ice_candidate(
+        &peer_connection,
+        peer_id,
+        transport.clone(),
+        session_id,
+        network_manager.ice_gathering(),
+    );
+    set_peer_connection_on_ice_connection_state_change(
+        &peer_connection,
+        peer_id,
+        network_manager.clone(),
+        {
+            // retrying is up to the peer which sent the offer
+            let network_manager = network_manager.clone();
+            move || {
+                network_manager.notify_connection_state(peer_id, ConnectionState::Failed);
+                network_manager.relay_connection_failed(peer_id);
+            }
+        },
+    );
+    set_peer_connection_on_ice_gathering_state_change(&peer_connection, {
+        let ice_gathering = network_manager.ice_gathering();
+        move || ice_gathering.gathering_complete(peer_id)
+    });
+    // peer answering the initial offer is the polite one
+    let negotiation = renegotiate_on_demand(
+        &peer_connection,
+        &network_manager,
+        transport.clone(),
+        session_id,
+        peer_id,
+        true,
     );
-    set_peer_connection_on_ice_candidate(&peer_connection, peer_id, websocket.clone(), session_id);
-    set_peer_connection_on_ice_connection_state_change(&peer_connection);
-    set_peer_connection_on_ice_gathering_state_change(&peer_connection);
-    set_peer_connection_on_negotiation_needed(&peer_connection);
 
-    network_manager
-        .inner
-        .borrow_mut()
-        .connections
-        .insert(peer_id, Connection::new(peer_connection.clone(), None));
+    network_manager.inner.borrow_mut().connections.insert(
+        peer_id,
+        Connection::new(peer_connection.clone(), negotiation, None),
+    );
     debug!(
         "(is_host: {}) added connection for {:?} successfully",
         is_host, peer_id
     );
 
-    let answer = create_sdp_answer(&peer_connection, offer)
-        .await
-        .expect("failed to create SDP answer");
+    let answer = create_sdp_answer(
+        &peer_connection,
+        offer,
+        network_manager.sdp_munger().as_ref(),
+    )
+    .await?;
     debug!(
         "received an offer from {:?} and created an answer: {}",
         peer_id, answer
     );
-    let signal_message = SignalMessage::SdpAnswer(session_id, peer_id, answer);
-    let signal_message =
-        rmp_serde::to_vec(&signal_message).expect("failed to serialize SignalMessage");
-    websocket
-        .send_with_u8_array(&signal_message)
-        .expect("failed to send SPD answer to signaling server");
+    send_signal_message(
+        &transport,
+        &SignalMessage::SdpAnswer(session_id, peer_id, answer),
+    )
+}
+
+/// Adds candidate received from `user_id`, candidates the browser refuses are skipped,
+/// see [`add_remote_candidate`].
+async fn ice_candidate(
+    network_manager: &NetworkManager,
+    user_id: UserId,
+    ice_candidate: &IceCandidate,
+) -> crate::Result<()> {
+    let peer_connection = network_manager
+        .inner
+        .borrow()
+        .connections
+        .get(&user_id)
+        .map(Clone::clone)
+        .map(|connection| connection.peer_connection)
+        .ok_or_else(|| {
+            anyhow!(
+                "no connection to send ice candidate to for given user_id: {:?}",
+                &user_id
+            )
+        })?;
+    let on_all_refused = {
+        let network_manager = network_manager.clone();
+        move || {
+            network_manager.notify(LifecycleEvent::SignalingError(format!(
+                "browser refused every ICE candidate of peer {}",
+                user_id
+            )));
+        }
+    };
+    add_remote_candidate(
+        &peer_connection,
+        ice_candidate,
+        user_id,
+        &network_manager.ice_gathering(),
+        on_all_refused,
+    )
+    .await;
     Ok(())
 }
 
-// #[cfg(test)]
-// mod test {
-//     use super::*;
-//     use mockall::mock;
-//     use wasm_bindgen_test::{wasm_bindgen_test, wasm_bindgen_test_configure};
-//     use web_sys::{RtcIceConnectionState, RtcIceGatheringState};
-//     use wasm_peers_protocol::SessionId;
-//
-//     wasm_bindgen_test_configure!(run_in_browser);
-//
-//     mock! {
-//         WebSocket {}
-//     }
-//
-//     // #[wasm_bindgen_test]
-//     async fn test_handle_session_ready_signal_is_successful() {
-//         let message = SignalMessage::SessionReady(SessionId::new("dummy-session-id".to_string()), true);
-//         let peer_connection = RtcPeerConnection::new().unwrap();
-//
-//         // TODO(tkarwowski): this should be mocked, but how do you pass a mock to a function expecting different type?
-//         //  I could introduce a trait, implement it for web_sys::WebSocket and MockWebSocket as well,
-//         //  but that's a lot of work...
-//         //  This is a integration test for now.
-//         let websocket = WebSocket::new("ws://0.0.0.0:9001/ws")
-//             .expect("local signaling server instance was not found");
-//         websocket.set_binary_type(web_sys::BinaryType::Arraybuffer);
-//
-//         // FIXME(tkarwowski): this fails because peer_connection state gets modified in other tests
-//         handle_websocket_message(message, peer_connection.clone(), websocket)
-//             .await
-//             .unwrap();
-//         assert!(peer_connection.local_description().is_some());
-//     }
-// }
+async fn sdp_answer(
+    network_manager: &NetworkManager,
+    is_host: bool,
+    session_id: SessionId,
+    user_id: UserId,
+    answer: &str,
+) -> crate::Result<()> {
+    let peer_connection = network_manager
+        .inner
+        .borrow()
+        .connections
+        .get(&user_id)
+        .map(Clone::clone)
+        .map(|connection| connection.peer_connection)
+        .ok_or_else(|| {
+            anyhow!(
+                "(is_host: {}) no connection to send answer for given user_id: {:?}",
+                is_host,
+                &user_id
+            )
+        })?;
+    let mut remote_session_description = RtcSessionDescriptionInit::new(RtcSdpType::Answer);
+    remote_session_description.sdp(answer);
+    JsFuture::from(peer_connection.set_remote_description(&remote_session_description))
+        .await
+        .map_err(|err| anyhow!("failed to set remote description: {:?}", err))?;
+    debug!(
+        "received answer from peer and set remote description: {}, {:?}",
+        answer, session_id
+    );
+    Ok(())
+}
+
+/// Tears down failed connection with `peer_id` and sends a fresh offer,
+/// unless all attempts allowed by [`RetryConfig`](crate::one_to_many::RetryConfig) were used up.
+#[allow(clippy::too_many_arguments)]
+fn retry_connection<T: DeserializeOwned + 'static>(
+    network_manager: NetworkManager,
+    transport: impl SignalingTransport + Clone + 'static,
+    max_retransmits: u16,
+    event_handler: EventHandler<T>,
+    is_host: bool,
+    session_id: SessionId,
+    peer_id: UserId,
+) {
+    let (attempt, max_attempts) = {
+        let mut inner = network_manager.inner.borrow_mut();
+        let max_attempts = inner.retry_config.max_attempts;
+        let attempt = inner.retry_attempts.entry(peer_id).or_insert(0);
+        *attempt = attempt.saturating_add(1);
+        (*attempt, max_attempts)
+    };
+    if attempt > max_attempts {
+        network_manager
+            .inner
+            .borrow_mut()
+            .retry_attempts
+            .remove(&peer_id);
+        warn!(
+            "connection with {:?} failed, giving up after {} retries",
+            peer_id, max_attempts
+        );
+        network_manager.notify_connection_state(peer_id, ConnectionState::Failed);
+        network_manager.notify(LifecycleEvent::Closed(
+            peer_id,
+            CloseReason::ConnectionFailed,
+        ));
+        network_manager.relay_connection_failed(peer_id);
+        return;
+    }
+
+    let failed_connection = network_manager
+        .inner
+        .borrow_mut()
+        .connections
+        .remove(&peer_id);
+    if let Some(connection) = failed_connection {
+        connection.peer_connection.close();
+    }
+    // closed channel of the removed connection is no longer reported
+    network_manager.relay_channel_closed(peer_id, false);
+    info!(
+        "connection with {:?} failed, retrying ({}/{})",
+        peer_id, attempt, max_attempts
+    );
+    network_manager.notify_connection_state(
+        peer_id,
+        ConnectionState::Retrying {
+            attempt,
+            max_attempts,
+        },
+    );
+    wasm_bindgen_futures::spawn_local(async move {
+        if let Err(err) = session_ready(
+            network_manager,
+            transport,
+            max_retransmits,
+            event_handler,
+            is_host,
+            session_id,
+            peer_id,
+        )
+        .await
+        {
+            error!("failed to re-create connection with {:?}: {}", peer_id, err);
+        }
+    });
+}
+
+/// Drops connection with peer that left the session, reporting it as closed
+/// if its data channel was open, as the channel stops reporting once it's no longer used for the peer.
+fn session_leave(network_manager: &NetworkManager, session_id: SessionId, peer_id: UserId) {
+    info!("peer {:?} left session {:?}", peer_id, session_id);
+    let connection = {
+        let mut inner = network_manager.inner.borrow_mut();
+        inner.retry_attempts.remove(&peer_id);
+        inner.stable_ids.remove(&peer_id);
+        inner.reliable_messages.forget(&peer_id);
+        inner.batcher.forget(&peer_id);
+        if inner.host_id == Some(peer_id) {
+            inner.host_id = None;
+        }
+        inner.connections.remove(&peer_id)
+    };
+    if let Some(connection) = connection {
+        let was_open = connection
+            .data_channel
+            .as_ref()
+            .map_or(false, |data_channel| {
+                matches!(
+                    data_channel.ready_state(),
+                    RtcDataChannelState::Open | RtcDataChannelState::Closing
+                )
+            });
+        if let Some(data_channel) = connection.data_channel {
+            data_channel.close();
+        }
+        connection.peer_connection.close();
+        if was_open {
+            network_manager.notify(LifecycleEvent::Closed(peer_id, CloseReason::PeerLeft));
+        }
+    }
+    network_manager.relay_channel_closed(peer_id, true);
+}
+
+/// Tells signaling server that the session is full if host already has as many live connections
+/// as clients it accepts, returning whether `peer_id` was turned away.
+fn turn_away_if_full(
+    network_manager: &NetworkManager,
+    transport: &impl SignalingTransport,
+    session_id: SessionId,
+    peer_id: UserId,
+) -> crate::Result<bool> {
+    let is_full = {
+        let inner = network_manager.inner.borrow();
+        inner.max_clients.map_or(false, |max_clients| {
+            inner
+                .connections
+                .values()
+                .filter(|&connection| is_live(connection))
+                .count()
+                >= max_clients
+        })
+    };
+    if is_full {
+        info!("session is full, turning away {:?}", peer_id);
+        send_signal_message(
+            transport,
+            &SignalMessage::SessionFullNotice(session_id, peer_id),
+        )?;
+    }
+    Ok(is_full)
+}
+
+/// Connection with given peer, unless there is none or it was already closed or failed.
+fn live_connection(network_manager: &NetworkManager, peer_id: UserId) -> Option<Connection> {
+    network_manager
+        .inner
+        .borrow()
+        .connections
+        .get(&peer_id)
+        .filter(|&connection| is_live(connection))
+        .cloned()
+}
+
+fn is_live(connection: &Connection) -> bool {
+    connection.peer_connection.signaling_state() != RtcSignalingState::Closed
+        && connection.peer_connection.ice_connection_state() != RtcIceConnectionState::Failed
+}
+
+/// Offer for an already established connection, answer it without recreating the connection.
+async fn renegotiate(
+    transport: impl SignalingTransport,
+    session_id: SessionId,
+    peer_id: UserId,
+    connection: &Connection,
+    offer: String,
+    on_sdp: Option<SdpMunger>,
+) -> crate::Result<()> {
+    let current_offer = connection
+        .peer_connection
+        .remote_description()
+        .map(|description| description.sdp());
+    if current_offer.as_deref() == Some(offer.as_str()) {
+        warn!("ignoring duplicate offer from {:?}", peer_id);
+        return Ok(());
+    }
+    if connection
+        .negotiation
+        .ignore_offer(&connection.peer_connection)
+    {
+        debug!("ignoring offer from {:?} colliding with our own", peer_id);
+        return Ok(());
+    }
+    let answer = create_sdp_answer(&connection.peer_connection, offer, on_sdp.as_ref()).await?;
+    debug!(
+        "received renegotiation offer from {:?} and created an answer: {}",
+        peer_id, answer
+    );
+    send_signal_message(
+        &transport,
+        &SignalMessage::SdpAnswer(session_id, peer_id, answer),
+    )
+}
+
+/// Sends a new offer to `peer_id` whenever the browser requests renegotiation of connection with it.
+fn renegotiate_on_demand(
+    peer_connection: &RtcPeerConnection,
+    network_manager: &NetworkManager,
+    transport: impl SignalingTransport + 'static,
+    session_id: SessionId,
+    peer_id: UserId,
+    polite: bool,
+) -> Rc<Negotiation> {
+    let negotiation = Rc::new(Negotiation::new(polite));
+    set_peer_connection_on_negotiation_needed(
+        peer_connection,
+        Rc::clone(&negotiation),
+        renegotiation_offer_sender(transport, session_id, peer_id),
+        {
+            let network_manager = network_manager.clone();
+            move || network_manager.notify_negotiation_needed()
+        },
+        network_manager.sdp_munger(),
+    );
+    negotiation
+}
+
+fn renegotiation_offer_sender(
+    transport: impl SignalingTransport,
+    session_id: SessionId,
+    peer_id: UserId,
+) -> impl Fn(String) -> crate::Result<()> {
+    move |offer| {
+        send_signal_message(
+            &transport,
+            &SignalMessage::SdpOffer(session_id, peer_id, offer),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::Cell;
+
+    use wasm_bindgen_test::{wasm_bindgen_test, wasm_bindgen_test_configure};
+
+    use super::*;
+    use crate::constants::DEFAULT_MAX_RETRANSMITS;
+    use crate::event::event_handler;
+    use crate::transport::MockTransport;
+    use crate::{ConnectionType, PeerEvent};
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    const SIGNALING_SERVER_URL: &str = "ws://0.0.0.0:9001/one-to-many";
+
+    fn connection_for(network_manager: &NetworkManager, peer_id: UserId) -> Connection {
+        network_manager
+            .inner
+            .borrow()
+            .connections
+            .get(&peer_id)
+            .cloned()
+            .expect("no connection for peer")
+    }
+
+    /// Handles `message`, returning signal messages sent in response.
+    async fn handle(
+        network_manager: &NetworkManager,
+        message: SignalMessage,
+        is_host: bool,
+    ) -> crate::Result<Vec<SignalMessage>> {
+        let transport = MockTransport::default();
+        handle_websocket_message(
+            network_manager.clone(),
+            message,
+            transport.clone(),
+            DEFAULT_MAX_RETRANSMITS,
+            event_handler(|_: PeerEvent<()>| {}),
+            is_host,
+        )
+        .await?;
+        Ok(transport.sent_messages())
+    }
+
+    fn network_manager(session_id: SessionId, is_host: bool) -> NetworkManager {
+        NetworkManager::new(
+            SIGNALING_SERVER_URL,
+            session_id,
+            ConnectionType::Local,
+            is_host,
+        )
+        .expect("failed to create network manager")
+    }
+
+    async fn remote_offer() -> String {
+        let remote_peer_connection =
+            RtcPeerConnection::new().expect("failed to create peer connection");
+        let _remote_data_channel = remote_peer_connection.create_data_channel("remote");
+        create_sdp_offer(&remote_peer_connection, None)
+            .await
+            .expect("failed to create SDP offer")
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_session_ready_sends_offer_to_peer() {
+        let session_id = SessionId::new(5680);
+        let peer_id = UserId::new(1);
+        let network_manager = network_manager(session_id, true);
+
+        let sent = handle(
+            &network_manager,
+            SignalMessage::SessionReady(session_id, peer_id),
+            true,
+        )
+        .await
+        .expect("failed to handle SessionReady");
+
+        assert!(matches!(
+            sent.as_slice(),
+            [SignalMessage::SdpOffer(sent_session_id, sent_peer_id, _)]
+                if *sent_session_id == session_id && *sent_peer_id == peer_id
+        ));
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_sdp_offer_sends_answer_to_peer() {
+        let session_id = SessionId::new(5681);
+        let peer_id = UserId::new(1);
+        let network_manager = network_manager(session_id, false);
+
+        let sent = handle(
+            &network_manager,
+            SignalMessage::SdpOffer(session_id, peer_id, remote_offer().await),
+            false,
+        )
+        .await
+        .expect("failed to handle SdpOffer");
+
+        assert!(matches!(
+            sent.as_slice(),
+            [SignalMessage::SdpAnswer(sent_session_id, sent_peer_id, _)]
+                if *sent_session_id == session_id && *sent_peer_id == peer_id
+        ));
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_ice_candidate_is_added_only_for_known_peer() {
+        let session_id = SessionId::new(5682);
+        let peer_id = UserId::new(1);
+        let network_manager = network_manager(session_id, false);
+        let ice_candidate = IceCandidate {
+            candidate: "candidate:1 1 udp 2122260223 192.168.1.2 54321 typ host".to_owned(),
+            sdp_mid: Some("0".to_owned()),
+            sdp_m_line_index: Some(0),
+        };
+
+        let unknown_peer = handle(
+            &network_manager,
+            SignalMessage::IceCandidate(session_id, peer_id, ice_candidate.clone()),
+            false,
+        )
+        .await;
+        unknown_peer.expect_err("ICE candidate from unknown peer should be rejected");
+
+        handle(
+            &network_manager,
+            SignalMessage::SdpOffer(session_id, peer_id, remote_offer().await),
+            false,
+        )
+        .await
+        .expect("failed to handle SdpOffer");
+        let sent = handle(
+            &network_manager,
+            SignalMessage::IceCandidate(session_id, peer_id, ice_candidate),
+            false,
+        )
+        .await
+        .expect("failed to handle IceCandidate");
+        assert!(sent.is_empty());
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_repeated_session_ready_keeps_existing_connection() {
+        let session_id = SessionId::new(5678);
+        let peer_id = UserId::new(1);
+        let network_manager = network_manager(session_id, true);
+
+        handle(
+            &network_manager,
+            SignalMessage::SessionReady(session_id, peer_id),
+            true,
+        )
+        .await
+        .expect("failed to handle SessionReady");
+        let first = connection_for(&network_manager, peer_id);
+        handle(
+            &network_manager,
+            SignalMessage::SessionReady(session_id, peer_id),
+            true,
+        )
+        .await
+        .expect("failed to handle SessionReady");
+        let second = connection_for(&network_manager, peer_id);
+
+        assert_eq!(first.peer_connection, second.peer_connection);
+        assert_eq!(first.data_channel, second.data_channel);
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_duplicate_offer_keeps_existing_connection() {
+        let session_id = SessionId::new(5679);
+        let peer_id = UserId::new(1);
+        let network_manager = network_manager(session_id, false);
+        let offer = remote_offer().await;
+
+        handle(
+            &network_manager,
+            SignalMessage::SdpOffer(session_id, peer_id, offer.clone()),
+            false,
+        )
+        .await
+        .expect("failed to handle SdpOffer");
+        // simulate data channel received from the host
+        let data_channel = connection_for(&network_manager, peer_id)
+            .peer_connection
+            .create_data_channel("local");
+        network_manager
+            .inner
+            .borrow_mut()
+            .connections
+            .get_mut(&peer_id)
+            .expect("no connection for peer")
+            .data_channel = Some(data_channel.clone());
+        let first = connection_for(&network_manager, peer_id);
+
+        handle(
+            &network_manager,
+            SignalMessage::SdpOffer(session_id, peer_id, offer),
+            false,
+        )
+        .await
+        .expect("failed to handle SdpOffer");
+        let second = connection_for(&network_manager, peer_id);
+
+        assert_eq!(first.peer_connection, second.peer_connection);
+        assert_eq!(second.data_channel, Some(data_channel));
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_ice_config_provider_is_called_before_peer_connection_is_created() {
+        let session_id = SessionId::new(5683);
+        let peer_id = UserId::new(1);
+        let network_manager = network_manager(session_id, true);
+        let fresh = ConnectionType::Stun {
+            urls: "stun:stun.example.com:3478".to_owned(),
+        };
+        let calls: Rc<Cell<u32>> = Rc::new(Cell::new(0));
+        network_manager.set_ice_config_provider({
+            let calls = Rc::clone(&calls);
+            let fresh = fresh.clone();
+            move || {
+                calls.set(calls.get().saturating_add(1));
+                std::future::ready(fresh.clone())
+            }
+        });
+
+        handle(
+            &network_manager,
+            SignalMessage::SessionReady(session_id, peer_id),
+            true,
+        )
+        .await
+        .expect("failed to handle SessionReady");
+
+        assert_eq!(calls.get(), 1);
+        assert_eq!(network_manager.connection_type(), fresh);
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_malformed_ice_candidates_are_skipped() {
+        let session_id = SessionId::new(5684);
+        let peer_id = UserId::new(1);
+        let network_manager = network_manager(session_id, false);
+        handle(
+            &network_manager,
+            SignalMessage::SdpOffer(session_id, peer_id, remote_offer().await),
+            false,
+        )
+        .await
+        .expect("failed to handle SdpOffer");
+
+        for candidate in [
+            "candidate:malformed",
+            "candidate:1 1 udp 1 0b7c.local notaport typ host",
+        ] {
+            let malformed = IceCandidate {
+                candidate: candidate.to_owned(),
+                sdp_mid: Some("0".to_owned()),
+                sdp_m_line_index: Some(0),
+            };
+            handle(
+                &network_manager,
+                SignalMessage::IceCandidate(session_id, peer_id, malformed),
+                false,
+            )
+            .await
+            .expect("malformed ICE candidate should be skipped");
+        }
+
+        let ice_gathering = network_manager.ice_gathering();
+        assert_eq!(ice_gathering.refused_remote_candidates(&peer_id), 2);
+        assert!(ice_gathering.all_remote_candidates_refused(&peer_id));
+    }
+}
@@ -1,144 +1,313 @@
-use js_sys::Uint8Array;
+use std::mem;
+use std::rc::Rc;
+
 use log::{debug, error, info};
-use serde::de::DeserializeOwned;
+use serde::de::{DeserializeOwned, IntoDeserializer};
 use wasm_bindgen::closure::Closure;
 use wasm_bindgen::{JsCast, JsValue};
 use wasm_peers_protocol::one_to_one::SignalMessage;
-use wasm_peers_protocol::{IceCandidate, SessionId};
+use wasm_peers_protocol::{
+    IceCandidate, JoinIntent, SessionId, PROTOCOL_MAJOR_VERSION, PROTOCOL_MINOR_VERSION,
+};
 use web_sys::{
-    MessageEvent, RtcDataChannel, RtcDataChannelEvent, RtcPeerConnection,
-    RtcPeerConnectionIceEvent, WebSocket,
+    MessageEvent, RtcDataChannel, RtcDataChannelEvent, RtcIceConnectionState, RtcPeerConnection,
+    RtcPeerConnectionIceEvent,
 };
 
+use crate::batch;
+use crate::event::{EventHandler, LifecycleEvent, LifecycleEventHandler};
 use crate::one_to_one::{websocket_handler, NetworkManager};
+use crate::span::in_current_span;
+use crate::transport::{send_signal_message, SignalingConnection};
+use crate::utils::{deserialize_message, Negotiation};
+use crate::{ChannelState, CloseReason, ConnectionState, PeerEvent};
 
 /// also calls:
 /// * `set_data_channel_on_open`
 /// * `set_data_channel_on_message`
 /// * `set_data_channel_on_error`
-pub fn set_peer_connection_on_data_channel<T: DeserializeOwned>(
+/// * `set_data_channel_on_close`
+pub fn set_peer_connection_on_data_channel<T: DeserializeOwned + 'static>(
     peer_connection: &RtcPeerConnection,
     network_manager: NetworkManager,
-    on_open_callback: impl FnMut() + Clone + 'static,
-    on_message_callback: impl FnMut(T) + Clone + 'static,
+    event_handler: EventHandler<T, ()>,
+    lifecycle_event_handler: LifecycleEventHandler<()>,
 ) {
-    let on_datachannel: Box<dyn FnMut(RtcDataChannelEvent)> =
-        Box::new(move |data_channel_event: RtcDataChannelEvent| {
+    let on_datachannel: Box<dyn FnMut(RtcDataChannelEvent)> = Box::new(in_current_span(
+        move |data_channel_event: RtcDataChannelEvent| {
             info!("received data channel");
             let data_channel = data_channel_event.channel();
 
-            set_data_channel_on_open(&data_channel, on_open_callback.clone());
+            set_data_channel_on_open(
+                &data_channel,
+                network_manager.clone(),
+                Rc::clone(&lifecycle_event_handler),
+            );
             set_data_channel_on_error(&data_channel);
-            set_data_channel_on_message(&data_channel, on_message_callback.clone());
+            set_data_channel_on_message(
+                &data_channel,
+                network_manager.clone(),
+                Rc::clone(&event_handler),
+            );
+            set_data_channel_on_close(
+                &data_channel,
+                network_manager.clone(),
+                Rc::clone(&lifecycle_event_handler),
+            );
 
             network_manager.inner.borrow_mut().data_channel = Some(data_channel);
-        });
+        },
+    ));
     let on_datachannel = Closure::wrap(on_datachannel);
     peer_connection.set_ondatachannel(Some(on_datachannel.as_ref().unchecked_ref()));
     on_datachannel.forget();
 }
 
 /// handle message sent by signaling server
-pub fn set_websocket_on_message(websocket: &WebSocket, peer_connection: RtcPeerConnection) {
-    {
-        let on_message_callback = {
-            let websocket = websocket.clone();
-            let peer_connection = peer_connection;
-            let on_message_callback: Box<dyn FnMut(MessageEvent)> =
-                Box::new(move |ev: MessageEvent| {
-                    let Ok(message) = ev.data().dyn_into::<Uint8Array>().map(|v| v.to_vec()) else {
-                        error!("failed to convert message to Uint8Array");
-                        return;
-                    };
-                    let Ok(message) = rmp_serde::from_slice(message.as_slice()) else {
-                        error!("failed to deserialize message");
-                        return;
-                    };
-                    let websocket_clone = websocket.clone();
-                    let peer_connection_clone = peer_connection.clone();
-                    wasm_bindgen_futures::spawn_local(async move {
-                        if let Err(err) = websocket_handler::handle_websocket_message(
-                            message,
-                            peer_connection_clone,
-                            websocket_clone,
-                        )
-                        .await
-                        {
-                            error!("error handling websocket message: {:?}", err);
-                        }
-                    });
-                });
-            Closure::wrap(on_message_callback)
+pub fn set_websocket_on_message(
+    signaling: &SignalingConnection,
+    network_manager: NetworkManager,
+    peer_connection: RtcPeerConnection,
+    negotiation: Rc<Negotiation>,
+    lifecycle_event_handler: LifecycleEventHandler<()>,
+) {
+    let signaling_clone = signaling.clone();
+    signaling.set_on_message(move |message: &[u8]| {
+        let Ok(message) = rmp_serde::from_slice(message) else {
+            error!("failed to deserialize message");
+            return;
         };
-        websocket.set_onmessage(Some(on_message_callback.as_ref().unchecked_ref()));
-        on_message_callback.forget();
-    }
+        let network_manager = network_manager.clone();
+        let signaling_clone = signaling_clone.clone();
+        let peer_connection_clone = peer_connection.clone();
+        let negotiation = Rc::clone(&negotiation);
+        let lifecycle_event_handler = Rc::clone(&lifecycle_event_handler);
+        wasm_bindgen_futures::spawn_local(async move {
+            if let Err(err) = websocket_handler::handle_websocket_message(
+                message,
+                network_manager,
+                peer_connection_clone,
+                signaling_clone,
+                negotiation,
+                Rc::clone(&lifecycle_event_handler),
+            )
+            .await
+            {
+                error!("error handling websocket message: {:?}", err);
+                (lifecycle_event_handler.borrow_mut())(LifecycleEvent::SignalingError(
+                    err.to_string(),
+                ));
+            }
+        });
+    });
 }
 
 /// once web socket is open, send a request to start or join a session
-pub fn set_websocket_on_open(websocket: &WebSocket, session_id: SessionId) {
-    {
-        let websocket_clone = websocket.clone();
-        let on_open_callback: Box<dyn FnMut(JsValue)> = Box::new(move |_| {
-            let signal_message = SignalMessage::SessionJoin(session_id);
-            let signal_message =
-                rmp_serde::to_vec(&signal_message).expect("failed serializing SignalMessage");
-            websocket_clone
-                .send_with_u8_array(&signal_message)
-                .expect("failed sending start-or-join message to the websocket");
-        });
-        let on_open_callback = Closure::wrap(on_open_callback);
-        websocket.set_onopen(Some(on_open_callback.as_ref().unchecked_ref()));
-        on_open_callback.forget();
-    }
+pub fn set_websocket_on_open(
+    signaling: &SignalingConnection,
+    network_manager: NetworkManager,
+    session_id: SessionId,
+) {
+    let signaling_clone = signaling.clone();
+    signaling.set_on_open(move || {
+        let join_intent = {
+            let mut inner = network_manager.inner.borrow_mut();
+            inner.signaling_reconnect_attempts = 0;
+            mem::take(&mut inner.join_intent)
+        };
+        // announced first, so that signaling server speaking an incompatible version refuses the connection
+        let protocol_version = SignalMessage::ProtocolVersion {
+            major: PROTOCOL_MAJOR_VERSION,
+            minor: PROTOCOL_MINOR_VERSION,
+        };
+        if let Err(err) = send_signal_message(&signaling_clone, &protocol_version) {
+            error!("failed to send protocol version: {}", err);
+        }
+        // plain join is understood by signaling servers not knowing about intents
+        let signal_message = match join_intent {
+            JoinIntent::CreateOrJoin => SignalMessage::SessionJoin(session_id),
+            intent => SignalMessage::SessionJoinWithIntent(session_id, intent),
+        };
+        if let Err(err) = send_signal_message(&signaling_clone, &signal_message) {
+            error!("failed to send start-or-join message: {}", err);
+        }
+    });
+}
+
+/// Tries to reconnect once connection with signaling server is lost.
+pub fn set_websocket_on_close(
+    signaling: &SignalingConnection,
+    network_manager: NetworkManager,
+    lifecycle_event_handler: LifecycleEventHandler<()>,
+) {
+    signaling.set_on_close(move || {
+        network_manager.signaling_lost(Rc::clone(&lifecycle_event_handler));
+    });
+}
+
+/// Passes on plain text received on a data channel started in text mode, `None` if it's not text
+/// or `T` can't be deserialized from a string.
+fn deserialize_text_message<T: DeserializeOwned>(data: &JsValue) -> Option<T> {
+    let text = data.as_string()?;
+    T::deserialize(IntoDeserializer::<serde::de::value::Error>::into_deserializer(text)).ok()
 }
 
-pub fn set_data_channel_on_message<T: DeserializeOwned>(
+/// Passes messages to the event handler, or to callbacks replacing it.
+pub fn set_data_channel_on_message<T: DeserializeOwned + 'static>(
     data_channel: &RtcDataChannel,
-    mut on_message_callback: impl FnMut(T) + 'static,
+    network_manager: NetworkManager,
+    event_handler: EventHandler<T, ()>,
 ) {
-    let on_message_callback: Box<dyn FnMut(MessageEvent)> = Box::new(move |ev: MessageEvent| {
-        let message = ev.data().dyn_into::<Uint8Array>().ok();
-        if let Some(message) = message.and_then(|t| rmp_serde::from_slice(&t.to_vec()).ok()) {
-            debug!("message from datachannel (will call on_message)");
-            on_message_callback(message);
+    let data_channel_clone = data_channel.clone();
+    let network_emulator = network_manager.inner.borrow().network_emulator.clone();
+    let handle_data = Rc::new(move |data: JsValue| {
+        let (text_mode, reliable_messages, replaced_callbacks) = {
+            let inner = network_manager.inner.borrow();
+            (
+                inner.text_mode,
+                inner.reliable_messages.clone(),
+                inner.replaced_callbacks.clone(),
+            )
+        };
+        if text_mode {
+            // plain text isn't framed by the library, so it's passed on as it is
+            match deserialize_text_message(&data) {
+                Some(message) => (event_handler.borrow_mut())(PeerEvent::Message((), message)),
+                None => debug!("dropping binary data received on text data channel"),
+            }
+            return;
+        }
+        let Some(data) = reliable_messages.receive((), &data_channel_clone, data) else {
+            return;
+        };
+        for message_data in batch::unpack(data) {
+            if let Some(ref callbacks) = replaced_callbacks {
+                (callbacks.on_message.borrow_mut())(message_data);
+            } else if let Some(message) = deserialize_message(&message_data) {
+                debug!("message from datachannel (will call on_message)");
+                (event_handler.borrow_mut())(PeerEvent::Message((), message));
+            }
         }
     });
+    let on_message_callback: Box<dyn FnMut(MessageEvent)> =
+        Box::new(in_current_span(move |ev: MessageEvent| {
+            let handle_data = Rc::clone(&handle_data);
+            let data = ev.data();
+            network_emulator.receive(move || handle_data(data));
+        }));
     let on_message_callback = Closure::wrap(on_message_callback);
     data_channel.set_onmessage(Some(on_message_callback.as_ref().unchecked_ref()));
     on_message_callback.forget();
 }
 
 pub fn set_data_channel_on_error(data_channel: &RtcDataChannel) {
-    let on_error: Box<dyn FnMut(JsValue)> = Box::new(move |data_channel_error| {
+    let on_error: Box<dyn FnMut(JsValue)> = Box::new(in_current_span(move |data_channel_error| {
         error!("data channel error: {:?}", data_channel_error);
-    });
+    }));
     let on_error = Closure::wrap(on_error);
     data_channel.set_onerror(Some(on_error.as_ref().unchecked_ref()));
     on_error.forget();
 }
 
+/// Reports the data channel opening to the event handler, or to callbacks replacing it.
 pub fn set_data_channel_on_open(
     data_channel: &RtcDataChannel,
-    mut on_open_callback: impl FnMut() + 'static,
+    network_manager: NetworkManager,
+    lifecycle_event_handler: LifecycleEventHandler<()>,
 ) {
-    let on_open_callback: Box<dyn FnMut(JsValue)> = Box::new(move |_| {
+    let on_open_callback: Box<dyn FnMut(JsValue)> = Box::new(in_current_span(move |_| {
         debug!("data channel is now open, calling on_open!");
-        on_open_callback();
-    });
+        let replaced_callbacks = network_manager.inner.borrow().replaced_callbacks.clone();
+        if let Some(callbacks) = replaced_callbacks {
+            (callbacks.on_open.borrow_mut())();
+        } else {
+            (lifecycle_event_handler.borrow_mut())(LifecycleEvent::Opened(()));
+        }
+    }));
     let on_open_callback = Closure::wrap(on_open_callback);
     data_channel.set_onopen(Some(on_open_callback.as_ref().unchecked_ref()));
     on_open_callback.forget();
 }
 
-pub fn set_peer_connection_on_ice_connection_state_change(peer_connection: &RtcPeerConnection) {
+/// Reports the data channel closing and then closed, unless it was already replaced
+/// by the one received from the other peer.
+pub fn set_data_channel_on_close(
+    data_channel: &RtcDataChannel,
+    network_manager: NetworkManager,
+    lifecycle_event_handler: LifecycleEventHandler<()>,
+) {
+    let is_current = {
+        let data_channel = data_channel.clone();
+        move || network_manager.inner.borrow().data_channel.as_ref() == Some(&data_channel)
+    };
+
+    let on_closing: Box<dyn FnMut(JsValue)> = Box::new(in_current_span({
+        let is_current = is_current.clone();
+        let lifecycle_event_handler = Rc::clone(&lifecycle_event_handler);
+        move |_| {
+            debug!("data channel is closing");
+            if is_current() {
+                (lifecycle_event_handler.borrow_mut())(LifecycleEvent::ChannelStateChanged(
+                    (),
+                    ChannelState::Closing,
+                ));
+            }
+        }
+    }));
+    let on_closing = Closure::wrap(on_closing);
+    // web-sys has no setter for `onclosing`
+    if let Err(err) = data_channel
+        .add_event_listener_with_callback("closing", on_closing.as_ref().unchecked_ref())
+    {
+        error!("failed to listen for data channel closing: {:?}", err);
+    }
+    on_closing.forget();
+
+    let on_close: Box<dyn FnMut(JsValue)> = Box::new(in_current_span(move |_| {
+        debug!("data channel closed");
+        if is_current() {
+            (lifecycle_event_handler.borrow_mut())(LifecycleEvent::ChannelStateChanged(
+                (),
+                ChannelState::Closed,
+            ));
+            (lifecycle_event_handler.borrow_mut())(LifecycleEvent::Closed(
+                (),
+                CloseReason::ChannelClosed,
+            ));
+        }
+    }));
+    let on_close = Closure::wrap(on_close);
+    data_channel.set_onclose(Some(on_close.as_ref().unchecked_ref()));
+    on_close.forget();
+}
+
+pub fn set_peer_connection_on_ice_connection_state_change(
+    peer_connection: &RtcPeerConnection,
+    lifecycle_event_handler: LifecycleEventHandler<()>,
+) {
     let peer_connection_clone = peer_connection.clone();
-    let on_ice_connection_state_change: Box<dyn FnMut()> = Box::new(move || {
-        debug!(
-            "connection state change: {:?}",
-            peer_connection_clone.ice_connection_state()
-        );
-    });
+    let on_ice_connection_state_change: Box<dyn FnMut(JsValue)> =
+        Box::new(in_current_span(move |_| {
+            let ice_connection_state = peer_connection_clone.ice_connection_state();
+            debug!("connection state change: {:?}", ice_connection_state);
+            let notify = |event| (lifecycle_event_handler.borrow_mut())(event);
+            match ice_connection_state {
+                RtcIceConnectionState::Connected | RtcIceConnectionState::Completed => {
+                    notify(LifecycleEvent::StateChanged((), ConnectionState::Connected));
+                }
+                RtcIceConnectionState::Disconnected => {
+                    notify(LifecycleEvent::StateChanged(
+                        (),
+                        ConnectionState::Disconnected,
+                    ));
+                }
+                RtcIceConnectionState::Failed => {
+                    notify(LifecycleEvent::StateChanged((), ConnectionState::Failed));
+                    notify(LifecycleEvent::Closed((), CloseReason::ConnectionFailed));
+                }
+                _ => {}
+            }
+        }));
     let on_ice_connection_state_change = Closure::wrap(on_ice_connection_state_change);
     peer_connection.set_oniceconnectionstatechange(Some(
         on_ice_connection_state_change.as_ref().unchecked_ref(),
@@ -146,30 +315,39 @@ pub fn set_peer_connection_on_ice_connection_state_change(peer_connection: &RtcP
     on_ice_connection_state_change.forget();
 }
 
+/// Sends ICE candidates through the signaling server connection currently used by `network_manager`,
+/// which changes if signaling server redirects peers to another one, unless its candidate filter rejects them.
 pub fn set_peer_connection_on_ice_candidate(
     peer_connection: &RtcPeerConnection,
-    websocket: WebSocket,
+    network_manager: NetworkManager,
     session_id: SessionId,
 ) {
     let on_ice_candidate: Box<dyn FnMut(RtcPeerConnectionIceEvent)> =
-        Box::new(move |ev: RtcPeerConnectionIceEvent| {
-            if let Some(candidate) = ev.candidate() {
-                let signaled_candidate = IceCandidate {
-                    candidate: candidate.candidate(),
-                    sdp_mid: candidate.sdp_mid(),
-                    sdp_m_line_index: candidate.sdp_m_line_index(),
-                };
-                debug!("signaled candidate: {:#?}", signaled_candidate);
-
-                let signal_message = SignalMessage::IceCandidate(session_id, signaled_candidate);
-                let signal_message =
-                    rmp_serde::to_vec(&signal_message).expect("failed to serialize SignalMessage");
-
-                websocket
-                    .send_with_u8_array(&signal_message)
-                    .unwrap_or_else(|_| error!("failed to send one of the ICE candidates"));
+        Box::new(in_current_span(move |ev: RtcPeerConnectionIceEvent| {
+            // gathering is complete once there is no candidate, which the other peer is told
+            // with an empty one
+            let signaled_candidate =
+                ev.candidate()
+                    .map_or_else(IceCandidate::end_of_candidates, |candidate| IceCandidate {
+                        candidate: candidate.candidate(),
+                        sdp_mid: candidate.sdp_mid(),
+                        sdp_m_line_index: candidate.sdp_m_line_index(),
+                    });
+            debug!("signaled candidate: {:#?}", signaled_candidate);
+
+            let (signaling, ice_gathering) = {
+                let inner = network_manager.inner.borrow();
+                (inner.signaling.clone(), inner.ice_gathering.clone())
+            };
+            if !ice_gathering.local_candidate((), &signaled_candidate.candidate) {
+                debug!("candidate filtered out, not signaling it");
+                return;
             }
-        });
+            let signal_message = SignalMessage::IceCandidate(session_id, signaled_candidate);
+            if let Err(err) = send_signal_message(&signaling, &signal_message) {
+                error!("failed to send one of the ICE candidates: {}", err);
+            }
+        }));
     let on_ice_candidate = Closure::wrap(on_ice_candidate);
     peer_connection.set_onicecandidate(Some(on_ice_candidate.as_ref().unchecked_ref()));
     on_ice_candidate.forget();
@@ -8,7 +8,7 @@ with [`NetworkManager::send_message`] method.
 
 This example shows two peers sending `ping` and `pong` messages to each other.
 
-```
+```no_run
 use wasm_peers::{ConnectionType, SessionId};
 use wasm_peers::one_to_one::NetworkManager;
 use web_sys::console;
@@ -20,68 +20,161 @@ let session_id = SessionId::new(1);
 let mut peer1 = NetworkManager::new(
     SIGNALING_SERVER_URL,
     session_id.clone(),
-    &ConnectionType::Stun { urls: STUN_SERVER_URL.to_string() },
+    ConnectionType::Stun { urls: STUN_SERVER_URL.to_string() },
 )
 .unwrap();
 
 let peer1_clone = peer1.clone();
 let peer1_on_open = move || peer1_clone.send_message("ping!").unwrap();
 let peer1_on_message = {
-    move |message| {
+    move |message: String| {
         console::log_1(&format!("peer1 received message: {}", message).into());
     }
 };
-peer1.start(peer1_on_open, peer1_on_message);
+peer1.start(peer1_on_open, peer1_on_message).unwrap();
 
 let mut peer2 = NetworkManager::new(
     SIGNALING_SERVER_URL,
     session_id,
-    &ConnectionType::Stun { urls: STUN_SERVER_URL.to_string() },
+    ConnectionType::Stun { urls: STUN_SERVER_URL.to_string() },
 )
 .unwrap();
 let peer2_on_open = || { /* do nothing */ };
 let peer2_clone = peer2.clone();
 let peer2_on_message = {
-    move |message| {
+    move |message: String| {
         console::log_1(&format!("peer2 received message: {}", message).into());
         peer2_clone.send_message("pong!").unwrap();
     }
 };
-peer2.start(peer2_on_open, peer2_on_message);
+peer2.start(peer2_on_open, peer2_on_message).unwrap();
 ```
 */
 
 use std::cell::RefCell;
+use std::fmt::{Debug, Display, Formatter};
+use std::future::{ready, Future};
 use std::rc::Rc;
 
 use anyhow::anyhow;
-use log::debug;
+use log::{debug, error, info};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
-use wasm_peers_protocol::SessionId;
-use web_sys::{RtcDataChannel, RtcDataChannelInit, RtcPeerConnection, WebSocket};
+use wasm_bindgen::JsValue;
+use wasm_peers_protocol::one_to_one::SignalMessage;
+use wasm_peers_protocol::{JoinIntent, SessionId, SessionInfo};
+use web_sys::{RtcDataChannel, RtcDataChannelState, RtcPeerConnection, RtcSignalingState};
 
-use crate::constants::DEFAULT_MAX_RETRANSMITS;
+use crate::batch::Batcher;
+use crate::constants::{
+    DEFAULT_MAX_RETRANSMITS, DEFAULT_SIGNALING_RECONNECT_ATTEMPTS, SESSION_LIST_TIMEOUT,
+    SIGNALING_RECONNECT_BASE_DELAY,
+};
+use crate::event::{event_handler, lifecycle_event_handler, LifecycleEvent, LifecycleEventHandler};
+use crate::ice::IceGathering;
+use crate::netem::NetworkEmulator;
 use crate::one_to_one::callbacks::{
-    set_data_channel_on_error, set_data_channel_on_message, set_data_channel_on_open,
-    set_peer_connection_on_data_channel, set_peer_connection_on_ice_candidate,
-    set_peer_connection_on_ice_connection_state_change, set_websocket_on_message,
-    set_websocket_on_open,
+    set_data_channel_on_close, set_data_channel_on_error, set_data_channel_on_message,
+    set_data_channel_on_open, set_peer_connection_on_data_channel,
+    set_peer_connection_on_ice_candidate, set_peer_connection_on_ice_connection_state_change,
+    set_websocket_on_close, set_websocket_on_message, set_websocket_on_open,
 };
+use crate::reliable::ReliableMessages;
+use crate::session_list::PendingSessionLists;
+#[cfg(feature = "test-utils")]
+use crate::test_utils::{LoopbackSignaling, Topology};
+use crate::transport::{send_signal_message, SignalingConnection, WebSocketKind};
 use crate::utils::{
-    create_peer_connection, set_peer_connection_on_ice_gathering_state_change,
-    set_peer_connection_on_negotiation_needed, ConnectionType,
+    create_data_channel_init, create_peer_connection, deserialize_message,
+    negotiated_max_message_size, set_peer_connection_on_ice_gathering_state_change,
+    set_peer_connection_on_negotiation_needed, sleep, ConnectionType, Negotiation,
+};
+#[cfg(feature = "netem")]
+use crate::NetworkConditions;
+use crate::{
+    AlreadyStartedError, CandidateFilter, CandidateInfo, CandidateSummary, ChannelModeError,
+    DataChannelConfig, DiagnosticsReport, MessageTooLargeError, PeerDiagnostics, PeerEvent,
 };
 
 mod callbacks;
+#[cfg(feature = "native")]
+pub mod native;
 mod websocket_handler;
 
-#[derive(Debug, Clone)]
+/// Value passed to the callback set with [`NetworkManager::with_signaling_reconnect_callback`]
+/// once all attempts to reconnect with signaling server failed.
+pub const SIGNALING_RECONNECT_FAILED: u32 = u32::MAX;
+
+type SignalingReconnectCallback = Rc<RefCell<dyn FnMut(u32)>>;
+
+/// Callbacks set with [`NetworkManager::replace_callbacks`], taking over from the ones given to `start`.
+#[derive(Clone)]
+struct ReplacedCallbacks {
+    on_open: Rc<RefCell<dyn FnMut()>>,
+    /// Deserializes the message itself, as it may expect a different type than the original callback
+    on_message: Rc<RefCell<dyn FnMut(JsValue)>>,
+}
+
+#[derive(Clone)]
 pub struct NetworkManagerInner {
     session_id: SessionId,
-    websocket: WebSocket,
+    signaling: SignalingConnection,
+    /// Kept so the peer connection can be re-created with the same configuration
+    connection_type: ConnectionType,
+    /// Where to reconnect once connection with signaling server is lost, `None` if it can't be lost
+    signaling_server_url: Option<String>,
+    /// Kind of `WebSocket` opened when reconnecting
+    websocket_kind: WebSocketKind,
+    /// Assigned by signaling server once the other peer joins the session
+    is_host: Option<bool>,
+    /// Sent with the first join only, rejoining after reconnect with signaling server may always create the session
+    join_intent: JoinIntent,
+    signaling_reconnect_attempts: u32,
+    on_signaling_reconnect: Option<SignalingReconnectCallback>,
+    data_channel_config: DataChannelConfig,
     peer_connection: RtcPeerConnection,
+    negotiation: Rc<Negotiation>,
     pub data_channel: Option<RtcDataChannel>,
+    /// Set by the first call to one of `start` methods, which can't be repeated
+    started: bool,
+    /// Set by [`NetworkManager::start_string`], data channel carries plain text instead of serialized messages
+    text_mode: bool,
+    replaced_callbacks: Option<ReplacedCallbacks>,
+    reliable_messages: ReliableMessages<()>,
+    batcher: Batcher<()>,
+    network_emulator: NetworkEmulator,
+    ice_gathering: IceGathering<()>,
+    session_lists: PendingSessionLists,
+}
+
+impl Debug for NetworkManagerInner {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NetworkManagerInner")
+            .field("session_id", &self.session_id)
+            .field("signaling", &self.signaling)
+            .field("connection_type", &self.connection_type)
+            .field("signaling_server_url", &self.signaling_server_url)
+            .field("websocket_kind", &self.websocket_kind)
+            .field("is_host", &self.is_host)
+            .field("join_intent", &self.join_intent)
+            .field(
+                "signaling_reconnect_attempts",
+                &self.signaling_reconnect_attempts,
+            )
+            .field("data_channel_config", &self.data_channel_config)
+            .field("peer_connection", &self.peer_connection)
+            .field("negotiation", &self.negotiation)
+            .field("data_channel", &self.data_channel)
+            .field("started", &self.started)
+            .field("text_mode", &self.text_mode)
+            .field("callbacks_replaced", &self.replaced_callbacks.is_some())
+            .field("reliable_messages", &self.reliable_messages)
+            .field("batcher", &self.batcher)
+            .field("network_emulator", &self.network_emulator)
+            .field("ice_gathering", &self.ice_gathering)
+            .field("session_lists", &self.session_lists)
+            .finish_non_exhaustive()
+    }
 }
 
 /// Abstraction over `WebRTC` peer-to-peer connection.
@@ -99,11 +192,36 @@ pub struct NetworkManagerInner {
 /// to allow possibility of referring to network manger itself from the callbacks.
 ///
 /// This class is a  pointer to the underlying resource and can be cloned freely.
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct NetworkManager {
     pub inner: Rc<RefCell<NetworkManagerInner>>,
 }
 
+impl Debug for NetworkManager {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NetworkManager")
+            .field("session_id", &self.session_id())
+            .field("is_host", &self.is_host())
+            .field("signaling_url", &self.signaling_url())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Display for NetworkManager {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "one-to-one peer in {}", self.session_id())?;
+        match self.is_host() {
+            Some(true) => write!(f, " as host")?,
+            Some(false) => write!(f, " as guest")?,
+            None => {}
+        }
+        if let Some(signaling_url) = self.signaling_url() {
+            write!(f, " via {}", signaling_url)?;
+        }
+        Ok(())
+    }
+}
+
 impl NetworkManager {
     /// Creates an instance with all resources required to create a connection.
     /// Requires an  address of an signaling server instance,
@@ -114,87 +232,487 @@ impl NetworkManager {
     pub fn new(
         signaling_server_url: &str,
         session_id: SessionId,
-        connection_type: &ConnectionType,
+        connection_type: ConnectionType,
     ) -> crate::Result<Self> {
-        let peer_connection = create_peer_connection(connection_type)?;
+        Self::with_signaling(
+            SignalingConnection::open(signaling_server_url)?,
+            Some(signaling_server_url.to_owned()),
+            session_id,
+            connection_type,
+        )
+    }
 
-        let websocket = WebSocket::new(signaling_server_url).map_err(|err| {
-            anyhow!(
-                "failed to create connection with signaling server on {}: {:?}",
-                signaling_server_url,
-                err
-            )
-        })?;
-        websocket.set_binary_type(web_sys::BinaryType::Arraybuffer);
+    /// Same as [`NetworkManager::new`], but creates a new session, reporting [`PeerEvent::SignalingError`]
+    /// with [`JoinError::AlreadyExists`](crate::JoinError::AlreadyExists) if it exists already.
+    ///
+    /// # Errors
+    /// This function errs if opening a `WebSocket` connection to URL provided by `signaling_server_url` fails.
+    pub fn new_create(
+        signaling_server_url: &str,
+        session_id: SessionId,
+        connection_type: ConnectionType,
+    ) -> crate::Result<Self> {
+        Ok(
+            Self::new(signaling_server_url, session_id, connection_type)?
+                .with_join_intent(JoinIntent::Create),
+        )
+    }
+
+    /// Same as [`NetworkManager::new`], but joins an existing session, e.g. with a code typed by the user,
+    /// reporting [`PeerEvent::SignalingError`] with [`JoinError::NoSuchSession`](crate::JoinError::NoSuchSession)
+    /// if it doesn't exist.
+    ///
+    /// # Errors
+    /// This function errs if opening a `WebSocket` connection to URL provided by `signaling_server_url` fails.
+    pub fn new_join(
+        signaling_server_url: &str,
+        session_id: SessionId,
+        connection_type: ConnectionType,
+    ) -> crate::Result<Self> {
+        Ok(
+            Self::new(signaling_server_url, session_id, connection_type)?
+                .with_join_intent(JoinIntent::Join),
+        )
+    }
 
+    /// Same as [`NetworkManager::new`], but connects to signaling server with `WebSocket` of `kind`,
+    /// e.g. `WebSocketKind::Node` in Node.js. Reconnecting opens the same kind.
+    ///
+    /// # Errors
+    /// This function errs if opening a `WebSocket` connection to URL provided by `signaling_server_url` fails.
+    pub fn with_websocket(
+        signaling_server_url: &str,
+        kind: WebSocketKind,
+        session_id: SessionId,
+        connection_type: ConnectionType,
+    ) -> crate::Result<Self> {
+        Ok(Self::with_signaling(
+            SignalingConnection::open_with(signaling_server_url, kind)?,
+            Some(signaling_server_url.to_owned()),
+            session_id,
+            connection_type,
+        )?
+        .with_websocket_kind(kind))
+    }
+
+    /// Same as [`NetworkManager::new`], but signal messages are exchanged through `signaling`
+    /// instead of a signaling server.
+    ///
+    /// # Errors
+    /// This function errs if creating a peer connection fails.
+    #[cfg(feature = "test-utils")]
+    pub fn with_loopback(
+        signaling: &LoopbackSignaling,
+        session_id: SessionId,
+        connection_type: ConnectionType,
+    ) -> crate::Result<Self> {
+        Self::with_signaling(
+            SignalingConnection::Loopback(signaling.connect(Topology::OneToOne)),
+            None,
+            session_id,
+            connection_type,
+        )
+    }
+
+    pub(crate) fn with_signaling(
+        signaling: SignalingConnection,
+        signaling_server_url: Option<String>,
+        session_id: SessionId,
+        connection_type: ConnectionType,
+    ) -> crate::Result<Self> {
+        let peer_connection = create_peer_connection(&connection_type)?;
         Ok(Self {
             inner: Rc::new(RefCell::new(NetworkManagerInner {
                 session_id,
-                websocket,
+                signaling,
+                connection_type,
+                signaling_server_url,
+                websocket_kind: WebSocketKind::default(),
+                is_host: None,
+                join_intent: JoinIntent::default(),
+                signaling_reconnect_attempts: 0,
+                on_signaling_reconnect: None,
+                data_channel_config: DataChannelConfig::default(),
                 peer_connection,
+                negotiation: Rc::new(Negotiation::default()),
                 data_channel: None,
+                started: false,
+                text_mode: false,
+                replaced_callbacks: None,
+                reliable_messages: ReliableMessages::default(),
+                batcher: Batcher::default(),
+                network_emulator: NetworkEmulator::default(),
+                ice_gathering: IceGathering::default(),
+                session_lists: PendingSessionLists::default(),
             })),
         })
     }
 
+    /// Calls `callback` with the attempt number each time connection with signaling server was lost
+    /// and reconnecting to it is attempted, e.g. to show "Reconnecting... attempt 3/5".
+    /// Up to 5 attempts are made, with delay doubled before each of them.
+    /// Once all of them fail, `callback` is called with [`SIGNALING_RECONNECT_FAILED`]
+    /// and [`PeerEvent::SignalingError`] is reported.
+    #[must_use]
+    pub fn with_signaling_reconnect_callback(self, callback: impl FnMut(u32) + 'static) -> Self {
+        self.inner.borrow_mut().on_signaling_reconnect = Some(Rc::new(RefCell::new(callback)));
+        self
+    }
+
+    /// Reconnects with signaling server through `WebSocket` of `kind`.
+    #[must_use]
+    pub(crate) fn with_websocket_kind(self, kind: WebSocketKind) -> Self {
+        self.inner.borrow_mut().websocket_kind = kind;
+        self
+    }
+
+    /// Joins the session with `intent` once started, see [`NetworkManager::new_create`] and [`NetworkManager::new_join`].
+    #[must_use]
+    pub fn with_join_intent(self, intent: JoinIntent) -> Self {
+        self.inner.borrow_mut().join_intent = intent;
+        self
+    }
+
+    /// Creates data channel according to `config` once started, e.g. with a descriptive label.
+    #[must_use]
+    pub fn with_data_channel_config(self, config: DataChannelConfig) -> Self {
+        self.inner.borrow_mut().data_channel_config = config;
+        self
+    }
+
+    /// Calls `callback` with details of each local ICE candidate gathered, e.g. to debug NAT traversal.
+    #[must_use]
+    pub fn with_local_candidate_callback(
+        self,
+        mut callback: impl FnMut(CandidateInfo) + 'static,
+    ) -> Self {
+        self.inner
+            .borrow()
+            .ice_gathering
+            .set_on_local_candidate(move |(), candidate| callback(candidate));
+        self
+    }
+
+    /// Calls `callback` once all local ICE candidates are gathered,
+    /// when [`NetworkManager::gathered_candidate_summary`] is final.
+    #[must_use]
+    pub fn with_gathering_complete_callback(self, mut callback: impl FnMut() + 'static) -> Self {
+        self.inner
+            .borrow()
+            .ice_gathering
+            .set_on_gathering_complete(move |()| callback());
+        self
+    }
+
+    /// Same as [`NetworkManager::new`], but resolves only once connection with signaling server is open.
+    ///
+    /// # Errors
+    /// This function errs if opening a `WebSocket` connection to URL provided by `signaling_server_url` fails.
+    pub async fn new_async(
+        signaling_server_url: &str,
+        session_id: SessionId,
+        connection_type: ConnectionType,
+    ) -> crate::Result<Self> {
+        let network_manager = Self::new(signaling_server_url, session_id, connection_type)?;
+        let signaling = network_manager.inner.borrow().signaling.clone();
+        signaling.wait_for_open().await?;
+        Ok(network_manager)
+    }
+
     /// Second part of the setup that begins the actual connection.
     /// Requires specifying a callbacks that are guaranteed to run
     /// when the connection opens and on each message received.
-
-    pub fn start<T: DeserializeOwned>(
+    ///
+    /// # Errors
+    /// Errs with [`AlreadyStartedError`] if network manager or any of its clones was already started.
+    pub fn start<T: DeserializeOwned + 'static>(
         &mut self,
-        on_open_callback: impl FnMut() + Clone + 'static,
-        on_message_callback: impl FnMut(T) + Clone + 'static,
-    ) {
+        on_open_callback: impl FnMut() + 'static,
+        on_message_callback: impl FnMut(T) + 'static,
+    ) -> crate::Result<()> {
         self.start_with_retransmits(
             DEFAULT_MAX_RETRANSMITS,
             on_open_callback,
             on_message_callback,
-        );
+        )
     }
 
-    pub fn start_with_retransmits<T: DeserializeOwned>(
+    /// Alternative to [`NetworkManager::start`] with a data channel carrying plain text instead of
+    /// serialized messages, e.g. for interop with JavaScript tools and debugging utilities.
+    /// Strings sent with [`NetworkManager::send_message_string`] reach `on_message_callback` as they are,
+    /// binary data is dropped. The other peer has to send plain text too, as text and binary modes
+    /// can't be mixed on the same data channel, [`NetworkManager::send_message`] errs with [`ChannelModeError`].
+    ///
+    /// # Errors
+    /// Errs with [`AlreadyStartedError`] if network manager or any of its clones was already started.
+    pub fn start_string(
+        &mut self,
+        on_open_callback: impl FnMut() + 'static,
+        on_message_callback: impl FnMut(String) + 'static,
+    ) -> crate::Result<()> {
+        if self.inner.borrow().started {
+            return Err(AlreadyStartedError.into());
+        }
+        self.inner.borrow_mut().text_mode = true;
+        self.start(on_open_callback, on_message_callback)
+    }
+
+    /// Same as [`NetworkManager::start`], but data channel gives up on a message
+    /// after `max_retransmits` retransmissions.
+    ///
+    /// # Errors
+    /// Errs with [`AlreadyStartedError`] if network manager or any of its clones was already started.
+    pub fn start_with_retransmits<T: DeserializeOwned + 'static>(
         &mut self,
         max_retransmits: u16,
-        on_open_callback: impl FnMut() + Clone + 'static,
-        on_message_callback: impl FnMut(T) + Clone + 'static,
-    ) {
+        mut on_open_callback: impl FnMut() + 'static,
+        mut on_message_callback: impl FnMut(T) + 'static,
+    ) -> crate::Result<()> {
+        self.start_with_event_handler_and_retransmits(max_retransmits, move |event| match event {
+            PeerEvent::Opened(()) => on_open_callback(),
+            PeerEvent::Message((), message) => on_message_callback(message),
+            _ => {}
+        })
+    }
+
+    /// Alternative to [`NetworkManager::start`] with a single handler receiving every [`PeerEvent`],
+    /// including the connection closing and signaling errors.
+    /// There is only one peer on the other end, so events don't identify it.
+    ///
+    /// # Errors
+    /// Errs with [`AlreadyStartedError`] if network manager or any of its clones was already started.
+    pub fn start_with_event_handler<T: DeserializeOwned + 'static>(
+        &mut self,
+        handler: impl FnMut(PeerEvent<T, ()>) + 'static,
+    ) -> crate::Result<()> {
+        self.start_with_event_handler_and_retransmits(DEFAULT_MAX_RETRANSMITS, handler)
+    }
+
+    /// Combination of [`NetworkManager::start_with_event_handler`]
+    /// and [`NetworkManager::start_with_retransmits`].
+    ///
+    /// # Errors
+    /// Errs with [`AlreadyStartedError`] if network manager or any of its clones was already started.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "start", skip_all, fields(session_id = %self.session_id().inner()))
+    )]
+    pub fn start_with_event_handler_and_retransmits<T: DeserializeOwned + 'static>(
+        &mut self,
+        max_retransmits: u16,
+        handler: impl FnMut(PeerEvent<T, ()>) + 'static,
+    ) -> crate::Result<()> {
+        if std::mem::replace(&mut self.inner.borrow_mut().started, true) {
+            return Err(AlreadyStartedError.into());
+        }
+        let handler = event_handler(handler);
+        let lifecycle_handler = lifecycle_event_handler(&handler);
         let NetworkManagerInner {
-            websocket,
+            signaling,
             peer_connection,
             session_id,
+            negotiation,
+            data_channel_config,
             ..
         } = self.inner.borrow().clone();
 
-        let mut init = RtcDataChannelInit::new();
-        init.max_retransmits(max_retransmits);
-        init.ordered(false);
-
-        let data_channel = peer_connection
-            .create_data_channel_with_data_channel_dict(&session_id.to_string(), &init);
+        let init = create_data_channel_init(max_retransmits, &data_channel_config);
+        let data_channel = peer_connection.create_data_channel_with_data_channel_dict(
+            &data_channel_config.label_or_session_id(session_id),
+            &init,
+        );
         debug!(
             "data_channel created with label: {:?}",
             data_channel.label()
         );
 
-        set_data_channel_on_open(&data_channel, on_open_callback.clone());
+        set_data_channel_on_open(&data_channel, self.clone(), Rc::clone(&lifecycle_handler));
         set_data_channel_on_error(&data_channel);
-        set_data_channel_on_message(&data_channel, on_message_callback.clone());
+        set_data_channel_on_message(&data_channel, self.clone(), Rc::clone(&handler));
+        set_data_channel_on_close(&data_channel, self.clone(), Rc::clone(&lifecycle_handler));
 
         self.inner.borrow_mut().data_channel = Some(data_channel);
         set_peer_connection_on_data_channel(
             &peer_connection,
             self.clone(),
-            on_open_callback,
-            on_message_callback,
+            handler,
+            Rc::clone(&lifecycle_handler),
+        );
+
+        set_peer_connection_on_ice_candidate(&peer_connection, self.clone(), session_id);
+        set_peer_connection_on_ice_connection_state_change(
+            &peer_connection,
+            Rc::clone(&lifecycle_handler),
+        );
+        let ice_gathering = self.inner.borrow().ice_gathering.clone();
+        set_peer_connection_on_ice_gathering_state_change(&peer_connection, move || {
+            ice_gathering.gathering_complete(());
+        });
+        set_peer_connection_on_negotiation_needed(
+            &peer_connection,
+            Rc::clone(&negotiation),
+            {
+                let network_manager = self.clone();
+                move |offer| {
+                    let current_signaling = network_manager.inner.borrow().signaling.clone();
+                    send_signal_message(
+                        &current_signaling,
+                        &SignalMessage::SdpOffer(session_id, offer),
+                    )
+                }
+            },
+            {
+                let on_negotiation_needed = data_channel_config.on_negotiation_needed;
+                move || {
+                    if let Some(negotiation_needed) = on_negotiation_needed.as_ref() {
+                        negotiation_needed.call();
+                    }
+                }
+            },
+            data_channel_config.on_sdp,
+        );
+        set_websocket_on_open(&signaling, self.clone(), session_id);
+        set_websocket_on_close(&signaling, self.clone(), Rc::clone(&lifecycle_handler));
+        set_websocket_on_message(
+            &signaling,
+            self.clone(),
+            peer_connection,
+            negotiation,
+            lifecycle_handler,
+        );
+        Ok(())
+    }
+
+    /// Replaces callbacks given to [`NetworkManager::start`] on a running connection,
+    /// e.g. once the application moves from lobby to the game, which may expect a different type of messages.
+    /// Messages and opening of the data channel are passed to the new callbacks from now on,
+    /// other events, like the connection closing, still reach the handler given to `start`.
+    /// If the data channel is already open, `on_open_callback` is called right away.
+    ///
+    /// # Errors
+    /// Errs if network manager wasn't started yet, or with [`ChannelModeError`]
+    /// if it was started with [`NetworkManager::start_string`].
+    pub fn replace_callbacks<T: DeserializeOwned + 'static>(
+        &self,
+        on_open_callback: impl FnMut() + 'static,
+        mut on_message_callback: impl FnMut(T) + 'static,
+    ) -> crate::Result<()> {
+        let on_open: Rc<RefCell<dyn FnMut()>> = Rc::new(RefCell::new(on_open_callback));
+        let is_open = {
+            let mut inner = self.inner.borrow_mut();
+            if !inner.started {
+                return Err(anyhow!("network manager wasn't started yet"));
+            }
+            if inner.text_mode {
+                return Err(ChannelModeError { text_mode: true }.into());
+            }
+            inner.replaced_callbacks = Some(ReplacedCallbacks {
+                on_open: Rc::clone(&on_open),
+                on_message: Rc::new(RefCell::new(move |data| {
+                    if let Some(message) = deserialize_message(&data) {
+                        on_message_callback(message);
+                    }
+                })),
+            });
+            inner.data_channel.as_ref().map_or(false, |data_channel| {
+                data_channel.ready_state() == RtcDataChannelState::Open
+            })
+        };
+        if is_open {
+            (on_open.borrow_mut())();
+        }
+        Ok(())
+    }
+
+    /// Replaces connection with signaling server with a new one to `signaling_server_url`
+    /// and joins the same session there. Established peer connection is kept.
+    pub(crate) fn reconnect(
+        &self,
+        signaling_server_url: &str,
+        lifecycle_event_handler: LifecycleEventHandler<()>,
+    ) -> crate::Result<()> {
+        let websocket_kind = self.inner.borrow().websocket_kind;
+        let signaling = SignalingConnection::open_with(signaling_server_url, websocket_kind)?;
+
+        let NetworkManagerInner {
+            signaling: old_signaling,
+            peer_connection,
+            session_id,
+            negotiation,
+            ..
+        } = self.inner.borrow().clone();
+        old_signaling.close();
+        {
+            let mut inner = self.inner.borrow_mut();
+            inner.signaling = signaling.clone();
+            inner.signaling_server_url = Some(signaling_server_url.to_owned());
+        }
+
+        set_websocket_on_open(&signaling, self.clone(), session_id);
+        set_websocket_on_close(
+            &signaling,
+            self.clone(),
+            Rc::clone(&lifecycle_event_handler),
         );
+        set_websocket_on_message(
+            &signaling,
+            self.clone(),
+            peer_connection,
+            negotiation,
+            lifecycle_event_handler,
+        );
+        Ok(())
+    }
+
+    /// Reconnects with the same signaling server after connection with it was lost,
+    /// waiting longer before each next attempt, until [`DEFAULT_SIGNALING_RECONNECT_ATTEMPTS`] are used up.
+    pub(crate) fn signaling_lost(&self, lifecycle_event_handler: LifecycleEventHandler<()>) {
+        let (signaling_server_url, attempt, on_signaling_reconnect) = {
+            let mut inner = self.inner.borrow_mut();
+            inner.signaling_reconnect_attempts =
+                inner.signaling_reconnect_attempts.saturating_add(1);
+            (
+                inner.signaling_server_url.clone(),
+                inner.signaling_reconnect_attempts,
+                inner.on_signaling_reconnect.clone(),
+            )
+        };
+        let Some(signaling_server_url) = signaling_server_url else {
+            return;
+        };
+        if attempt > DEFAULT_SIGNALING_RECONNECT_ATTEMPTS {
+            error!("failed to reconnect with signaling server");
+            if let Some(on_signaling_reconnect) = on_signaling_reconnect {
+                (on_signaling_reconnect.borrow_mut())(SIGNALING_RECONNECT_FAILED);
+            }
+            (lifecycle_event_handler.borrow_mut())(LifecycleEvent::SignalingError(
+                "connection with signaling server lost".to_owned(),
+            ));
+            return;
+        }
 
-        set_peer_connection_on_ice_candidate(&peer_connection, websocket.clone(), session_id);
-        set_peer_connection_on_ice_connection_state_change(&peer_connection);
-        set_peer_connection_on_ice_gathering_state_change(&peer_connection);
-        set_peer_connection_on_negotiation_needed(&peer_connection);
-        set_websocket_on_open(&websocket, session_id);
-        set_websocket_on_message(&websocket, peer_connection);
+        info!(
+            "connection with signaling server lost, reconnecting ({}/{})",
+            attempt, DEFAULT_SIGNALING_RECONNECT_ATTEMPTS
+        );
+        if let Some(on_signaling_reconnect) = on_signaling_reconnect {
+            (on_signaling_reconnect.borrow_mut())(attempt);
+        }
+        let delay = SIGNALING_RECONNECT_BASE_DELAY
+            .saturating_mul(u32::saturating_pow(2, attempt.saturating_sub(1)));
+        let network_manager = self.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            if let Err(err) = sleep(delay).await {
+                error!("failed to wait before reconnecting: {}", err);
+            }
+            // failing to connect closes the new connection, which schedules the next attempt
+            if let Err(err) = network_manager
+                .reconnect(&signaling_server_url, Rc::clone(&lifecycle_event_handler))
+            {
+                error!("failed to reconnect with signaling server: {}", err);
+                network_manager.signaling_lost(lifecycle_event_handler);
+            }
+        });
     }
 
     fn datachannel(&self) -> crate::Result<RtcDataChannel> {
@@ -207,18 +725,261 @@ impl NetworkManager {
             .clone())
     }
 
+    /// Session given to [`NetworkManager::new`].
+    #[must_use]
+    pub fn session_id(&self) -> SessionId {
+        self.inner.borrow().session_id
+    }
+
+    /// Whether this peer is the one sending the offer, `None` until signaling server
+    /// reports that the other peer joined the session.
+    #[must_use]
+    pub fn is_host(&self) -> Option<bool> {
+        self.inner.borrow().is_host
+    }
+
+    /// Address of the signaling server currently used, `None` with loopback signaling.
+    #[must_use]
+    pub fn signaling_url(&self) -> Option<String> {
+        self.inner.borrow().signaling_server_url.clone()
+    }
+
+    /// Type of connection given to [`NetworkManager::new`].
+    #[must_use]
+    pub fn connection_type(&self) -> ConnectionType {
+        self.inner.borrow().connection_type.clone()
+    }
+
+    /// Signaling state of the peer connection, useful when diagnosing failed handshakes.
+    #[must_use]
+    pub fn signaling_state(&self) -> RtcSignalingState {
+        self.inner.borrow().peer_connection.signaling_state()
+    }
+
+    /// Ready state of the data channel, `None` until it's created.
+    /// Channel can close while ICE stays connected, e.g. when the other application closed just the channel.
+    #[must_use]
+    pub fn channel_ready_state(&self) -> Option<RtcDataChannelState> {
+        self.inner
+            .borrow()
+            .data_channel
+            .as_ref()
+            .map(RtcDataChannel::ready_state)
+    }
+
+    /// Largest message the other peer accepts, negotiated once the connection is established,
+    /// `None` before that or if browser doesn't expose it.
+    /// It can be used to raise [`DataChannelConfig::max_message_size`] for this connection.
+    #[must_use]
+    pub fn negotiated_max_message_size(&self) -> Option<usize> {
+        negotiated_max_message_size(&self.inner.borrow().peer_connection)
+    }
+
+    /// Number of local ICE candidates of each type gathered so far, e.g. to warn that there are no relay ones
+    /// because TURN server isn't configured right, before the connection fails.
+    #[must_use]
+    pub fn gathered_candidate_summary(&self) -> CandidateSummary {
+        self.inner.borrow().ice_gathering.summary()
+    }
+
+    /// Signals to the other peer only local ICE candidates that `filter` allows from now on,
+    /// e.g. [`CandidateFilter::no_host`] to not expose local network details.
+    /// Number of candidates it suppressed is included in [`NetworkManager::diagnostics`].
+    pub fn set_candidate_filter(&self, filter: impl Into<CandidateFilter>) {
+        self.inner
+            .borrow()
+            .ice_gathering
+            .set_filter(Some(filter.into()));
+    }
+
+    /// Underlying peer connection, e.g. to add media tracks or read its statistics.
+    /// It's a reference to the same JavaScript object, which stays the same for the lifetime of network manager.
+    ///
+    /// # Warning
+    /// Network manager relies on the handlers it set on the peer connection, and on being the only one
+    /// negotiating it. Replacing those handlers, closing the connection or changing its configuration
+    /// may break messaging in ways that the library can't report.
+    #[must_use]
+    pub fn as_raw_peer_connection(&self) -> RtcPeerConnection {
+        self.inner.borrow().peer_connection.clone()
+    }
+
+    /// Gathers state of the connection with signaling server and with the other peer,
+    /// to be attached to bug reports about peers that fail to connect.
+    ///
+    /// Returned future resolves right away, it's asynchronous so that statistics
+    /// which browsers only provide asynchronously can be included later on.
+    pub fn diagnostics(&self) -> impl Future<Output = DiagnosticsReport> {
+        let inner = self.inner.borrow();
+        ready(DiagnosticsReport::new(
+            inner.session_id,
+            &inner.signaling,
+            inner.signaling_server_url.clone(),
+            inner.is_host,
+            &inner.connection_type,
+            vec![PeerDiagnostics::new(
+                None,
+                &inner.peer_connection,
+                inner.data_channel.as_ref(),
+                inner.ice_gathering.refused_remote_candidates(&()),
+                inner.ice_gathering.suppressed_local_candidates(&()),
+            )],
+        ))
+    }
+
+    /// Asks signaling server for sessions open on its one-to-one endpoint, e.g. to show a lobby of games.
+    /// Resolves with an empty list if the server doesn't respond within 5 seconds.
+    ///
+    /// Response is only handled once network manager is started, so it has to be called after
+    /// one of `start` methods.
+    pub fn list_sessions(&self) -> impl Future<Output = Vec<SessionInfo>> {
+        let (signaling, session_lists) = {
+            let inner = self.inner.borrow();
+            (inner.signaling.clone(), inner.session_lists.clone())
+        };
+        async move {
+            session_lists
+                .request(
+                    &signaling,
+                    &SignalMessage::SessionListRequest,
+                    SESSION_LIST_TIMEOUT,
+                )
+                .await
+        }
+    }
+
     /// Send message to the other end of the connection.
     ///
     /// # Errors
     /// It might fail if the connection is not yet set up
     /// and thus should only be called after `on_open_callback` triggers.
     /// Otherwise it will result in an error:
-    /// - if sending of the message was tried before data channel was established or,
+    /// - if sending of the message was tried before data channel was established,
+    /// - with [`MessageTooLargeError`](crate::MessageTooLargeError) if serialized message is larger than
+    ///   [`DataChannelConfig::max_message_size`],
+    /// - with [`ChannelModeError`] if network manager was started with [`NetworkManager::start_string`] or,
     /// - if sending of the message failed.
     pub fn send_message<T: Serialize + ?Sized>(&self, message: &T) -> crate::Result<()> {
-        let message = rmp_serde::to_vec(message)?;
-        self.datachannel()?
-            .send_with_u8_array(&message)
-            .map_err(|err| anyhow!("failed to send string: {:?}", err))
+        let message = {
+            let inner = self.inner.borrow();
+            if inner.text_mode {
+                return Err(ChannelModeError { text_mode: true }.into());
+            }
+            inner.data_channel_config.serialize_message(message)?
+        };
+        let network_emulator = self.inner.borrow().network_emulator.clone();
+        let network_manager = self.clone();
+        network_emulator.send(move || {
+            let data_channel = network_manager.datachannel()?;
+            let inner = network_manager.inner.borrow();
+            inner
+                .batcher
+                .push((), &data_channel, &message, &inner.data_channel_config)
+        })
+    }
+
+    /// Sends `message` as plain text, without serialization, to the other end of the data channel
+    /// started with [`NetworkManager::start_string`].
+    ///
+    /// # Errors
+    /// This function errs for the same reasons as [`NetworkManager::send_message`],
+    /// with [`MessageTooLargeError`] if `message` is longer than [`DataChannelConfig::max_message_size`] in bytes,
+    /// or with [`ChannelModeError`] unless network manager was started with [`NetworkManager::start_string`].
+    pub fn send_message_string(&self, message: &str) -> crate::Result<()> {
+        {
+            let inner = self.inner.borrow();
+            if !inner.text_mode {
+                return Err(ChannelModeError { text_mode: false }.into());
+            }
+            let limit = inner.data_channel_config.max_message_size;
+            if message.len() > limit {
+                return Err(MessageTooLargeError {
+                    size: message.len(),
+                    limit,
+                }
+                .into());
+            }
+        }
+        let message = message.to_owned();
+        let network_emulator = self.inner.borrow().network_emulator.clone();
+        let network_manager = self.clone();
+        network_emulator.send(move || {
+            network_manager
+                .datachannel()?
+                .send_with_str(&message)
+                .map_err(|err| anyhow!("failed to send message: {:?}", err))
+        })
+    }
+
+    /// Delays, drops and reorders messages sent and received from now on as configured,
+    /// [`NetworkConditions::default`] turns it off.
+    #[cfg(feature = "netem")]
+    pub fn set_network_conditions(&self, conditions: NetworkConditions) {
+        self.inner
+            .borrow()
+            .network_emulator
+            .set_conditions(conditions);
+    }
+
+    /// Sends messages waiting in a batch right away, instead of on the next animation frame,
+    /// e.g. before closing the connection. Does nothing unless [`DataChannelConfig::batching`] is enabled.
+    ///
+    /// # Errors
+    /// This function errs if sending the batch failed.
+    pub fn flush(&self) -> crate::Result<()> {
+        let batcher = self.inner.borrow().batcher.clone();
+        batcher
+            .flush()
+            .into_iter()
+            .next()
+            .map_or(Ok(()), |((), err)| Err(err))
+    }
+
+    /// Sends message to the other end of the connection and resolves once it's acknowledged,
+    /// resending it when acknowledgement doesn't arrive in time, as configured with
+    /// [`DataChannelConfig::ack_timeout`] and [`DataChannelConfig::max_send_attempts`].
+    /// The other peer receives the message once, no matter how many times it arrived.
+    ///
+    /// # Errors
+    /// This function errs for the same reasons as [`NetworkManager::send_message`],
+    /// or with [`NotAcknowledgedError`](crate::NotAcknowledgedError) if no attempt was acknowledged.
+    pub fn send_message_reliably<T: Serialize + ?Sized>(
+        &self,
+        message: &T,
+    ) -> impl Future<Output = crate::Result<()>> {
+        let message = {
+            let inner = self.inner.borrow();
+            if inner.text_mode {
+                Err(ChannelModeError { text_mode: true }.into())
+            } else {
+                inner.data_channel_config.serialize_message(message)
+            }
+        };
+        let network_manager = self.clone();
+        async move {
+            let message = message?;
+            let data_channel = network_manager.datachannel()?;
+            let (reliable_messages, data_channel_config) = {
+                let inner = network_manager.inner.borrow();
+                (
+                    inner.reliable_messages.clone(),
+                    inner.data_channel_config.clone(),
+                )
+            };
+            reliable_messages
+                .send(&data_channel, &message, &data_channel_config)
+                .await
+        }
+    }
+
+    /// Closes the data channel, the peer connection and the connection with signaling server.
+    /// Network manager shouldn't be used afterwards.
+    pub fn close(&self) {
+        let inner = self.inner.borrow();
+        if let Some(ref data_channel) = inner.data_channel {
+            data_channel.close();
+        }
+        inner.peer_connection.close();
+        inner.signaling.close();
     }
 }
@@ -0,0 +1,672 @@
+/*!
+Native (non-WASM) implementation of one-to-one network manager, built on `webrtc-rs` and `tokio`.
+
+It speaks the same signaling protocol and message encoding as [`NetworkManager`](super::NetworkManager),
+so a native peer, e.g. an authoritative game server, can connect with a browser peer.
+API mirrors the WASM one, so code shared between both can switch implementations with `cfg`:
+
+```ignore
+#[cfg(target_arch = "wasm32")]
+use wasm_peers::one_to_one::NetworkManager;
+#[cfg(not(target_arch = "wasm32"))]
+use wasm_peers::one_to_one::native::NetworkManager;
+
+let mut peer = NetworkManager::new_async(SIGNALING_SERVER_URL, session_id, connection_type).await?;
+peer.start(|| println!("connected"), |message: String| println!("received: {}", message));
+```
+
+All methods must be called from within a `tokio` runtime, callbacks are called from its tasks.
+*/
+
+use std::sync::{Arc, Mutex};
+
+use anyhow::anyhow;
+use futures_util::{SinkExt, StreamExt};
+use log::{debug, error, info, warn};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::Message;
+use wasm_peers_protocol::one_to_one::SignalMessage;
+use wasm_peers_protocol::{
+    IceCandidate, RedactedSdp, SessionId, PROTOCOL_MAJOR_VERSION, PROTOCOL_MINOR_VERSION,
+};
+use webrtc::api::APIBuilder;
+use webrtc::data_channel::data_channel_init::RTCDataChannelInit;
+use webrtc::data_channel::data_channel_message::DataChannelMessage;
+use webrtc::data_channel::RTCDataChannel;
+use webrtc::ice_transport::ice_candidate::{RTCIceCandidate, RTCIceCandidateInit};
+use webrtc::ice_transport::ice_connection_state::RTCIceConnectionState;
+use webrtc::ice_transport::ice_server::RTCIceServer;
+use webrtc::peer_connection::configuration::RTCConfiguration;
+use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
+use webrtc::peer_connection::RTCPeerConnection;
+
+use crate::constants::DEFAULT_MAX_RETRANSMITS;
+use crate::utils::serialize_message;
+use crate::{
+    AlreadyStartedError, ChannelState, CloseReason, ConnectionState, ConnectionType, PeerEvent,
+    ProtocolVersionMismatchError,
+};
+
+type WebSocketStream =
+    tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+type EventHandler<T> = Arc<Mutex<dyn FnMut(PeerEvent<T, ()>) + Send>>;
+
+struct NetworkManagerInner {
+    session_id: SessionId,
+    signaling_server_url: String,
+    connection_type: ConnectionType,
+    peer_connection: Arc<RTCPeerConnection>,
+    data_channel: Mutex<Option<Arc<RTCDataChannel>>>,
+    /// Frames to be sent to signaling server, sent by task spawned in [`NetworkManager::start`]
+    signaling_tx: mpsc::UnboundedSender<Vec<u8>>,
+    /// Taken by [`NetworkManager::start`]
+    signaling: Mutex<Option<(WebSocketStream, mpsc::UnboundedReceiver<Vec<u8>>)>>,
+    /// Serialized user messages, sent in order by task spawned in [`NetworkManager::start`]
+    outgoing_tx: mpsc::UnboundedSender<Vec<u8>>,
+    outgoing_rx: Mutex<Option<mpsc::UnboundedReceiver<Vec<u8>>>>,
+}
+
+/// Native counterpart of [`one_to_one::NetworkManager`](super::NetworkManager).
+///
+/// This class is a pointer to the underlying resource and can be cloned freely.
+#[derive(Clone)]
+pub struct NetworkManager {
+    inner: Arc<NetworkManagerInner>,
+}
+
+impl std::fmt::Debug for NetworkManager {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NetworkManager")
+            .field("session_id", &self.inner.session_id)
+            .field("signaling_url", &self.inner.signaling_server_url)
+            .finish_non_exhaustive()
+    }
+}
+
+impl std::fmt::Display for NetworkManager {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "one-to-one peer in {} via {}",
+            self.inner.session_id, self.inner.signaling_server_url
+        )
+    }
+}
+
+impl NetworkManager {
+    /// Same as [`one_to_one::NetworkManager::new_async`](super::NetworkManager::new_async),
+    /// connecting to signaling server is always asynchronous for native peers.
+    ///
+    /// # Errors
+    /// This function errs if creating a peer connection or connecting to signaling server fails.
+    pub async fn new_async(
+        signaling_server_url: &str,
+        session_id: SessionId,
+        connection_type: ConnectionType,
+    ) -> crate::Result<Self> {
+        let api = APIBuilder::new().build();
+        let peer_connection = api
+            .new_peer_connection(create_configuration(&connection_type))
+            .await?;
+
+        let (websocket, _) = tokio_tungstenite::connect_async(signaling_server_url)
+            .await
+            .map_err(|err| {
+                anyhow!(
+                    "failed to create connection with signaling server on {}: {:?}",
+                    signaling_server_url,
+                    err
+                )
+            })?;
+        let (signaling_tx, signaling_rx) = mpsc::unbounded_channel();
+        let (outgoing_tx, outgoing_rx) = mpsc::unbounded_channel();
+
+        Ok(Self {
+            inner: Arc::new(NetworkManagerInner {
+                session_id,
+                signaling_server_url: signaling_server_url.to_owned(),
+                connection_type,
+                peer_connection: Arc::new(peer_connection),
+                data_channel: Mutex::new(None),
+                signaling_tx,
+                signaling: Mutex::new(Some((websocket, signaling_rx))),
+                outgoing_tx,
+                outgoing_rx: Mutex::new(Some(outgoing_rx)),
+            }),
+        })
+    }
+
+    /// Same as [`one_to_one::NetworkManager::start`](super::NetworkManager::start).
+    ///
+    /// # Errors
+    /// This function errs with [`AlreadyStartedError`] if network manager was already started.
+    pub async fn start<T: DeserializeOwned + Send + 'static>(
+        &mut self,
+        mut on_open_callback: impl FnMut() + Send + 'static,
+        mut on_message_callback: impl FnMut(T) + Send + 'static,
+    ) -> crate::Result<()> {
+        self.start_with_event_handler(move |event| match event {
+            PeerEvent::Opened(()) => on_open_callback(),
+            PeerEvent::Message((), message) => on_message_callback(message),
+            _ => {}
+        })
+        .await
+    }
+
+    /// Same as [`one_to_one::NetworkManager::start_with_event_handler`](super::NetworkManager::start_with_event_handler).
+    ///
+    /// # Errors
+    /// This function errs with [`AlreadyStartedError`] if network manager was already started.
+    pub async fn start_with_event_handler<T: DeserializeOwned + Send + 'static>(
+        &mut self,
+        handler: impl FnMut(PeerEvent<T, ()>) + Send + 'static,
+    ) -> crate::Result<()> {
+        self.start_with_event_handler_and_retransmits(DEFAULT_MAX_RETRANSMITS, handler)
+            .await
+    }
+
+    /// # Errors
+    /// This function errs with [`AlreadyStartedError`] if network manager was already started
+    /// or if creating a data channel fails.
+    pub async fn start_with_event_handler_and_retransmits<T: DeserializeOwned + Send + 'static>(
+        &mut self,
+        max_retransmits: u16,
+        handler: impl FnMut(PeerEvent<T, ()>) + Send + 'static,
+    ) -> crate::Result<()> {
+        let handler: EventHandler<T> = Arc::new(Mutex::new(handler));
+        let (websocket, signaling_rx) = take(&self.inner.signaling)?;
+        let outgoing_rx = take(&self.inner.outgoing_rx)?;
+        let peer_connection = Arc::clone(&self.inner.peer_connection);
+        let session_id = self.inner.session_id;
+
+        let init = RTCDataChannelInit {
+            ordered: Some(cfg!(feature = "json-messages")),
+            max_retransmits: Some(max_retransmits),
+            ..RTCDataChannelInit::default()
+        };
+        let data_channel = peer_connection
+            .create_data_channel(&session_id.to_string(), Some(init))
+            .await?;
+        self.set_data_channel(data_channel, &handler);
+
+        peer_connection.on_data_channel({
+            let network_manager = self.clone();
+            let handler = Arc::clone(&handler);
+            Box::new(move |received_channel| {
+                info!("received data channel");
+                network_manager.set_data_channel(received_channel, &handler);
+                Box::pin(async {})
+            })
+        });
+        peer_connection.on_ice_candidate({
+            let signaling_tx = self.inner.signaling_tx.clone();
+            Box::new(move |candidate: Option<RTCIceCandidate>| {
+                if let Err(err) = send_ice_candidate(&signaling_tx, session_id, candidate.as_ref())
+                {
+                    error!("failed to send one of the ICE candidates: {}", err);
+                }
+                Box::pin(async {})
+            })
+        });
+        peer_connection.on_ice_connection_state_change({
+            let handler = Arc::clone(&handler);
+            Box::new(move |ice_connection_state| {
+                debug!("connection state change: {}", ice_connection_state);
+                match ice_connection_state {
+                    RTCIceConnectionState::Connected | RTCIceConnectionState::Completed => {
+                        notify(
+                            &handler,
+                            PeerEvent::StateChanged((), ConnectionState::Connected),
+                        );
+                    }
+                    RTCIceConnectionState::Disconnected => notify(
+                        &handler,
+                        PeerEvent::StateChanged((), ConnectionState::Disconnected),
+                    ),
+                    RTCIceConnectionState::Failed => {
+                        notify(
+                            &handler,
+                            PeerEvent::StateChanged((), ConnectionState::Failed),
+                        );
+                        notify(
+                            &handler,
+                            PeerEvent::Closed((), CloseReason::ConnectionFailed),
+                        );
+                    }
+                    _ => {}
+                }
+                Box::pin(async {})
+            })
+        });
+
+        tokio::spawn(run_outgoing(self.clone(), outgoing_rx));
+        tokio::spawn(run_signaling(
+            self.clone(),
+            websocket,
+            signaling_rx,
+            handler,
+        ));
+        // announced first, so that signaling server speaking an incompatible version refuses the connection
+        send_signal(
+            &self.inner.signaling_tx,
+            &SignalMessage::ProtocolVersion {
+                major: PROTOCOL_MAJOR_VERSION,
+                minor: PROTOCOL_MINOR_VERSION,
+            },
+        )?;
+        send_signal(
+            &self.inner.signaling_tx,
+            &SignalMessage::SessionJoin(session_id),
+        )
+    }
+
+    /// Same as [`one_to_one::NetworkManager::session_id`](super::NetworkManager::session_id).
+    #[must_use]
+    pub fn session_id(&self) -> SessionId {
+        self.inner.session_id
+    }
+
+    /// Same as [`one_to_one::NetworkManager::signaling_url`](super::NetworkManager::signaling_url),
+    /// always set for native peers.
+    #[must_use]
+    pub fn signaling_url(&self) -> Option<String> {
+        Some(self.inner.signaling_server_url.clone())
+    }
+
+    /// Same as [`one_to_one::NetworkManager::connection_type`](super::NetworkManager::connection_type).
+    #[must_use]
+    pub fn connection_type(&self) -> ConnectionType {
+        self.inner.connection_type.clone()
+    }
+
+    /// Same as [`one_to_one::NetworkManager::send_message`](super::NetworkManager::send_message).
+    /// Messages are sent in order by a background task, so only errors known up front are reported.
+    ///
+    /// # Errors
+    /// This function errs if data channel is not yet established or serializing message fails.
+    pub fn send_message<T: Serialize + ?Sized>(&self, message: &T) -> crate::Result<()> {
+        if lock(&self.inner.data_channel)?.is_none() {
+            return Err(anyhow!("no data channel set on instance yet"));
+        }
+        self.inner
+            .outgoing_tx
+            .send(serialize_message(message)?)
+            .map_err(|_err| anyhow!("network manager was closed"))
+    }
+
+    /// Same as [`one_to_one::NetworkManager::close`](super::NetworkManager::close).
+    pub async fn close(&self) {
+        if let Err(err) = self.inner.peer_connection.close().await {
+            error!("failed to close peer connection: {}", err);
+        }
+    }
+
+    /// Sets up callbacks of `data_channel` and makes it the one used for sending messages.
+    fn set_data_channel<T: DeserializeOwned + Send + 'static>(
+        &self,
+        data_channel: Arc<RTCDataChannel>,
+        handler: &EventHandler<T>,
+    ) {
+        data_channel.on_open({
+            let handler = Arc::clone(handler);
+            Box::new(move || {
+                debug!("data channel is now open, calling on_open!");
+                notify(&handler, PeerEvent::Opened(()));
+                Box::pin(async {})
+            })
+        });
+        data_channel.on_message({
+            let handler = Arc::clone(handler);
+            Box::new(move |message| {
+                if let Some(message) = deserialize_message(&message) {
+                    notify(&handler, PeerEvent::Message((), message));
+                }
+                Box::pin(async {})
+            })
+        });
+        data_channel.on_close({
+            let network_manager = self.clone();
+            let handler = Arc::clone(handler);
+            let data_channel = Arc::downgrade(&data_channel);
+            Box::new(move || {
+                debug!("data channel closed");
+                let is_current = lock(&network_manager.inner.data_channel)
+                    .ok()
+                    .and_then(|current| current.as_ref().map(Arc::downgrade))
+                    .map_or(false, |current| current.ptr_eq(&data_channel));
+                if is_current {
+                    notify(
+                        &handler,
+                        PeerEvent::ChannelStateChanged((), ChannelState::Closed),
+                    );
+                    notify(&handler, PeerEvent::Closed((), CloseReason::ChannelClosed));
+                }
+                Box::pin(async {})
+            })
+        });
+        match lock(&self.inner.data_channel) {
+            Ok(mut current) => *current = Some(data_channel),
+            Err(err) => error!("failed to set data channel: {}", err),
+        }
+    }
+}
+
+/// Sends serialized user messages over the current data channel.
+async fn run_outgoing(
+    network_manager: NetworkManager,
+    mut outgoing_rx: mpsc::UnboundedReceiver<Vec<u8>>,
+) {
+    while let Some(message) = outgoing_rx.recv().await {
+        let data_channel = match lock(&network_manager.inner.data_channel) {
+            Ok(data_channel) => data_channel.clone(),
+            Err(err) => {
+                error!("failed to get data channel: {}", err);
+                continue;
+            }
+        };
+        let Some(data_channel) = data_channel else {
+            error!("no data channel to send message with");
+            continue;
+        };
+        if let Err(err) = send_serialized_message(&data_channel, message).await {
+            error!("failed to send message: {}", err);
+        }
+    }
+}
+
+/// Sends queued signaling frames and handles messages from signaling server until it disconnects.
+async fn run_signaling<T: DeserializeOwned + Send + 'static>(
+    network_manager: NetworkManager,
+    mut websocket: WebSocketStream,
+    mut signaling_rx: mpsc::UnboundedReceiver<Vec<u8>>,
+    handler: EventHandler<T>,
+) {
+    loop {
+        tokio::select! {
+            frame = signaling_rx.recv() => {
+                let Some(frame) = frame else { break };
+                if let Err(err) = websocket.send(Message::Binary(frame)).await {
+                    error!("failed to send message across the websocket: {}", err);
+                }
+            }
+            message = websocket.next() => {
+                let message = match message {
+                    Some(Ok(Message::Binary(message))) => message,
+                    Some(Ok(_)) => continue,
+                    Some(Err(err)) => {
+                        error!("signaling server connection error: {}", err);
+                        break;
+                    }
+                    None => break,
+                };
+                let Ok(message) = rmp_serde::from_slice::<SignalMessage>(&message) else {
+                    error!("failed to deserialize message");
+                    continue;
+                };
+                if let SignalMessage::Redirect(new_server_url) = message {
+                    info!("signaling server redirected peer to {}", new_server_url);
+                    match tokio_tungstenite::connect_async(new_server_url.as_str()).await {
+                        Ok((new_websocket, _)) => {
+                            websocket = new_websocket;
+                            let version = SignalMessage::ProtocolVersion {
+                                major: PROTOCOL_MAJOR_VERSION,
+                                minor: PROTOCOL_MINOR_VERSION,
+                            };
+                            let session_join = SignalMessage::SessionJoin(network_manager.inner.session_id);
+                            if let Err(err) = send_signal(&network_manager.inner.signaling_tx, &version)
+                                .and_then(|()| send_signal(&network_manager.inner.signaling_tx, &session_join))
+                            {
+                                error!("failed to join session after redirect: {}", err);
+                            }
+                        }
+                        Err(err) => {
+                            notify(&handler, PeerEvent::SignalingError(err.to_string()));
+                            break;
+                        }
+                    }
+                    continue;
+                }
+                if let Err(err) = handle_websocket_message(&network_manager, message, &handler).await {
+                    error!("error handling websocket message: {}", err);
+                    notify(&handler, PeerEvent::SignalingError(err.to_string()));
+                }
+            }
+        }
+    }
+    debug!("connection with signaling server closed");
+}
+
+/// Same as [`one_to_one::websocket_handler::handle_websocket_message`](super::websocket_handler::handle_websocket_message),
+/// minus the redirect handled by [`run_signaling`].
+async fn handle_websocket_message<T>(
+    network_manager: &NetworkManager,
+    message: SignalMessage,
+    handler: &EventHandler<T>,
+) -> crate::Result<()> {
+    let peer_connection = &network_manager.inner.peer_connection;
+    let signaling_tx = &network_manager.inner.signaling_tx;
+    match message {
+        SignalMessage::SessionReady(session_id, is_host) => {
+            info!("peer received info that session is ready {:?}", session_id);
+            if is_host {
+                let offer = peer_connection.create_offer(None).await?;
+                peer_connection.set_local_description(offer.clone()).await?;
+                send_signal(
+                    signaling_tx,
+                    &SignalMessage::SdpOffer(session_id, offer.sdp),
+                )?;
+                debug!("(is_host: {}) sent an offer successfully", is_host);
+            }
+        }
+        SignalMessage::SdpOffer(session_id, offer) => {
+            peer_connection
+                .set_remote_description(RTCSessionDescription::offer(offer)?)
+                .await?;
+            let answer = peer_connection.create_answer(None).await?;
+            peer_connection
+                .set_local_description(answer.clone())
+                .await?;
+            debug!(
+                "received an offer and created an answer: {}",
+                RedactedSdp(&answer.sdp)
+            );
+            send_signal(
+                signaling_tx,
+                &SignalMessage::SdpAnswer(session_id, answer.sdp),
+            )?;
+        }
+        SignalMessage::SdpAnswer(session_id, answer) => {
+            peer_connection
+                .set_remote_description(RTCSessionDescription::answer(answer)?)
+                .await?;
+            debug!(
+                "received answer from peer and set remote description: {:?}",
+                session_id
+            );
+        }
+        SignalMessage::IceCandidate(_session_id, ice_candidate) => {
+            debug!("peer received ice candidate: {:?}", &ice_candidate);
+            add_ice_candidate(peer_connection, ice_candidate).await;
+        }
+        SignalMessage::Error(session_id, error) => {
+            error!(
+                "signaling server returned error: session id: {:?}, error:{}",
+                session_id, error
+            );
+            notify(handler, PeerEvent::SignalingError(error));
+        }
+        SignalMessage::SessionJoinError(session_id, error) => {
+            error!(
+                "signaling server refused joining session: session id: {:?}, error: {}",
+                session_id, error
+            );
+            notify(handler, PeerEvent::SignalingError(error.to_string()));
+        }
+        SignalMessage::SessionJoin(_) | SignalMessage::SessionJoinWithIntent(..) => {
+            error!("error, SessionJoin should only be sent by peers to signaling server");
+        }
+        SignalMessage::SessionListRequest => {
+            error!("error, SessionListRequest should only be sent by peers to signaling server");
+        }
+        SignalMessage::SessionListResponse(_sessions) => {
+            debug!("ignoring session list, native peers don't request it");
+        }
+        SignalMessage::Redirect(_new_server_url) => {
+            error!("error, Redirect should be handled together with signaling server connection");
+        }
+        SignalMessage::ProtocolVersion { major, minor } => {
+            info!("signaling server speaks protocol v{}.{}", major, minor);
+        }
+        SignalMessage::ProtocolVersionMismatch {
+            server_major,
+            server_minor,
+        } => {
+            let error = ProtocolVersionMismatchError {
+                server_major,
+                server_minor,
+            };
+            notify(handler, PeerEvent::SignalingError(error.to_string()));
+        }
+    }
+    Ok(())
+}
+
+/// Adds candidate received from the other peer, one that can't be added is skipped,
+/// as the remaining ones may still connect the peers.
+async fn add_ice_candidate(peer_connection: &RTCPeerConnection, ice_candidate: IceCandidate) {
+    let candidate = ice_candidate.candidate.clone();
+    let result = peer_connection
+        .add_ice_candidate(RTCIceCandidateInit {
+            candidate: ice_candidate.candidate,
+            sdp_mid: ice_candidate.sdp_mid,
+            sdp_mline_index: ice_candidate.sdp_m_line_index,
+            username_fragment: None,
+        })
+        .await;
+    if let Err(err) = result {
+        warn!(
+            "failed to add ICE candidate {:?}, skipping it: {}",
+            candidate, err
+        );
+    }
+}
+
+fn create_configuration(connection_type: &ConnectionType) -> RTCConfiguration {
+    let ice_servers = match *connection_type {
+        ConnectionType::Local => vec![],
+        ConnectionType::Stun { ref urls } => vec![RTCIceServer {
+            urls: vec![urls.clone()],
+            ..RTCIceServer::default()
+        }],
+        ConnectionType::StunAndTurn {
+            ref stun_urls,
+            ref turn_urls,
+            ref username,
+            ref credential,
+        } => vec![
+            RTCIceServer {
+                urls: vec![stun_urls.clone()],
+                ..RTCIceServer::default()
+            },
+            RTCIceServer {
+                urls: vec![turn_urls.clone()],
+                username: username.clone(),
+                credential: credential.clone(),
+                ..RTCIceServer::default()
+            },
+        ],
+    };
+    RTCConfiguration {
+        ice_servers,
+        ..RTCConfiguration::default()
+    }
+}
+
+fn send_signal(
+    signaling_tx: &mpsc::UnboundedSender<Vec<u8>>,
+    signal_message: &SignalMessage,
+) -> crate::Result<()> {
+    signaling_tx
+        .send(rmp_serde::to_vec(signal_message)?)
+        .map_err(|_err| anyhow!("connection with signaling server was closed"))
+}
+
+fn send_ice_candidate(
+    signaling_tx: &mpsc::UnboundedSender<Vec<u8>>,
+    session_id: SessionId,
+    candidate: Option<&RTCIceCandidate>,
+) -> crate::Result<()> {
+    let signaled_candidate = match candidate {
+        Some(candidate) => {
+            let candidate = candidate.to_json()?;
+            IceCandidate {
+                candidate: candidate.candidate,
+                sdp_mid: candidate.sdp_mid,
+                sdp_m_line_index: candidate.sdp_mline_index,
+            }
+        }
+        // gathering is complete, the other peer is told with an empty candidate
+        None => IceCandidate::end_of_candidates(),
+    };
+    debug!("signaled candidate: {:#?}", signaled_candidate);
+    send_signal(
+        signaling_tx,
+        &SignalMessage::IceCandidate(session_id, signaled_candidate),
+    )
+}
+
+/// Sends already serialized message as binary data.
+#[cfg(not(feature = "json-messages"))]
+async fn send_serialized_message(
+    data_channel: &RTCDataChannel,
+    message: Vec<u8>,
+) -> crate::Result<()> {
+    data_channel.send(&bytes::Bytes::from(message)).await?;
+    Ok(())
+}
+
+/// Sends already serialized message as text data.
+#[cfg(feature = "json-messages")]
+async fn send_serialized_message(
+    data_channel: &RTCDataChannel,
+    message: Vec<u8>,
+) -> crate::Result<()> {
+    data_channel.send_text(String::from_utf8(message)?).await?;
+    Ok(())
+}
+
+/// Deserializes data received on a data channel, `None` if it's not a valid `MessagePack` message.
+#[cfg(not(feature = "json-messages"))]
+fn deserialize_message<T: DeserializeOwned>(message: &DataChannelMessage) -> Option<T> {
+    rmp_serde::from_slice(&message.data).ok()
+}
+
+/// Deserializes data received on a data channel, `None` if it's not a valid JSON message.
+#[cfg(feature = "json-messages")]
+fn deserialize_message<T: DeserializeOwned>(message: &DataChannelMessage) -> Option<T> {
+    if message.is_string {
+        serde_json::from_slice(&message.data).ok()
+    } else {
+        None
+    }
+}
+
+fn notify<T>(handler: &EventHandler<T>, event: PeerEvent<T, ()>) {
+    match handler.lock() {
+        Ok(mut handler) => handler(event),
+        Err(err) => error!("event handler panicked before: {}", err),
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> crate::Result<std::sync::MutexGuard<'_, T>> {
+    mutex
+        .lock()
+        .map_err(|err| anyhow!("network manager state was poisoned: {}", err))
+}
+
+fn take<T>(value: &Mutex<Option<T>>) -> crate::Result<T> {
+    Ok(lock(value)?.take().ok_or(AlreadyStartedError)?)
+}
@@ -1,120 +1,337 @@
+use std::rc::Rc;
+
 use ::log::{debug, error, info};
 use anyhow::anyhow;
 use wasm_bindgen_futures::JsFuture;
 use wasm_peers_protocol::one_to_one::SignalMessage;
-use web_sys::{
-    RtcIceCandidate, RtcIceCandidateInit, RtcPeerConnection, RtcSdpType, RtcSessionDescriptionInit,
-    WebSocket,
-};
+use wasm_peers_protocol::{IceCandidate, RedactedSdp};
+use web_sys::{RtcPeerConnection, RtcSdpType, RtcSessionDescriptionInit};
 
-use crate::utils::{create_sdp_answer, create_sdp_offer};
+use crate::event::{LifecycleEvent, LifecycleEventHandler};
+use crate::ice::add_remote_candidate;
+use crate::one_to_one::NetworkManager;
+use crate::transport::{send_signal_message, SignalingTransport};
+use crate::utils::{create_sdp_answer, create_sdp_offer, Negotiation};
+use crate::ProtocolVersionMismatchError;
 
 /// Basically a state  spread across host, client and signaling server,
 /// handling each step in session and then `WebRTC` setup.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(skip_all, fields(session_id = %network_manager.session_id().inner()))
+)]
 pub async fn handle_websocket_message(
     message: SignalMessage,
+    network_manager: NetworkManager,
     peer_connection: RtcPeerConnection,
-    websocket: WebSocket,
+    transport: impl SignalingTransport,
+    negotiation: Rc<Negotiation>,
+    lifecycle_event_handler: LifecycleEventHandler<()>,
 ) -> crate::Result<()> {
     match message {
-        SignalMessage::SessionJoin(_session_id) => {
+        SignalMessage::SessionJoin(..) | SignalMessage::SessionJoinWithIntent(..) => {
             error!("error, SessionStartOrJoin should only be sent by peers to signaling server");
         }
+        SignalMessage::SessionListRequest => {
+            error!("error, SessionListRequest should only be sent by peers to signaling server");
+        }
         SignalMessage::SessionReady(session_id, is_host) => {
             info!("peer received info that session is ready {:?}", session_id);
+            network_manager.inner.borrow_mut().is_host = Some(is_host);
+            negotiation.set_polite(!is_host);
             if is_host {
-                let offer = create_sdp_offer(&peer_connection).await?;
-                let signal_message = SignalMessage::SdpOffer(session_id, offer);
-                let signal_message = rmp_serde::to_vec(&signal_message)?;
-                websocket
-                    .send_with_u8_array(&signal_message)
-                    .map_err(|err| {
-                        anyhow!("failed to send message across the websocket: {:?}", err)
-                    })?;
+                let on_sdp = network_manager
+                    .inner
+                    .borrow()
+                    .data_channel_config
+                    .on_sdp
+                    .clone();
+                let offer = create_sdp_offer(&peer_connection, on_sdp.as_ref()).await?;
+                send_signal_message(&transport, &SignalMessage::SdpOffer(session_id, offer))?;
                 debug!("(is_host: {}) sent an offer successfully", is_host);
             }
         }
         SignalMessage::SdpOffer(session_id, offer) => {
-            let answer = create_sdp_answer(&peer_connection, offer).await?;
-            debug!("received an offer and created an answer: {}", answer);
-            let signal_message = SignalMessage::SdpAnswer(session_id, answer);
-            let signal_message = rmp_serde::to_vec(&signal_message)?;
-            if let Err(err) = websocket.send_with_u8_array(&signal_message) {
-                error!("failed to send signal message: {:?}", err);
+            if negotiation.ignore_offer(&peer_connection) {
+                debug!("ignoring offer colliding with our own: {:?}", session_id);
+                return Ok(());
             }
+            let on_sdp = network_manager
+                .inner
+                .borrow()
+                .data_channel_config
+                .on_sdp
+                .clone();
+            let answer = create_sdp_answer(&peer_connection, offer, on_sdp.as_ref()).await?;
+            debug!(
+                "received an offer and created an answer: {}",
+                RedactedSdp(&answer)
+            );
+            send_signal_message(&transport, &SignalMessage::SdpAnswer(session_id, answer))?;
         }
         SignalMessage::SdpAnswer(session_id, answer) => {
             let mut remote_session_description = RtcSessionDescriptionInit::new(RtcSdpType::Answer);
             remote_session_description.sdp(&answer);
             JsFuture::from(peer_connection.set_remote_description(&remote_session_description))
                 .await
-                .expect("failed to set remote descripiton");
+                .map_err(|err| anyhow!("failed to set remote description: {:?}", err))?;
             debug!(
                 "received answer from peer and set remote description: {}, {:?}",
                 answer, session_id
             );
         }
-        SignalMessage::IceCandidate(_session_id, ice_candidate) => {
-            debug!("peer received ice candidate: {:?}", &ice_candidate);
-
-            let mut rtc_candidate = RtcIceCandidateInit::new("");
-            rtc_candidate.candidate(&ice_candidate.candidate);
-            rtc_candidate.sdp_m_line_index(ice_candidate.sdp_m_line_index);
-            rtc_candidate.sdp_mid(ice_candidate.sdp_mid.as_deref());
-
-            let rtc_candidate =
-                RtcIceCandidate::new(&rtc_candidate).expect("failed to create new RtcIceCandidate");
-            JsFuture::from(
-                peer_connection.add_ice_candidate_with_opt_rtc_ice_candidate(Some(&rtc_candidate)),
-            )
-            .await
-            .expect("failed to add ICE candidate");
-            debug!("added ice candidate {:?}", ice_candidate);
+        SignalMessage::IceCandidate(_session_id, candidate) => {
+            let handler = lifecycle_event_handler;
+            ice_candidate(&network_manager, &peer_connection, &candidate, handler).await;
         }
         SignalMessage::Error(session_id, error) => {
             error!(
                 "signaling server returned error: session id: {:?}, error:{}",
                 session_id, error
             );
+            (lifecycle_event_handler.borrow_mut())(LifecycleEvent::SignalingError(error));
+        }
+        SignalMessage::SessionJoinError(session_id, error) => {
+            error!(
+                "signaling server refused joining session: session id: {:?}, error: {}",
+                session_id, error
+            );
+            (lifecycle_event_handler.borrow_mut())(LifecycleEvent::SignalingError(
+                error.to_string(),
+            ));
+        }
+        SignalMessage::Redirect(new_server_url) => {
+            info!("signaling server redirected peer to {}", new_server_url);
+            network_manager.reconnect(&new_server_url, lifecycle_event_handler)?;
+        }
+        SignalMessage::SessionListResponse(sessions) => {
+            let session_lists = network_manager.inner.borrow().session_lists.clone();
+            session_lists.received(sessions);
+        }
+        SignalMessage::ProtocolVersion { major, minor } => {
+            info!("signaling server speaks protocol v{}.{}", major, minor);
+        }
+        SignalMessage::ProtocolVersionMismatch {
+            server_major,
+            server_minor,
+        } => {
+            return Err(ProtocolVersionMismatchError {
+                server_major,
+                server_minor,
+            }
+            .into());
         }
     }
 
     Ok(())
 }
 
-// // TODO(tkarwowski): uncomment once mocks work
-// #[cfg(test)]
-// mod test {
-//     use super::*;
-//     use mockall::mock;
-//     use wasm_bindgen_test::wasm_bindgen_test_configure;
-//
-//     use wasm_peers_protocol::SessionId;
-//
-//     wasm_bindgen_test_configure!(run_in_browser);
-//
-//     mock! {
-//         WebSocket {}
-//     }
-//
-//     #[wasm_bindgen_test]
-//     async fn test_handle_session_ready_signal_is_successful() {
-//         let message =
-//             SignalMessage::SessionReady(SessionId::new("dummy-session-id".to_string()), true);
-//         let peer_connection = RtcPeerConnection::new().unwrap();
-//
-//         // TODO(tkarwowski): this should be mocked, but how do you pass a mock to a function expecting different type?
-//         //  I could introduce a trait, implement it for web_sys::WebSocket and MockWebSocket as well,
-//         //  but that's a lot of work...
-//         //  This is a integration test for now.
-//         let websocket = WebSocket::new("ws://0.0.0.0:9001/ws")
-//             .expect("local signaling server instance was not found");
-//         websocket.set_binary_type(web_sys::BinaryType::Arraybuffer);
-//
-//         // FIXME(tkarwowski): this fails because peer_connection state gets modified in other tests
-//         handle_websocket_message(message, peer_connection.clone(), websocket)
-//             .await
-//             .unwrap();
-//         assert!(peer_connection.local_description().is_some());
-//     }
-// }
+/// Adds candidate received from the other peer, candidates the browser refuses are skipped,
+/// see [`add_remote_candidate`].
+async fn ice_candidate(
+    network_manager: &NetworkManager,
+    peer_connection: &RtcPeerConnection,
+    ice_candidate: &IceCandidate,
+    lifecycle_event_handler: LifecycleEventHandler<()>,
+) {
+    let ice_gathering = network_manager.inner.borrow().ice_gathering.clone();
+    let on_all_refused = move || {
+        (lifecycle_event_handler.borrow_mut())(LifecycleEvent::SignalingError(
+            "browser refused every ICE candidate of the other peer".to_owned(),
+        ));
+    };
+    add_remote_candidate(
+        peer_connection,
+        ice_candidate,
+        (),
+        &ice_gathering,
+        on_all_refused,
+    )
+    .await;
+}
+
+#[cfg(test)]
+mod test {
+    use wasm_bindgen_test::{wasm_bindgen_test, wasm_bindgen_test_configure};
+    use wasm_peers_protocol::SessionId;
+
+    use super::*;
+    use crate::event::{event_handler, lifecycle_event_handler};
+    use crate::transport::MockTransport;
+    use crate::{ConnectionType, PeerEvent};
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    const SIGNALING_SERVER_URL: &str = "ws://0.0.0.0:9001/one-to-one";
+
+    async fn handle(
+        network_manager: &NetworkManager,
+        message: SignalMessage,
+        transport: &MockTransport,
+    ) -> crate::Result<()> {
+        let (peer_connection, negotiation) = {
+            let inner = network_manager.inner.borrow();
+            (inner.peer_connection.clone(), Rc::clone(&inner.negotiation))
+        };
+        handle_websocket_message(
+            message,
+            network_manager.clone(),
+            peer_connection,
+            transport.clone(),
+            negotiation,
+            lifecycle_event_handler(&event_handler(|_: PeerEvent<(), ()>| {})),
+        )
+        .await
+    }
+
+    fn network_manager(session_id: SessionId) -> NetworkManager {
+        NetworkManager::new(SIGNALING_SERVER_URL, session_id, ConnectionType::Local)
+            .expect("failed to create network manager")
+    }
+
+    async fn remote_offer() -> String {
+        let remote_peer_connection =
+            RtcPeerConnection::new().expect("failed to create peer connection");
+        let _remote_data_channel = remote_peer_connection.create_data_channel("remote");
+        create_sdp_offer(&remote_peer_connection, None)
+            .await
+            .expect("failed to create SDP offer")
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_session_ready_as_host_sends_offer() {
+        let session_id = SessionId::new(1);
+        let network_manager = network_manager(session_id);
+        let transport = MockTransport::default();
+
+        handle(
+            &network_manager,
+            SignalMessage::SessionReady(session_id, true),
+            &transport,
+        )
+        .await
+        .expect("failed to handle SessionReady");
+
+        assert_eq!(network_manager.is_host(), Some(true));
+        let sent = transport.sent_messages::<SignalMessage>();
+        assert!(matches!(
+            sent.as_slice(),
+            [SignalMessage::SdpOffer(id, _)] if *id == session_id
+        ));
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_session_ready_as_guest_sends_nothing() {
+        let session_id = SessionId::new(2);
+        let network_manager = network_manager(session_id);
+        let transport = MockTransport::default();
+
+        handle(
+            &network_manager,
+            SignalMessage::SessionReady(session_id, false),
+            &transport,
+        )
+        .await
+        .expect("failed to handle SessionReady");
+
+        assert_eq!(network_manager.is_host(), Some(false));
+        assert!(transport.sent_messages::<SignalMessage>().is_empty());
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_sdp_offer_sends_answer() {
+        let session_id = SessionId::new(3);
+        let network_manager = network_manager(session_id);
+        let transport = MockTransport::default();
+
+        handle(
+            &network_manager,
+            SignalMessage::SdpOffer(session_id, remote_offer().await),
+            &transport,
+        )
+        .await
+        .expect("failed to handle SdpOffer");
+
+        let sent = transport.sent_messages::<SignalMessage>();
+        assert!(matches!(
+            sent.as_slice(),
+            [SignalMessage::SdpAnswer(id, _)] if *id == session_id
+        ));
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_ice_candidate_is_added_after_offer() {
+        let session_id = SessionId::new(4);
+        let network_manager = network_manager(session_id);
+        let transport = MockTransport::default();
+        handle(
+            &network_manager,
+            SignalMessage::SdpOffer(session_id, remote_offer().await),
+            &transport,
+        )
+        .await
+        .expect("failed to handle SdpOffer");
+
+        let ice_candidate = IceCandidate {
+            candidate: "candidate:1 1 udp 2122260223 192.168.1.2 54321 typ host".to_owned(),
+            sdp_mid: Some("0".to_owned()),
+            sdp_m_line_index: Some(0),
+        };
+        handle(
+            &network_manager,
+            SignalMessage::IceCandidate(session_id, ice_candidate),
+            &transport,
+        )
+        .await
+        .expect("failed to handle IceCandidate");
+
+        // only the answer was sent
+        assert_eq!(transport.sent_messages::<SignalMessage>().len(), 1);
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_malformed_ice_candidate_is_skipped() {
+        let session_id = SessionId::new(5);
+        let network_manager = network_manager(session_id);
+        let transport = MockTransport::default();
+        handle(
+            &network_manager,
+            SignalMessage::SdpOffer(session_id, remote_offer().await),
+            &transport,
+        )
+        .await
+        .expect("failed to handle SdpOffer");
+
+        let malformed = IceCandidate {
+            candidate: "candidate:malformed".to_owned(),
+            sdp_mid: Some("0".to_owned()),
+            sdp_m_line_index: Some(0),
+        };
+        handle(
+            &network_manager,
+            SignalMessage::IceCandidate(session_id, malformed),
+            &transport,
+        )
+        .await
+        .expect("malformed ICE candidate should be skipped");
+        let ice_candidate = IceCandidate {
+            candidate: "candidate:1 1 udp 2122260223 192.168.1.2 54321 typ host".to_owned(),
+            sdp_mid: Some("0".to_owned()),
+            sdp_m_line_index: Some(0),
+        };
+        handle(
+            &network_manager,
+            SignalMessage::IceCandidate(session_id, ice_candidate),
+            &transport,
+        )
+        .await
+        .expect("failed to handle IceCandidate");
+
+        let report = network_manager.diagnostics().await;
+        let refused = report
+            .peers
+            .first()
+            .map(|peer| peer.refused_remote_candidates);
+        assert_eq!(refused, Some(1));
+    }
+}
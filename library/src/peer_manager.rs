@@ -0,0 +1,140 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+#[cfg(any(feature = "one-to-many", feature = "many-to-many"))]
+use wasm_peers_protocol::UserId;
+
+#[cfg(feature = "many-to-many")]
+use crate::many_to_many;
+#[cfg(feature = "one-to-many")]
+use crate::one_to_many::{MiniClient, MiniServer};
+#[cfg(feature = "one-to-one")]
+use crate::one_to_one;
+use crate::PeerEvent;
+
+/// Network manager of any topology, that can be owned by a hook from `hooks` module
+/// or wrapped by [`EnvelopeNetworkManager`](crate::envelope::EnvelopeNetworkManager).
+pub trait PeerManager: Clone + 'static {
+    /// Identifies peers in [`PeerEvent`]s
+    type Id: Clone + PartialEq + 'static;
+
+    /// Same as `start_with_event_handler` of the underlying network manager.
+    ///
+    /// # Errors
+    /// Errs with [`AlreadyStartedError`](crate::AlreadyStartedError) if it was already started.
+    fn start_handling<T: DeserializeOwned + 'static>(
+        &mut self,
+        handler: impl FnMut(PeerEvent<T, Self::Id>) + 'static,
+    ) -> crate::Result<()>;
+
+    /// Sends message to a single peer.
+    ///
+    /// # Errors
+    /// Same as `send_message` of the underlying network manager.
+    fn send_to<T: Serialize + ?Sized>(&self, id: Self::Id, message: &T) -> crate::Result<()>;
+
+    /// Sends message to all connected peers.
+    ///
+    /// # Errors
+    /// Same as `send_message_to_all` of the underlying network manager.
+    fn send_to_all<T: Serialize + ?Sized>(&self, message: &T) -> crate::Result<()>;
+
+    /// Closes all connections.
+    fn disconnect(&self);
+}
+
+#[cfg(feature = "one-to-one")]
+impl PeerManager for one_to_one::NetworkManager {
+    type Id = ();
+
+    fn start_handling<T: DeserializeOwned + 'static>(
+        &mut self,
+        handler: impl FnMut(PeerEvent<T, ()>) + 'static,
+    ) -> crate::Result<()> {
+        self.start_with_event_handler(handler)
+    }
+
+    fn send_to<T: Serialize + ?Sized>(&self, (): (), message: &T) -> crate::Result<()> {
+        self.send_message(message)
+    }
+
+    fn send_to_all<T: Serialize + ?Sized>(&self, message: &T) -> crate::Result<()> {
+        self.send_message(message)
+    }
+
+    fn disconnect(&self) {
+        self.close();
+    }
+}
+
+#[cfg(feature = "one-to-many")]
+impl PeerManager for MiniServer {
+    type Id = UserId;
+
+    fn start_handling<T: DeserializeOwned + 'static>(
+        &mut self,
+        handler: impl FnMut(PeerEvent<T>) + 'static,
+    ) -> crate::Result<()> {
+        self.start_with_event_handler(handler)
+    }
+
+    fn send_to<T: Serialize + ?Sized>(&self, id: UserId, message: &T) -> crate::Result<()> {
+        self.send_message(id, message)
+    }
+
+    fn send_to_all<T: Serialize + ?Sized>(&self, message: &T) -> crate::Result<()> {
+        self.send_message_to_all(message)
+    }
+
+    fn disconnect(&self) {
+        self.close();
+    }
+}
+
+#[cfg(feature = "one-to-many")]
+impl PeerManager for MiniClient {
+    type Id = UserId;
+
+    fn start_handling<T: DeserializeOwned + 'static>(
+        &mut self,
+        handler: impl FnMut(PeerEvent<T>) + 'static,
+    ) -> crate::Result<()> {
+        self.start_with_event_handler(handler)
+    }
+
+    /// Client is only connected to the host, so message is sent to it regardless of `id`.
+    fn send_to<T: Serialize + ?Sized>(&self, _id: UserId, message: &T) -> crate::Result<()> {
+        self.send_message_to_host(message)
+    }
+
+    fn send_to_all<T: Serialize + ?Sized>(&self, message: &T) -> crate::Result<()> {
+        self.send_message_to_host(message)
+    }
+
+    fn disconnect(&self) {
+        self.close();
+    }
+}
+
+#[cfg(feature = "many-to-many")]
+impl PeerManager for many_to_many::NetworkManager {
+    type Id = UserId;
+
+    fn start_handling<T: DeserializeOwned + 'static>(
+        &mut self,
+        handler: impl FnMut(PeerEvent<T>) + 'static,
+    ) -> crate::Result<()> {
+        self.start_with_event_handler(handler)
+    }
+
+    fn send_to<T: Serialize + ?Sized>(&self, id: UserId, message: &T) -> crate::Result<()> {
+        self.send_message(id, message)
+    }
+
+    fn send_to_all<T: Serialize + ?Sized>(&self, message: &T) -> crate::Result<()> {
+        self.send_message_to_all(message)
+    }
+
+    fn disconnect(&self) {
+        self.close();
+    }
+}
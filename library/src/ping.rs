@@ -0,0 +1,171 @@
+/*!
+Measurement of round trip time to peers, with ping and pong frames sent over data channels.
+
+Frames are sent as text starting with a NUL character, so that they can't be mistaken for user messages:
+those are either binary `MessagePack` or JSON text, which never starts with it.
+Received pings are answered automatically, so measuring works with any peer using this library.
+*/
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use js_sys::{Function, Promise};
+use log::error;
+use wasm_bindgen::JsValue;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::RtcDataChannel;
+
+const PING_PREFIX: &str = "\0ping:";
+const PONG_PREFIX: &str = "\0pong:";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ControlFrame {
+    Ping(u32),
+    Pong(u32),
+}
+
+impl ControlFrame {
+    fn parse(text: &str) -> Option<Self> {
+        if let Some(id) = text.strip_prefix(PING_PREFIX) {
+            id.parse().ok().map(Self::Ping)
+        } else if let Some(id) = text.strip_prefix(PONG_PREFIX) {
+            id.parse().ok().map(Self::Pong)
+        } else {
+            None
+        }
+    }
+
+    fn to_text(self) -> String {
+        match self {
+            Self::Ping(id) => format!("{}{}", PING_PREFIX, id),
+            Self::Pong(id) => format!("{}{}", PONG_PREFIX, id),
+        }
+    }
+
+    fn send(self, data_channel: &RtcDataChannel) -> crate::Result<()> {
+        data_channel
+            .send_with_str(&self.to_text())
+            .map_err(|err| anyhow!("failed to send {:?}: {:?}", self, err))
+    }
+}
+
+#[derive(Debug, Default)]
+struct PendingPingsInner {
+    next_id: u32,
+    /// Resolve functions of promises waiting for pongs, called with the time of pong's arrival
+    resolvers: HashMap<u32, Function>,
+}
+
+/// Pings waiting for their pongs, shared by all data channels of a network manager.
+#[derive(Debug, Clone, Default)]
+pub struct PendingPings {
+    inner: Rc<RefCell<PendingPingsInner>>,
+}
+
+impl PendingPings {
+    /// Answers ping or completes measurement waiting for pong received on `data_channel`.
+    /// Returns `false` if `data` is not a ping or pong frame, but a user message.
+    pub fn handle_frame(&self, data_channel: &RtcDataChannel, data: &JsValue) -> bool {
+        let Some(frame) = data.as_string().as_deref().and_then(ControlFrame::parse) else {
+            return false;
+        };
+        match frame {
+            ControlFrame::Ping(id) => {
+                if let Err(err) = ControlFrame::Pong(id).send(data_channel) {
+                    error!("failed to answer ping: {}", err);
+                }
+            }
+            ControlFrame::Pong(id) => {
+                let resolver = self.inner.borrow_mut().resolvers.remove(&id);
+                if let Some(resolve) = resolver {
+                    let _result = resolve.call1(&JsValue::NULL, &JsValue::from_f64(now()));
+                }
+            }
+        }
+        true
+    }
+
+    /// Sends a ping over `data_channel` and resolves with the time it took to receive a pong,
+    /// `None` if it didn't arrive within `timeout`.
+    ///
+    /// # Errors
+    /// This function errs if setting the timeout or sending the ping fails.
+    pub async fn ping(
+        &self,
+        data_channel: &RtcDataChannel,
+        timeout: Duration,
+    ) -> crate::Result<Option<Duration>> {
+        let window = web_sys::window().ok_or_else(|| anyhow!("no global window object"))?;
+        let id = {
+            let mut inner = self.inner.borrow_mut();
+            let id = inner.next_id;
+            inner.next_id = id.wrapping_add(1);
+            id
+        };
+        let timeout = i32::try_from(timeout.as_millis()).unwrap_or(i32::MAX);
+        let mut result = Ok(0);
+        let pong = Promise::new(&mut |resolve, _reject| {
+            result = window.set_timeout_with_callback_and_timeout_and_arguments_1(
+                &resolve,
+                timeout,
+                &JsValue::NULL,
+            );
+            self.inner.borrow_mut().resolvers.insert(id, resolve);
+        });
+        let sent_at = now();
+        let sent = result
+            .map_err(|err| anyhow!("failed to set timeout: {:?}", err))
+            .and_then(|_| ControlFrame::Ping(id).send(data_channel));
+        if let Err(err) = sent {
+            self.inner.borrow_mut().resolvers.remove(&id);
+            return Err(err);
+        }
+        let received_at = JsFuture::from(pong)
+            .await
+            .map_err(|err| anyhow!("waiting for pong failed: {:?}", err))?;
+        // still registered if timeout resolved the promise with null before pong arrived
+        self.inner.borrow_mut().resolvers.remove(&id);
+        Ok(received_at
+            .as_f64()
+            .map(|received_at| millis(received_at).saturating_sub(millis(sent_at))))
+    }
+}
+
+/// Milliseconds from `performance.now()`, or from `Date.now()` outside of a browser window.
+fn now() -> f64 {
+    web_sys::window()
+        .and_then(|window| window.performance())
+        .map_or_else(js_sys::Date::now, |performance| performance.now())
+}
+
+fn millis(millis: f64) -> Duration {
+    if millis.is_finite() && millis > 0.0 {
+        Duration::from_secs_f64(millis).div_f64(1000.0)
+    } else {
+        Duration::ZERO
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+
+    #[wasm_bindgen_test]
+    fn test_control_frames_round_trip() {
+        for frame in [ControlFrame::Ping(0), ControlFrame::Pong(u32::MAX)] {
+            assert_eq!(ControlFrame::parse(&frame.to_text()), Some(frame));
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn test_user_messages_are_not_control_frames() {
+        assert_eq!(ControlFrame::parse(r#""ping:1""#), None);
+        assert_eq!(ControlFrame::parse("\0ping:not-a-number"), None);
+        assert_eq!(ControlFrame::parse(""), None);
+    }
+}
@@ -0,0 +1,508 @@
+/*!
+Independent streams of messages, e.g. chat, game state and voice activity flags, over a single network manager.
+
+[`PubSubNetworkManager`] wraps a network manager of any topology. Messages sent with a [`Publisher`]
+of a topic are delivered to the callback subscribed to the same topic with
+[`PubSubNetworkManager::subscribe`], each topic having its own message type.
+
+Topic names are sent along with messages until the receiving peer acknowledges them,
+after that messages only carry the small id given to the topic by its sender,
+so they're a few bytes longer than the same messages sent without topics.
+Messages of topics nobody subscribed to are passed to [`PubSubNetworkManager::set_on_unknown_topic`]
+callback, messages that can't be deserialized into the subscribed type are logged and dropped.
+
+Both peers have to use [`PubSubNetworkManager`], as messages are sent in frames carrying their topics.
+
+# Example
+
+```no_run
+use wasm_peers::many_to_many::NetworkManager;
+use wasm_peers::pubsub::PubSubNetworkManager;
+use wasm_peers::{ConnectionType, PeerEvent, SessionId};
+use web_sys::console;
+
+let network_manager = NetworkManager::new(
+    "ws://0.0.0.0:9001/many-to-many",
+    SessionId::new(1),
+    ConnectionType::Local,
+)
+.expect("failed to connect to signaling server");
+let mut network_manager = PubSubNetworkManager::new(network_manager);
+let chat = network_manager.publisher::<String>("chat");
+network_manager.subscribe("chat", |user_id, message: String| {
+    console::log_1(&format!("{}: {}", user_id, message).into());
+});
+network_manager.subscribe("speaking", |user_id, speaking: bool| {
+    console::log_1(&format!("{} speaking: {}", user_id, speaking).into());
+});
+network_manager
+    .start_with_event_handler(move |event| {
+        if let PeerEvent::Opened(user_id) = event {
+            let _result = chat.send_to(user_id, &"hello!".to_owned());
+        }
+    })
+    .unwrap();
+```
+*/
+
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::convert::{Infallible, TryFrom};
+use std::fmt::Formatter;
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::rc::Rc;
+
+use log::{debug, error};
+use serde::de::{DeserializeOwned, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::utils::{deserialize_serialized_message, serialize_message};
+use crate::{PeerEvent, PeerManager};
+
+/// Message serialized by its publisher, sent as binary data within a [`Frame`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Payload<'a>(pub(crate) Cow<'a, [u8]>);
+
+impl Serialize for Payload<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Payload<'_> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let bytes = deserializer.deserialize_byte_buf(PayloadVisitor)?;
+        Ok(Self(Cow::Owned(bytes)))
+    }
+}
+
+/// Reads payload from binary data, or from a sequence of numbers it's serialized into in JSON.
+struct PayloadVisitor;
+
+impl<'de> Visitor<'de> for PayloadVisitor {
+    type Value = Vec<u8>;
+
+    fn expecting(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+        formatter.write_str("serialized message")
+    }
+
+    fn visit_bytes<E: serde::de::Error>(self, bytes: &[u8]) -> Result<Self::Value, E> {
+        Ok(bytes.to_vec())
+    }
+
+    fn visit_byte_buf<E: serde::de::Error>(self, bytes: Vec<u8>) -> Result<Self::Value, E> {
+        Ok(bytes)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(byte) = seq.next_element()? {
+            bytes.push(byte);
+        }
+        Ok(bytes)
+    }
+}
+
+/// Message of a topic, or acknowledgement of the topic's id once `payload` is `None`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Frame<'a> {
+    /// Id given to the topic by the peer which publishes it
+    topic: u32,
+    /// Name of the topic, sent until receiving peer acknowledges `topic` id
+    name: Option<Cow<'a, str>>,
+    payload: Option<Payload<'a>>,
+}
+
+type Subscriber<Id> = Rc<RefCell<dyn FnMut(Id, &[u8])>>;
+type OnUnknownTopic<Id> = Rc<RefCell<dyn FnMut(Id, String)>>;
+
+/// What to do with a frame received from a peer.
+#[derive(Debug, PartialEq, Eq)]
+enum Received {
+    /// Peer acknowledged a topic or sent message of a topic which it didn't name yet
+    Nothing,
+    /// Message of the named topic, along with id to acknowledge if the frame named it
+    Message {
+        name: String,
+        payload: Vec<u8>,
+        acknowledge: Option<u32>,
+    },
+}
+
+struct PubSubState<Id> {
+    /// Names of topics published by this peer, indexed by their ids
+    topics: Vec<String>,
+    /// Connected peers along with ids of topics they acknowledged
+    peers: HashMap<Id, HashSet<u32>>,
+    /// Names of topics published by each peer, by their ids
+    remote_topics: HashMap<Id, HashMap<u32, String>>,
+    subscribers: HashMap<String, Subscriber<Id>>,
+    on_unknown_topic: Option<OnUnknownTopic<Id>>,
+}
+
+impl<Id: Clone + Eq + Hash> PubSubState<Id> {
+    fn new() -> Self {
+        Self {
+            topics: Vec::new(),
+            peers: HashMap::new(),
+            remote_topics: HashMap::new(),
+            subscribers: HashMap::new(),
+            on_unknown_topic: None,
+        }
+    }
+
+    /// Id of topic `name`, given to it the first time it's published.
+    fn intern(&mut self, name: &str) -> u32 {
+        if let Some(topic) = self.topics.iter().position(|topic| topic == name) {
+            return u32::try_from(topic).unwrap_or(u32::MAX);
+        }
+        self.topics.push(name.to_owned());
+        u32::try_from(self.topics.len().saturating_sub(1)).unwrap_or(u32::MAX)
+    }
+
+    /// Frame carrying `payload` of `topic` to peer `id`, named unless the peer acknowledged it.
+    fn frame<'a>(&'a self, id: &Id, topic: u32, payload: &'a [u8]) -> Frame<'a> {
+        let acknowledged = self
+            .peers
+            .get(id)
+            .map_or(false, |acknowledged| acknowledged.contains(&topic));
+        let name = if acknowledged {
+            None
+        } else {
+            usize::try_from(topic)
+                .ok()
+                .and_then(|topic| self.topics.get(topic))
+                .map(|name| Cow::Borrowed(name.as_str()))
+        };
+        Frame {
+            topic,
+            name,
+            payload: Some(Payload(Cow::Borrowed(payload))),
+        }
+    }
+
+    fn receive(&mut self, id: &Id, frame: Frame<'static>) -> Received {
+        let Some(Payload(payload)) = frame.payload else {
+            if let Some(acknowledged) = self.peers.get_mut(id) {
+                acknowledged.insert(frame.topic);
+            }
+            return Received::Nothing;
+        };
+        let remote_topics = self.remote_topics.entry(id.clone()).or_default();
+        let acknowledge = frame.name.map(|name| {
+            remote_topics.insert(frame.topic, name.into_owned());
+            frame.topic
+        });
+        let Some(name) = remote_topics.get(&frame.topic) else {
+            error!(
+                "dropping message of topic {} that wasn't named",
+                frame.topic
+            );
+            return Received::Nothing;
+        };
+        Received::Message {
+            name: name.clone(),
+            payload: payload.into_owned(),
+            acknowledge,
+        }
+    }
+
+    fn forget(&mut self, id: &Id) {
+        self.peers.remove(id);
+        self.remote_topics.remove(id);
+    }
+}
+
+/// Wrapper over network manager of any topology, routing messages to subscribers of their topics.
+/// See [module documentation](self) for details.
+pub struct PubSubNetworkManager<M: PeerManager> {
+    inner: M,
+    state: Rc<RefCell<PubSubState<M::Id>>>,
+}
+
+impl<M: PeerManager> Clone for PubSubNetworkManager<M> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            state: Rc::clone(&self.state),
+        }
+    }
+}
+
+impl<M: PeerManager> PubSubNetworkManager<M>
+where
+    M::Id: Eq + Hash,
+{
+    /// Wraps `inner`, which shouldn't be started yet.
+    pub fn new(inner: M) -> Self {
+        Self {
+            inner,
+            state: Rc::new(RefCell::new(PubSubState::new())),
+        }
+    }
+
+    /// Handle sending messages of type `T` to subscribers of `topic`.
+    pub fn publisher<T: Serialize + ?Sized>(&self, topic: &str) -> Publisher<M, T> {
+        Publisher {
+            network_manager: self.clone(),
+            topic: self.state.borrow_mut().intern(topic),
+            message: PhantomData,
+        }
+    }
+
+    /// Registers a callback receiving messages of `topic` along with the peer that sent them,
+    /// replacing the previous subscriber of the same topic.
+    pub fn subscribe<T: DeserializeOwned + 'static>(
+        &self,
+        topic: &str,
+        mut callback: impl FnMut(M::Id, T) + 'static,
+    ) {
+        let name = topic.to_owned();
+        let subscriber = move |id, payload: &[u8]| match deserialize_serialized_message(payload) {
+            Some(message) => callback(id, message),
+            None => error!("failed to deserialize message of topic {}", name),
+        };
+        self.state
+            .borrow_mut()
+            .subscribers
+            .insert(topic.to_owned(), Rc::new(RefCell::new(subscriber)));
+    }
+
+    /// Stops passing messages of `topic` to its subscriber.
+    pub fn unsubscribe(&self, topic: &str) {
+        self.state.borrow_mut().subscribers.remove(topic);
+    }
+
+    /// Registers a callback receiving names of topics, which no one subscribed to, of messages from each peer.
+    /// Without it such messages are only logged.
+    pub fn set_on_unknown_topic(&self, on_unknown_topic: impl FnMut(M::Id, String) + 'static) {
+        self.state.borrow_mut().on_unknown_topic = Some(Rc::new(RefCell::new(on_unknown_topic)));
+    }
+
+    /// Starts the underlying network manager, calling `handler` with every event
+    /// but messages, which are passed to subscribers of their topics instead.
+    ///
+    /// # Errors
+    /// Errs with [`AlreadyStartedError`](crate::AlreadyStartedError) if it was already started.
+    pub fn start_with_event_handler(
+        &mut self,
+        mut handler: impl FnMut(PeerEvent<Infallible, M::Id>) + 'static,
+    ) -> crate::Result<()> {
+        let network_manager = self.clone();
+        self.inner
+            .start_handling(move |event: PeerEvent<Frame<'static>, M::Id>| match event {
+                PeerEvent::Opened(id) => {
+                    network_manager
+                        .state
+                        .borrow_mut()
+                        .peers
+                        .insert(id.clone(), HashSet::new());
+                    handler(PeerEvent::Opened(id));
+                }
+                PeerEvent::Message(id, frame) => network_manager.receive(id, frame),
+                PeerEvent::Closed(id, reason) => {
+                    network_manager.state.borrow_mut().forget(&id);
+                    handler(PeerEvent::Closed(id, reason));
+                }
+                PeerEvent::SignalingError(err) => handler(PeerEvent::SignalingError(err)),
+                PeerEvent::StateChanged(id, state) => handler(PeerEvent::StateChanged(id, state)),
+                PeerEvent::ChannelStateChanged(id, state) => {
+                    handler(PeerEvent::ChannelStateChanged(id, state));
+                }
+            })
+    }
+
+    fn receive(&self, id: M::Id, frame: Frame<'static>) {
+        let Received::Message {
+            name,
+            payload,
+            acknowledge,
+        } = self.state.borrow_mut().receive(&id, frame)
+        else {
+            return;
+        };
+        if let Some(topic) = acknowledge {
+            let acknowledgement = Frame {
+                topic,
+                name: None,
+                payload: None,
+            };
+            if let Err(err) = self.inner.send_to(id.clone(), &acknowledgement) {
+                error!("failed to acknowledge topic {}: {}", name, err);
+            }
+        }
+        // clone callbacks out, so that they can freely use the network manager
+        let (subscriber, on_unknown_topic) = {
+            let state = self.state.borrow();
+            (
+                state.subscribers.get(&name).cloned(),
+                state.on_unknown_topic.clone(),
+            )
+        };
+        match (subscriber, on_unknown_topic) {
+            (Some(subscriber), _) => (subscriber.borrow_mut())(id, &payload),
+            (None, Some(on_unknown_topic)) => (on_unknown_topic.borrow_mut())(id, name),
+            (None, None) => debug!("dropping message of topic {} without subscriber", name),
+        }
+    }
+
+    fn send_payload(&self, id: M::Id, topic: u32, payload: &[u8]) -> crate::Result<()> {
+        let state = self.state.borrow();
+        let frame = state.frame(&id, topic, payload);
+        self.inner.send_to(id, &frame)
+    }
+
+    /// Closes the underlying network manager.
+    pub fn close(&self) {
+        self.inner.disconnect();
+    }
+}
+
+/// Handle sending messages of a single topic, created with [`PubSubNetworkManager::publisher`].
+pub struct Publisher<M: PeerManager, T: ?Sized> {
+    network_manager: PubSubNetworkManager<M>,
+    topic: u32,
+    message: PhantomData<fn(&T)>,
+}
+
+impl<M: PeerManager, T: ?Sized> Clone for Publisher<M, T> {
+    fn clone(&self) -> Self {
+        Self {
+            network_manager: self.network_manager.clone(),
+            topic: self.topic,
+            message: PhantomData,
+        }
+    }
+}
+
+impl<M: PeerManager, T: Serialize + ?Sized> Publisher<M, T>
+where
+    M::Id: Eq + Hash,
+{
+    /// Sends message to subscribers of the topic among all connected peers.
+    ///
+    /// # Errors
+    /// This function errs if serialization fails, otherwise it tries to send message to all peers
+    /// and returns the first error that occurred.
+    pub fn send(&self, message: &T) -> crate::Result<()> {
+        let payload = serialize_message(message)?;
+        let ids: Vec<_> = self
+            .network_manager
+            .state
+            .borrow()
+            .peers
+            .keys()
+            .cloned()
+            .collect();
+        ids.into_iter()
+            .map(|id| self.network_manager.send_payload(id, self.topic, &payload))
+            .fold(Ok(()), Result::and)
+    }
+
+    /// Sends message to subscriber of the topic on a single peer.
+    ///
+    /// # Errors
+    /// Same as `send_message` of the underlying network manager.
+    pub fn send_to(&self, id: M::Id, message: &T) -> crate::Result<()> {
+        let payload = serialize_message(message)?;
+        self.network_manager.send_payload(id, self.topic, &payload)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+
+    fn received(state: &mut PubSubState<u8>, frame: &Frame<'_>) -> Received {
+        let frame = serialize_message(frame).expect("failed to serialize frame");
+        let frame = deserialize_serialized_message(&frame).expect("failed to deserialize frame");
+        state.receive(&1, frame)
+    }
+
+    #[wasm_bindgen_test]
+    fn test_topics_are_interned_once() {
+        let mut state = PubSubState::<u8>::new();
+        assert_eq!(state.intern("chat"), 0);
+        assert_eq!(state.intern("game"), 1);
+        assert_eq!(state.intern("chat"), 0);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_topic_is_named_until_acknowledged() {
+        let mut sender = PubSubState::<u8>::new();
+        let mut receiver = PubSubState::<u8>::new();
+        sender.peers.insert(1, HashSet::new());
+        let topic = sender.intern("chat");
+
+        let named = sender.frame(&1, topic, b"hello");
+        assert_eq!(named.name.as_deref(), Some("chat"));
+        let acknowledged = Received::Message {
+            name: "chat".to_owned(),
+            payload: b"hello".to_vec(),
+            acknowledge: Some(topic),
+        };
+        assert_eq!(received(&mut receiver, &named), acknowledged);
+
+        let acknowledgement = Frame {
+            topic,
+            name: None,
+            payload: None,
+        };
+        assert_eq!(received(&mut sender, &acknowledgement), Received::Nothing);
+        let interned = sender.frame(&1, topic, b"hello");
+        assert_eq!(interned.name, None);
+        let message = Received::Message {
+            name: "chat".to_owned(),
+            payload: b"hello".to_vec(),
+            acknowledge: None,
+        };
+        assert_eq!(received(&mut receiver, &interned), message);
+    }
+
+    #[cfg(not(feature = "json-messages"))]
+    #[wasm_bindgen_test]
+    fn test_interned_topic_adds_few_bytes() {
+        let mut state = PubSubState::<u8>::new();
+        let topic = state.intern("chat");
+        state.peers.insert(1, [topic].into_iter().collect());
+        let payload = serialize_message("hello").expect("failed to serialize message");
+        let frame = serialize_message(&state.frame(&1, topic, &payload))
+            .expect("failed to serialize frame");
+        assert!(
+            frame.len() <= payload.len().saturating_add(5),
+            "{:?}",
+            frame
+        );
+    }
+
+    #[wasm_bindgen_test]
+    fn test_message_of_unnamed_topic_is_dropped() {
+        let mut state = PubSubState::<u8>::new();
+        let frame = Frame {
+            topic: 3,
+            name: None,
+            payload: Some(Payload(Cow::Borrowed(b"hello"))),
+        };
+        assert_eq!(received(&mut state, &frame), Received::Nothing);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_forgotten_peer_has_to_name_topics_again() {
+        let mut state = PubSubState::<u8>::new();
+        state.peers.insert(1, [0].into_iter().collect());
+        state
+            .remote_topics
+            .entry(1)
+            .or_default()
+            .insert(0, "chat".to_owned());
+        state.forget(&1);
+        state.peers.insert(1, HashSet::new());
+        let topic = state.intern("chat");
+        assert!(state.frame(&1, topic, b"").name.is_some());
+        assert!(!state.remote_topics.contains_key(&1));
+    }
+}
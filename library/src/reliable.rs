@@ -0,0 +1,243 @@
+/*!
+Acknowledged delivery of single messages over data channels that may lose them,
+with `send_message_reliably` methods of network managers.
+
+Reliable messages are binary frames starting with `0xc1`, like relayed ones, followed by `0xff`,
+id of the message and the serialized message. Receiving peer answers each of them with a text frame
+starting with a NUL character, like pongs, and passes the message on only the first time it arrives.
+Text frames starting with it are reserved for the library, sending user messages that do errs,
+so that they aren't taken for acknowledgements.
+Sending peer resends the message until it's acknowledged, waiting twice as long before each next attempt.
+*/
+
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryInto;
+use std::hash::Hash;
+use std::rc::Rc;
+
+use anyhow::anyhow;
+use js_sys::{Function, Promise, Uint8Array};
+use log::{debug, error};
+use wasm_bindgen::JsValue;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::RtcDataChannel;
+
+use crate::constants::{DEFAULT_ACK_TIMEOUT, DEFAULT_MAX_SEND_ATTEMPTS};
+use crate::utils::{has_frame_marker, random_u128, serialized_message_to_js};
+use crate::{DataChannelConfig, NotAcknowledgedError};
+
+/// Never starts a `MessagePack` message, second byte tells it apart from relay frames
+const RELIABLE_MARKER: [u8; 2] = [0xc1, 0xff];
+const ACK_PREFIX: &str = "\0ack:";
+/// How many ids of the latest messages received from each peer are remembered to drop their duplicates
+const SEEN_IDS: usize = 256;
+
+fn to_frame(id: u32, message: &[u8]) -> Vec<u8> {
+    [&RELIABLE_MARKER[..], &id.to_be_bytes(), message].concat()
+}
+
+fn parse_frame(bytes: &[u8]) -> Option<(u32, &[u8])> {
+    let rest = bytes.strip_prefix(&RELIABLE_MARKER[..])?;
+    let id = u32::from_be_bytes(rest.get(..4)?.try_into().ok()?);
+    Some((id, rest.get(4..)?))
+}
+
+fn parse_ack(text: &str) -> Option<u32> {
+    text.strip_prefix(ACK_PREFIX)?.parse().ok()
+}
+
+fn ack_to_text(id: u32) -> String {
+    format!("{}{}", ACK_PREFIX, id)
+}
+
+/// Ids of messages recently received from a single peer, oldest first.
+#[derive(Debug, Default)]
+struct SeenIds(VecDeque<u32>);
+
+impl SeenIds {
+    /// Remembers `id`, returning `false` if it was already seen.
+    fn insert(&mut self, id: u32) -> bool {
+        if self.0.contains(&id) {
+            return false;
+        }
+        if self.0.len() >= SEEN_IDS {
+            self.0.pop_front();
+        }
+        self.0.push_back(id);
+        true
+    }
+}
+
+#[derive(Debug)]
+struct ReliableMessagesInner<Id> {
+    next_id: u32,
+    /// Resolve functions of promises waiting for acknowledgements, called with `true` once one arrives
+    resolvers: HashMap<u32, Function>,
+    seen: HashMap<Id, SeenIds>,
+}
+
+/// Reliable messages waiting for acknowledgements and ids of received ones,
+/// shared by all data channels of a network manager.
+#[derive(Debug, Clone)]
+pub struct ReliableMessages<Id> {
+    inner: Rc<RefCell<ReliableMessagesInner<Id>>>,
+}
+
+impl<Id> Default for ReliableMessages<Id> {
+    fn default() -> Self {
+        Self {
+            inner: Rc::new(RefCell::new(ReliableMessagesInner {
+                // random, so that messages of a restarted peer aren't mistaken for duplicates
+                next_id: u32::try_from(random_u128() >> 96).unwrap_or(0),
+                resolvers: HashMap::new(),
+                seen: HashMap::new(),
+            })),
+        }
+    }
+}
+
+impl<Id: Eq + Hash> ReliableMessages<Id> {
+    /// Acknowledges reliable message or completes sending waiting for acknowledgement received on `data_channel`.
+    /// Returns data to be handled as a user message: `data` itself if it's not a reliable message,
+    /// message carried by it if it arrived for the first time, and `None` otherwise.
+    /// Text starting with the acknowledgement prefix is never a user message, as sending those errs.
+    pub fn receive(
+        &self,
+        from: Id,
+        data_channel: &RtcDataChannel,
+        data: JsValue,
+    ) -> Option<JsValue> {
+        if let Some(id) = data.as_string().as_deref().and_then(parse_ack) {
+            let resolver = self.inner.borrow_mut().resolvers.remove(&id);
+            if let Some(resolve) = resolver {
+                let _result = resolve.call1(&JsValue::NULL, &JsValue::TRUE);
+            }
+            return None;
+        }
+        if !has_frame_marker(&data, RELIABLE_MARKER) {
+            return Some(data);
+        }
+        let bytes = Uint8Array::new(&data).to_vec();
+        let (id, message) = parse_frame(&bytes)?;
+        if let Err(err) = data_channel.send_with_str(&ack_to_text(id)) {
+            error!("failed to acknowledge message {}: {:?}", id, err);
+        }
+        if !self
+            .inner
+            .borrow_mut()
+            .seen
+            .entry(from)
+            .or_default()
+            .insert(id)
+        {
+            debug!("dropping duplicated message {}", id);
+            return None;
+        }
+        Some(serialized_message_to_js(message))
+    }
+
+    /// Forgets messages received from `peer`, once it left.
+    #[cfg(feature = "one-to-many")]
+    pub fn forget(&self, peer: &Id) {
+        self.inner.borrow_mut().seen.remove(peer);
+    }
+
+    /// Sends serialized `message` over `data_channel` until it's acknowledged,
+    /// up to [`DataChannelConfig::max_send_attempts`] times.
+    ///
+    /// # Errors
+    /// This function errs with [`NotAcknowledgedError`] if no attempt was acknowledged,
+    /// or if setting the timeout or sending the message fails.
+    pub async fn send(
+        &self,
+        data_channel: &RtcDataChannel,
+        message: &[u8],
+        config: &DataChannelConfig,
+    ) -> crate::Result<()> {
+        let window = web_sys::window().ok_or_else(|| anyhow!("no global window object"))?;
+        let id = {
+            let mut inner = self.inner.borrow_mut();
+            let id = inner.next_id;
+            inner.next_id = id.wrapping_add(1);
+            id
+        };
+        let frame = to_frame(id, message);
+        let attempts = config
+            .max_send_attempts
+            .unwrap_or(DEFAULT_MAX_SEND_ATTEMPTS);
+        let mut timeout = config.ack_timeout.unwrap_or(DEFAULT_ACK_TIMEOUT);
+        for attempt in 1..=attempts {
+            let millis = i32::try_from(timeout.as_millis()).unwrap_or(i32::MAX);
+            let mut result = Ok(0);
+            let acknowledged = Promise::new(&mut |resolve, _reject| {
+                result = window.set_timeout_with_callback_and_timeout_and_arguments_1(
+                    &resolve,
+                    millis,
+                    &JsValue::NULL,
+                );
+                self.inner.borrow_mut().resolvers.insert(id, resolve);
+            });
+            let sent = result
+                .map_err(|err| anyhow!("failed to set timeout: {:?}", err))
+                .and_then(|_| {
+                    data_channel
+                        .send_with_u8_array(&frame)
+                        .map_err(|err| anyhow!("failed to send message: {:?}", err))
+                });
+            if let Err(err) = sent {
+                self.inner.borrow_mut().resolvers.remove(&id);
+                return Err(err);
+            }
+            let acknowledged = JsFuture::from(acknowledged)
+                .await
+                .map_err(|err| anyhow!("waiting for acknowledgement failed: {:?}", err))?;
+            if acknowledged.is_truthy() {
+                return Ok(());
+            }
+            debug!(
+                "message {} wasn't acknowledged in {:?} ({}/{})",
+                id, timeout, attempt, attempts
+            );
+            timeout = timeout.saturating_mul(2);
+        }
+        // still registered, as timeout resolved the promise
+        self.inner.borrow_mut().resolvers.remove(&id);
+        Err(NotAcknowledgedError { attempts }.into())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+
+    #[wasm_bindgen_test]
+    fn test_frames_round_trip() {
+        let message = [0x92, 0x01, 0x02];
+        assert_eq!(
+            parse_frame(&to_frame(u32::MAX, &message)),
+            Some((u32::MAX, &message[..]))
+        );
+        assert_eq!(parse_ack(&ack_to_text(0)), Some(0));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_other_messages_are_not_reliable_frames() {
+        assert_eq!(parse_frame(&[0xc1, 0x00, 0x00, 0x00, 0x00, 0x01]), None);
+        assert_eq!(parse_frame(&RELIABLE_MARKER), None);
+        assert_eq!(parse_ack("\0pong:1"), None);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_oldest_seen_ids_are_forgotten() {
+        let mut seen = SeenIds::default();
+        assert!(seen.insert(0));
+        assert!(!seen.insert(0));
+        for id in (1..).take(SEEN_IDS) {
+            assert!(seen.insert(id));
+        }
+        assert!(seen.insert(0));
+    }
+}
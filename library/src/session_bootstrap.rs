@@ -0,0 +1,130 @@
+/*!
+Session id shared through the page URL, so that peers join a session by opening a link.
+
+[`SessionBootstrap::from_location`] reads session id from `?session=` query parameter
+or `#session=` fragment parameter of the page URL. Without one, or if it's malformed,
+a new random session id is generated, meaning this peer starts a new session.
+Either way its canonical encoding is written back into the URL with `history.replaceState`,
+so no page is reloaded and no history entry is added that the back button would get stuck on.
+
+# Example
+
+```no_run
+use wasm_peers::many_to_many::NetworkManager;
+use wasm_peers::session_bootstrap::SessionBootstrap;
+use wasm_peers::ConnectionType;
+use web_sys::console;
+
+let bootstrap = SessionBootstrap::from_location().unwrap();
+if bootstrap.is_new_session() {
+    console::log_1(&format!("invite others with {}", bootstrap.share_url()).into());
+}
+let network_manager = NetworkManager::new(
+    "ws://0.0.0.0:9001/many-to-many",
+    bootstrap.session_id(),
+    ConnectionType::Local,
+)
+.unwrap();
+```
+*/
+
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use wasm_bindgen::JsValue;
+use wasm_peers_protocol::SessionId;
+use web_sys::{Url, UrlSearchParams};
+
+use crate::get_random_session_id;
+
+/// Whether session id was generated, instead of read from the URL.
+pub type IsNewSession = bool;
+
+/// Name of the query and fragment parameter holding session id
+const SESSION_PARAM: &str = "session";
+
+/// Session id read from the page URL, or generated and written into it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionBootstrap {
+    session_id: SessionId,
+    is_new_session: IsNewSession,
+    share_url: String,
+}
+
+impl SessionBootstrap {
+    /// Reads session id from the page URL, or generates a new one, and writes it back into the URL.
+    /// Session id found in the fragment stays there, a new one is put in the query.
+    ///
+    /// # Errors
+    /// This function errs if it's called outside of a page with a window, or the URL can't be replaced.
+    pub fn from_location() -> crate::Result<Self> {
+        let window = web_sys::window().ok_or_else(|| anyhow!("no global window object"))?;
+        let href = window
+            .location()
+            .href()
+            .map_err(|err| anyhow!("failed to read page URL: {:?}", err))?;
+        let bootstrap =
+            Self::from_url(&href).map_err(|err| anyhow!("failed to parse page URL: {:?}", err))?;
+        let history = window
+            .history()
+            .map_err(|err| anyhow!("failed to access history: {:?}", err))?;
+        // keep the state, e.g. of a router, only the URL changes
+        let state = history
+            .state()
+            .map_err(|err| anyhow!("failed to read history state: {:?}", err))?;
+        history
+            .replace_state_with_url(&state, "", Some(&bootstrap.share_url))
+            .map_err(|err| anyhow!("failed to replace page URL: {:?}", err))?;
+        Ok(bootstrap)
+    }
+
+    /// Same as [`SessionBootstrap::from_location`], but for a given URL, which is left unchanged.
+    ///
+    /// # Errors
+    /// This function errs if `url` isn't a valid absolute URL.
+    pub fn from_url(url: &str) -> Result<Self, JsValue> {
+        let url = Url::new(url)?;
+        let hash_params = UrlSearchParams::new_with_str(url.hash().trim_start_matches('#'))?;
+        let in_hash = !url.search_params().has(SESSION_PARAM) && hash_params.has(SESSION_PARAM);
+        let params = if in_hash {
+            hash_params
+        } else {
+            url.search_params()
+        };
+
+        let read = params
+            .get(SESSION_PARAM)
+            .and_then(|session_id| SessionId::from_str(&session_id).ok());
+        let (session_id, is_new_session) = match read {
+            Some(session_id) => (session_id, false),
+            None => (get_random_session_id(), true),
+        };
+        params.set(SESSION_PARAM, &session_id.inner().to_string());
+        if in_hash {
+            url.set_hash(&String::from(params.to_string()));
+        }
+        Ok(Self {
+            session_id,
+            is_new_session,
+            share_url: url.href(),
+        })
+    }
+
+    /// Session to join.
+    #[must_use]
+    pub const fn session_id(&self) -> SessionId {
+        self.session_id
+    }
+
+    /// Whether there was no valid session id in the URL, so this peer starts a new session.
+    #[must_use]
+    pub const fn is_new_session(&self) -> IsNewSession {
+        self.is_new_session
+    }
+
+    /// URL that joins the same session, e.g. for a copy-to-clipboard button.
+    #[must_use]
+    pub fn share_url(&self) -> String {
+        self.share_url.clone()
+    }
+}
@@ -0,0 +1,93 @@
+/*!
+Listing of sessions open on signaling server, e.g. to show a lobby of games that can be joined.
+
+Responses aren't matched with requests, as each of them carries the current state of the server:
+every request waiting when one arrives is resolved with it.
+*/
+
+use std::cell::RefCell;
+use std::mem;
+use std::rc::Rc;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use js_sys::{Function, Promise};
+use log::error;
+use serde::Serialize;
+use wasm_bindgen::JsValue;
+use wasm_bindgen_futures::JsFuture;
+use wasm_peers_protocol::SessionInfo;
+
+use crate::transport::{send_signal_message, SignalingTransport};
+
+#[derive(Debug, Default)]
+struct PendingSessionListsInner {
+    /// Resolve functions of promises waiting for a response, called with `true` once it arrives
+    resolvers: Vec<Function>,
+    received: Vec<SessionInfo>,
+}
+
+/// Session list requests of a network manager waiting for the response of signaling server.
+#[derive(Debug, Clone, Default)]
+pub struct PendingSessionLists {
+    inner: Rc<RefCell<PendingSessionListsInner>>,
+}
+
+impl PendingSessionLists {
+    /// Resolves all waiting requests with `sessions` received from signaling server.
+    pub fn received(&self, sessions: Vec<SessionInfo>) {
+        let resolvers = {
+            let mut inner = self.inner.borrow_mut();
+            inner.received = sessions;
+            mem::take(&mut inner.resolvers)
+        };
+        for resolve in resolvers {
+            let _result = resolve.call1(&JsValue::NULL, &JsValue::TRUE);
+        }
+    }
+
+    /// Sends `request` through `transport` and resolves with sessions from the response,
+    /// empty if it didn't arrive within `timeout`.
+    pub async fn request(
+        &self,
+        transport: &impl SignalingTransport,
+        request: &impl Serialize,
+        timeout: Duration,
+    ) -> Vec<SessionInfo> {
+        self.try_request(transport, request, timeout)
+            .await
+            .unwrap_or_else(|err| {
+                error!("failed to list sessions: {}", err);
+                Vec::new()
+            })
+    }
+
+    async fn try_request(
+        &self,
+        transport: &impl SignalingTransport,
+        request: &impl Serialize,
+        timeout: Duration,
+    ) -> crate::Result<Vec<SessionInfo>> {
+        let window = web_sys::window().ok_or_else(|| anyhow!("no global window object"))?;
+        let timeout = i32::try_from(timeout.as_millis()).unwrap_or(i32::MAX);
+        let mut result = Ok(0);
+        let response = Promise::new(&mut |resolve, _reject| {
+            result = window.set_timeout_with_callback_and_timeout_and_arguments_1(
+                &resolve,
+                timeout,
+                &JsValue::FALSE,
+            );
+            self.inner.borrow_mut().resolvers.push(resolve);
+        });
+        result.map_err(|err| anyhow!("failed to set timeout: {:?}", err))?;
+        send_signal_message(transport, request)?;
+        let received = JsFuture::from(response)
+            .await
+            .map_err(|err| anyhow!("waiting for session list failed: {:?}", err))?;
+        if received.as_bool() == Some(true) {
+            Ok(self.inner.borrow().received.clone())
+        } else {
+            Err(anyhow!("signaling server didn't respond in time"))
+        }
+    }
+}
@@ -0,0 +1,462 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::mem;
+use std::rc::Rc;
+
+#[cfg(feature = "worker")]
+use anyhow::anyhow;
+use log::debug;
+use wasm_peers_protocol::{one_to_many, one_to_one, SessionId};
+#[cfg(feature = "worker")]
+use web_sys::Worker;
+
+#[cfg(feature = "many-to-many")]
+use crate::many_to_many;
+#[cfg(feature = "one-to-many")]
+use crate::one_to_many::{MiniClient, MiniServer};
+#[cfg(feature = "test-utils")]
+use crate::test_utils::{LoopbackSignaling, Topology};
+use crate::transport::{SharedSession, SignalingConnection, SignalingTransport, WebSocketKind};
+#[cfg(feature = "worker")]
+use crate::worker::WorkerWebSocket;
+#[cfg(any(feature = "one-to-one", feature = "one-to-many"))]
+use crate::ConnectionType;
+
+type OnMessage = Rc<RefCell<dyn FnMut(&[u8])>>;
+type OnClose = Rc<RefCell<dyn FnMut()>>;
+
+/// Callbacks of the network manager of a single session.
+#[derive(Default)]
+struct SessionHandlers {
+    on_message: Option<OnMessage>,
+    on_close: Option<OnClose>,
+}
+
+struct SignalingClientInner {
+    connection: SignalingConnection,
+    signaling_server_url: Option<String>,
+    /// Kind of `WebSocket` one-to-one network managers reconnect with
+    websocket_kind: WebSocketKind,
+    is_open: Cell<bool>,
+    /// Called once the connection opens
+    on_open: RefCell<Vec<Box<dyn FnOnce()>>>,
+    sessions: RefCell<HashMap<SessionId, SessionHandlers>>,
+}
+
+/// Connection with signaling server shared by network managers of several sessions.
+///
+/// Signal messages of every session are sent through the same `WebSocket`,
+/// incoming ones are passed to the network manager of the session they're about.
+/// All network managers created from a single client have to match the signaling server endpoint
+/// it's connected to, e.g. `/one-to-one` serves only [`SignalingClient::one_to_one`] ones,
+/// and there can be only one of them for each session.
+///
+/// Closing a network manager only stops passing messages to it,
+/// signaling server keeps it in its session until [`SignalingClient::close`] closes the connection.
+/// Once the connection is lost, one-to-one network managers reconnect on connections of their own.
+///
+/// This class is a pointer to the underlying resource and can be cloned freely.
+///
+/// # Example
+///
+/// ```no_run
+/// use wasm_peers::{ConnectionType, SessionId, SignalingClient};
+/// use web_sys::console;
+///
+/// const SIGNALING_SERVER_URL: &str = "ws://0.0.0.0:9001/one-to-one";
+///
+/// let signaling = SignalingClient::new(SIGNALING_SERVER_URL)
+///     .expect("failed to connect to signaling server");
+/// for session_id in [SessionId::new(1), SessionId::new(2)] {
+///     let mut peer = signaling
+///         .one_to_one(session_id, ConnectionType::Local)
+///         .expect("failed to create peer connection");
+///     peer.start(
+///         move || console::log_1(&format!("connected in {}", session_id).into()),
+///         |message: String| console::log_1(&message.into()),
+///     )
+///     .expect("failed to start peer");
+/// }
+/// ```
+#[derive(Clone)]
+pub struct SignalingClient {
+    inner: Rc<SignalingClientInner>,
+}
+
+impl Debug for SignalingClient {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SignalingClient")
+            .field("signaling_url", &self.inner.signaling_server_url)
+            .field("websocket_kind", &self.inner.websocket_kind)
+            .field("is_open", &self.inner.is_open.get())
+            .field(
+                "sessions",
+                &self.inner.sessions.borrow().keys().collect::<Vec<_>>(),
+            )
+            .finish_non_exhaustive()
+    }
+}
+
+impl SignalingClient {
+    /// Opens a connection with signaling server at `signaling_server_url`.
+    ///
+    /// # Errors
+    /// This function errs if opening a `WebSocket` connection to URL provided by `signaling_server_url` fails.
+    pub fn new(signaling_server_url: &str) -> crate::Result<Self> {
+        Self::with_websocket(signaling_server_url, WebSocketKind::Browser)
+    }
+
+    /// Same as [`SignalingClient::new`], but opens `WebSocket` of `kind`, e.g. `WebSocketKind::Node` in Node.js.
+    /// One-to-one network managers created from the client reconnect with the same kind.
+    ///
+    /// # Errors
+    /// This function errs if opening a `WebSocket` connection to URL provided by `signaling_server_url` fails.
+    pub fn with_websocket(signaling_server_url: &str, kind: WebSocketKind) -> crate::Result<Self> {
+        Ok(Self::with_connection(
+            SignalingConnection::open_with(signaling_server_url, kind)?,
+            Some(signaling_server_url.to_owned()),
+            kind,
+        ))
+    }
+
+    /// Same as [`SignalingClient::new`], but resolves only once connection with signaling server is open.
+    ///
+    /// # Errors
+    /// This function errs if opening a `WebSocket` connection to URL provided by `signaling_server_url` fails.
+    pub async fn new_async(signaling_server_url: &str) -> crate::Result<Self> {
+        let connection = SignalingConnection::open(signaling_server_url)?;
+        // waiting replaces `onopen` handler of the websocket, so it's done before the client sets its own
+        connection.wait_for_open().await?;
+        Ok(Self::with_connection(
+            connection,
+            Some(signaling_server_url.to_owned()),
+            WebSocketKind::Browser,
+        ))
+    }
+
+    /// Same as [`SignalingClient::new`], but the connection is opened by `worker`,
+    /// which has to call [`serve_signaling`](crate::worker::serve_signaling) first.
+    ///
+    /// # Errors
+    /// This function errs if posting the request to open the connection to `worker` fails.
+    #[cfg(feature = "worker")]
+    pub fn in_worker(worker: &Worker, signaling_server_url: &str) -> crate::Result<Self> {
+        let websocket = WorkerWebSocket::open(worker, signaling_server_url).map_err(|err| {
+            anyhow!(
+                "failed to create connection with signaling server on {} in worker: {:?}",
+                signaling_server_url,
+                err
+            )
+        })?;
+        Ok(Self::with_connection(
+            SignalingConnection::WebSocket(Rc::new(websocket)),
+            Some(signaling_server_url.to_owned()),
+            WebSocketKind::Browser,
+        ))
+    }
+
+    /// Same as [`SignalingClient::new`], but signal messages are exchanged through `signaling`,
+    /// which handles them like signaling server endpoint of `topology` would.
+    #[cfg(feature = "test-utils")]
+    #[must_use]
+    pub fn with_loopback(signaling: &LoopbackSignaling, topology: Topology) -> Self {
+        Self::with_connection(
+            SignalingConnection::Loopback(signaling.connect(topology)),
+            None,
+            WebSocketKind::Browser,
+        )
+    }
+
+    fn with_connection(
+        connection: SignalingConnection,
+        signaling_server_url: Option<String>,
+        websocket_kind: WebSocketKind,
+    ) -> Self {
+        let client = Self {
+            inner: Rc::new(SignalingClientInner {
+                connection,
+                signaling_server_url,
+                websocket_kind,
+                is_open: Cell::new(false),
+                on_open: RefCell::new(Vec::new()),
+                sessions: RefCell::new(HashMap::new()),
+            }),
+        };
+        let shared = &client.inner.connection;
+        let on_open_client = client.clone();
+        shared.set_on_open(move || on_open_client.opened());
+        let on_message_client = client.clone();
+        shared.set_on_message(move |message| on_message_client.dispatch(message));
+        let on_close_client = client.clone();
+        shared.set_on_close(move || on_close_client.connection_lost());
+        client
+    }
+
+    /// Creates one-to-one network manager signaling through this connection.
+    ///
+    /// # Errors
+    /// This function errs if creating a peer connection fails.
+    #[cfg(feature = "one-to-one")]
+    pub fn one_to_one(
+        &self,
+        session_id: SessionId,
+        connection_type: ConnectionType,
+    ) -> crate::Result<crate::one_to_one::NetworkManager> {
+        Ok(crate::one_to_one::NetworkManager::with_signaling(
+            self.session(session_id),
+            self.signaling_url(),
+            session_id,
+            connection_type,
+        )?
+        .with_websocket_kind(self.inner.websocket_kind))
+    }
+
+    /// Creates [`MiniServer`] signaling through this connection.
+    #[cfg(feature = "one-to-many")]
+    #[must_use]
+    pub fn mini_server(
+        &self,
+        session_id: SessionId,
+        connection_type: ConnectionType,
+    ) -> MiniServer {
+        MiniServer::with_signaling(
+            self.session(session_id),
+            self.signaling_url(),
+            session_id,
+            connection_type,
+        )
+    }
+
+    /// Creates [`MiniClient`] signaling through this connection.
+    #[cfg(feature = "one-to-many")]
+    #[must_use]
+    pub fn mini_client(
+        &self,
+        session_id: SessionId,
+        connection_type: ConnectionType,
+    ) -> MiniClient {
+        MiniClient::with_signaling(
+            self.session(session_id),
+            self.signaling_url(),
+            session_id,
+            connection_type,
+        )
+    }
+
+    /// Creates many-to-many network manager signaling through this connection.
+    #[cfg(feature = "many-to-many")]
+    #[must_use]
+    pub fn many_to_many(
+        &self,
+        session_id: SessionId,
+        connection_type: ConnectionType,
+    ) -> many_to_many::NetworkManager {
+        many_to_many::NetworkManager::with_signaling(
+            self.session(session_id),
+            self.signaling_url(),
+            session_id,
+            connection_type,
+        )
+    }
+
+    /// Address of the signaling server, `None` with loopback signaling.
+    #[must_use]
+    pub fn signaling_url(&self) -> Option<String> {
+        self.inner.signaling_server_url.clone()
+    }
+
+    /// Closes the connection, which leaves sessions of all network managers created from it.
+    pub fn close(&self) {
+        self.inner.sessions.borrow_mut().clear();
+        self.inner.connection.close();
+    }
+
+    fn session(&self, session_id: SessionId) -> SignalingConnection {
+        SignalingConnection::Shared(SharedSession {
+            client: self.clone(),
+            session_id,
+        })
+    }
+
+    pub(crate) fn send(&self, message: &[u8]) -> crate::Result<()> {
+        self.inner.connection.send(message)
+    }
+
+    pub(crate) fn is_open(&self) -> bool {
+        self.inner.is_open.get()
+    }
+
+    pub(crate) fn ready_state_name(&self) -> &'static str {
+        self.inner.connection.ready_state_name()
+    }
+
+    /// Calls `on_open` once connection is open, right away if it already is.
+    pub(crate) fn set_on_open(&self, mut on_open: impl FnMut() + 'static) {
+        if self.is_open() {
+            on_open();
+        } else {
+            self.inner.on_open.borrow_mut().push(Box::new(on_open));
+        }
+    }
+
+    pub(crate) fn set_on_message(
+        &self,
+        session_id: SessionId,
+        on_message: impl FnMut(&[u8]) + 'static,
+    ) {
+        self.inner
+            .sessions
+            .borrow_mut()
+            .entry(session_id)
+            .or_default()
+            .on_message = Some(Rc::new(RefCell::new(on_message)));
+    }
+
+    pub(crate) fn set_on_close(&self, session_id: SessionId, on_close: impl FnMut() + 'static) {
+        self.inner
+            .sessions
+            .borrow_mut()
+            .entry(session_id)
+            .or_default()
+            .on_close = Some(Rc::new(RefCell::new(on_close)));
+    }
+
+    /// Stops passing messages of `session_id` on, the connection stays open for other sessions.
+    pub(crate) fn remove_session(&self, session_id: SessionId) {
+        self.inner.sessions.borrow_mut().remove(&session_id);
+    }
+
+    fn opened(&self) {
+        self.inner.is_open.set(true);
+        let pending = mem::take(&mut *self.inner.on_open.borrow_mut());
+        for on_open in pending {
+            on_open();
+        }
+    }
+
+    /// Passes `message` to the network manager of its session,
+    /// messages concerning the whole connection, e.g. `Redirect`, to all of them.
+    fn dispatch(&self, message: &[u8]) {
+        let recipients: Vec<_> = {
+            let sessions = self.inner.sessions.borrow();
+            match message_session_id(message) {
+                Some(session_id) => sessions
+                    .get(&session_id)
+                    .and_then(|handlers| handlers.on_message.clone())
+                    .into_iter()
+                    .collect(),
+                None => sessions
+                    .values()
+                    .filter_map(|handlers| handlers.on_message.clone())
+                    .collect(),
+            }
+        };
+        if recipients.is_empty() {
+            debug!("no network manager for signal message, dropping it");
+        }
+        for on_message in recipients {
+            (on_message.borrow_mut())(message);
+        }
+    }
+
+    fn connection_lost(&self) {
+        self.inner.is_open.set(false);
+        let recipients: Vec<_> = self
+            .inner
+            .sessions
+            .borrow()
+            .values()
+            .filter_map(|handlers| handlers.on_close.clone())
+            .collect();
+        for on_close in recipients {
+            (on_close.borrow_mut())();
+        }
+    }
+}
+
+/// Session that serialized signal message of any topology is about,
+/// `None` if it concerns the whole connection or can't be deserialized.
+fn message_session_id(message: &[u8]) -> Option<SessionId> {
+    if let Ok(message) = rmp_serde::from_slice::<one_to_many::SignalMessage>(message) {
+        return one_to_many_session_id(&message);
+    }
+    rmp_serde::from_slice::<one_to_one::SignalMessage>(message)
+        .ok()
+        .and_then(|message| one_to_one_session_id(&message))
+}
+
+const fn one_to_one_session_id(message: &one_to_one::SignalMessage) -> Option<SessionId> {
+    use one_to_one::SignalMessage;
+
+    match *message {
+        SignalMessage::SessionJoin(session_id)
+        | SignalMessage::SessionReady(session_id, _)
+        | SignalMessage::SdpOffer(session_id, _)
+        | SignalMessage::SdpAnswer(session_id, _)
+        | SignalMessage::IceCandidate(session_id, _)
+        | SignalMessage::Error(session_id, _)
+        | SignalMessage::SessionJoinWithIntent(session_id, _)
+        | SignalMessage::SessionJoinError(session_id, _) => Some(session_id),
+        SignalMessage::Redirect(_)
+        | SignalMessage::SessionListRequest
+        | SignalMessage::SessionListResponse(_)
+        | SignalMessage::ProtocolVersion { .. }
+        | SignalMessage::ProtocolVersionMismatch { .. } => None,
+    }
+}
+
+const fn one_to_many_session_id(message: &one_to_many::SignalMessage) -> Option<SessionId> {
+    use one_to_many::SignalMessage;
+
+    match *message {
+        SignalMessage::SessionJoin(session_id, _)
+        | SignalMessage::SessionReady(session_id, _)
+        | SignalMessage::SdpOffer(session_id, _, _)
+        | SignalMessage::SdpAnswer(session_id, _, _)
+        | SignalMessage::IceCandidate(session_id, _, _)
+        | SignalMessage::Error(session_id, _, _)
+        | SignalMessage::SessionLeave(session_id, _)
+        | SignalMessage::SessionJoinAs(session_id, _)
+        | SignalMessage::ClientIdentity(session_id, _, _)
+        | SignalMessage::SessionJoinWithIntent(session_id, _, _)
+        | SignalMessage::SessionJoinHost(session_id, _, _)
+        | SignalMessage::SessionJoinError(session_id, _)
+        | SignalMessage::SessionFullNotice(session_id, _)
+        | SignalMessage::SessionMembers(session_id, _, _) => Some(session_id),
+        SignalMessage::SessionListRequest
+        | SignalMessage::SessionListResponse(_)
+        | SignalMessage::ProtocolVersion { .. }
+        | SignalMessage::ProtocolVersionMismatch { .. } => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use wasm_bindgen_test::wasm_bindgen_test;
+    use wasm_peers_protocol::UserId;
+
+    use super::*;
+
+    #[wasm_bindgen_test]
+    fn test_messages_are_routed_by_their_session() {
+        let session_id = SessionId::new(u128::MAX);
+        let one_to_one = rmp_serde::to_vec(&one_to_one::SignalMessage::SdpOffer(
+            session_id,
+            "offer".to_owned(),
+        ))
+        .expect("failed to serialize message");
+        assert_eq!(message_session_id(&one_to_one), Some(session_id));
+        let one_to_many = rmp_serde::to_vec(&one_to_many::SignalMessage::SessionLeave(
+            session_id,
+            UserId::new(2),
+        ))
+        .expect("failed to serialize message");
+        assert_eq!(message_session_id(&one_to_many), Some(session_id));
+        let redirect = rmp_serde::to_vec(&one_to_one::SignalMessage::Redirect(
+            "ws://localhost:9001/one-to-one".to_owned(),
+        ))
+        .expect("failed to serialize message");
+        assert_eq!(message_session_id(&redirect), None);
+    }
+}
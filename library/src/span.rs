@@ -0,0 +1,16 @@
+//! Spans correlating log lines with the session and peer they belong to, see `tracing` feature.
+
+/// Wraps a callback of JavaScript event, so that it runs within the span current at the time of wrapping,
+/// e.g. the span of network manager's `start` or of handling signal message from a given peer.
+///
+/// Without `tracing` feature callback is returned as is.
+pub fn in_current_span<A>(callback: impl FnMut(A)) -> impl FnMut(A) {
+    #[cfg(feature = "tracing")]
+    {
+        let span = tracing::Span::current();
+        let mut callback = callback;
+        move |arg| span.in_scope(|| callback(arg))
+    }
+    #[cfg(not(feature = "tracing"))]
+    callback
+}
@@ -0,0 +1,296 @@
+/*!
+Value kept in sync between all peers of a many-to-many session.
+
+[`SyncedValue`] sends the whole value to all connected peers every time it's [set](SyncedValue::set)
+and merges values received from them with [`Merge::merge`].
+By default the value written last wins, writes being ordered by their Lamport timestamps,
+so that all peers end up with the same value even when they write it at the same time.
+Types can override [`Merge::merge`] to combine concurrent writes instead, e.g. by taking union of sets.
+
+A peer joining the session asks the first peer it connects to for the current value,
+and each following one until any of them answers.
+
+It's not a CRDT library, the whole value is sent on every write, which suits small documents and settings.
+All peers should start with the same initial value, and use the network manager only for the synced value.
+
+# Example
+
+```no_run
+use serde::{Deserialize, Serialize};
+use wasm_peers::many_to_many::NetworkManager;
+use wasm_peers::synced::{Merge, SyncedValue};
+use wasm_peers::{ConnectionType, SessionId};
+use web_sys::console;
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+struct Document {
+    text: String,
+}
+
+// the last writer wins
+impl Merge for Document {}
+
+let network_manager = NetworkManager::new(
+    "ws://0.0.0.0:9001/many-to-many",
+    SessionId::new(1),
+    ConnectionType::Local,
+)
+.expect("failed to connect to signaling server");
+let mut document = SyncedValue::new(network_manager, Document::default());
+document.set_on_change(|document: Document| console::log_1(&document.text.into()));
+document.start().unwrap();
+document
+    .set(Document {
+        text: "hello".to_owned(),
+    })
+    .unwrap();
+```
+*/
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use log::{debug, error};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::many_to_many::NetworkManager;
+use crate::utils::random_u128;
+use crate::PeerEvent;
+
+/// Combines value received from another peer with the local one.
+pub trait Merge: Sized {
+    /// Merges `remote` value into this one, `remote_is_later` if it was written after this one.
+    /// All peers have to end up with the same value regardless of the order in which they merge values,
+    /// and merging the same value twice mustn't change it.
+    ///
+    /// By default `remote` replaces this value if it was written later, i.e. the last writer wins.
+    fn merge(&mut self, remote: Self, remote_is_later: bool) {
+        if remote_is_later {
+            *self = remote;
+        }
+    }
+}
+
+/// Lamport timestamp of a write, with ties broken by random id of the writing peer,
+/// so that all peers order writes the same way.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+struct Stamp {
+    timestamp: u64,
+    writer: u64,
+}
+
+/// Messages exchanged by [`SyncedValue`]s of the peers.
+#[derive(Debug, Serialize, Deserialize)]
+enum SyncMessage<T> {
+    /// Asks for the current value, sent by peers joining the session
+    StateRequest,
+    /// Current value, sent after each write and in answer to [`SyncMessage::StateRequest`]
+    State(Stamp, T),
+}
+
+type OnChange<T> = Rc<RefCell<dyn FnMut(T)>>;
+
+struct SyncedValueInner<T> {
+    value: T,
+    /// Latest write merged into `value`, initial value isn't written by anyone
+    stamp: Stamp,
+    /// Identifies writes of this peer
+    writer: u64,
+    /// Whether some peer already sent its value, so that there's no need to ask the next ones
+    received: bool,
+    on_change: Option<OnChange<T>>,
+}
+
+impl<T: Merge> SyncedValueInner<T> {
+    fn new(value: T) -> Self {
+        Self {
+            value,
+            stamp: Stamp::default(),
+            writer: u64::try_from(random_u128() >> 64).unwrap_or(0),
+            received: false,
+            on_change: None,
+        }
+    }
+
+    /// Replaces the value with one written later than everything merged so far.
+    fn write(&mut self, value: T) -> Stamp {
+        self.value = value;
+        self.stamp = Stamp {
+            timestamp: self.stamp.timestamp.saturating_add(1),
+            writer: self.writer,
+        };
+        self.stamp
+    }
+
+    fn merge(&mut self, stamp: Stamp, value: T) {
+        self.value.merge(value, stamp > self.stamp);
+        self.stamp = self.stamp.max(stamp);
+        self.received = true;
+    }
+}
+
+/// Value kept in sync between all peers of a many-to-many session.
+/// See [module documentation](self) for details.
+pub struct SyncedValue<T> {
+    network_manager: NetworkManager,
+    inner: Rc<RefCell<SyncedValueInner<T>>>,
+}
+
+impl<T> Clone for SyncedValue<T> {
+    fn clone(&self) -> Self {
+        Self {
+            network_manager: self.network_manager.clone(),
+            inner: Rc::clone(&self.inner),
+        }
+    }
+}
+
+impl<T: Serialize + DeserializeOwned + Merge + Clone + 'static> SyncedValue<T> {
+    /// Wraps `network_manager`, which shouldn't be started yet, starting with `initial` value.
+    pub fn new(network_manager: NetworkManager, initial: T) -> Self {
+        Self {
+            network_manager,
+            inner: Rc::new(RefCell::new(SyncedValueInner::new(initial))),
+        }
+    }
+
+    /// Starts the underlying network manager, exchanging the value with peers once connections open.
+    ///
+    /// # Errors
+    /// Errs with [`AlreadyStartedError`](crate::AlreadyStartedError) if it was already started.
+    pub fn start(&mut self) -> crate::Result<()> {
+        let network_manager = self.network_manager.clone();
+        let inner = Rc::clone(&self.inner);
+        self.network_manager
+            .start_with_event_handler(move |event: PeerEvent<SyncMessage<T>>| match event {
+                PeerEvent::Opened(user_id) => {
+                    if inner.borrow().received {
+                        return;
+                    }
+                    let request: SyncMessage<T> = SyncMessage::StateRequest;
+                    if let Err(err) = network_manager.send_message(user_id, &request) {
+                        error!("failed to ask {} for synced value: {}", user_id, err);
+                    }
+                }
+                PeerEvent::Message(user_id, SyncMessage::StateRequest) => {
+                    let inner = inner.borrow();
+                    let state = SyncMessage::State(inner.stamp, &inner.value);
+                    if let Err(err) = network_manager.send_message(user_id, &state) {
+                        error!("failed to send synced value to {}: {}", user_id, err);
+                    }
+                }
+                PeerEvent::Message(user_id, SyncMessage::State(stamp, value)) => {
+                    debug!(
+                        "merging synced value written at {:?} from {}",
+                        stamp, user_id
+                    );
+                    // clone the callback out, so that it can freely use the synced value
+                    let (value, on_change) = {
+                        let mut inner = inner.borrow_mut();
+                        inner.merge(stamp, value);
+                        (inner.value.clone(), inner.on_change.clone())
+                    };
+                    if let Some(on_change) = on_change {
+                        (on_change.borrow_mut())(value);
+                    }
+                }
+                _ => {}
+            })
+    }
+
+    /// Current value, including all writes of other peers merged so far.
+    #[must_use]
+    pub fn get(&self) -> T {
+        self.inner.borrow().value.clone()
+    }
+
+    /// Replaces the value and sends it to all connected peers.
+    ///
+    /// # Errors
+    /// This function errs if serializing the value fails, it's replaced locally regardless.
+    pub fn set(&self, value: T) -> crate::Result<()> {
+        let mut inner = self.inner.borrow_mut();
+        let stamp = inner.write(value);
+        self.network_manager
+            .send_message_to_all(&SyncMessage::State(stamp, &inner.value))
+    }
+
+    /// Registers a callback called with the merged value every time a value from another peer is merged,
+    /// writes with [`SyncedValue::set`] don't call it.
+    pub fn set_on_change(&self, on_change: impl FnMut(T) + 'static) {
+        self.inner.borrow_mut().on_change = Some(Rc::new(RefCell::new(on_change)));
+    }
+
+    /// Underlying network manager, e.g. to check connected peers.
+    #[must_use]
+    pub fn network_manager(&self) -> &NetworkManager {
+        &self.network_manager
+    }
+
+    /// Closes the underlying network manager.
+    pub fn close(&self) {
+        self.network_manager.close();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeSet;
+
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+
+    impl Merge for u32 {}
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    struct Union(BTreeSet<u32>);
+
+    impl Merge for Union {
+        fn merge(&mut self, remote: Self, _remote_is_later: bool) {
+            self.0.extend(remote.0);
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn test_later_write_wins_regardless_of_merge_order() {
+        let mut first = SyncedValueInner::new(0);
+        let mut second = SyncedValueInner::new(0);
+        let first_write = first.write(1);
+        let second_write = second.write(2);
+        let later = first_write.max(second_write);
+        first.merge(second_write, 2);
+        second.merge(first_write, 1);
+        assert_eq!(first.value, second.value);
+        assert_eq!(first.stamp, later);
+        assert_eq!(second.stamp, later);
+
+        let next_write = first.write(3);
+        assert!(next_write > later);
+        second.merge(next_write, 3);
+        second.merge(next_write, 3);
+        second.merge(later, 1);
+        assert_eq!(second.value, 3);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_initial_value_is_kept_until_written() {
+        let mut value = SyncedValueInner::new(1);
+        value.merge(Stamp::default(), 2);
+        assert_eq!(value.value, 1);
+        assert!(value.received);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_custom_merge_combines_concurrent_writes() {
+        let mut first = SyncedValueInner::new(Union(BTreeSet::new()));
+        let mut second = SyncedValueInner::new(Union(BTreeSet::new()));
+        let first_write = first.write(Union([1].into_iter().collect()));
+        let second_write = second.write(Union([2].into_iter().collect()));
+        first.merge(second_write, second.value.clone());
+        second.merge(first_write, Union([1].into_iter().collect()));
+        assert_eq!(first.value, Union([1, 2].into_iter().collect()));
+        assert_eq!(first.value, second.value);
+    }
+}
@@ -0,0 +1,681 @@
+/*!
+In-memory replacement for signaling server, available with `test-utils` feature.
+
+[`LoopbackSignaling`] passes signal messages between network managers created with `with_loopback`
+constructors, following the same rules as the modules of
+[signaling server](https://docs.rs/wasm-peers-signaling-server/latest/wasm_peers_signaling_server/),
+so tests can connect peers within a single page without a running server.
+The only difference is that in one-to-one sessions the first peer to join is always the host.
+
+# Example
+
+```no_run
+use wasm_peers::one_to_one::NetworkManager;
+use wasm_peers::test_utils::LoopbackSignaling;
+use wasm_peers::{ConnectionType, SessionId};
+
+let signaling = LoopbackSignaling::new();
+let peer_generator = || {
+    let mut peer =
+        NetworkManager::with_loopback(&signaling, SessionId::new(1), ConnectionType::Local)
+            .expect("failed to create peer connection");
+    let peer_clone = peer.clone();
+    peer.start(
+        move || peer_clone.send_message("ping!").expect("failed to send message"),
+        |message: String| assert_eq!(message, "ping!"),
+    )
+    .expect("failed to start peer");
+};
+peer_generator();
+peer_generator();
+```
+*/
+
+use std::cell::RefCell;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::{Debug, Formatter};
+use std::iter;
+use std::rc::Rc;
+
+use anyhow::anyhow;
+use js_sys::Promise;
+use log::{debug, error};
+use serde::Serialize;
+use wasm_bindgen::JsValue;
+use wasm_bindgen_futures::JsFuture;
+use wasm_peers_protocol::{
+    one_to_many, one_to_one, JoinError, JoinIntent, SessionId, SessionInfo, StableId, TopologyType,
+    UserId, PROTOCOL_MAJOR_VERSION, PROTOCOL_MINOR_VERSION,
+};
+
+/// Signaling server module that would handle the connection, decided by the type of network manager,
+/// or given to [`SignalingClient::with_loopback`](crate::SignalingClient::with_loopback).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Topology {
+    /// Pairs of [`one_to_one::NetworkManager`](crate::one_to_one::NetworkManager)
+    OneToOne,
+    /// [`MiniServer`](crate::one_to_many::MiniServer) with its [`MiniClient`](crate::one_to_many::MiniClient)s
+    OneToMany,
+    /// [`many_to_many::NetworkManager`](crate::many_to_many::NetworkManager)s
+    ManyToMany,
+}
+
+type OnMessage = Rc<RefCell<dyn FnMut(&[u8])>>;
+
+struct User {
+    topology: Topology,
+    on_message: Option<OnMessage>,
+    /// Messages received before `on_message` was set
+    pending: Vec<Vec<u8>>,
+}
+
+impl Debug for User {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("User")
+            .field("topology", &self.topology)
+            .field("pending", &self.pending)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Default)]
+struct OneToOneSession {
+    first: Option<UserId>,
+    second: Option<UserId>,
+}
+
+#[derive(Debug, Default)]
+struct OneToManySession {
+    host: Option<UserId>,
+    users: HashSet<UserId>,
+    pending_users: VecDeque<UserId>,
+    stable_ids: HashMap<UserId, StableId>,
+    /// Set once host turned a client away, until one of the clients leaves
+    full: bool,
+}
+
+#[derive(Debug, Default)]
+struct LoopbackSignalingInner {
+    next_user_id: u64,
+    users: HashMap<UserId, User>,
+    one_to_one_sessions: HashMap<SessionId, OneToOneSession>,
+    one_to_many_sessions: HashMap<SessionId, OneToManySession>,
+    many_to_many_sessions: HashMap<SessionId, HashSet<UserId>>,
+}
+
+/// Signaling server running in memory, shared by all network managers connected to it.
+///
+/// This class is a pointer to the underlying resource and can be cloned freely.
+#[derive(Debug, Clone, Default)]
+pub struct LoopbackSignaling {
+    inner: Rc<RefCell<LoopbackSignalingInner>>,
+}
+
+impl LoopbackSignaling {
+    /// Creates a signaling server without any sessions.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn connect(&self, topology: Topology) -> LoopbackConnection {
+        let mut inner = self.inner.borrow_mut();
+        inner.next_user_id = inner.next_user_id.wrapping_add(1);
+        let user_id = UserId::new(inner.next_user_id);
+        inner.users.insert(
+            user_id,
+            User {
+                topology,
+                on_message: None,
+                pending: Vec::new(),
+            },
+        );
+        LoopbackConnection {
+            signaling: self.clone(),
+            user_id,
+        }
+    }
+
+    /// Delivers `message` asynchronously, like it would be sent through the network.
+    fn deliver(&self, recipient_id: UserId, message: Vec<u8>) {
+        let signaling = self.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            let on_message = {
+                let mut inner = signaling.inner.borrow_mut();
+                let Some(recipient) = inner.users.get_mut(&recipient_id) else {
+                    debug!(
+                        "recipient {:?} disconnected, dropping message",
+                        recipient_id
+                    );
+                    return;
+                };
+                let Some(ref on_message) = recipient.on_message else {
+                    recipient.pending.push(message);
+                    return;
+                };
+                Rc::clone(on_message)
+            };
+            (on_message.borrow_mut())(&message);
+        });
+    }
+
+    fn handle_message(&self, sender_id: UserId, message: &[u8]) -> crate::Result<()> {
+        let responses = {
+            let mut inner = self.inner.borrow_mut();
+            let topology = inner
+                .users
+                .get(&sender_id)
+                .ok_or_else(|| anyhow!("user {:?} is not connected", sender_id))?
+                .topology;
+            match topology {
+                Topology::OneToOne => serialize_all(
+                    inner.one_to_one_message(sender_id, rmp_serde::from_slice(message)?)?,
+                )?,
+                Topology::OneToMany => serialize_all(
+                    inner.one_to_many_message(sender_id, rmp_serde::from_slice(message)?),
+                )?,
+                Topology::ManyToMany => serialize_all(
+                    inner.many_to_many_message(sender_id, rmp_serde::from_slice(message)?),
+                )?,
+            }
+        };
+        for (recipient_id, response) in responses {
+            self.deliver(recipient_id, response);
+        }
+        Ok(())
+    }
+}
+
+fn serialize_all<M: Serialize>(
+    responses: Vec<(UserId, M)>,
+) -> crate::Result<Vec<(UserId, Vec<u8>)>> {
+    responses
+        .into_iter()
+        .map(|(recipient_id, response)| Ok((recipient_id, rmp_serde::to_vec(&response)?)))
+        .collect()
+}
+
+impl LoopbackSignalingInner {
+    fn one_to_one_message(
+        &mut self,
+        sender_id: UserId,
+        message: one_to_one::SignalMessage,
+    ) -> crate::Result<Vec<(UserId, one_to_one::SignalMessage)>> {
+        use one_to_one::SignalMessage;
+
+        debug!("message received from user {:?}: {:?}", sender_id, message);
+        let message = match message {
+            SignalMessage::SessionJoinWithIntent(session_id, intent) => {
+                if let Some(error) = join_error(&self.one_to_one_sessions, session_id, intent) {
+                    return Ok(vec![(
+                        sender_id,
+                        SignalMessage::SessionJoinError(session_id, error),
+                    )]);
+                }
+                SignalMessage::SessionJoin(session_id)
+            }
+            message => message,
+        };
+        let session_id = match message {
+            SignalMessage::SessionJoin(session_id) => {
+                return Ok(match self.one_to_one_sessions.entry(session_id) {
+                    Entry::Vacant(entry) => {
+                        entry.insert(OneToOneSession {
+                            first: Some(sender_id),
+                            second: None,
+                        });
+                        Vec::new()
+                    }
+                    // connection is already in this session, it can't be paired with itself
+                    Entry::Occupied(entry) if entry.get().first == Some(sender_id) => {
+                        let error = "already joined this session".to_owned();
+                        vec![(sender_id, SignalMessage::Error(session_id, error))]
+                    }
+                    Entry::Occupied(mut entry) => {
+                        entry.get_mut().second = Some(sender_id);
+                        entry.get().first.map_or_else(Vec::new, |first_id| {
+                            vec![
+                                (first_id, SignalMessage::SessionReady(session_id, true)),
+                                (sender_id, SignalMessage::SessionReady(session_id, false)),
+                            ]
+                        })
+                    }
+                });
+            }
+            SignalMessage::ProtocolVersion { .. } => {
+                let version = SignalMessage::ProtocolVersion {
+                    major: PROTOCOL_MAJOR_VERSION,
+                    minor: PROTOCOL_MINOR_VERSION,
+                };
+                return Ok(vec![(sender_id, version)]);
+            }
+            SignalMessage::SessionListRequest => {
+                let sessions = self
+                    .one_to_one_sessions
+                    .iter()
+                    .map(|(&session_id, session)| {
+                        let peer_count = [session.first, session.second].iter().flatten().count();
+                        (session_id, peer_count)
+                    });
+                let sessions = session_list(sessions, TopologyType::OneToOne);
+                return Ok(vec![(
+                    sender_id,
+                    SignalMessage::SessionListResponse(sessions),
+                )]);
+            }
+            SignalMessage::SdpOffer(session_id, _)
+            | SignalMessage::SdpAnswer(session_id, _)
+            | SignalMessage::IceCandidate(session_id, _) => session_id,
+            other => {
+                error!("received unexpected signal message: {:?}", other);
+                return Ok(Vec::new());
+            }
+        };
+        // pass the message to the other user in session without changing anything
+        let session = self
+            .one_to_one_sessions
+            .get(&session_id)
+            .ok_or_else(|| anyhow!("no such session: {:?}", session_id))?;
+        let recipient_id = if Some(sender_id) == session.first {
+            session.second
+        } else {
+            session.first
+        }
+        .ok_or_else(|| anyhow!("missing second user in session: {:?}", session_id))?;
+        Ok(vec![(recipient_id, message)])
+    }
+
+    fn one_to_many_message(
+        &mut self,
+        sender_id: UserId,
+        message: one_to_many::SignalMessage,
+    ) -> Vec<(UserId, one_to_many::SignalMessage)> {
+        use one_to_many::SignalMessage;
+
+        debug!("message received from user {:?}: {:?}", sender_id, message);
+        match message {
+            SignalMessage::SessionJoinWithIntent(session_id, is_host, intent) => {
+                match join_error(&self.one_to_many_sessions, session_id, intent) {
+                    Some(error) => vec![(
+                        sender_id,
+                        SignalMessage::SessionJoinError(session_id, error),
+                    )],
+                    None => self.one_to_many_join(sender_id, session_id, is_host, None),
+                }
+            }
+            // loopback doesn't support multiple hosts, so only the first one is connected
+            SignalMessage::SessionJoinHost(session_id, _, intent) => {
+                match join_error(&self.one_to_many_sessions, session_id, intent) {
+                    Some(error) => vec![(
+                        sender_id,
+                        SignalMessage::SessionJoinError(session_id, error),
+                    )],
+                    None => self.one_to_many_join(sender_id, session_id, true, None),
+                }
+            }
+            SignalMessage::SessionJoin(session_id, is_host) => {
+                self.one_to_many_join(sender_id, session_id, is_host, None)
+            }
+            SignalMessage::SessionJoinAs(session_id, stable_id) => {
+                self.one_to_many_join(sender_id, session_id, false, Some(stable_id))
+            }
+            SignalMessage::SessionListRequest => {
+                let sessions = self
+                    .one_to_many_sessions
+                    .iter()
+                    .map(|(&session_id, session)| {
+                        let host_count = usize::from(session.host.is_some());
+                        (session_id, host_count.saturating_add(session.users.len()))
+                    });
+                let sessions = session_list(sessions, TopologyType::OneToMany);
+                vec![(sender_id, SignalMessage::SessionListResponse(sessions))]
+            }
+            SignalMessage::SessionFullNotice(session_id, client_id) => {
+                self.one_to_many_full(sender_id, session_id, client_id)
+            }
+            SignalMessage::ProtocolVersion { .. } => vec![(
+                sender_id,
+                SignalMessage::ProtocolVersion {
+                    major: PROTOCOL_MAJOR_VERSION,
+                    minor: PROTOCOL_MINOR_VERSION,
+                },
+            )],
+            SignalMessage::SessionReady(..) | SignalMessage::Error(..) => Vec::new(),
+            message => relay_one_to_many(sender_id, message).into_iter().collect(),
+        }
+    }
+
+    fn one_to_many_join(
+        &mut self,
+        sender_id: UserId,
+        session_id: SessionId,
+        is_host: bool,
+        stable_id: Option<StableId>,
+    ) -> Vec<(UserId, one_to_many::SignalMessage)> {
+        use one_to_many::SignalMessage;
+
+        let session = self.one_to_many_sessions.entry(session_id).or_default();
+        if !is_host && session.full {
+            let error = "session is full".to_owned();
+            return vec![(
+                sender_id,
+                SignalMessage::Error(session_id, sender_id, error),
+            )];
+        }
+        if let Some(stable_id) = stable_id {
+            if session.stable_ids.values().any(|&other| other == stable_id) {
+                let error = "stable id is used by another client".to_owned();
+                return vec![(
+                    sender_id,
+                    SignalMessage::Error(session_id, sender_id, error),
+                )];
+            }
+            session.stable_ids.insert(sender_id, stable_id);
+        }
+        if is_host && session.host.is_none() {
+            session.host = Some(sender_id);
+            // start connections with all users that joined before the host
+            let pending_users: Vec<_> = session.pending_users.drain(..).collect();
+            pending_users
+                .into_iter()
+                .flat_map(|user_id| connect_one_to_many(session, session_id, sender_id, user_id))
+                .collect()
+        } else if is_host {
+            error!("connecting user wants to be a host, but host is already present!");
+            Vec::new()
+        } else {
+            session.users.insert(sender_id);
+            if let Some(host_id) = session.host {
+                connect_one_to_many(session, session_id, host_id, sender_id)
+            } else {
+                session.pending_users.push_back(sender_id);
+                Vec::new()
+            }
+        }
+    }
+
+    /// Removes client turned away by the host, telling it that the session is full.
+    fn one_to_many_full(
+        &mut self,
+        sender_id: UserId,
+        session_id: SessionId,
+        client_id: UserId,
+    ) -> Vec<(UserId, one_to_many::SignalMessage)> {
+        use one_to_many::SignalMessage;
+
+        let Some(session) = self.one_to_many_sessions.get_mut(&session_id) else {
+            return Vec::new();
+        };
+        if session.host != Some(sender_id) || !session.users.remove(&client_id) {
+            return Vec::new();
+        }
+        session.stable_ids.remove(&client_id);
+        session.full = true;
+        let error = "session is full".to_owned();
+        vec![(
+            client_id,
+            SignalMessage::Error(session_id, client_id, error),
+        )]
+    }
+
+    fn many_to_many_message(
+        &mut self,
+        sender_id: UserId,
+        message: one_to_many::SignalMessage,
+    ) -> Vec<(UserId, one_to_many::SignalMessage)> {
+        use one_to_many::SignalMessage;
+
+        debug!("message received from user {:?}: {:?}", sender_id, message);
+        let message = match message {
+            // mesh peers have no hosts
+            SignalMessage::SessionJoinWithIntent(session_id, _, intent)
+            | SignalMessage::SessionJoinHost(session_id, _, intent) => {
+                if let Some(error) = join_error(&self.many_to_many_sessions, session_id, intent) {
+                    return vec![(
+                        sender_id,
+                        SignalMessage::SessionJoinError(session_id, error),
+                    )];
+                }
+                SignalMessage::SessionJoin(session_id, false)
+            }
+            message => message,
+        };
+        match message {
+            SignalMessage::SessionJoin(session_id, _) => {
+                let users = self.many_to_many_sessions.entry(session_id).or_default();
+                let mut members: Vec<UserId> = users.iter().copied().collect();
+                members.push(sender_id);
+                members.sort_unstable();
+                // tell members, then start connections with all already present users
+                let responses = iter::once(SignalMessage::SessionMembers(
+                    session_id, sender_id, members,
+                ))
+                .chain(
+                    users
+                        .iter()
+                        .map(|&user_id| SignalMessage::SessionReady(session_id, user_id)),
+                )
+                .map(|response| (sender_id, response))
+                .collect();
+                users.insert(sender_id);
+                responses
+            }
+            SignalMessage::SessionReady(session_id, recipient_id) => {
+                vec![(
+                    recipient_id,
+                    SignalMessage::SessionReady(session_id, sender_id),
+                )]
+            }
+            SignalMessage::SessionListRequest => {
+                let sessions = self
+                    .many_to_many_sessions
+                    .iter()
+                    .map(|(&session_id, users)| (session_id, users.len()));
+                let sessions = session_list(sessions, TopologyType::ManyToMany);
+                vec![(sender_id, SignalMessage::SessionListResponse(sessions))]
+            }
+            SignalMessage::ProtocolVersion { .. } => vec![(
+                sender_id,
+                SignalMessage::ProtocolVersion {
+                    major: PROTOCOL_MAJOR_VERSION,
+                    minor: PROTOCOL_MINOR_VERSION,
+                },
+            )],
+            message => relay_one_to_many(sender_id, message).into_iter().collect(),
+        }
+    }
+
+    /// Removes user from its sessions, returning messages telling remaining users that it left.
+    fn disconnect(&mut self, user_id: UserId) -> Vec<(UserId, one_to_many::SignalMessage)> {
+        use one_to_many::SignalMessage;
+
+        let mut responses = Vec::new();
+        self.users.remove(&user_id);
+        self.one_to_one_sessions.retain(|_, session| {
+            if session.first == Some(user_id) {
+                session.first = None;
+            } else if session.second == Some(user_id) {
+                session.second = None;
+            }
+            session.first.is_some() || session.second.is_some()
+        });
+        self.one_to_many_sessions.retain(|&session_id, session| {
+            if session.host == Some(user_id) {
+                session.host = None;
+                session.full = false;
+                session.pending_users = session.users.iter().copied().collect();
+                responses.extend(session.users.iter().map(|&client_id| {
+                    (client_id, SignalMessage::SessionLeave(session_id, user_id))
+                }));
+            } else if session.users.remove(&user_id) {
+                // stable id is free to rejoin with once its user leaves
+                session.stable_ids.remove(&user_id);
+                session.full = false;
+                session
+                    .pending_users
+                    .retain(|&pending_id| pending_id != user_id);
+                if let Some(host_id) = session.host {
+                    responses.push((host_id, SignalMessage::SessionLeave(session_id, user_id)));
+                }
+            }
+            session.host.is_some() || !session.users.is_empty()
+        });
+        self.many_to_many_sessions.retain(|_, users| {
+            users.remove(&user_id);
+            !users.is_empty()
+        });
+        responses
+    }
+}
+
+/// Tells both the host and the client about each other.
+/// Host learns client's stable id first, if it has one.
+fn connect_one_to_many(
+    session: &OneToManySession,
+    session_id: SessionId,
+    host_id: UserId,
+    client_id: UserId,
+) -> Vec<(UserId, one_to_many::SignalMessage)> {
+    use one_to_many::SignalMessage;
+
+    let identity = session.stable_ids.get(&client_id).map(|&stable_id| {
+        (
+            host_id,
+            SignalMessage::ClientIdentity(session_id, client_id, stable_id),
+        )
+    });
+    identity
+        .into_iter()
+        .chain([
+            (host_id, SignalMessage::SessionReady(session_id, client_id)),
+            (client_id, SignalMessage::SessionReady(session_id, host_id)),
+        ])
+        .collect()
+}
+
+/// Why user joining session `session_id` with `intent` is refused, same as signaling server.
+fn join_error<S>(
+    sessions: &HashMap<SessionId, S>,
+    session_id: SessionId,
+    intent: JoinIntent,
+) -> Option<JoinError> {
+    match (intent, sessions.contains_key(&session_id)) {
+        (JoinIntent::Join, false) => Some(JoinError::NoSuchSession),
+        (JoinIntent::Create, true) => Some(JoinError::AlreadyExists),
+        _ => None,
+    }
+}
+
+/// Sessions with their peer counts as listed by signaling server, ordered by their ids.
+fn session_list(
+    sessions: impl Iterator<Item = (SessionId, usize)>,
+    topology: TopologyType,
+) -> Vec<SessionInfo> {
+    let mut sessions: Vec<_> = sessions
+        .map(|(session_id, peer_count)| SessionInfo {
+            session_id,
+            peer_count,
+            topology,
+        })
+        .collect();
+    sessions.sort_unstable_by_key(|session| session.session_id.inner());
+    sessions
+}
+
+/// Passes a message addressed to a single peer, replacing recipient with the sender.
+fn relay_one_to_many(
+    sender_id: UserId,
+    message: one_to_many::SignalMessage,
+) -> Option<(UserId, one_to_many::SignalMessage)> {
+    use one_to_many::SignalMessage;
+
+    match message {
+        SignalMessage::SdpOffer(session_id, recipient_id, offer) => Some((
+            recipient_id,
+            SignalMessage::SdpOffer(session_id, sender_id, offer),
+        )),
+        SignalMessage::SdpAnswer(session_id, recipient_id, answer) => Some((
+            recipient_id,
+            SignalMessage::SdpAnswer(session_id, sender_id, answer),
+        )),
+        SignalMessage::IceCandidate(session_id, recipient_id, candidate) => Some((
+            recipient_id,
+            SignalMessage::IceCandidate(session_id, sender_id, candidate),
+        )),
+        SignalMessage::Error(session_id, recipient_id, error) => Some((
+            recipient_id,
+            SignalMessage::Error(session_id, sender_id, error),
+        )),
+        other => {
+            error!("received unexpected signal message: {:?}", other);
+            None
+        }
+    }
+}
+
+/// Connection of a single network manager with [`LoopbackSignaling`].
+#[derive(Debug, Clone)]
+pub struct LoopbackConnection {
+    signaling: LoopbackSignaling,
+    user_id: UserId,
+}
+
+impl LoopbackConnection {
+    /// Handles the message like signaling server would, errors are only logged.
+    pub(crate) fn send(&self, message: &[u8]) {
+        if let Err(err) = self.signaling.handle_message(self.user_id, message) {
+            error!(
+                "error while handling message from user {:?}: {}",
+                self.user_id, err
+            );
+        }
+    }
+
+    pub(crate) fn set_on_message(&self, on_message: impl FnMut(&[u8]) + 'static) {
+        let pending = {
+            let mut inner = self.signaling.inner.borrow_mut();
+            let Some(user) = inner.users.get_mut(&self.user_id) else {
+                error!("user {:?} is already disconnected", self.user_id);
+                return;
+            };
+            user.on_message = Some(Rc::new(RefCell::new(on_message)));
+            std::mem::take(&mut user.pending)
+        };
+        for message in pending {
+            self.signaling.deliver(self.user_id, message);
+        }
+    }
+
+    pub(crate) fn close(&self) {
+        let responses = self.signaling.inner.borrow_mut().disconnect(self.user_id);
+        match serialize_all(responses) {
+            Ok(responses) => {
+                for (recipient_id, response) in responses {
+                    self.signaling.deliver(recipient_id, response);
+                }
+            }
+            Err(err) => error!(
+                "failed to notify users that {:?} left: {}",
+                self.user_id, err
+            ),
+        }
+    }
+}
+
+/// Returns a callback and a future that resolves once the callback is called,
+/// e.g. to wait in a test until both peers are connected.
+///
+/// # Panics
+/// Callback panics if the promise can't be resolved.
+pub fn completion() -> (impl Fn(), JsFuture) {
+    let mut resolve = None;
+    let promise = Promise::new(&mut |resolve_fn, _| resolve = Some(resolve_fn));
+    let resolve = resolve.expect("promise executor wasn't called");
+    let complete = move || {
+        resolve
+            .call0(&JsValue::NULL)
+            .expect("failed to resolve promise");
+    };
+    (complete, JsFuture::from(promise))
+}
@@ -0,0 +1,318 @@
+use std::fmt::Debug;
+use std::rc::Rc;
+
+use anyhow::anyhow;
+use futures_util::future::LocalBoxFuture;
+use futures_util::FutureExt;
+use js_sys::Uint8Array;
+use log::error;
+use serde::Serialize;
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_peers_protocol::SessionId;
+use web_sys::{MessageEvent, WebSocket};
+
+#[cfg(feature = "gloo-websocket")]
+use crate::gloo_websocket::GlooWebSocket;
+#[cfg(feature = "node")]
+use crate::node::NodeWebSocket;
+#[cfg(feature = "test-utils")]
+use crate::test_utils::LoopbackConnection;
+use crate::utils::wait_for_websocket_open;
+use crate::SignalingClient;
+
+/// Connection with signaling server that signal messages are sent through.
+///
+/// Implemented for [`WebSocket`], it exists so that signaling can be tested without a live server.
+pub trait SignalingTransport {
+    /// Sends a single serialized signal message.
+    fn send(&self, message: &[u8]) -> crate::Result<()>;
+}
+
+impl SignalingTransport for WebSocket {
+    fn send(&self, message: &[u8]) -> crate::Result<()> {
+        self.send_with_u8_array(message)
+            .map_err(|err| anyhow!("failed to send message across the websocket: {:?}", err))
+    }
+}
+
+/// Callback receiving binary messages of a [`WebSocketLike`].
+pub type OnMessage = Box<dyn FnMut(&[u8])>;
+
+/// `WebSocket` connection with signaling server, abstracted over the environment it's opened in.
+///
+/// Implemented for browser's [`WebSocket`] and, with `node` feature, for `WebSocket` of the `ws` package,
+/// as Node.js doesn't provide one before version 22. With `gloo-websocket` feature
+/// it's implemented for `WebSocket` of the `gloo-net` crate. Which one is opened is chosen with [`WebSocketKind`].
+pub trait WebSocketLike: Debug {
+    /// Sends a single binary message.
+    ///
+    /// # Errors
+    /// This function errs if connection isn't open.
+    fn send(&self, message: &[u8]) -> crate::Result<()>;
+
+    /// Resolves once connection is open, errs if it fails to connect first.
+    fn wait_for_open(&self) -> LocalBoxFuture<'_, crate::Result<()>>;
+
+    /// Calls `on_open` once connection is open, right away if it already is.
+    fn set_on_open(&self, on_open: Box<dyn FnMut()>);
+
+    /// Calls `on_message` with every binary message received.
+    fn set_on_message(&self, on_message: OnMessage);
+
+    /// Calls `on_close` once connection is lost or couldn't be opened.
+    fn set_on_close(&self, on_close: Box<dyn FnMut()>);
+
+    /// Name of the ready state, one of `connecting`, `open`, `closing` and `closed`.
+    fn ready_state_name(&self) -> &'static str;
+
+    /// Stops calling `on_message` and `on_close` callbacks and closes the connection.
+    fn close(&self);
+}
+
+impl WebSocketLike for WebSocket {
+    fn send(&self, message: &[u8]) -> crate::Result<()> {
+        SignalingTransport::send(self, message)
+    }
+
+    fn wait_for_open(&self) -> LocalBoxFuture<'_, crate::Result<()>> {
+        wait_for_websocket_open(self).boxed_local()
+    }
+
+    fn set_on_open(&self, mut on_open: Box<dyn FnMut()>) {
+        // websocket might have been opened already by `NetworkManager::new_async`
+        if self.ready_state() == Self::OPEN {
+            on_open();
+            return;
+        }
+        let on_open: Box<dyn FnMut(JsValue)> = Box::new(move |_| on_open());
+        let on_open = Closure::wrap(on_open);
+        self.set_onopen(Some(on_open.as_ref().unchecked_ref()));
+        on_open.forget();
+    }
+
+    fn set_on_message(&self, mut on_message: OnMessage) {
+        let on_message: Box<dyn FnMut(MessageEvent)> =
+            Box::new(
+                move |ev: MessageEvent| match ev.data().dyn_into::<Uint8Array>() {
+                    Ok(message) => on_message(&message.to_vec()),
+                    Err(_) => error!("failed to convert message to Uint8Array"),
+                },
+            );
+        let on_message = Closure::wrap(on_message);
+        self.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+        on_message.forget();
+    }
+
+    fn set_on_close(&self, mut on_close: Box<dyn FnMut()>) {
+        let on_close: Box<dyn FnMut(JsValue)> = Box::new(move |_| on_close());
+        let on_close = Closure::wrap(on_close);
+        self.set_onclose(Some(on_close.as_ref().unchecked_ref()));
+        on_close.forget();
+    }
+
+    fn ready_state_name(&self) -> &'static str {
+        match self.ready_state() {
+            Self::CONNECTING => "connecting",
+            Self::OPEN => "open",
+            Self::CLOSING => "closing",
+            _ => "closed",
+        }
+    }
+
+    fn close(&self) {
+        self.set_onmessage(None);
+        self.set_onclose(None);
+        if let Err(err) = WebSocket::close(self) {
+            error!("failed to close signaling server connection: {:?}", err);
+        }
+    }
+}
+
+/// Kind of `WebSocket` that connections with signaling server are opened with.
+///
+/// Features enabling other kinds are additive, browser's `WebSocket` is opened unless another kind
+/// is passed explicitly, e.g. to [`SignalingClient::with_websocket`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WebSocketKind {
+    /// Browser's global `WebSocket`
+    #[default]
+    Browser,
+    /// `WebSocket` of the `ws` package, for peers running in Node.js
+    #[cfg(feature = "node")]
+    Node,
+    /// `WebSocket` of the `gloo-net` crate, driven by a single task
+    #[cfg(feature = "gloo-websocket")]
+    Gloo,
+}
+
+impl WebSocketKind {
+    /// Opens `WebSocket` of this kind receiving binary messages as `ArrayBuffer`s.
+    fn open(self, url: &str) -> Result<Rc<dyn WebSocketLike>, JsValue> {
+        match self {
+            Self::Browser => {
+                let websocket = WebSocket::new(url)?;
+                websocket.set_binary_type(web_sys::BinaryType::Arraybuffer);
+                Ok(Rc::new(websocket))
+            }
+            #[cfg(feature = "node")]
+            Self::Node => Ok(Rc::new(NodeWebSocket::open(url)?)),
+            #[cfg(feature = "gloo-websocket")]
+            Self::Gloo => Ok(Rc::new(GlooWebSocket::open(url)?)),
+        }
+    }
+}
+
+/// Connection with signaling server owned by a network manager.
+#[derive(Debug, Clone)]
+pub enum SignalingConnection {
+    WebSocket(Rc<dyn WebSocketLike>),
+    #[cfg(feature = "test-utils")]
+    Loopback(LoopbackConnection),
+    Shared(SharedSession),
+}
+
+/// Session of a network manager created from [`SignalingClient`], signaling through its connection.
+#[derive(Debug, Clone)]
+pub struct SharedSession {
+    pub client: SignalingClient,
+    pub session_id: SessionId,
+}
+
+impl SignalingTransport for SignalingConnection {
+    fn send(&self, message: &[u8]) -> crate::Result<()> {
+        match *self {
+            Self::WebSocket(ref websocket) => websocket.send(message),
+            #[cfg(feature = "test-utils")]
+            Self::Loopback(ref loopback) => {
+                loopback.send(message);
+                Ok(())
+            }
+            Self::Shared(ref shared) => shared.client.send(message),
+        }
+    }
+}
+
+impl SignalingConnection {
+    /// Opens a browser's `WebSocket` connection with signaling server at `signaling_server_url`.
+    pub fn open(signaling_server_url: &str) -> crate::Result<Self> {
+        Self::open_with(signaling_server_url, WebSocketKind::Browser)
+    }
+
+    /// Same as [`SignalingConnection::open`], but opens `WebSocket` of `kind`.
+    pub fn open_with(signaling_server_url: &str, kind: WebSocketKind) -> crate::Result<Self> {
+        let websocket = kind.open(signaling_server_url).map_err(|err| {
+            anyhow!(
+                "failed to create connection with signaling server on {}: {:?}",
+                signaling_server_url,
+                err
+            )
+        })?;
+        Ok(Self::WebSocket(websocket))
+    }
+
+    /// Resolves once connection is open, errs if it fails to connect first.
+    pub async fn wait_for_open(&self) -> crate::Result<()> {
+        match *self {
+            Self::WebSocket(ref websocket) => websocket.wait_for_open().await,
+            #[cfg(feature = "test-utils")]
+            Self::Loopback(_) => Ok(()),
+            // shared connection is waited for by `SignalingClient::new_async` instead
+            Self::Shared(ref shared) if shared.client.is_open() => Ok(()),
+            Self::Shared(_) => Err(anyhow!(
+                "shared connection with signaling server isn't open yet"
+            )),
+        }
+    }
+
+    /// Calls `on_open` once connection is open, right away if it already is.
+    pub fn set_on_open(&self, on_open: impl FnMut() + 'static) {
+        match *self {
+            Self::WebSocket(ref websocket) => websocket.set_on_open(Box::new(on_open)),
+            #[cfg(feature = "test-utils")]
+            Self::Loopback(_) => {
+                let mut on_open = on_open;
+                on_open();
+            }
+            Self::Shared(ref shared) => shared.client.set_on_open(on_open),
+        }
+    }
+
+    /// Calls `on_message` with every message received from signaling server.
+    pub fn set_on_message(&self, on_message: impl FnMut(&[u8]) + 'static) {
+        match *self {
+            Self::WebSocket(ref websocket) => websocket.set_on_message(Box::new(on_message)),
+            #[cfg(feature = "test-utils")]
+            Self::Loopback(ref loopback) => loopback.set_on_message(on_message),
+            Self::Shared(ref shared) => shared.client.set_on_message(shared.session_id, on_message),
+        }
+    }
+
+    /// Calls `on_close` once connection is lost or couldn't be opened,
+    /// but not when it's closed with [`SignalingConnection::close`].
+    pub fn set_on_close(&self, on_close: impl FnMut() + 'static) {
+        match *self {
+            Self::WebSocket(ref websocket) => websocket.set_on_close(Box::new(on_close)),
+            // loopback connection is never lost
+            #[cfg(feature = "test-utils")]
+            Self::Loopback(_) => {}
+            Self::Shared(ref shared) => shared.client.set_on_close(shared.session_id, on_close),
+        }
+    }
+
+    /// Name of the connection's ready state, as reported by [`DiagnosticsReport`](crate::DiagnosticsReport).
+    pub fn ready_state_name(&self) -> &'static str {
+        match *self {
+            Self::WebSocket(ref websocket) => websocket.ready_state_name(),
+            #[cfg(feature = "test-utils")]
+            Self::Loopback(_) => "loopback",
+            Self::Shared(ref shared) => shared.client.ready_state_name(),
+        }
+    }
+
+    /// Stops receiving messages and closes the connection,
+    /// shared one is only left open for other sessions.
+    pub fn close(&self) {
+        match *self {
+            Self::WebSocket(ref websocket) => websocket.close(),
+            #[cfg(feature = "test-utils")]
+            Self::Loopback(ref loopback) => loopback.close(),
+            Self::Shared(ref shared) => shared.client.remove_session(shared.session_id),
+        }
+    }
+}
+
+/// Serializes signal message with `MessagePack` and sends it through `transport`.
+pub fn send_signal_message<M: Serialize>(
+    transport: &impl SignalingTransport,
+    signal_message: &M,
+) -> crate::Result<()> {
+    transport.send(&rmp_serde::to_vec(signal_message)?)
+}
+
+/// Transport recording all sent messages instead of sending them.
+#[cfg(test)]
+#[derive(Debug, Clone, Default)]
+pub struct MockTransport {
+    pub sent: std::rc::Rc<std::cell::RefCell<Vec<Vec<u8>>>>,
+}
+
+#[cfg(test)]
+impl MockTransport {
+    /// Deserializes all messages sent so far.
+    pub fn sent_messages<M: serde::de::DeserializeOwned>(&self) -> Vec<M> {
+        self.sent
+            .borrow()
+            .iter()
+            .map(|message| rmp_serde::from_slice(message).expect("sent invalid signal message"))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+impl SignalingTransport for MockTransport {
+    fn send(&self, message: &[u8]) -> crate::Result<()> {
+        self.sent.borrow_mut().push(message.to_vec());
+        Ok(())
+    }
+}
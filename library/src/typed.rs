@@ -0,0 +1,269 @@
+/*!
+Messages of several types, e.g. player movement, chat and inventory changes, each passed to its own handler.
+
+[`TypedNetworkManager`] wraps a network manager of any topology. Each message is sent along with
+the name of its type, as returned by [`std::any::type_name`], and received messages are passed to the handler
+registered for their type with [`TypedNetworkManager::add_handler`], instead of a single callback
+matching over an enum of all of them.
+Messages of types without a handler, and ones that can't be deserialized into the handled type,
+are logged and dropped.
+
+Type names aren't guaranteed to be stable between compiler versions, so both peers have to use
+[`TypedNetworkManager`] and be built from the same sources and with the same compiler.
+Messages are handled by their exact type, so `String` handler doesn't receive messages sent as `str`.
+
+# Example
+
+```no_run
+use serde::{Deserialize, Serialize};
+use wasm_peers::many_to_many::NetworkManager;
+use wasm_peers::typed::TypedNetworkManager;
+use wasm_peers::{ConnectionType, PeerEvent, SessionId};
+use web_sys::console;
+
+#[derive(Serialize, Deserialize)]
+struct Movement {
+    x: f32,
+    y: f32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Chat(String);
+
+let network_manager = NetworkManager::new(
+    "ws://0.0.0.0:9001/many-to-many",
+    SessionId::new(1),
+    ConnectionType::Local,
+)
+.expect("failed to connect to signaling server");
+let mut network_manager = TypedNetworkManager::new(network_manager);
+network_manager
+    .add_handler(|user_id, movement: Movement| {
+        console::log_1(&format!("{} moved to {}, {}", user_id, movement.x, movement.y).into());
+    })
+    .add_handler(|user_id, Chat(message)| {
+        console::log_1(&format!("{}: {}", user_id, message).into());
+    });
+let sender = network_manager.clone();
+network_manager
+    .start_with_event_handler(move |event| {
+        if let PeerEvent::Opened(user_id) = event {
+            let _result = sender.send_message(user_id, &Chat("hello!".to_owned()));
+        }
+    })
+    .unwrap();
+```
+*/
+
+use std::any::type_name;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::rc::Rc;
+
+use log::{debug, error};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::pubsub::Payload;
+use crate::utils::{deserialize_serialized_message, serialize_message};
+use crate::{PeerEvent, PeerManager};
+
+/// Serialized message along with the name of its type.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct TypedMessage<'a> {
+    type_name: Cow<'a, str>,
+    payload: Payload<'a>,
+}
+
+impl<'a> TypedMessage<'a> {
+    fn new<T: ?Sized>(payload: &'a [u8]) -> Self {
+        Self {
+            type_name: Cow::Borrowed(type_name::<T>()),
+            payload: Payload(Cow::Borrowed(payload)),
+        }
+    }
+}
+
+type Handler<Id> = Rc<RefCell<dyn FnMut(Id, &[u8])>>;
+type Handlers<Id> = HashMap<&'static str, Handler<Id>>;
+
+/// Handler deserializing messages into `T` before passing them to `callback`.
+fn typed_handler<Id, T: DeserializeOwned + 'static>(
+    mut callback: impl FnMut(Id, T) + 'static,
+) -> Handler<Id> {
+    Rc::new(RefCell::new(
+        move |id, payload: &[u8]| match deserialize_serialized_message(payload) {
+            Some(message) => callback(id, message),
+            None => error!("failed to deserialize message of type {}", type_name::<T>()),
+        },
+    ))
+}
+
+/// Passes `message` to the handler of its type.
+fn dispatch<Id>(handlers: &RefCell<Handlers<Id>>, id: Id, message: &TypedMessage<'_>) {
+    // clone handler out, so that it can freely add handlers
+    let handler = handlers.borrow().get(message.type_name.as_ref()).cloned();
+    match handler {
+        Some(handler) => (handler.borrow_mut())(id, &message.payload.0),
+        None => debug!(
+            "dropping message of type {} without handler",
+            message.type_name
+        ),
+    }
+}
+
+/// Wrapper over network manager of any topology, passing messages to handlers of their types.
+/// See [module documentation](self) for details.
+pub struct TypedNetworkManager<M: PeerManager> {
+    inner: M,
+    handlers: Rc<RefCell<Handlers<M::Id>>>,
+}
+
+impl<M: PeerManager> Clone for TypedNetworkManager<M> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            handlers: Rc::clone(&self.handlers),
+        }
+    }
+}
+
+impl<M: PeerManager> TypedNetworkManager<M> {
+    /// Wraps `inner`, which shouldn't be started yet.
+    pub fn new(inner: M) -> Self {
+        Self {
+            inner,
+            handlers: Rc::new(RefCell::new(HashMap::new())),
+        }
+    }
+
+    /// Registers a handler receiving messages of type `T` along with the peer that sent them,
+    /// replacing the previous handler of the same type.
+    pub fn add_handler<T: DeserializeOwned + 'static>(
+        &mut self,
+        handler: impl FnMut(M::Id, T) + 'static,
+    ) -> &mut Self {
+        self.handlers
+            .borrow_mut()
+            .insert(type_name::<T>(), typed_handler(handler));
+        self
+    }
+
+    /// Starts the underlying network manager, calling `handler` with every event
+    /// but messages, which are passed to handlers of their types instead.
+    ///
+    /// # Errors
+    /// Errs with [`AlreadyStartedError`](crate::AlreadyStartedError) if it was already started.
+    pub fn start_with_event_handler(
+        &mut self,
+        mut handler: impl FnMut(PeerEvent<Infallible, M::Id>) + 'static,
+    ) -> crate::Result<()> {
+        let handlers = Rc::clone(&self.handlers);
+        self.inner.start_handling(
+            move |event: PeerEvent<TypedMessage<'static>, M::Id>| match event {
+                PeerEvent::Opened(id) => handler(PeerEvent::Opened(id)),
+                PeerEvent::Message(id, message) => dispatch(&handlers, id, &message),
+                PeerEvent::Closed(id, reason) => handler(PeerEvent::Closed(id, reason)),
+                PeerEvent::SignalingError(err) => handler(PeerEvent::SignalingError(err)),
+                PeerEvent::StateChanged(id, state) => {
+                    handler(PeerEvent::StateChanged(id, state));
+                }
+                PeerEvent::ChannelStateChanged(id, state) => {
+                    handler(PeerEvent::ChannelStateChanged(id, state));
+                }
+            },
+        )
+    }
+
+    /// Sends message to the handler of its type on a single peer.
+    ///
+    /// # Errors
+    /// This function errs if serialization fails, otherwise same as `send_message` of the underlying network manager.
+    pub fn send_message<T: Serialize + ?Sized>(&self, id: M::Id, message: &T) -> crate::Result<()> {
+        let payload = serialize_message(message)?;
+        self.inner.send_to(id, &TypedMessage::new::<T>(&payload))
+    }
+
+    /// Sends message to handlers of its type on all connected peers.
+    ///
+    /// # Errors
+    /// This function errs if serialization fails, otherwise same as `send_message_to_all` of the underlying network manager.
+    pub fn send_message_to_all<T: Serialize + ?Sized>(&self, message: &T) -> crate::Result<()> {
+        let payload = serialize_message(message)?;
+        self.inner.send_to_all(&TypedMessage::new::<T>(&payload))
+    }
+
+    /// Closes the underlying network manager.
+    pub fn close(&self) {
+        self.inner.disconnect();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde::{Deserialize, Serialize};
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Movement {
+        x: i32,
+        y: i32,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Chat(String);
+
+    /// Message of type `T` as received from a peer.
+    fn received<T: Serialize>(message: &T) -> TypedMessage<'static> {
+        let payload = serialize_message(message).expect("failed to serialize message");
+        let typed = serialize_message(&TypedMessage::new::<T>(&payload))
+            .expect("failed to serialize typed message");
+        deserialize_serialized_message(&typed).expect("failed to deserialize typed message")
+    }
+
+    fn recording<T: DeserializeOwned + 'static>(
+        handlers: &RefCell<Handlers<u8>>,
+    ) -> Rc<RefCell<Vec<(u8, T)>>> {
+        let received = Rc::new(RefCell::new(Vec::new()));
+        let received_clone = Rc::clone(&received);
+        handlers.borrow_mut().insert(
+            type_name::<T>(),
+            typed_handler(move |id, message: T| received_clone.borrow_mut().push((id, message))),
+        );
+        received
+    }
+
+    #[wasm_bindgen_test]
+    fn test_messages_are_dispatched_to_handlers_of_their_types() {
+        let handlers = RefCell::new(Handlers::new());
+        let movements = recording::<Movement>(&handlers);
+        let chats = recording::<Chat>(&handlers);
+
+        dispatch(&handlers, 1, &received(&Movement { x: 1, y: 2 }));
+        dispatch(&handlers, 2, &received(&Chat("hello".to_owned())));
+
+        assert_eq!(*movements.borrow(), [(1, Movement { x: 1, y: 2 })]);
+        assert_eq!(*chats.borrow(), [(2, Chat("hello".to_owned()))]);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_message_without_handler_is_dropped() {
+        let handlers = RefCell::new(Handlers::new());
+        let chats = recording::<Chat>(&handlers);
+        dispatch(&handlers, 1, &received(&Movement { x: 1, y: 2 }));
+        assert!(chats.borrow().is_empty());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_message_not_matching_its_type_is_dropped() {
+        let handlers = RefCell::new(Handlers::new());
+        let movements = recording::<Movement>(&handlers);
+        let payload = serialize_message("not a movement").expect("failed to serialize message");
+        dispatch(&handlers, 1, &TypedMessage::new::<Movement>(&payload));
+        assert!(movements.borrow().is_empty());
+    }
+}
@@ -1,20 +1,101 @@
+use std::cell::{Cell, RefCell};
+use std::fmt::{Debug, Display, Formatter};
+use std::rc::Rc;
+use std::time::Duration;
+
 use anyhow::anyhow;
-use js_sys::{Array, Object, Reflect};
-use log::debug;
+use js_sys::{Array, ArrayBuffer, Number, Object, Promise, Reflect, Uint8Array, JSON};
+use log::{debug, error};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use wasm_bindgen::closure::Closure;
+use wasm_bindgen::prelude::wasm_bindgen;
 use wasm_bindgen::{JsCast, JsValue};
 use wasm_bindgen_futures::JsFuture;
-use wasm_peers_protocol::SessionId;
-use web_sys::{RtcConfiguration, RtcPeerConnection, RtcSdpType, RtcSessionDescriptionInit};
+#[cfg(feature = "uuid")]
+use wasm_peers_protocol::StableId;
+use wasm_peers_protocol::{IceCandidate, SessionId, UserId};
+use web_sys::{
+    RtcConfiguration, RtcDataChannel, RtcDataChannelInit, RtcIceCandidate, RtcIceCandidateInit,
+    RtcIceGatheringState, RtcPeerConnection, RtcSdpType, RtcSessionDescriptionInit,
+    RtcSignalingState, WebSocket,
+};
+
+use crate::constants::{
+    DEFAULT_BATCH_MAX_LATENCY, DEFAULT_MAX_BATCH_BYTES, DEFAULT_MAX_MESSAGE_SIZE,
+};
+use crate::MessageTooLargeError;
 
 /// Returns a new `SessionId` instance that can be used to identify a session by signaling server.
+#[cfg(feature = "uuid")]
 #[must_use]
 pub fn get_random_session_id() -> SessionId {
     SessionId::new(uuid::Uuid::new_v4().as_u128())
 }
 
-/// Specifies what kind of peer connection to create
-#[derive(Debug, Clone)]
+/// Returns a new random `StableId`, to be kept by the client, e.g. in local storage,
+/// and passed to [`MiniClient::set_stable_id`](crate::one_to_many::MiniClient::set_stable_id) every time.
+#[cfg(feature = "uuid")]
+#[must_use]
+pub fn get_random_stable_id() -> StableId {
+    StableId::new(uuid::Uuid::new_v4().as_u128())
+}
+
+/// Random number for internal ids and simulated network conditions, from `crypto.getRandomValues`.
+#[cfg(all(feature = "uuid", any(feature = "one-to-one", feature = "one-to-many")))]
+pub(crate) fn random_u128() -> u128 {
+    uuid::Uuid::new_v4().as_u128()
+}
+
+/// Random number for internal ids and simulated network conditions, from `Math.random`,
+/// which is good enough for them and doesn't need `uuid` dependency.
+#[cfg(all(
+    not(feature = "uuid"),
+    any(feature = "one-to-one", feature = "one-to-many")
+))]
+#[allow(
+    clippy::as_conversions,
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    clippy::float_arithmetic
+)]
+pub(crate) fn random_u128() -> u128 {
+    (0..4).fold(0, |random, _| {
+        let word = (js_sys::Math::random() * f64::from(u32::MAX)) as u32;
+        random << 32 | u128::from(word)
+    })
+}
+
+/// State of a connection with a single peer reported to the application.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// ICE connectivity checks succeeded
+    Connected,
+    /// ICE connectivity was lost, browser may still recover from it on its own
+    Disconnected,
+    /// ICE failed and connection is being re-created, `attempt` counts from 1
+    Retrying { attempt: u32, max_attempts: u32 },
+    /// ICE failed and no more attempts will be made
+    Failed,
+    /// Direct connection failed, messages are relayed through `via`, a peer connected to both sides,
+    /// see `many_to_many::NetworkManager::set_relay_enabled`
+    Relayed { via: UserId },
+}
+
+/// State of the data channel with a single peer, which can close while ICE stays connected,
+/// e.g. when the other application closed just the channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelState {
+    /// Channel is closing, messages can no longer be sent through it
+    Closing,
+    /// Channel is closed, followed by [`PeerEvent::Closed`](crate::PeerEvent::Closed)
+    Closed,
+}
+
+/// Specifies what kind of peer connection to create.
+///
+/// Debug output hides TURN credential, so it's safe to log.
+#[derive(Clone, PartialEq, Eq, Hash)]
 pub enum ConnectionType {
     /// Within local network
     Local,
@@ -29,6 +110,138 @@ pub enum ConnectionType {
     },
 }
 
+impl ConnectionType {
+    /// Reads connection type from a plain JavaScript object, one of:
+    /// - `{ type: "local" }`,
+    /// - `{ type: "stun", urls: "..." }`,
+    /// - `{ type: "stunAndTurn", stunUrls: "...", turnUrls: "...", username: "...", credential: "..." }`.
+    ///
+    /// # Errors
+    /// This function errs if `type` is not one of the above or if any of the required keys is missing.
+    pub fn from_js_object(js: &JsValue) -> crate::Result<Self> {
+        let connection_type = get_string_property(js, "type")?;
+        match connection_type.as_str() {
+            "local" => Ok(Self::Local),
+            "stun" => Ok(Self::Stun {
+                urls: get_string_property(js, "urls")?,
+            }),
+            "stunAndTurn" => Ok(Self::StunAndTurn {
+                stun_urls: get_string_property(js, "stunUrls")?,
+                turn_urls: get_string_property(js, "turnUrls")?,
+                username: get_string_property(js, "username")?,
+                credential: get_string_property(js, "credential")?,
+            }),
+            other => Err(anyhow!("unknown connection type: {}", other)),
+        }
+    }
+}
+
+impl Debug for ConnectionType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Self::Local => f.write_str("Local"),
+            Self::Stun { ref urls } => f.debug_struct("Stun").field("urls", urls).finish(),
+            Self::StunAndTurn {
+                ref stun_urls,
+                ref turn_urls,
+                ref username,
+                ..
+            } => f
+                .debug_struct("StunAndTurn")
+                .field("stun_urls", stun_urls)
+                .field("turn_urls", turn_urls)
+                .field("username", username)
+                .field("credential", &"[redacted]")
+                .finish(),
+        }
+    }
+}
+
+impl Display for ConnectionType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Self::Local => f.write_str("local network"),
+            Self::Stun { ref urls } => write!(f, "STUN via {}", urls),
+            Self::StunAndTurn {
+                ref stun_urls,
+                ref turn_urls,
+                ..
+            } => write!(f, "STUN via {} with TURN via {}", stun_urls, turn_urls),
+        }
+    }
+}
+
+fn get_string_property(js: &JsValue, key: &str) -> crate::Result<String> {
+    Reflect::get(js, &JsValue::from_str(key))
+        .map_err(|err| anyhow!("failed to get value for '{}' key: {:?}", key, err))?
+        .as_string()
+        .ok_or_else(|| anyhow!("'{}' key is missing or is not a string", key))
+}
+
+/// [`ConnectionType`] handle that can be created and passed around from JavaScript.
+#[wasm_bindgen]
+#[derive(Debug, Clone)]
+pub struct JsConnectionType {
+    inner: ConnectionType,
+}
+
+#[wasm_bindgen]
+impl JsConnectionType {
+    /// Same as [`ConnectionType::Local`]
+    #[must_use]
+    pub fn local() -> Self {
+        Self {
+            inner: ConnectionType::Local,
+        }
+    }
+
+    /// Same as [`ConnectionType::Stun`]
+    #[must_use]
+    pub fn stun(urls: &str) -> Self {
+        Self {
+            inner: ConnectionType::Stun {
+                urls: urls.to_owned(),
+            },
+        }
+    }
+
+    /// Same as [`ConnectionType::StunAndTurn`]
+    #[must_use]
+    #[wasm_bindgen(js_name = stunAndTurn)]
+    pub fn stun_and_turn(
+        stun_urls: &str,
+        turn_urls: &str,
+        username: &str,
+        credential: &str,
+    ) -> Self {
+        Self {
+            inner: ConnectionType::StunAndTurn {
+                stun_urls: stun_urls.to_owned(),
+                turn_urls: turn_urls.to_owned(),
+                username: username.to_owned(),
+                credential: credential.to_owned(),
+            },
+        }
+    }
+
+    /// Same as [`ConnectionType::from_js_object`]
+    ///
+    /// # Errors
+    /// This function errs if passed object doesn't describe a valid connection type.
+    #[wasm_bindgen(js_name = fromObject)]
+    pub fn from_object(js: &JsValue) -> Result<JsConnectionType, JsValue> {
+        ConnectionType::from_js_object(js)
+            .map(|inner| Self { inner })
+            .map_err(|err| JsValue::from_str(&err.to_string()))
+    }
+}
+
+impl From<JsConnectionType> for ConnectionType {
+    fn from(js_connection_type: JsConnectionType) -> Self {
+        js_connection_type.inner
+    }
+}
+
 pub fn create_peer_connection(
     connection_type: &ConnectionType,
 ) -> crate::Result<RtcPeerConnection> {
@@ -116,7 +329,11 @@ pub fn create_peer_connection(
     }
 }
 
-pub async fn create_sdp_offer(peer_connection: &RtcPeerConnection) -> crate::Result<String> {
+/// Creates an offer and sets it as the local description, modified with `on_sdp` if any.
+pub async fn create_sdp_offer(
+    peer_connection: &RtcPeerConnection,
+    on_sdp: Option<&SdpMunger>,
+) -> crate::Result<String> {
     let offer = JsFuture::from(peer_connection.create_offer())
         .await
         .map_err(|error| {
@@ -134,6 +351,7 @@ pub async fn create_sdp_offer(peer_connection: &RtcPeerConnection) -> crate::Res
         })?
         .as_string()
         .ok_or_else(|| anyhow!("no 'sdp' key in offer object"))?;
+    let offer = munge_sdp(on_sdp, offer);
     let mut local_session_description = RtcSessionDescriptionInit::new(RtcSdpType::Offer);
     local_session_description.sdp(&offer);
     JsFuture::from(peer_connection.set_local_description(&local_session_description))
@@ -148,9 +366,12 @@ pub async fn create_sdp_offer(peer_connection: &RtcPeerConnection) -> crate::Res
     Ok(offer)
 }
 
+/// Sets `offer` as the remote description, then creates an answer and sets it as the local description,
+/// modified with `on_sdp` if any.
 pub async fn create_sdp_answer(
     peer_connection: &RtcPeerConnection,
     offer: String,
+    on_sdp: Option<&SdpMunger>,
 ) -> crate::Result<String> {
     let mut remote_session_description = RtcSessionDescriptionInit::new(RtcSdpType::Offer);
     remote_session_description.sdp(&offer);
@@ -170,6 +391,7 @@ pub async fn create_sdp_answer(
         })?
         .as_string()
         .ok_or_else(|| anyhow!("failed to represent object value as string"))?;
+    let answer = munge_sdp(on_sdp, answer);
 
     let mut local_session_description = RtcSessionDescriptionInit::new(RtcSdpType::Answer);
     local_session_description.sdp(&answer);
@@ -180,22 +402,529 @@ pub async fn create_sdp_answer(
     Ok(answer)
 }
 
-pub fn set_peer_connection_on_negotiation_needed(peer_connection: &RtcPeerConnection) {
+/// Adds `ice_candidate` received from the remote peer to `peer_connection`.
+pub async fn add_ice_candidate(
+    peer_connection: &RtcPeerConnection,
+    ice_candidate: &IceCandidate,
+) -> crate::Result<()> {
+    debug!("peer received ice candidate: {:?}", ice_candidate);
+
+    // end of candidates can't be constructed without media it belongs to,
+    // it's signaled to the browser by adding no candidate instead
+    let rtc_candidate = if ice_candidate.is_end_of_candidates() {
+        None
+    } else {
+        let mut rtc_candidate = RtcIceCandidateInit::new("");
+        rtc_candidate.candidate(&ice_candidate.candidate);
+        rtc_candidate.sdp_m_line_index(ice_candidate.sdp_m_line_index);
+        rtc_candidate.sdp_mid(ice_candidate.sdp_mid.as_deref());
+        Some(
+            RtcIceCandidate::new(&rtc_candidate)
+                .map_err(|err| anyhow!("failed to create RTC ICE candidate: {:?}", err))?,
+        )
+    };
+    JsFuture::from(
+        peer_connection.add_ice_candidate_with_opt_rtc_ice_candidate(rtc_candidate.as_ref()),
+    )
+    .await
+    .map_err(|err| anyhow!("failed to add ICE candidate: {:?}", err))?;
+    debug!("added ice candidate {:?}", ice_candidate);
+    Ok(())
+}
+
+/// Parses ICE candidate in the browser's native representation, e.g. an `RTCIceCandidate`,
+/// result of its `toJSON()` or that result stringified, for interop with third-party `WebRTC` code.
+///
+/// # Errors
+/// This function errs if `js` has no `candidate` string, or if `sdpMid` or `sdpMLineIndex`
+/// are neither missing, `null`, nor of the expected type.
+#[allow(
+    clippy::as_conversions,
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss
+)]
+pub fn ice_candidate_from_js_value(js: &JsValue) -> crate::Result<IceCandidate> {
+    let js = match js.as_string() {
+        Some(json) => JSON::parse(&json)
+            .map_err(|err| anyhow!("failed to parse ICE candidate JSON: {:?}", err))?,
+        None => js.clone(),
+    };
+    let candidate = get_string_property(&js, "candidate")?;
+    let sdp_mid = get_optional_property(&js, "sdpMid")?
+        .map(|sdp_mid| {
+            sdp_mid
+                .as_string()
+                .ok_or_else(|| anyhow!("'sdpMid' key is not a string"))
+        })
+        .transpose()?;
+    let sdp_m_line_index = get_optional_property(&js, "sdpMLineIndex")?
+        .map(|index| {
+            Some(index)
+                .filter(Number::is_integer)
+                .and_then(|index| index.as_f64())
+                .filter(|index| (0.0..=f64::from(u16::MAX)).contains(index))
+                .map(|index| index as u16)
+                .ok_or_else(|| anyhow!("'sdpMLineIndex' key is not a valid index"))
+        })
+        .transpose()?;
+    Ok(IceCandidate {
+        candidate,
+        sdp_mid,
+        sdp_m_line_index,
+    })
+}
+
+/// ICE candidate as an `RTCIceCandidateInit` object, which third-party `WebRTC` code
+/// and `RTCPeerConnection.addIceCandidate` accept.
+#[must_use]
+pub fn ice_candidate_to_js_value(ice_candidate: &IceCandidate) -> JsValue {
+    let init = Object::new();
+    let properties = [
+        ("candidate", JsValue::from_str(&ice_candidate.candidate)),
+        (
+            "sdpMid",
+            ice_candidate
+                .sdp_mid
+                .as_deref()
+                .map_or(JsValue::NULL, JsValue::from_str),
+        ),
+        (
+            "sdpMLineIndex",
+            ice_candidate
+                .sdp_m_line_index
+                .map_or(JsValue::NULL, JsValue::from),
+        ),
+    ];
+    for (key, value) in properties {
+        // setting a property of a plain object can't throw
+        let _is_set = Reflect::set(&init, &JsValue::from_str(key), &value).unwrap_or(false);
+    }
+    init.into()
+}
+
+/// Value of `key`, `None` if it's missing or `null`.
+fn get_optional_property(js: &JsValue, key: &str) -> crate::Result<Option<JsValue>> {
+    let value = Reflect::get(js, &JsValue::from_str(key))
+        .map_err(|err| anyhow!("failed to get value for '{}' key: {:?}", key, err))?;
+    Ok(Some(value).filter(|value| !value.is_null() && !value.is_undefined()))
+}
+
+fn munge_sdp(on_sdp: Option<&SdpMunger>, sdp: String) -> String {
+    match on_sdp {
+        Some(munger) => munger.munge(sdp),
+        None => sdp,
+    }
+}
+
+/// Resolves once `websocket` is open, errs if it fails to connect first.
+pub async fn wait_for_websocket_open(websocket: &WebSocket) -> crate::Result<()> {
+    if websocket.ready_state() == WebSocket::OPEN {
+        return Ok(());
+    }
+    let opened = Promise::new(&mut |resolve, reject| {
+        websocket.set_onopen(Some(&resolve));
+        websocket.set_onerror(Some(&reject));
+    });
+    let result = JsFuture::from(opened).await;
+    websocket.set_onopen(None);
+    websocket.set_onerror(None);
+    result
+        .map(|_| ())
+        .map_err(|err| anyhow!("failed to connect to signaling server: {:?}", err))
+}
+
+/// Resolves after `duration` passes, using `setTimeout`.
+pub async fn sleep(duration: Duration) -> crate::Result<()> {
+    let window = web_sys::window().ok_or_else(|| anyhow!("no global window object"))?;
+    let timeout = i32::try_from(duration.as_millis()).unwrap_or(i32::MAX);
+    let mut result = Ok(0);
+    let elapsed = Promise::new(&mut |resolve, _reject| {
+        result = window.set_timeout_with_callback_and_timeout_and_arguments_0(&resolve, timeout);
+    });
+    result.map_err(|err| anyhow!("failed to set timeout: {:?}", err))?;
+    JsFuture::from(elapsed)
+        .await
+        .map(|_| ())
+        .map_err(|err| anyhow!("timeout failed: {:?}", err))
+}
+
+/// Configuration of data channels created by network managers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataChannelConfig {
+    /// Label shown e.g. in `chrome://webrtc-internals`, session id by default.
+    /// In one-to-many and many-to-many topologies id of the other peer is appended to it,
+    /// e.g. `chess-game-data-2`.
+    pub label: Option<String>,
+    /// Application protocol spoken over data channels, e.g. `game-state-v2`, which the other peer
+    /// can read from `RTCDataChannel.protocol` without parsing the label. Empty by default, as in the `WebRTC` spec.
+    pub protocol: Option<String>,
+    /// Largest serialized message that can be sent, in bytes, 64 KiB by default, within limits of current browsers.
+    /// Larger messages are refused with [`MessageTooLargeError`] before reaching the data channel,
+    /// so large payloads have to be split into chunks. Limit of a particular connection
+    /// can be read with `negotiated_max_message_size` of network managers.
+    pub max_message_size: usize,
+    /// How long `send_message_reliably` of network managers waits for acknowledgement before resending
+    /// the message, 250 ms by default, doubled after each attempt.
+    pub ack_timeout: Option<Duration>,
+    /// How many times `send_message_reliably` of network managers sends the message before giving up,
+    /// 5 by default.
+    pub max_send_attempts: Option<u32>,
+    /// Batches messages sent to a peer in quick succession into a single data channel message, disabled by default.
+    /// Peers using versions of the library from before batching was introduced drop batched messages.
+    pub batching: Option<BatchConfig>,
+    /// Modifies every SDP offer and answer created by the browser before it's set as the local description
+    /// and sent to the other peer, e.g. to remove IPv6 candidates or add bandwidth constraints.
+    /// SDP is used as created by default.
+    ///
+    /// # Warning
+    /// Modifying SDP can cause connections to fail, browsers only accept some changes of it.
+    pub on_sdp: Option<SdpMunger>,
+    /// Called whenever the browser requests renegotiation of an established connection, e.g. after a media track
+    /// or another data channel was added to it, right before the new offer is created and sent to the other peer,
+    /// which answers it on its own. Never called for the initial offer. Only logged by default.
+    pub on_negotiation_needed: Option<NegotiationNeededHandler>,
+}
+
+/// Function modifying SDP, see [`DataChannelConfig::on_sdp`].
+#[derive(Clone)]
+pub struct SdpMunger(Rc<RefCell<dyn FnMut(String) -> String>>);
+
+impl SdpMunger {
+    pub fn new(munge: impl FnMut(String) -> String + 'static) -> Self {
+        Self(Rc::new(RefCell::new(munge)))
+    }
+
+    pub(crate) fn munge(&self, sdp: String) -> String {
+        (self.0.borrow_mut())(sdp)
+    }
+}
+
+impl Debug for SdpMunger {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SdpMunger(..)")
+    }
+}
+
+/// Configs are equal only if they share the same function.
+impl PartialEq for SdpMunger {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::as_ptr(&self.0).cast::<()>() == Rc::as_ptr(&other.0).cast::<()>()
+    }
+}
+
+impl Eq for SdpMunger {}
+
+/// Function called on renegotiation, see [`DataChannelConfig::on_negotiation_needed`].
+#[derive(Clone)]
+pub struct NegotiationNeededHandler(Rc<RefCell<dyn FnMut()>>);
+
+impl NegotiationNeededHandler {
+    pub fn new(handler: impl FnMut() + 'static) -> Self {
+        Self(Rc::new(RefCell::new(handler)))
+    }
+
+    pub(crate) fn call(&self) {
+        (self.0.borrow_mut())();
+    }
+}
+
+impl Debug for NegotiationNeededHandler {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("NegotiationNeededHandler(..)")
+    }
+}
+
+/// Configs are equal only if they share the same function.
+impl PartialEq for NegotiationNeededHandler {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::as_ptr(&self.0).cast::<()>() == Rc::as_ptr(&other.0).cast::<()>()
+    }
+}
+
+impl Eq for NegotiationNeededHandler {}
+
+/// Configuration of batching messages, see [`DataChannelConfig::batching`].
+///
+/// Messages sent to a peer are collected until the next animation frame, or `max_latency` if it comes sooner,
+/// then sent in a single data channel message. Pending batches can also be sent right away
+/// with `flush` methods of network managers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchConfig {
+    /// Largest batch in bytes, 16 KiB by default, never more than [`DataChannelConfig::max_message_size`].
+    /// Message that doesn't fit in the current batch starts the next one.
+    pub max_batch_bytes: usize,
+    /// Longest time a message waits in a batch, 50 ms by default,
+    /// as animation frames don't come while the page is in the background.
+    pub max_latency: Duration,
+}
+
+impl Default for BatchConfig {
+    fn default() -> Self {
+        Self {
+            max_batch_bytes: DEFAULT_MAX_BATCH_BYTES,
+            max_latency: DEFAULT_BATCH_MAX_LATENCY,
+        }
+    }
+}
+
+impl Default for DataChannelConfig {
+    fn default() -> Self {
+        Self {
+            label: None,
+            protocol: None,
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            ack_timeout: None,
+            max_send_attempts: None,
+            batching: None,
+            on_sdp: None,
+            on_negotiation_needed: None,
+        }
+    }
+}
+
+impl DataChannelConfig {
+    pub(crate) fn label_or_session_id(&self, session_id: SessionId) -> String {
+        self.label.clone().unwrap_or_else(|| session_id.to_string())
+    }
+
+    /// Serializes user message, refusing it if it's larger than [`DataChannelConfig::max_message_size`].
+    pub(crate) fn serialize_message<T: Serialize + ?Sized>(
+        &self,
+        message: &T,
+    ) -> crate::Result<Vec<u8>> {
+        let message = serialize_message(message)?;
+        if message.len() > self.max_message_size {
+            return Err(MessageTooLargeError {
+                size: message.len(),
+                limit: self.max_message_size,
+            }
+            .into());
+        }
+        Ok(message)
+    }
+
+    /// Largest batch that can be sent, as batches are subject to `max_message_size` like any other message.
+    pub(crate) fn max_batch_bytes(&self, batching: &BatchConfig) -> usize {
+        batching.max_batch_bytes.min(self.max_message_size)
+    }
+}
+
+/// Largest message the other peer accepts, negotiated by the SCTP transport of `peer_connection`.
+/// `None` until the transport is established, or if browser doesn't expose it.
+#[allow(
+    clippy::as_conversions,
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss
+)]
+pub fn negotiated_max_message_size(peer_connection: &RtcPeerConnection) -> Option<usize> {
+    let sctp = Reflect::get(peer_connection, &JsValue::from_str("sctp")).ok()?;
+    let max_message_size = Reflect::get(&sctp, &JsValue::from_str("maxMessageSize"))
+        .ok()?
+        .as_f64()?;
+    // infinity, meaning there is no limit, saturates to `usize::MAX`
+    Some(max_message_size as usize)
+}
+
+/// Application protocol of `data_channel`, which `web-sys` has no getter for.
+/// Empty if none was set, or if browser doesn't expose it.
+pub fn data_channel_protocol(data_channel: &RtcDataChannel) -> String {
+    Reflect::get(data_channel, &JsValue::from_str("protocol"))
+        .ok()
+        .and_then(|protocol| protocol.as_string())
+        .unwrap_or_default()
+}
+
+/// Data channel options shared by all topologies.
+/// `MessagePack` messages use unordered delivery, JSON text messages keep the default ordered one.
+pub fn create_data_channel_init(
+    max_retransmits: u16,
+    config: &DataChannelConfig,
+) -> RtcDataChannelInit {
+    let mut init = RtcDataChannelInit::new();
+    init.max_retransmits(max_retransmits);
+    if let Some(ref protocol) = config.protocol {
+        init.protocol(protocol);
+    }
+    if !cfg!(feature = "json-messages") {
+        init.ordered(false);
+    }
+    init
+}
+
+/// Serializes user message with `MessagePack`.
+#[cfg(not(feature = "json-messages"))]
+pub fn serialize_message<T: Serialize + ?Sized>(message: &T) -> crate::Result<Vec<u8>> {
+    Ok(rmp_serde::to_vec(message)?)
+}
+
+/// Serializes user message with JSON.
+#[cfg(feature = "json-messages")]
+pub fn serialize_message<T: Serialize + ?Sized>(message: &T) -> crate::Result<Vec<u8>> {
+    Ok(serde_json::to_vec(message)?)
+}
+
+/// Sends already serialized message as binary data.
+#[cfg(not(feature = "json-messages"))]
+pub fn send_serialized_message(data_channel: &RtcDataChannel, message: &[u8]) -> crate::Result<()> {
+    data_channel
+        .send_with_u8_array(message)
+        .map_err(|err| anyhow!("failed to send message: {:?}", err))
+}
+
+/// Starts text frames of the library, e.g. pings and acknowledgements of reliable messages,
+/// so user messages sent as text never do.
+#[cfg(feature = "json-messages")]
+const CONTROL_TEXT_PREFIX: char = '\0';
+
+/// Sends already serialized message as text data.
+///
+/// # Errors
+/// Text starting with [`CONTROL_TEXT_PREFIX`] is refused, the other peer would take it for a control frame.
+#[cfg(feature = "json-messages")]
+pub fn send_serialized_message(data_channel: &RtcDataChannel, message: &[u8]) -> crate::Result<()> {
+    let message = std::str::from_utf8(message)?;
+    if message.starts_with(CONTROL_TEXT_PREFIX) {
+        return Err(anyhow!(
+            "text messages starting with NUL are reserved for control frames"
+        ));
+    }
+    data_channel
+        .send_with_str(message)
+        .map_err(|err| anyhow!("failed to send message: {:?}", err))
+}
+
+/// Deserializes data received on a data channel, `None` if it's not a valid `MessagePack` message.
+#[cfg(not(feature = "json-messages"))]
+pub fn deserialize_message<T: DeserializeOwned>(data: &JsValue) -> Option<T> {
+    let message = data.dyn_ref::<Uint8Array>()?;
+    deserialize_serialized_message(&message.to_vec())
+}
+
+/// Deserializes message serialized by the other peer, e.g. relayed by an intermediary.
+#[cfg(not(feature = "json-messages"))]
+pub fn deserialize_serialized_message<T: DeserializeOwned>(message: &[u8]) -> Option<T> {
+    rmp_serde::from_slice(message).ok()
+}
+
+/// Deserializes data received on a data channel, `None` if it's not a valid JSON message.
+#[cfg(feature = "json-messages")]
+pub fn deserialize_message<T: DeserializeOwned>(data: &JsValue) -> Option<T> {
+    let message = data.as_string()?;
+    deserialize_serialized_message(message.as_bytes())
+}
+
+/// Deserializes message serialized by the other peer, e.g. relayed by an intermediary.
+#[cfg(feature = "json-messages")]
+pub fn deserialize_serialized_message<T: DeserializeOwned>(message: &[u8]) -> Option<T> {
+    serde_json::from_slice(message).ok()
+}
+
+/// Message serialized by the other peer and carried by a library frame, in the form user messages are received in.
+#[cfg(not(feature = "json-messages"))]
+pub fn serialized_message_to_js(message: &[u8]) -> JsValue {
+    Uint8Array::from(message).into()
+}
+
+/// Message serialized by the other peer and carried by a library frame, in the form user messages are received in.
+#[cfg(feature = "json-messages")]
+pub fn serialized_message_to_js(message: &[u8]) -> JsValue {
+    std::str::from_utf8(message).map_or(JsValue::UNDEFINED, JsValue::from_str)
+}
+
+/// Whether binary `data` received on a data channel starts with `marker` of a library frame,
+/// checked without copying it.
+pub fn has_frame_marker(data: &JsValue, marker: [u8; 2]) -> bool {
+    if !(data.is_instance_of::<ArrayBuffer>() || data.is_instance_of::<Uint8Array>()) {
+        return false;
+    }
+    let bytes = Uint8Array::new(data);
+    bytes.length() > 1 && bytes.get_index(0) == marker[0] && bytes.get_index(1) == marker[1]
+}
+
+/// State of the "perfect negotiation" pattern for a single peer connection.
+///
+/// Peer that sent the initial offer is impolite and ignores offers colliding with its own,
+/// the other one is polite and always accepts them, rolling back its own offer if needed.
+#[derive(Debug, Default)]
+pub struct Negotiation {
+    making_offer: Cell<bool>,
+    polite: Cell<bool>,
+}
+
+impl Negotiation {
+    pub fn new(polite: bool) -> Self {
+        Self {
+            making_offer: Cell::new(false),
+            polite: Cell::new(polite),
+        }
+    }
+
+    #[cfg(feature = "one-to-one")]
+    pub fn set_polite(&self, polite: bool) {
+        self.polite.set(polite);
+    }
+
+    /// Whether an incoming offer collides with our own and should be dropped.
+    pub fn ignore_offer(&self, peer_connection: &RtcPeerConnection) -> bool {
+        let offer_collision = self.making_offer.get()
+            || peer_connection.signaling_state() != RtcSignalingState::Stable;
+        offer_collision && !self.polite.get()
+    }
+}
+
+/// Renegotiates the connection whenever browser requests it after the initial negotiation,
+/// e.g. when a new data channel or track is added to an established connection.
+/// The offer is passed to `send_offer` which should deliver it through the signaling server,
+/// `on_renegotiation` is called before each offer is created, `on_sdp` modifies it if any.
+pub fn set_peer_connection_on_negotiation_needed(
+    peer_connection: &RtcPeerConnection,
+    negotiation: Rc<Negotiation>,
+    send_offer: impl Fn(String) -> crate::Result<()> + 'static,
+    mut on_renegotiation: impl FnMut() + 'static,
+    on_sdp: Option<SdpMunger>,
+) {
+    let peer_connection_clone = peer_connection.clone();
+    let send_offer = Rc::new(send_offer);
     let on_negotiation_needed: Box<dyn FnMut()> = Box::new(move || {
         debug!("on negotiation needed event occurred");
+        // initial offer is sent by the signaling flow once both peers are in session
+        if peer_connection_clone.remote_description().is_none() || negotiation.making_offer.get() {
+            return;
+        }
+        on_renegotiation();
+        let offering_peer_connection = peer_connection_clone.clone();
+        let negotiation = Rc::clone(&negotiation);
+        let send_offer = Rc::clone(&send_offer);
+        let on_sdp = on_sdp.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            negotiation.making_offer.set(true);
+            let result = match create_sdp_offer(&offering_peer_connection, on_sdp.as_ref()).await {
+                Ok(offer) => send_offer(offer),
+                Err(err) => Err(err),
+            };
+            negotiation.making_offer.set(false);
+            if let Err(err) = result {
+                error!("failed to renegotiate peer connection: {:?}", err);
+            }
+        });
     });
     let on_negotiation_needed = Closure::wrap(on_negotiation_needed);
     peer_connection.set_onnegotiationneeded(Some(on_negotiation_needed.as_ref().unchecked_ref()));
     on_negotiation_needed.forget();
 }
 
-pub fn set_peer_connection_on_ice_gathering_state_change(peer_connection: &RtcPeerConnection) {
+/// Calls `on_complete` once `peer_connection` gathers all of its ICE candidates.
+pub fn set_peer_connection_on_ice_gathering_state_change(
+    peer_connection: &RtcPeerConnection,
+    mut on_complete: impl FnMut() + 'static,
+) {
     let peer_connection_clone = peer_connection.clone();
     let on_ice_gathering_state_change: Box<dyn FnMut()> = Box::new(move || {
-        debug!(
-            "ice gathering state: {:?}",
-            peer_connection_clone.ice_gathering_state()
-        );
+        let state = peer_connection_clone.ice_gathering_state();
+        debug!("ice gathering state: {:?}", state);
+        if state == RtcIceGatheringState::Complete {
+            on_complete();
+        }
     });
     let on_ice_gathering_state_change = Closure::wrap(on_ice_gathering_state_change);
     peer_connection.set_onicegatheringstatechange(Some(
@@ -206,6 +935,9 @@ pub fn set_peer_connection_on_ice_gathering_state_change(peer_connection: &RtcPe
 
 #[cfg(test)]
 mod test {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
     use wasm_bindgen_test::{wasm_bindgen_test, wasm_bindgen_test_configure};
     use web_sys::{RtcIceConnectionState, RtcIceGatheringState};
 
@@ -213,6 +945,19 @@ mod test {
 
     wasm_bindgen_test_configure!(run_in_browser);
 
+    #[cfg(feature = "json-messages")]
+    #[wasm_bindgen_test]
+    fn test_text_reserved_for_control_frames_is_not_sent() {
+        let peer_connection = RtcPeerConnection::new().expect("failed to create peer connection");
+        let data_channel = peer_connection.create_data_channel("reserved");
+        let reserved =
+            send_serialized_message(&data_channel, b"\0ack:1").expect_err("reserved text was sent");
+        assert_eq!(
+            reserved.to_string(),
+            "text messages starting with NUL are reserved for control frames"
+        );
+    }
+
     #[wasm_bindgen_test]
     fn test_create_stun_peer_connection_is_successful() {
         let peer_connection = create_peer_connection(&ConnectionType::Local)
@@ -230,7 +975,7 @@ mod test {
     #[wasm_bindgen_test]
     async fn test_create_sdp_offer_is_successful() {
         let peer_connection = RtcPeerConnection::new().expect("failed to create peer connection");
-        let _offer = create_sdp_offer(&peer_connection)
+        let _offer = create_sdp_offer(&peer_connection, None)
             .await
             .expect("failed to create SDP offer");
         assert!(peer_connection.local_description().is_some());
@@ -239,13 +984,212 @@ mod test {
     #[wasm_bindgen_test]
     async fn test_create_sdp_answer_is_successful() {
         let peer_connection = RtcPeerConnection::new().expect("failed to create peer connection");
-        let offer = create_sdp_offer(&peer_connection)
+        let offer = create_sdp_offer(&peer_connection, None)
             .await
             .expect("failed to create SDP offer");
-        let _answer = create_sdp_answer(&peer_connection, offer)
+        let _answer = create_sdp_answer(&peer_connection, offer, None)
             .await
             .expect("failed to create SDP answer");
         assert!(peer_connection.local_description().is_some());
         assert!(peer_connection.remote_description().is_some());
     }
+
+    #[wasm_bindgen_test]
+    async fn test_created_sdp_offer_is_munged_before_set_as_local_description() {
+        let peer_connection = RtcPeerConnection::new().expect("failed to create peer connection");
+        let _data_channel = peer_connection.create_data_channel("munged");
+        let munged = Rc::new(RefCell::new(Vec::new()));
+        let on_sdp = SdpMunger::new({
+            let munged = Rc::clone(&munged);
+            move |sdp: String| {
+                // removes IPv6 candidates, there are none before gathering, so SDP stays valid
+                let sdp: String = sdp
+                    .split_inclusive("\r\n")
+                    .filter(|line| {
+                        !line.starts_with("a=candidate:")
+                            || !line
+                                .split(' ')
+                                .nth(4)
+                                .map_or(false, |address| address.contains(':'))
+                    })
+                    .collect();
+                munged.borrow_mut().push(sdp.clone());
+                sdp
+            }
+        });
+        let offer = create_sdp_offer(&peer_connection, Some(&on_sdp))
+            .await
+            .expect("failed to create SDP offer");
+        assert_eq!(*munged.borrow(), vec![offer.clone()]);
+        assert_eq!(
+            peer_connection
+                .local_description()
+                .map(|description| description.sdp()),
+            Some(offer)
+        );
+        assert_eq!(on_sdp, on_sdp.clone());
+        assert_ne!(on_sdp, SdpMunger::new(|sdp| sdp));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_connection_type_from_js_object_is_successful() {
+        let js = Object::new();
+        Reflect::set(&js, &"type".into(), &"stun".into()).expect("failed to set 'type' key");
+        Reflect::set(&js, &"urls".into(), &"stun:stun.example.com".into())
+            .expect("failed to set 'urls' key");
+        let connection_type =
+            ConnectionType::from_js_object(&js).expect("failed to read connection type");
+        assert!(matches!(
+            connection_type,
+            ConnectionType::Stun { ref urls } if urls == "stun:stun.example.com"
+        ));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_connection_type_from_js_object_fails_on_missing_keys() {
+        let js = Object::new();
+        Reflect::set(&js, &"type".into(), &"stunAndTurn".into()).expect("failed to set 'type' key");
+        let missing_key =
+            ConnectionType::from_js_object(&js).expect_err("missing keys were accepted");
+        assert_eq!(
+            missing_key.to_string(),
+            "'stunUrls' key is missing or is not a string"
+        );
+        let not_object =
+            ConnectionType::from_js_object(&JsValue::NULL).expect_err("null was accepted");
+        assert!(
+            not_object
+                .to_string()
+                .starts_with("failed to get value for 'type' key"),
+            "unexpected error: {not_object}"
+        );
+    }
+
+    #[wasm_bindgen_test]
+    fn test_ice_candidate_round_trips_through_browser_representation() {
+        let ice_candidate = IceCandidate {
+            candidate: "candidate:1 1 udp 2122260223 192.168.1.2 54321 typ host".to_owned(),
+            sdp_mid: Some("0".to_owned()),
+            sdp_m_line_index: Some(0),
+        };
+        let rtc_candidate = RtcIceCandidate::new(
+            ice_candidate_to_js_value(&ice_candidate).unchecked_ref::<RtcIceCandidateInit>(),
+        )
+        .expect("failed to create RTC ICE candidate");
+        assert_eq!(
+            ice_candidate_from_js_value(&rtc_candidate).expect("failed to parse RTC ICE candidate"),
+            ice_candidate
+        );
+        let json = rtc_candidate.to_json();
+        assert_eq!(
+            ice_candidate_from_js_value(&json).expect("failed to parse ICE candidate object"),
+            ice_candidate
+        );
+        let stringified = JSON::stringify(&json).expect("failed to stringify ICE candidate");
+        assert_eq!(
+            ice_candidate_from_js_value(&stringified).expect("failed to parse ICE candidate JSON"),
+            ice_candidate
+        );
+    }
+
+    #[wasm_bindgen_test]
+    fn test_ice_candidate_from_js_value_fails_on_invalid_keys() {
+        let js = Object::new();
+        Reflect::set(&js, &"candidate".into(), &"candidate:1".into())
+            .expect("failed to set 'candidate' key");
+        Reflect::set(&js, &"sdpMid".into(), &JsValue::NULL).expect("failed to set 'sdpMid' key");
+        assert_eq!(
+            ice_candidate_from_js_value(&js).expect("failed to parse ICE candidate"),
+            IceCandidate {
+                candidate: "candidate:1".to_owned(),
+                sdp_mid: None,
+                sdp_m_line_index: None,
+            }
+        );
+        Reflect::set(&js, &"sdpMLineIndex".into(), &1.5.into())
+            .expect("failed to set 'sdpMLineIndex' key");
+        ice_candidate_from_js_value(&js).expect_err("fractional index was accepted");
+        ice_candidate_from_js_value(&Object::new()).expect_err("missing candidate was accepted");
+        ice_candidate_from_js_value(&"not json".into()).expect_err("invalid JSON was accepted");
+    }
+
+    #[wasm_bindgen_test]
+    fn test_connection_type_hides_turn_credential() {
+        let connection_type = ConnectionType::StunAndTurn {
+            stun_urls: "stun:stun.example.com".to_owned(),
+            turn_urls: "turn:turn.example.com".to_owned(),
+            username: "user".to_owned(),
+            credential: "secret-credential".to_owned(),
+        };
+        let debug = format!("{:?}", connection_type);
+        assert!(debug.contains("turn:turn.example.com"));
+        assert!(!debug.contains("secret"));
+        assert_eq!(
+            connection_type.to_string(),
+            "STUN via stun:stun.example.com with TURN via turn:turn.example.com"
+        );
+    }
+
+    #[wasm_bindgen_test]
+    fn test_batches_are_not_larger_than_messages() {
+        let batching = BatchConfig::default();
+        let config = DataChannelConfig {
+            max_message_size: 1024,
+            ..DataChannelConfig::default()
+        };
+        assert_eq!(config.max_batch_bytes(&batching), 1024);
+        assert_eq!(
+            DataChannelConfig::default().max_batch_bytes(&batching),
+            batching.max_batch_bytes
+        );
+    }
+
+    fn hash_of(connection_type: &ConnectionType) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        connection_type.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[wasm_bindgen_test]
+    fn test_identical_connection_types_hash_equally() {
+        let first = ConnectionType::Stun {
+            urls: "stun:stun.example.com".to_owned(),
+        };
+        let second = ConnectionType::Stun {
+            urls: "stun:stun.example.com".to_owned(),
+        };
+        assert_eq!(first, second);
+        assert_eq!(hash_of(&first), hash_of(&second));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_different_connection_types_hash_differently() {
+        let first = ConnectionType::Stun {
+            urls: "stun:stun.example.com".to_owned(),
+        };
+        let second = ConnectionType::Stun {
+            urls: "stun:stun.example.org".to_owned(),
+        };
+        assert_ne!(first, second);
+        assert_ne!(hash_of(&first), hash_of(&second));
+        assert_ne!(hash_of(&first), hash_of(&ConnectionType::Local));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_serialized_message_round_trips() {
+        let message = vec!["ping".to_owned(), "pong".to_owned()];
+        let serialized = serialize_message(&message).expect("failed to serialize message");
+        #[cfg(not(feature = "json-messages"))]
+        let data: JsValue = Uint8Array::from(serialized.as_slice()).into();
+        #[cfg(feature = "json-messages")]
+        let data = JsValue::from_str(std::str::from_utf8(&serialized).expect("invalid UTF-8"));
+        assert_eq!(deserialize_message::<Vec<String>>(&data), Some(message));
+    }
+
+    #[cfg(feature = "json-messages")]
+    #[wasm_bindgen_test]
+    fn test_json_message_is_plain_text() {
+        let serialized = serialize_message("ping!").expect("failed to serialize message");
+        assert_eq!(serialized, br#""ping!""#);
+    }
 }
@@ -0,0 +1,362 @@
+/*!
+Connection with signaling server kept in a dedicated Web Worker, so that its `WebSocket` traffic
+and waiting for it don't run on the main thread.
+
+The boundary is the signaling connection: `RTCPeerConnection` isn't available in workers of every browser,
+so peer connections, data channels and network managers stay on the main thread, and so does (de)serialization
+of user messages, which happens on the thread their callbacks run on. Worker only owns the `WebSocket`.
+
+Worker script loads the library and calls [`serve_signaling`] once. Main thread then opens connections through it
+with [`SignalingClient::in_worker`](crate::SignalingClient::in_worker) and creates network managers from the client.
+Each connection gets a `MessageChannel` of its own, signal messages are passed through it as transferred `ArrayBuffer`s
+and connection events as `open` and `close` strings.
+
+# Example
+
+```no_run
+// worker.rs, built into the script the worker runs
+use wasm_bindgen::prelude::wasm_bindgen;
+
+#[wasm_bindgen(start)]
+pub fn start() {
+    wasm_peers::worker::serve_signaling().expect("failed to serve signaling");
+}
+```
+
+```no_run
+// main thread
+use wasm_peers::{ConnectionType, SessionId, SignalingClient};
+use web_sys::Worker;
+
+let worker = Worker::new("./worker.js").expect("failed to start worker");
+let signaling = SignalingClient::in_worker(&worker, "ws://0.0.0.0:9001/one-to-one")
+    .expect("failed to connect to signaling server");
+let peer = signaling
+    .one_to_one(SessionId::new(1), ConnectionType::Local)
+    .expect("failed to create peer connection");
+```
+*/
+
+use std::cell::{Cell, RefCell};
+use std::fmt::{Debug, Formatter};
+use std::mem;
+use std::rc::Rc;
+
+use anyhow::anyhow;
+use futures_util::future::LocalBoxFuture;
+use futures_util::FutureExt;
+use js_sys::{Array, ArrayBuffer, Function, Promise, Uint8Array};
+use log::{debug, error};
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    DedicatedWorkerGlobalScope, MessageChannel, MessageEvent, MessagePort, WebSocket, Worker,
+};
+
+use crate::transport::{OnMessage, WebSocketLike};
+
+/// Tells requests to open a connection apart from other messages the worker receives
+const CONNECT_TAG: &str = "wasm-peers-signaling";
+const OPEN_EVENT: &str = "open";
+const CLOSE_EVENT: &str = "close";
+
+/// Message asking the worker to connect to `url` and pass messages through `port`.
+fn connect_request(url: &str, port: &MessagePort) -> Array {
+    Array::of3(&CONNECT_TAG.into(), &url.into(), port)
+}
+
+fn parse_connect_request(data: &JsValue) -> Option<(String, MessagePort)> {
+    let request = data.dyn_ref::<Array>()?;
+    if request.length() != 3 || request.get(0).as_string().as_deref() != Some(CONNECT_TAG) {
+        return None;
+    }
+    Some((request.get(1).as_string()?, request.get(2).dyn_into().ok()?))
+}
+
+/// Posts a copy of `message` through `port`, transferring the copy's buffer.
+fn post_bytes(port: &MessagePort, message: &[u8]) -> crate::Result<()> {
+    post_buffer(port, &Uint8Array::from(message).buffer())
+}
+
+fn post_buffer(port: &MessagePort, buffer: &ArrayBuffer) -> crate::Result<()> {
+    port.post_message_with_transferable(buffer, &Array::of1(buffer))
+        .map_err(|err| anyhow!("failed to post message to the other thread: {:?}", err))
+}
+
+/// Opens connections with signaling server requested by [`SignalingClient::in_worker`](crate::SignalingClient::in_worker),
+/// to be called once in a dedicated worker.
+///
+/// Other messages the worker receives are left to its own handlers.
+///
+/// # Errors
+/// This function errs if it's not called in a dedicated worker.
+pub fn serve_signaling() -> crate::Result<()> {
+    let scope: DedicatedWorkerGlobalScope = js_sys::global().dyn_into().map_err(|err| {
+        anyhow!(
+            "signaling can only be served in a dedicated worker: {:?}",
+            err
+        )
+    })?;
+    let on_message: Box<dyn FnMut(MessageEvent)> = Box::new(|ev: MessageEvent| {
+        let Some((url, port)) = parse_connect_request(&ev.data()) else {
+            return;
+        };
+        if let Err(err) = connect(&url, &port) {
+            error!("failed to connect to signaling server on {}: {}", url, err);
+        }
+    });
+    let on_message = Closure::wrap(on_message);
+    scope
+        .add_event_listener_with_callback("message", on_message.as_ref().unchecked_ref())
+        .map_err(|err| anyhow!("failed to listen for messages: {:?}", err))?;
+    on_message.forget();
+    Ok(())
+}
+
+/// Opens `WebSocket` connection with signaling server at `url`, passing its messages and events through `port`.
+fn connect(url: &str, port: &MessagePort) -> crate::Result<()> {
+    let websocket = match WebSocket::new(url) {
+        Ok(websocket) => websocket,
+        Err(err) => {
+            let _result = port.post_message(&CLOSE_EVENT.into());
+            return Err(anyhow!("failed to create websocket: {:?}", err));
+        }
+    };
+    websocket.set_binary_type(web_sys::BinaryType::Arraybuffer);
+
+    let event_forwarder = |event: &'static str| {
+        let port = port.clone();
+        let forward: Box<dyn FnMut(JsValue)> = Box::new(move |_| {
+            if let Err(err) = port.post_message(&event.into()) {
+                error!("failed to pass {} event to main thread: {:?}", event, err);
+            }
+        });
+        Closure::wrap(forward)
+    };
+    let on_open = event_forwarder(OPEN_EVENT);
+    websocket.set_onopen(Some(on_open.as_ref().unchecked_ref()));
+    on_open.forget();
+    let on_close = event_forwarder(CLOSE_EVENT);
+    websocket.set_onclose(Some(on_close.as_ref().unchecked_ref()));
+    on_close.forget();
+
+    let on_message: Box<dyn FnMut(MessageEvent)> = {
+        let port = port.clone();
+        Box::new(
+            move |ev: MessageEvent| match ev.data().dyn_into::<ArrayBuffer>() {
+                Ok(buffer) => {
+                    if let Err(err) = post_buffer(&port, &buffer) {
+                        error!("{}", err);
+                    }
+                }
+                Err(_) => error!("failed to convert message to ArrayBuffer"),
+            },
+        )
+    };
+    let on_message = Closure::wrap(on_message);
+    websocket.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+    on_message.forget();
+
+    let on_port_message: Box<dyn FnMut(MessageEvent)> = {
+        let port = port.clone();
+        Box::new(move |ev: MessageEvent| {
+            let data = ev.data();
+            if let Some(buffer) = data.dyn_ref::<ArrayBuffer>() {
+                if let Err(err) = websocket.send_with_array_buffer(buffer) {
+                    error!("failed to send message across the websocket: {:?}", err);
+                }
+            } else if data.as_string().as_deref() == Some(CLOSE_EVENT) {
+                debug!("main thread closed signaling server connection");
+                websocket.set_onmessage(None);
+                websocket.set_onclose(None);
+                if let Err(err) = websocket.close() {
+                    error!("failed to close signaling server connection: {:?}", err);
+                }
+                port.close();
+            }
+        })
+    };
+    let on_port_message = Closure::wrap(on_port_message);
+    port.set_onmessage(Some(on_port_message.as_ref().unchecked_ref()));
+    on_port_message.forget();
+    Ok(())
+}
+
+/// Callbacks of a [`WorkerWebSocket`], along with those waiting for it to open.
+#[derive(Default)]
+struct Handlers {
+    on_open: Option<Box<dyn FnMut()>>,
+    on_message: Option<OnMessage>,
+    on_close: Option<Box<dyn FnMut()>>,
+    /// Resolve functions of promises returned by `wait_for_open`, called with `true` once it opens
+    /// and with `false` once it closes
+    open_waiters: Vec<Function>,
+}
+
+/// Connection with signaling server opened by a worker serving signaling, seen from the main thread.
+pub struct WorkerWebSocket {
+    port: MessagePort,
+    /// One of `WebSocket` ready state constants
+    ready_state: Rc<Cell<u16>>,
+    handlers: Rc<RefCell<Handlers>>,
+}
+
+impl Debug for WorkerWebSocket {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WorkerWebSocket")
+            .field("ready_state", &self.ready_state_name())
+            .finish_non_exhaustive()
+    }
+}
+
+impl WorkerWebSocket {
+    /// Asks `worker` to open connection with signaling server at `url`.
+    ///
+    /// # Errors
+    /// This function errs if creating a `MessageChannel` or posting the request to `worker` fails.
+    pub fn open(worker: &Worker, url: &str) -> Result<Self, JsValue> {
+        let channel = MessageChannel::new()?;
+        let websocket = Self {
+            port: channel.port1(),
+            ready_state: Rc::new(Cell::new(WebSocket::CONNECTING)),
+            handlers: Rc::default(),
+        };
+        websocket.listen();
+        let port = channel.port2();
+        worker.post_message_with_transfer(&connect_request(url, &port), &Array::of1(&port))?;
+        Ok(websocket)
+    }
+
+    fn listen(&self) {
+        let ready_state = Rc::clone(&self.ready_state);
+        let handlers = Rc::clone(&self.handlers);
+        let on_message: Box<dyn FnMut(MessageEvent)> = Box::new(move |ev: MessageEvent| {
+            if ready_state.get() == WebSocket::CLOSED {
+                return;
+            }
+            let data = ev.data();
+            if data.is_instance_of::<ArrayBuffer>() {
+                let message = Uint8Array::new(&data).to_vec();
+                if let Some(ref mut on_message) = handlers.borrow_mut().on_message {
+                    on_message(&message);
+                }
+                return;
+            }
+            let is_open = match data.as_string().as_deref() {
+                Some(OPEN_EVENT) => true,
+                Some(CLOSE_EVENT) => false,
+                _ => {
+                    error!("unexpected message from signaling worker: {:?}", data);
+                    return;
+                }
+            };
+            ready_state.set(if is_open {
+                WebSocket::OPEN
+            } else {
+                WebSocket::CLOSED
+            });
+            let waiters = mem::take(&mut handlers.borrow_mut().open_waiters);
+            for resolve in waiters {
+                let _result = resolve.call1(&JsValue::NULL, &JsValue::from_bool(is_open));
+            }
+            let mut handlers = handlers.borrow_mut();
+            let callback = if is_open {
+                &mut handlers.on_open
+            } else {
+                &mut handlers.on_close
+            };
+            if let Some(ref mut callback) = *callback {
+                callback();
+            }
+        });
+        let on_message = Closure::wrap(on_message);
+        self.port
+            .set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+        on_message.forget();
+    }
+}
+
+impl WebSocketLike for WorkerWebSocket {
+    fn send(&self, message: &[u8]) -> crate::Result<()> {
+        if self.ready_state.get() != WebSocket::OPEN {
+            return Err(anyhow!(
+                "failed to send message across the websocket: connection is {}",
+                self.ready_state_name()
+            ));
+        }
+        post_bytes(&self.port, message)
+    }
+
+    fn wait_for_open(&self) -> LocalBoxFuture<'_, crate::Result<()>> {
+        async move {
+            match self.ready_state.get() {
+                WebSocket::OPEN => return Ok(()),
+                WebSocket::CONNECTING => {}
+                _ => return Err(anyhow!("connection with signaling server is closed")),
+            }
+            let opened = Promise::new(&mut |resolve, _reject| {
+                self.handlers.borrow_mut().open_waiters.push(resolve);
+            });
+            let opened = JsFuture::from(opened)
+                .await
+                .map_err(|err| anyhow!("waiting for connection failed: {:?}", err))?;
+            if opened.is_truthy() {
+                Ok(())
+            } else {
+                Err(anyhow!("failed to connect to signaling server"))
+            }
+        }
+        .boxed_local()
+    }
+
+    fn set_on_open(&self, mut on_open: Box<dyn FnMut()>) {
+        if self.ready_state.get() == WebSocket::OPEN {
+            on_open();
+            return;
+        }
+        self.handlers.borrow_mut().on_open = Some(on_open);
+    }
+
+    fn set_on_message(&self, on_message: OnMessage) {
+        self.handlers.borrow_mut().on_message = Some(on_message);
+    }
+
+    fn set_on_close(&self, on_close: Box<dyn FnMut()>) {
+        self.handlers.borrow_mut().on_close = Some(on_close);
+    }
+
+    fn ready_state_name(&self) -> &'static str {
+        match self.ready_state.get() {
+            WebSocket::CONNECTING => "connecting",
+            WebSocket::OPEN => "open",
+            WebSocket::CLOSING => "closing",
+            _ => "closed",
+        }
+    }
+
+    fn close(&self) {
+        // stops passing messages on without calling `on_close`, like closing a `WebSocket` does
+        self.ready_state.set(WebSocket::CLOSED);
+        if let Err(err) = self.port.post_message(&CLOSE_EVENT.into()) {
+            error!("failed to close signaling server connection: {:?}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+
+    #[wasm_bindgen_test]
+    fn test_connect_requests_are_told_apart_from_other_messages() {
+        let channel = MessageChannel::new().expect("failed to create message channel");
+        let request = connect_request("ws://0.0.0.0:9001/one-to-one", &channel.port2());
+        let (url, _port) = parse_connect_request(&request).expect("failed to parse request");
+        assert_eq!(url, "ws://0.0.0.0:9001/one-to-one");
+        assert!(parse_connect_request(&JsValue::from_str(CONNECT_TAG)).is_none());
+        assert!(parse_connect_request(&Array::of2(&CONNECT_TAG.into(), &url.into())).is_none());
+    }
+}
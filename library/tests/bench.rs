@@ -0,0 +1,251 @@
+//! Throughput and latency benchmarks for the Web and headless browsers.
+//!
+//! Ignored by default, run them with `cargo xtask bench`. Each case prints a single line
+//! starting with `bench: ` followed by its JSON summary, so that results can be compared between changes.
+
+#![cfg(target_arch = "wasm32")]
+
+use std::cell::{Cell, RefCell};
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+use js_sys::Promise;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::JsValue;
+use wasm_bindgen_futures::JsFuture;
+use wasm_bindgen_test::{wasm_bindgen_test, wasm_bindgen_test_configure};
+use wasm_peers::test_utils::LoopbackSignaling;
+use wasm_peers::{one_to_many, one_to_one, ConnectionType, SessionId};
+use web_sys::console;
+
+wasm_bindgen_test_configure!(run_in_browser);
+
+/// Serialized size of benchmarked messages
+const MESSAGE_SIZES: [usize; 3] = [64, 1024, 16 * 1024];
+/// Room left for the timestamp and encoding of each message within its size
+const SAMPLE_OVERHEAD: usize = 48;
+const MESSAGES: usize = 1000;
+/// Messages sent before giving browser a chance to deliver them
+const BURST: usize = 50;
+const CLIENTS: usize = 4;
+/// How long to wait for the rest of messages, as partially reliable channels may drop some
+const TIMEOUT_MS: f64 = 10_000.0;
+
+#[derive(Serialize, Deserialize)]
+struct Sample {
+    /// `performance.now()` when the message was sent
+    sent_at: f64,
+    payload: String,
+}
+
+impl Sample {
+    fn new(message_size: usize) -> Self {
+        Self {
+            sent_at: now(),
+            payload: "x".repeat(message_size - SAMPLE_OVERHEAD),
+        }
+    }
+}
+
+/// Results of a single case, printed as JSON.
+#[derive(Serialize)]
+struct Summary {
+    topology: &'static str,
+    clients: usize,
+    message_size: usize,
+    sent: usize,
+    received: usize,
+    messages_per_sec: f64,
+    latency_p50_ms: f64,
+    latency_p90_ms: f64,
+    latency_p99_ms: f64,
+    latency_max_ms: f64,
+}
+
+/// Latencies of messages received by all peers, in milliseconds.
+#[derive(Default)]
+struct Latencies {
+    latencies: RefCell<Vec<f64>>,
+    last_received_at: Cell<f64>,
+}
+
+impl Latencies {
+    fn record(&self, sample: &Sample) {
+        let received_at = now();
+        self.latencies
+            .borrow_mut()
+            .push(received_at - sample.sent_at);
+        self.last_received_at.set(received_at);
+    }
+
+    fn len(&self) -> usize {
+        self.latencies.borrow().len()
+    }
+
+    fn summary(
+        &self,
+        topology: &'static str,
+        clients: usize,
+        message_size: usize,
+        started_at: f64,
+    ) -> Summary {
+        let mut latencies = self.latencies.borrow().clone();
+        latencies.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+        let percentile = |percent: usize| {
+            latencies
+                .get((latencies.len() * percent / 100).min(latencies.len().saturating_sub(1)))
+                .copied()
+                .unwrap_or(f64::NAN)
+        };
+        let elapsed_secs = (self.last_received_at.get() - started_at) / 1000.0;
+        Summary {
+            topology,
+            clients,
+            message_size,
+            sent: MESSAGES * clients,
+            received: latencies.len(),
+            messages_per_sec: latencies.len() as f64 / elapsed_secs,
+            latency_p50_ms: percentile(50),
+            latency_p90_ms: percentile(90),
+            latency_p99_ms: percentile(99),
+            latency_max_ms: latencies.last().copied().unwrap_or(f64::NAN),
+        }
+    }
+}
+
+fn now() -> f64 {
+    web_sys::window().unwrap().performance().unwrap().now()
+}
+
+/// Resolves after `millis`, letting browser deliver messages in the meantime.
+async fn sleep(millis: i32) {
+    let promise = Promise::new(&mut |resolve, _| {
+        web_sys::window()
+            .unwrap()
+            .set_timeout_with_callback_and_timeout_and_arguments_0(&resolve, millis)
+            .unwrap();
+    });
+    JsFuture::from(promise).await.unwrap();
+}
+
+/// Waits until `condition` holds, or until timeout passes.
+async fn wait_until(condition: impl Fn() -> bool) {
+    let deadline = now() + TIMEOUT_MS;
+    while !condition() && now() < deadline {
+        sleep(10).await;
+    }
+}
+
+/// Calls `send` for each message in bursts, then waits until `expected` messages were received.
+async fn pump(send: impl Fn(&Sample), latencies: &Latencies, message_size: usize, expected: usize) {
+    for sent in 1..=MESSAGES {
+        send(&Sample::new(message_size));
+        if sent % BURST == 0 {
+            sleep(0).await;
+        }
+    }
+    wait_until(|| latencies.len() >= expected).await;
+}
+
+fn print(summary: &Summary) {
+    let summary = serde_json::to_string(summary).unwrap();
+    console::log_1(&JsValue::from_str(&format!("bench: {}", summary)));
+}
+
+#[wasm_bindgen_test]
+#[ignore]
+async fn one_to_one_throughput_and_latency() {
+    for message_size in MESSAGE_SIZES {
+        let signaling = LoopbackSignaling::new();
+        let latencies = Rc::new(Latencies::default());
+        let opened = Rc::new(Cell::new(false));
+
+        let mut sender = one_to_one::NetworkManager::with_loopback(
+            &signaling,
+            SessionId::new(1234),
+            ConnectionType::Local,
+        )
+        .unwrap();
+        let opened_clone = Rc::clone(&opened);
+        sender
+            .start(move || opened_clone.set(true), |_: ()| {})
+            .unwrap();
+        let mut receiver = one_to_one::NetworkManager::with_loopback(
+            &signaling,
+            SessionId::new(1234),
+            ConnectionType::Local,
+        )
+        .unwrap();
+        let latencies_clone = Rc::clone(&latencies);
+        receiver
+            .start(|| {}, move |sample: Sample| latencies_clone.record(&sample))
+            .unwrap();
+        wait_until(|| opened.get()).await;
+        assert!(opened.get(), "peers didn't connect");
+
+        let started_at = now();
+        pump(
+            |sample| sender.send_message(sample).unwrap(),
+            &latencies,
+            message_size,
+            MESSAGES,
+        )
+        .await;
+        print(&latencies.summary("one-to-one", 1, message_size, started_at));
+        sender.close();
+        receiver.close();
+    }
+}
+
+#[wasm_bindgen_test]
+#[ignore]
+async fn one_to_many_throughput_and_latency() {
+    for message_size in MESSAGE_SIZES {
+        let signaling = LoopbackSignaling::new();
+        let latencies = Rc::new(Latencies::default());
+        let opened = Rc::new(Cell::new(0));
+
+        let mut server = one_to_many::MiniServer::with_loopback(
+            &signaling,
+            SessionId::new(1234),
+            ConnectionType::Local,
+        );
+        let opened_clone = Rc::clone(&opened);
+        server
+            .start(
+                move |_| opened_clone.set(opened_clone.get() + 1),
+                |_, _: ()| {},
+            )
+            .unwrap();
+        let clients: Vec<_> = (0..CLIENTS)
+            .map(|_| {
+                let mut client = one_to_many::MiniClient::with_loopback(
+                    &signaling,
+                    SessionId::new(1234),
+                    ConnectionType::Local,
+                );
+                let latencies = Rc::clone(&latencies);
+                client
+                    .start(|| {}, move |sample: Sample| latencies.record(&sample))
+                    .unwrap();
+                client
+            })
+            .collect();
+        wait_until(|| opened.get() == CLIENTS).await;
+        assert_eq!(opened.get(), CLIENTS, "clients didn't connect");
+
+        let started_at = now();
+        pump(
+            |sample| server.send_message_to_all(sample).unwrap(),
+            &latencies,
+            message_size,
+            MESSAGES * CLIENTS,
+        )
+        .await;
+        print(&latencies.summary("one-to-many", CLIENTS, message_size, started_at));
+        server.close();
+        for client in clients {
+            client.close();
+        }
+    }
+}
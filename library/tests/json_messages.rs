@@ -0,0 +1,84 @@
+//! Test suite for the Web and headless browsers, with data channel messages encoded as JSON.
+
+#![cfg(all(target_arch = "wasm32", feature = "json-messages"))]
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use serde::{Deserialize, Serialize};
+use wasm_bindgen_test::{wasm_bindgen_test, wasm_bindgen_test_configure};
+use wasm_peers::one_to_one::NetworkManager;
+use wasm_peers::{ConnectionType, SessionId};
+use web_sys::console;
+
+const SIGNALING_SERVER_URL: &str = "ws://0.0.0.0:9001/one-to-one";
+
+wasm_bindgen_test_configure!(run_in_browser);
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Greeting {
+    from: String,
+    text: String,
+}
+
+#[wasm_bindgen_test]
+fn network_manager_starts_successfully() {
+    let mut server = NetworkManager::new(
+        SIGNALING_SERVER_URL,
+        SessionId::new(4321),
+        ConnectionType::Local,
+    )
+    .unwrap();
+    server.start(|| {}, |_: Greeting| {}).unwrap();
+}
+
+#[wasm_bindgen_test]
+fn structured_message_passes_both_ways() {
+    let server_received_message = Rc::new(RefCell::new(false));
+    let client_received_message = Rc::new(RefCell::new(false));
+
+    let mut server = NetworkManager::new(
+        SIGNALING_SERVER_URL,
+        SessionId::new(4321),
+        ConnectionType::Local,
+    )
+    .unwrap();
+
+    let server_clone = server.clone();
+    let server_on_open = move || {
+        server_clone
+            .send_message(&Greeting {
+                from: "server".to_owned(),
+                text: "ping!".to_owned(),
+            })
+            .unwrap();
+    };
+    let server_on_message = move |message: Greeting| {
+        console::log_1(&format!("server received message: {:?}", message).into());
+        *server_received_message.borrow_mut() = true;
+    };
+    server.start(server_on_open, server_on_message).unwrap();
+
+    let mut client = NetworkManager::new(
+        SIGNALING_SERVER_URL,
+        SessionId::new(4321),
+        ConnectionType::Local,
+    )
+    .unwrap();
+    let client_on_open = || { /* do nothing */ };
+    let client_clone = client.clone();
+    let client_on_message = move |message: Greeting| {
+        console::log_1(&format!("client received message: {:?}", message).into());
+        client_clone
+            .send_message(&Greeting {
+                from: "client".to_owned(),
+                text: "pong!".to_owned(),
+            })
+            .unwrap();
+        *client_received_message.borrow_mut() = true;
+    };
+    client.start(client_on_open, client_on_message).unwrap();
+
+    // assert!(*client_received_message.borrow());
+    // assert!(*server_received_message.borrow());
+}
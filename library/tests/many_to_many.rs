@@ -2,78 +2,286 @@
 
 #![cfg(target_arch = "wasm32")]
 
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::rc::Rc;
 
+use serde::{Deserialize, Serialize};
 use wasm_bindgen_test::{wasm_bindgen_test, wasm_bindgen_test_configure};
 use wasm_peers::many_to_many::NetworkManager;
+use wasm_peers::synced::{Merge, SyncedValue};
+use wasm_peers::test_utils::{completion, LoopbackSignaling};
 use wasm_peers::{ConnectionType, SessionId};
 use web_sys::console;
 
-const SIGNALING_SERVER_URL: &str = "ws://0.0.0.0:9001/one-to-many";
-
 wasm_bindgen_test_configure!(run_in_browser);
 
 #[wasm_bindgen_test]
 fn network_manager_starts_successfully() {
-    let mut server = NetworkManager::new(
-        SIGNALING_SERVER_URL,
+    let mut server = NetworkManager::with_loopback(
+        &LoopbackSignaling::new(),
         SessionId::new(1234),
         ConnectionType::Local,
-    )
-    .unwrap();
-    server.start(|_| {}, |_, _: ()| {});
+    );
+    server.start(|_| {}, |_, _: ()| {}).unwrap();
 }
 
 #[wasm_bindgen_test]
-fn single_message_passes_between_all() {
-    let opened_connections_count = Rc::new(RefCell::new(0));
-    let received_messages_count = Rc::new(RefCell::new(0));
+fn network_manager_keeps_connection_type() {
+    let server = NetworkManager::with_loopback(
+        &LoopbackSignaling::new(),
+        SessionId::new(1234),
+        ConnectionType::Local,
+    );
+    assert_eq!(server.connection_type(), ConnectionType::Local);
+}
+
+#[wasm_bindgen_test]
+async fn single_message_passes_between_all() {
+    const PEERS_COUNT: usize = 4;
+    // each peer sends `ping` to and receives `pong` from every other peer
+    const EXPECTED_MESSAGES_COUNT: usize = 2 * PEERS_COUNT * (PEERS_COUNT - 1);
+
+    let signaling = LoopbackSignaling::new();
+    let (all_received_messages, all_received) = completion();
+    let all_received_messages = Rc::new(all_received_messages);
+    let received_messages_count = Rc::new(Cell::new(0));
 
     let peer_generator = || {
-        let mut server = NetworkManager::new(
-            SIGNALING_SERVER_URL,
-            SessionId::new(1234),
-            ConnectionType::Local,
-        )
-        .unwrap();
+        let mut server =
+            NetworkManager::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local);
 
         let server_clone = server.clone();
-        let opened_connections_count = opened_connections_count.clone();
-        let server_on_open = {
-            move |user_id| {
-                console::log_1(&format!("connection to user established: {:?}", user_id).into());
-                *opened_connections_count.borrow_mut() += 1;
-                server_clone
-                    .send_message(user_id, &"ping!".to_owned())
-                    .unwrap();
-            }
+        let server_on_open = move |user_id| {
+            console::log_1(&format!("connection to user established: {:?}", user_id).into());
+            server_clone
+                .send_message(user_id, &"ping!".to_owned())
+                .unwrap();
         };
 
         let server_clone = server.clone();
-        let received_messages_count = received_messages_count.clone();
-        let server_on_message = {
-            move |user_id, message: String| {
-                console::log_1(
-                    &format!(
-                        "server received message from client {:?}: {}",
-                        user_id, message
-                    )
-                    .into(),
-                );
-                *received_messages_count.borrow_mut() += 1;
+        let received_messages_count = Rc::clone(&received_messages_count);
+        let all_received_messages = Rc::clone(&all_received_messages);
+        let server_on_message = move |user_id, message: String| {
+            console::log_1(
+                &format!(
+                    "server received message from client {:?}: {}",
+                    user_id, message
+                )
+                .into(),
+            );
+            if message == "ping!" {
                 server_clone
                     .send_message(user_id, &"pong!".to_owned())
                     .unwrap();
             }
+            received_messages_count.set(received_messages_count.get() + 1);
+            if received_messages_count.get() == EXPECTED_MESSAGES_COUNT {
+                all_received_messages();
+            }
+        };
+        server.start(server_on_open, server_on_message).unwrap();
+    };
+    for _ in 0..PEERS_COUNT {
+        peer_generator();
+    }
+
+    all_received.await.unwrap();
+}
+
+#[wasm_bindgen_test]
+async fn connected_peer_is_no_longer_pending() {
+    let signaling = LoopbackSignaling::new();
+    let (first_opened_connection, first_opened) = completion();
+
+    let mut first =
+        NetworkManager::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local);
+    assert!(first.pending_peers().is_empty());
+    assert!(first.connected_peers().is_empty());
+    let opened_with = Rc::new(Cell::new(None));
+    let first_on_open = {
+        let opened_with = Rc::clone(&opened_with);
+        move |user_id| {
+            opened_with.set(Some(user_id));
+            first_opened_connection();
+        }
+    };
+    first.start(first_on_open, |_, _: ()| {}).unwrap();
+    let mut second =
+        NetworkManager::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local);
+    second.start(|_| {}, |_, _: ()| {}).unwrap();
+
+    first_opened.await.unwrap();
+    let second_id = opened_with.get().unwrap();
+    assert_eq!(first.connected_peers(), vec![second_id]);
+    assert!(!first.pending_peers().contains(&second_id));
+}
+
+#[wasm_bindgen_test]
+async fn every_peer_is_fully_meshed_once_all_connections_open() {
+    const PEERS_COUNT: usize = 3;
+
+    let signaling = LoopbackSignaling::new();
+    let (all_opened_connections, all_opened) = completion();
+    let all_opened_connections = Rc::new(all_opened_connections);
+    let opened_count = Rc::new(Cell::new(0));
+
+    let mut peers = Vec::new();
+    for _ in 0..PEERS_COUNT {
+        let mut peer =
+            NetworkManager::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local);
+        assert_eq!(peer.mesh_size(), 0);
+        let on_open = {
+            let opened_count = Rc::clone(&opened_count);
+            let all_opened_connections = Rc::clone(&all_opened_connections);
+            move |_| {
+                opened_count.set(opened_count.get() + 1);
+                if opened_count.get() == PEERS_COUNT * (PEERS_COUNT - 1) {
+                    all_opened_connections();
+                }
+            }
+        };
+        peer.start(on_open, |_, _: ()| {}).unwrap();
+        peers.push(peer);
+    }
+
+    all_opened.await.unwrap();
+    for peer in &peers {
+        assert_eq!(peer.mesh_size(), PEERS_COUNT - 1);
+        assert!(peer.is_fully_meshed(PEERS_COUNT));
+        assert!(!peer.is_fully_meshed(PEERS_COUNT + 1));
+    }
+}
+
+#[wasm_bindgen_test]
+async fn peers_agree_on_leader_and_elect_next_one_once_it_leaves() {
+    const PEERS_COUNT: usize = 3;
+
+    let signaling = LoopbackSignaling::new();
+    let (all_opened_connections, all_opened) = completion();
+    let all_opened_connections = Rc::new(all_opened_connections);
+    let opened_count = Rc::new(Cell::new(0));
+
+    let mut peers = Vec::new();
+    for _ in 0..PEERS_COUNT {
+        let mut peer =
+            NetworkManager::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local);
+        assert_eq!(peer.current_leader(), None);
+        let on_open = {
+            let opened_count = Rc::clone(&opened_count);
+            let all_opened_connections = Rc::clone(&all_opened_connections);
+            move |_| {
+                opened_count.set(opened_count.get() + 1);
+                if opened_count.get() == PEERS_COUNT * (PEERS_COUNT - 1) {
+                    all_opened_connections();
+                }
+            }
         };
-        server.start(server_on_open, server_on_message);
+        peer.start(on_open, |_, _: ()| {}).unwrap();
+        peers.push(peer);
+    }
+    all_opened.await.unwrap();
+
+    let leader = peers[0].current_leader().unwrap();
+    assert!(peers
+        .iter()
+        .all(|peer| peer.current_leader() == Some(leader)));
+    assert_eq!(peers.iter().filter(|peer| peer.is_leader()).count(), 1);
+    let leader_index = peers.iter().position(NetworkManager::is_leader).unwrap();
+    let leader_peer = peers.remove(leader_index);
+    assert!(leader_peer
+        .connected_peers()
+        .iter()
+        .all(|&peer_id| leader < peer_id));
+
+    let (all_elected_leader, all_elected) = completion();
+    let all_elected_leader = Rc::new(all_elected_leader);
+    let new_leaders = Rc::new(RefCell::new(Vec::new()));
+    for peer in &peers {
+        let new_leaders = Rc::clone(&new_leaders);
+        let all_elected_leader = Rc::clone(&all_elected_leader);
+        peer.set_on_leader_changed(move |new_leader| {
+            new_leaders.borrow_mut().push(new_leader);
+            if new_leaders.borrow().len() == PEERS_COUNT - 1 {
+                all_elected_leader();
+            }
+        });
+    }
+    leader_peer.close();
+    all_elected.await.unwrap();
+
+    let new_leader = peers[0].current_leader().unwrap();
+    assert_ne!(new_leader, leader);
+    assert_eq!(*new_leaders.borrow(), vec![new_leader; PEERS_COUNT - 1]);
+    assert!(peers
+        .iter()
+        .all(|peer| peer.current_leader() == Some(new_leader)));
+    assert_eq!(peers.iter().filter(|peer| peer.is_leader()).count(), 1);
+}
+
+#[wasm_bindgen_test]
+async fn all_connected_peers_answer_pings() {
+    let signaling = LoopbackSignaling::new();
+    let (first_opened_connection, first_opened) = completion();
+
+    let mut first =
+        NetworkManager::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local);
+    first
+        .start(move |_| first_opened_connection(), |_, _: ()| {})
+        .unwrap();
+    let mut second =
+        NetworkManager::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local);
+    second.start(|_| {}, |_, _: ()| {}).unwrap();
+
+    first_opened.await.unwrap();
+    let latencies = first.ping_latency_all().await;
+    assert_eq!(latencies.len(), 1);
+    assert_eq!(latencies[0].0, first.connected_peers()[0]);
+    assert!(latencies[0].1.is_some());
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+struct Text(String);
+
+impl Merge for Text {}
+
+#[wasm_bindgen_test]
+async fn synced_value_reaches_joining_peer_and_converges_after_concurrent_writes() {
+    let signaling = LoopbackSignaling::new();
+    let synced_value = || {
+        SyncedValue::new(
+            NetworkManager::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local),
+            Text::default(),
+        )
     };
-    peer_generator();
-    peer_generator();
-    peer_generator();
-    peer_generator();
 
-    // assert!(*client_received_message.borrow());
-    // assert!(*server_received_message.borrow());
+    let mut first = synced_value();
+    first.start().unwrap();
+    first
+        .set(Text("written before anyone joined".to_owned()))
+        .unwrap();
+    let mut second = synced_value();
+    // both peers ask the other one for its value once connected
+    let (first_merged_value, first_merged) = completion();
+    let (second_merged_value, second_merged) = completion();
+    first.set_on_change(move |_| first_merged_value());
+    second.set_on_change(move |_| second_merged_value());
+    second.start().unwrap();
+    first_merged.await.unwrap();
+    second_merged.await.unwrap();
+    assert_eq!(
+        second.get(),
+        Text("written before anyone joined".to_owned())
+    );
+    assert_eq!(first.get(), second.get());
+
+    let (first_merged_value, first_merged) = completion();
+    let (second_merged_value, second_merged) = completion();
+    first.set_on_change(move |_| first_merged_value());
+    second.set_on_change(move |_| second_merged_value());
+    first.set(Text("first".to_owned())).unwrap();
+    second.set(Text("second".to_owned())).unwrap();
+    first_merged.await.unwrap();
+    second_merged.await.unwrap();
+    assert_eq!(first.get(), second.get());
+    assert!([Text("first".to_owned()), Text("second".to_owned())].contains(&first.get()));
 }
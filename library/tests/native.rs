@@ -0,0 +1,57 @@
+//! Test suite for native peers, requires a signaling server running on 0.0.0.0:9001.
+
+#![cfg(all(not(target_arch = "wasm32"), feature = "native"))]
+
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+use wasm_peers::one_to_one::native::NetworkManager;
+use wasm_peers::{ConnectionType, SessionId};
+
+const SIGNALING_SERVER_URL: &str = "ws://0.0.0.0:9001/one-to-one";
+
+#[tokio::test(flavor = "multi_thread")]
+async fn single_message_passes_both_ways_between_native_peers() {
+    let session_id = SessionId::new(8765);
+    let (received_tx, mut received_rx) = mpsc::unbounded_channel();
+
+    let mut server =
+        NetworkManager::new_async(SIGNALING_SERVER_URL, session_id, ConnectionType::Local)
+            .await
+            .unwrap();
+    let server_clone = server.clone();
+    let server_received_tx = received_tx.clone();
+    server
+        .start(
+            move || server_clone.send_message("ping!").unwrap(),
+            move |message: String| server_received_tx.send(message).unwrap(),
+        )
+        .await
+        .unwrap();
+
+    let mut client =
+        NetworkManager::new_async(SIGNALING_SERVER_URL, session_id, ConnectionType::Local)
+            .await
+            .unwrap();
+    let client_clone = client.clone();
+    client
+        .start(
+            || {},
+            move |message: String| {
+                client_clone.send_message("pong!").unwrap();
+                received_tx.send(message).unwrap();
+            },
+        )
+        .await
+        .unwrap();
+
+    let mut received = Vec::new();
+    for _ in 0..2 {
+        let message = tokio::time::timeout(Duration::from_secs(10), received_rx.recv())
+            .await
+            .expect("peers didn't exchange messages in time");
+        received.push(message.unwrap());
+    }
+    received.sort();
+    assert_eq!(received, ["ping!", "pong!"]);
+}
@@ -0,0 +1,85 @@
+//! Test suite for the Web and headless browsers.
+
+#![cfg(all(target_arch = "wasm32", feature = "netem"))]
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use js_sys::Date;
+use wasm_bindgen_futures::JsFuture;
+use wasm_bindgen_test::{wasm_bindgen_test, wasm_bindgen_test_configure};
+use wasm_peers::one_to_one::NetworkManager;
+use wasm_peers::test_utils::{completion, LoopbackSignaling};
+use wasm_peers::{ConnectionType, NetworkConditions, SessionId};
+
+wasm_bindgen_test_configure!(run_in_browser);
+
+/// Connects two peers, returning the one to send messages from, messages received by the other one,
+/// and a future that resolves once it received `expected` of them.
+async fn connected_pair(expected: usize) -> (NetworkManager, Rc<RefCell<Vec<String>>>, JsFuture) {
+    let signaling = LoopbackSignaling::new();
+    let (sender_opened, sender_open) = completion();
+    let (received_all_messages, received_all) = completion();
+
+    let mut sender =
+        NetworkManager::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local)
+            .unwrap();
+    sender.start(sender_opened, |_: ()| {}).unwrap();
+
+    let received = Rc::new(RefCell::new(Vec::new()));
+    let mut receiver =
+        NetworkManager::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local)
+            .unwrap();
+    let received_clone = Rc::clone(&received);
+    let on_message = move |message: String| {
+        received_clone.borrow_mut().push(message);
+        if received_clone.borrow().len() == expected {
+            received_all_messages();
+        }
+    };
+    receiver.start(|| {}, on_message).unwrap();
+    sender_open.await.unwrap();
+    (sender, received, received_all)
+}
+
+#[wasm_bindgen_test]
+async fn lost_messages_are_never_received() {
+    let (sender, received, received_all) = connected_pair(1).await;
+    sender.set_network_conditions(NetworkConditions {
+        loss_pct: 100,
+        ..NetworkConditions::default()
+    });
+    sender.send_message("lost").unwrap();
+    sender.set_network_conditions(NetworkConditions::default());
+    sender.send_message("kept").unwrap();
+    received_all.await.unwrap();
+    assert_eq!(*received.borrow(), ["kept"]);
+}
+
+#[wasm_bindgen_test]
+async fn messages_are_delivered_after_latency() {
+    let (sender, received, received_all) = connected_pair(1).await;
+    sender.set_network_conditions(NetworkConditions {
+        latency_ms: 200,
+        ..NetworkConditions::default()
+    });
+    let sent_at = Date::now();
+    sender.send_message("delayed").unwrap();
+    received_all.await.unwrap();
+    assert!(Date::now() - sent_at >= 200.0);
+    assert_eq!(*received.borrow(), ["delayed"]);
+}
+
+#[wasm_bindgen_test]
+async fn reordered_message_is_overtaken() {
+    let (sender, received, received_all) = connected_pair(2).await;
+    sender.set_network_conditions(NetworkConditions {
+        reorder_pct: 100,
+        ..NetworkConditions::default()
+    });
+    sender.send_message("first").unwrap();
+    sender.set_network_conditions(NetworkConditions::default());
+    sender.send_message("second").unwrap();
+    received_all.await.unwrap();
+    assert_eq!(*received.borrow(), ["second", "first"]);
+}
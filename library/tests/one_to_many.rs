@@ -2,92 +2,763 @@
 
 #![cfg(target_arch = "wasm32")]
 
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::rc::Rc;
 
 use wasm_bindgen_test::{wasm_bindgen_test, wasm_bindgen_test_configure};
 use wasm_peers::one_to_many::{MiniClient, MiniServer};
-use wasm_peers::{ConnectionType, SessionId};
+use wasm_peers::test_utils::{completion, LoopbackSignaling};
+use wasm_peers::{
+    AlreadyStartedError, BatchConfig, CandidateInfo, CloseReason, ConnectionType,
+    DataChannelConfig, MultiSendError, PeerEvent, SessionId, SessionInfo, StableId, TopologyType,
+    UserId,
+};
 use web_sys::console;
 
-const SIGNALING_SERVER_URL: &str = "ws://0.0.0.0:9001/one-to-many";
-
 wasm_bindgen_test_configure!(run_in_browser);
 
 #[wasm_bindgen_test]
 fn network_manager_starts_successfully() {
-    let mut server = MiniServer::new(
-        SIGNALING_SERVER_URL,
+    let mut server = MiniServer::with_loopback(
+        &LoopbackSignaling::new(),
         SessionId::new(1234),
         ConnectionType::Local,
-    )
-    .unwrap();
-    server.start(|_| {}, |_, _: ()| {});
+    );
+    server.start(|_| {}, |_, _: ()| {}).unwrap();
+}
+
+#[wasm_bindgen_test]
+fn network_manager_starts_with_event_handler_successfully() {
+    let mut server = MiniServer::with_loopback(
+        &LoopbackSignaling::new(),
+        SessionId::new(1234),
+        ConnectionType::Local,
+    );
+    server
+        .start_with_event_handler(|event: PeerEvent<()>| {
+            console::log_1(&format!("event: {:?}", event).into());
+        })
+        .unwrap();
+}
+
+#[wasm_bindgen_test]
+fn network_manager_describes_its_session() {
+    let signaling = LoopbackSignaling::new();
+    let server = MiniServer::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local);
+    let client = MiniClient::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local);
+
+    assert_eq!(server.session_id(), SessionId::new(1234));
+    assert_eq!(client.signaling_url(), None);
+    assert_eq!(server.to_string(), "one-to-many host in SessionId(1234)");
+    assert_eq!(client.to_string(), "one-to-many client in SessionId(1234)");
+    assert_eq!(
+        format!("{:?}", client),
+        "MiniClient { session_id: SessionId(1234), signaling_url: None, .. }"
+    );
 }
 
 #[wasm_bindgen_test]
-fn single_message_passes_both_ways() {
-    let server_received_message = Rc::new(RefCell::new(false));
-    let client_received_message = Rc::new(RefCell::new(false));
+fn network_manager_cannot_be_started_twice() {
+    let mut server = MiniServer::with_loopback(
+        &LoopbackSignaling::new(),
+        SessionId::new(1234),
+        ConnectionType::Local,
+    );
+    server.start(|_| {}, |_, _: ()| {}).unwrap();
+    let error = server
+        .start_with_event_handler(|_: PeerEvent<()>| {})
+        .unwrap_err();
+    assert_eq!(error.downcast_ref(), Some(&AlreadyStartedError));
+}
 
-    let mut server = MiniServer::new(
-        SIGNALING_SERVER_URL,
+#[wasm_bindgen_test]
+fn sending_to_many_reports_each_failed_user() {
+    let server = MiniServer::with_loopback(
+        &LoopbackSignaling::new(),
         SessionId::new(1234),
         ConnectionType::Local,
-    )
-    .unwrap();
-    let server_open_connections_count = Rc::new(RefCell::new(0));
+    );
+    let user_ids = [UserId::new(1), UserId::new(2)];
+    let error = server
+        .send_message_to_many(&user_ids, &"ping!".to_owned())
+        .unwrap_err();
+    let failed_user_ids: Vec<_> = error
+        .downcast_ref::<MultiSendError>()
+        .unwrap()
+        .failures
+        .iter()
+        .map(|&(user_id, _)| user_id)
+        .collect();
+    assert_eq!(failed_user_ids, user_ids);
+}
+
+#[wasm_bindgen_test]
+fn signaling_state_is_unknown_without_connection() {
+    let signaling = LoopbackSignaling::new();
+    let server = MiniServer::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local);
+    let client = MiniClient::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local);
+    assert_eq!(server.signaling_state_for(UserId::new(1)), None);
+    assert_eq!(client.signaling_state(), None);
+    assert!(server.peer_connection_for(UserId::new(1)).is_none());
+    assert!(client.peer_connection().is_none());
+}
+
+#[wasm_bindgen_test]
+async fn single_message_passes_both_ways() {
+    let signaling = LoopbackSignaling::new();
+    let (server_received_messages, server_received) = completion();
+    let (clients_received_messages, clients_received) = completion();
+
+    let mut server =
+        MiniServer::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local);
+    let server_open_connections_count = Rc::new(Cell::new(0));
+    let server_received_messages_count = Rc::new(Cell::new(0));
 
     let server_clone = server.clone();
-    let server_on_open = {
-        move |user_id| {
-            console::log_1(&format!("connection to user established: {:?}", user_id).into());
-            *server_open_connections_count.borrow_mut() += 1;
-            if *server_open_connections_count.borrow() == 2 {
-                server_clone
-                    .send_message_to_all(&"ping!".to_owned())
-                    .unwrap();
-            }
+    let server_on_open = move |user_id| {
+        console::log_1(&format!("connection to user established: {:?}", user_id).into());
+        server_open_connections_count.set(server_open_connections_count.get() + 1);
+        if server_open_connections_count.get() == 2 {
+            server_clone
+                .send_message_to_all(&"ping!".to_owned())
+                .unwrap();
         }
     };
-    let server_on_message = {
-        move |user_id, message: String| {
-            console::log_1(
-                &format!(
-                    "server received message from client {:?}: {}",
-                    user_id, message
-                )
-                .into(),
-            );
-            *server_received_message.borrow_mut() = true;
+    let server_on_message = move |user_id, message: String| {
+        console::log_1(
+            &format!(
+                "server received message from client {:?}: {}",
+                user_id, message
+            )
+            .into(),
+        );
+        assert_eq!(message, "pong!");
+        server_received_messages_count.set(server_received_messages_count.get() + 1);
+        if server_received_messages_count.get() == 2 {
+            server_received_messages();
         }
     };
-    server.start(server_on_open, server_on_message);
+    server.start(server_on_open, server_on_message).unwrap();
 
+    let clients_received_messages_count = Rc::new(Cell::new(0));
+    let clients_received_messages = Rc::new(clients_received_messages);
     let client_generator = || {
-        let mut client = MiniClient::new(
-            SIGNALING_SERVER_URL,
-            SessionId::new(1234),
-            ConnectionType::Local,
-        )
-        .unwrap();
+        let mut client =
+            MiniClient::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local);
         let client_on_open = || { /* do nothing */ };
         let client_clone = client.clone();
-        let client_on_message = {
-            let client_received_message = client_received_message.clone();
-            move |message: String| {
-                console::log_1(&format!("client received message: {}", message).into());
+        let clients_received_messages_count = Rc::clone(&clients_received_messages_count);
+        let clients_received_messages = Rc::clone(&clients_received_messages);
+        let client_on_message = move |message: String| {
+            console::log_1(&format!("client received message: {}", message).into());
+            assert_eq!(message, "ping!");
+            client_clone
+                .send_message_to_host(&"pong!".to_owned())
+                .unwrap();
+            clients_received_messages_count.set(clients_received_messages_count.get() + 1);
+            if clients_received_messages_count.get() == 2 {
+                clients_received_messages();
+            }
+        };
+        client.start(client_on_open, client_on_message).unwrap();
+    };
+    client_generator();
+    client_generator();
+
+    clients_received.await.unwrap();
+    server_received.await.unwrap();
+}
+
+#[wasm_bindgen_test]
+async fn message_is_broadcast_to_all_clients_but_sender() {
+    let signaling = LoopbackSignaling::new();
+    let (other_client_received_message, other_client_received) = completion();
+
+    let mut server =
+        MiniServer::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local);
+    let server_open_connections = Rc::new(RefCell::new(Vec::new()));
+    let server_clone = server.clone();
+    let server_on_open = move |user_id| {
+        server_open_connections.borrow_mut().push(user_id);
+        if server_open_connections.borrow().len() == 2 {
+            server_clone
+                .send_message(user_id, &"go!".to_owned())
+                .unwrap();
+        }
+    };
+    let server_clone = server.clone();
+    let server_on_message = move |sender_id, message: String| {
+        server_clone
+            .broadcast_except_sender(sender_id, &message)
+            .unwrap();
+    };
+    server.start(server_on_open, server_on_message).unwrap();
+
+    let other_client_received_message = Rc::new(other_client_received_message);
+    let client_generator = || {
+        let mut client =
+            MiniClient::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local);
+        let client_clone = client.clone();
+        let sent = Cell::new(false);
+        let other_client_received_message = Rc::clone(&other_client_received_message);
+        let client_on_message = move |message: String| {
+            if message == "go!" {
+                sent.set(true);
                 client_clone
-                    .send_message_to_host(&"pong!".to_owned())
+                    .send_message_to_host(&"hello!".to_owned())
                     .unwrap();
-                *client_received_message.borrow_mut() = true;
+            } else {
+                assert_eq!(message, "hello!");
+                assert!(!sent.get(), "message was sent back to its sender");
+                other_client_received_message();
             }
         };
-        client.start(client_on_open, client_on_message);
+        client
+            .start(|| { /* do nothing */ }, client_on_message)
+            .unwrap();
     };
     client_generator();
     client_generator();
 
-    // assert!(*client_received_message.borrow());
-    // assert!(*server_received_message.borrow());
+    other_client_received.await.unwrap();
+}
+
+#[wasm_bindgen_test]
+async fn client_joining_before_host_is_connected() {
+    let signaling = LoopbackSignaling::new();
+    let (client_received_message, client_received) = completion();
+
+    let mut client =
+        MiniClient::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local);
+    let client_on_message = move |message: String| {
+        assert_eq!(message, "ping!");
+        client_received_message();
+    };
+    client
+        .start(|| { /* do nothing */ }, client_on_message)
+        .unwrap();
+
+    let mut server =
+        MiniServer::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local);
+    let server_clone = server.clone();
+    let server_on_open = move |user_id| {
+        server_clone
+            .send_message(user_id, &"ping!".to_owned())
+            .unwrap();
+    };
+    server
+        .start(
+            server_on_open,
+            |_user_id, _message: String| { /* do nothing */ },
+        )
+        .unwrap();
+
+    client_received.await.unwrap();
+}
+
+#[wasm_bindgen_test]
+async fn diagnostics_describe_connected_client() {
+    let signaling = LoopbackSignaling::new();
+    let (server_opened_connection, server_opened) = completion();
+
+    let mut server =
+        MiniServer::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local);
+    server
+        .start(
+            move |_user_id| server_opened_connection(),
+            |_user_id, _message: String| { /* do nothing */ },
+        )
+        .unwrap();
+    let mut client =
+        MiniClient::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local);
+    client
+        .start(
+            || { /* do nothing */ },
+            |_message: String| { /* do nothing */ },
+        )
+        .unwrap();
+    server_opened.await.unwrap();
+
+    let report = server.diagnostics().await;
+    assert_eq!(report.is_host, Some(true));
+    assert_eq!(report.connection_type, "Local");
+    assert_eq!(report.peers.len(), 1);
+    let peer = &report.peers[0];
+    assert!(peer.user_id.is_some());
+    assert!(peer.has_local_description && peer.has_remote_description);
+    assert_eq!(
+        peer.data_channel
+            .as_ref()
+            .map(|data_channel| data_channel.ready_state.as_str()),
+        Some("open")
+    );
+}
+
+#[wasm_bindgen_test]
+async fn data_channels_are_labeled_with_configured_label_and_peer_id() {
+    let signaling = LoopbackSignaling::new();
+    let (server_opened_connection, server_opened) = completion();
+
+    let mut server =
+        MiniServer::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local);
+    server.set_data_channel_config(DataChannelConfig {
+        label: Some("game".to_owned()),
+        ..DataChannelConfig::default()
+    });
+    server
+        .start(
+            move |_user_id| server_opened_connection(),
+            |_user_id, _message: String| { /* do nothing */ },
+        )
+        .unwrap();
+    let mut client =
+        MiniClient::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local);
+    client
+        .start(
+            || { /* do nothing */ },
+            |_message: String| { /* do nothing */ },
+        )
+        .unwrap();
+    server_opened.await.unwrap();
+
+    let report = server.diagnostics().await;
+    let peer = &report.peers[0];
+    assert_eq!(
+        peer.data_channel
+            .as_ref()
+            .map(|data_channel| data_channel.label.clone()),
+        peer.user_id
+            .map(|user_id| format!("game-{}", user_id.into_inner()))
+    );
+    assert_eq!(
+        peer.data_channel
+            .as_ref()
+            .map(|data_channel| data_channel.protocol.clone()),
+        Some(String::new())
+    );
+}
+
+#[wasm_bindgen_test]
+async fn data_channels_carry_configured_protocol_to_clients() {
+    let signaling = LoopbackSignaling::new();
+    let (client_opened_connection, client_opened) = completion();
+
+    let mut server =
+        MiniServer::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local);
+    server.set_data_channel_config(DataChannelConfig {
+        protocol: Some("game-state-v2".to_owned()),
+        ..DataChannelConfig::default()
+    });
+    server
+        .start(|_user_id| {}, |_user_id, _message: String| {})
+        .unwrap();
+    let mut client =
+        MiniClient::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local);
+    client
+        .start(client_opened_connection, |_message: String| {})
+        .unwrap();
+    client_opened.await.unwrap();
+
+    for report in [server.diagnostics().await, client.diagnostics().await] {
+        let data_channel = report.peers[0].data_channel.as_ref().unwrap();
+        assert_eq!(data_channel.protocol, "game-state-v2");
+    }
+}
+
+#[wasm_bindgen_test]
+async fn client_knows_server_id_once_connected() {
+    let signaling = LoopbackSignaling::new();
+    let (client_opened_connection, client_opened) = completion();
+
+    let mut server =
+        MiniServer::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local);
+    server.start(|_| {}, |_, _: ()| {}).unwrap();
+    let mut client =
+        MiniClient::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local);
+    assert_eq!(client.get_server_id(), None);
+    let opened_with = Rc::new(Cell::new(None));
+    {
+        let opened_with = Rc::clone(&opened_with);
+        client
+            .start_with_event_handler(move |event: PeerEvent<()>| {
+                if let PeerEvent::Opened(host_id) = event {
+                    opened_with.set(Some(host_id));
+                    client_opened_connection();
+                }
+            })
+            .unwrap();
+    }
+
+    client_opened.await.unwrap();
+    assert!(opened_with.get().is_some());
+    assert_eq!(client.get_server_id(), opened_with.get());
+}
+
+#[wasm_bindgen_test]
+async fn reliable_message_is_received_once_before_sending_resolves() {
+    let signaling = LoopbackSignaling::new();
+    let (client_opened_connection, client_opened) = completion();
+
+    let mut server =
+        MiniServer::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local);
+    let received = Rc::new(RefCell::new(Vec::new()));
+    {
+        let received = Rc::clone(&received);
+        server
+            .start(
+                |_| {},
+                move |_, message: String| {
+                    received.borrow_mut().push(message);
+                },
+            )
+            .unwrap();
+    }
+    let mut client =
+        MiniClient::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local);
+    client.start(client_opened_connection, |_: ()| {}).unwrap();
+    client_opened.await.unwrap();
+
+    client
+        .send_message_to_host_reliably("game over")
+        .await
+        .unwrap();
+    assert_eq!(*received.borrow(), vec!["game over".to_owned()]);
+}
+
+#[wasm_bindgen_test]
+async fn local_candidates_are_reported_until_gathering_completes() {
+    let signaling = LoopbackSignaling::new();
+    let (server_gathered_candidates, server_gathered) = completion();
+
+    let mut server =
+        MiniServer::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local);
+    let reported = Rc::new(Cell::new(0));
+    {
+        let reported = Rc::clone(&reported);
+        server.set_on_local_candidate(move |_, candidate: CandidateInfo| {
+            console::log_1(&format!("local candidate: {:?}", candidate).into());
+            reported.set(reported.get() + 1);
+        });
+    }
+    server.set_on_gathering_complete(move |_| server_gathered_candidates());
+    server.start(|_| {}, |_, _: ()| {}).unwrap();
+    let mut client =
+        MiniClient::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local);
+    client.start(|| {}, |_: ()| {}).unwrap();
+
+    server_gathered.await.unwrap();
+    let summary = server.gathered_candidate_summary();
+    assert!(summary.host > 0);
+    assert_eq!(summary.total(), reported.get());
+}
+
+#[wasm_bindgen_test]
+async fn batched_messages_are_received_in_order() {
+    let signaling = LoopbackSignaling::new();
+    let (client_opened_connection, client_opened) = completion();
+    let (server_received_all, server_received) = completion();
+
+    let mut server =
+        MiniServer::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local);
+    let received = Rc::new(RefCell::new(Vec::new()));
+    {
+        let received = Rc::clone(&received);
+        server
+            .start(
+                |_| {},
+                move |_, message: u32| {
+                    received.borrow_mut().push(message);
+                    if received.borrow().len() == 3 {
+                        server_received_all();
+                    }
+                },
+            )
+            .unwrap();
+    }
+    let mut client =
+        MiniClient::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local);
+    client.set_data_channel_config(DataChannelConfig {
+        batching: Some(BatchConfig::default()),
+        ..DataChannelConfig::default()
+    });
+    client.start(client_opened_connection, |_: ()| {}).unwrap();
+    client_opened.await.unwrap();
+
+    for message in 1..=3 {
+        client.send_message_to_host(&message).unwrap();
+    }
+    client.flush().unwrap();
+    server_received.await.unwrap();
+    assert_eq!(*received.borrow(), vec![1, 2, 3]);
+}
+
+#[wasm_bindgen_test]
+async fn snapshot_lists_connected_clients() {
+    let signaling = LoopbackSignaling::new();
+    let (server_opened_connection, server_opened) = completion();
+
+    let mut server =
+        MiniServer::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local);
+    assert!(server.snapshot().connected_clients.is_empty());
+    let client_id = Rc::new(Cell::new(None));
+    {
+        let client_id = Rc::clone(&client_id);
+        server
+            .start(
+                move |user_id| {
+                    client_id.set(Some(user_id));
+                    server_opened_connection();
+                },
+                |_, _: ()| {},
+            )
+            .unwrap();
+    }
+    let mut client =
+        MiniClient::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local);
+    client.start(|| {}, |_: ()| {}).unwrap();
+
+    server_opened.await.unwrap();
+    let snapshot = server.snapshot();
+    assert_eq!(snapshot.session_id, SessionId::new(1234));
+    assert_eq!(
+        snapshot.connected_clients,
+        client_id.get().into_iter().collect::<Vec<_>>()
+    );
+    assert!(snapshot.timestamp_ms > 0.0);
+}
+
+#[wasm_bindgen_test]
+async fn server_is_told_about_client_that_left() {
+    let signaling = LoopbackSignaling::new();
+    let (server_opened_connection, server_opened) = completion();
+    let (server_closed_connection, server_closed) = completion();
+
+    let mut server =
+        MiniServer::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local);
+    let events = Rc::new(Cell::new((None, None)));
+    {
+        let events = Rc::clone(&events);
+        server
+            .start_with_event_handler(move |event: PeerEvent<()>| match event {
+                PeerEvent::Opened(user_id) => {
+                    events.set((Some(user_id), None));
+                    server_opened_connection();
+                }
+                PeerEvent::Closed(user_id, reason) => {
+                    events.set((events.get().0, Some((user_id, reason))));
+                    server_closed_connection();
+                }
+                _ => {}
+            })
+            .unwrap();
+    }
+    let mut client =
+        MiniClient::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local);
+    client.start(|| {}, |_: ()| {}).unwrap();
+
+    server_opened.await.unwrap();
+    client.close();
+    server_closed.await.unwrap();
+    let (client_id, closed) = events.get();
+    let client_id = client_id.unwrap();
+    assert_eq!(closed, Some((client_id, CloseReason::PeerLeft)));
+    assert!(server.connected_peers().is_empty());
+    assert!(server.peer_connection_for(client_id).is_none());
+}
+
+#[wasm_bindgen_test]
+async fn clients_over_max_clients_are_turned_away() {
+    let signaling = LoopbackSignaling::new();
+    let (server_opened_connection, server_opened) = completion();
+    let (client_turned_away, turned_away) = completion();
+
+    let mut server =
+        MiniServer::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local);
+    server.set_max_clients(1);
+    server
+        .start(move |_| server_opened_connection(), |_, _: ()| {})
+        .unwrap();
+    assert_eq!(server.max_clients(), Some(1));
+    assert_eq!(server.connected_count(), 0);
+
+    let mut accepted =
+        MiniClient::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local);
+    accepted.start(|| {}, |_: ()| {}).unwrap();
+    server_opened.await.unwrap();
+
+    let error = Rc::new(RefCell::new(None));
+    let mut rejected =
+        MiniClient::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local);
+    {
+        let error = Rc::clone(&error);
+        rejected
+            .start_with_event_handler(move |event: PeerEvent<()>| {
+                if let PeerEvent::SignalingError(message) = event {
+                    *error.borrow_mut() = Some(message);
+                    client_turned_away();
+                }
+            })
+            .unwrap();
+    }
+    turned_away.await.unwrap();
+    assert_eq!(error.borrow().as_deref(), Some("session is full"));
+    assert_eq!(server.connected_count(), 1);
+    assert_eq!(server.client_ids().len(), 1);
+}
+
+#[wasm_bindgen_test]
+async fn server_recognizes_client_rejoining_with_stable_id() {
+    let signaling = LoopbackSignaling::new();
+    let stable_id = StableId::new(4321);
+    let (first_opened_connection, first_opened) = completion();
+    let (second_opened_connection, second_opened) = completion();
+
+    let mut server =
+        MiniServer::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local);
+    let opened = Rc::new(RefCell::new(Vec::new()));
+    {
+        let server_clone = server.clone();
+        let opened = Rc::clone(&opened);
+        server
+            .start(
+                move |user_id| {
+                    // stable id is already known once the connection opens
+                    let mut opened = opened.borrow_mut();
+                    opened.push((user_id, server_clone.stable_id_for(user_id)));
+                    if opened.len() == 1 {
+                        first_opened_connection();
+                    } else {
+                        second_opened_connection();
+                    }
+                },
+                |_, _: ()| {},
+            )
+            .unwrap();
+    }
+    let client_generator = || {
+        let mut client =
+            MiniClient::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local);
+        client.set_stable_id(stable_id);
+        client.start(|| {}, |_: ()| {}).unwrap();
+        client
+    };
+
+    let client = client_generator();
+    first_opened.await.unwrap();
+    // e.g. page reload
+    client.close();
+    let _rejoined = client_generator();
+    second_opened.await.unwrap();
+
+    let opened = opened.borrow();
+    assert_eq!(
+        opened.iter().map(|&(_, id)| id).collect::<Vec<_>>(),
+        vec![Some(stable_id); 2]
+    );
+    assert_ne!(
+        opened.first().map(|&(user_id, _)| user_id),
+        opened.last().map(|&(user_id, _)| user_id)
+    );
+    assert_eq!(server.connected_peers().len(), 1);
+}
+
+#[wasm_bindgen_test]
+async fn client_messages_are_relayed_through_host() {
+    let signaling = LoopbackSignaling::new();
+    let (client_received_message, client_received) = completion();
+
+    let mut server =
+        MiniServer::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local);
+    server.enable_relay(true);
+    let relayed = Rc::new(RefCell::new(Vec::new()));
+    {
+        let relayed = Rc::clone(&relayed);
+        server.set_relay_policy(move |from, to| {
+            relayed.borrow_mut().push((from, to));
+            true
+        });
+    }
+    let opened = Rc::new(RefCell::new(Vec::new()));
+    {
+        let server_clone = server.clone();
+        let opened = Rc::clone(&opened);
+        server
+            .start(
+                move |user_id| {
+                    let mut opened = opened.borrow_mut();
+                    opened.push(user_id);
+                    // tell the first client who to send the message to
+                    if let [first, second] = opened[..] {
+                        server_clone
+                            .send_message(first, &second.into_inner())
+                            .unwrap();
+                    }
+                },
+                |user_id, message: u64| {
+                    panic!("host received message {} from {:?}", message, user_id)
+                },
+            )
+            .unwrap();
+    }
+
+    let received = Rc::new(Cell::new(None));
+    let client_received_message = Rc::new(client_received_message);
+    let client_generator = || {
+        let mut client =
+            MiniClient::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local);
+        let client_clone = client.clone();
+        let received = Rc::clone(&received);
+        let client_received_message = Rc::clone(&client_received_message);
+        client
+            .start_with_sender(
+                || {},
+                move |sender, message: u64| {
+                    if Some(sender) == client_clone.get_server_id() {
+                        client_clone
+                            .send_message_to_peer(UserId::new(message), &42_u64)
+                            .unwrap();
+                    } else {
+                        received.set(Some((sender, message)));
+                        client_received_message();
+                    }
+                },
+            )
+            .unwrap();
+        client
+    };
+    let _first_client = client_generator();
+    let _second_client = client_generator();
+
+    client_received.await.unwrap();
+    let opened = opened.borrow();
+    assert_eq!(received.get(), Some((opened[0], 42)));
+    assert_eq!(*relayed.borrow(), vec![(opened[0], opened[1])]);
+}
+
+#[wasm_bindgen_test]
+async fn sessions_are_listed_with_their_peer_counts() {
+    let signaling = LoopbackSignaling::new();
+    let mut server =
+        MiniServer::with_loopback(&signaling, SessionId::new(7), ConnectionType::Local);
+    server.start(|_| {}, |_, _: ()| {}).unwrap();
+    let mut client =
+        MiniClient::with_loopback(&signaling, SessionId::new(3), ConnectionType::Local);
+    client.start(|| {}, |_: ()| {}).unwrap();
+
+    let sessions = client.list_sessions().await;
+    assert_eq!(
+        sessions,
+        vec![
+            SessionInfo {
+                session_id: SessionId::new(3),
+                peer_count: 1,
+                topology: TopologyType::OneToMany,
+            },
+            SessionInfo {
+                session_id: SessionId::new(7),
+                peer_count: 1,
+                topology: TopologyType::OneToMany,
+            },
+        ]
+    );
 }
@@ -2,13 +2,18 @@
 
 #![cfg(target_arch = "wasm32")]
 
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::rc::Rc;
 
 use wasm_bindgen_test::{wasm_bindgen_test, wasm_bindgen_test_configure};
 use wasm_peers::one_to_one::NetworkManager;
-use wasm_peers::{ConnectionType, SessionId};
-use web_sys::console;
+use wasm_peers::test_utils::{completion, LoopbackSignaling};
+use wasm_peers::{
+    AlreadyStartedError, ChannelModeError, ChannelState, CloseReason, ConnectionType,
+    DataChannelConfig, JoinError, JoinIntent, MessageTooLargeError, NegotiationNeededHandler,
+    PeerDiagnostics, PeerEvent, SessionId,
+};
+use web_sys::{console, RtcDataChannelState, RtcSignalingState};
 
 const SIGNALING_SERVER_URL: &str = "ws://0.0.0.0:9001/one-to-one";
 
@@ -16,56 +21,621 @@ wasm_bindgen_test_configure!(run_in_browser);
 
 #[wasm_bindgen_test]
 fn network_manager_starts_successfully() {
-    let mut server = NetworkManager::new(
+    let mut server = NetworkManager::with_loopback(
+        &LoopbackSignaling::new(),
+        SessionId::new(1234),
+        ConnectionType::Local,
+    )
+    .unwrap();
+    server.start(|| {}, |_: ()| {}).unwrap();
+}
+
+/// Requires signaling server to be running, see `cargo xtask test`.
+#[wasm_bindgen_test]
+async fn network_manager_created_asynchronously_starts_successfully() {
+    let mut server = NetworkManager::new_async(
         SIGNALING_SERVER_URL,
         SessionId::new(1234),
-        &ConnectionType::Local,
+        ConnectionType::Local,
     )
+    .await
     .unwrap();
-    server.start(|| {}, |_: ()| {});
+    server.start(|| {}, |_: ()| {}).unwrap();
 }
 
 #[wasm_bindgen_test]
-fn single_message_passes_both_ways() {
-    let server_received_message = Rc::new(RefCell::new(false));
-    let client_received_message = Rc::new(RefCell::new(false));
+fn network_manager_starts_with_event_handler_successfully() {
+    let mut server = NetworkManager::with_loopback(
+        &LoopbackSignaling::new(),
+        SessionId::new(1234),
+        ConnectionType::Local,
+    )
+    .unwrap();
+    server
+        .start_with_event_handler(|event: PeerEvent<(), ()>| {
+            console::log_1(&format!("event: {:?}", event).into());
+        })
+        .unwrap();
+}
 
-    let mut server = NetworkManager::new(
-        SIGNALING_SERVER_URL,
+#[wasm_bindgen_test]
+fn network_manager_keeps_connection_type() {
+    let connection_type = ConnectionType::Stun {
+        urls: "stun:openrelay.metered.ca:80".to_owned(),
+    };
+    let server = NetworkManager::with_loopback(
+        &LoopbackSignaling::new(),
         SessionId::new(1234),
-        &ConnectionType::Local,
+        connection_type.clone(),
     )
     .unwrap();
+    assert_eq!(server.connection_type(), connection_type);
+}
+
+#[wasm_bindgen_test]
+async fn network_manager_describes_its_session() {
+    let signaling = LoopbackSignaling::new();
+    let (server_opened, server_open) = completion();
+    let (client_opened, client_open) = completion();
+
+    let mut server =
+        NetworkManager::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local)
+            .unwrap();
+    assert_eq!(server.session_id(), SessionId::new(1234));
+    assert_eq!(server.is_host(), None);
+    assert_eq!(server.signaling_url(), None);
+    assert_eq!(server.to_string(), "one-to-one peer in SessionId(1234)");
+
+    server.start(server_opened, |_: ()| {}).unwrap();
+    let mut client =
+        NetworkManager::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local)
+            .unwrap();
+    client.start(client_opened, |_: ()| {}).unwrap();
+    server_open.await.unwrap();
+    client_open.await.unwrap();
+
+    assert_eq!(server.is_host(), Some(true));
+    assert_eq!(client.is_host(), Some(false));
+    assert_eq!(
+        format!("{:?}", client),
+        "NetworkManager { session_id: SessionId(1234), is_host: Some(false), signaling_url: None, .. }"
+    );
+}
+
+#[wasm_bindgen_test]
+fn network_manager_cannot_be_started_twice() {
+    let mut server = NetworkManager::with_loopback(
+        &LoopbackSignaling::new(),
+        SessionId::new(1234),
+        ConnectionType::Local,
+    )
+    .unwrap();
+    server.start(|| {}, |_: ()| {}).unwrap();
+    let error = server.clone().start(|| {}, |_: ()| {}).unwrap_err();
+    assert_eq!(error.downcast_ref(), Some(&AlreadyStartedError));
+}
+
+#[wasm_bindgen_test]
+async fn diagnostics_describe_idle_peer_without_credentials() {
+    let connection_type = ConnectionType::StunAndTurn {
+        stun_urls: "stun:stun.example.com:3478".to_owned(),
+        turn_urls: "turn:turn.example.com:3478".to_owned(),
+        username: "secret-username".to_owned(),
+        credential: "secret-credential".to_owned(),
+    };
+    let network_manager = NetworkManager::with_loopback(
+        &LoopbackSignaling::new(),
+        SessionId::new(1234),
+        connection_type,
+    )
+    .unwrap();
+
+    let report = network_manager.diagnostics().await;
+    assert_eq!(report.session_id, "1234");
+    assert_eq!(report.signaling_url, None);
+    assert_eq!(report.signaling_state, "loopback");
+    assert_eq!(report.is_host, None);
+    assert!(report
+        .connection_type
+        .contains("turn:turn.example.com:3478"));
+    assert!(!report.connection_type.contains("secret"));
+    assert!(report.user_agent.is_some());
+    assert_eq!(
+        report.peers,
+        vec![PeerDiagnostics {
+            user_id: None,
+            ice_connection_state: "new".to_owned(),
+            ice_gathering_state: "new".to_owned(),
+            signaling_state: "stable".to_owned(),
+            has_local_description: false,
+            has_remote_description: false,
+            refused_remote_candidates: 0,
+            suppressed_local_candidates: 0,
+            data_channel: None,
+        }]
+    );
+}
+
+#[wasm_bindgen_test]
+async fn single_message_passes_both_ways() {
+    let signaling = LoopbackSignaling::new();
+    let (server_received_message, server_received) = completion();
+    let (client_received_message, client_received) = completion();
+
+    let mut server =
+        NetworkManager::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local)
+            .unwrap();
 
     let server_clone = server.clone();
     let server_on_open = move || server_clone.send_message("ping!").unwrap();
-    let server_on_message = {
-        let server_received_message = server_received_message;
-        move |message: String| {
-            console::log_1(&format!("server received message: {}", message).into());
-            *server_received_message.borrow_mut() = true;
-        }
+    let server_on_message = move |message: String| {
+        console::log_1(&format!("server received message: {}", message).into());
+        assert_eq!(message, "pong!");
+        server_received_message();
     };
-    server.start(server_on_open, server_on_message);
+    server.start(server_on_open, server_on_message).unwrap();
 
-    let mut client = NetworkManager::new(
-        SIGNALING_SERVER_URL,
+    let mut client =
+        NetworkManager::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local)
+            .unwrap();
+    let client_on_open = || { /* do nothing */ };
+    let client_clone = client.clone();
+    let client_on_message = move |message: String| {
+        console::log_1(&format!("client received message: {}", message).into());
+        assert_eq!(message, "ping!");
+        client_clone.send_message("pong!").unwrap();
+        client_received_message();
+    };
+    client.start(client_on_open, client_on_message).unwrap();
+
+    client_received.await.unwrap();
+    server_received.await.unwrap();
+}
+
+#[wasm_bindgen_test]
+async fn plain_text_passes_both_ways() {
+    let signaling = LoopbackSignaling::new();
+    let (server_received_message, server_received) = completion();
+    let (client_received_message, client_received) = completion();
+
+    let mut server =
+        NetworkManager::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local)
+            .unwrap();
+    let server_clone = server.clone();
+    let server_on_open = move || {
+        let err = server_clone.send_message("ping!").unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ChannelModeError>(),
+            Some(&ChannelModeError { text_mode: true })
+        );
+        server_clone
+            .send_message_string("{\"ping\": true}")
+            .unwrap();
+    };
+    let server_on_message = move |message: String| {
+        assert_eq!(message, "pong!");
+        server_received_message();
+    };
+    server
+        .start_string(server_on_open, server_on_message)
+        .unwrap();
+
+    let mut client =
+        NetworkManager::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local)
+            .unwrap();
+    let client_clone = client.clone();
+    let client_on_message = move |message: String| {
+        // received as sent, without serialization
+        assert_eq!(message, "{\"ping\": true}");
+        client_clone.send_message_string("pong!").unwrap();
+        client_received_message();
+    };
+    client.start_string(|| {}, client_on_message).unwrap();
+
+    client_received.await.unwrap();
+    server_received.await.unwrap();
+}
+
+#[wasm_bindgen_test]
+fn plain_text_is_refused_on_binary_data_channel() {
+    let network_manager = NetworkManager::with_loopback(
+        &LoopbackSignaling::new(),
         SessionId::new(1234),
-        &ConnectionType::Local,
+        ConnectionType::Local,
     )
     .unwrap();
-    let client_on_open = || { /* do nothing */ };
-    let client_clone = client.clone();
-    let client_on_message = {
-        let client_received_message = client_received_message;
-        move |message: String| {
-            console::log_1(&format!("client received message: {}", message).into());
-            client_clone.send_message("pong!").unwrap();
-            *client_received_message.borrow_mut() = true;
+    let err = network_manager.send_message_string("ping!").unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<ChannelModeError>(),
+        Some(&ChannelModeError { text_mode: false })
+    );
+}
+
+#[wasm_bindgen_test]
+async fn boxed_callbacks_without_clone_are_accepted() {
+    let signaling = LoopbackSignaling::new();
+    let (client_received_message, client_received) = completion();
+
+    let mut server =
+        NetworkManager::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local)
+            .unwrap();
+    let server_clone = server.clone();
+    let server_on_open: Box<dyn FnMut()> =
+        Box::new(move || server_clone.send_message("ping!").unwrap());
+    server.start(server_on_open, |_: String| {}).unwrap();
+
+    let mut client =
+        NetworkManager::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local)
+            .unwrap();
+    // callback owns its state, without sharing it through `Rc<RefCell<_>>`
+    let mut received_messages = Vec::new();
+    let client_on_message: Box<dyn FnMut(String)> = Box::new(move |message| {
+        received_messages.push(message);
+        if received_messages.len() == 1 {
+            client_received_message();
         }
+    });
+    client.start(|| {}, client_on_message).unwrap();
+
+    client_received.await.unwrap();
+}
+
+#[wasm_bindgen_test]
+async fn replaced_callbacks_receive_messages_of_different_type() {
+    let signaling = LoopbackSignaling::new();
+    let (server_opened, server_open) = completion();
+    let (server_reopened, server_reopen) = completion();
+    let (server_received_message, server_received) = completion();
+
+    let mut server =
+        NetworkManager::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local)
+            .unwrap();
+    server
+        .start(server_opened, |message: String| {
+            panic!("original callback received message: {}", message);
+        })
+        .unwrap();
+
+    let mut client =
+        NetworkManager::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local)
+            .unwrap();
+    let client_clone = client.clone();
+    let client_on_message = move |message: String| {
+        assert_eq!(message, "game started");
+        client_clone.send_message(&42_u32).unwrap();
+    };
+    client.start(|| {}, client_on_message).unwrap();
+    server_open.await.unwrap();
+
+    // data channel is already open, so the new `on_open` is called right away
+    server
+        .replace_callbacks(server_reopened, move |message: u32| {
+            assert_eq!(message, 42);
+            server_received_message();
+        })
+        .unwrap();
+    server_reopen.await.unwrap();
+    server.send_message("game started").unwrap();
+    server_received.await.unwrap();
+}
+
+#[wasm_bindgen_test]
+fn callbacks_cannot_be_replaced_before_start() {
+    let server = NetworkManager::with_loopback(
+        &LoopbackSignaling::new(),
+        SessionId::new(1234),
+        ConnectionType::Local,
+    )
+    .unwrap();
+    server.replace_callbacks(|| {}, |_: ()| {}).unwrap_err();
+}
+
+#[wasm_bindgen_test]
+async fn reliable_message_is_received_once_before_sending_resolves() {
+    let signaling = LoopbackSignaling::new();
+    let (server_opened, server_open) = completion();
+    let (client_opened, client_open) = completion();
+
+    let mut server =
+        NetworkManager::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local)
+            .unwrap();
+    server.start(server_opened, |_: ()| {}).unwrap();
+    let mut client =
+        NetworkManager::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local)
+            .unwrap();
+    let received = Rc::new(RefCell::new(Vec::new()));
+    {
+        let received = Rc::clone(&received);
+        client
+            .start(client_opened, move |message: String| {
+                received.borrow_mut().push(message);
+            })
+            .unwrap();
+    }
+    server_open.await.unwrap();
+    client_open.await.unwrap();
+
+    server.send_message_reliably("you were hit").await.unwrap();
+    assert_eq!(*received.borrow(), vec!["you were hit".to_owned()]);
+}
+
+#[wasm_bindgen_test]
+async fn signaling_state_is_stable_once_connected() {
+    let signaling = LoopbackSignaling::new();
+    let (server_opened, server_open) = completion();
+    let (client_opened, client_open) = completion();
+
+    let mut server =
+        NetworkManager::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local)
+            .unwrap();
+    assert_eq!(server.signaling_state(), RtcSignalingState::Stable);
+    server.start(server_opened, |_: ()| {}).unwrap();
+    let mut client =
+        NetworkManager::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local)
+            .unwrap();
+    client.start(client_opened, |_: ()| {}).unwrap();
+
+    server_open.await.unwrap();
+    client_open.await.unwrap();
+    assert_eq!(server.signaling_state(), RtcSignalingState::Stable);
+    assert_eq!(client.signaling_state(), RtcSignalingState::Stable);
+
+    server.close();
+    assert_eq!(server.signaling_state(), RtcSignalingState::Closed);
+}
+
+#[wasm_bindgen_test]
+fn data_channel_is_labeled_with_session_id_by_default() {
+    let signaling = LoopbackSignaling::new();
+    let mut default_label =
+        NetworkManager::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local)
+            .unwrap();
+    default_label.start(|| {}, |_: ()| {}).unwrap();
+    let mut custom_label =
+        NetworkManager::with_loopback(&signaling, SessionId::new(5678), ConnectionType::Local)
+            .unwrap()
+            .with_data_channel_config(DataChannelConfig {
+                label: Some("chess-game-data".to_owned()),
+                ..DataChannelConfig::default()
+            });
+    custom_label.start(|| {}, |_: ()| {}).unwrap();
+
+    let label = |network_manager: &NetworkManager| {
+        network_manager
+            .inner
+            .borrow()
+            .data_channel
+            .as_ref()
+            .map(web_sys::RtcDataChannel::label)
+    };
+    assert_eq!(
+        label(&default_label),
+        Some(SessionId::new(1234).to_string())
+    );
+    assert_eq!(label(&custom_label), Some("chess-game-data".to_owned()));
+}
+
+#[wasm_bindgen_test]
+async fn data_channel_carries_configured_protocol() {
+    let signaling = LoopbackSignaling::new();
+    let (server_opened, server_open) = completion();
+    let (client_opened, client_open) = completion();
+    let config = DataChannelConfig {
+        protocol: Some("chat".to_owned()),
+        ..DataChannelConfig::default()
+    };
+
+    let mut server =
+        NetworkManager::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local)
+            .unwrap()
+            .with_data_channel_config(config.clone());
+    server.start(server_opened, |_: ()| {}).unwrap();
+    let mut client =
+        NetworkManager::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local)
+            .unwrap()
+            .with_data_channel_config(config);
+    client.start(client_opened, |_: ()| {}).unwrap();
+    server_open.await.unwrap();
+    client_open.await.unwrap();
+
+    for network_manager in [&server, &client] {
+        let report = network_manager.diagnostics().await;
+        let data_channel = report.peers[0].data_channel.as_ref().unwrap();
+        assert_eq!(data_channel.protocol, "chat");
+    }
+}
+
+#[wasm_bindgen_test]
+async fn raw_peer_connection_is_the_one_used_for_messaging() {
+    let signaling = LoopbackSignaling::new();
+    let (server_opened, server_open) = completion();
+    let (client_opened, client_open) = completion();
+
+    let mut server =
+        NetworkManager::with_loopback(&signaling, SessionId::new(4321), ConnectionType::Local)
+            .unwrap();
+    let peer_connection = server.as_raw_peer_connection();
+    server.start(server_opened, |_: ()| {}).unwrap();
+    let mut client =
+        NetworkManager::with_loopback(&signaling, SessionId::new(4321), ConnectionType::Local)
+            .unwrap();
+    client.start(client_opened, |_: ()| {}).unwrap();
+
+    server_open.await.unwrap();
+    client_open.await.unwrap();
+    assert!(peer_connection.remote_description().is_some());
+
+    server.close();
+    assert_eq!(peer_connection.signaling_state(), RtcSignalingState::Closed);
+}
+
+#[wasm_bindgen_test]
+async fn channel_closed_by_other_peer_is_reported() {
+    let signaling = LoopbackSignaling::new();
+    let (server_opened, server_open) = completion();
+    let (server_closed, server_close) = completion();
+
+    let mut server =
+        NetworkManager::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local)
+            .unwrap();
+    assert_eq!(server.channel_ready_state(), None);
+    let events = Rc::new(RefCell::new(Vec::new()));
+    {
+        let events = Rc::clone(&events);
+        server
+            .start_with_event_handler(move |event: PeerEvent<(), ()>| match event {
+                PeerEvent::Opened(()) => server_opened(),
+                PeerEvent::ChannelStateChanged((), state) => events.borrow_mut().push(state),
+                PeerEvent::Closed((), reason) => {
+                    assert_eq!(reason, CloseReason::ChannelClosed);
+                    server_closed();
+                }
+                _ => {}
+            })
+            .unwrap();
+    }
+    let mut client =
+        NetworkManager::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local)
+            .unwrap();
+    client.start(|| {}, |_: ()| {}).unwrap();
+
+    server_open.await.unwrap();
+    assert_eq!(
+        server.channel_ready_state(),
+        Some(RtcDataChannelState::Open)
+    );
+    client.close();
+    server_close.await.unwrap();
+    assert_eq!(events.borrow().last(), Some(&ChannelState::Closed));
+    assert_eq!(
+        server.channel_ready_state(),
+        Some(RtcDataChannelState::Closed)
+    );
+}
+
+#[wasm_bindgen_test]
+async fn joining_missing_session_is_reported_until_it_is_created() {
+    let signaling = LoopbackSignaling::new();
+    let (join_refused, refused) = completion();
+    let mut joining =
+        NetworkManager::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local)
+            .unwrap()
+            .with_join_intent(JoinIntent::Join);
+    joining
+        .start_with_event_handler(move |event: PeerEvent<(), ()>| {
+            if let PeerEvent::SignalingError(error) = event {
+                assert_eq!(error, JoinError::NoSuchSession.to_string());
+                join_refused();
+            }
+        })
+        .unwrap();
+    refused.await.unwrap();
+
+    let (creator_opened, creator_open) = completion();
+    let mut creator =
+        NetworkManager::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local)
+            .unwrap()
+            .with_join_intent(JoinIntent::Create);
+    creator.start(creator_opened, |_: ()| {}).unwrap();
+    let mut joining =
+        NetworkManager::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local)
+            .unwrap()
+            .with_join_intent(JoinIntent::Join);
+    joining.start(|| {}, |_: ()| {}).unwrap();
+    creator_open.await.unwrap();
+}
+
+#[wasm_bindgen_test]
+async fn negotiation_needed_handler_is_called_only_on_renegotiation() {
+    let signaling = LoopbackSignaling::new();
+    let (server_opened, server_open) = completion();
+    let (client_opened, client_open) = completion();
+    let (renegotiated, renegotiation) = completion();
+    let renegotiations = Rc::new(Cell::new(0));
+    let config = DataChannelConfig {
+        on_negotiation_needed: Some(NegotiationNeededHandler::new({
+            let renegotiations = Rc::clone(&renegotiations);
+            move || {
+                renegotiations.set(renegotiations.get() + 1);
+                renegotiated();
+            }
+        })),
+        ..DataChannelConfig::default()
     };
-    client.start(client_on_open, client_on_message);
 
-    // assert!(*client_received_message.borrow());
-    // assert!(*server_received_message.borrow());
+    let mut server =
+        NetworkManager::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local)
+            .unwrap()
+            .with_data_channel_config(config.clone());
+    server.start(server_opened, |_: ()| {}).unwrap();
+    let mut client =
+        NetworkManager::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local)
+            .unwrap()
+            .with_data_channel_config(config);
+    client.start(client_opened, |_: ()| {}).unwrap();
+
+    server_open.await.unwrap();
+    client_open.await.unwrap();
+    // initial offer was negotiated without it
+    assert_eq!(renegotiations.get(), 0);
+
+    server.as_raw_peer_connection().create_data_channel("extra");
+    renegotiation.await.unwrap();
+    assert_eq!(renegotiations.get(), 1);
+}
+
+#[wasm_bindgen_test]
+async fn oversized_message_is_rejected_before_sending() {
+    let signaling = LoopbackSignaling::new();
+    let (server_opened, server_open) = completion();
+    let (client_opened, client_open) = completion();
+
+    let mut server =
+        NetworkManager::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local)
+            .unwrap()
+            .with_data_channel_config(DataChannelConfig {
+                max_message_size: 128 * 1024,
+                ..DataChannelConfig::default()
+            });
+    server.start(server_opened, |_: String| {}).unwrap();
+    let mut client =
+        NetworkManager::with_loopback(&signaling, SessionId::new(1234), ConnectionType::Local)
+            .unwrap();
+    client.start(client_opened, |_: String| {}).unwrap();
+
+    server_open.await.unwrap();
+    client_open.await.unwrap();
+    let message = "a".repeat(80 * 1024);
+    let err = client.send_message(&message).unwrap_err();
+    let err = err.downcast_ref::<MessageTooLargeError>().unwrap();
+    assert!(err.size > 80 * 1024);
+    assert_eq!(err.limit, 64 * 1024);
+    assert_eq!(
+        client.channel_ready_state(),
+        Some(RtcDataChannelState::Open)
+    );
+
+    server.send_message(&message).unwrap();
+    assert!(server.negotiated_max_message_size().unwrap() >= 128 * 1024);
+}
+
+/// Requires `native_pong` example to be running, see `cargo xtask test`.
+#[wasm_bindgen_test]
+fn single_message_passes_between_browser_and_native_peer() {
+    let received_pong = Rc::new(RefCell::new(false));
+
+    let mut peer = NetworkManager::new(
+        SIGNALING_SERVER_URL,
+        SessionId::new(5678),
+        ConnectionType::Local,
+    )
+    .unwrap();
+
+    let peer_clone = peer.clone();
+    let peer_on_open = move || peer_clone.send_message("ping!").unwrap();
+    let peer_on_message = move |message: String| {
+        console::log_1(&format!("browser peer received message: {}", message).into());
+        *received_pong.borrow_mut() = message == "pong!";
+    };
+    peer.start(peer_on_open, peer_on_message).unwrap();
 }
@@ -0,0 +1,88 @@
+//! Test suite for the Web and headless browsers.
+
+#![cfg(all(target_arch = "wasm32", feature = "session-bootstrap"))]
+
+use wasm_bindgen::JsValue;
+use wasm_bindgen_test::{wasm_bindgen_test, wasm_bindgen_test_configure};
+use wasm_peers::session_bootstrap::SessionBootstrap;
+use wasm_peers::SessionId;
+use web_sys::Url;
+
+wasm_bindgen_test_configure!(run_in_browser);
+
+/// Replaces the page URL with `path` relative to it, returning the previous one to restore.
+fn replace_location(path: &str) -> String {
+    let window = web_sys::window().unwrap();
+    let previous = window.location().href().unwrap();
+    window
+        .history()
+        .unwrap()
+        .replace_state_with_url(&JsValue::NULL, "", Some(path))
+        .unwrap();
+    previous
+}
+
+fn location() -> Url {
+    Url::new(&web_sys::window().unwrap().location().href().unwrap()).unwrap()
+}
+
+#[wasm_bindgen_test]
+fn session_id_is_read_from_query() {
+    let previous = replace_location("?session=42");
+    let bootstrap = SessionBootstrap::from_location().unwrap();
+    assert_eq!(bootstrap.session_id(), SessionId::new(42));
+    assert!(!bootstrap.is_new_session());
+    assert_eq!(location().search(), "?session=42");
+    assert_eq!(bootstrap.share_url(), location().href());
+    replace_location(&previous);
+}
+
+#[wasm_bindgen_test]
+fn session_id_is_read_from_fragment_and_kept_there() {
+    let previous = replace_location("?room=lobby#view=map&session=SessionId(7)");
+    let bootstrap = SessionBootstrap::from_location().unwrap();
+    assert_eq!(bootstrap.session_id(), SessionId::new(7));
+    assert!(!bootstrap.is_new_session());
+    assert_eq!(location().search(), "?room=lobby");
+    assert_eq!(location().hash(), "#view=map&session=7");
+    replace_location(&previous);
+}
+
+#[wasm_bindgen_test]
+fn missing_session_id_is_generated_and_written_into_query() {
+    let previous = replace_location("?room=lobby");
+    let bootstrap = SessionBootstrap::from_location().unwrap();
+    assert!(bootstrap.is_new_session());
+    let written = location().search_params().get("session").unwrap();
+    assert_eq!(written, bootstrap.session_id().inner().to_string());
+    assert_eq!(location().search_params().get("room").unwrap(), "lobby");
+
+    // opening the shared URL joins the same session
+    replace_location(&bootstrap.share_url());
+    let joined = SessionBootstrap::from_location().unwrap();
+    assert_eq!(joined.session_id(), bootstrap.session_id());
+    assert!(!joined.is_new_session());
+    replace_location(&previous);
+}
+
+#[wasm_bindgen_test]
+fn malformed_session_id_is_regenerated() {
+    let previous = replace_location("?session=not-a-number");
+    let bootstrap = SessionBootstrap::from_location().unwrap();
+    assert!(bootstrap.is_new_session());
+    assert_eq!(
+        location().search_params().get("session").unwrap(),
+        bootstrap.session_id().inner().to_string()
+    );
+    replace_location(&previous);
+}
+
+#[wasm_bindgen_test]
+fn url_is_replaced_without_adding_history_entry() {
+    let previous = replace_location("?other=1");
+    let history = web_sys::window().unwrap().history().unwrap();
+    let length = history.length().unwrap();
+    SessionBootstrap::from_location().unwrap();
+    assert_eq!(history.length().unwrap(), length);
+    replace_location(&previous);
+}
@@ -0,0 +1,70 @@
+//! Test suite for the Web and headless browsers.
+
+#![cfg(target_arch = "wasm32")]
+
+use wasm_bindgen_test::{wasm_bindgen_test, wasm_bindgen_test_configure};
+use wasm_peers::one_to_many::MiniServer;
+use wasm_peers::one_to_one::NetworkManager;
+use wasm_peers::test_utils::{completion, LoopbackSignaling, Topology};
+use wasm_peers::{ConnectionType, SessionId, SignalingClient};
+
+wasm_bindgen_test_configure!(run_in_browser);
+
+#[wasm_bindgen_test]
+async fn one_to_one_sessions_share_connection() {
+    let signaling = LoopbackSignaling::new();
+    let shared = SignalingClient::with_loopback(&signaling, Topology::OneToOne);
+    let mut received = Vec::new();
+    for session_id in [SessionId::new(1), SessionId::new(2)] {
+        let mut peer = shared
+            .one_to_one(session_id, ConnectionType::Local)
+            .unwrap();
+        let peer_clone = peer.clone();
+        peer.start(
+            move || peer_clone.send_message(&session_id.to_string()).unwrap(),
+            |_: String| {},
+        )
+        .unwrap();
+
+        let (message_received, message) = completion();
+        let mut partner =
+            NetworkManager::with_loopback(&signaling, session_id, ConnectionType::Local).unwrap();
+        partner
+            .start(
+                || {},
+                move |message: String| {
+                    // each partner receives only messages of its own session
+                    assert_eq!(message, session_id.to_string());
+                    message_received();
+                },
+            )
+            .unwrap();
+        received.push(message);
+    }
+    for message in received {
+        message.await.unwrap();
+    }
+}
+
+#[wasm_bindgen_test]
+async fn mini_client_joins_several_sessions() {
+    let signaling = LoopbackSignaling::new();
+    let shared = SignalingClient::with_loopback(&signaling, Topology::OneToMany);
+    let mut opened = Vec::new();
+    let mut servers = Vec::new();
+    for session_id in [SessionId::new(1), SessionId::new(2)] {
+        let mut server = MiniServer::with_loopback(&signaling, session_id, ConnectionType::Local);
+        server.start(|_| {}, |_, _: ()| {}).unwrap();
+        let (client_opened, client_open) = completion();
+        let mut client = shared.mini_client(session_id, ConnectionType::Local);
+        client.start(client_opened, |_: ()| {}).unwrap();
+        opened.push(client_open);
+        servers.push(server);
+    }
+    for client_open in opened {
+        client_open.await.unwrap();
+    }
+    for server in servers {
+        server.close();
+    }
+}
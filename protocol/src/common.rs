@@ -1,11 +1,16 @@
-use std::fmt::{Display, Formatter};
-use std::str::FromStr;
+use alloc::string::{String, ToString};
+use core::fmt::{Debug, Display, Formatter};
+use core::num::ParseIntError;
+use core::str::FromStr;
 
 use serde::{Deserialize, Serialize};
 
+use crate::RedactedSdp;
+
 /// Unique identifier of signaling session that each user provides
 /// when communicating with the signaling server.
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct SessionId(u128);
 
 impl SessionId {
@@ -24,22 +29,69 @@ impl SessionId {
 }
 
 impl FromStr for SessionId {
-    type Err = <u128 as FromStr>::Err;
+    type Err = ParseIntError;
 
+    /// Parses either the bare number or the [`Display`] form, `SessionId(<number>)`.
+    /// Fails with the error of parsing the number, whose [`ParseIntError::kind`] tells what's wrong with it.
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(Self(s.parse()?))
+        let inner = s
+            .strip_prefix("SessionId(")
+            .and_then(|s| s.strip_suffix(')'))
+            .unwrap_or(s);
+        Ok(Self(inner.parse()?))
+    }
+}
+
+impl From<u128> for SessionId {
+    fn from(val: u128) -> Self {
+        Self(val)
+    }
+}
+
+impl From<u64> for SessionId {
+    fn from(val: u64) -> Self {
+        Self(val.into())
+    }
+}
+
+impl From<&str> for SessionId {
+    /// Derives session id from its name with 128-bit FNV-1a hash,
+    /// so that peers using the same name join the same session.
+    ///
+    /// Unlike [`SessionId::from_str`], names aren't parsed, so `SessionId::from("42")` isn't `SessionId(42)`.
+    fn from(name: &str) -> Self {
+        const OFFSET_BASIS: u128 = 0x6c62_272e_07bb_0142_62b8_2175_6295_c58d;
+        const PRIME: u128 = 0x0000_0000_0100_0000_0000_0000_0000_013b;
+
+        Self(name.bytes().fold(OFFSET_BASIS, |hash, byte| {
+            (hash ^ u128::from(byte)).wrapping_mul(PRIME)
+        }))
+    }
+}
+
+impl From<String> for SessionId {
+    fn from(name: String) -> Self {
+        Self::from(name.as_str())
     }
 }
 
 impl Display for SessionId {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         write!(f, "SessionId({})", self.0)
     }
 }
 
+impl From<SessionId> for String {
+    /// Same as [`SessionId::to_string`], which can be parsed back with [`SessionId::from_str`].
+    fn from(session_id: SessionId) -> Self {
+        session_id.to_string()
+    }
+}
+
 /// Unique identifier of each peer connected to signaling server
 /// useful when communicating in one-to-many and many-to-many .
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize, Hash)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct UserId(u64);
 
 impl UserId {
@@ -63,19 +115,312 @@ impl From<u64> for UserId {
     }
 }
 
+impl From<u32> for UserId {
+    fn from(val: u32) -> Self {
+        Self(val.into())
+    }
+}
+
+impl From<usize> for UserId {
+    // usize is at most 64 bits wide on every platform Rust supports
+    #[allow(clippy::as_conversions, clippy::cast_possible_truncation)]
+    fn from(val: usize) -> Self {
+        Self(val as u64)
+    }
+}
+
 impl Display for UserId {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         write!(f, "{}", self.0)
     }
 }
 
+/// Identifier generated by the client itself, e.g. a UUID kept in local storage,
+/// that stays the same across reconnects, unlike [`UserId`] assigned on each connection.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct StableId(u128);
+
+impl StableId {
+    /// Wrap `u128` into a `StableId` `struct`
+    #[must_use]
+    pub const fn new(inner: u128) -> Self {
+        Self(inner)
+    }
+
+    /// Acquire the underlying type
+    #[must_use]
+    #[allow(clippy::missing_const_for_fn)]
+    pub fn inner(&self) -> u128 {
+        self.0
+    }
+}
+
+impl From<u128> for StableId {
+    fn from(val: u128) -> Self {
+        Self(val)
+    }
+}
+
+impl Display for StableId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "StableId({})", self.0)
+    }
+}
+
 /// Unique identifier specifying which peer is host and will be creating an offer,
 /// and which will await it.
 pub type IsHost = bool;
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+/// Whether further hosts can join one-to-many session, set by its first host.
+pub type AllowMultiHost = bool;
+
+/// Topology of a signaling session, each one is served by its own endpoint of the signaling server.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum TopologyType {
+    OneToOne,
+    OneToMany,
+    ManyToMany,
+}
+
+/// Session open on the signaling server, listed in response to `SessionListRequest`,
+/// e.g. to show a lobby of games that can be joined.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct SessionInfo {
+    pub session_id: SessionId,
+    /// Number of users in session, including the ones restored by the server that are yet to rejoin
+    pub peer_count: usize,
+    pub topology: TopologyType,
+}
+
+/// Whether a user joining a session expects it to exist already, sent with `SessionJoinWithIntent`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum JoinIntent {
+    /// Create a new session, refused with [`JoinError::AlreadyExists`] if it exists
+    Create,
+    /// Join an existing session, e.g. with a code typed by the user,
+    /// refused with [`JoinError::NoSuchSession`] if it doesn't exist
+    Join,
+    /// Join the session, creating it if it doesn't exist, same as plain `SessionJoin`
+    CreateOrJoin,
+}
+
+// `#[default]` variants aren't supported by the minimum Rust version of wasm-peers
+#[allow(clippy::derivable_impls)]
+impl Default for JoinIntent {
+    fn default() -> Self {
+        Self::CreateOrJoin
+    }
+}
+
+/// Why signaling server refused a user joining a session with [`JoinIntent`], sent with `SessionJoinError`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum JoinError {
+    /// Session joined with [`JoinIntent::Join`] doesn't exist
+    NoSuchSession,
+    /// Session joined with [`JoinIntent::Create`] already exists
+    AlreadyExists,
+}
+
+impl Display for JoinError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match *self {
+            Self::NoSuchSession => f.write_str("no such session"),
+            Self::AlreadyExists => f.write_str("session already exists"),
+        }
+    }
+}
+
+/// Debug output masks IP addresses and ICE credentials of the candidate, see [`RedactedSdp`].
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct IceCandidate {
     pub candidate: String,
     pub sdp_mid: Option<String>,
     pub sdp_m_line_index: Option<u16>,
 }
+
+impl IceCandidate {
+    /// Candidate with an empty `candidate` string, telling the other peer that gathering is complete
+    /// and no more candidates will follow.
+    #[must_use]
+    pub const fn end_of_candidates() -> Self {
+        Self {
+            candidate: String::new(),
+            sdp_mid: None,
+            sdp_m_line_index: None,
+        }
+    }
+
+    /// Whether this is [`IceCandidate::end_of_candidates`] rather than an actual candidate.
+    #[must_use]
+    #[allow(clippy::missing_const_for_fn)]
+    pub fn is_end_of_candidates(&self) -> bool {
+        self.candidate.is_empty()
+    }
+}
+
+impl Debug for IceCandidate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("IceCandidate")
+            .field("candidate", &RedactedSdp(&self.candidate))
+            .field("sdp_mid", &self.sdp_mid)
+            .field("sdp_m_line_index", &self.sdp_m_line_index)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::hash_map::DefaultHasher;
+    use std::collections::HashMap;
+    use std::hash::{Hash, Hasher};
+    use std::num::IntErrorKind;
+
+    use super::*;
+
+    fn hash_of(value: &impl Hash) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn test_end_of_candidates_is_recognized() {
+        assert!(IceCandidate::end_of_candidates().is_end_of_candidates());
+        let candidate = IceCandidate {
+            candidate: "candidate:1 1 udp 2122260223 192.168.0.2 54321 typ host".to_owned(),
+            sdp_mid: Some("0".to_owned()),
+            sdp_m_line_index: Some(0),
+        };
+        assert!(!candidate.is_end_of_candidates());
+    }
+
+    #[test]
+    fn test_session_id_is_parsed_from_number() {
+        assert_eq!("42".parse(), Ok(SessionId::new(42)));
+        assert_eq!(u128::MAX.to_string().parse(), Ok(SessionId::new(u128::MAX)));
+    }
+
+    #[test]
+    fn test_session_id_display_round_trips() {
+        for session_id in [
+            SessionId::new(0),
+            SessionId::new(42),
+            SessionId::new(u128::MAX),
+        ] {
+            assert_eq!(session_id.to_string().parse(), Ok(session_id));
+        }
+    }
+
+    #[test]
+    fn test_session_id_is_converted_into_parsable_string() {
+        let session_id = SessionId::new(42);
+        let string = String::from(session_id);
+        assert_eq!(string, "SessionId(42)");
+        assert_eq!(string.parse(), Ok(session_id));
+    }
+
+    #[test]
+    fn test_invalid_session_id_is_rejected() {
+        for invalid in ["", "-1", "abc", "SessionId(abc)", "SessionId(1", "1)"] {
+            assert!(
+                invalid.parse::<SessionId>().is_err(),
+                "{invalid:?} was parsed"
+            );
+        }
+    }
+
+    #[test]
+    fn test_session_id_parse_error_tells_what_is_wrong() {
+        let kind_of = |invalid: &str| {
+            invalid
+                .parse::<SessionId>()
+                .map(|_| ())
+                .map_err(|err| *err.kind())
+        };
+        assert_eq!(kind_of(""), Err(IntErrorKind::Empty));
+        assert_eq!(kind_of("SessionId()"), Err(IntErrorKind::Empty));
+        assert_eq!(kind_of("SessionId(abc)"), Err(IntErrorKind::InvalidDigit));
+        assert_eq!(
+            kind_of("340282366920938463463374607431768211456"),
+            Err(IntErrorKind::PosOverflow)
+        );
+    }
+
+    #[test]
+    fn test_session_id_is_converted_from_numbers() {
+        let inner: u128 = 42;
+        assert_eq!(SessionId::from(inner), SessionId::new(42));
+        assert_eq!(SessionId::from(u64::MAX), SessionId::new(u64::MAX.into()));
+    }
+
+    #[test]
+    fn test_session_id_is_derived_from_name() {
+        assert_eq!(
+            SessionId::from("my-session"),
+            SessionId::from("my-session".to_owned())
+        );
+        assert_ne!(
+            SessionId::from("my-session"),
+            SessionId::from("my-session-2")
+        );
+        // reference value of FNV-1a, ids must stay the same across versions for peers to meet
+        assert_eq!(
+            SessionId::from(""),
+            SessionId::new(0x6c62_272e_07bb_0142_62b8_2175_6295_c58d)
+        );
+        assert_eq!(
+            SessionId::from("a"),
+            SessionId::new(0xd228_cb69_6f1a_8caf_7891_2b70_4e4a_8964)
+        );
+    }
+
+    #[test]
+    fn test_user_id_is_converted_from_numbers() {
+        let (inner_u32, inner_usize): (u32, usize) = (7, 7);
+        assert_eq!(UserId::from(inner_u32), UserId::new(7));
+        assert_eq!(UserId::from(inner_usize), UserId::new(7));
+        assert_eq!(UserId::from(u64::MAX), UserId::new(u64::MAX));
+    }
+
+    #[test]
+    fn test_user_id_display_is_bare_number() {
+        assert_eq!(UserId::new(7).to_string(), "7");
+    }
+
+    #[test]
+    fn test_stable_id_display_shows_inner_value() {
+        assert_eq!(StableId::from(u128::MAX).inner(), u128::MAX);
+        assert_eq!(StableId::new(7).to_string(), "StableId(7)");
+    }
+
+    #[test]
+    fn test_user_ids_are_ordered_by_value() {
+        let mut user_ids = vec![UserId::new(3), UserId::new(1), UserId::new(2)];
+        user_ids.sort();
+        assert_eq!(
+            user_ids,
+            vec![UserId::new(1), UserId::new(2), UserId::new(3)]
+        );
+        assert!(UserId::new(u64::MAX) > UserId::from(u64::MIN));
+    }
+
+    #[test]
+    fn test_user_ids_hash_like_their_values() {
+        let one: u64 = 1;
+        assert_eq!(hash_of(&UserId::new(1)), hash_of(&UserId::from(one)));
+        assert_ne!(hash_of(&UserId::new(1)), hash_of(&UserId::new(2)));
+
+        let mut users = HashMap::new();
+        users.insert(UserId::new(1), "first");
+        users.insert(UserId::from(one), "replaced");
+        users.insert(UserId::new(2), "second");
+        assert_eq!(users.len(), 2);
+        assert_eq!(users.get(&UserId::new(1)), Some(&"replaced"));
+    }
+}
@@ -0,0 +1,47 @@
+/*!
+Support for fuzzing code that handles signal messages, enabled with `arbitrary` feature.
+
+Signal messages of every topology, along with ids and ICE candidates they carry, implement [`Arbitrary`],
+so fuzz targets can take well-formed messages as input instead of raw bytes:
+
+```ignore
+libfuzzer_sys::fuzz_target!(|message: SignalMessage| {
+    let encoded = rmp_serde::to_vec(&message).unwrap();
+    assert_eq!(rmp_serde::from_slice::<SignalMessage>(&encoded).unwrap(), message);
+});
+```
+
+Targets of the signaling server are in `signaling-server/fuzz`, run them with `cargo fuzz run <target>` from there.
+*/
+
+pub use arbitrary::{Arbitrary, Unstructured};
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{many_to_many, one_to_many, one_to_one};
+
+    fn round_trip<'a, M>(data: &'a [u8])
+    where
+        M: Arbitrary<'a>
+            + serde::Serialize
+            + serde::de::DeserializeOwned
+            + PartialEq
+            + core::fmt::Debug,
+    {
+        let message = M::arbitrary_take_rest(Unstructured::new(data)).expect("not enough data");
+        let encoded = rmp_serde::to_vec(&message).expect("failed to encode");
+        assert_eq!(rmp_serde::from_slice::<M>(&encoded).ok(), Some(message));
+    }
+
+    #[test]
+    fn test_arbitrary_messages_round_trip() {
+        let data: Vec<u8> = (0..=255).collect();
+        for offset in 0..32 {
+            let data = data.get(offset..).unwrap_or_default();
+            round_trip::<one_to_one::SignalMessage>(data);
+            round_trip::<one_to_many::SignalMessage>(data);
+            round_trip::<many_to_many::SignalMessage>(data);
+        }
+    }
+}
@@ -1,8 +1,19 @@
 /*!
 Helper crate that declares common types and structures shared between [wasm-peers](https://docs.rs/wasm-peers/latest/wasm_peers/)
 and [wasm-peers-signaling-server](https://docs.rs/wasm-peers-signaling-server/latest/wasm_peers_signaling_server/).
+
+With `no-std` feature the crate only depends on `core` and `alloc`,
+so signal messages can be exchanged from embedded targets as well.
+
+With `arbitrary` feature all of them can be generated by fuzzers, see [`fuzzing`] module.
+Fuzzers run on `std` targets and code derived by `arbitrary` refers to `std`, so the crate
+is built with `std` whenever it's enabled, even together with `no-std`.
+
+Debug output of signal messages is safe to log, as SDP and ICE candidates are redacted with [`RedactedSdp`],
+unless `log-full-sdp` feature is enabled.
 */
 
+#![cfg_attr(all(feature = "no-std", not(feature = "arbitrary"), not(test)), no_std)]
 #![allow(clippy::module_name_repetitions)]
 // clippy WARN level lints
 #![warn(
@@ -65,10 +76,35 @@ and [wasm-peers-signaling-server](https://docs.rs/wasm-peers-signaling-server/la
     clippy::unseparated_literal_suffix,
     clippy::verbose_file_reads
 )]
+// `no-std` only drops the dependency on `std`, API stays the same with or without it
+#![allow(clippy::negative_feature_names)]
+
+extern crate alloc;
 
 mod common;
+#[cfg(feature = "arbitrary")]
+pub mod fuzzing;
 pub mod many_to_many;
 pub mod one_to_many;
 pub mod one_to_one;
+mod redact;
+
+pub use common::{
+    AllowMultiHost, IceCandidate, IsHost, JoinError, JoinIntent, SessionId, SessionInfo, StableId,
+    TopologyType, UserId,
+};
+pub use redact::RedactedSdp;
+
+/// Version of the `MessagePack` encoding of signal messages.
+///
+/// It has to be bumped whenever messages encoded by an older version can no longer be decoded,
+/// which is checked against fixtures in `tests/fixtures`.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Major version of the protocol announced by users with `ProtocolVersion` signal message,
+/// signaling server refuses users speaking a different one. Same as [`PROTOCOL_VERSION`].
+pub const PROTOCOL_MAJOR_VERSION: u8 = 1;
 
-pub use common::{IceCandidate, IsHost, SessionId, UserId};
+/// Minor version of the protocol announced by users with `ProtocolVersion` signal message,
+/// bumped whenever signal messages are added, which peers speaking an older one just ignore.
+pub const PROTOCOL_MINOR_VERSION: u8 = 2;
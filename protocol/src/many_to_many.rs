@@ -3,15 +3,22 @@ Signaling messages exchanged between used by `NetworkManagers` and signaling ser
 to facilitate communication in many-to-many topology.
 */
 
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{Debug, Formatter};
+
 use serde::{Deserialize, Serialize};
 
 use crate::common::IceCandidate;
-use crate::{SessionId, UserId};
+use crate::{JoinError, JoinIntent, RedactedSdp, SessionId, SessionInfo, UserId};
 
 /// `Enum` consisting of two main categories are messages used to setup signaling session
 /// and messages used to setup `WebRTC` connection afterwards.
 /// Most of the include [`SessionId`] and [`UserId`] to uniquely identify each peer.
-#[derive(Debug, Serialize, Deserialize)]
+///
+/// Debug output redacts SDP and ICE candidates, see [`RedactedSdp`].
+#[derive(PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum SignalMessage {
     /// Either client or server connecting to signaling session
     SessionJoin(SessionId),
@@ -30,4 +37,71 @@ pub enum SignalMessage {
 
     /// Generic error containing detailed information about the cause
     Error(SessionId, String),
+
+    /// Asks signaling server for sessions open on the endpoint the user is connected to
+    SessionListRequest,
+
+    /// Sessions open on the endpoint, sent back to the user that asked for them with `SessionListRequest`
+    SessionListResponse(Vec<SessionInfo>),
+
+    /// User connecting to signaling session, telling whether it expects the session to exist already.
+    /// Signaling server answers with `SessionJoinError` if the session doesn't match `JoinIntent`,
+    /// otherwise it's the same as `SessionJoin`
+    SessionJoinWithIntent(SessionId, JoinIntent),
+
+    /// Sent back to the user whose `SessionJoinWithIntent` was refused, it's not in the session
+    SessionJoinError(SessionId, JoinError),
+}
+
+impl Debug for SignalMessage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match *self {
+            Self::SessionJoin(ref session_id) => {
+                f.debug_tuple("SessionJoin").field(session_id).finish()
+            }
+            Self::SessionReady(ref session_id, ref user_id) => f
+                .debug_tuple("SessionReady")
+                .field(session_id)
+                .field(user_id)
+                .finish(),
+            Self::SdpOffer(ref session_id, ref user_id, ref offer) => f
+                .debug_tuple("SdpOffer")
+                .field(session_id)
+                .field(user_id)
+                .field(&RedactedSdp(offer))
+                .finish(),
+            Self::SdpAnswer(ref session_id, ref user_id, ref answer) => f
+                .debug_tuple("SdpAnswer")
+                .field(session_id)
+                .field(user_id)
+                .field(&RedactedSdp(answer))
+                .finish(),
+            Self::IceCandidate(ref session_id, ref user_id, ref candidate) => f
+                .debug_tuple("IceCandidate")
+                .field(session_id)
+                .field(user_id)
+                .field(candidate)
+                .finish(),
+            Self::Error(ref session_id, ref error) => f
+                .debug_tuple("Error")
+                .field(session_id)
+                .field(error)
+                .finish(),
+            Self::SessionListRequest => f.write_str("SessionListRequest"),
+            Self::SessionListResponse(ref sessions) => f
+                .debug_tuple("SessionListResponse")
+                .field(sessions)
+                .finish(),
+            Self::SessionJoinWithIntent(ref session_id, ref intent) => f
+                .debug_tuple("SessionJoinWithIntent")
+                .field(session_id)
+                .field(intent)
+                .finish(),
+            Self::SessionJoinError(ref session_id, ref error) => f
+                .debug_tuple("SessionJoinError")
+                .field(session_id)
+                .field(error)
+                .finish(),
+        }
+    }
 }
@@ -3,15 +3,25 @@ Signaling messages exchanged between used by `MiniServer`, `MiniClient` and sign
 to facilitate communication in client-server topology.
 */
 
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{Debug, Formatter};
+
 use serde::{Deserialize, Serialize};
 
 use crate::common::IceCandidate;
-use crate::{IsHost, SessionId, UserId};
+use crate::{
+    AllowMultiHost, IsHost, JoinError, JoinIntent, RedactedSdp, SessionId, SessionInfo, StableId,
+    UserId,
+};
 
 /// `Enum` consisting of two main categories are messages used to setup signaling session
 /// and messages used to setup `WebRTC` connection afterwards.
 /// Most of the include [`SessionId`] and [`UserId`] to uniquely identify each peer.
-#[derive(Debug, Serialize, Deserialize)]
+///
+/// Debug output redacts SDP and ICE candidates, see [`RedactedSdp`].
+#[derive(PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum SignalMessage {
     /// Either client or server connecting to signaling session
     SessionJoin(SessionId, IsHost),
@@ -30,4 +40,156 @@ pub enum SignalMessage {
 
     /// Generic error containing detailed information about the cause
     Error(SessionId, UserId, String),
+
+    /// Report back to the users connected with the given user that it left the session,
+    /// i.e. to the host once a client disconnects and to every client once the host disconnects
+    SessionLeave(SessionId, UserId),
+
+    /// Client connecting to signaling session under its [`StableId`],
+    /// taking over the place of the user that previously joined with it
+    SessionJoinAs(SessionId, StableId),
+
+    /// Report to the host the [`StableId`] of the given client, sent right before `SessionReady` with it
+    ClientIdentity(SessionId, UserId, StableId),
+
+    /// Asks signaling server for sessions open on the endpoint the user is connected to
+    SessionListRequest,
+
+    /// Sessions open on the endpoint, sent back to the user that asked for them with `SessionListRequest`
+    SessionListResponse(Vec<SessionInfo>),
+
+    /// Sent by the host that turned the given client away as it already has as many clients as it accepts,
+    /// signaling server tells the client and answers further joins with `Error` until one of the clients leaves
+    SessionFullNotice(SessionId, UserId),
+
+    /// Report to the user joining a many-to-many session its own [`UserId`]
+    /// and ids of all members of the session including itself, in ascending order,
+    /// sent right before any `SessionReady`
+    SessionMembers(SessionId, UserId, Vec<UserId>),
+
+    /// Sent by users to signaling server before any other message, announcing the version of the protocol
+    /// they speak, see [`PROTOCOL_MAJOR_VERSION`](crate::PROTOCOL_MAJOR_VERSION).
+    /// Signaling server answers with its own version if the major versions match
+    ProtocolVersion { major: u8, minor: u8 },
+
+    /// Sent back by signaling server speaking an incompatible major version of the protocol,
+    /// right before it closes the connection
+    ProtocolVersionMismatch { server_major: u8, server_minor: u8 },
+
+    /// User connecting to signaling session, telling whether it expects the session to exist already.
+    /// Signaling server answers with `SessionJoinError` if the session doesn't match `JoinIntent`,
+    /// otherwise it's the same as `SessionJoin`
+    SessionJoinWithIntent(SessionId, IsHost, JoinIntent),
+
+    /// Sent back to the user whose `SessionJoinWithIntent` was refused, it's not in the session
+    SessionJoinError(SessionId, JoinError),
+
+    /// Host connecting to signaling session, same as `SessionJoinWithIntent` with `IsHost` set.
+    /// If it's the first host of the session and `AllowMultiHost` is set,
+    /// further hosts joining the session are added to it instead of being refused,
+    /// and each client is connected with every host
+    SessionJoinHost(SessionId, AllowMultiHost, JoinIntent),
+}
+
+impl Debug for SignalMessage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match *self {
+            Self::SessionJoin(ref session_id, ref is_host) => f
+                .debug_tuple("SessionJoin")
+                .field(session_id)
+                .field(is_host)
+                .finish(),
+            Self::SessionReady(ref session_id, ref user_id) => f
+                .debug_tuple("SessionReady")
+                .field(session_id)
+                .field(user_id)
+                .finish(),
+            Self::SdpOffer(ref session_id, ref user_id, ref offer) => f
+                .debug_tuple("SdpOffer")
+                .field(session_id)
+                .field(user_id)
+                .field(&RedactedSdp(offer))
+                .finish(),
+            Self::SdpAnswer(ref session_id, ref user_id, ref answer) => f
+                .debug_tuple("SdpAnswer")
+                .field(session_id)
+                .field(user_id)
+                .field(&RedactedSdp(answer))
+                .finish(),
+            Self::IceCandidate(ref session_id, ref user_id, ref candidate) => f
+                .debug_tuple("IceCandidate")
+                .field(session_id)
+                .field(user_id)
+                .field(candidate)
+                .finish(),
+            Self::Error(ref session_id, ref user_id, ref error) => f
+                .debug_tuple("Error")
+                .field(session_id)
+                .field(user_id)
+                .field(error)
+                .finish(),
+            Self::SessionLeave(ref session_id, ref user_id) => f
+                .debug_tuple("SessionLeave")
+                .field(session_id)
+                .field(user_id)
+                .finish(),
+            Self::SessionJoinAs(ref session_id, ref stable_id) => f
+                .debug_tuple("SessionJoinAs")
+                .field(session_id)
+                .field(stable_id)
+                .finish(),
+            Self::ClientIdentity(ref session_id, ref user_id, ref stable_id) => f
+                .debug_tuple("ClientIdentity")
+                .field(session_id)
+                .field(user_id)
+                .field(stable_id)
+                .finish(),
+            Self::SessionListRequest => f.write_str("SessionListRequest"),
+            Self::SessionListResponse(ref sessions) => f
+                .debug_tuple("SessionListResponse")
+                .field(sessions)
+                .finish(),
+            Self::SessionFullNotice(ref session_id, ref user_id) => f
+                .debug_tuple("SessionFullNotice")
+                .field(session_id)
+                .field(user_id)
+                .finish(),
+            Self::SessionMembers(ref session_id, ref user_id, ref members) => f
+                .debug_tuple("SessionMembers")
+                .field(session_id)
+                .field(user_id)
+                .field(members)
+                .finish(),
+            Self::ProtocolVersion { major, minor } => f
+                .debug_struct("ProtocolVersion")
+                .field("major", &major)
+                .field("minor", &minor)
+                .finish(),
+            Self::ProtocolVersionMismatch {
+                server_major,
+                server_minor,
+            } => f
+                .debug_struct("ProtocolVersionMismatch")
+                .field("server_major", &server_major)
+                .field("server_minor", &server_minor)
+                .finish(),
+            Self::SessionJoinWithIntent(ref session_id, ref is_host, ref intent) => f
+                .debug_tuple("SessionJoinWithIntent")
+                .field(session_id)
+                .field(is_host)
+                .field(intent)
+                .finish(),
+            Self::SessionJoinError(ref session_id, ref error) => f
+                .debug_tuple("SessionJoinError")
+                .field(session_id)
+                .field(error)
+                .finish(),
+            Self::SessionJoinHost(ref session_id, ref allow_multi_host, ref intent) => f
+                .debug_tuple("SessionJoinHost")
+                .field(session_id)
+                .field(allow_multi_host)
+                .field(intent)
+                .finish(),
+        }
+    }
 }
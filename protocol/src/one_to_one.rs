@@ -3,15 +3,22 @@ Signaling messages exchanged between used by `MiniServer`, `MiniClient` and sign
 to facilitate communication in client-server topology.
  */
 
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{Debug, Formatter};
+
 use serde::{Deserialize, Serialize};
 
 use crate::common::IceCandidate;
-use crate::{IsHost, SessionId};
+use crate::{IsHost, JoinError, JoinIntent, RedactedSdp, SessionId, SessionInfo};
 
 /// `Enum` consisting of two main categories are messages used to setup signaling session
 /// and messages used to setup `WebRTC` connection afterwards.
 /// All of the messages include [`SessionId`] which is enough to identify the other peer in the connection.
-#[derive(Debug, Serialize, Deserialize)]
+///
+/// Debug output redacts SDP and ICE candidates, see [`RedactedSdp`].
+#[derive(PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum SignalMessage {
     /// Either client or server connecting to signaling session
     SessionJoin(SessionId),
@@ -27,4 +34,95 @@ pub enum SignalMessage {
 
     /// Generic error containing detailed information about the cause
     Error(SessionId, String),
+
+    /// Sent by signaling server that's about to go offline,
+    /// peers should reconnect to signaling server under given URL and join their session again
+    Redirect(String),
+
+    /// Asks signaling server for sessions open on the endpoint the user is connected to
+    SessionListRequest,
+
+    /// Sessions open on the endpoint, sent back to the user that asked for them with `SessionListRequest`
+    SessionListResponse(Vec<SessionInfo>),
+
+    /// Sent by users to signaling server before any other message, announcing the version of the protocol
+    /// they speak, see [`PROTOCOL_MAJOR_VERSION`](crate::PROTOCOL_MAJOR_VERSION).
+    /// Signaling server answers with its own version if the major versions match
+    ProtocolVersion { major: u8, minor: u8 },
+
+    /// Sent back by signaling server speaking an incompatible major version of the protocol,
+    /// right before it closes the connection
+    ProtocolVersionMismatch { server_major: u8, server_minor: u8 },
+
+    /// User connecting to signaling session, telling whether it expects the session to exist already.
+    /// Signaling server answers with `SessionJoinError` if the session doesn't match `JoinIntent`,
+    /// otherwise it's the same as `SessionJoin`
+    SessionJoinWithIntent(SessionId, JoinIntent),
+
+    /// Sent back to the user whose `SessionJoinWithIntent` was refused, it's not in the session
+    SessionJoinError(SessionId, JoinError),
+}
+
+impl Debug for SignalMessage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match *self {
+            Self::SessionJoin(ref session_id) => {
+                f.debug_tuple("SessionJoin").field(session_id).finish()
+            }
+            Self::SessionReady(ref session_id, ref is_host) => f
+                .debug_tuple("SessionReady")
+                .field(session_id)
+                .field(is_host)
+                .finish(),
+            Self::SdpOffer(ref session_id, ref offer) => f
+                .debug_tuple("SdpOffer")
+                .field(session_id)
+                .field(&RedactedSdp(offer))
+                .finish(),
+            Self::SdpAnswer(ref session_id, ref answer) => f
+                .debug_tuple("SdpAnswer")
+                .field(session_id)
+                .field(&RedactedSdp(answer))
+                .finish(),
+            Self::IceCandidate(ref session_id, ref candidate) => f
+                .debug_tuple("IceCandidate")
+                .field(session_id)
+                .field(candidate)
+                .finish(),
+            Self::Error(ref session_id, ref error) => f
+                .debug_tuple("Error")
+                .field(session_id)
+                .field(error)
+                .finish(),
+            Self::Redirect(ref url) => f.debug_tuple("Redirect").field(url).finish(),
+            Self::SessionListRequest => f.write_str("SessionListRequest"),
+            Self::SessionListResponse(ref sessions) => f
+                .debug_tuple("SessionListResponse")
+                .field(sessions)
+                .finish(),
+            Self::ProtocolVersion { major, minor } => f
+                .debug_struct("ProtocolVersion")
+                .field("major", &major)
+                .field("minor", &minor)
+                .finish(),
+            Self::ProtocolVersionMismatch {
+                server_major,
+                server_minor,
+            } => f
+                .debug_struct("ProtocolVersionMismatch")
+                .field("server_major", &server_major)
+                .field("server_minor", &server_minor)
+                .finish(),
+            Self::SessionJoinWithIntent(ref session_id, ref intent) => f
+                .debug_tuple("SessionJoinWithIntent")
+                .field(session_id)
+                .field(intent)
+                .finish(),
+            Self::SessionJoinError(ref session_id, ref error) => f
+                .debug_tuple("SessionJoinError")
+                .field(session_id)
+                .field(error)
+                .finish(),
+        }
+    }
 }
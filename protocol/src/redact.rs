@@ -0,0 +1,117 @@
+/*!
+Redaction of SDP and ICE candidates before they are logged, as they carry ICE credentials
+and local IP addresses of users.
+
+Debug output of [`IceCandidate`](crate::IceCandidate) and of signal messages of every topology
+is redacted this way, unless `log-full-sdp` feature is enabled, e.g. for local debugging.
+ */
+
+use core::fmt::{self, Debug, Display, Formatter, Write};
+use core::net::IpAddr;
+
+/// Placeholder for IP addresses
+const REDACTED_IP: &str = "[ip]";
+/// Placeholder for ICE username fragments within candidates
+const REDACTED: &str = "[redacted]";
+
+/// SDP or a single ICE candidate, formatted for logs with `a=ice-ufrag` and `a=ice-pwd` lines dropped
+/// and IP addresses masked. Formatted verbatim with `log-full-sdp` feature.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct RedactedSdp<'a>(pub &'a str);
+
+impl Display for RedactedSdp<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write_sdp(f, self.0, false)
+    }
+}
+
+impl Debug for RedactedSdp<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_char('"')?;
+        write_sdp(f, self.0, true)?;
+        f.write_char('"')
+    }
+}
+
+fn write_sdp(f: &mut Formatter<'_>, sdp: &str, escape: bool) -> fmt::Result {
+    if cfg!(feature = "log-full-sdp") {
+        return write_str(f, sdp, escape);
+    }
+    for line in sdp.split_inclusive('\n') {
+        let content = line.trim_end_matches(&['\r', '\n'][..]);
+        if content.starts_with("a=ice-ufrag:") || content.starts_with("a=ice-pwd:") {
+            continue;
+        }
+        let mut previous = "";
+        for (index, token) in content.split(' ').enumerate() {
+            if index > 0 {
+                f.write_char(' ')?;
+            }
+            if previous == "ufrag" {
+                f.write_str(REDACTED)?;
+            } else if token.parse::<IpAddr>().is_ok() {
+                f.write_str(REDACTED_IP)?;
+            } else {
+                write_str(f, token, escape)?;
+            }
+            previous = token;
+        }
+        write_str(f, line.strip_prefix(content).unwrap_or_default(), escape)?;
+    }
+    Ok(())
+}
+
+fn write_str(f: &mut Formatter<'_>, value: &str, escape: bool) -> fmt::Result {
+    if escape {
+        write!(f, "{}", value.escape_debug())
+    } else {
+        f.write_str(value)
+    }
+}
+
+#[cfg(all(test, not(feature = "log-full-sdp")))]
+mod test {
+    use super::*;
+
+    const OFFER: &str = "v=0\r\n\
+        o=- 4611731400430051336 2 IN IP4 127.0.0.1\r\n\
+        c=IN IP4 192.168.0.2\r\n\
+        a=ice-ufrag:EsAw\r\n\
+        a=ice-pwd:bP+XJMM09aR8AiX1jdukzR6Y\r\n\
+        a=candidate:1 1 udp 2122260223 fe80::1 54321 typ host ufrag EsAw\r\n\
+        a=fingerprint:sha-256 AB:CD:EF:01:23:45:67:89:AB:CD:EF:01:23:45:67:89\r\n";
+
+    #[test]
+    fn test_ice_credentials_and_ips_are_redacted() {
+        assert_eq!(
+            RedactedSdp(OFFER).to_string(),
+            "v=0\r\n\
+             o=- 4611731400430051336 2 IN IP4 [ip]\r\n\
+             c=IN IP4 [ip]\r\n\
+             a=candidate:1 1 udp 2122260223 [ip] 54321 typ host ufrag [redacted]\r\n\
+             a=fingerprint:sha-256 AB:CD:EF:01:23:45:67:89:AB:CD:EF:01:23:45:67:89\r\n"
+        );
+    }
+
+    #[test]
+    fn test_debug_output_is_escaped() {
+        assert_eq!(
+            format!(
+                "{:?}",
+                RedactedSdp("c=IN IP4 10.0.0.1\r\nm=\"application\"")
+            ),
+            r#""c=IN IP4 [ip]\r\nm=\"application\"""#
+        );
+    }
+
+    #[test]
+    fn test_candidate_is_redacted() {
+        let candidate = "candidate:842163049 1 udp 1677729535 203.0.113.5 3478 typ srflx \
+                         raddr 192.168.0.2 rport 54321 generation 0 ufrag EsAw";
+        assert_eq!(
+            RedactedSdp(candidate).to_string(),
+            "candidate:842163049 1 udp 1677729535 [ip] 3478 typ srflx \
+             raddr [ip] rport 54321 generation 0 ufrag [redacted]"
+        );
+    }
+}
@@ -0,0 +1,247 @@
+use std::fmt::Debug;
+
+use proptest::option;
+use proptest::prelude::*;
+use proptest::strategy::LazyJust;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use wasm_peers_protocol::{
+    many_to_many, one_to_many, one_to_one, IceCandidate, JoinError, JoinIntent, SessionId,
+    SessionInfo, StableId, TopologyType, UserId,
+};
+
+fn round_trip<M>(message: &M) -> Result<(), TestCaseError>
+where
+    M: Serialize + DeserializeOwned + PartialEq + Debug,
+{
+    let encoded = rmp_serde::to_vec(message)
+        .map_err(|err| TestCaseError::fail(format!("failed to encode: {err}")))?;
+    let decoded: M = rmp_serde::from_slice(&encoded)
+        .map_err(|err| TestCaseError::fail(format!("failed to decode: {err}")))?;
+    prop_assert_eq!(&decoded, message);
+    Ok(())
+}
+
+fn session_id() -> impl Strategy<Value = SessionId> {
+    any::<u128>().prop_map(SessionId::new)
+}
+
+fn user_id() -> impl Strategy<Value = UserId> {
+    any::<u64>().prop_map(UserId::new)
+}
+
+fn stable_id() -> impl Strategy<Value = StableId> {
+    any::<u128>().prop_map(StableId::new)
+}
+
+/// Any string, SDP and errors are passed on without being parsed
+fn text() -> impl Strategy<Value = String> {
+    any::<String>()
+}
+
+fn ice_candidate() -> impl Strategy<Value = IceCandidate> {
+    (text(), option::of(text()), option::of(any::<u16>())).prop_map(
+        |(candidate, sdp_mid, sdp_m_line_index)| IceCandidate {
+            candidate,
+            sdp_mid,
+            sdp_m_line_index,
+        },
+    )
+}
+
+fn session_list() -> impl Strategy<Value = Vec<SessionInfo>> {
+    let topology = prop_oneof![
+        Just(TopologyType::OneToOne),
+        Just(TopologyType::OneToMany),
+        Just(TopologyType::ManyToMany),
+    ];
+    let session_info =
+        (session_id(), any::<usize>(), topology).prop_map(|(session_id, peer_count, topology)| {
+            SessionInfo {
+                session_id,
+                peer_count,
+                topology,
+            }
+        });
+    proptest::collection::vec(session_info, 0..8)
+}
+
+fn join_intent() -> impl Strategy<Value = JoinIntent> {
+    prop_oneof![
+        Just(JoinIntent::Create),
+        Just(JoinIntent::Join),
+        Just(JoinIntent::CreateOrJoin),
+    ]
+}
+
+fn join_error() -> impl Strategy<Value = JoinError> {
+    prop_oneof![
+        Just(JoinError::NoSuchSession),
+        Just(JoinError::AlreadyExists)
+    ]
+}
+
+fn one_to_one_message() -> impl Strategy<Value = one_to_one::SignalMessage> {
+    use one_to_one::SignalMessage;
+
+    prop_oneof![
+        session_id().prop_map(SignalMessage::SessionJoin),
+        (session_id(), any::<bool>())
+            .prop_map(|(id, is_host)| SignalMessage::SessionReady(id, is_host)),
+        (session_id(), text()).prop_map(|(id, sdp)| SignalMessage::SdpOffer(id, sdp)),
+        (session_id(), text()).prop_map(|(id, sdp)| SignalMessage::SdpAnswer(id, sdp)),
+        (session_id(), ice_candidate())
+            .prop_map(|(id, candidate)| SignalMessage::IceCandidate(id, candidate)),
+        (session_id(), text()).prop_map(|(id, error)| SignalMessage::Error(id, error)),
+        text().prop_map(SignalMessage::Redirect),
+        LazyJust::new(|| SignalMessage::SessionListRequest),
+        session_list().prop_map(SignalMessage::SessionListResponse),
+        (any::<u8>(), any::<u8>())
+            .prop_map(|(major, minor)| SignalMessage::ProtocolVersion { major, minor }),
+        (any::<u8>(), any::<u8>()).prop_map(|(server_major, server_minor)| {
+            SignalMessage::ProtocolVersionMismatch {
+                server_major,
+                server_minor,
+            }
+        }),
+        (session_id(), join_intent())
+            .prop_map(|(id, intent)| SignalMessage::SessionJoinWithIntent(id, intent)),
+        (session_id(), join_error())
+            .prop_map(|(id, error)| SignalMessage::SessionJoinError(id, error)),
+    ]
+}
+
+fn one_to_many_message() -> impl Strategy<Value = one_to_many::SignalMessage> {
+    use one_to_many::SignalMessage;
+
+    prop_oneof![
+        (session_id(), any::<bool>())
+            .prop_map(|(id, is_host)| SignalMessage::SessionJoin(id, is_host)),
+        (session_id(), user_id()).prop_map(|(id, user)| SignalMessage::SessionReady(id, user)),
+        (session_id(), user_id(), text())
+            .prop_map(|(id, user, sdp)| SignalMessage::SdpOffer(id, user, sdp)),
+        (session_id(), user_id(), text())
+            .prop_map(|(id, user, sdp)| SignalMessage::SdpAnswer(id, user, sdp)),
+        (session_id(), user_id(), ice_candidate())
+            .prop_map(|(id, user, candidate)| SignalMessage::IceCandidate(id, user, candidate)),
+        (session_id(), user_id(), text())
+            .prop_map(|(id, user, error)| SignalMessage::Error(id, user, error)),
+        (session_id(), user_id()).prop_map(|(id, user)| SignalMessage::SessionLeave(id, user)),
+        (session_id(), stable_id())
+            .prop_map(|(id, stable_id)| SignalMessage::SessionJoinAs(id, stable_id)),
+        (session_id(), user_id(), stable_id()).prop_map(|(id, user, stable_id)| {
+            SignalMessage::ClientIdentity(id, user, stable_id)
+        }),
+        LazyJust::new(|| SignalMessage::SessionListRequest),
+        session_list().prop_map(SignalMessage::SessionListResponse),
+        (session_id(), user_id()).prop_map(|(id, user)| SignalMessage::SessionFullNotice(id, user)),
+        (
+            session_id(),
+            user_id(),
+            prop::collection::vec(user_id(), 0..8)
+        )
+            .prop_map(|(id, user, members)| SignalMessage::SessionMembers(id, user, members)),
+        (any::<u8>(), any::<u8>())
+            .prop_map(|(major, minor)| SignalMessage::ProtocolVersion { major, minor }),
+        (any::<u8>(), any::<u8>()).prop_map(|(server_major, server_minor)| {
+            SignalMessage::ProtocolVersionMismatch {
+                server_major,
+                server_minor,
+            }
+        }),
+        (session_id(), any::<bool>(), join_intent()).prop_map(|(id, is_host, intent)| {
+            SignalMessage::SessionJoinWithIntent(id, is_host, intent)
+        }),
+        (session_id(), join_error())
+            .prop_map(|(id, error)| SignalMessage::SessionJoinError(id, error)),
+        (session_id(), any::<bool>(), join_intent()).prop_map(|(id, allow_multi_host, intent)| {
+            SignalMessage::SessionJoinHost(id, allow_multi_host, intent)
+        }),
+    ]
+}
+
+fn many_to_many_message() -> impl Strategy<Value = many_to_many::SignalMessage> {
+    use many_to_many::SignalMessage;
+
+    prop_oneof![
+        session_id().prop_map(SignalMessage::SessionJoin),
+        (session_id(), user_id()).prop_map(|(id, user)| SignalMessage::SessionReady(id, user)),
+        (session_id(), user_id(), text())
+            .prop_map(|(id, user, sdp)| SignalMessage::SdpOffer(id, user, sdp)),
+        (session_id(), user_id(), text())
+            .prop_map(|(id, user, sdp)| SignalMessage::SdpAnswer(id, user, sdp)),
+        (session_id(), user_id(), ice_candidate())
+            .prop_map(|(id, user, candidate)| SignalMessage::IceCandidate(id, user, candidate)),
+        (session_id(), text()).prop_map(|(id, error)| SignalMessage::Error(id, error)),
+        LazyJust::new(|| SignalMessage::SessionListRequest),
+        session_list().prop_map(SignalMessage::SessionListResponse),
+        (session_id(), join_intent())
+            .prop_map(|(id, intent)| SignalMessage::SessionJoinWithIntent(id, intent)),
+        (session_id(), join_error())
+            .prop_map(|(id, error)| SignalMessage::SessionJoinError(id, error)),
+    ]
+}
+
+proptest! {
+    #[test]
+    fn test_one_to_one_messages_round_trip(message in one_to_one_message()) {
+        round_trip(&message)?;
+    }
+
+    #[test]
+    fn test_one_to_many_messages_round_trip(message in one_to_many_message()) {
+        round_trip(&message)?;
+    }
+
+    #[test]
+    fn test_many_to_many_messages_round_trip(message in many_to_many_message()) {
+        round_trip(&message)?;
+    }
+
+    #[test]
+    fn test_session_id_display_round_trips(session_id in session_id()) {
+        prop_assert_eq!(session_id.to_string().parse::<SessionId>(), Ok(session_id));
+        prop_assert_eq!(session_id.inner().to_string().parse::<SessionId>(), Ok(session_id));
+    }
+
+    #[test]
+    fn test_user_id_order_matches_inner_value(first in any::<u64>(), second in any::<u64>()) {
+        prop_assert_eq!(UserId::new(first).cmp(&UserId::new(second)), first.cmp(&second));
+    }
+}
+
+/// Missing fields are encoded as msgpack `nil` rather than skipped, so candidates keep their shape.
+#[test]
+fn test_ice_candidate_without_optional_fields_round_trips() {
+    const NIL: u8 = 0xc0;
+
+    for (sdp_mid, sdp_m_line_index) in [
+        (None, None),
+        (Some("0".to_owned()), None),
+        (None, Some(0)),
+        (Some("0".to_owned()), Some(0)),
+    ] {
+        let candidate = IceCandidate {
+            candidate: "candidate:1 1 udp 2122260223 192.168.0.2 54321 typ host".to_owned(),
+            sdp_mid,
+            sdp_m_line_index,
+        };
+        let message =
+            one_to_one::SignalMessage::IceCandidate(SessionId::new(u128::MAX), candidate.clone());
+        let encoded = rmp_serde::to_vec(&message).expect("failed to encode");
+        let decoded: one_to_one::SignalMessage =
+            rmp_serde::from_slice(&encoded).expect("failed to decode");
+        assert_eq!(decoded, message);
+
+        let encoded = rmp_serde::to_vec(&candidate).expect("failed to encode");
+        let nil_count = encoded
+            .iter()
+            .rev()
+            .take(2)
+            .filter(|&&byte| byte == NIL)
+            .count();
+        let none_count = usize::from(candidate.sdp_mid.is_none())
+            + usize::from(candidate.sdp_m_line_index.is_none());
+        assert_eq!(nil_count, none_count, "unexpected encoding {encoded:02x?}");
+    }
+}
@@ -0,0 +1,293 @@
+//! Signal messages encoded by every released version of the protocol, stored in `tests/fixtures/v<version>`
+//! as lines of message name and hex encoded bytes.
+//!
+//! Fixtures of older versions must stay decodable, as clients and signaling server are deployed independently.
+//! Fixtures of the current [`PROTOCOL_VERSION`] must match today's encoding exactly,
+//! so that any change of the wire format is a conscious decision, followed by bumping the version
+//! and adding fixtures for it.
+
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::fs;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use wasm_peers_protocol::{
+    many_to_many, one_to_many, one_to_one, IceCandidate, JoinError, JoinIntent, SessionId,
+    SessionInfo, StableId, TopologyType, UserId, PROTOCOL_MAJOR_VERSION, PROTOCOL_VERSION,
+};
+
+const SESSION_ID: SessionId = SessionId::new(0x0123_4567_89ab_cdef_0123_4567_89ab_cdef);
+const USER_ID: UserId = UserId::new(42);
+const STABLE_ID: StableId = StableId::new(0xfedc_ba98_7654_3210_fedc_ba98_7654_3210);
+const SDP: &str = "v=0\r\no=- 4611731400430051336 2 IN IP4 127.0.0.1\r\n";
+
+fn ice_candidate() -> IceCandidate {
+    IceCandidate {
+        candidate: "candidate:842163049 1 udp 1677729535 192.0.2.1 3478 typ srflx".to_owned(),
+        sdp_mid: Some("0".to_owned()),
+        sdp_m_line_index: Some(0),
+    }
+}
+
+fn session_list(topology: TopologyType) -> Vec<SessionInfo> {
+    vec![SessionInfo {
+        session_id: SESSION_ID,
+        peer_count: 2,
+        topology,
+    }]
+}
+
+fn one_to_one_samples() -> BTreeMap<&'static str, one_to_one::SignalMessage> {
+    use one_to_one::SignalMessage;
+
+    BTreeMap::from([
+        ("SessionJoin", SignalMessage::SessionJoin(SESSION_ID)),
+        (
+            "SessionReady",
+            SignalMessage::SessionReady(SESSION_ID, true),
+        ),
+        (
+            "SdpOffer",
+            SignalMessage::SdpOffer(SESSION_ID, SDP.to_owned()),
+        ),
+        (
+            "SdpAnswer",
+            SignalMessage::SdpAnswer(SESSION_ID, SDP.to_owned()),
+        ),
+        (
+            "IceCandidate",
+            SignalMessage::IceCandidate(SESSION_ID, ice_candidate()),
+        ),
+        (
+            "Error",
+            SignalMessage::Error(SESSION_ID, "session is full".to_owned()),
+        ),
+        (
+            "Redirect",
+            SignalMessage::Redirect("wss://new.server.com/one-to-one".to_owned()),
+        ),
+        ("SessionListRequest", SignalMessage::SessionListRequest),
+        (
+            "SessionListResponse",
+            SignalMessage::SessionListResponse(session_list(TopologyType::OneToOne)),
+        ),
+        (
+            "ProtocolVersion",
+            SignalMessage::ProtocolVersion { major: 1, minor: 2 },
+        ),
+        (
+            "ProtocolVersionMismatch",
+            SignalMessage::ProtocolVersionMismatch {
+                server_major: 2,
+                server_minor: 0,
+            },
+        ),
+        (
+            "SessionJoinWithIntent",
+            SignalMessage::SessionJoinWithIntent(SESSION_ID, JoinIntent::Join),
+        ),
+        (
+            "SessionJoinError",
+            SignalMessage::SessionJoinError(SESSION_ID, JoinError::NoSuchSession),
+        ),
+    ])
+}
+
+fn one_to_many_samples() -> BTreeMap<&'static str, one_to_many::SignalMessage> {
+    use one_to_many::SignalMessage;
+
+    BTreeMap::from([
+        ("SessionJoin", SignalMessage::SessionJoin(SESSION_ID, false)),
+        (
+            "SessionReady",
+            SignalMessage::SessionReady(SESSION_ID, USER_ID),
+        ),
+        (
+            "SdpOffer",
+            SignalMessage::SdpOffer(SESSION_ID, USER_ID, SDP.to_owned()),
+        ),
+        (
+            "SdpAnswer",
+            SignalMessage::SdpAnswer(SESSION_ID, USER_ID, SDP.to_owned()),
+        ),
+        (
+            "IceCandidate",
+            SignalMessage::IceCandidate(SESSION_ID, USER_ID, ice_candidate()),
+        ),
+        (
+            "Error",
+            SignalMessage::Error(SESSION_ID, USER_ID, "no such peer".to_owned()),
+        ),
+        (
+            "SessionLeave",
+            SignalMessage::SessionLeave(SESSION_ID, USER_ID),
+        ),
+        (
+            "SessionJoinAs",
+            SignalMessage::SessionJoinAs(SESSION_ID, STABLE_ID),
+        ),
+        (
+            "ClientIdentity",
+            SignalMessage::ClientIdentity(SESSION_ID, USER_ID, STABLE_ID),
+        ),
+        ("SessionListRequest", SignalMessage::SessionListRequest),
+        (
+            "SessionListResponse",
+            SignalMessage::SessionListResponse(session_list(TopologyType::OneToMany)),
+        ),
+        (
+            "SessionFullNotice",
+            SignalMessage::SessionFullNotice(SESSION_ID, USER_ID),
+        ),
+        (
+            "SessionMembers",
+            SignalMessage::SessionMembers(SESSION_ID, USER_ID, vec![UserId::new(7), USER_ID]),
+        ),
+        (
+            "ProtocolVersion",
+            SignalMessage::ProtocolVersion { major: 1, minor: 2 },
+        ),
+        (
+            "ProtocolVersionMismatch",
+            SignalMessage::ProtocolVersionMismatch {
+                server_major: 2,
+                server_minor: 0,
+            },
+        ),
+        (
+            "SessionJoinWithIntent",
+            SignalMessage::SessionJoinWithIntent(SESSION_ID, true, JoinIntent::Create),
+        ),
+        (
+            "SessionJoinError",
+            SignalMessage::SessionJoinError(SESSION_ID, JoinError::AlreadyExists),
+        ),
+        (
+            "SessionJoinHost",
+            SignalMessage::SessionJoinHost(SESSION_ID, true, JoinIntent::CreateOrJoin),
+        ),
+    ])
+}
+
+fn many_to_many_samples() -> BTreeMap<&'static str, many_to_many::SignalMessage> {
+    use many_to_many::SignalMessage;
+
+    BTreeMap::from([
+        ("SessionJoin", SignalMessage::SessionJoin(SESSION_ID)),
+        (
+            "SessionReady",
+            SignalMessage::SessionReady(SESSION_ID, USER_ID),
+        ),
+        (
+            "SdpOffer",
+            SignalMessage::SdpOffer(SESSION_ID, USER_ID, SDP.to_owned()),
+        ),
+        (
+            "SdpAnswer",
+            SignalMessage::SdpAnswer(SESSION_ID, USER_ID, SDP.to_owned()),
+        ),
+        (
+            "IceCandidate",
+            SignalMessage::IceCandidate(SESSION_ID, USER_ID, ice_candidate()),
+        ),
+        (
+            "Error",
+            SignalMessage::Error(SESSION_ID, "no such session".to_owned()),
+        ),
+        ("SessionListRequest", SignalMessage::SessionListRequest),
+        (
+            "SessionListResponse",
+            SignalMessage::SessionListResponse(session_list(TopologyType::ManyToMany)),
+        ),
+        (
+            "SessionJoinWithIntent",
+            SignalMessage::SessionJoinWithIntent(SESSION_ID, JoinIntent::CreateOrJoin),
+        ),
+        (
+            "SessionJoinError",
+            SignalMessage::SessionJoinError(SESSION_ID, JoinError::NoSuchSession),
+        ),
+    ])
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn decode_hex(hex: &str) -> Vec<u8> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).expect("invalid hex in fixture"))
+        .collect()
+}
+
+fn read_fixtures(version: u32, module: &str) -> BTreeMap<String, Vec<u8>> {
+    let path = format!(
+        "{}/tests/fixtures/v{version}/{module}.txt",
+        env!("CARGO_MANIFEST_DIR")
+    );
+    fs::read_to_string(&path)
+        .unwrap_or_else(|err| panic!("failed to read {path}: {err}"))
+        .lines()
+        .map(|line| {
+            let (name, hex) = line
+                .split_once(' ')
+                .unwrap_or_else(|| panic!("invalid line in {path}: {line:?}"));
+            (name.to_owned(), decode_hex(hex))
+        })
+        .collect()
+}
+
+fn check_wire_format<M>(module: &str, samples: &BTreeMap<&'static str, M>)
+where
+    M: Serialize + DeserializeOwned + PartialEq + Debug,
+{
+    for version in 1..=PROTOCOL_VERSION {
+        for (name, bytes) in read_fixtures(version, module) {
+            let sample = samples
+                .get(name.as_str())
+                .unwrap_or_else(|| panic!("{module}::{name} from protocol v{version} was removed"));
+            let decoded: M = rmp_serde::from_slice(&bytes).unwrap_or_else(|err| {
+                panic!(
+                    "{module}::{name} encoded by protocol v{version} is no longer decodable: {err}"
+                )
+            });
+            assert_eq!(
+                &decoded, sample,
+                "{module}::{name} encoded by protocol v{version} decodes differently"
+            );
+        }
+    }
+
+    let fixtures = read_fixtures(PROTOCOL_VERSION, module);
+    for (name, sample) in samples {
+        let encoded = rmp_serde::to_vec(sample).expect("failed to encode sample");
+        assert_eq!(
+            fixtures.get(*name).map(|bytes| encode_hex(bytes)),
+            Some(encode_hex(&encoded)),
+            "encoding of {module}::{name} differs from protocol v{PROTOCOL_VERSION}, \
+             if that's intended bump PROTOCOL_VERSION and add fixtures for it"
+        );
+    }
+}
+
+#[test]
+fn test_one_to_one_wire_format_is_stable() {
+    check_wire_format("one_to_one", &one_to_one_samples());
+}
+
+#[test]
+fn test_one_to_many_wire_format_is_stable() {
+    check_wire_format("one_to_many", &one_to_many_samples());
+}
+
+#[test]
+fn test_many_to_many_wire_format_is_stable() {
+    check_wire_format("many_to_many", &many_to_many_samples());
+}
+
+#[test]
+fn test_major_version_follows_wire_format_version() {
+    assert_eq!(u32::from(PROTOCOL_MAJOR_VERSION), PROTOCOL_VERSION);
+}
@@ -0,0 +1,13 @@
+//! Feeds arbitrary bytes to the parsing of signal messages received by the signaling server,
+//! which must reject malformed ones with an error instead of panicking.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use wasm_peers_protocol::{many_to_many, one_to_many, one_to_one};
+
+fuzz_target!(|data: &[u8]| {
+    let _ = rmp_serde::from_slice::<one_to_one::SignalMessage>(data);
+    let _ = rmp_serde::from_slice::<one_to_many::SignalMessage>(data);
+    let _ = rmp_serde::from_slice::<many_to_many::SignalMessage>(data);
+});
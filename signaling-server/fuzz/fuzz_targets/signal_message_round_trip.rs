@@ -0,0 +1,20 @@
+//! Checks that every signal message decodes back to itself once encoded.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use wasm_peers_protocol::{many_to_many, one_to_many, one_to_one};
+
+fuzz_target!(|messages: (
+    one_to_one::SignalMessage,
+    one_to_many::SignalMessage,
+    many_to_many::SignalMessage,
+)| {
+    let (one_to_one, one_to_many, many_to_many) = messages;
+    let encoded = rmp_serde::to_vec(&one_to_one).expect("failed to encode message");
+    assert_eq!(rmp_serde::from_slice(&encoded).ok(), Some(one_to_one));
+    let encoded = rmp_serde::to_vec(&one_to_many).expect("failed to encode message");
+    assert_eq!(rmp_serde::from_slice(&encoded).ok(), Some(one_to_many));
+    let encoded = rmp_serde::to_vec(&many_to_many).expect("failed to encode message");
+    assert_eq!(rmp_serde::from_slice(&encoded).ok(), Some(many_to_many));
+});
@@ -0,0 +1,164 @@
+//! Endpoints for operators of the server, served under `/admin` by [`create_with_config`](crate::router::create_with_config).
+//!
+//! Every request has to carry `Authorization: Bearer <admin_token>` header with the token
+//! of [`ServerState::with_admin_token`] (`ADMIN_TOKEN` environment variable of the binary),
+//! otherwise it's rejected with `401 Unauthorized`. All requests are rejected if the token isn't set.
+
+use axum::extract::{Path, Query, State};
+use axum::http::{Request, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{delete, get, post};
+use axum::{Json, Router};
+use log::{error, info};
+use serde::Deserialize;
+use wasm_peers_protocol::{SessionId, UserId};
+
+use crate::router::{ServerState, REDIRECT_GRACE_PERIOD};
+use crate::session_audit::SessionEvent;
+use crate::{many_to_many, one_to_many, one_to_one};
+
+/// Error sent to users of sessions closed with `DELETE /admin/sessions/{session_id}`.
+pub const SESSION_CLOSED_REASON: &str = "session was closed by server administrator";
+
+/// Error sent to users kicked with `POST /admin/sessions/{session_id}/kick/{user_id}`,
+/// also the reason of the close frame of their connection.
+pub const KICKED_REASON: &str = "kicked from session by server administrator";
+
+/// Admin routes, each of them prefixed with `prefix`, behind the authentication middleware.
+pub(crate) fn router(prefix: &str, server_state: ServerState) -> Router<ServerState> {
+    Router::new()
+        .route(&format!("{prefix}/admin/redirect"), get(redirect_handler))
+        .route(
+            &format!("{prefix}/admin/sessions/:session_id"),
+            delete(close_session_handler),
+        )
+        .route(
+            &format!("{prefix}/admin/sessions/:session_id/events"),
+            get(session_events_handler),
+        )
+        .route(
+            &format!("{prefix}/admin/sessions/:session_id/kick/:user_id"),
+            post(kick_handler),
+        )
+        .route_layer(middleware::from_fn_with_state(
+            server_state,
+            admin_middleware,
+        ))
+}
+
+/// Turns away requests that don't carry admin token in `Authorization` header.
+async fn admin_middleware<B>(
+    State(state): State<ServerState>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    if !state.is_admin(request.headers()) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    next.run(request).await
+}
+
+#[derive(Deserialize)]
+struct RedirectQuery {
+    to: String,
+}
+
+/// Tells all one-to-one users to reconnect to another signaling server
+/// and shuts the server down after [`REDIRECT_GRACE_PERIOD`].
+async fn redirect_handler(
+    State(state): State<ServerState>,
+    Query(query): Query<RedirectQuery>,
+) -> StatusCode {
+    if let Err(err) = one_to_one::redirect_all(&state.one_to_one, &query.to).await {
+        error!("failed to redirect users: {err}");
+        return StatusCode::INTERNAL_SERVER_ERROR;
+    }
+    info!(
+        "users redirected to {}, shutting down in {:?}",
+        query.to, REDIRECT_GRACE_PERIOD
+    );
+    tokio::spawn(async move {
+        tokio::time::sleep(REDIRECT_GRACE_PERIOD).await;
+        state.request_shutdown();
+    });
+    StatusCode::ACCEPTED
+}
+
+/// Closes session with the given id in every topology, responds with `404 Not Found` if there is none.
+async fn close_session_handler(
+    State(state): State<ServerState>,
+    Path(session_id): Path<String>,
+) -> StatusCode {
+    let Ok(session_id) = session_id.parse::<SessionId>() else {
+        return StatusCode::BAD_REQUEST;
+    };
+    let closed = async {
+        let one_to_one =
+            one_to_one::close_session(&state.one_to_one, session_id, SESSION_CLOSED_REASON).await?;
+        let one_to_many =
+            one_to_many::close_session(&state.one_to_many, session_id, SESSION_CLOSED_REASON)
+                .await?;
+        let many_to_many =
+            many_to_many::close_session(&state.many_to_many, session_id, SESSION_CLOSED_REASON)
+                .await?;
+        crate::Result::Ok(one_to_one || one_to_many || many_to_many)
+    };
+    match closed.await {
+        Ok(true) => {
+            info!("session {session_id:?} closed by admin");
+            StatusCode::NO_CONTENT
+        }
+        Ok(false) => StatusCode::NOT_FOUND,
+        Err(err) => {
+            error!("failed to close session {session_id:?}: {err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+/// Responds with events of the session kept in the [session audit log](crate::session_audit),
+/// oldest first, empty if there are none.
+async fn session_events_handler(
+    State(state): State<ServerState>,
+    Path(session_id): Path<String>,
+) -> Result<Json<Vec<SessionEvent>>, StatusCode> {
+    let session_id = session_id
+        .parse::<SessionId>()
+        .map_err(|_err| StatusCode::BAD_REQUEST)?;
+    let events = state.session_audit_log().read().await.events(session_id);
+    Ok(Json(events))
+}
+
+/// Disconnects user from signaling server, responds with `404 Not Found`
+/// if it isn't in session with the given id in any topology.
+async fn kick_handler(
+    State(state): State<ServerState>,
+    Path((session_id, user_id)): Path<(String, u64)>,
+) -> StatusCode {
+    let Ok(session_id) = session_id.parse::<SessionId>() else {
+        return StatusCode::BAD_REQUEST;
+    };
+    let user_id = UserId::new(user_id);
+    let kicked = async {
+        let one_to_one =
+            one_to_one::kick_user(&state.one_to_one, session_id, user_id, KICKED_REASON).await?;
+        let one_to_many =
+            one_to_many::kick_user(&state.one_to_many, session_id, user_id, KICKED_REASON).await?;
+        let many_to_many =
+            many_to_many::kick_user(&state.many_to_many, session_id, user_id, KICKED_REASON)
+                .await?;
+        crate::Result::Ok(one_to_one || one_to_many || many_to_many)
+    };
+    match kicked.await {
+        Ok(true) => {
+            info!("user {user_id:?} kicked from session {session_id:?} by admin");
+            StatusCode::NO_CONTENT
+        }
+        Ok(false) => StatusCode::NOT_FOUND,
+        Err(err) => {
+            error!("failed to kick user {user_id:?}: {err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
@@ -0,0 +1,183 @@
+//! `permessage-deflate` compression of websockets, negotiated with clients offering it, e.g. all browsers.
+//! Websockets of axum don't support extensions, so such clients are upgraded with soketto instead,
+//! and handled by [`SignalingHub`](crate::hub::SignalingHub) like any other websocket.
+//! Soketto only closes connections with `Normal Closure`, so code and reason of close frames
+//! sent to these clients are dropped.
+
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use axum::body::Body;
+use axum::extract::ws::Message;
+use axum::http::{HeaderMap, Request};
+use axum::response::{IntoResponse, Response};
+use futures_util::io::{AsyncRead, AsyncWrite};
+use futures_util::{ready, sink, stream, Sink, Stream};
+use hyper::upgrade::{OnUpgrade, Upgraded};
+use log::error;
+use soketto::connection::{self, Mode, Receiver, Sender};
+use soketto::extension::deflate::Deflate;
+use soketto::handshake::http::Server;
+use soketto::Data;
+use tokio::io::{AsyncWriteExt, ReadHalf};
+use tokio::sync::mpsc;
+use tokio_util::compat::{Compat, TokioAsyncReadCompatExt};
+use tokio_util::sync::PollSender;
+
+const SEC_WEBSOCKET_EXTENSIONS: &str = "Sec-WebSocket-Extensions";
+
+/// Frames queued for the task writing them out, before writes of soketto wait for it.
+const WRITE_QUEUE_FRAMES: usize = 32;
+
+/// `true` if the client offers `permessage-deflate` in its upgrade request.
+pub(crate) fn offers_deflate(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(SEC_WEBSOCKET_EXTENSIONS)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|extension| extension.split(';').next().map(str::trim) == Some("permessage-deflate"))
+}
+
+/// Websocket upgrade with `permessage-deflate` agreed with the client, connected once response is sent.
+pub(crate) struct DeflateUpgrade {
+    server: Server,
+    on_upgrade: OnUpgrade,
+}
+
+/// Websocket connected with [`DeflateUpgrade`], as sink of messages sent to the client
+/// and stream of messages received from it.
+pub(crate) type DeflateWebSocket = (
+    Pin<Box<dyn Sink<Message, Error = connection::Error> + Send>>,
+    Pin<Box<dyn Stream<Item = Result<Message, connection::Error>> + Send>>,
+);
+
+impl DeflateUpgrade {
+    /// Accepts websocket upgrade `request`, returning response to be sent to the client.
+    ///
+    /// # Errors
+    /// Fails if `request` isn't a valid websocket upgrade request.
+    pub(crate) fn new(mut request: Request<Body>) -> crate::Result<(Self, Response)> {
+        let mut server = Server::new();
+        server.add_extension(Box::new(Deflate::new(Mode::Server)));
+        let response = server.receive_request(&request)?;
+        let on_upgrade = hyper::upgrade::on(&mut request);
+        let upgrade = Self { server, on_upgrade };
+        Ok((upgrade, response.map(|()| Body::empty()).into_response()))
+    }
+
+    /// Waits for the connection to be upgraded, limiting size of frames received from the client if given.
+    ///
+    /// # Errors
+    /// Fails if the connection closed before it was upgraded.
+    pub(crate) async fn connect(
+        self,
+        max_frame_size: Option<usize>,
+    ) -> crate::Result<DeflateWebSocket> {
+        let (read, mut write) = tokio::io::split(self.on_upgrade.await?);
+        let (writes_tx, mut writes_rx) = mpsc::channel::<Vec<u8>>(WRITE_QUEUE_FRAMES);
+        tokio::spawn(async move {
+            while let Some(bytes) = writes_rx.recv().await {
+                if let Err(err) = write.write_all(&bytes).await {
+                    error!("websocket write error: {err}");
+                    return;
+                }
+            }
+            if let Err(err) = write.shutdown().await {
+                error!("failed to shut down websocket: {err}");
+            }
+        });
+
+        let io = QueuedIo {
+            read: read.compat(),
+            writes: PollSender::new(writes_tx),
+        };
+        let mut builder = self.server.into_builder(io);
+        if let Some(max_frame_size) = max_frame_size {
+            builder.set_max_frame_size(max_frame_size);
+        }
+        let (sender, receiver) = builder.finish();
+        Ok((
+            Box::pin(message_sink(sender)),
+            Box::pin(message_stream(receiver)),
+        ))
+    }
+}
+
+/// Sends messages to the client, closing the connection on close message.
+fn message_sink(sender: Sender<QueuedIo>) -> impl Sink<Message, Error = connection::Error> + Send {
+    sink::unfold(sender, |mut sender, message: Message| async move {
+        match message {
+            Message::Binary(data) => sender.send_binary_mut(data).await?,
+            Message::Text(text) => sender.send_text_owned(text).await?,
+            Message::Close(_) => {
+                sender.close().await?;
+                return Ok(sender);
+            }
+            // soketto answers pings on its own, server doesn't send them
+            Message::Ping(_) | Message::Pong(_) => {}
+        }
+        sender.flush().await?;
+        Ok(sender)
+    })
+}
+
+/// Receives messages from the client until it closes the connection.
+fn message_stream(
+    receiver: Receiver<QueuedIo>,
+) -> impl Stream<Item = Result<Message, connection::Error>> + Send {
+    stream::unfold(receiver, |mut receiver| async move {
+        let mut data = Vec::new();
+        let message = match receiver.receive_data(&mut data).await {
+            Ok(Data::Binary(_)) => Ok(Message::Binary(data)),
+            Ok(Data::Text(_)) => String::from_utf8(data)
+                .map(Message::Text)
+                .map_err(|err| connection::Error::Utf8(err.utf8_error())),
+            Err(connection::Error::Closed) => return None,
+            Err(err) => Err(err),
+        };
+        Some((message, receiver))
+    })
+}
+
+/// Upgraded connection whose writes are queued for the task writing them out,
+/// waiting for room in the queue if the client doesn't keep up.
+struct QueuedIo {
+    read: Compat<ReadHalf<Upgraded>>,
+    writes: PollSender<Vec<u8>>,
+}
+
+impl AsyncRead for QueuedIo {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.read).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for QueuedIo {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let result = ready!(self.writes.poll_reserve(cx))
+            .and_then(|()| self.writes.send_item(buf.to_vec()))
+            .map(|()| buf.len())
+            .map_err(|_closed| io::ErrorKind::BrokenPipe.into());
+        Poll::Ready(result)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    /// Lets the writing task shut the connection down once it wrote out the queue.
+    fn poll_close(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.writes.close();
+        Poll::Ready(Ok(()))
+    }
+}
@@ -0,0 +1,367 @@
+use std::env;
+use std::path::PathBuf;
+
+/// Default size after which the audit log is rotated, 10 MiB.
+pub const DEFAULT_AUDIT_LOG_MAX_BYTES: u64 = 10 * 1024 * 1024;
+
+/// Default time given to users to disconnect once the server is shutting down.
+pub const DEFAULT_GRACEFUL_SHUTDOWN_TIMEOUT_SECS: u64 = 30;
+
+/// Default number of events kept in the audit log of each session.
+pub const DEFAULT_SESSION_AUDIT_MAX_EVENTS: usize = 1000;
+
+/// Default time after which events of the session audit log are pruned, a day.
+pub const DEFAULT_SESSION_TTL_SECS: u64 = 24 * 60 * 60;
+
+/// Default limit of SDP size, 100 KiB.
+pub const DEFAULT_MAX_SDP_BYTES: usize = 100 * 1024;
+
+/// Default limit of SDP lines, far more than any browser sends even with many media sections.
+pub const DEFAULT_MAX_SDP_LINES: usize = 1000;
+
+/// Default limit of a single ICE candidate size.
+pub const DEFAULT_MAX_CANDIDATE_BYTES: usize = 1024;
+
+/// Limits of sessions, users over them are turned away with an error message.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SessionLimits {
+    /// Maximum number of sessions of each topology
+    pub max_sessions: Option<usize>,
+    /// Maximum number of users in a single session, one-to-one sessions never have more than two
+    pub max_users_per_session: Option<usize>,
+}
+
+impl SessionLimits {
+    /// Reason to turn away a user joining session with `users_count` users already in it,
+    /// `None` if it can join. Session is new if it has no users yet.
+    #[must_use]
+    pub fn rejection(&self, sessions_count: usize, users_count: usize) -> Option<&'static str> {
+        let is_new = users_count == 0;
+        if is_new
+            && self
+                .max_sessions
+                .is_some_and(|max_sessions| sessions_count >= max_sessions)
+        {
+            Some("too many sessions")
+        } else if self
+            .max_users_per_session
+            .is_some_and(|max_users| users_count >= max_users)
+        {
+            Some("session is full")
+        } else {
+            None
+        }
+    }
+}
+
+/// Limits of SDP and ICE candidates relayed between users, messages over them or malformed ones
+/// are answered with an error message and not passed on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PayloadLimits {
+    /// Maximum size of SDP offers and answers in bytes
+    pub max_sdp_bytes: usize,
+    /// Maximum number of lines of SDP offers and answers
+    pub max_sdp_lines: usize,
+    /// Maximum size of a single ICE candidate in bytes
+    pub max_candidate_bytes: usize,
+}
+
+impl Default for PayloadLimits {
+    fn default() -> Self {
+        Self {
+            max_sdp_bytes: DEFAULT_MAX_SDP_BYTES,
+            max_sdp_lines: DEFAULT_MAX_SDP_LINES,
+            max_candidate_bytes: DEFAULT_MAX_CANDIDATE_BYTES,
+        }
+    }
+}
+
+impl PayloadLimits {
+    /// Reason to reject SDP offer or answer, `None` if it can be relayed.
+    #[must_use]
+    pub fn sdp_rejection(&self, sdp: &str) -> Option<&'static str> {
+        if sdp.len() > self.max_sdp_bytes {
+            Some("SDP is too large")
+        } else if !sdp.starts_with("v=0") {
+            Some("SDP doesn't start with version line")
+        } else if sdp.lines().count() > self.max_sdp_lines {
+            Some("SDP has too many lines")
+        } else {
+            None
+        }
+    }
+
+    /// Reason to reject ICE candidate, `None` if it can be relayed.
+    /// Empty candidate, which signals end of candidates, is always relayed.
+    #[must_use]
+    pub fn candidate_rejection(&self, candidate: &str) -> Option<&'static str> {
+        if candidate.len() > self.max_candidate_bytes {
+            Some("ICE candidate is too large")
+        } else if !candidate.is_empty() && !is_candidate(candidate) {
+            Some("malformed ICE candidate")
+        } else {
+            None
+        }
+    }
+}
+
+/// Whether `candidate` has the fields required by the `candidate-attribute` grammar of RFC 8839:
+/// `candidate:<foundation> <component> <transport> <priority> <address> <port> typ <type>`,
+/// `a=` prefix is accepted too.
+fn is_candidate(candidate: &str) -> bool {
+    let Some(attribute) = candidate
+        .strip_prefix("a=")
+        .unwrap_or(candidate)
+        .strip_prefix("candidate:")
+    else {
+        return false;
+    };
+    let fields: Vec<&str> = attribute.split_ascii_whitespace().collect();
+    let [foundation, component, transport, priority, address, port, typ, candidate_type, ..] =
+        fields[..]
+    else {
+        return false;
+    };
+    !foundation.is_empty()
+        && component.parse::<u16>().is_ok()
+        && !transport.is_empty()
+        && priority.parse::<u32>().is_ok()
+        && !address.is_empty()
+        && port.parse::<u16>().is_ok()
+        && typ == "typ"
+        && !candidate_type.is_empty()
+}
+
+/// Origins of pages whose users can connect, checked against `Origin` header sent by browsers.
+///
+/// Patterns are either full origins, e.g. `https://example.com`, or hosts matching any scheme,
+/// e.g. `example.com:8080`. Leading `*.` matches any subdomain, e.g. `https://*.example.com`.
+/// Empty list allows all origins.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AllowedOrigins {
+    pub patterns: Vec<String>,
+}
+
+impl AllowedOrigins {
+    /// Whether page with `origin` can connect.
+    #[must_use]
+    pub fn allows(&self, origin: &str) -> bool {
+        self.patterns.is_empty()
+            || self
+                .patterns
+                .iter()
+                .any(|pattern| origin_matches(pattern, origin))
+    }
+}
+
+fn origin_matches(pattern: &str, origin: &str) -> bool {
+    let (pattern_scheme, pattern_host) = split_origin(pattern);
+    let (scheme, host) = split_origin(origin);
+    if pattern_scheme.is_some() && pattern_scheme != scheme {
+        return false;
+    }
+    match pattern_host.strip_prefix("*.") {
+        Some(domain) => host
+            .strip_suffix(domain)
+            .and_then(|subdomain| subdomain.strip_suffix('.'))
+            .is_some_and(|subdomain| !subdomain.is_empty()),
+        None => host.eq_ignore_ascii_case(pattern_host),
+    }
+}
+
+/// Splits origin into scheme, if there is one, and host with port.
+fn split_origin(origin: &str) -> (Option<&str>, &str) {
+    match origin.split_once("://") {
+        Some((scheme, host)) => (Some(scheme), host),
+        None => (None, origin),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// File to which [`MessageLogger`](crate::message_logger::MessageLogger) writes
+    /// received signal messages, audit log is disabled if not set
+    pub audit_log_path: Option<PathBuf>,
+    /// Size after which the audit log is rotated
+    pub audit_log_max_bytes: u64,
+    /// Write SDP and ICE candidates to the audit log instead of redacting them
+    pub log_sensitive: bool,
+    /// PEM encoded certificate chain, server accepts only `wss://` connections if it's set
+    /// together with [`ServerConfig::tls_key_path`]
+    pub tls_cert_path: Option<PathBuf>,
+    /// PEM encoded private key of the certificate
+    pub tls_key_path: Option<PathBuf>,
+    /// Token required by admin endpoints, which are disabled if not set
+    pub admin_token: Option<String>,
+    /// Secret users have to pass as `token` query parameter of the signaling URL,
+    /// e.g. `wss://signaling.example.com/one-to-one?token=<secret>`, anyone can connect if not set
+    pub auth_secret: Option<String>,
+    pub session_limits: SessionLimits,
+    /// Limits of SDP and ICE candidates passed between users
+    pub payload_limits: PayloadLimits,
+    /// Websocket upgrades from browsers on other pages are rejected with `403 Forbidden`,
+    /// clients that don't send `Origin` header, i.e. other than browsers, are always let in
+    pub allowed_origins: AllowedOrigins,
+    /// Directory where sessions of each topology are saved as JSON, restored once the server restarts,
+    /// sessions are kept only in memory if not set
+    pub session_store_dir: Option<PathBuf>,
+    /// Redis through which users and sessions are shared by instances of the server, e.g. `redis://redis:6379`,
+    /// see [`redis`](crate::redis) module
+    #[cfg(feature = "redis")]
+    pub redis_url: Option<String>,
+    /// Seconds to wait for users to disconnect after their connections were closed on shutdown,
+    /// see [`ServerState::close_connections`](crate::router::ServerState::close_connections)
+    pub graceful_shutdown_timeout_secs: u64,
+    /// Number of events kept for each session by [`SessionAuditLog`](crate::session_audit::SessionAuditLog),
+    /// older ones are dropped, none are kept if it's zero
+    pub session_audit_max_events: usize,
+    /// Seconds after which events of the session audit log are pruned
+    pub session_ttl_secs: u64,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            audit_log_path: None,
+            audit_log_max_bytes: DEFAULT_AUDIT_LOG_MAX_BYTES,
+            log_sensitive: false,
+            tls_cert_path: None,
+            tls_key_path: None,
+            admin_token: None,
+            auth_secret: None,
+            session_limits: SessionLimits::default(),
+            payload_limits: PayloadLimits::default(),
+            allowed_origins: AllowedOrigins::default(),
+            session_store_dir: None,
+            #[cfg(feature = "redis")]
+            redis_url: None,
+            graceful_shutdown_timeout_secs: DEFAULT_GRACEFUL_SHUTDOWN_TIMEOUT_SECS,
+            session_audit_max_events: DEFAULT_SESSION_AUDIT_MAX_EVENTS,
+            session_ttl_secs: DEFAULT_SESSION_TTL_SECS,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Reads config from `AUDIT_LOG_PATH`, `AUDIT_LOG_MAX_BYTES`, `AUDIT_LOG_SENSITIVE`,
+    /// `TLS_CERT_PATH`, `TLS_KEY_PATH`, `ADMIN_TOKEN`, `GRACEFUL_SHUTDOWN_TIMEOUT_SECS`,
+    /// `SESSION_AUDIT_MAX_EVENTS` and `SESSION_TTL_SECS` environment variables,
+    /// using defaults for the missing ones.
+    /// Binary reads the remaining options from its command line, see `--help`.
+    ///
+    /// # Errors
+    /// Fails if any of the variables is malformed.
+    pub fn from_env() -> crate::Result<Self> {
+        let mut config = Self {
+            audit_log_path: env::var_os("AUDIT_LOG_PATH").map(PathBuf::from),
+            tls_cert_path: env::var_os("TLS_CERT_PATH").map(PathBuf::from),
+            tls_key_path: env::var_os("TLS_KEY_PATH").map(PathBuf::from),
+            admin_token: env::var("ADMIN_TOKEN").ok(),
+            ..Self::default()
+        };
+        if let Ok(max_bytes) = env::var("AUDIT_LOG_MAX_BYTES") {
+            config.audit_log_max_bytes = max_bytes.parse()?;
+        }
+        if let Ok(log_sensitive) = env::var("AUDIT_LOG_SENSITIVE") {
+            config.log_sensitive = log_sensitive.parse()?;
+        }
+        if let Ok(timeout_secs) = env::var("GRACEFUL_SHUTDOWN_TIMEOUT_SECS") {
+            config.graceful_shutdown_timeout_secs = timeout_secs.parse()?;
+        }
+        if let Ok(max_events) = env::var("SESSION_AUDIT_MAX_EVENTS") {
+            config.session_audit_max_events = max_events.parse()?;
+        }
+        if let Ok(ttl_secs) = env::var("SESSION_TTL_SECS") {
+            config.session_ttl_secs = ttl_secs.parse()?;
+        }
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_session_limits_reject_users_over_them() {
+        let limits = SessionLimits {
+            max_sessions: Some(2),
+            max_users_per_session: Some(3),
+        };
+        assert_eq!(limits.rejection(1, 0), None);
+        assert_eq!(limits.rejection(2, 0), Some("too many sessions"));
+        // joining existing session doesn't create a new one
+        assert_eq!(limits.rejection(2, 2), None);
+        assert_eq!(limits.rejection(2, 3), Some("session is full"));
+        assert_eq!(
+            SessionLimits::default().rejection(usize::MAX, usize::MAX),
+            None
+        );
+    }
+
+    #[test]
+    fn test_payload_limits_reject_oversized_and_malformed_payloads() {
+        let limits = PayloadLimits {
+            max_sdp_bytes: 64,
+            max_sdp_lines: 3,
+            max_candidate_bytes: 96,
+        };
+        assert_eq!(
+            limits.sdp_rejection("v=0\r\no=- 1 2 IN IP4 127.0.0.1\r\ns=-\r\n"),
+            None
+        );
+        assert_eq!(
+            limits.sdp_rejection(&format!("v=0\r\n{}", "a".repeat(64))),
+            Some("SDP is too large")
+        );
+        assert_eq!(
+            limits.sdp_rejection("offer"),
+            Some("SDP doesn't start with version line")
+        );
+        assert_eq!(
+            limits.sdp_rejection("v=0\r\na=1\r\na=2\r\na=3\r\n"),
+            Some("SDP has too many lines")
+        );
+
+        let candidate = "candidate:842163049 1 udp 1677729535 203.0.113.5 3478 typ srflx \
+                         raddr 192.168.0.2 rport 54321";
+        assert_eq!(limits.candidate_rejection(candidate), None);
+        assert_eq!(
+            limits.candidate_rejection("a=candidate:1 1 tcp 1518280447 ::1 9 typ host"),
+            None
+        );
+        assert_eq!(limits.candidate_rejection(""), None);
+        assert_eq!(
+            limits.candidate_rejection(&format!("{candidate} {}", "a".repeat(64))),
+            Some("ICE candidate is too large")
+        );
+        for malformed in [
+            "host",
+            "candidate:1 1 udp 2122260223 192.168.0.2 54321",
+            "candidate:1 one udp 2122260223 192.168.0.2 54321 typ host",
+            "candidate:1 1 udp 2122260223 192.168.0.2 port typ host",
+            "candidate:1 1 udp 2122260223 192.168.0.2 54321 type host",
+        ] {
+            assert_eq!(
+                limits.candidate_rejection(malformed),
+                Some("malformed ICE candidate"),
+                "{malformed}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_allowed_origins_match_exact_and_wildcard_patterns() {
+        let allowed = AllowedOrigins {
+            patterns: vec!["https://example.com".to_owned(), "*.example.org".to_owned()],
+        };
+        assert!(allowed.allows("https://example.com"));
+        assert!(!allowed.allows("http://example.com"));
+        assert!(!allowed.allows("https://evil-example.com"));
+        assert!(allowed.allows("https://app.example.org"));
+        assert!(allowed.allows("http://a.b.example.org"));
+        assert!(!allowed.allows("https://example.org"));
+        assert!(!allowed.allows("https://notexample.org"));
+        assert!(AllowedOrigins::default().allows("https://anything.com"));
+    }
+}
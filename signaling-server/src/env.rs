@@ -1,4 +1,3 @@
-use once_cell::sync::Lazy;
 use serde::Deserialize;
 
 #[derive(Deserialize, Debug)]
@@ -7,5 +6,10 @@ pub struct Env {
     pub example_list: Vec<String>,
 }
 
-/// Access to parsed environment variables.
-pub static ENV: Lazy<Env> = Lazy::new(|| envy::from_env().expect("some env vars missing"));
+/// Parses environment variables.
+///
+/// # Errors
+/// Fails if any of the variables is missing or malformed.
+pub fn env() -> crate::Result<Env> {
+    Ok(envy::from_env()?)
+}
@@ -0,0 +1,517 @@
+//! Topology independent part of the signaling server: user id allocation, connection registration,
+//! forwarding messages to websockets and disconnect cleanup.
+//! What happens with the messages is decided by a [`SessionPolicy`].
+
+use std::collections::HashMap;
+use std::fmt::{Debug, Display};
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use axum::async_trait;
+use axum::body::Body;
+use axum::extract::ws::{close_code, CloseFrame, Message, WebSocket};
+use axum::extract::{FromRequestParts, WebSocketUpgrade};
+use axum::http::{Request, StatusCode};
+use axum::response::IntoResponse;
+use axum::routing::{get, MethodRouter};
+use futures_util::{FutureExt, Sink, SinkExt, Stream, StreamExt, TryFutureExt};
+use log::{error, info, warn};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tokio::sync::{mpsc, RwLock};
+use tokio_stream::wrappers::UnboundedReceiverStream;
+use wasm_peers_protocol::{
+    JoinError, JoinIntent, SessionId, SessionInfo, TopologyType, UserId, PROTOCOL_MAJOR_VERSION,
+    PROTOCOL_MINOR_VERSION,
+};
+
+use crate::compression::{self, DeflateUpgrade};
+use crate::config::PayloadLimits;
+use crate::message_logger::{Audited, MessageLogger};
+use crate::relay::{LocalRelay, Relay};
+use crate::session_audit::SharedSessionAuditLog;
+use crate::span;
+use crate::store::StoredSession;
+use crate::validation::Validated;
+use crate::version::{Versioned, INCOMPATIBLE_VERSION_REASON};
+
+pub type Connections = Arc<RwLock<HashMap<UserId, mpsc::UnboundedSender<Message>>>>;
+
+/// Messages to be sent out, each with its recipient.
+pub type Outgoing<M> = Vec<(UserId, M)>;
+
+/// Decides how users are grouped into sessions and where their messages go.
+/// Implemented by [`one_to_one::Policy`](crate::one_to_one::Policy),
+/// [`one_to_many::Policy`](crate::one_to_many::Policy) and [`many_to_many::Policy`](crate::many_to_many::Policy),
+/// custom policies can wrap them e.g. to limit the number of users.
+///
+/// Policy is shared by all connections of a hub, so it keeps its state behind a lock.
+#[async_trait]
+pub trait SessionPolicy: Send + Sync + 'static {
+    type Message: Serialize + DeserializeOwned + Audited + Validated + Versioned + Debug + Send;
+
+    /// Recorded as `topology` field of the connection span
+    const TOPOLOGY: &'static str;
+
+    /// Called once user connects, before any of its messages are routed.
+    ///
+    /// # Errors
+    /// User is disconnected straight away if it fails.
+    async fn on_join(&self, _user_id: UserId) -> crate::Result<()> {
+        Ok(())
+    }
+
+    /// Handles `message` received from `sender_id`, returning messages to pass on.
+    ///
+    /// # Errors
+    /// Errors are logged, user stays connected.
+    async fn route(
+        &self,
+        sender_id: UserId,
+        message: Self::Message,
+    ) -> crate::Result<Outgoing<Self::Message>>;
+
+    /// Removes disconnected user from its sessions, returning messages to send to the remaining users.
+    async fn on_disconnect(&self, user_id: UserId) -> Outgoing<Self::Message>;
+}
+
+/// Sessions of a single topology sent back in response to `SessionListRequest`, ordered by their ids.
+pub(crate) fn session_list<S: StoredSession>(
+    sessions: &HashMap<SessionId, S>,
+    topology: TopologyType,
+) -> Vec<SessionInfo> {
+    let mut session_list: Vec<_> = sessions
+        .iter()
+        .map(|(&session_id, session)| SessionInfo {
+            session_id,
+            peer_count: session.users().len(),
+            topology,
+        })
+        .collect();
+    session_list.sort_unstable_by_key(|session| session.session_id.inner());
+    session_list
+}
+
+/// Why user joining session `session_id` with `intent` is refused, `None` if it can join.
+pub(crate) fn join_error<S>(
+    sessions: &HashMap<SessionId, S>,
+    session_id: SessionId,
+    intent: JoinIntent,
+) -> Option<JoinError> {
+    match (intent, sessions.contains_key(&session_id)) {
+        (JoinIntent::Join, false) => Some(JoinError::NoSuchSession),
+        (JoinIntent::Create, true) => Some(JoinError::AlreadyExists),
+        _ => None,
+    }
+}
+
+/// Whether `user_id` is a member of session `session_id`.
+pub(crate) fn is_member<S: StoredSession>(
+    sessions: &HashMap<SessionId, S>,
+    session_id: SessionId,
+    user_id: UserId,
+) -> bool {
+    sessions
+        .get(&session_id)
+        .is_some_and(|session| session.users().contains(&user_id))
+}
+
+/// Connects websocket users with a [`SessionPolicy`].
+/// Cloning is cheap, clones share connections and the policy.
+pub struct SignalingHub<P> {
+    policy: Arc<P>,
+    connections: Connections,
+    relay: Arc<dyn Relay>,
+    message_logger: Option<Arc<MessageLogger>>,
+    session_audit_log: Option<SharedSessionAuditLog>,
+    max_frame_size: Option<usize>,
+    payload_limits: PayloadLimits,
+    /// Set once handling of any connection panicked
+    panicked: Arc<AtomicBool>,
+}
+
+impl<P> Clone for SignalingHub<P> {
+    fn clone(&self) -> Self {
+        Self {
+            policy: Arc::clone(&self.policy),
+            connections: Arc::clone(&self.connections),
+            relay: Arc::clone(&self.relay),
+            message_logger: self.message_logger.clone(),
+            session_audit_log: self.session_audit_log.clone(),
+            max_frame_size: self.max_frame_size,
+            payload_limits: self.payload_limits,
+            panicked: Arc::clone(&self.panicked),
+        }
+    }
+}
+
+impl<P: SessionPolicy + Default> Default for SignalingHub<P> {
+    fn default() -> Self {
+        Self::new(P::default())
+    }
+}
+
+impl<P: SessionPolicy> SignalingHub<P> {
+    #[must_use]
+    pub fn new(policy: P) -> Self {
+        Self::with_connections(policy, Connections::default())
+    }
+
+    /// Creates hub registering users in `connections`, e.g. to observe them from the embedding application.
+    #[must_use]
+    pub fn with_connections(policy: P, connections: Connections) -> Self {
+        Self {
+            policy: Arc::new(policy),
+            connections,
+            relay: Arc::new(LocalRelay::default()),
+            message_logger: None,
+            session_audit_log: None,
+            max_frame_size: None,
+            payload_limits: PayloadLimits::default(),
+            panicked: Arc::default(),
+        }
+    }
+
+    /// Allocates user ids and reaches users connected to other instances of the server through `relay`.
+    #[must_use]
+    pub fn with_relay(self, relay: Arc<dyn Relay>) -> Self {
+        Self { relay, ..self }
+    }
+
+    /// Records every signal message received from users with `message_logger`.
+    #[must_use]
+    pub fn with_message_logger(self, message_logger: Arc<MessageLogger>) -> Self {
+        Self {
+            message_logger: Some(message_logger),
+            ..self
+        }
+    }
+
+    /// Records joins, SDP and ICE candidates accepted from users and their disconnects in `session_audit_log`.
+    #[must_use]
+    pub fn with_session_audit_log(self, session_audit_log: SharedSessionAuditLog) -> Self {
+        Self {
+            session_audit_log: Some(session_audit_log),
+            ..self
+        }
+    }
+
+    /// Limits size of websocket frames received from users, axum's default of 16 MiB is used if not set.
+    #[must_use]
+    pub fn with_max_frame_size(self, max_frame_size: usize) -> Self {
+        Self {
+            max_frame_size: Some(max_frame_size),
+            ..self
+        }
+    }
+
+    /// Rejects SDP and ICE candidates over `payload_limits` or malformed ones, [`PayloadLimits::default`] if not set.
+    #[must_use]
+    pub fn with_payload_limits(self, payload_limits: PayloadLimits) -> Self {
+        Self {
+            payload_limits,
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn policy(&self) -> &P {
+        &self.policy
+    }
+
+    #[must_use]
+    pub fn connections(&self) -> &Connections {
+        &self.connections
+    }
+
+    /// `false` once handling of any connection accepted by [`SignalingHub::method_router`] panicked,
+    /// as its user may have been left behind in connections or sessions.
+    #[must_use]
+    pub fn is_healthy(&self) -> bool {
+        !self.panicked.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn mark_unhealthy(&self) {
+        self.panicked.store(true, Ordering::Relaxed);
+    }
+
+    /// Route accepting websocket connections, to be mounted on any [`Router`](axum::Router).
+    pub fn method_router<S>(self) -> MethodRouter<S>
+    where
+        S: Clone + Send + Sync + 'static,
+    {
+        get(move |request: Request<Body>| async move {
+            if compression::offers_deflate(request.headers()) {
+                let (upgrade, response) = match DeflateUpgrade::new(request) {
+                    Ok(upgrade) => upgrade,
+                    Err(err) => return (StatusCode::BAD_REQUEST, err.to_string()).into_response(),
+                };
+                tokio::spawn(async move {
+                    match upgrade.connect(self.max_frame_size).await {
+                        Ok((user_ws_tx, user_ws_rx)) => {
+                            self.catch_panic(self.clone().serve(user_ws_tx, user_ws_rx))
+                                .await;
+                        }
+                        Err(err) => error!("websocket upgrade failed: {err}"),
+                    }
+                });
+                return response;
+            }
+            let (mut parts, _body) = request.into_parts();
+            let ws = match WebSocketUpgrade::from_request_parts(&mut parts, &()).await {
+                Ok(ws) => ws,
+                Err(rejection) => return rejection.into_response(),
+            };
+            let ws = match self.max_frame_size {
+                Some(max_frame_size) => ws.max_frame_size(max_frame_size),
+                None => ws,
+            };
+            ws.on_upgrade(move |socket| async move {
+                self.catch_panic(self.clone().user_connected(socket)).await;
+            })
+        })
+    }
+
+    /// Marks hub as unhealthy if handling of a connection panics.
+    async fn catch_panic(&self, connection: impl Future<Output = ()>) {
+        if AssertUnwindSafe(connection).catch_unwind().await.is_err() {
+            error!("{} connection handler panicked", P::TOPOLOGY);
+            self.mark_unhealthy();
+        }
+    }
+
+    /// Handles user connected over `ws` until it disconnects.
+    pub async fn user_connected(self, ws: WebSocket) {
+        let (user_ws_tx, user_ws_rx) = ws.split();
+        self.serve(user_ws_tx, user_ws_rx).await;
+    }
+
+    /// Handles user connected over websocket split into `user_ws_tx` and `user_ws_rx` until it disconnects.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "connection",
+            skip_all,
+            fields(
+                topology = P::TOPOLOGY,
+                user_id = tracing::field::Empty,
+                session_id = tracing::field::Empty
+            )
+        )
+    )]
+    async fn serve<Tx, Rx, E>(self, mut user_ws_tx: Tx, mut user_ws_rx: Rx)
+    where
+        Tx: Sink<Message> + Send + Unpin + 'static,
+        Tx::Error: Display,
+        Rx: Stream<Item = Result<Message, E>> + Unpin,
+        E: Display,
+    {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let mut rx = UnboundedReceiverStream::new(rx);
+
+        tokio::task::spawn(async move {
+            while let Some(message) = rx.next().await {
+                user_ws_tx
+                    .send(message)
+                    .unwrap_or_else(|e| error!("websocket send error: {e}"))
+                    .await;
+            }
+        });
+
+        let user_id = match self.register(tx).await {
+            Ok(user_id) => user_id,
+            Err(err) => {
+                error!("failed to register new user: {err}");
+                return;
+            }
+        };
+        span::record_user(user_id);
+        info!("new user connected: {user_id:?}");
+
+        if let Err(err) = self.policy.on_join(user_id).await {
+            error!("user {user_id:?} turned away: {err}");
+            self.unregister(user_id).await;
+            return;
+        }
+
+        while let Some(result) = user_ws_rx.next().await {
+            let msg = match result {
+                Ok(msg) => msg,
+                Err(err) => {
+                    error!("websocket error (user_id={user_id:?}): {err}");
+                    break;
+                }
+            };
+
+            if let Err(err) = self.user_message(user_id, msg).await {
+                error!("error while handling message from user {user_id:?}: {err}");
+            }
+        }
+
+        error!("user disconnected: {user_id:?}");
+        self.unregister(user_id).await;
+        let outgoing = self.policy.on_disconnect(user_id).await;
+        if let Some(ref session_audit_log) = self.session_audit_log {
+            session_audit_log.write().await.record_disconnect(user_id);
+        }
+        if let Err(err) = self.send(outgoing).await {
+            error!("error while notifying about disconnected user {user_id:?}: {err}");
+        }
+    }
+
+    /// Sends `message` to every user connected to this instance of the server.
+    ///
+    /// # Errors
+    /// Fails only if the message can't be serialized, users that can't be reached are just logged.
+    pub async fn send_to_all(&self, message: &P::Message) -> crate::Result<()> {
+        let message = rmp_serde::to_vec(message)?;
+        for (user_id, sender) in self.connections.read().await.iter() {
+            if let Err(err) = sender.send(Message::Binary(message.clone())) {
+                error!("failed to send message to user {user_id:?}: {err}");
+            }
+        }
+        Ok(())
+    }
+
+    /// Closes websockets of all users connected to this instance of the server with `Going Away` code
+    /// and `reason`, e.g. before it shuts down. Users are removed from their sessions once they disconnect.
+    pub async fn close_all(&self, reason: &str) {
+        for (user_id, sender) in self.connections.read().await.iter() {
+            let frame = CloseFrame {
+                code: close_code::AWAY,
+                reason: reason.to_owned().into(),
+            };
+            if let Err(err) = sender.send(Message::Close(Some(frame))) {
+                error!("failed to close connection of user {user_id:?}: {err}");
+            }
+        }
+    }
+
+    /// Sends `message` to `user_id` and closes its websocket with `Policy Violation` code and `reason`,
+    /// e.g. on request of server administrator. User is removed from its sessions once it disconnects.
+    /// Users connected to other instances of the server are only sent the message.
+    ///
+    /// # Errors
+    /// Fails only if the message can't be serialized.
+    pub async fn kick(
+        &self,
+        user_id: UserId,
+        message: P::Message,
+        reason: &str,
+    ) -> crate::Result<()> {
+        self.send(vec![(user_id, message)]).await?;
+        if let Some(sender) = self.connections.read().await.get(&user_id) {
+            let frame = CloseFrame {
+                code: close_code::POLICY,
+                reason: reason.to_owned().into(),
+            };
+            if let Err(err) = sender.send(Message::Close(Some(frame))) {
+                error!("failed to close connection of user {user_id:?}: {err}");
+            }
+        }
+        Ok(())
+    }
+
+    /// Assigns a new id to user receiving messages through `sender`.
+    pub(crate) async fn register(
+        &self,
+        sender: mpsc::UnboundedSender<Message>,
+    ) -> crate::Result<UserId> {
+        let user_id = self.relay.next_user_id().await?;
+        self.relay.subscribe(user_id, sender.clone()).await?;
+        self.connections.write().await.insert(user_id, sender);
+        Ok(user_id)
+    }
+
+    async fn unregister(&self, user_id: UserId) {
+        self.connections.write().await.remove(&user_id);
+        self.relay.unsubscribe(user_id).await;
+    }
+
+    async fn user_message(&self, sender_id: UserId, msg: Message) -> crate::Result<()> {
+        let data = msg.into_data();
+        let request = rmp_serde::from_slice::<P::Message>(&data)?;
+        if let Some(ref message_logger) = self.message_logger {
+            message_logger.log(sender_id, &request, data.len());
+        }
+        if let Some((major, minor)) = request.announced_version() {
+            return self.negotiate_version(sender_id, major, minor).await;
+        }
+        if let Some(reason) = request.rejection(&self.payload_limits) {
+            error!(
+                "{} from user {sender_id:?} rejected: {reason}",
+                request.message_type()
+            );
+            if let Some(session_id) = request.session_id() {
+                let reason = format!("{} rejected: {reason}", request.message_type());
+                let error = P::Message::error(session_id, sender_id, reason);
+                self.send(vec![(sender_id, error)]).await?;
+            }
+            return Ok(());
+        }
+        if let (Some(session_audit_log), Some(session_id), Some(event_type)) = (
+            self.session_audit_log.as_ref(),
+            request.session_id(),
+            request.session_event(),
+        ) {
+            session_audit_log
+                .write()
+                .await
+                .record(session_id, sender_id, event_type);
+        }
+        // SDP and ICE candidates are redacted by Debug of signal messages, unless built with `log-full-sdp`
+        info!("message received from user {sender_id:?}: {request:?}");
+        let outgoing = self.policy.route(sender_id, request).await?;
+        self.send(outgoing).await
+    }
+
+    /// Answers user announcing its protocol version with the server's one,
+    /// or refuses it with [`Versioned::version_mismatch`] if the major versions differ.
+    async fn negotiate_version(&self, user_id: UserId, major: u8, minor: u8) -> crate::Result<()> {
+        if major == PROTOCOL_MAJOR_VERSION {
+            info!("user {user_id:?} speaks protocol v{major}.{minor}");
+            return self
+                .send(vec![(user_id, P::Message::server_version())])
+                .await;
+        }
+        warn!(
+            "user {user_id:?} speaks protocol v{major}.{minor}, incompatible with v{PROTOCOL_MAJOR_VERSION}.{PROTOCOL_MINOR_VERSION}"
+        );
+        self.kick(
+            user_id,
+            P::Message::version_mismatch(),
+            INCOMPATIBLE_VERSION_REASON,
+        )
+        .await
+    }
+
+    /// Sends each message to its recipient, through relay if it's connected to another instance.
+    /// Fails only if a message can't be serialized, users that can't be reached are just logged.
+    pub(crate) async fn send(&self, outgoing: Outgoing<P::Message>) -> crate::Result<()> {
+        if outgoing.is_empty() {
+            return Ok(());
+        }
+        let mut relayed = Vec::new();
+        {
+            let connections = self.connections.read().await;
+            for (recipient_id, message) in outgoing {
+                let message = rmp_serde::to_vec(&message)?;
+                let Some(sender) = connections.get(&recipient_id) else {
+                    relayed.push((recipient_id, message));
+                    continue;
+                };
+                if let Err(err) = sender.send(Message::Binary(message)) {
+                    error!("failed to send message to user {recipient_id:?}: {err}");
+                }
+            }
+        }
+        for (recipient_id, message) in relayed {
+            if let Err(err) = self.relay.forward(recipient_id, message).await {
+                error!("failed to relay message to user {recipient_id:?}: {err}");
+            }
+        }
+        Ok(())
+    }
+}
@@ -63,11 +63,26 @@
     clippy::unseparated_literal_suffix,
     clippy::verbose_file_reads
 )]
+// `no-std` feature of the protocol crate is reported for every workspace member
+#![allow(clippy::negative_feature_names)]
 
+pub mod admin;
+mod compression;
+pub mod config;
 mod error;
+pub mod hub;
 pub mod many_to_many;
+pub mod message_logger;
 pub mod one_to_many;
 pub mod one_to_one;
+#[cfg(feature = "redis")]
+pub mod redis;
+pub mod relay;
 pub mod router;
+pub mod session_audit;
+mod span;
+pub mod store;
+pub mod validation;
+pub mod version;
 
 pub use error::{Error, Result};
@@ -1,33 +1,337 @@
-use std::env;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr, TcpListener};
+use std::path::PathBuf;
 use std::str::FromStr;
+use std::time::Duration;
 
-use log::{info, LevelFilter};
+use clap::Parser;
+use log::{error, info, LevelFilter};
 use simplelog::{ColorChoice, Config, TermLogger, TerminalMode};
-use wasm_peers_signaling_server::router::{self, ServerState};
+use wasm_peers_signaling_server::config::{
+    AllowedOrigins, PayloadLimits, ServerConfig, SessionLimits, DEFAULT_MAX_CANDIDATE_BYTES,
+    DEFAULT_MAX_SDP_BYTES, DEFAULT_MAX_SDP_LINES,
+};
+use wasm_peers_signaling_server::router::{self, RouterConfig, ServerState};
+
+/// Signaling server for wasm-peers.
+/// Audit log and admin endpoints are configured with environment variables, see README.
+#[derive(Debug, Parser)]
+#[command(author, version)]
+struct Args {
+    /// Address to listen on, e.g. `0.0.0.0:9001` or `[::]:9001`, overrides `--host` and `--port`
+    address: Option<SocketAddr>,
+    /// Interface to listen on, `::` or `[::]` for all IPv6 interfaces
+    #[arg(long, env = "SIGNALING_HOST", default_value = "0.0.0.0", value_parser = parse_host)]
+    host: IpAddr,
+    #[arg(long, env = "PORT", default_value_t = 9001)]
+    port: u16,
+    /// One of `off`, `error`, `warn`, `info`, `debug` or `trace`, `RUST_LOG` is used if not set, `info` by default
+    #[arg(long, env = "LOG_LEVEL")]
+    log_level: Option<LevelFilter>,
+    /// Maximum number of sessions of each topology
+    #[arg(long, env = "MAX_SESSIONS")]
+    max_sessions: Option<usize>,
+    /// Maximum number of users in a single session
+    #[arg(long, env = "MAX_USERS_PER_SESSION")]
+    max_users_per_session: Option<usize>,
+    /// Maximum size of SDP offers and answers in bytes, larger ones are not passed on
+    #[arg(long, env = "MAX_SDP_BYTES", default_value_t = DEFAULT_MAX_SDP_BYTES)]
+    max_sdp_bytes: usize,
+    /// Maximum number of lines of SDP offers and answers
+    #[arg(long, env = "MAX_SDP_LINES", default_value_t = DEFAULT_MAX_SDP_LINES)]
+    max_sdp_lines: usize,
+    /// Maximum size of a single ICE candidate in bytes
+    #[arg(long, env = "MAX_CANDIDATE_BYTES", default_value_t = DEFAULT_MAX_CANDIDATE_BYTES)]
+    max_candidate_bytes: usize,
+    /// Secret users have to pass as `token` query parameter of the signaling URL
+    #[arg(long, env = "AUTH_SECRET", hide_env_values = true)]
+    auth_secret: Option<String>,
+    /// Comma separated origins of pages whose users can connect, e.g. `https://*.example.com`, all by default
+    #[arg(long, env = "ALLOWED_ORIGINS", value_delimiter = ',')]
+    allowed_origins: Vec<String>,
+    /// Serve `/metrics` with the number of connected users and open sessions
+    #[arg(long, env = "METRICS")]
+    metrics: bool,
+    /// PEM encoded certificate chain, only `wss://` connections are accepted if it's set
+    #[arg(long, env = "TLS_CERT_PATH", requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+    /// PEM encoded private key of the certificate
+    #[arg(long, env = "TLS_KEY_PATH", requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+    /// Port on which plain HTTP requests are redirected to HTTPS, e.g. 80
+    #[arg(long, env = "HTTP_REDIRECT_PORT", requires = "tls_cert")]
+    http_redirect_port: Option<u16>,
+    /// Directory where sessions are saved, so that they survive restarts of the server
+    #[arg(long, env = "SESSION_STORE_DIR")]
+    session_store_dir: Option<PathBuf>,
+    /// Redis shared with other instances of the server, e.g. `redis://redis:6379`
+    #[cfg(feature = "redis")]
+    #[arg(
+        long,
+        env = "REDIS_URL",
+        hide_env_values = true,
+        conflicts_with = "session_store_dir"
+    )]
+    redis_url: Option<String>,
+}
+
+impl Args {
+    fn address(&self) -> SocketAddr {
+        self.address
+            .unwrap_or_else(|| SocketAddr::new(self.host, self.port))
+    }
+
+    /// Level given with `--log-level`, otherwise `rust_log` if it's a plain level like `RUST_LOG=warn`,
+    /// per module filters aren't supported.
+    fn log_level(&self, rust_log: Option<&str>) -> LevelFilter {
+        self.log_level
+            .or_else(|| rust_log.and_then(|level| LevelFilter::from_str(level.trim()).ok()))
+            .unwrap_or(LevelFilter::Info)
+    }
+
+    /// Config read from environment variables, with command line options applied on top.
+    fn server_config(&self) -> anyhow::Result<ServerConfig> {
+        Ok(ServerConfig {
+            tls_cert_path: self.tls_cert.clone(),
+            tls_key_path: self.tls_key.clone(),
+            auth_secret: self.auth_secret.clone(),
+            session_limits: SessionLimits {
+                max_sessions: self.max_sessions,
+                max_users_per_session: self.max_users_per_session,
+            },
+            payload_limits: PayloadLimits {
+                max_sdp_bytes: self.max_sdp_bytes,
+                max_sdp_lines: self.max_sdp_lines,
+                max_candidate_bytes: self.max_candidate_bytes,
+            },
+            allowed_origins: AllowedOrigins {
+                patterns: self.allowed_origins.clone(),
+            },
+            session_store_dir: self.session_store_dir.clone(),
+            #[cfg(feature = "redis")]
+            redis_url: self.redis_url.clone(),
+            ..ServerConfig::from_env()?
+        })
+    }
+}
+
+/// Resolves once the process is asked to terminate, with `SIGTERM`, e.g. by `docker stop`, or with Ctrl+C.
+async fn termination_signal() -> anyhow::Result<()> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut terminate = signal(SignalKind::terminate())?;
+        tokio::select! {
+            _ = terminate.recv() => {}
+            result = tokio::signal::ctrl_c() => result?,
+        }
+    }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await?;
+    Ok(())
+}
+
+/// Parses IP address, also in brackets like in IPv6 socket addresses, e.g. `[::]`.
+fn parse_host(host: &str) -> Result<IpAddr, std::net::AddrParseError> {
+    host.strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+        .unwrap_or(host)
+        .parse()
+}
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    let log_level = args.log_level(std::env::var("RUST_LOG").ok().as_deref());
     TermLogger::init(
-        LevelFilter::Debug,
+        log_level,
         Config::default(),
         TerminalMode::Mixed,
         ColorChoice::Auto,
     )?;
 
-    let server_state = ServerState::default();
-    let app = router::create(server_state);
+    let config = args.server_config()?;
+    // secrets are never logged, only whether they are set
+    info!(
+        "Log level: {}, TLS: {}, authentication: {}, admin endpoints: {}, metrics: {}, {:?}, {:?}, allowed origins: {:?}, session store: {:?}",
+        log_level,
+        config.tls_cert_path.is_some(),
+        config.auth_secret.is_some(),
+        config.admin_token.is_some(),
+        args.metrics,
+        config.session_limits,
+        config.payload_limits,
+        config.allowed_origins.patterns,
+        config.session_store_dir,
+    );
+    // admin endpoints, authentication and audit log are only enabled if configured
+    let server_state = ServerState::from_config(&config)?;
+    let router_config = RouterConfig {
+        enable_metrics: args.metrics,
+        ..RouterConfig::default()
+    };
+    let address = args.address();
+    let shutdown_timeout = Duration::from_secs(config.graceful_shutdown_timeout_secs);
 
-    let address = env::args()
-        .nth(1)
-        .unwrap_or_else(|| "0.0.0.0:9001".to_string());
-    let address = SocketAddr::from_str(&address)?;
+    // server stops accepting connections and closes the open ones once terminated
+    tokio::spawn({
+        let server_state = server_state.clone();
+        async move {
+            match termination_signal().await {
+                Ok(()) => {
+                    info!("Shutting down");
+                    server_state.request_shutdown();
+                }
+                Err(err) => error!("failed to listen for termination signal: {err}"),
+            }
+        }
+    });
 
-    info!("Listening on: http://{}", address);
+    // TLS is terminated by the server itself only if both certificate and its key are given
+    if let (Some(cert_path), Some(key_path)) = (&config.tls_cert_path, &config.tls_key_path) {
+        let tls_config = router::load_tls_config(cert_path, key_path).await?;
+        if let Some(http_redirect_port) = args.http_redirect_port {
+            let redirect_address = SocketAddr::new(address.ip(), http_redirect_port);
+            let redirect = axum::Server::try_bind(&redirect_address)?
+                .serve(router::https_redirect(address.port()).into_make_service());
+            info!("Redirecting http://{redirect_address} to HTTPS");
+            tokio::spawn(async move {
+                if let Err(err) = redirect.await {
+                    error!("HTTP redirect server failed: {err}");
+                }
+            });
+        }
+        info!("Listening on: https://{address}");
+        return router::serve_with_tls(
+            TcpListener::bind(address)?,
+            server_state,
+            router_config,
+            tls_config,
+            shutdown_timeout,
+        )
+        .await;
+    }
 
-    axum::Server::bind(&address)
-        .serve(app.into_make_service())
-        .await?;
+    info!("Listening on: http://{address}");
+    router::serve(
+        TcpListener::bind(address)?,
+        server_state,
+        router_config,
+        shutdown_timeout,
+    )
+    .await
+}
 
-    Ok(())
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_ipv6_host_is_accepted_with_and_without_brackets() {
+        for host in ["::", "[::]"] {
+            let args = Args::try_parse_from(["server", "--host", host, "--port", "9002"])
+                .expect("failed to parse arguments");
+            assert_eq!(
+                args.address(),
+                "[::]:9002".parse().expect("invalid address")
+            );
+        }
+    }
+
+    #[test]
+    fn test_address_argument_overrides_host_and_port() {
+        let args = Args::try_parse_from(["server", "--port", "9002", "127.0.0.1:9003"])
+            .expect("failed to parse arguments");
+        assert_eq!(
+            args.address(),
+            "127.0.0.1:9003".parse().expect("invalid address")
+        );
+    }
+
+    #[test]
+    fn test_limits_secret_origins_and_store_are_mapped_into_config() {
+        let args = Args::try_parse_from([
+            "server",
+            "--max-sessions",
+            "10",
+            "--max-users-per-session",
+            "4",
+            "--max-sdp-bytes",
+            "2048",
+            "--auth-secret",
+            "secret",
+            "--allowed-origins",
+            "https://example.com,*.example.org",
+            "--metrics",
+            "--session-store-dir",
+            "/var/lib/wasm-peers",
+        ])
+        .expect("failed to parse arguments");
+        let config = args.server_config().expect("failed to read config");
+        assert_eq!(
+            config.session_limits,
+            SessionLimits {
+                max_sessions: Some(10),
+                max_users_per_session: Some(4),
+            }
+        );
+        assert_eq!(
+            config.payload_limits,
+            PayloadLimits {
+                max_sdp_bytes: 2048,
+                ..PayloadLimits::default()
+            }
+        );
+        assert_eq!(config.auth_secret.as_deref(), Some("secret"));
+        assert_eq!(
+            config.allowed_origins.patterns,
+            vec!["https://example.com".to_owned(), "*.example.org".to_owned()]
+        );
+        assert!(args.metrics);
+        assert_eq!(
+            config.session_store_dir,
+            Some(PathBuf::from("/var/lib/wasm-peers"))
+        );
+    }
+
+    #[test]
+    fn test_log_level_falls_back_to_rust_log_and_then_info() {
+        let args = Args::try_parse_from(["server"]).expect("failed to parse arguments");
+        assert_eq!(args.log_level(None), LevelFilter::Info);
+        assert_eq!(args.log_level(Some("warn")), LevelFilter::Warn);
+        assert_eq!(
+            args.log_level(Some("wasm_peers_signaling_server=trace")),
+            LevelFilter::Info
+        );
+
+        let args = Args::try_parse_from(["server", "--log-level", "error"])
+            .expect("failed to parse arguments");
+        assert_eq!(args.log_level(Some("warn")), LevelFilter::Error);
+    }
+
+    #[test]
+    fn test_tls_options_require_each_other() {
+        let args = Args::try_parse_from([
+            "server",
+            "--tls-cert",
+            "cert.pem",
+            "--tls-key",
+            "key.pem",
+            "--http-redirect-port",
+            "80",
+        ])
+        .expect("failed to parse arguments");
+        let config = args.server_config().expect("failed to read config");
+        assert_eq!(config.tls_cert_path, Some(PathBuf::from("cert.pem")));
+        assert_eq!(config.tls_key_path, Some(PathBuf::from("key.pem")));
+        assert_eq!(args.http_redirect_port, Some(80));
+
+        for incomplete in [
+            &["server", "--tls-cert", "cert.pem"][..],
+            &["server", "--tls-key", "key.pem"],
+            &["server", "--http-redirect-port", "80"],
+        ] {
+            assert!(Args::try_parse_from(incomplete).is_err());
+        }
+    }
 }
@@ -1,164 +1,294 @@
 use std::collections::{HashMap, HashSet};
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::iter;
 use std::sync::Arc;
 
-use anyhow::anyhow;
-use axum::extract::ws::{Message, WebSocket};
-use futures_util::{SinkExt, StreamExt, TryFutureExt};
-use log::{error, info, warn};
-use tokio::sync::{mpsc, RwLock};
-use tokio_stream::wrappers::UnboundedReceiverStream;
+use axum::async_trait;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
 use wasm_peers_protocol::one_to_many::SignalMessage;
-use wasm_peers_protocol::{SessionId, UserId};
+use wasm_peers_protocol::{JoinIntent, SessionId, SessionInfo, TopologyType, UserId};
 
-#[derive(Default, Debug)]
+use crate::config::SessionLimits;
+use crate::hub::{self, Outgoing, SessionPolicy, SignalingHub};
+use crate::span;
+use crate::store::{self, SessionStore, StoredSession};
+
+#[derive(Default, Debug, Serialize, Deserialize)]
 pub struct Session {
     pub users: HashSet<UserId>,
+    /// Users restored from a store that haven't rejoined yet, see [`Policy::with_store`]
+    #[serde(default)]
+    pub rejoining: HashSet<UserId>,
 }
 
-pub type Connections = Arc<RwLock<HashMap<UserId, mpsc::UnboundedSender<Message>>>>;
-pub type Sessions = Arc<RwLock<HashMap<SessionId, Session>>>;
+impl Session {
+    /// Removes one of the users awaited to rejoin, whose place is taken by a rejoining user.
+    fn reclaim(&mut self) -> bool {
+        let Some(&stale_id) = self.rejoining.iter().next() else {
+            return false;
+        };
+        self.rejoining.remove(&stale_id);
+        self.users.remove(&stale_id);
+        true
+    }
+}
 
-static NEXT_USER_ID: AtomicU64 = AtomicU64::new(1);
+impl StoredSession for Session {
+    fn users(&self) -> Vec<UserId> {
+        self.users.iter().copied().collect()
+    }
 
-pub async fn user_connected(ws: WebSocket, connections: Connections, sessions: Sessions) {
-    let user_id = UserId::new(NEXT_USER_ID.fetch_add(1, Ordering::Relaxed));
-    info!("new user connected: {:?}", user_id);
+    fn rejoining_mut(&mut self) -> &mut HashSet<UserId> {
+        &mut self.rejoining
+    }
+}
 
-    let (mut user_ws_tx, mut user_ws_rx) = ws.split();
+pub type Sessions = Arc<RwLock<HashMap<SessionId, Session>>>;
 
-    let (tx, rx) = mpsc::unbounded_channel();
-    let mut rx = UnboundedReceiverStream::new(rx);
+/// Connects every user in session with each other, newcomers send offers to already present users.
+#[derive(Default)]
+pub struct Policy {
+    sessions: Sessions,
+    limits: SessionLimits,
+    store: Option<Arc<dyn SessionStore<Session>>>,
+}
 
-    tokio::task::spawn(async move {
-        while let Some(message) = rx.next().await {
-            user_ws_tx
-                .send(message)
-                .unwrap_or_else(|e| error!("websocket send error: {}", e))
-                .await;
+impl Policy {
+    /// Creates policy keeping sessions in `sessions`, e.g. to observe or pre-populate them
+    /// from the embedding application.
+    #[must_use]
+    pub fn with_sessions(sessions: Sessions) -> Self {
+        Self {
+            sessions,
+            limits: SessionLimits::default(),
+            store: None,
         }
-    });
+    }
 
-    connections.write().await.insert(user_id, tx);
+    /// Turns away users joining sessions over `limits`.
+    #[must_use]
+    pub fn with_limits(self, limits: SessionLimits) -> Self {
+        Self { limits, ..self }
+    }
 
-    while let Some(result) = user_ws_rx.next().await {
-        let msg = match result {
-            Ok(msg) => msg,
-            Err(e) => {
-                error!("websocket error (id={:?}): {}", user_id, e);
-                break;
-            }
-        };
+    /// Saves sessions to `store` after every change, restoring the ones saved before the server restarted.
+    /// Users rejoining restored sessions take over places of their previous connections.
+    ///
+    /// # Errors
+    /// Fails if sessions can't be loaded from `store`.
+    pub fn with_store(self, store: Arc<dyn SessionStore<Session>>) -> crate::Result<Self> {
+        store::restore(&*store, &self.sessions)?;
+        Ok(Self {
+            store: Some(store),
+            ..self
+        })
+    }
 
-        if let Err(err) = user_message(user_id, msg, &connections, &sessions).await {
-            error!("error while handling user message: {}", err);
-        }
+    #[must_use]
+    pub fn sessions(&self) -> &Sessions {
+        &self.sessions
     }
 
-    error!("user disconnected: {:?}", user_id);
-    user_disconnected(user_id, &connections, &sessions).await;
-}
+    /// Open sessions, as sent to users asking for them with [`SignalMessage::SessionListRequest`].
+    pub async fn session_list(&self) -> Vec<SessionInfo> {
+        hub::session_list(
+            &*store::lock(self.store.as_deref(), &self.sessions).await,
+            TopologyType::ManyToMany,
+        )
+    }
 
-async fn user_message(
-    sender_id: UserId,
-    msg: Message,
-    connections: &Connections,
-    sessions: &Sessions,
-) -> crate::Result<()> {
-    let request = rmp_serde::from_slice::<SignalMessage>(msg.into_data().as_ref())?;
-    info!("message received from user {:?}: {:?}", sender_id, request);
-    match request {
-        SignalMessage::SessionJoin(session_id, _) => {
-            let mut sessions_writer = sessions.write().await;
-            let session = sessions_writer
-                .entry(session_id)
-                .or_insert_with(Session::default);
-            let connections_reader = connections.read().await;
-
-            // start connections with all already present users
-            for client_id in &session.users {
-                {
-                    let host_response = SignalMessage::SessionReady(session_id, *client_id);
-                    let host_response = rmp_serde::to_vec(&host_response)?;
-                    connections_reader
-                        .get(&sender_id)
-                        .ok_or(anyhow!("host not in connections"))?
-                        .send(Message::Binary(host_response))?;
-                }
-            }
-            session.users.insert(sender_id);
-        }
-        // pass offer to the other user in session without changing anything
-        SignalMessage::SdpOffer(session_id, recipient_id, offer) => {
-            let response = SignalMessage::SdpOffer(session_id, sender_id, offer);
-            let response = rmp_serde::to_vec(&response)?;
-            let connections_reader = connections.read().await;
-            connections_reader
-                .get(&recipient_id)
-                .ok_or(anyhow!("tried to send offer to non existing user"))?
-                .send(Message::Binary(response))?;
-        }
-        // pass answer to the other user in session without changing anything
-        SignalMessage::SdpAnswer(session_id, recipient_id, answer) => {
-            let response = SignalMessage::SdpAnswer(session_id, sender_id, answer);
-            let response = rmp_serde::to_vec(&response)?;
-            let connections_reader = connections.read().await;
-            connections_reader
-                .get(&recipient_id)
-                .ok_or(anyhow!("tried to send answer to non existing user"))?
-                .send(Message::Binary(response))?;
-        }
-        SignalMessage::IceCandidate(session_id, recipient_id, candidate) => {
-            let response = SignalMessage::IceCandidate(session_id, sender_id, candidate);
-            let response = rmp_serde::to_vec(&response)?;
-            let connections_reader = connections.read().await;
-            connections_reader
-                .get(&recipient_id)
-                .ok_or(anyhow!("tried to send ice candidate to non existing user"))?
-                .send(Message::Binary(response))?;
-        }
-        SignalMessage::SessionReady(session_id, recipient_id) => {
-            let response = SignalMessage::SessionReady(session_id, sender_id);
-            let response = rmp_serde::to_vec(&response)?;
-            let connections_reader = connections.read().await;
-            connections_reader
-                .get(&recipient_id)
-                .ok_or(anyhow!("tried to send ready message to non existing user"))?
-                .send(Message::Binary(response))?;
+    /// Removes session `session_id` without telling its users, `None` if there's no such session.
+    pub async fn remove_session(&self, session_id: SessionId) -> Option<Session> {
+        let mut sessions = store::lock(self.store.as_deref(), &self.sessions).await;
+        let session = sessions.remove(&session_id)?;
+        store::save(self.store.as_deref(), &sessions);
+        Some(session)
+    }
+
+    async fn session_join(
+        &self,
+        sender_id: UserId,
+        session_id: SessionId,
+        intent: JoinIntent,
+    ) -> Outgoing<SignalMessage> {
+        let mut sessions_writer = store::lock(self.store.as_deref(), &self.sessions).await;
+        if let Some(error) = hub::join_error(&sessions_writer, session_id, intent) {
+            return vec![(
+                sender_id,
+                SignalMessage::SessionJoinError(session_id, error),
+            )];
         }
-        SignalMessage::Error(session_id, recipient_id, error) => {
-            warn!(
-                "error message received from user {:?}: {:?}",
-                sender_id, error
-            );
-            let response = SignalMessage::Error(session_id, sender_id, error);
-            let response = rmp_serde::to_vec(&response)?;
-            let connections_reader = connections.read().await;
-            connections_reader
-                .get(&recipient_id)
-                .ok_or(anyhow!("tried to send ready message to non existing user"))?
-                .send(Message::Binary(response))?;
+        let rejoined = sessions_writer
+            .get_mut(&session_id)
+            .is_some_and(Session::reclaim);
+        let users_count = sessions_writer
+            .get(&session_id)
+            .map_or(0, |session| session.users.len());
+        // rejoining users keep their places even if limits were lowered in the meantime
+        let rejection = self
+            .limits
+            .rejection(sessions_writer.len(), users_count)
+            .filter(|_| !rejoined);
+        if let Some(reason) = rejection {
+            let response = SignalMessage::Error(session_id, sender_id, reason.to_owned());
+            return vec![(sender_id, response)];
         }
+        let session = sessions_writer
+            .entry(session_id)
+            .or_insert_with(Session::default);
+
+        // start connections with all already present users, except for the ones yet to rejoin
+        let present: Vec<UserId> = session
+            .users
+            .difference(&session.rejoining)
+            .copied()
+            .collect();
+        // newcomer learns all members first, so that every peer elects the same leader
+        let mut members = present.clone();
+        members.push(sender_id);
+        members.sort_unstable();
+        let outgoing = iter::once(SignalMessage::SessionMembers(
+            session_id, sender_id, members,
+        ))
+        .chain(
+            present
+                .into_iter()
+                .map(|client_id| SignalMessage::SessionReady(session_id, client_id)),
+        )
+        .map(|response| (sender_id, response))
+        .collect();
+        session.users.insert(sender_id);
+        store::save(self.store.as_deref(), &sessions_writer);
+        outgoing
     }
-    Ok(())
 }
 
-async fn user_disconnected(user_id: UserId, connections: &Connections, sessions: &Sessions) {
-    connections.write().await.remove(&user_id);
+#[async_trait]
+impl SessionPolicy for Policy {
+    type Message = SignalMessage;
 
-    let mut session_to_delete = None;
-    let mut sessions = sessions.write().await;
-    for (session_id, session) in sessions.iter_mut() {
-        if session.users.contains(&user_id) {
-            session.users.remove(&user_id);
-        }
-        if session.users.is_empty() {
-            session_to_delete = Some(*session_id);
-            break;
-        }
+    const TOPOLOGY: &'static str = "many-to-many";
+
+    async fn route(
+        &self,
+        sender_id: UserId,
+        message: SignalMessage,
+    ) -> crate::Result<Outgoing<SignalMessage>> {
+        let outgoing = match message {
+            // stable ids only matter to one-to-many hosts, mesh peers join under new ids every time
+            SignalMessage::SessionJoin(session_id, _)
+            | SignalMessage::SessionJoinAs(session_id, _) => {
+                span::record_session(session_id);
+                self.session_join(sender_id, session_id, JoinIntent::CreateOrJoin)
+                    .await
+            }
+            // mesh peers are all equal, there are no hosts to allow more of
+            SignalMessage::SessionJoinWithIntent(session_id, _, intent)
+            | SignalMessage::SessionJoinHost(session_id, _, intent) => {
+                span::record_session(session_id);
+                self.session_join(sender_id, session_id, intent).await
+            }
+            // pass offer to the other user in session without changing anything
+            SignalMessage::SdpOffer(session_id, recipient_id, offer) => {
+                let response = SignalMessage::SdpOffer(session_id, sender_id, offer);
+                vec![(recipient_id, response)]
+            }
+            // pass answer to the other user in session without changing anything
+            SignalMessage::SdpAnswer(session_id, recipient_id, answer) => {
+                let response = SignalMessage::SdpAnswer(session_id, sender_id, answer);
+                vec![(recipient_id, response)]
+            }
+            SignalMessage::IceCandidate(session_id, recipient_id, candidate) => {
+                let response = SignalMessage::IceCandidate(session_id, sender_id, candidate);
+                vec![(recipient_id, response)]
+            }
+            SignalMessage::SessionReady(session_id, recipient_id) => {
+                let response = SignalMessage::SessionReady(session_id, sender_id);
+                vec![(recipient_id, response)]
+            }
+            SignalMessage::Error(session_id, recipient_id, error) => {
+                warn!("error message received from user {sender_id:?}: {error:?}");
+                let response = SignalMessage::Error(session_id, sender_id, error);
+                vec![(recipient_id, response)]
+            }
+            // answered by the hub before messages are routed
+            SignalMessage::ProtocolVersion { .. } => Vec::new(),
+            SignalMessage::SessionListRequest => vec![(
+                sender_id,
+                SignalMessage::SessionListResponse(self.session_list().await),
+            )],
+            message @ (SignalMessage::SessionLeave(..)
+            | SignalMessage::ClientIdentity(..)
+            | SignalMessage::SessionListResponse(..)
+            | SignalMessage::SessionFullNotice(..)
+            | SignalMessage::SessionMembers(..)
+            | SignalMessage::SessionJoinError(..)
+            | SignalMessage::ProtocolVersionMismatch { .. }) => {
+                warn!(
+                    "{message:?} should only be sent by signaling server, received from user {sender_id:?}"
+                );
+                Vec::new()
+            }
+        };
+        Ok(outgoing)
     }
-    // remove session if it's empty
-    if let Some(session_id) = session_to_delete {
-        sessions.remove(&session_id);
+
+    async fn on_disconnect(&self, user_id: UserId) -> Outgoing<SignalMessage> {
+        let mut sessions = store::lock(self.store.as_deref(), &self.sessions).await;
+        // user may be in several sessions over the same connection, each emptied one is removed
+        sessions.retain(|_, session| !session.users.remove(&user_id) || !session.users.is_empty());
+        store::save(self.store.as_deref(), &sessions);
+        Vec::new()
+    }
+}
+
+/// Closes session `session_id`, telling its users why with [`SignalMessage::Error`].
+/// Users stay connected to signaling server. Returns `false` if there's no such session.
+///
+/// # Errors
+/// Fails only if the message can't be serialized, users that can't be reached are just logged.
+pub async fn close_session(
+    hub: &SignalingHub<Policy>,
+    session_id: SessionId,
+    reason: &str,
+) -> crate::Result<bool> {
+    let Some(session) = hub.policy().remove_session(session_id).await else {
+        return Ok(false);
+    };
+    let outgoing = session
+        .users()
+        .into_iter()
+        .map(|user_id| {
+            (
+                user_id,
+                SignalMessage::Error(session_id, user_id, reason.to_owned()),
+            )
+        })
+        .collect();
+    hub.send(outgoing).await?;
+    Ok(true)
+}
+
+/// Disconnects `user_id` from signaling server, telling it why with [`SignalMessage::Error`] first,
+/// see [`SignalingHub::kick`]. Returns `false` if the user isn't in session `session_id`.
+///
+/// # Errors
+/// Fails only if the message can't be serialized.
+pub async fn kick_user(
+    hub: &SignalingHub<Policy>,
+    session_id: SessionId,
+    user_id: UserId,
+    reason: &str,
+) -> crate::Result<bool> {
+    let policy = hub.policy();
+    let sessions = store::lock(policy.store.as_deref(), &policy.sessions).await;
+    let is_member = hub::is_member(&sessions, session_id, user_id);
+    drop(sessions);
+    if !is_member {
+        return Ok(false);
     }
+    let message = SignalMessage::Error(session_id, user_id, reason.to_owned());
+    hub.kick(user_id, message, reason).await?;
+    Ok(true)
 }
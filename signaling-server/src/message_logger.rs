@@ -0,0 +1,416 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::anyhow;
+use log::error;
+use serde::Serialize;
+use wasm_peers_protocol::{one_to_many, one_to_one, SessionId, UserId};
+
+use crate::config::ServerConfig;
+use crate::session_audit::SessionEventType;
+
+/// Placeholder for SDP and ICE candidates, unless [`ServerConfig::log_sensitive`] is set.
+const REDACTED: &str = "[redacted]";
+
+/// Signal message that can be recorded in the audit log.
+pub trait Audited {
+    /// Name of the message variant
+    fn message_type(&self) -> &'static str;
+
+    fn session_id(&self) -> Option<SessionId>;
+
+    /// SDP or ICE candidate carried by the message, if any
+    fn sensitive_payload(&self) -> Option<String>;
+
+    /// Event recorded in the [session audit log](crate::session_audit) once the message is accepted
+    fn session_event(&self) -> Option<SessionEventType>;
+}
+
+impl Audited for one_to_one::SignalMessage {
+    fn message_type(&self) -> &'static str {
+        match *self {
+            Self::SessionJoin(..) => "SessionJoin",
+            Self::SessionReady(..) => "SessionReady",
+            Self::SdpOffer(..) => "SdpOffer",
+            Self::SdpAnswer(..) => "SdpAnswer",
+            Self::IceCandidate(..) => "IceCandidate",
+            Self::Error(..) => "Error",
+            Self::Redirect(..) => "Redirect",
+            Self::SessionListRequest => "SessionListRequest",
+            Self::SessionListResponse(..) => "SessionListResponse",
+            Self::ProtocolVersion { .. } => "ProtocolVersion",
+            Self::ProtocolVersionMismatch { .. } => "ProtocolVersionMismatch",
+            Self::SessionJoinWithIntent(..) => "SessionJoinWithIntent",
+            Self::SessionJoinError(..) => "SessionJoinError",
+        }
+    }
+
+    fn session_id(&self) -> Option<SessionId> {
+        match *self {
+            Self::SessionJoin(session_id)
+            | Self::SessionReady(session_id, _)
+            | Self::SdpOffer(session_id, _)
+            | Self::SdpAnswer(session_id, _)
+            | Self::IceCandidate(session_id, _)
+            | Self::Error(session_id, _)
+            | Self::SessionJoinWithIntent(session_id, _)
+            | Self::SessionJoinError(session_id, _) => Some(session_id),
+            Self::Redirect(_)
+            | Self::SessionListRequest
+            | Self::SessionListResponse(_)
+            | Self::ProtocolVersion { .. }
+            | Self::ProtocolVersionMismatch { .. } => None,
+        }
+    }
+
+    fn sensitive_payload(&self) -> Option<String> {
+        match *self {
+            Self::SdpOffer(_, ref sdp) | Self::SdpAnswer(_, ref sdp) => Some(sdp.clone()),
+            Self::IceCandidate(_, ref candidate) => Some(candidate.candidate.clone()),
+            _ => None,
+        }
+    }
+
+    fn session_event(&self) -> Option<SessionEventType> {
+        match *self {
+            Self::SessionJoin(..) | Self::SessionJoinWithIntent(..) => Some(SessionEventType::Join),
+            Self::SdpOffer(..) => Some(SessionEventType::Offer),
+            Self::SdpAnswer(..) => Some(SessionEventType::Answer),
+            Self::IceCandidate(..) => Some(SessionEventType::Ice),
+            _ => None,
+        }
+    }
+}
+
+impl Audited for one_to_many::SignalMessage {
+    fn message_type(&self) -> &'static str {
+        match *self {
+            Self::SessionJoin(..) => "SessionJoin",
+            Self::SessionReady(..) => "SessionReady",
+            Self::SdpOffer(..) => "SdpOffer",
+            Self::SdpAnswer(..) => "SdpAnswer",
+            Self::IceCandidate(..) => "IceCandidate",
+            Self::Error(..) => "Error",
+            Self::SessionLeave(..) => "SessionLeave",
+            Self::SessionJoinAs(..) => "SessionJoinAs",
+            Self::ClientIdentity(..) => "ClientIdentity",
+            Self::SessionListRequest => "SessionListRequest",
+            Self::SessionListResponse(..) => "SessionListResponse",
+            Self::SessionFullNotice(..) => "SessionFullNotice",
+            Self::SessionMembers(..) => "SessionMembers",
+            Self::ProtocolVersion { .. } => "ProtocolVersion",
+            Self::ProtocolVersionMismatch { .. } => "ProtocolVersionMismatch",
+            Self::SessionJoinWithIntent(..) => "SessionJoinWithIntent",
+            Self::SessionJoinError(..) => "SessionJoinError",
+            Self::SessionJoinHost(..) => "SessionJoinHost",
+        }
+    }
+
+    fn session_id(&self) -> Option<SessionId> {
+        match *self {
+            Self::SessionJoin(session_id, _)
+            | Self::SessionReady(session_id, _)
+            | Self::SdpOffer(session_id, _, _)
+            | Self::SdpAnswer(session_id, _, _)
+            | Self::IceCandidate(session_id, _, _)
+            | Self::Error(session_id, _, _)
+            | Self::SessionLeave(session_id, _)
+            | Self::SessionJoinAs(session_id, _)
+            | Self::ClientIdentity(session_id, _, _)
+            | Self::SessionFullNotice(session_id, _)
+            | Self::SessionMembers(session_id, _, _)
+            | Self::SessionJoinWithIntent(session_id, _, _)
+            | Self::SessionJoinError(session_id, _)
+            | Self::SessionJoinHost(session_id, _, _) => Some(session_id),
+            Self::SessionListRequest
+            | Self::SessionListResponse(_)
+            | Self::ProtocolVersion { .. }
+            | Self::ProtocolVersionMismatch { .. } => None,
+        }
+    }
+
+    fn sensitive_payload(&self) -> Option<String> {
+        match *self {
+            Self::SdpOffer(_, _, ref sdp) | Self::SdpAnswer(_, _, ref sdp) => Some(sdp.clone()),
+            Self::IceCandidate(_, _, ref candidate) => Some(candidate.candidate.clone()),
+            _ => None,
+        }
+    }
+
+    fn session_event(&self) -> Option<SessionEventType> {
+        match *self {
+            Self::SessionJoin(..)
+            | Self::SessionJoinAs(..)
+            | Self::SessionJoinWithIntent(..)
+            | Self::SessionJoinHost(..) => Some(SessionEventType::Join),
+            Self::SdpOffer(..) => Some(SessionEventType::Offer),
+            Self::SdpAnswer(..) => Some(SessionEventType::Answer),
+            Self::IceCandidate(..) => Some(SessionEventType::Ice),
+            _ => None,
+        }
+    }
+}
+
+/// Single line of the audit log.
+#[derive(Debug, Serialize)]
+struct AuditEntry {
+    /// Milliseconds since UNIX epoch
+    timestamp: u64,
+    user_id: u64,
+    /// Session ids don't fit into JSON numbers, so they're written as strings
+    session_id: Option<String>,
+    message_type: &'static str,
+    payload_bytes: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    payload: Option<String>,
+}
+
+#[derive(Debug)]
+struct LogFile {
+    file: File,
+    size: u64,
+}
+
+/// Writes every signal message received from users as a JSON line to the audit log file,
+/// to help debug connection issues.
+///
+/// Once the file would grow beyond [`ServerConfig::audit_log_max_bytes`],
+/// it's moved to the same path with `.1` appended, replacing the previous one, and a new file is started.
+#[derive(Debug)]
+pub struct MessageLogger {
+    path: PathBuf,
+    max_bytes: u64,
+    log_sensitive: bool,
+    log_file: Mutex<LogFile>,
+}
+
+impl MessageLogger {
+    /// Opens audit log file for appending, if [`ServerConfig::audit_log_path`] is set.
+    ///
+    /// # Errors
+    /// Fails if the file can't be opened.
+    pub fn open(config: &ServerConfig) -> crate::Result<Option<Self>> {
+        let Some(ref path) = config.audit_log_path else {
+            return Ok(None);
+        };
+        Ok(Some(Self {
+            path: path.clone(),
+            max_bytes: config.audit_log_max_bytes,
+            log_sensitive: config.log_sensitive,
+            log_file: Mutex::new(open_log_file(path)?),
+        }))
+    }
+
+    /// Records `message` received from `user_id`, `payload_bytes` long.
+    /// Failures are only reported with `log::error`, so they don't break signaling.
+    pub fn log(&self, user_id: UserId, message: &impl Audited, payload_bytes: usize) {
+        let entry = AuditEntry {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |duration| {
+                    u64::try_from(duration.as_millis()).unwrap_or(u64::MAX)
+                }),
+            user_id: user_id.into_inner(),
+            session_id: message
+                .session_id()
+                .map(|session_id| session_id.inner().to_string()),
+            message_type: message.message_type(),
+            payload_bytes,
+            payload: message.sensitive_payload().map(|payload| {
+                if self.log_sensitive {
+                    payload
+                } else {
+                    REDACTED.to_owned()
+                }
+            }),
+        };
+        if let Err(err) = self.write(&entry) {
+            error!("failed to write audit log entry: {err}");
+        }
+    }
+
+    fn write(&self, entry: &AuditEntry) -> crate::Result<()> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        let line_len = u64::try_from(line.len())?;
+
+        let mut log_file = self
+            .log_file
+            .lock()
+            .map_err(|err| anyhow!("audit log lock poisoned: {err}"))?;
+        if log_file.size > 0 && log_file.size.saturating_add(line_len) > self.max_bytes {
+            self.rotate(&mut log_file)?;
+        }
+        log_file.file.write_all(&line)?;
+        log_file.size = log_file.size.saturating_add(line_len);
+        Ok(())
+    }
+
+    fn rotate(&self, log_file: &mut LogFile) -> crate::Result<()> {
+        let mut rotated_path = self.path.clone().into_os_string();
+        rotated_path.push(".1");
+        fs::rename(&self.path, rotated_path)?;
+        *log_file = open_log_file(&self.path)?;
+        Ok(())
+    }
+}
+
+fn open_log_file(path: &Path) -> crate::Result<LogFile> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let size = file.metadata()?.len();
+    Ok(LogFile { file, size })
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+    use wasm_peers_protocol::IceCandidate;
+
+    use super::*;
+
+    fn logger(name: &str, max_bytes: u64, log_sensitive: bool) -> (MessageLogger, PathBuf) {
+        let path = std::env::temp_dir().join(format!(
+            "wasm-peers-audit-{}-{}.log",
+            name,
+            std::process::id()
+        ));
+        let _result = fs::remove_file(&path);
+        let config = ServerConfig {
+            audit_log_path: Some(path.clone()),
+            audit_log_max_bytes: max_bytes,
+            log_sensitive,
+            ..ServerConfig::default()
+        };
+        let logger = MessageLogger::open(&config)
+            .expect("failed to open audit log")
+            .expect("audit log path is set");
+        (logger, path)
+    }
+
+    /// Reads entries without timestamps, which can't be compared.
+    fn read_entries(path: &Path) -> Vec<serde_json::Value> {
+        fs::read_to_string(path)
+            .expect("failed to read audit log")
+            .lines()
+            .map(|line| {
+                let mut entry: serde_json::Value =
+                    serde_json::from_str(line).expect("invalid audit log line");
+                entry
+                    .as_object_mut()
+                    .expect("audit log entry is not an object")
+                    .remove("timestamp")
+                    .expect("audit log entry has no timestamp");
+                entry
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_sensitive_fields_are_redacted() {
+        let (logger, path) = logger("redacted", u64::MAX, false);
+        let session_id = SessionId::new(u128::MAX);
+        logger.log(
+            UserId::new(1),
+            &one_to_one::SignalMessage::SdpOffer(session_id, "v=0 secret".to_owned()),
+            42,
+        );
+        logger.log(
+            UserId::new(2),
+            &one_to_many::SignalMessage::IceCandidate(
+                session_id,
+                UserId::new(1),
+                IceCandidate {
+                    candidate: "candidate:1 1 udp 1 192.168.1.2 54321 typ host".to_owned(),
+                    sdp_mid: None,
+                    sdp_m_line_index: None,
+                },
+            ),
+            24,
+        );
+
+        assert_eq!(
+            read_entries(&path),
+            vec![
+                json!({
+                    "user_id": 1,
+                    "session_id": u128::MAX.to_string(),
+                    "message_type": "SdpOffer",
+                    "payload_bytes": 42,
+                    "payload": REDACTED,
+                }),
+                json!({
+                    "user_id": 2,
+                    "session_id": u128::MAX.to_string(),
+                    "message_type": "IceCandidate",
+                    "payload_bytes": 24,
+                    "payload": REDACTED,
+                }),
+            ]
+        );
+        fs::remove_file(path).expect("failed to remove audit log");
+    }
+
+    #[test]
+    fn test_sensitive_fields_are_logged_if_enabled() {
+        let (logger, path) = logger("sensitive", u64::MAX, true);
+        logger.log(
+            UserId::new(1),
+            &one_to_one::SignalMessage::SdpAnswer(SessionId::new(1), "v=0 secret".to_owned()),
+            42,
+        );
+        logger.log(
+            UserId::new(1),
+            &one_to_one::SignalMessage::SessionJoin(SessionId::new(1)),
+            8,
+        );
+
+        assert_eq!(
+            read_entries(&path),
+            vec![
+                json!({
+                    "user_id": 1,
+                    "session_id": "1",
+                    "message_type": "SdpAnswer",
+                    "payload_bytes": 42,
+                    "payload": "v=0 secret",
+                }),
+                json!({
+                    "user_id": 1,
+                    "session_id": "1",
+                    "message_type": "SessionJoin",
+                    "payload_bytes": 8,
+                }),
+            ]
+        );
+        fs::remove_file(path).expect("failed to remove audit log");
+    }
+
+    #[test]
+    fn test_log_is_rotated_once_too_big() {
+        let (logger, path) = logger("rotated", 200, false);
+        for user_id in 0..5 {
+            logger.log(
+                UserId::new(user_id),
+                &one_to_one::SignalMessage::SessionJoin(SessionId::new(1)),
+                8,
+            );
+        }
+
+        let mut rotated_path = path.clone().into_os_string();
+        rotated_path.push(".1");
+        let rotated_entries = read_entries(Path::new(&rotated_path));
+        let entries = read_entries(&path);
+        assert!(fs::metadata(&path).expect("no audit log").len() <= 200);
+        assert_eq!(
+            entries.last().and_then(|entry| entry.get("user_id")),
+            Some(&json!(4)),
+            "latest entry should be in the current file"
+        );
+        assert!(rotated_entries.len() + entries.len() <= 5);
+        fs::remove_file(path).expect("failed to remove audit log");
+        fs::remove_file(rotated_path).expect("failed to remove rotated audit log");
+    }
+}
@@ -1,163 +1,556 @@
-use std::collections::{HashMap, HashSet};
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::iter;
 use std::sync::Arc;
 
-use anyhow::anyhow;
-use axum::extract::ws::{Message, WebSocket};
-use futures_util::{SinkExt, StreamExt, TryFutureExt};
-use log::{error, info};
-use tokio::sync::{mpsc, RwLock};
-use tokio_stream::wrappers::UnboundedReceiverStream;
+use axum::async_trait;
+use log::{error, info, warn};
+use serde::{Deserialize, Deserializer, Serialize};
+use tokio::sync::RwLock;
 use wasm_peers_protocol::one_to_many::SignalMessage;
-use wasm_peers_protocol::{SessionId, UserId};
+use wasm_peers_protocol::{JoinIntent, SessionId, SessionInfo, StableId, TopologyType, UserId};
 
-#[derive(Default, Debug)]
+use crate::config::SessionLimits;
+use crate::hub::{self, Outgoing, SessionPolicy, SignalingHub};
+use crate::span;
+use crate::store::{self, SessionStore, StoredSession};
+
+/// Error sent to clients turned away from a session whose host has as many clients as it accepts.
+pub const SESSION_FULL_REASON: &str = "session is full";
+
+#[derive(Default, Debug, Serialize, Deserialize)]
 pub struct Session {
-    pub host: Option<UserId>,
+    /// Connected with every client, there's more than one only if the first host allowed it
+    #[serde(alias = "host", deserialize_with = "deserialize_hosts")]
+    pub hosts: HashSet<UserId>,
+    /// Set by the first host with [`SignalMessage::SessionJoinHost`], further hosts are refused otherwise
+    #[serde(default)]
+    pub multi_host: bool,
     pub users: HashSet<UserId>,
+    /// Users waiting for a host to join, in the order they joined
+    #[serde(default)]
+    pub pending_users: VecDeque<UserId>,
+    /// Users restored from a store that haven't rejoined yet, see [`Policy::with_store`]
+    #[serde(default)]
+    pub rejoining: HashSet<UserId>,
+    /// Clients that joined with a stable id, kept after they leave so that they can rejoin under it
+    #[serde(default)]
+    pub stable_ids: HashMap<StableId, UserId>,
+    /// Set once the host turned a client away with [`SignalMessage::SessionFullNotice`],
+    /// further clients are turned away by the server until one of the clients leaves
+    #[serde(default)]
+    pub full: bool,
 }
 
-pub type Connections = Arc<RwLock<HashMap<UserId, mpsc::UnboundedSender<Message>>>>;
-pub type Sessions = Arc<RwLock<HashMap<SessionId, Session>>>;
+impl Session {
+    /// Hosts, except for the ones yet to rejoin.
+    fn present_hosts(&self) -> Vec<UserId> {
+        self.hosts.difference(&self.rejoining).copied().collect()
+    }
 
-static NEXT_USER_ID: AtomicU64 = AtomicU64::new(1);
+    /// Clients, except for the ones yet to rejoin.
+    fn present_clients(&self) -> Vec<UserId> {
+        self.users.difference(&self.rejoining).copied().collect()
+    }
 
-pub async fn user_connected(ws: WebSocket, connections: Connections, sessions: Sessions) {
-    let user_id = UserId::new(NEXT_USER_ID.fetch_add(1, Ordering::Relaxed));
-    info!("new user connected: {:?}", user_id);
+    /// Removes one of the hosts or clients awaited to rejoin, whose place is taken by a rejoining user.
+    fn reclaim(&mut self, is_host: bool) -> bool {
+        if is_host {
+            let Some(&stale_id) = self.hosts.intersection(&self.rejoining).next() else {
+                return false;
+            };
+            self.rejoining.remove(&stale_id);
+            self.hosts.remove(&stale_id);
+            return true;
+        }
+        // clients with stable ids can only be taken over by their own rejoins
+        let Some(&stale_id) = self.users.iter().find(|&&user_id| {
+            self.rejoining.contains(&user_id) && self.stable_id_of(user_id).is_none()
+        }) else {
+            return false;
+        };
+        self.rejoining.remove(&stale_id);
+        self.users.remove(&stale_id);
+        true
+    }
 
-    let (mut user_ws_tx, mut user_ws_rx) = ws.split();
+    /// Removes client that previously joined with `stable_id` if it's yet to rejoin,
+    /// telling the hosts that it left, as they may still be connected with it.
+    ///
+    /// Fails if that client is still connected, as no two clients can share a stable id.
+    fn reclaim_stable_id(
+        &mut self,
+        session_id: SessionId,
+        stable_id: StableId,
+        outgoing: &mut Outgoing<SignalMessage>,
+    ) -> Result<bool, &'static str> {
+        let previous_id = match self.stable_ids.get(&stable_id) {
+            Some(&user_id) if self.users.contains(&user_id) => user_id,
+            // it's either new or its previous connection already left
+            _ => return Ok(false),
+        };
+        if !self.rejoining.remove(&previous_id) {
+            return Err("stable id is used by another client");
+        }
+        self.users.remove(&previous_id);
+        outgoing.extend(self.present_hosts().into_iter().map(|host_id| {
+            (
+                host_id,
+                SignalMessage::SessionLeave(session_id, previous_id),
+            )
+        }));
+        Ok(true)
+    }
 
-    let (tx, rx) = mpsc::unbounded_channel();
-    let mut rx = UnboundedReceiverStream::new(rx);
+    fn stable_id_of(&self, user_id: UserId) -> Option<StableId> {
+        self.stable_ids
+            .iter()
+            .find(|&(_, &holder_id)| holder_id == user_id)
+            .map(|(&stable_id, _)| stable_id)
+    }
+
+    /// Tells both the host and the client about each other, after which the host sends an offer.
+    /// Host learns client's stable id first, if it has one.
+    fn connect(
+        &self,
+        session_id: SessionId,
+        host_id: UserId,
+        client_id: UserId,
+    ) -> Outgoing<SignalMessage> {
+        let identity = self.stable_id_of(client_id).map(|stable_id| {
+            (
+                host_id,
+                SignalMessage::ClientIdentity(session_id, client_id, stable_id),
+            )
+        });
+        identity
+            .into_iter()
+            .chain([
+                (host_id, SignalMessage::SessionReady(session_id, client_id)),
+                (client_id, SignalMessage::SessionReady(session_id, host_id)),
+            ])
+            .collect()
+    }
+}
+
+impl StoredSession for Session {
+    fn users(&self) -> Vec<UserId> {
+        self.hosts.iter().chain(&self.users).copied().collect()
+    }
+
+    fn rejoining_mut(&mut self) -> &mut HashSet<UserId> {
+        &mut self.rejoining
+    }
 
-    tokio::task::spawn(async move {
-        while let Some(message) = rx.next().await {
-            user_ws_tx
-                .send(message)
-                .unwrap_or_else(|e| error!("websocket send error: {}", e))
-                .await;
+    fn forget_connections(&mut self) {
+        self.pending_users.clear();
+        self.full = false;
+    }
+}
+
+/// Reads hosts saved either as a set, or as the single host saved before sessions could have more of them.
+fn deserialize_hosts<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<HashSet<UserId>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Hosts {
+        Many(HashSet<UserId>),
+        Single(Option<UserId>),
+    }
+
+    Ok(match Hosts::deserialize(deserializer)? {
+        Hosts::Many(hosts) => hosts,
+        Hosts::Single(host) => host.into_iter().collect(),
+    })
+}
+
+pub type Sessions = Arc<RwLock<HashMap<SessionId, Session>>>;
+
+/// Connects hosts with every client in session, clients joining before any host wait for one.
+/// Session has a single host, unless its first host allowed more with [`SignalMessage::SessionJoinHost`].
+#[derive(Default)]
+pub struct Policy {
+    sessions: Sessions,
+    limits: SessionLimits,
+    store: Option<Arc<dyn SessionStore<Session>>>,
+}
+
+impl Policy {
+    /// Creates policy keeping sessions in `sessions`, e.g. to observe or pre-populate them
+    /// from the embedding application.
+    #[must_use]
+    pub fn with_sessions(sessions: Sessions) -> Self {
+        Self {
+            sessions,
+            limits: SessionLimits::default(),
+            store: None,
         }
-    });
+    }
 
-    connections.write().await.insert(user_id, tx);
+    /// Turns away users joining sessions over `limits`.
+    #[must_use]
+    pub fn with_limits(self, limits: SessionLimits) -> Self {
+        Self { limits, ..self }
+    }
+
+    /// Saves sessions to `store` after every change, restoring the ones saved before the server restarted.
+    /// Users rejoining restored sessions take over places of their previous connections,
+    /// clients wait for the host to rejoin.
+    ///
+    /// # Errors
+    /// Fails if sessions can't be loaded from `store`.
+    pub fn with_store(self, store: Arc<dyn SessionStore<Session>>) -> crate::Result<Self> {
+        store::restore(&*store, &self.sessions)?;
+        Ok(Self {
+            store: Some(store),
+            ..self
+        })
+    }
+
+    #[must_use]
+    pub fn sessions(&self) -> &Sessions {
+        &self.sessions
+    }
 
-    while let Some(result) = user_ws_rx.next().await {
-        let msg = match result {
-            Ok(msg) => msg,
-            Err(e) => {
-                error!("websocket error (id={:?}): {}", user_id, e);
-                break;
+    /// Open sessions, as sent to users asking for them with [`SignalMessage::SessionListRequest`].
+    pub async fn session_list(&self) -> Vec<SessionInfo> {
+        hub::session_list(
+            &*store::lock(self.store.as_deref(), &self.sessions).await,
+            TopologyType::OneToMany,
+        )
+    }
+
+    /// Removes session `session_id` without telling its users, `None` if there's no such session.
+    pub async fn remove_session(&self, session_id: SessionId) -> Option<Session> {
+        let mut sessions = store::lock(self.store.as_deref(), &self.sessions).await;
+        let session = sessions.remove(&session_id)?;
+        store::save(self.store.as_deref(), &sessions);
+        Some(session)
+    }
+
+    /// Connects joining client with every host, or queues it until a host joins.
+    /// Both the host and the client are told about each other with [`SignalMessage::SessionReady`],
+    /// after which the host sends an offer.
+    ///
+    /// Client joining with a `stable_id` takes over the place of the one that previously joined with it.
+    /// Session exists once anyone joined it, even if it's only clients waiting for the host.
+    /// First host decides with `allow_multi_host` whether further hosts can join.
+    async fn session_join(
+        &self,
+        sender_id: UserId,
+        session_id: SessionId,
+        is_host: bool,
+        stable_id: Option<StableId>,
+        intent: JoinIntent,
+        allow_multi_host: bool,
+    ) -> Outgoing<SignalMessage> {
+        let mut sessions_writer = store::lock(self.store.as_deref(), &self.sessions).await;
+        if let Some(error) = hub::join_error(&sessions_writer, session_id, intent) {
+            return vec![(
+                sender_id,
+                SignalMessage::SessionJoinError(session_id, error),
+            )];
+        }
+        let mut outgoing = Vec::new();
+        let reclaimed = match (sessions_writer.get_mut(&session_id), stable_id) {
+            (Some(session), Some(stable_id)) => {
+                session.reclaim_stable_id(session_id, stable_id, &mut outgoing)
             }
+            (Some(session), None) => Ok(session.reclaim(is_host)),
+            (None, _) => Ok(false),
         };
+        let rejoined = match reclaimed {
+            Ok(rejoined) => rejoined,
+            Err(reason) => {
+                let response = SignalMessage::Error(session_id, sender_id, reason.to_owned());
+                return vec![(sender_id, response)];
+            }
+        };
+        let users_count = sessions_writer.get(&session_id).map_or(0, |session| {
+            session.hosts.len().saturating_add(session.users.len())
+        });
+        // rejoining users keep their places even if limits were lowered in the meantime
+        let rejection = self
+            .limits
+            .rejection(sessions_writer.len(), users_count)
+            .filter(|_| !rejoined);
+        if let Some(reason) = rejection {
+            let response = SignalMessage::Error(session_id, sender_id, reason.to_owned());
+            return vec![(sender_id, response)];
+        }
+        let full = sessions_writer
+            .get(&session_id)
+            .is_some_and(|session| session.full);
+        if full && !is_host && !rejoined {
+            let response =
+                SignalMessage::Error(session_id, sender_id, SESSION_FULL_REASON.to_owned());
+            return vec![(sender_id, response)];
+        }
+        let session = sessions_writer
+            .entry(session_id)
+            .or_insert_with(Session::default);
+        if let Some(stable_id) = stable_id {
+            session.stable_ids.insert(stable_id, sender_id);
+        }
 
-        if let Err(err) = user_message(user_id, msg, &connections, &sessions).await {
-            error!("error while handling user message: {}", err);
+        if is_host && (session.hosts.is_empty() || session.multi_host) {
+            if session.hosts.is_empty() {
+                session.multi_host = allow_multi_host;
+            } else {
+                info!("user {sender_id:?} joins session {session_id:?} as another host");
+            }
+            let clients: Vec<UserId> = if session.present_hosts().is_empty() {
+                // start connections with all users that joined before the host, in the order they joined
+                session.pending_users.drain(..).collect()
+            } else {
+                session.present_clients()
+            };
+            session.hosts.insert(sender_id);
+            for client_id in clients {
+                outgoing.extend(session.connect(session_id, sender_id, client_id));
+            }
+        } else if is_host {
+            error!("connecting user wants to be a host, but host is already present!");
+            // TODO: proceed with connecting user as a normal user
+        } else {
+            session.users.insert(sender_id);
+            let hosts = session.present_hosts();
+            if hosts.is_empty() {
+                info!("user {sender_id:?} waits for host of session {session_id:?}");
+                session.pending_users.push_back(sender_id);
+            }
+            for host_id in hosts {
+                outgoing.extend(session.connect(session_id, host_id, sender_id));
+            }
         }
+        store::save(self.store.as_deref(), &sessions_writer);
+        outgoing
     }
 
-    error!("user disconnected: {:?}", user_id);
-    user_disconnected(user_id, &connections, &sessions).await;
+    /// Removes client that one of the hosts of session `session_id` turned away, telling it why
+    /// and the other hosts that it left, and turns away further clients until one of the remaining ones leaves.
+    /// Notices from users other than the hosts are ignored.
+    async fn session_full(
+        &self,
+        sender_id: UserId,
+        session_id: SessionId,
+        client_id: UserId,
+    ) -> Outgoing<SignalMessage> {
+        let mut sessions = store::lock(self.store.as_deref(), &self.sessions).await;
+        let Some(session) = sessions.get_mut(&session_id) else {
+            return Vec::new();
+        };
+        if !session.hosts.contains(&sender_id) {
+            warn!("user {sender_id:?} isn't host of session {session_id:?}, ignoring its SessionFullNotice");
+            return Vec::new();
+        }
+        if !session.users.remove(&client_id) {
+            return Vec::new();
+        }
+        info!("user {client_id:?} turned away from full session {session_id:?}");
+        session
+            .pending_users
+            .retain(|&pending_id| pending_id != client_id);
+        session
+            .stable_ids
+            .retain(|_, &mut holder_id| holder_id != client_id);
+        session.full = true;
+        let other_hosts = session
+            .present_hosts()
+            .into_iter()
+            .filter(|&host_id| host_id != sender_id)
+            .map(|host_id| (host_id, SignalMessage::SessionLeave(session_id, client_id)));
+        let response = SignalMessage::Error(session_id, client_id, SESSION_FULL_REASON.to_owned());
+        let outgoing = iter::once((client_id, response))
+            .chain(other_hosts)
+            .collect();
+        store::save(self.store.as_deref(), &sessions);
+        outgoing
+    }
 }
 
-async fn user_message(
-    sender_id: UserId,
-    msg: Message,
-    connections: &Connections,
-    sessions: &Sessions,
-) -> crate::Result<()> {
-    let request = rmp_serde::from_slice::<SignalMessage>(msg.into_data().as_ref())?;
-    info!("message received from user {:?}: {:?}", sender_id, request);
-    match request {
-        SignalMessage::SessionJoin(session_id, is_host) => {
-            let mut sessions_writer = sessions.write().await;
-            let session = sessions_writer
-                .entry(session_id)
-                .or_insert_with(Session::default);
-            let connections_reader = connections.read().await;
-
-            if is_host && session.host.is_none() {
-                session.host = Some(sender_id);
-                // start connections with all already present users
-                for client_id in &session.users {
-                    {
-                        let host_response = SignalMessage::SessionReady(session_id, *client_id);
-                        let host_response = rmp_serde::to_vec(&host_response)?;
-                        connections_reader
-                            .get(&sender_id)
-                            .ok_or(anyhow!("host not in connections"))?
-                            .send(Message::Binary(host_response))?;
-                    }
+#[async_trait]
+impl SessionPolicy for Policy {
+    type Message = SignalMessage;
+
+    const TOPOLOGY: &'static str = "one-to-many";
+
+    async fn route(
+        &self,
+        sender_id: UserId,
+        message: SignalMessage,
+    ) -> crate::Result<Outgoing<SignalMessage>> {
+        let outgoing = match message {
+            SignalMessage::SessionJoin(session_id, is_host) => {
+                span::record_session(session_id);
+                self.session_join(
+                    sender_id,
+                    session_id,
+                    is_host,
+                    None,
+                    JoinIntent::CreateOrJoin,
+                    false,
+                )
+                .await
+            }
+            SignalMessage::SessionJoinWithIntent(session_id, is_host, intent) => {
+                span::record_session(session_id);
+                self.session_join(sender_id, session_id, is_host, None, intent, false)
+                    .await
+            }
+            SignalMessage::SessionJoinHost(session_id, allow_multi_host, intent) => {
+                span::record_session(session_id);
+                self.session_join(sender_id, session_id, true, None, intent, allow_multi_host)
+                    .await
+            }
+            SignalMessage::SessionJoinAs(session_id, stable_id) => {
+                span::record_session(session_id);
+                self.session_join(
+                    sender_id,
+                    session_id,
+                    false,
+                    Some(stable_id),
+                    JoinIntent::CreateOrJoin,
+                    false,
+                )
+                .await
+            }
+            // pass offer to the other user in session without changing anything
+            SignalMessage::SdpOffer(session_id, recipient_id, offer) => {
+                let response = SignalMessage::SdpOffer(session_id, sender_id, offer);
+                vec![(recipient_id, response)]
+            }
+            // pass answer to the other user in session without changing anything
+            SignalMessage::SdpAnswer(session_id, recipient_id, answer) => {
+                let response = SignalMessage::SdpAnswer(session_id, sender_id, answer);
+                vec![(recipient_id, response)]
+            }
+            SignalMessage::IceCandidate(session_id, recipient_id, candidate) => {
+                let response = SignalMessage::IceCandidate(session_id, sender_id, candidate);
+                vec![(recipient_id, response)]
+            }
+            SignalMessage::SessionListRequest => vec![(
+                sender_id,
+                SignalMessage::SessionListResponse(self.session_list().await),
+            )],
+            SignalMessage::SessionFullNotice(session_id, client_id) => {
+                self.session_full(sender_id, session_id, client_id).await
+            }
+            _ => Vec::new(),
+        };
+        Ok(outgoing)
+    }
+
+    async fn on_disconnect(&self, user_id: UserId) -> Outgoing<SignalMessage> {
+        let mut sessions = store::lock(self.store.as_deref(), &self.sessions).await;
+        let mut sessions_to_delete = Vec::new();
+        let mut outgoing = Vec::new();
+        // user may be in several sessions over the same connection
+        for (session_id, session) in sessions.iter_mut() {
+            if session.hosts.remove(&user_id) {
+                let clients = session.present_clients();
+                outgoing.extend(clients.iter().map(|&client_id| {
+                    (client_id, SignalMessage::SessionLeave(*session_id, user_id))
+                }));
+                if session.present_hosts().is_empty() {
+                    // next host may accept more clients
+                    session.full = false;
+                    // remaining users are connected with the next host once it joins
+                    session.pending_users = clients.into();
                 }
-            } else if is_host && session.host.is_some() {
-                error!("connecting user wants to be a host, but host is already present!");
-                // TODO: proceed with connecting user as a normal user
-            } else {
-                // connect new user with host
-                session.users.insert(sender_id);
-
-                // TODO: wait for host instead of ignoring connecting users
-                if let Some(host_id) = session.host {
-                    let host_response = SignalMessage::SessionReady(session_id, sender_id);
-                    let host_response = rmp_serde::to_vec(&host_response)?;
-                    connections_reader
-                        .get(&host_id)
-                        .ok_or(anyhow!("host not in connections"))?
-                        .send(Message::Binary(host_response))?;
+                if session.hosts.is_empty() {
+                    // next host decides whether others can join
+                    session.multi_host = false;
                 }
+            } else if session.users.remove(&user_id) {
+                session.full = false;
+                session
+                    .pending_users
+                    .retain(|&pending_id| pending_id != user_id);
+                outgoing.extend(
+                    session.present_hosts().into_iter().map(|host_id| {
+                        (host_id, SignalMessage::SessionLeave(*session_id, user_id))
+                    }),
+                );
+            } else {
+                continue;
+            }
+            if session.hosts.is_empty() && session.users.is_empty() {
+                sessions_to_delete.push(*session_id);
             }
         }
-        // pass offer to the other user in session without changing anything
-        SignalMessage::SdpOffer(session_id, recipient_id, offer) => {
-            let response = SignalMessage::SdpOffer(session_id, sender_id, offer);
-            let response = rmp_serde::to_vec(&response)?;
-            let connections_reader = connections.read().await;
-            connections_reader
-                .get(&recipient_id)
-                .ok_or(anyhow!("tried to send offer to non existing user"))?
-                .send(Message::Binary(response))?;
-        }
-        // pass answer to the other user in session without changing anything
-        SignalMessage::SdpAnswer(session_id, recipient_id, answer) => {
-            let response = SignalMessage::SdpAnswer(session_id, sender_id, answer);
-            let response = rmp_serde::to_vec(&response)?;
-            let connections_reader = connections.read().await;
-            connections_reader
-                .get(&recipient_id)
-                .ok_or(anyhow!("tried to send answer to non existing user"))?
-                .send(Message::Binary(response))?;
-        }
-        SignalMessage::IceCandidate(session_id, recipient_id, candidate) => {
-            let response = SignalMessage::IceCandidate(session_id, sender_id, candidate);
-            let response = rmp_serde::to_vec(&response)?;
-            let connections_reader = connections.read().await;
-            connections_reader
-                .get(&recipient_id)
-                .ok_or_else(|| anyhow!("no sender for given id"))?
-                .send(Message::Binary(response))?;
+        // remove sessions left empty
+        for session_id in sessions_to_delete {
+            sessions.remove(&session_id);
         }
-        _ => {}
+        store::save(self.store.as_deref(), &sessions);
+        outgoing
     }
-    Ok(())
 }
 
-async fn user_disconnected(user_id: UserId, connections: &Connections, sessions: &Sessions) {
-    connections.write().await.remove(&user_id);
+/// Closes session `session_id`, telling its users why with [`SignalMessage::Error`].
+/// Users stay connected to signaling server. Returns `false` if there's no such session.
+///
+/// # Errors
+/// Fails only if the message can't be serialized, users that can't be reached are just logged.
+pub async fn close_session(
+    hub: &SignalingHub<Policy>,
+    session_id: SessionId,
+    reason: &str,
+) -> crate::Result<bool> {
+    let Some(session) = hub.policy().remove_session(session_id).await else {
+        return Ok(false);
+    };
+    let outgoing = session
+        .users()
+        .into_iter()
+        .map(|user_id| {
+            (
+                user_id,
+                SignalMessage::Error(session_id, user_id, reason.to_owned()),
+            )
+        })
+        .collect();
+    hub.send(outgoing).await?;
+    Ok(true)
+}
 
-    let mut session_to_delete = None;
-    for (session_id, session) in sessions.write().await.iter_mut() {
-        if session.host == Some(user_id) {
-            session.host = None;
-        } else if session.users.contains(&user_id) {
-            session.users.remove(&user_id);
-        }
-        if session.host.is_none() && session.users.is_empty() {
-            session_to_delete = Some(*session_id);
-            break;
-        }
+/// Disconnects `user_id` from signaling server, telling it why with [`SignalMessage::Error`] first,
+/// see [`SignalingHub::kick`]. Returns `false` if the user isn't in session `session_id`.
+///
+/// # Errors
+/// Fails only if the message can't be serialized.
+pub async fn kick_user(
+    hub: &SignalingHub<Policy>,
+    session_id: SessionId,
+    user_id: UserId,
+    reason: &str,
+) -> crate::Result<bool> {
+    let policy = hub.policy();
+    let sessions = store::lock(policy.store.as_deref(), &policy.sessions).await;
+    let is_member = hub::is_member(&sessions, session_id, user_id);
+    drop(sessions);
+    if !is_member {
+        return Ok(false);
     }
-    // remove session if it's empty
-    if let Some(session_id) = session_to_delete {
-        sessions.write().await.remove(&session_id);
+    let message = SignalMessage::Error(session_id, user_id, reason.to_owned());
+    hub.kick(user_id, message, reason).await?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_session_saved_with_single_host_is_restored() {
+        let single_host: Session = serde_json::from_str(r#"{"host":1,"users":[2]}"#)
+            .expect("failed to deserialize session");
+        assert_eq!(single_host.hosts, [UserId::new(1)].into_iter().collect());
+        assert!(!single_host.multi_host);
+
+        let no_host: Session = serde_json::from_str(r#"{"host":null,"users":[2]}"#)
+            .expect("failed to deserialize session");
+        assert!(no_host.hosts.is_empty());
     }
 }
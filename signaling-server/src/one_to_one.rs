@@ -1,226 +1,460 @@
 use std::collections::hash_map::Entry;
-use std::collections::HashMap;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
 use anyhow::anyhow;
-use axum::extract::ws::{Message, WebSocket};
-use futures_util::{SinkExt, StreamExt};
+use axum::async_trait;
 use log::{error, info};
-use tokio::sync::{mpsc, RwLock};
-use tokio_stream::wrappers::UnboundedReceiverStream;
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
 use wasm_peers_protocol::one_to_one::SignalMessage;
-use wasm_peers_protocol::{SessionId, UserId};
+use wasm_peers_protocol::{JoinIntent, SessionId, SessionInfo, TopologyType, UserId};
 
+use crate::config::SessionLimits;
+use crate::hub::{self, Outgoing, SessionPolicy, SignalingHub};
+use crate::span;
+use crate::store::{self, SessionStore, StoredSession};
+
+#[derive(Serialize, Deserialize)]
 pub struct Session {
     pub first: Option<UserId>,
     pub second: Option<UserId>,
+    #[serde(default)]
     pub offer_received: bool,
+    /// Users restored from a store that haven't rejoined yet, see [`Policy::with_store`]
+    #[serde(default)]
+    pub rejoining: HashSet<UserId>,
 }
 
-pub type Connections = Arc<RwLock<HashMap<UserId, mpsc::UnboundedSender<Message>>>>;
-pub type Sessions = Arc<RwLock<HashMap<SessionId, Session>>>;
+impl Session {
+    /// Puts `user_id` in place of a user awaited to rejoin, returns `false` if there is none.
+    fn reclaim(&mut self, user_id: UserId) -> bool {
+        let rejoining = &mut self.rejoining;
+        let stale_slot = [&mut self.first, &mut self.second]
+            .into_iter()
+            .find(|slot| slot.is_some_and(|stale_id| rejoining.contains(&stale_id)));
+        let Some(slot) = stale_slot else {
+            return false;
+        };
+        if let Some(stale_id) = slot.replace(user_id) {
+            rejoining.remove(&stale_id);
+        }
+        true
+    }
 
-static NEXT_USER_ID: AtomicU64 = AtomicU64::new(1);
+    fn users_count(&self) -> usize {
+        usize::from(self.first.is_some()).saturating_add(usize::from(self.second.is_some()))
+    }
 
-pub async fn user_connected(ws: WebSocket, connections: Connections, sessions: Sessions) {
-    let user_id = UserId::new(NEXT_USER_ID.fetch_add(1, Ordering::Relaxed));
-    info!("new user connected: {:?}", user_id);
+    /// The other user in session, if it already joined.
+    fn other_user(&self, session_id: SessionId, user_id: UserId) -> crate::Result<UserId> {
+        if Some(user_id) == self.first {
+            self.second
+        } else {
+            self.first
+        }
+        .ok_or_else(|| anyhow!("missing second user in session: {session_id:?}"))
+    }
+}
 
-    let (mut user_ws_tx, mut user_ws_rx) = ws.split();
+impl StoredSession for Session {
+    fn users(&self) -> Vec<UserId> {
+        self.first.into_iter().chain(self.second).collect()
+    }
 
-    let (tx, rx) = mpsc::unbounded_channel();
-    let mut rx = UnboundedReceiverStream::new(rx);
+    fn rejoining_mut(&mut self) -> &mut HashSet<UserId> {
+        &mut self.rejoining
+    }
 
-    tokio::task::spawn(async move {
-        while let Some(message) = rx.next().await {
-            user_ws_tx
-                .send(message)
-                .await
-                .unwrap_or_else(|e| error!("websocket send error: {}", e));
-        }
-    });
+    fn forget_connections(&mut self) {
+        self.offer_received = false;
+    }
+}
 
-    connections.write().await.insert(user_id, tx);
+pub type Sessions = Arc<RwLock<HashMap<SessionId, Session>>>;
 
-    while let Some(result) = user_ws_rx.next().await {
-        let msg = match result {
-            Ok(msg) => msg,
-            Err(err) => {
-                error!("websocket error (user_id={:?}): {}", user_id, err);
-                break;
-            }
-        };
+/// Pairs two users in each session, randomly choosing which one of them sends the offer.
+#[derive(Default)]
+pub struct Policy {
+    sessions: Sessions,
+    limits: SessionLimits,
+    store: Option<Arc<dyn SessionStore<Session>>>,
+}
 
-        if let Err(err) = user_message(user_id, msg, &connections, &sessions).await {
-            error!("user_message error: {}", err);
+impl Policy {
+    /// Creates policy keeping sessions in `sessions`, e.g. to observe or pre-populate them
+    /// from the embedding application.
+    #[must_use]
+    pub fn with_sessions(sessions: Sessions) -> Self {
+        Self {
+            sessions,
+            limits: SessionLimits::default(),
+            store: None,
         }
     }
 
-    error!("user disconnected: {:?}", user_id);
-    user_disconnected(user_id, &connections, &sessions).await;
-}
+    /// Turns away users joining sessions over `limits`.
+    #[must_use]
+    pub fn with_limits(self, limits: SessionLimits) -> Self {
+        Self { limits, ..self }
+    }
 
-async fn user_message(
-    user_id: UserId,
-    msg: Message,
-    connections: &Connections,
-    sessions: &Sessions,
-) -> crate::Result<()> {
-    let request = rmp_serde::from_slice::<SignalMessage>(msg.into_data().as_ref())?;
-    info!("message received from user {:?}: {:?}", user_id, request);
-    match request {
-        SignalMessage::SessionJoin(session_id) => {
-            session_join(sessions, connections, user_id, session_id).await?;
-        }
-        // pass offer to the other user in session without changing anything
-        SignalMessage::SdpOffer(session_id, offer) => {
-            sdp_offer(sessions, connections, user_id, session_id, offer).await?;
+    /// Saves sessions to `store` after every change, restoring the ones saved before the server restarted.
+    /// Users rejoining restored sessions take over places of their previous connections,
+    /// once both of them are back they're told that session is ready again.
+    ///
+    /// # Errors
+    /// Fails if sessions can't be loaded from `store`.
+    pub fn with_store(self, store: Arc<dyn SessionStore<Session>>) -> crate::Result<Self> {
+        store::restore(&*store, &self.sessions)?;
+        Ok(Self {
+            store: Some(store),
+            ..self
+        })
+    }
+
+    #[must_use]
+    pub fn sessions(&self) -> &Sessions {
+        &self.sessions
+    }
+
+    /// Open sessions, as sent to users asking for them with [`SignalMessage::SessionListRequest`].
+    pub async fn session_list(&self) -> Vec<SessionInfo> {
+        hub::session_list(
+            &*store::lock(self.store.as_deref(), &self.sessions).await,
+            TopologyType::OneToOne,
+        )
+    }
+
+    /// Removes session `session_id` without telling its users, `None` if there's no such session.
+    pub async fn remove_session(&self, session_id: SessionId) -> Option<Session> {
+        let mut sessions = store::lock(self.store.as_deref(), &self.sessions).await;
+        let session = sessions.remove(&session_id)?;
+        store::save(self.store.as_deref(), &sessions);
+        Some(session)
+    }
+
+    async fn session_join(
+        &self,
+        user_id: UserId,
+        session_id: SessionId,
+        intent: JoinIntent,
+    ) -> Outgoing<SignalMessage> {
+        let mut sessions = store::lock(self.store.as_deref(), &self.sessions).await;
+        if let Some(error) = hub::join_error(&sessions, session_id, intent) {
+            return vec![(user_id, SignalMessage::SessionJoinError(session_id, error))];
         }
-        // pass answer to the other user in session without changing anything
-        SignalMessage::SdpAnswer(session_id, answer) => {
-            let sessions = sessions.read().await;
-            let session = sessions
-                .get(&session_id)
-                .ok_or_else(|| anyhow!("no such session: {:?}", &session_id))?;
-            let recipient_id = if Some(user_id) == session.first {
-                session.second
-            } else {
-                session.first
+        if let Some(session) = sessions.get_mut(&session_id) {
+            if session.reclaim(user_id) {
+                let other_id = session
+                    .other_user(session_id, user_id)
+                    .ok()
+                    .filter(|other_id| !session.rejoining.contains(other_id));
+                let outgoing = other_id.map_or_else(Vec::new, |other_id| {
+                    session.offer_received = false;
+                    session_ready(session_id, other_id, user_id)
+                });
+                store::save(self.store.as_deref(), &sessions);
+                return outgoing;
             }
-            .ok_or_else(|| anyhow!("missing second user in session: {:?}", &session_id))?;
-            let response = SignalMessage::SdpAnswer(session_id, answer);
-            let response = rmp_serde::to_vec(&response)?;
-            let connections_reader = connections.read().await;
-            connections_reader
-                .get(&recipient_id)
-                .ok_or_else(|| anyhow!("no sender for given recipient_id"))?
-                .send(Message::Binary(response))?;
         }
-        SignalMessage::IceCandidate(session_id, candidate) => {
-            let sessions = sessions.read().await;
-            let session = sessions
-                .get(&session_id)
-                .ok_or_else(|| anyhow!("no such session: {:?}", &session_id))?;
-            let recipient_id = if Some(user_id) == session.first {
-                session.second
-            } else {
-                session.first
-            }
-            .ok_or_else(|| anyhow!("missing second user in session: {:?}", &session_id))?;
-            let response = SignalMessage::IceCandidate(session_id, candidate);
-            let response = rmp_serde::to_vec(&response)?;
-            let connections_reader = connections.read().await;
-            connections_reader
-                .get(&recipient_id)
-                .ok_or_else(|| anyhow!("no sender for given recipient_id"))?
-                .send(Message::Binary(response))?;
+        let users_count = sessions.get(&session_id).map_or(0, Session::users_count);
+        if let Some(reason) = self.limits.rejection(sessions.len(), users_count) {
+            return vec![(user_id, SignalMessage::Error(session_id, reason.to_owned()))];
         }
-        other => {
-            error!("received unexpected signal message: {:?}", other);
+        let outgoing = match sessions.entry(session_id) {
+            // on first user in session - create session object and store connecting user id
+            Entry::Vacant(entry) => {
+                entry.insert(Session {
+                    first: Some(user_id),
+                    second: None,
+                    offer_received: false,
+                    rejoining: HashSet::new(),
+                });
+                Vec::new()
+            }
+            // connection is already in this session, it can't be paired with itself
+            Entry::Occupied(entry) if entry.get().users().contains(&user_id) => {
+                let response =
+                    SignalMessage::Error(session_id, "already joined this session".to_owned());
+                vec![(user_id, response)]
+            }
+            // session already has both users, turn away anyone else
+            Entry::Occupied(entry)
+                if entry.get().first.is_some() && entry.get().second.is_some() =>
+            {
+                let response = SignalMessage::Error(session_id, "session is full".to_owned());
+                vec![(user_id, response)]
+            }
+            // on second user - put him in the free place, which is the first one if its user left,
+            // and notify users that session is ready
+            Entry::Occupied(mut entry) => {
+                let session = entry.get_mut();
+                let other_id = session.first.or(session.second);
+                let free_slot = if session.first.is_none() {
+                    &mut session.first
+                } else {
+                    &mut session.second
+                };
+                *free_slot = Some(user_id);
+                other_id.map_or_else(Vec::new, |other_id| {
+                    session_ready(session_id, other_id, user_id)
+                })
+            }
+        };
+        store::save(self.store.as_deref(), &sessions);
+        outgoing
+    }
+
+    async fn sdp_offer(
+        &self,
+        user_id: UserId,
+        session_id: SessionId,
+        offer: String,
+    ) -> crate::Result<Outgoing<SignalMessage>> {
+        let mut sessions = store::lock(self.store.as_deref(), &self.sessions).await;
+        let session = sessions
+            .get_mut(&session_id)
+            .ok_or_else(|| anyhow!("no such session: {session_id:?}"))?;
+        if session.offer_received {
+            info!("offer already sent by the the peer, ignoring the second offer: {session_id:?}");
+        } else {
+            session.offer_received = true;
         }
+
+        let recipient_id = session.other_user(session_id, user_id)?;
+        store::save(self.store.as_deref(), &sessions);
+        Ok(vec![(
+            recipient_id,
+            SignalMessage::SdpOffer(session_id, offer),
+        )])
+    }
+
+    /// Passes `message` to the other user in session without changing anything.
+    async fn relay(
+        &self,
+        user_id: UserId,
+        session_id: SessionId,
+        message: SignalMessage,
+    ) -> crate::Result<Outgoing<SignalMessage>> {
+        let sessions = store::lock(self.store.as_deref(), &self.sessions).await;
+        let recipient_id = sessions
+            .get(&session_id)
+            .ok_or_else(|| anyhow!("no such session: {session_id:?}"))?
+            .other_user(session_id, user_id)?;
+        Ok(vec![(recipient_id, message)])
     }
-    Ok(())
 }
 
-async fn session_join(
-    sessions: &Sessions,
-    connections: &Connections,
-    user_id: UserId,
+/// Tells both users that session is ready, host role is assigned randomly
+/// so that the first user isn't always the one creating an offer.
+fn session_ready(
     session_id: SessionId,
-) -> crate::Result<()> {
-    let mut sessions = sessions.write().await;
-    match sessions.entry(session_id) {
-        // on first user in session - create session object and store connecting user id
-        Entry::Vacant(entry) => {
-            entry.insert(Session {
-                first: Some(user_id),
-                second: None,
-                offer_received: false,
-            });
-        }
-        // on second user - add him to existing session and notify users that session is ready
-        Entry::Occupied(mut entry) => {
-            entry.get_mut().second = Some(user_id);
-            let first_response = SignalMessage::SessionReady(session_id, true);
-            let first_response = rmp_serde::to_vec(&first_response)?;
-            let second_response = SignalMessage::SessionReady(session_id, false);
-            let second_response = rmp_serde::to_vec(&second_response)?;
-
-            let connections_reader = connections.read().await;
-            if let Some(first_id) = entry.get().first {
-                connections_reader
-                    .get(&first_id)
-                    .ok_or_else(|| anyhow!("no sender for given id"))?
-                    .send(Message::Binary(first_response))?;
-                connections_reader
-                    .get(&user_id)
-                    .ok_or_else(|| anyhow!("no sender for given id"))?
-                    .send(Message::Binary(second_response))?;
+    first_id: UserId,
+    second_id: UserId,
+) -> Outgoing<SignalMessage> {
+    let first_is_host = rand::random::<bool>();
+    vec![
+        (
+            first_id,
+            SignalMessage::SessionReady(session_id, first_is_host),
+        ),
+        (
+            second_id,
+            SignalMessage::SessionReady(session_id, !first_is_host),
+        ),
+    ]
+}
+
+#[async_trait]
+impl SessionPolicy for Policy {
+    type Message = SignalMessage;
+
+    const TOPOLOGY: &'static str = "one-to-one";
+
+    async fn route(
+        &self,
+        sender_id: UserId,
+        message: SignalMessage,
+    ) -> crate::Result<Outgoing<SignalMessage>> {
+        match message {
+            SignalMessage::SessionJoin(session_id) => {
+                span::record_session(session_id);
+                Ok(self
+                    .session_join(sender_id, session_id, JoinIntent::CreateOrJoin)
+                    .await)
+            }
+            SignalMessage::SessionJoinWithIntent(session_id, intent) => {
+                span::record_session(session_id);
+                Ok(self.session_join(sender_id, session_id, intent).await)
+            }
+            SignalMessage::SdpOffer(session_id, offer) => {
+                self.sdp_offer(sender_id, session_id, offer).await
+            }
+            SignalMessage::SdpAnswer(session_id, _)
+            | SignalMessage::IceCandidate(session_id, _) => {
+                self.relay(sender_id, session_id, message).await
+            }
+            SignalMessage::SessionListRequest => Ok(vec![(
+                sender_id,
+                SignalMessage::SessionListResponse(self.session_list().await),
+            )]),
+            other => {
+                error!("received unexpected signal message: {other:?}");
+                Ok(Vec::new())
             }
         }
     }
-    Ok(())
+
+    async fn on_disconnect(&self, user_id: UserId) -> Outgoing<SignalMessage> {
+        let mut sessions = store::lock(self.store.as_deref(), &self.sessions).await;
+        // user may be in several sessions over the same connection, each emptied one is removed
+        sessions.retain(|_, session| {
+            let mut left = false;
+            for slot in [&mut session.first, &mut session.second] {
+                if *slot == Some(user_id) {
+                    *slot = None;
+                    left = true;
+                }
+            }
+            if left {
+                // user joining in place of the one that left is paired anew
+                session.offer_received = false;
+            }
+            !left || session.first.is_some() || session.second.is_some()
+        });
+        store::save(self.store.as_deref(), &sessions);
+        Vec::new()
+    }
 }
 
-async fn sdp_offer(
-    sessions: &Sessions,
-    connections: &Connections,
-    user_id: UserId,
+/// Tells every user connected to `hub` to reconnect to signaling server under `new_server_url`.
+///
+/// # Errors
+/// Fails only if the message can't be serialized, users that can't be reached are just logged.
+pub async fn redirect_all(hub: &SignalingHub<Policy>, new_server_url: &str) -> crate::Result<()> {
+    hub.send_to_all(&SignalMessage::Redirect(new_server_url.to_owned()))
+        .await
+}
+
+/// Closes session `session_id`, telling its users why with [`SignalMessage::Error`].
+/// Users stay connected to signaling server. Returns `false` if there's no such session.
+///
+/// # Errors
+/// Fails only if the message can't be serialized, users that can't be reached are just logged.
+pub async fn close_session(
+    hub: &SignalingHub<Policy>,
     session_id: SessionId,
-    offer: String,
-) -> crate::Result<()> {
-    let mut sessions = sessions.write().await;
-    let session = sessions
-        .get_mut(&session_id)
-        .ok_or_else(|| anyhow!("no such session: {:?}", &session_id))?;
-    if session.offer_received {
-        info!(
-            "offer already sent by the the peer, ignoring the second offer: {:?}",
-            session_id
-        );
-    } else {
-        session.offer_received = true;
-    }
-
-    let recipient_id = if Some(user_id) == session.first {
-        session.second
-    } else {
-        session.first
-    }
-    .ok_or_else(|| anyhow!("missing second user in session: {:?}", &session_id))?;
-    let response = SignalMessage::SdpOffer(session_id, offer);
-    let response = rmp_serde::to_vec(&response)?;
-    let connections_reader = connections.read().await;
-    connections_reader
-        .get(&recipient_id)
-        .ok_or_else(|| anyhow!("no sender for given recipient_id"))?
-        .send(Message::Binary(response))?;
-    Ok(())
+    reason: &str,
+) -> crate::Result<bool> {
+    let Some(session) = hub.policy().remove_session(session_id).await else {
+        return Ok(false);
+    };
+    let outgoing = session
+        .users()
+        .into_iter()
+        .map(|user_id| (user_id, SignalMessage::Error(session_id, reason.to_owned())))
+        .collect();
+    hub.send(outgoing).await?;
+    Ok(true)
+}
+
+/// Disconnects `user_id` from signaling server, telling it why with [`SignalMessage::Error`] first,
+/// see [`SignalingHub::kick`]. Returns `false` if the user isn't in session `session_id`.
+///
+/// # Errors
+/// Fails only if the message can't be serialized.
+pub async fn kick_user(
+    hub: &SignalingHub<Policy>,
+    session_id: SessionId,
+    user_id: UserId,
+    reason: &str,
+) -> crate::Result<bool> {
+    let policy = hub.policy();
+    let sessions = store::lock(policy.store.as_deref(), &policy.sessions).await;
+    let is_member = hub::is_member(&sessions, session_id, user_id);
+    drop(sessions);
+    if !is_member {
+        return Ok(false);
+    }
+    let message = SignalMessage::Error(session_id, reason.to_owned());
+    hub.kick(user_id, message, reason).await?;
+    Ok(true)
 }
 
-async fn user_disconnected(user_id: UserId, connections: &Connections, sessions: &Sessions) {
-    connections.write().await.remove(&user_id);
+#[cfg(test)]
+mod test {
+    use axum::extract::ws::Message;
+    use tokio::sync::mpsc;
 
-    let mut sessions = sessions.write().await;
-    let mut session_to_delete = None;
-    for (session_id, session) in sessions.iter_mut() {
-        if session.first == Some(user_id) {
-            session.first = None;
-            if session.first.is_none() && session.second.is_none() {
-                session_to_delete = Some(*session_id);
-            }
-            break;
-        } else if session.second == Some(user_id) {
-            session.second = None;
-            if session.first.is_none() && session.second.is_none() {
-                session_to_delete = Some(*session_id);
-            }
-            break;
+    use super::*;
+
+    fn ready_is_host(outgoing: &Outgoing<SignalMessage>, user_id: UserId) -> Option<bool> {
+        outgoing
+            .iter()
+            .find_map(|&(recipient_id, ref message)| match *message {
+                SignalMessage::SessionReady(_, is_host) if recipient_id == user_id => Some(is_host),
+                _ => None,
+            })
+    }
+
+    #[tokio::test]
+    async fn test_host_role_is_assigned_randomly() {
+        let policy = Policy::default();
+        let mut first_is_host_results = Vec::new();
+
+        for (first_id, second_id) in (0..1000).zip(1000..2000) {
+            let (first_id, second_id) = (UserId::new(first_id), UserId::new(second_id));
+            let session_id = SessionId::new(u128::from(first_id.into_inner()));
+            let first_joined = policy
+                .route(first_id, SignalMessage::SessionJoin(session_id))
+                .await
+                .expect("first user failed to join");
+            assert!(first_joined.is_empty());
+            let outgoing = policy
+                .route(second_id, SignalMessage::SessionJoin(session_id))
+                .await
+                .expect("second user failed to join");
+
+            let first_is_host = ready_is_host(&outgoing, first_id);
+            let second_is_host = ready_is_host(&outgoing, second_id);
+            let first_is_host = first_is_host.expect("first user didn't receive SessionReady");
+            let second_is_host = second_is_host.expect("second user didn't receive SessionReady");
+            assert_ne!(first_is_host, second_is_host);
+            first_is_host_results.push(first_is_host);
         }
+
+        let first_is_host_count = first_is_host_results
+            .into_iter()
+            .filter(|is_host| *is_host)
+            .count();
+        assert!(
+            (400..=600).contains(&first_is_host_count),
+            "first user was host in {first_is_host_count} out of 1000 sessions"
+        );
     }
-    // remove session if it's empty
-    if let Some(session_id) = session_to_delete {
-        sessions.remove(&session_id);
+
+    #[tokio::test]
+    async fn test_redirect_is_sent_to_all_users() {
+        let hub = SignalingHub::<Policy>::default();
+        let mut receivers = Vec::new();
+        for _ in 0..3 {
+            let (tx, rx) = mpsc::unbounded_channel::<Message>();
+            hub.register(tx).await.expect("failed to register user");
+            receivers.push(rx);
+        }
+
+        redirect_all(&hub, "wss://new.server.com")
+            .await
+            .expect("failed to redirect users");
+
+        for mut rx in receivers {
+            let message = rx.recv().await.expect("user didn't receive a message");
+            let message = rmp_serde::from_slice(&message.into_data());
+            assert!(matches!(
+                message,
+                Ok(SignalMessage::Redirect(url)) if url == "wss://new.server.com"
+            ));
+        }
     }
 }
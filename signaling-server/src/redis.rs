@@ -0,0 +1,387 @@
+//! Relay and session store shared by instances of the server through Redis, enabled with `redis` feature,
+//! so that users of the same session can be connected to different replicas behind a load balancer.
+//!
+//! Keys of each topology are kept under their own prefix, see [`key_prefix`]:
+//! - `<prefix>:next-user-id` counts user ids allocated by all instances,
+//! - `<prefix>:user:<user id>` is pub/sub channel of the user, subscribed by the instance it's connected to,
+//! - `<prefix>:session:<session id>` holds JSON of the session, which expires [`SESSION_TTL_SECS`] after its last change,
+//!   so that sessions of users connected to an instance that crashed don't stay forever,
+//! - `<prefix>:lock` is held by the instance changing sessions, for at most [`LOCK_TTL`].
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::anyhow;
+use axum::async_trait;
+use axum::extract::ws::Message;
+use futures_util::StreamExt;
+use log::{error, warn};
+use rand::Rng;
+use redis::aio::{ConnectionManager, PubSubSink};
+use redis::{
+    AsyncCommands, Client, Commands, Connection, ExistenceCheck, Msg, Script, SetExpiry, SetOptions,
+};
+use tokio::sync::{mpsc, OnceCell};
+use wasm_peers_protocol::{SessionId, UserId};
+
+use crate::relay::Relay;
+use crate::store::{SessionStore, StoredSession};
+
+/// Sessions expire this long after their last change, unless set otherwise with [`RedisStore::with_ttl`].
+pub const SESSION_TTL_SECS: u64 = 24 * 60 * 60;
+
+/// Lock of sessions is released after this long even if the instance holding it crashed.
+pub const LOCK_TTL: Duration = Duration::from_secs(5);
+
+/// How often an instance waiting for the lock of sessions tries to take it.
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Deletes the lock only if it's still held with the given token, so that a lock that expired
+/// and was taken by another instance isn't released.
+const UNLOCK_SCRIPT: &str = r#"
+if redis.call("get", KEYS[1]) == ARGV[1] then
+    return redis.call("del", KEYS[1])
+end
+return 0
+"#;
+
+/// Prefix of keys of `topology`, e.g. `wasm-peers:one-to-one`.
+#[must_use]
+pub fn key_prefix(topology: &str) -> String {
+    format!("wasm-peers:{topology}")
+}
+
+type Subscribers = Arc<Mutex<HashMap<UserId, mpsc::UnboundedSender<Message>>>>;
+
+/// Allocates user ids with a counter shared by all instances, and passes messages to users
+/// connected to other instances through their pub/sub channels.
+///
+/// Connections with Redis are opened once relay is first used. Users connected to instance that lost
+/// its connection with Redis don't receive messages from other instances until it's restarted.
+pub struct RedisRelay {
+    client: Client,
+    prefix: String,
+    connection: OnceCell<ConnectionManager>,
+    subscriber: OnceCell<PubSubSink>,
+    /// Users connected to this instance
+    subscribers: Subscribers,
+}
+
+impl RedisRelay {
+    /// Creates relay using keys under `prefix`, see [`key_prefix`].
+    ///
+    /// # Errors
+    /// Fails if `url` isn't a valid Redis URL, e.g. `redis://redis:6379`.
+    pub fn new(url: &str, prefix: String) -> crate::Result<Self> {
+        Ok(Self {
+            client: Client::open(url)?,
+            prefix,
+            connection: OnceCell::new(),
+            subscriber: OnceCell::new(),
+            subscribers: Arc::default(),
+        })
+    }
+
+    fn channel(&self, user_id: UserId) -> String {
+        format!("{}:user:{}", self.prefix, user_id.into_inner())
+    }
+
+    async fn connection(&self) -> crate::Result<ConnectionManager> {
+        let connection = self
+            .connection
+            .get_or_try_init(|| ConnectionManager::new(self.client.clone()))
+            .await?;
+        Ok(connection.clone())
+    }
+
+    /// Sink subscribing channels of users, messages received on them are passed on by a spawned task.
+    async fn subscriber(&self) -> crate::Result<PubSubSink> {
+        let subscriber = self
+            .subscriber
+            .get_or_try_init(|| async {
+                let (sink, mut stream) = self.client.get_async_pubsub().await?.split();
+                let subscribers = Arc::clone(&self.subscribers);
+                tokio::spawn(async move {
+                    while let Some(msg) = stream.next().await {
+                        deliver(&subscribers, &msg);
+                    }
+                    error!("connection with Redis closed, messages relayed by other instances are lost");
+                });
+                Ok::<_, redis::RedisError>(sink)
+            })
+            .await?;
+        Ok(subscriber.clone())
+    }
+}
+
+/// Passes message received on channel of a user to its websocket.
+fn deliver(subscribers: &Subscribers, msg: &Msg) {
+    let user_id = msg
+        .get_channel_name()
+        .rsplit(':')
+        .next()
+        .and_then(|user_id| user_id.parse().ok())
+        .map(UserId::new);
+    let Some(user_id) = user_id else {
+        warn!(
+            "message on unexpected channel {}, ignoring it",
+            msg.get_channel_name()
+        );
+        return;
+    };
+    let subscribers = subscribers.lock().unwrap_or_else(PoisonError::into_inner);
+    let Some(sender) = subscribers.get(&user_id) else {
+        warn!("relayed message for user {user_id:?} that isn't connected anymore");
+        return;
+    };
+    let message = Message::Binary(msg.get_payload_bytes().to_vec());
+    if let Err(err) = sender.send(message) {
+        error!("failed to send relayed message to user {user_id:?}: {err}");
+    }
+}
+
+#[async_trait]
+impl Relay for RedisRelay {
+    async fn next_user_id(&self) -> crate::Result<UserId> {
+        let key = format!("{}:next-user-id", self.prefix);
+        let user_id: u64 = self.connection().await?.incr(key, 1).await?;
+        Ok(UserId::new(user_id))
+    }
+
+    async fn subscribe(
+        &self,
+        user_id: UserId,
+        sender: mpsc::UnboundedSender<Message>,
+    ) -> crate::Result<()> {
+        let mut subscriber = self.subscriber().await?;
+        self.subscribers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(user_id, sender);
+        subscriber.subscribe(self.channel(user_id)).await?;
+        Ok(())
+    }
+
+    async fn unsubscribe(&self, user_id: UserId) {
+        self.subscribers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&user_id);
+        let result = match self.subscriber().await {
+            Ok(mut subscriber) => subscriber
+                .unsubscribe(self.channel(user_id))
+                .await
+                .map_err(anyhow::Error::from),
+            Err(err) => Err(err),
+        };
+        if let Err(err) = result {
+            error!("failed to unsubscribe channel of user {user_id:?}: {err}");
+        }
+    }
+
+    async fn forward(&self, recipient_id: UserId, message: Vec<u8>) -> crate::Result<()> {
+        let receivers: usize = self
+            .connection()
+            .await?
+            .publish(self.channel(recipient_id), message)
+            .await?;
+        if receivers == 0 {
+            return Err(anyhow!("no instance has user {recipient_id:?}"));
+        }
+        Ok(())
+    }
+}
+
+/// Keeps sessions of a single topology in Redis, where all instances of the server read and change them
+/// under a lock. Each session is kept under its own key, which expires [`SESSION_TTL_SECS`] after its last change.
+///
+/// Sessions aren't restored once the server restarts, as they're read before every change anyway.
+/// Redis is accessed synchronously, like the file of [`JsonFileStore`](crate::store::JsonFileStore),
+/// so waiting for the lock held by another instance blocks the thread for up to [`LOCK_TTL`].
+pub struct RedisStore {
+    client: Client,
+    prefix: String,
+    ttl: Duration,
+    connection: Mutex<Option<Connection>>,
+    /// Token of the lock held by this instance
+    lock_token: Mutex<Option<String>>,
+    /// JSON of sessions as they were read once locked, so that only the changed ones are written
+    locked: Mutex<HashMap<SessionId, String>>,
+}
+
+impl RedisStore {
+    /// Creates store using keys under `prefix`, see [`key_prefix`].
+    ///
+    /// # Errors
+    /// Fails if `url` isn't a valid Redis URL, e.g. `redis://redis:6379`.
+    pub fn new(url: &str, prefix: String) -> crate::Result<Self> {
+        Ok(Self {
+            client: Client::open(url)?,
+            prefix,
+            ttl: Duration::from_secs(SESSION_TTL_SECS),
+            connection: Mutex::default(),
+            lock_token: Mutex::default(),
+            locked: Mutex::default(),
+        })
+    }
+
+    /// Expires sessions `ttl` after their last change instead of [`SESSION_TTL_SECS`].
+    #[must_use]
+    pub fn with_ttl(self, ttl: Duration) -> Self {
+        Self { ttl, ..self }
+    }
+
+    fn session_key(&self, session_id: SessionId) -> String {
+        format!("{}:session:{}", self.prefix, session_id.inner())
+    }
+
+    fn lock_key(&self) -> String {
+        format!("{}:lock", self.prefix)
+    }
+
+    /// Runs `command` on connection with Redis, which is opened again after it fails.
+    fn with_connection<T>(
+        &self,
+        command: impl FnOnce(&mut Connection) -> redis::RedisResult<T>,
+    ) -> crate::Result<T> {
+        let mut connection = lock(&self.connection);
+        let result = if let Some(ref mut connection) = *connection {
+            command(connection)
+        } else {
+            let mut new_connection = self.client.get_connection()?;
+            let result = command(&mut new_connection);
+            *connection = Some(new_connection);
+            result
+        };
+        if result.is_err() {
+            *connection = None;
+        }
+        Ok(result?)
+    }
+
+    /// Takes the lock of sessions, waiting for another instance to release it for at most [`LOCK_TTL`].
+    fn take_lock(&self) -> crate::Result<()> {
+        let token = rand::thread_rng().gen::<u64>().to_string();
+        let options = SetOptions::default()
+            .conditional_set(ExistenceCheck::NX)
+            .with_expiration(SetExpiry::PX(duration_millis(LOCK_TTL)));
+        let started = Instant::now();
+        loop {
+            let locked: Option<String> = self.with_connection(|connection| {
+                connection.set_options(self.lock_key(), &token, options)
+            })?;
+            if locked.is_some() {
+                *lock(&self.lock_token) = Some(token);
+                return Ok(());
+            }
+            if started.elapsed() >= LOCK_TTL {
+                return Err(anyhow!("sessions are locked by another instance"));
+            }
+            thread::sleep(LOCK_RETRY_INTERVAL);
+        }
+    }
+
+    /// Reads JSON of all sessions.
+    fn read_sessions(&self) -> crate::Result<HashMap<SessionId, String>> {
+        let pattern = format!("{}:session:*", self.prefix);
+        let keys: Vec<String> = self.with_connection(|connection| {
+            connection
+                .scan_match::<_, String>(pattern)
+                .map(Iterator::collect)
+        })?;
+        if keys.is_empty() {
+            return Ok(HashMap::new());
+        }
+        let values: Vec<Option<String>> =
+            self.with_connection(|connection| connection.mget(&keys))?;
+        let mut sessions = HashMap::new();
+        // sessions that expired in the meantime are skipped
+        for (key, value) in keys.iter().zip(values) {
+            let session_id = key
+                .rsplit(':')
+                .next()
+                .and_then(|session_id| session_id.parse().ok())
+                .map(SessionId::new)
+                .ok_or_else(|| anyhow!("malformed session key {key}"))?;
+            if let Some(value) = value {
+                sessions.insert(session_id, value);
+            }
+        }
+        Ok(sessions)
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn duration_millis(duration: Duration) -> u64 {
+    u64::try_from(duration.as_millis()).unwrap_or(u64::MAX)
+}
+
+impl<S: StoredSession> SessionStore<S> for RedisStore {
+    fn load(&self) -> crate::Result<HashMap<SessionId, S>> {
+        Ok(HashMap::new())
+    }
+
+    fn save(&self, sessions: &HashMap<SessionId, S>) -> crate::Result<()> {
+        // sessions kept in memory when locking failed may be outdated, they must not replace current ones
+        if lock(&self.lock_token).is_none() {
+            return Err(anyhow!("sessions aren't locked, they can't be saved"));
+        }
+        let mut locked = lock(&self.locked);
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        let mut saved = HashMap::new();
+        for (&session_id, session) in sessions {
+            let json = serde_json::to_string(session)?;
+            if locked.get(&session_id) != Some(&json) {
+                pipe.set_ex(self.session_key(session_id), &json, self.ttl.as_secs())
+                    .ignore();
+            }
+            saved.insert(session_id, json);
+        }
+        for &session_id in locked.keys() {
+            if !sessions.contains_key(&session_id) {
+                pipe.del(self.session_key(session_id)).ignore();
+            }
+        }
+        self.with_connection(|connection| pipe.query::<()>(connection))?;
+        *locked = saved;
+        Ok(())
+    }
+
+    fn lock(&self) -> crate::Result<Option<HashMap<SessionId, S>>> {
+        self.take_lock()?;
+        let sessions = self.read_sessions().and_then(|current| {
+            let sessions = current
+                .iter()
+                .map(|(&session_id, json)| Ok((session_id, serde_json::from_str(json)?)))
+                .collect::<crate::Result<_>>()?;
+            *lock(&self.locked) = current;
+            Ok(sessions)
+        });
+        if sessions.is_err() {
+            // sessions kept in memory are used instead, which mustn't be saved
+            SessionStore::<S>::unlock(self);
+        }
+        sessions.map(Some)
+    }
+
+    fn unlock(&self) {
+        let Some(token) = lock(&self.lock_token).take() else {
+            return;
+        };
+        let result = self.with_connection(|connection| {
+            Script::new(UNLOCK_SCRIPT)
+                .key(self.lock_key())
+                .arg(token)
+                .invoke::<()>(connection)
+        });
+        if let Err(err) = result {
+            error!("failed to unlock sessions, they stay locked for {LOCK_TTL:?}: {err}");
+        }
+    }
+}
@@ -0,0 +1,92 @@
+//! Delivery of signal messages between instances of the server, for deployments with multiple replicas
+//! behind a load balancer, e.g. [`RedisRelay`](crate::redis::RedisRelay) backed by Redis pub/sub
+//! with a channel per [`UserId`].
+//!
+//! Users of a session may then be connected to different instances, so sessions have to be shared as well:
+//! policies can be given a [`SessionStore`](crate::store::SessionStore) shared by all instances
+//! with `Policy::with_store`, or replaced with a custom [`SessionPolicy`](crate::hub::SessionPolicy).
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use anyhow::anyhow;
+use axum::async_trait;
+use axum::extract::ws::Message;
+use tokio::sync::mpsc;
+use wasm_peers_protocol::UserId;
+
+/// Allocates user ids and forwards messages to users connected to other instances of the server.
+/// Each [`SignalingHub`](crate::hub::SignalingHub) uses [`LocalRelay`] unless given another one.
+#[async_trait]
+pub trait Relay: Send + Sync + 'static {
+    /// Allocates id of a newly connected user, unique among all instances.
+    ///
+    /// # Errors
+    /// User is disconnected straight away if it fails.
+    async fn next_user_id(&self) -> crate::Result<UserId>;
+
+    /// Called once `user_id` connects to this instance, messages forwarded to it
+    /// by other instances should be passed to `sender` until it's unsubscribed.
+    ///
+    /// # Errors
+    /// User is disconnected straight away if it fails.
+    async fn subscribe(
+        &self,
+        user_id: UserId,
+        sender: mpsc::UnboundedSender<Message>,
+    ) -> crate::Result<()>;
+
+    /// Called once `user_id` disconnects from this instance.
+    async fn unsubscribe(&self, user_id: UserId);
+
+    /// Forwards serialized `message` to `recipient_id`, which isn't connected to this instance.
+    ///
+    /// # Errors
+    /// Errors are logged, message is dropped.
+    async fn forward(&self, recipient_id: UserId, message: Vec<u8>) -> crate::Result<()>;
+}
+
+/// Relay of a single instance of the server, all users are connected to it.
+#[derive(Debug)]
+pub struct LocalRelay {
+    next_user_id: AtomicU64,
+}
+
+impl Default for LocalRelay {
+    fn default() -> Self {
+        Self::after(None)
+    }
+}
+
+impl LocalRelay {
+    /// Creates relay allocating ids following `last_user_id`, starting from 1 if it's `None`.
+    #[must_use]
+    pub fn after(last_user_id: Option<UserId>) -> Self {
+        let last_user_id = last_user_id.map_or(0, UserId::into_inner);
+        Self {
+            next_user_id: AtomicU64::new(last_user_id.saturating_add(1)),
+        }
+    }
+}
+
+#[async_trait]
+impl Relay for LocalRelay {
+    async fn next_user_id(&self) -> crate::Result<UserId> {
+        Ok(UserId::new(
+            self.next_user_id.fetch_add(1, Ordering::Relaxed),
+        ))
+    }
+
+    async fn subscribe(
+        &self,
+        _user_id: UserId,
+        _sender: mpsc::UnboundedSender<Message>,
+    ) -> crate::Result<()> {
+        Ok(())
+    }
+
+    async fn unsubscribe(&self, _user_id: UserId) {}
+
+    async fn forward(&self, recipient_id: UserId, _message: Vec<u8>) -> crate::Result<()> {
+        Err(anyhow!("user {recipient_id:?} not in connections"))
+    }
+}
@@ -1,63 +1,645 @@
-use axum::extract::{State, WebSocketUpgrade};
-use axum::response::Response;
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+use std::net::TcpListener;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use axum::extract::{Host, Query, State};
+use axum::http::uri::PathAndQuery;
+use axum::http::{header, HeaderMap, Request, StatusCode, Uri};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Redirect, Response};
 use axum::routing::get;
-use axum::Router;
+use axum::{Json, Router};
+use axum_server::Handle;
+use log::info;
+use rustls_pemfile::Item;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{Notify, RwLock};
+use tokio::task::JoinHandle;
+use wasm_peers_protocol::SessionId;
 
+use crate::admin;
+use crate::config::{AllowedOrigins, ServerConfig};
+use crate::hub::{SessionPolicy, SignalingHub};
+use crate::message_logger::MessageLogger;
+#[cfg(feature = "redis")]
+use crate::redis::{self, RedisRelay, RedisStore};
+use crate::relay::Relay;
+use crate::session_audit::{SessionAuditLog, SharedSessionAuditLog};
+use crate::store::{self, JsonFileStore, StoredSession};
 use crate::{many_to_many, one_to_many, one_to_one};
 
+/// Certificate and private key used by [`serve_with_tls`], usually read with [`load_tls_config`].
+pub use axum_server::tls_rustls::RustlsConfig;
+
+/// Time given to redirected users to reconnect before the server shuts down.
+pub const REDIRECT_GRACE_PERIOD: Duration = Duration::from_secs(10);
+
+/// Reason of the close frame sent to users by [`ServerState::close_connections`].
+pub const SHUTDOWN_CLOSE_REASON: &str = "server is shutting down";
+
 #[derive(Default, Clone)]
 pub struct ServerState {
-    one_to_one_connections: one_to_one::Connections,
-    one_to_one_sessions: one_to_one::Sessions,
-    one_to_many_connections: one_to_many::Connections,
-    one_to_many_sessions: one_to_many::Sessions,
-    many_to_many_connections: many_to_many::Connections,
-    many_to_many_sessions: many_to_many::Sessions,
+    pub(crate) one_to_one: SignalingHub<one_to_one::Policy>,
+    pub(crate) one_to_many: SignalingHub<one_to_many::Policy>,
+    pub(crate) many_to_many: SignalingHub<many_to_many::Policy>,
+    admin_token: Option<Arc<str>>,
+    auth_secret: Option<Arc<str>>,
+    allowed_origins: Arc<AllowedOrigins>,
+    message_logger: Option<Arc<MessageLogger>>,
+    session_audit_log: SharedSessionAuditLog,
+    shutdown: Arc<Notify>,
 }
 
-#[allow(clippy::unused_async)]
-async fn health_handler() -> &'static str {
-    "OK"
+/// Which routes are served by [`create_with_config`] and where.
+#[derive(Debug, Clone)]
+pub struct RouterConfig {
+    /// Prepended to every route, e.g. `/signal` serves `/signal/one-to-one`, empty by default
+    pub path_prefix: String,
+    /// Serve `/health`, which responds with `OK`, or with `503 Service Unavailable` and [`HealthReport`]
+    /// once handling of any connection panicked
+    pub enable_health: bool,
+    /// Serve `/metrics` with the number of connected users and open sessions of each topology,
+    /// in Prometheus text format
+    pub enable_metrics: bool,
+    /// Limit of websocket frames received from users, see [`SignalingHub::with_max_frame_size`]
+    pub max_frame_size: Option<usize>,
+}
+
+impl Default for RouterConfig {
+    fn default() -> Self {
+        Self {
+            path_prefix: String::new(),
+            enable_health: true,
+            enable_metrics: false,
+            max_frame_size: None,
+        }
+    }
+}
+
+impl ServerState {
+    /// Creates state with [admin endpoints](crate::admin) enabled, requests to them must carry
+    /// `Authorization: Bearer <admin_token>` header.
+    #[must_use]
+    pub fn with_admin_token(admin_token: &str) -> Self {
+        Self {
+            admin_token: Some(admin_token.into()),
+            ..Self::default()
+        }
+    }
+
+    /// Creates state with admin endpoints, authentication, origin checks, audit log, session and payload limits
+    /// and session store set up from `config`.
+    ///
+    /// # Errors
+    /// Fails if the audit log can't be opened or saved sessions can't be restored.
+    pub fn from_config(config: &ServerConfig) -> crate::Result<Self> {
+        let limits = config.session_limits;
+        let mut one_to_one = one_to_one::Policy::default().with_limits(limits);
+        let mut one_to_many = one_to_many::Policy::default().with_limits(limits);
+        let mut many_to_many = many_to_many::Policy::default().with_limits(limits);
+        if let Some(ref dir) = config.session_store_dir {
+            std::fs::create_dir_all(dir)?;
+            let store =
+                |topology: &str| Arc::new(JsonFileStore::new(dir.join(format!("{topology}.json"))));
+            one_to_one = one_to_one.with_store(store(one_to_one::Policy::TOPOLOGY))?;
+            one_to_many = one_to_many.with_store(store(one_to_many::Policy::TOPOLOGY))?;
+            many_to_many = many_to_many.with_store(store(many_to_many::Policy::TOPOLOGY))?;
+        }
+        #[cfg(feature = "redis")]
+        if let Some(ref url) = config.redis_url {
+            let store = |topology: &str| -> crate::Result<_> {
+                Ok(Arc::new(RedisStore::new(url, redis::key_prefix(topology))?))
+            };
+            one_to_one = one_to_one.with_store(store(one_to_one::Policy::TOPOLOGY)?)?;
+            one_to_many = one_to_many.with_store(store(one_to_many::Policy::TOPOLOGY)?)?;
+            many_to_many = many_to_many.with_store(store(many_to_many::Policy::TOPOLOGY)?)?;
+        }
+        let one_to_one_relay = relay(config, one_to_one::Policy::TOPOLOGY, one_to_one.sessions())?;
+        let one_to_many_relay = relay(
+            config,
+            one_to_many::Policy::TOPOLOGY,
+            one_to_many.sessions(),
+        )?;
+        let many_to_many_relay = relay(
+            config,
+            many_to_many::Policy::TOPOLOGY,
+            many_to_many.sessions(),
+        )?;
+        Ok(Self {
+            one_to_one: SignalingHub::new(one_to_one)
+                .with_relay(one_to_one_relay)
+                .with_payload_limits(config.payload_limits),
+            one_to_many: SignalingHub::new(one_to_many)
+                .with_relay(one_to_many_relay)
+                .with_payload_limits(config.payload_limits),
+            many_to_many: SignalingHub::new(many_to_many)
+                .with_relay(many_to_many_relay)
+                .with_payload_limits(config.payload_limits),
+            admin_token: config.admin_token.as_deref().map(Arc::from),
+            auth_secret: config.auth_secret.as_deref().map(Arc::from),
+            allowed_origins: Arc::new(config.allowed_origins.clone()),
+            message_logger: MessageLogger::open(config)?.map(Arc::new),
+            session_audit_log: Arc::new(RwLock::new(SessionAuditLog::from_config(config))),
+            shutdown: Arc::default(),
+        })
+    }
+
+    /// Records every signal message received from users with `message_logger`.
+    #[must_use]
+    pub fn with_message_logger(self, message_logger: MessageLogger) -> Self {
+        Self {
+            message_logger: Some(Arc::new(message_logger)),
+            ..self
+        }
+    }
+
+    /// Rejects websocket upgrades from browsers on pages with origin that isn't allowed.
+    #[must_use]
+    pub fn with_allowed_origins(self, allowed_origins: AllowedOrigins) -> Self {
+        Self {
+            allowed_origins: Arc::new(allowed_origins),
+            ..self
+        }
+    }
+
+    /// Serves one-to-one endpoint with `hub`, e.g. one created with [`SignalingHub::with_connections`]
+    /// and [`one_to_one::Policy::with_sessions`], so that the embedding application can observe
+    /// or pre-populate connections and sessions.
+    #[must_use]
+    pub fn with_one_to_one_hub(self, hub: SignalingHub<one_to_one::Policy>) -> Self {
+        Self {
+            one_to_one: hub,
+            ..self
+        }
+    }
+
+    /// Same as [`ServerState::with_one_to_one_hub`], for one-to-many endpoint.
+    #[must_use]
+    pub fn with_one_to_many_hub(self, hub: SignalingHub<one_to_many::Policy>) -> Self {
+        Self {
+            one_to_many: hub,
+            ..self
+        }
+    }
+
+    /// Same as [`ServerState::with_one_to_one_hub`], for many-to-many endpoint.
+    #[must_use]
+    pub fn with_many_to_many_hub(self, hub: SignalingHub<many_to_many::Policy>) -> Self {
+        Self {
+            many_to_many: hub,
+            ..self
+        }
+    }
+
+    /// Connection events of each session, served by `GET /admin/sessions/{session_id}/events`.
+    #[must_use]
+    pub fn session_audit_log(&self) -> &SharedSessionAuditLog {
+        &self.session_audit_log
+    }
+
+    #[must_use]
+    pub fn one_to_one_sessions(&self) -> &one_to_one::Sessions {
+        self.one_to_one.policy().sessions()
+    }
+
+    #[must_use]
+    pub fn one_to_many_sessions(&self) -> &one_to_many::Sessions {
+        self.one_to_many.policy().sessions()
+    }
+
+    #[must_use]
+    pub fn many_to_many_sessions(&self) -> &many_to_many::Sessions {
+        self.many_to_many.policy().sessions()
+    }
+
+    /// Resolves once server was asked to shut down, e.g. after redirecting all users.
+    pub async fn shutdown_requested(&self) {
+        self.shutdown.notified().await;
+    }
+
+    /// Asks server to shut down, e.g. once the process receives `SIGTERM`, see [`ServerState::shutdown_requested`].
+    pub fn request_shutdown(&self) {
+        self.shutdown.notify_one();
+    }
+
+    /// Closes connections of all users and waits up to `timeout` for them to disconnect,
+    /// called by [`serve`] and [`serve_with_tls`] as soon as shutdown was requested.
+    /// Browser peers reconnect on their own, e.g. to a restarted instance of the server.
+    pub async fn close_connections(&self, timeout: Duration) {
+        self.one_to_one.close_all(SHUTDOWN_CLOSE_REASON).await;
+        self.one_to_many.close_all(SHUTDOWN_CLOSE_REASON).await;
+        self.many_to_many.close_all(SHUTDOWN_CLOSE_REASON).await;
+        let disconnected = async {
+            while self.connected_users().await > 0 {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        };
+        if tokio::time::timeout(timeout, disconnected).await.is_err() {
+            info!(
+                "{} users still connected after {:?}, shutting down anyway",
+                self.connected_users().await,
+                timeout
+            );
+        }
+    }
+
+    /// Number of users connected to all topologies.
+    async fn connected_users(&self) -> usize {
+        let one_to_one = self.one_to_one.connections().read().await.len();
+        let one_to_many = self.one_to_many.connections().read().await.len();
+        let many_to_many = self.many_to_many.connections().read().await.len();
+        one_to_one
+            .saturating_add(one_to_many)
+            .saturating_add(many_to_many)
+    }
+
+    /// Hub configured with message logger, session audit log and frame size limit of the server.
+    fn hub<P: SessionPolicy>(
+        &self,
+        hub: &SignalingHub<P>,
+        max_frame_size: Option<usize>,
+    ) -> SignalingHub<P> {
+        let mut hub = hub.clone();
+        if let Some(ref message_logger) = self.message_logger {
+            hub = hub.with_message_logger(Arc::clone(message_logger));
+        }
+        hub = hub.with_session_audit_log(Arc::clone(&self.session_audit_log));
+        if let Some(max_frame_size) = max_frame_size {
+            hub = hub.with_max_frame_size(max_frame_size);
+        }
+        hub
+    }
+
+    pub(crate) fn is_admin(&self, headers: &HeaderMap) -> bool {
+        let Some(ref admin_token) = self.admin_token else {
+            return false;
+        };
+        headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            == Some(&**admin_token)
+    }
+}
+
+/// Relay of `topology` shared through [`ServerConfig::redis_url`] if it's set, otherwise one
+/// allocating user ids after the ones of restored `sessions`.
+#[cfg_attr(not(feature = "redis"), allow(unused_variables))]
+fn relay<S: StoredSession, H: BuildHasher>(
+    config: &ServerConfig,
+    topology: &str,
+    sessions: &RwLock<HashMap<SessionId, S, H>>,
+) -> crate::Result<Arc<dyn Relay>> {
+    #[cfg(feature = "redis")]
+    if let Some(ref url) = config.redis_url {
+        return Ok(Arc::new(RedisRelay::new(url, redis::key_prefix(topology))?));
+    }
+    Ok(Arc::new(store::relay_after_restored(sessions)?))
+}
+
+#[derive(Deserialize)]
+struct AuthQuery {
+    token: Option<String>,
+}
+
+/// Turns away users that don't pass [`ServerConfig::auth_secret`] as `token` query parameter.
+async fn auth_middleware<B>(
+    State(state): State<ServerState>,
+    Query(query): Query<AuthQuery>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    match state.auth_secret {
+        Some(ref auth_secret) if query.token.as_deref() != Some(&**auth_secret) => {
+            StatusCode::UNAUTHORIZED.into_response()
+        }
+        _ => next.run(request).await,
+    }
+}
+
+/// Turns away browsers on pages with origin other than [`ServerConfig::allowed_origins`].
+async fn origin_middleware<B>(
+    State(state): State<ServerState>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let origin = request.headers().get(header::ORIGIN);
+    match origin {
+        Some(origin)
+            if !origin
+                .to_str()
+                .is_ok_and(|origin| state.allowed_origins.allows(origin)) =>
+        {
+            info!("rejected websocket upgrade from origin {origin:?}");
+            StatusCode::FORBIDDEN.into_response()
+        }
+        _ => next.run(request).await,
+    }
+}
+
+/// Body of `/health` response once the server is unhealthy.
+#[derive(Debug, Serialize)]
+pub struct HealthReport {
+    /// Always `unhealthy`
+    pub status: &'static str,
+    /// Topologies which connection handler panicked, see [`SignalingHub::is_healthy`]
+    pub unhealthy_topologies: Vec<&'static str>,
+}
+
+fn unhealthy_topology<P: SessionPolicy>(hub: &SignalingHub<P>) -> Option<&'static str> {
+    (!hub.is_healthy()).then_some(P::TOPOLOGY)
 }
 
 #[allow(clippy::unused_async)]
-async fn one_to_one_handler(State(state): State<ServerState>, ws: WebSocketUpgrade) -> Response {
-    ws.on_upgrade(move |socket| {
-        one_to_one::user_connected(
-            socket,
-            state.one_to_one_connections,
-            state.one_to_one_sessions,
+async fn health_handler(State(state): State<ServerState>) -> Response {
+    let unhealthy_topologies: Vec<_> = [
+        unhealthy_topology(&state.one_to_one),
+        unhealthy_topology(&state.one_to_many),
+        unhealthy_topology(&state.many_to_many),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    if unhealthy_topologies.is_empty() {
+        return "OK".into_response();
+    }
+    let report = HealthReport {
+        status: "unhealthy",
+        unhealthy_topologies,
+    };
+    (StatusCode::SERVICE_UNAVAILABLE, Json(report)).into_response()
+}
+
+/// Metrics of a single topology in Prometheus text format.
+async fn topology_metrics<P: SessionPolicy>(
+    hub: &SignalingHub<P>,
+    sessions_count: usize,
+) -> String {
+    format!(
+        "wasm_peers_connected_users{{topology=\"{topology}\"}} {users}\n\
+         wasm_peers_sessions{{topology=\"{topology}\"}} {sessions_count}\n",
+        topology = P::TOPOLOGY,
+        users = hub.connections().read().await.len(),
+    )
+}
+
+async fn metrics_handler(State(state): State<ServerState>) -> String {
+    let mut metrics = topology_metrics(
+        &state.one_to_one,
+        state.one_to_one_sessions().read().await.len(),
+    )
+    .await;
+    metrics.push_str(
+        &topology_metrics(
+            &state.one_to_many,
+            state.one_to_many_sessions().read().await.len(),
         )
-    })
+        .await,
+    );
+    metrics.push_str(
+        &topology_metrics(
+            &state.many_to_many,
+            state.many_to_many_sessions().read().await.len(),
+        )
+        .await,
+    );
+    metrics
 }
 
-#[allow(clippy::unused_async)]
-async fn one_to_many_handler(State(state): State<ServerState>, ws: WebSocketUpgrade) -> Response {
-    ws.on_upgrade(move |socket| {
-        one_to_many::user_connected(
-            socket,
-            state.one_to_many_connections,
-            state.one_to_many_sessions,
+/// Routes with default [`RouterConfig`].
+pub fn create(server_state: ServerState) -> Router {
+    create_with_config(server_state, RouterConfig::default())
+}
+
+/// Routes of the server, to be served on their own or nested in another app with [`Router::nest`].
+pub fn create_with_config(server_state: ServerState, config: RouterConfig) -> Router {
+    let RouterConfig {
+        path_prefix,
+        enable_health,
+        enable_metrics,
+        max_frame_size,
+    } = config;
+    let prefix = path_prefix.trim_end_matches('/');
+    let mut router = Router::new()
+        .route(
+            &format!("{prefix}/one-to-one"),
+            server_state
+                .hub(&server_state.one_to_one, max_frame_size)
+                .method_router(),
         )
-    })
+        .route(
+            &format!("{prefix}/one-to-many"),
+            server_state
+                .hub(&server_state.one_to_many, max_frame_size)
+                .method_router(),
+        )
+        .route(
+            &format!("{prefix}/many-to-many"),
+            server_state
+                .hub(&server_state.many_to_many, max_frame_size)
+                .method_router(),
+        )
+        .route_layer(middleware::from_fn_with_state(
+            server_state.clone(),
+            auth_middleware,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            server_state.clone(),
+            origin_middleware,
+        ))
+        .merge(admin::router(prefix, server_state.clone()));
+    if enable_health {
+        router = router.route(&format!("{prefix}/health"), get(health_handler));
+    }
+    if enable_metrics {
+        router = router.route(&format!("{prefix}/metrics"), get(metrics_handler));
+    }
+    router.with_state(server_state)
 }
 
-#[allow(clippy::unused_async)]
-async fn many_to_many_handler(State(state): State<ServerState>, ws: WebSocketUpgrade) -> Response {
-    ws.on_upgrade(move |socket| {
-        many_to_many::user_connected(
-            socket,
-            state.many_to_many_connections,
-            state.many_to_many_sessions,
+/// Serves the same routes as [`create_with_config`] over plain HTTP on `listener`.
+/// Server shuts down once [`ServerState::shutdown_requested`] resolves, closing connections of all users
+/// right away and giving them `shutdown_timeout` to disconnect, see [`ServerState::close_connections`].
+///
+/// # Errors
+/// Fails if accepting connections fails.
+pub async fn serve(
+    listener: TcpListener,
+    server_state: ServerState,
+    router_config: RouterConfig,
+    shutdown_timeout: Duration,
+) -> crate::Result<()> {
+    let handle = Handle::new();
+    let shutdown = tokio::spawn(shut_down_when_requested(
+        server_state.clone(),
+        handle.clone(),
+        shutdown_timeout,
+    ));
+    let result = axum_server::from_tcp(listener)
+        .handle(handle)
+        .serve(create_with_config(server_state, router_config).into_make_service())
+        .await;
+    finish_shutdown(shutdown, result).await
+}
+
+/// Serves the same routes as [`create_with_config`] over HTTPS on `listener`,
+/// so users connect with `wss://` URLs, which browsers require on pages served over HTTPS.
+/// Server shuts down like the one of [`serve`].
+///
+/// # Errors
+/// Fails if accepting connections fails.
+pub async fn serve_with_tls(
+    listener: TcpListener,
+    server_state: ServerState,
+    router_config: RouterConfig,
+    tls_config: RustlsConfig,
+    shutdown_timeout: Duration,
+) -> crate::Result<()> {
+    let handle = Handle::new();
+    let shutdown = tokio::spawn(shut_down_when_requested(
+        server_state.clone(),
+        handle.clone(),
+        shutdown_timeout,
+    ));
+    let result = axum_server::from_tcp_rustls(listener, tls_config)
+        .handle(handle)
+        .serve(create_with_config(server_state, router_config).into_make_service())
+        .await;
+    finish_shutdown(shutdown, result).await
+}
+
+/// Stops accepting connections through `handle` once shutdown was requested,
+/// while close frames are sent to users, instead of waiting for them to disconnect on their own.
+async fn shut_down_when_requested(server_state: ServerState, handle: Handle, timeout: Duration) {
+    server_state.shutdown_requested().await;
+    handle.graceful_shutdown(Some(timeout));
+    server_state.close_connections(timeout).await;
+}
+
+/// Waits for users to disconnect once server stopped, unless it failed before shutdown was requested.
+async fn finish_shutdown(
+    shutdown: JoinHandle<()>,
+    result: std::io::Result<()>,
+) -> crate::Result<()> {
+    if result.is_ok() {
+        shutdown.await?;
+    } else {
+        shutdown.abort();
+    }
+    Ok(result?)
+}
+
+/// Reads PEM encoded certificate chain and its private key for [`serve_with_tls`].
+///
+/// # Errors
+/// Fails with a message naming the file that can't be read, doesn't contain a certificate
+/// or a private key respectively, or if rustls rejects them.
+pub async fn load_tls_config(cert_path: &Path, key_path: &Path) -> crate::Result<RustlsConfig> {
+    let cert = tokio::fs::read(cert_path).await.map_err(|err| {
+        anyhow!(
+            "failed to read TLS certificate {}: {}",
+            cert_path.display(),
+            err
+        )
+    })?;
+    let key = tokio::fs::read(key_path).await.map_err(|err| {
+        anyhow!(
+            "failed to read TLS private key {}: {}",
+            key_path.display(),
+            err
+        )
+    })?;
+    let certs = rustls_pemfile::certs(&mut cert.as_slice())
+        .map_err(|err| anyhow!("malformed PEM file {}: {}", cert_path.display(), err))?;
+    if certs.is_empty() {
+        return Err(anyhow!(
+            "no certificate found in {}, expected a PEM file with `BEGIN CERTIFICATE` block",
+            cert_path.display()
+        ));
+    }
+    let has_key = rustls_pemfile::read_all(&mut key.as_slice())
+        .map_err(|err| anyhow!("malformed PEM file {}: {}", key_path.display(), err))?
+        .iter()
+        .any(|item| matches!(item, Item::RSAKey(_) | Item::PKCS8Key(_) | Item::ECKey(_)));
+    if !has_key {
+        return Err(anyhow!(
+            "no private key found in {}, expected a PEM file with `BEGIN PRIVATE KEY` block",
+            key_path.display()
+        ));
+    }
+    RustlsConfig::from_pem(cert, key).await.map_err(|err| {
+        anyhow!(
+            "invalid TLS certificate {} or private key {}: {}",
+            cert_path.display(),
+            key_path.display(),
+            err
         )
     })
 }
 
-pub fn create(server_state: ServerState) -> Router {
-    Router::new()
-        .route("/health", get(health_handler))
-        .route("/one-to-one", get(one_to_one_handler))
-        .route("/one-to-many", get(one_to_many_handler))
-        .route("/many-to-many", get(many_to_many_handler))
-        .with_state(server_state)
+/// Redirects every request to the same host and path over HTTPS on `https_port`,
+/// to be served on the plain HTTP port next to [`serve_with_tls`].
+pub fn https_redirect(https_port: u16) -> Router {
+    Router::new().fallback(move |Host(host): Host, uri: Uri| async move {
+        Redirect::permanent(&https_url(&host, https_port, &uri))
+    })
+}
+
+fn https_url(host: &str, https_port: u16, uri: &Uri) -> String {
+    // `Host` header carries the port of the plain HTTP listener, unless it's the default one
+    let host = host
+        .rsplit_once(':')
+        .filter(|&(_, port)| port.parse::<u16>().is_ok())
+        .map_or(host, |(host, _)| host);
+    let path = uri.path_and_query().map_or("/", PathAndQuery::as_str);
+    if https_port == 443 {
+        format!("https://{host}{path}")
+    } else {
+        format!("https://{host}:{https_port}{path}")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use axum::body::HttpBody;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_health_reports_topologies_that_panicked() {
+        let server_state = ServerState::default();
+        let healthy = health_handler(State(server_state.clone())).await;
+        assert_eq!(healthy.status(), StatusCode::OK);
+
+        server_state.one_to_many.mark_unhealthy();
+        let unhealthy = health_handler(State(server_state)).await;
+        assert_eq!(unhealthy.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body = unhealthy
+            .into_body()
+            .data()
+            .await
+            .expect("empty body")
+            .expect("failed to read body");
+        assert_eq!(
+            body,
+            r#"{"status":"unhealthy","unhealthy_topologies":["one-to-many"]}"#
+        );
+    }
+
+    #[test]
+    fn test_https_url_keeps_host_and_path() {
+        let uri: Uri = "/one-to-one?token=secret".parse().expect("invalid uri");
+        assert_eq!(
+            https_url("example.com:8080", 8443, &uri),
+            "https://example.com:8443/one-to-one?token=secret"
+        );
+        assert_eq!(
+            https_url("[::1]", 443, &uri),
+            "https://[::1]/one-to-one?token=secret"
+        );
+    }
 }
@@ -0,0 +1,290 @@
+//! In-memory log of connection events of each session, e.g. to audit who connected to which sessions
+//! for billing or abuse detection. Served by `GET /admin/sessions/{session_id}/events`, see [`crate::admin`].
+//!
+//! Each session keeps its latest [`ServerConfig::session_audit_max_events`] events,
+//! and events older than [`ServerConfig::session_ttl_secs`] are pruned.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use tokio::sync::RwLock;
+use wasm_peers_protocol::{SessionId, UserId};
+
+use crate::config::{ServerConfig, DEFAULT_SESSION_AUDIT_MAX_EVENTS, DEFAULT_SESSION_TTL_SECS};
+
+/// How often all sessions are checked for expired events, sessions with new events are checked every time.
+const PRUNE_INTERVAL_MILLIS: u64 = 60_000;
+
+/// Audit log shared by the hubs of all topologies and admin endpoints.
+pub type SharedSessionAuditLog = Arc<RwLock<SessionAuditLog>>;
+
+/// What a user did in a session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionEventType {
+    /// User asked to join the session
+    Join,
+    /// User that joined the session disconnected
+    Leave,
+    /// User sent SDP offer
+    Offer,
+    /// User sent SDP answer
+    Answer,
+    /// User sent ICE candidate
+    Ice,
+}
+
+/// Single entry of a session's audit log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct SessionEvent {
+    /// Milliseconds since UNIX epoch
+    pub timestamp: u64,
+    pub event_type: SessionEventType,
+    pub user_id: UserId,
+}
+
+/// Latest events of every session, each session's ones in a circular buffer.
+#[derive(Debug)]
+pub struct SessionAuditLog {
+    max_events: usize,
+    ttl_millis: u64,
+    sessions: HashMap<SessionId, VecDeque<SessionEvent>>,
+    /// Sessions each connected user joined, so that its disconnect is recorded as leaving them
+    memberships: HashMap<UserId, Vec<SessionId>>,
+    /// When all sessions are checked for expired events next
+    next_prune: u64,
+}
+
+impl Default for SessionAuditLog {
+    fn default() -> Self {
+        Self::new(
+            DEFAULT_SESSION_AUDIT_MAX_EVENTS,
+            Duration::from_secs(DEFAULT_SESSION_TTL_SECS),
+        )
+    }
+}
+
+impl SessionAuditLog {
+    /// Keeps up to `max_events` events of each session, none if it's zero, for `ttl` at most.
+    #[must_use]
+    pub fn new(max_events: usize, ttl: Duration) -> Self {
+        Self {
+            max_events,
+            ttl_millis: u64::try_from(ttl.as_millis()).unwrap_or(u64::MAX),
+            sessions: HashMap::new(),
+            memberships: HashMap::new(),
+            next_prune: 0,
+        }
+    }
+
+    /// Log limited by [`ServerConfig::session_audit_max_events`] and [`ServerConfig::session_ttl_secs`].
+    #[must_use]
+    pub fn from_config(config: &ServerConfig) -> Self {
+        Self::new(
+            config.session_audit_max_events,
+            Duration::from_secs(config.session_ttl_secs),
+        )
+    }
+
+    /// Records that `user_id` did `event_type` in session `session_id` just now.
+    pub fn record(&mut self, session_id: SessionId, user_id: UserId, event_type: SessionEventType) {
+        self.record_at(session_id, user_id, event_type, now_millis());
+    }
+
+    /// Records that `user_id` left all sessions it joined, once it disconnected.
+    pub fn record_disconnect(&mut self, user_id: UserId) {
+        self.record_disconnect_at(user_id, now_millis());
+    }
+
+    /// Events of session `session_id` that aren't expired yet, oldest first.
+    #[must_use]
+    pub fn events(&self, session_id: SessionId) -> Vec<SessionEvent> {
+        self.events_at(session_id, now_millis())
+    }
+
+    fn record_at(
+        &mut self,
+        session_id: SessionId,
+        user_id: UserId,
+        event_type: SessionEventType,
+        timestamp: u64,
+    ) {
+        if self.max_events == 0 {
+            return;
+        }
+        if event_type == SessionEventType::Join {
+            let joined = self.memberships.entry(user_id).or_default();
+            if !joined.contains(&session_id) {
+                joined.push(session_id);
+            }
+        }
+        let cutoff = timestamp.saturating_sub(self.ttl_millis);
+        let events = self.sessions.entry(session_id).or_default();
+        while events
+            .front()
+            .is_some_and(|event| event.timestamp < cutoff || events.len() >= self.max_events)
+        {
+            events.pop_front();
+        }
+        events.push_back(SessionEvent {
+            timestamp,
+            event_type,
+            user_id,
+        });
+        if timestamp >= self.next_prune {
+            self.prune(cutoff);
+            self.next_prune = timestamp.saturating_add(PRUNE_INTERVAL_MILLIS);
+        }
+    }
+
+    fn record_disconnect_at(&mut self, user_id: UserId, timestamp: u64) {
+        for session_id in self.memberships.remove(&user_id).unwrap_or_default() {
+            self.record_at(session_id, user_id, SessionEventType::Leave, timestamp);
+        }
+    }
+
+    fn events_at(&self, session_id: SessionId, timestamp: u64) -> Vec<SessionEvent> {
+        let cutoff = timestamp.saturating_sub(self.ttl_millis);
+        self.sessions
+            .get(&session_id)
+            .map(|events| {
+                events
+                    .iter()
+                    .filter(|event| event.timestamp >= cutoff)
+                    .copied()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Removes events recorded before `cutoff`, and sessions left without events.
+    fn prune(&mut self, cutoff: u64) {
+        self.sessions.retain(|_, events| {
+            while events.front().is_some_and(|event| event.timestamp < cutoff) {
+                events.pop_front();
+            }
+            !events.is_empty()
+        });
+    }
+}
+
+/// Milliseconds since UNIX epoch.
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| {
+            u64::try_from(duration.as_millis()).unwrap_or(u64::MAX)
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const TTL_MILLIS: u64 = 10_000;
+
+    fn log(max_events: usize) -> SessionAuditLog {
+        SessionAuditLog::new(max_events, Duration::from_millis(TTL_MILLIS))
+    }
+
+    fn event_types(events: &[SessionEvent]) -> Vec<SessionEventType> {
+        events.iter().map(|event| event.event_type).collect()
+    }
+
+    #[test]
+    fn test_oldest_events_are_dropped_once_session_is_full() {
+        let mut log = log(3);
+        let session_id = SessionId::new(1);
+        let user_id = UserId::new(1);
+        log.record_at(session_id, user_id, SessionEventType::Join, 1);
+        log.record_at(session_id, user_id, SessionEventType::Offer, 2);
+        log.record_at(session_id, user_id, SessionEventType::Ice, 3);
+        log.record_at(session_id, user_id, SessionEventType::Ice, 4);
+        let events = log.events_at(session_id, 4);
+        assert_eq!(
+            event_types(&events),
+            [
+                SessionEventType::Offer,
+                SessionEventType::Ice,
+                SessionEventType::Ice
+            ]
+        );
+        assert_eq!(events.first().map(|event| event.timestamp), Some(2));
+        assert!(log.events_at(SessionId::new(2), 4).is_empty());
+
+        let mut disabled = self::log(0);
+        disabled.record_at(session_id, user_id, SessionEventType::Join, 1);
+        assert!(disabled.events_at(session_id, 1).is_empty());
+    }
+
+    #[test]
+    fn test_disconnect_is_recorded_as_leaving_joined_sessions() {
+        let mut log = log(10);
+        let first_session = SessionId::new(1);
+        let second_session = SessionId::new(2);
+        let user_id = UserId::new(7);
+        log.record_at(first_session, user_id, SessionEventType::Join, 1);
+        log.record_at(first_session, user_id, SessionEventType::Join, 2);
+        log.record_at(second_session, user_id, SessionEventType::Join, 3);
+        log.record_at(second_session, UserId::new(8), SessionEventType::Answer, 4);
+        log.record_disconnect_at(user_id, 5);
+        assert_eq!(
+            event_types(&log.events_at(first_session, 5)),
+            [
+                SessionEventType::Join,
+                SessionEventType::Join,
+                SessionEventType::Leave
+            ]
+        );
+        assert_eq!(
+            log.events_at(second_session, 5).last(),
+            Some(&SessionEvent {
+                timestamp: 5,
+                event_type: SessionEventType::Leave,
+                user_id,
+            })
+        );
+        // user that never joined doesn't leave anything
+        log.record_disconnect_at(UserId::new(8), 6);
+        assert_eq!(log.events_at(second_session, 6).len(), 3);
+    }
+
+    #[test]
+    fn test_expired_events_are_pruned() {
+        let mut log = log(10);
+        let idle_session = SessionId::new(1);
+        let active_session = SessionId::new(2);
+        let user_id = UserId::new(1);
+        log.record_at(idle_session, user_id, SessionEventType::Join, 0);
+        log.record_at(active_session, user_id, SessionEventType::Join, 0);
+        assert_eq!(log.events_at(idle_session, TTL_MILLIS).len(), 1);
+        assert!(log.events_at(idle_session, TTL_MILLIS + 1).is_empty());
+
+        log.record_at(
+            active_session,
+            user_id,
+            SessionEventType::Offer,
+            PRUNE_INTERVAL_MILLIS,
+        );
+        assert_eq!(
+            event_types(&log.events_at(active_session, PRUNE_INTERVAL_MILLIS)),
+            [SessionEventType::Offer]
+        );
+        assert!(!log.sessions.contains_key(&idle_session));
+    }
+
+    #[test]
+    fn test_events_are_serialized_with_lowercase_types() {
+        let event = SessionEvent {
+            timestamp: 1_700_000_000_000,
+            event_type: SessionEventType::Ice,
+            user_id: UserId::new(3),
+        };
+        assert_eq!(
+            serde_json::to_string(&event).expect("failed to serialize event"),
+            r#"{"timestamp":1700000000000,"event_type":"ice","user_id":3}"#
+        );
+    }
+}
@@ -0,0 +1,17 @@
+//! Fields of the span of each websocket connection, see `tracing` feature.
+
+use wasm_peers_protocol::{SessionId, UserId};
+
+/// Records id assigned to the connected user in the span of its connection.
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub fn record_user(user_id: UserId) {
+    #[cfg(feature = "tracing")]
+    tracing::Span::current().record("user_id", user_id.into_inner());
+}
+
+/// Records session joined by the user in the span of its connection.
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub fn record_session(session_id: SessionId) {
+    #[cfg(feature = "tracing")]
+    tracing::Span::current().record("session_id", tracing::field::display(session_id.inner()));
+}
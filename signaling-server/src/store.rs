@@ -0,0 +1,236 @@
+//! Persistence of sessions across restarts of the server, e.g. on every deploy.
+//!
+//! Only membership of sessions is stored, connections are per process.
+//! Users of restored sessions are awaited to rejoin: once a user reconnects and sends `SessionJoin`
+//! for the session again, it takes over the place of one of them instead of joining as a brand-new user.
+//! One-to-one clients of `wasm-peers` do this on their own after connection with signaling server is lost.
+//!
+//! Stores can also be shared by multiple instances of the server, e.g. [`RedisStore`](crate::redis::RedisStore),
+//! in which case sessions are read from the store before every change instead of being restored.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::hash::BuildHasher;
+use std::io::{BufReader, ErrorKind};
+use std::ops::{Deref, DerefMut};
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use log::error;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tokio::sync::{RwLock, RwLockWriteGuard};
+use wasm_peers_protocol::{SessionId, UserId};
+
+use crate::relay::LocalRelay;
+
+/// Session that can be kept in a [`SessionStore`].
+pub trait StoredSession: Serialize + DeserializeOwned + Send + Sync + 'static {
+    /// Users that are members of the session
+    fn users(&self) -> Vec<UserId>;
+
+    /// Users restored from a store that haven't rejoined yet
+    fn rejoining_mut(&mut self) -> &mut HashSet<UserId>;
+
+    /// Resets state that only holds while users are connected, e.g. users waiting for a host,
+    /// called on sessions restored after the server restarted.
+    fn forget_connections(&mut self) {}
+}
+
+/// Where sessions of a single topology are kept, e.g. a file or a key-value database.
+pub trait SessionStore<S>: Send + Sync + 'static {
+    /// Reads sessions saved before the server restarted.
+    ///
+    /// # Errors
+    /// Server doesn't start if it fails.
+    fn load(&self) -> crate::Result<HashMap<SessionId, S>>;
+
+    /// Replaces saved sessions with `sessions`, called after every change of them.
+    ///
+    /// # Errors
+    /// Errors are logged, sessions are saved again on the next change.
+    fn save(&self, sessions: &HashMap<SessionId, S>) -> crate::Result<()>;
+
+    /// Called before sessions are read or changed. Stores shared by multiple instances of the server
+    /// keep other instances from changing sessions until [`SessionStore::unlock`] and return the current ones,
+    /// which replace sessions kept in memory. Others return `None`, as only this instance changes sessions.
+    ///
+    /// # Errors
+    /// Errors are logged, sessions kept in memory are used instead.
+    fn lock(&self) -> crate::Result<Option<HashMap<SessionId, S>>> {
+        Ok(None)
+    }
+
+    /// Called once sessions locked with [`SessionStore::lock`] were saved or left as they were.
+    fn unlock(&self) {}
+}
+
+/// Keeps sessions as JSON in a single file, rewritten on every change.
+#[derive(Debug, Clone)]
+pub struct JsonFileStore {
+    path: PathBuf,
+}
+
+impl JsonFileStore {
+    /// Creates store keeping sessions in file at `path`, which doesn't have to exist yet.
+    #[must_use]
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl<S: StoredSession> SessionStore<S> for JsonFileStore {
+    fn load(&self) -> crate::Result<HashMap<SessionId, S>> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(HashMap::new()),
+            Err(err) => {
+                return Err(anyhow!(
+                    "failed to read sessions from {}: {}",
+                    self.path.display(),
+                    err
+                ))
+            }
+        };
+        serde_json::from_reader(BufReader::new(file))
+            .map_err(|err| anyhow!("malformed sessions file {}: {}", self.path.display(), err))
+    }
+
+    fn save(&self, sessions: &HashMap<SessionId, S>) -> crate::Result<()> {
+        // written next to the file and renamed, so that a crash never leaves it half-written
+        let temp_path = self.path.with_extension("tmp");
+        fs::write(&temp_path, serde_json::to_vec(sessions)?)?;
+        fs::rename(&temp_path, &self.path)?;
+        Ok(())
+    }
+}
+
+/// Loads sessions from `store` into `sessions`, with all of their users awaited to rejoin.
+pub(crate) fn restore<S: StoredSession>(
+    store: &dyn SessionStore<S>,
+    sessions: &RwLock<HashMap<SessionId, S>>,
+) -> crate::Result<()> {
+    let mut restored = store.load()?;
+    for session in restored.values_mut() {
+        session.forget_connections();
+        let users = session.users();
+        session.rejoining_mut().extend(users);
+    }
+    sessions
+        .try_write()
+        .map_err(|_locked| anyhow!("sessions are in use, they can't be restored"))?
+        .extend(restored);
+    Ok(())
+}
+
+/// Sessions locked with [`lock`], unlocked in their store once dropped.
+pub(crate) struct LockedSessions<'a, S: 'static> {
+    sessions: RwLockWriteGuard<'a, HashMap<SessionId, S>>,
+    store: Option<&'a dyn SessionStore<S>>,
+}
+
+impl<S> Deref for LockedSessions<'_, S> {
+    type Target = HashMap<SessionId, S>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.sessions
+    }
+}
+
+impl<S> DerefMut for LockedSessions<'_, S> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.sessions
+    }
+}
+
+impl<S> Drop for LockedSessions<'_, S> {
+    fn drop(&mut self) {
+        if let Some(store) = self.store {
+            store.unlock();
+        }
+    }
+}
+
+/// Locks `sessions` in memory and in `store`, if there is one, replacing them with the ones
+/// in the store if it's shared by multiple instances of the server.
+pub(crate) async fn lock<'a, S: StoredSession>(
+    store: Option<&'a dyn SessionStore<S>>,
+    sessions: &'a RwLock<HashMap<SessionId, S>>,
+) -> LockedSessions<'a, S> {
+    let mut locked = LockedSessions {
+        sessions: sessions.write().await,
+        store,
+    };
+    if let Some(store) = store {
+        match store.lock() {
+            Ok(Some(current)) => *locked = current,
+            Ok(None) => {}
+            Err(err) => error!("failed to lock sessions, using the ones in memory: {err}"),
+        }
+    }
+    locked
+}
+
+/// Saves `sessions` to `store`, if there is one, logging failures.
+pub(crate) fn save<S: StoredSession>(
+    store: Option<&dyn SessionStore<S>>,
+    sessions: &HashMap<SessionId, S>,
+) {
+    if let Some(store) = store {
+        if let Err(err) = store.save(sessions) {
+            error!("failed to save sessions: {err}");
+        }
+    }
+}
+
+/// Relay allocating ids after the ones of users in restored `sessions`,
+/// so that new users aren't mistaken for the ones awaited to rejoin.
+///
+/// # Errors
+/// Fails if `sessions` are locked.
+pub fn relay_after_restored<S: StoredSession, H: BuildHasher>(
+    sessions: &RwLock<HashMap<SessionId, S, H>>,
+) -> crate::Result<LocalRelay> {
+    let last_user_id = sessions
+        .try_read()
+        .map_err(|_locked| anyhow!("sessions are in use"))?
+        .values()
+        .flat_map(StoredSession::users)
+        .max();
+    Ok(LocalRelay::after(last_user_id))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::many_to_many::Session;
+
+    #[test]
+    fn test_json_file_store_round_trips_sessions() {
+        let path =
+            std::env::temp_dir().join(format!("wasm-peers-sessions-{}.json", std::process::id()));
+        let store = JsonFileStore::new(&path);
+        assert!(SessionStore::<Session>::load(&store)
+            .expect("missing file should be empty")
+            .is_empty());
+
+        let session_id = SessionId::new(u128::MAX);
+        let mut sessions = HashMap::new();
+        sessions.insert(
+            session_id,
+            Session {
+                users: [UserId::new(1), UserId::new(2)].into_iter().collect(),
+                ..Session::default()
+            },
+        );
+        store.save(&sessions).expect("failed to save sessions");
+
+        let restored = RwLock::new(HashMap::new());
+        restore::<Session>(&store, &restored).expect("failed to restore sessions");
+        let restored = restored.into_inner();
+        let session = restored.get(&session_id).expect("session wasn't restored");
+        assert_eq!(session.users.len(), 2);
+        assert_eq!(session.rejoining, session.users);
+        fs::remove_file(path).expect("failed to remove sessions file");
+    }
+}
@@ -0,0 +1,49 @@
+//! Sanity checks of SDP and ICE candidates, so that malformed or oversized payloads
+//! are turned away by the server instead of being passed on to other users.
+
+use wasm_peers_protocol::{one_to_many, one_to_one, SessionId, UserId};
+
+use crate::config::PayloadLimits;
+
+/// Signal message whose payload is checked by [`SignalingHub`](crate::hub::SignalingHub) before it's routed.
+pub trait Validated: Sized {
+    /// Reason to reject the message, `None` if it can be routed.
+    fn rejection(&self, limits: &PayloadLimits) -> Option<&'static str>;
+
+    /// Error telling `user_id` that its message in session `session_id` was rejected.
+    fn error(session_id: SessionId, user_id: UserId, reason: String) -> Self;
+}
+
+impl Validated for one_to_one::SignalMessage {
+    fn rejection(&self, limits: &PayloadLimits) -> Option<&'static str> {
+        match *self {
+            Self::SdpOffer(_, ref sdp) | Self::SdpAnswer(_, ref sdp) => limits.sdp_rejection(sdp),
+            Self::IceCandidate(_, ref candidate) => {
+                limits.candidate_rejection(&candidate.candidate)
+            }
+            _ => None,
+        }
+    }
+
+    fn error(session_id: SessionId, _user_id: UserId, reason: String) -> Self {
+        Self::Error(session_id, reason)
+    }
+}
+
+impl Validated for one_to_many::SignalMessage {
+    fn rejection(&self, limits: &PayloadLimits) -> Option<&'static str> {
+        match *self {
+            Self::SdpOffer(_, _, ref sdp) | Self::SdpAnswer(_, _, ref sdp) => {
+                limits.sdp_rejection(sdp)
+            }
+            Self::IceCandidate(_, _, ref candidate) => {
+                limits.candidate_rejection(&candidate.candidate)
+            }
+            _ => None,
+        }
+    }
+
+    fn error(session_id: SessionId, user_id: UserId, reason: String) -> Self {
+        Self::Error(session_id, user_id, reason)
+    }
+}
@@ -0,0 +1,68 @@
+//! Negotiation of the protocol version, so that users speaking an incompatible one are told so
+//! instead of their messages failing to decode.
+
+use wasm_peers_protocol::{
+    one_to_many, one_to_one, PROTOCOL_MAJOR_VERSION, PROTOCOL_MINOR_VERSION,
+};
+
+/// Reason of closing connection of a user announcing an incompatible major version of the protocol.
+pub const INCOMPATIBLE_VERSION_REASON: &str = "incompatible protocol version";
+
+/// Signal message announcing the protocol version, answered by [`SignalingHub`](crate::hub::SignalingHub)
+/// instead of being routed.
+pub trait Versioned: Sized {
+    /// Major and minor version announced by the user, `None` for other messages.
+    fn announced_version(&self) -> Option<(u8, u8)>;
+
+    /// Version of the protocol spoken by this server, sent back to users announcing a compatible one.
+    fn server_version() -> Self;
+
+    /// Tells user announcing an incompatible version which one this server speaks.
+    fn version_mismatch() -> Self;
+}
+
+impl Versioned for one_to_one::SignalMessage {
+    fn announced_version(&self) -> Option<(u8, u8)> {
+        match *self {
+            Self::ProtocolVersion { major, minor } => Some((major, minor)),
+            _ => None,
+        }
+    }
+
+    fn server_version() -> Self {
+        Self::ProtocolVersion {
+            major: PROTOCOL_MAJOR_VERSION,
+            minor: PROTOCOL_MINOR_VERSION,
+        }
+    }
+
+    fn version_mismatch() -> Self {
+        Self::ProtocolVersionMismatch {
+            server_major: PROTOCOL_MAJOR_VERSION,
+            server_minor: PROTOCOL_MINOR_VERSION,
+        }
+    }
+}
+
+impl Versioned for one_to_many::SignalMessage {
+    fn announced_version(&self) -> Option<(u8, u8)> {
+        match *self {
+            Self::ProtocolVersion { major, minor } => Some((major, minor)),
+            _ => None,
+        }
+    }
+
+    fn server_version() -> Self {
+        Self::ProtocolVersion {
+            major: PROTOCOL_MAJOR_VERSION,
+            minor: PROTOCOL_MINOR_VERSION,
+        }
+    }
+
+    fn version_mismatch() -> Self {
+        Self::ProtocolVersionMismatch {
+            server_major: PROTOCOL_MAJOR_VERSION,
+            server_minor: PROTOCOL_MINOR_VERSION,
+        }
+    }
+}
@@ -0,0 +1,2637 @@
+use std::future::Future;
+use std::net::{SocketAddr, TcpListener};
+use std::time::Duration;
+
+use futures_util::{SinkExt, StreamExt};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+use wasm_peers_protocol::{
+    one_to_many, one_to_one, IceCandidate, JoinError, JoinIntent, SessionId, SessionInfo, StableId,
+    TopologyType, UserId,
+};
+use wasm_peers_signaling_server::router::{self, ServerState};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Starts the server on an ephemeral port.
+fn spawn_server() -> (ServerState, SocketAddr) {
+    let server_state = ServerState::default();
+    let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind listener");
+    let address = listener.local_addr().expect("listener has no address");
+    let server = axum::Server::from_tcp(listener)
+        .expect("failed to create server")
+        .serve(router::create(server_state.clone()).into_make_service());
+    tokio::spawn(server);
+    (server_state, address)
+}
+
+/// Sends a plain HTTP `GET` request for `path`, returning the whole response.
+async fn http_get(address: SocketAddr, path: &str) -> String {
+    let mut stream = TcpStream::connect(address)
+        .await
+        .expect("failed to connect to server");
+    let request = format!("GET {path} HTTP/1.1\r\nHost: {address}\r\nConnection: close\r\n\r\n");
+    tokio::io::AsyncWriteExt::write_all(&mut stream, request.as_bytes())
+        .await
+        .expect("failed to send request");
+    let mut response = String::new();
+    tokio::io::AsyncReadExt::read_to_string(&mut stream, &mut response)
+        .await
+        .expect("failed to read response");
+    response
+}
+
+struct Client {
+    ws: WebSocketStream<MaybeTlsStream<TcpStream>>,
+}
+
+impl Client {
+    async fn connect(address: SocketAddr, endpoint: &str) -> Self {
+        let (ws, _response) =
+            tokio_tungstenite::connect_async(format!("ws://{address}/{endpoint}"))
+                .await
+                .expect("failed to connect to signaling server");
+        Self { ws }
+    }
+
+    async fn send(&mut self, message: &impl Serialize) {
+        let message = rmp_serde::to_vec(message).expect("failed to serialize message");
+        self.ws
+            .send(Message::Binary(message))
+            .await
+            .expect("failed to send message");
+    }
+
+    async fn recv<M: DeserializeOwned>(&mut self) -> M {
+        let message = tokio::time::timeout(TIMEOUT, self.ws.next())
+            .await
+            .expect("no message received in time")
+            .expect("connection closed")
+            .expect("websocket error");
+        rmp_serde::from_slice(&message.into_data()).expect("failed to deserialize message")
+    }
+
+    async fn assert_no_message(&mut self) {
+        let message = tokio::time::timeout(Duration::from_millis(200), self.ws.next()).await;
+        assert!(message.is_err(), "unexpected message: {message:?}");
+    }
+
+    async fn close(mut self) {
+        self.ws
+            .close(None)
+            .await
+            .expect("failed to close connection");
+    }
+}
+
+/// Waits until `condition` holds, as server notices disconnects asynchronously.
+async fn eventually<F, Fut>(mut condition: F)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = bool>,
+{
+    for _ in 0..100 {
+        if condition().await {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    panic!("condition not met in time");
+}
+
+const OFFER: &str = "v=0\r\no=- 1 1 IN IP4 127.0.0.1\r\ns=-\r\nt=0 0\r\n";
+const ANSWER: &str = "v=0\r\no=- 2 1 IN IP4 127.0.0.1\r\ns=-\r\nt=0 0\r\n";
+
+/// Host candidate with `foundation`, so that candidates of different users can be told apart.
+fn ice_candidate(foundation: &str) -> IceCandidate {
+    IceCandidate {
+        candidate: format!("candidate:{foundation} 1 udp 2122260223 192.168.0.2 54321 typ host"),
+        sdp_mid: Some("0".to_owned()),
+        sdp_m_line_index: Some(0),
+    }
+}
+
+mod one_to_one_topology {
+    use one_to_one::SignalMessage;
+
+    use super::*;
+
+    /// Joins two users into session and returns them as (host, other).
+    async fn join_pair(address: SocketAddr, session_id: SessionId) -> (Client, Client) {
+        let mut first = Client::connect(address, "one-to-one").await;
+        let mut second = Client::connect(address, "one-to-one").await;
+        first.send(&SignalMessage::SessionJoin(session_id)).await;
+        // make sure first user is in session before second one joins
+        first.assert_no_message().await;
+        second.send(&SignalMessage::SessionJoin(session_id)).await;
+
+        let (
+            SignalMessage::SessionReady(first_session_id, first_is_host),
+            SignalMessage::SessionReady(second_session_id, second_is_host),
+        ) = (first.recv().await, second.recv().await)
+        else {
+            panic!("expected SessionReady for both users");
+        };
+        assert_eq!(first_session_id, session_id);
+        assert_eq!(second_session_id, session_id);
+        assert_ne!(
+            first_is_host, second_is_host,
+            "exactly one user should be host"
+        );
+        if first_is_host {
+            (first, second)
+        } else {
+            (second, first)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_offer_answer_and_candidates_are_relayed() {
+        let (_server_state, address) = spawn_server();
+        let session_id = SessionId::new(1);
+        let (mut host, mut other) = join_pair(address, session_id).await;
+
+        host.send(&SignalMessage::SdpOffer(session_id, OFFER.to_owned()))
+            .await;
+        assert_eq!(
+            other.recv::<SignalMessage>().await,
+            SignalMessage::SdpOffer(session_id, OFFER.to_owned())
+        );
+
+        other
+            .send(&SignalMessage::SdpAnswer(session_id, ANSWER.to_owned()))
+            .await;
+        assert_eq!(
+            host.recv::<SignalMessage>().await,
+            SignalMessage::SdpAnswer(session_id, ANSWER.to_owned())
+        );
+
+        host.send(&SignalMessage::IceCandidate(
+            session_id,
+            ice_candidate("host"),
+        ))
+        .await;
+        other
+            .send(&SignalMessage::IceCandidate(
+                session_id,
+                ice_candidate("other"),
+            ))
+            .await;
+        assert_eq!(
+            other.recv::<SignalMessage>().await,
+            SignalMessage::IceCandidate(session_id, ice_candidate("host"))
+        );
+        assert_eq!(
+            host.recv::<SignalMessage>().await,
+            SignalMessage::IceCandidate(session_id, ice_candidate("other"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_malformed_and_oversized_payloads_are_not_relayed() {
+        let (_server_state, address) = spawn_server();
+        let session_id = SessionId::new(1);
+        let (mut host, mut other) = join_pair(address, session_id).await;
+
+        let oversized = format!("{OFFER}a={}\r\n", "a".repeat(100 * 1024));
+        for (message, error) in [
+            (
+                SignalMessage::SdpOffer(session_id, "offer".to_owned()),
+                "SdpOffer rejected: SDP doesn't start with version line",
+            ),
+            (
+                SignalMessage::SdpOffer(session_id, oversized),
+                "SdpOffer rejected: SDP is too large",
+            ),
+            (
+                SignalMessage::IceCandidate(
+                    session_id,
+                    IceCandidate {
+                        candidate: "host".to_owned(),
+                        ..ice_candidate("host")
+                    },
+                ),
+                "IceCandidate rejected: malformed ICE candidate",
+            ),
+        ] {
+            host.send(&message).await;
+            assert_eq!(
+                host.recv::<SignalMessage>().await,
+                SignalMessage::Error(session_id, error.to_owned())
+            );
+            other.assert_no_message().await;
+        }
+
+        // valid payloads are still relayed
+        host.send(&SignalMessage::SdpOffer(session_id, OFFER.to_owned()))
+            .await;
+        assert_eq!(
+            other.recv::<SignalMessage>().await,
+            SignalMessage::SdpOffer(session_id, OFFER.to_owned())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_third_user_is_rejected() {
+        let (server_state, address) = spawn_server();
+        let session_id = SessionId::new(1);
+        let (mut host, mut other) = join_pair(address, session_id).await;
+
+        let mut third = Client::connect(address, "one-to-one").await;
+        third.send(&SignalMessage::SessionJoin(session_id)).await;
+        assert!(matches!(
+            third.recv::<SignalMessage>().await,
+            SignalMessage::Error(error_session_id, _) if error_session_id == session_id
+        ));
+        host.assert_no_message().await;
+        other.assert_no_message().await;
+
+        // session still relays between the original pair
+        host.send(&SignalMessage::SdpOffer(session_id, OFFER.to_owned()))
+            .await;
+        assert_eq!(
+            other.recv::<SignalMessage>().await,
+            SignalMessage::SdpOffer(session_id, OFFER.to_owned())
+        );
+        third.assert_no_message().await;
+        let sessions = server_state.one_to_one_sessions().read().await;
+        let session = sessions.get(&session_id).expect("session was removed");
+        assert!(session.first.is_some() && session.second.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_join_intent_is_checked_against_existing_sessions() {
+        let (server_state, address) = spawn_server();
+        let session_id = SessionId::new(1);
+        let mut first = Client::connect(address, "one-to-one").await;
+        first
+            .send(&SignalMessage::SessionJoinWithIntent(
+                session_id,
+                JoinIntent::Join,
+            ))
+            .await;
+        assert_eq!(
+            first.recv::<SignalMessage>().await,
+            SignalMessage::SessionJoinError(session_id, JoinError::NoSuchSession)
+        );
+        assert!(server_state.one_to_one_sessions().read().await.is_empty());
+
+        first
+            .send(&SignalMessage::SessionJoinWithIntent(
+                session_id,
+                JoinIntent::Create,
+            ))
+            .await;
+        first.assert_no_message().await;
+        let mut second = Client::connect(address, "one-to-one").await;
+        second
+            .send(&SignalMessage::SessionJoinWithIntent(
+                session_id,
+                JoinIntent::Create,
+            ))
+            .await;
+        assert_eq!(
+            second.recv::<SignalMessage>().await,
+            SignalMessage::SessionJoinError(session_id, JoinError::AlreadyExists)
+        );
+        first.assert_no_message().await;
+
+        second
+            .send(&SignalMessage::SessionJoinWithIntent(
+                session_id,
+                JoinIntent::Join,
+            ))
+            .await;
+        assert!(matches!(
+            first.recv::<SignalMessage>().await,
+            SignalMessage::SessionReady(ready_session_id, _) if ready_session_id == session_id
+        ));
+        assert!(matches!(
+            second.recv::<SignalMessage>().await,
+            SignalMessage::SessionReady(ready_session_id, _) if ready_session_id == session_id
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_session_is_removed_once_both_users_disconnect() {
+        let (server_state, address) = spawn_server();
+        let session_id = SessionId::new(1);
+        let (host, other) = join_pair(address, session_id).await;
+
+        host.close().await;
+        eventually(|| async {
+            server_state
+                .one_to_one_sessions()
+                .read()
+                .await
+                .get(&session_id)
+                .is_some_and(|session| session.first.is_some() != session.second.is_some())
+        })
+        .await;
+
+        other.close().await;
+        eventually(|| async { server_state.one_to_one_sessions().read().await.is_empty() }).await;
+    }
+
+    #[tokio::test]
+    async fn test_first_user_rejoins_while_second_stays() {
+        let (server_state, address) = spawn_server();
+        let session_id = SessionId::new(1);
+        let mut first = Client::connect(address, "one-to-one").await;
+        let mut second = Client::connect(address, "one-to-one").await;
+        first.send(&SignalMessage::SessionJoin(session_id)).await;
+        first.assert_no_message().await;
+        second.send(&SignalMessage::SessionJoin(session_id)).await;
+        let _ready: SignalMessage = first.recv().await;
+        let _ready: SignalMessage = second.recv().await;
+        let second_id = server_state
+            .one_to_one_sessions()
+            .read()
+            .await
+            .get(&session_id)
+            .and_then(|session| session.second)
+            .expect("second user isn't in session");
+
+        first.close().await;
+        eventually(|| async {
+            server_state
+                .one_to_one_sessions()
+                .read()
+                .await
+                .get(&session_id)
+                .is_some_and(|session| session.first.is_none())
+        })
+        .await;
+
+        // rejoining user takes the free place instead of the one of the user that stayed
+        let mut rejoined = Client::connect(address, "one-to-one").await;
+        rejoined.send(&SignalMessage::SessionJoin(session_id)).await;
+        assert!(matches!(
+            rejoined.recv::<SignalMessage>().await,
+            SignalMessage::SessionReady(ready_session_id, _) if ready_session_id == session_id
+        ));
+        assert!(matches!(
+            second.recv::<SignalMessage>().await,
+            SignalMessage::SessionReady(ready_session_id, _) if ready_session_id == session_id
+        ));
+        {
+            let sessions = server_state.one_to_one_sessions().read().await;
+            let session = sessions.get(&session_id).expect("session was removed");
+            assert!(session.first.is_some());
+            assert_eq!(session.second, Some(second_id));
+            assert!(!session.offer_received);
+        }
+
+        rejoined
+            .send(&SignalMessage::SdpOffer(session_id, OFFER.to_owned()))
+            .await;
+        assert_eq!(
+            second.recv::<SignalMessage>().await,
+            SignalMessage::SdpOffer(session_id, OFFER.to_owned())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_one_connection_is_paired_in_several_sessions() {
+        let (server_state, address) = spawn_server();
+        let mut shared = Client::connect(address, "one-to-one").await;
+        let mut partners = Vec::new();
+        for session_id in [SessionId::new(1), SessionId::new(2)] {
+            shared.send(&SignalMessage::SessionJoin(session_id)).await;
+            shared.assert_no_message().await;
+            let mut partner = Client::connect(address, "one-to-one").await;
+            partner.send(&SignalMessage::SessionJoin(session_id)).await;
+            assert!(matches!(
+                shared.recv::<SignalMessage>().await,
+                SignalMessage::SessionReady(ready_session_id, _) if ready_session_id == session_id
+            ));
+            let _ready: SignalMessage = partner.recv().await;
+            partners.push(partner);
+        }
+
+        // joining the same session again doesn't pair the connection with itself
+        shared
+            .send(&SignalMessage::SessionJoin(SessionId::new(1)))
+            .await;
+        assert!(matches!(
+            shared.recv::<SignalMessage>().await,
+            SignalMessage::Error(error_session_id, _) if error_session_id == SessionId::new(1)
+        ));
+
+        // each session relays only to its own partner
+        shared
+            .send(&SignalMessage::SdpOffer(
+                SessionId::new(2),
+                OFFER.to_owned(),
+            ))
+            .await;
+        assert_eq!(
+            partners[1].recv::<SignalMessage>().await,
+            SignalMessage::SdpOffer(SessionId::new(2), OFFER.to_owned())
+        );
+        partners[0].assert_no_message().await;
+
+        // disconnecting leaves every session of the connection
+        shared.close().await;
+        eventually(|| async {
+            server_state
+                .one_to_one_sessions()
+                .read()
+                .await
+                .values()
+                .all(|session| session.first.is_some() != session.second.is_some())
+        })
+        .await;
+        for partner in partners {
+            partner.close().await;
+        }
+        eventually(|| async { server_state.one_to_one_sessions().read().await.is_empty() }).await;
+    }
+
+    #[tokio::test]
+    async fn test_session_list_reports_open_sessions() {
+        let (_server_state, address) = spawn_server();
+        let (_host, _other) = join_pair(address, SessionId::new(2)).await;
+        let mut waiting = Client::connect(address, "one-to-one").await;
+        waiting
+            .send(&SignalMessage::SessionJoin(SessionId::new(1)))
+            .await;
+        waiting.assert_no_message().await;
+
+        let mut lobby = Client::connect(address, "one-to-one").await;
+        lobby.send(&SignalMessage::SessionListRequest).await;
+        assert_eq!(
+            lobby.recv::<SignalMessage>().await,
+            SignalMessage::SessionListResponse(vec![
+                SessionInfo {
+                    session_id: SessionId::new(1),
+                    peer_count: 1,
+                    topology: TopologyType::OneToOne,
+                },
+                SessionInfo {
+                    session_id: SessionId::new(2),
+                    peer_count: 2,
+                    topology: TopologyType::OneToOne,
+                },
+            ])
+        );
+        waiting.assert_no_message().await;
+    }
+}
+
+mod one_to_many_topology {
+    use one_to_many::SignalMessage;
+
+    use super::*;
+
+    /// Relays offer, answer and candidate between host and client,
+    /// returns id of the host as seen by the client.
+    async fn assert_relayed(
+        session_id: SessionId,
+        host: &mut Client,
+        client: &mut Client,
+        client_id: UserId,
+    ) -> UserId {
+        host.send(&SignalMessage::SdpOffer(
+            session_id,
+            client_id,
+            OFFER.to_owned(),
+        ))
+        .await;
+        let SignalMessage::SdpOffer(offer_session_id, host_id, offer) = client.recv().await else {
+            panic!("expected SdpOffer");
+        };
+        assert_eq!((offer_session_id, offer.as_str()), (session_id, OFFER));
+
+        client
+            .send(&SignalMessage::SdpAnswer(
+                session_id,
+                host_id,
+                ANSWER.to_owned(),
+            ))
+            .await;
+        assert_eq!(
+            host.recv::<SignalMessage>().await,
+            SignalMessage::SdpAnswer(session_id, client_id, ANSWER.to_owned())
+        );
+
+        client
+            .send(&SignalMessage::IceCandidate(
+                session_id,
+                host_id,
+                ice_candidate("client"),
+            ))
+            .await;
+        assert_eq!(
+            host.recv::<SignalMessage>().await,
+            SignalMessage::IceCandidate(session_id, client_id, ice_candidate("client"))
+        );
+        host_id
+    }
+
+    /// Returns id of the peer that `user` was told about.
+    async fn recv_session_ready(user: &mut Client, session_id: SessionId) -> UserId {
+        let SignalMessage::SessionReady(ready_session_id, peer_id) = user.recv().await else {
+            panic!("expected SessionReady");
+        };
+        assert_eq!(ready_session_id, session_id);
+        peer_id
+    }
+
+    #[tokio::test]
+    async fn test_host_first_is_told_about_each_client() {
+        let (_server_state, address) = spawn_server();
+        let session_id = SessionId::new(1);
+        let mut host = Client::connect(address, "one-to-many").await;
+        host.send(&SignalMessage::SessionJoin(session_id, true))
+            .await;
+        host.assert_no_message().await;
+
+        let mut first_client = Client::connect(address, "one-to-many").await;
+        first_client
+            .send(&SignalMessage::SessionJoin(session_id, false))
+            .await;
+        let first_client_id = recv_session_ready(&mut host, session_id).await;
+        let host_id = recv_session_ready(&mut first_client, session_id).await;
+        let mut second_client = Client::connect(address, "one-to-many").await;
+        second_client
+            .send(&SignalMessage::SessionJoin(session_id, false))
+            .await;
+        let second_client_id = recv_session_ready(&mut host, session_id).await;
+        assert_eq!(
+            recv_session_ready(&mut second_client, session_id).await,
+            host_id
+        );
+        assert_ne!(first_client_id, second_client_id);
+
+        // messages are routed only to their recipient
+        assert_eq!(
+            assert_relayed(session_id, &mut host, &mut first_client, first_client_id).await,
+            host_id
+        );
+        second_client.assert_no_message().await;
+        assert_eq!(
+            assert_relayed(session_id, &mut host, &mut second_client, second_client_id).await,
+            host_id
+        );
+        first_client.assert_no_message().await;
+    }
+
+    #[tokio::test]
+    async fn test_client_first_is_announced_once_host_joins() {
+        let (_server_state, address) = spawn_server();
+        let session_id = SessionId::new(1);
+        let mut client = Client::connect(address, "one-to-many").await;
+        client
+            .send(&SignalMessage::SessionJoin(session_id, false))
+            .await;
+        client.assert_no_message().await;
+
+        let mut host = Client::connect(address, "one-to-many").await;
+        host.send(&SignalMessage::SessionJoin(session_id, true))
+            .await;
+        let client_id = recv_session_ready(&mut host, session_id).await;
+        let host_id = recv_session_ready(&mut client, session_id).await;
+        assert_eq!(
+            assert_relayed(session_id, &mut host, &mut client, client_id).await,
+            host_id
+        );
+    }
+
+    #[tokio::test]
+    async fn test_every_host_is_connected_with_each_client_once_first_host_allowed_them() {
+        let (server_state, address) = spawn_server();
+        let session_id = SessionId::new(1);
+        let mut first_host = Client::connect(address, "one-to-many").await;
+        first_host
+            .send(&SignalMessage::SessionJoinHost(
+                session_id,
+                true,
+                JoinIntent::Create,
+            ))
+            .await;
+        first_host.assert_no_message().await;
+        let mut second_host = Client::connect(address, "one-to-many").await;
+        second_host
+            .send(&SignalMessage::SessionJoin(session_id, true))
+            .await;
+        second_host.assert_no_message().await;
+
+        let mut client = Client::connect(address, "one-to-many").await;
+        client
+            .send(&SignalMessage::SessionJoin(session_id, false))
+            .await;
+        let client_id = recv_session_ready(&mut first_host, session_id).await;
+        assert_eq!(
+            recv_session_ready(&mut second_host, session_id).await,
+            client_id
+        );
+        let host_ids = [
+            recv_session_ready(&mut client, session_id).await,
+            recv_session_ready(&mut client, session_id).await,
+        ];
+        assert_ne!(host_ids[0], host_ids[1]);
+        let second_host_id =
+            assert_relayed(session_id, &mut second_host, &mut client, client_id).await;
+        assert!(host_ids.contains(&second_host_id));
+
+        // client stays connected with the remaining host
+        second_host.close().await;
+        assert_eq!(
+            client.recv::<SignalMessage>().await,
+            SignalMessage::SessionLeave(session_id, second_host_id)
+        );
+        first_host.assert_no_message().await;
+        let sessions = server_state.one_to_many_sessions().read().await;
+        assert_eq!(sessions[&session_id].hosts.len(), 1);
+        assert!(sessions[&session_id].pending_users.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_second_host_is_refused_unless_first_host_allowed_it() {
+        let (server_state, address) = spawn_server();
+        let session_id = SessionId::new(1);
+        let mut first_host = Client::connect(address, "one-to-many").await;
+        first_host
+            .send(&SignalMessage::SessionJoinHost(
+                session_id,
+                false,
+                JoinIntent::CreateOrJoin,
+            ))
+            .await;
+        let mut second_host = Client::connect(address, "one-to-many").await;
+        second_host
+            .send(&SignalMessage::SessionJoin(session_id, true))
+            .await;
+
+        let mut client = Client::connect(address, "one-to-many").await;
+        client
+            .send(&SignalMessage::SessionJoin(session_id, false))
+            .await;
+        let client_id = recv_session_ready(&mut first_host, session_id).await;
+        recv_session_ready(&mut client, session_id).await;
+        client.assert_no_message().await;
+        second_host.assert_no_message().await;
+        let sessions = server_state.one_to_many_sessions().read().await;
+        assert_eq!(sessions[&session_id].hosts.len(), 1);
+        assert!(sessions[&session_id].users.contains(&client_id));
+    }
+
+    #[tokio::test]
+    async fn test_client_joining_missing_session_is_refused() {
+        let (server_state, address) = spawn_server();
+        let session_id = SessionId::new(1);
+        let mut client = Client::connect(address, "one-to-many").await;
+        client
+            .send(&SignalMessage::SessionJoinWithIntent(
+                session_id,
+                false,
+                JoinIntent::Join,
+            ))
+            .await;
+        assert_eq!(
+            client.recv::<SignalMessage>().await,
+            SignalMessage::SessionJoinError(session_id, JoinError::NoSuchSession)
+        );
+        assert!(server_state.one_to_many_sessions().read().await.is_empty());
+
+        let mut host = Client::connect(address, "one-to-many").await;
+        host.send(&SignalMessage::SessionJoinWithIntent(
+            session_id,
+            true,
+            JoinIntent::Create,
+        ))
+        .await;
+        host.assert_no_message().await;
+        client
+            .send(&SignalMessage::SessionJoinWithIntent(
+                session_id,
+                false,
+                JoinIntent::Join,
+            ))
+            .await;
+        let client_id = recv_session_ready(&mut host, session_id).await;
+        let host_id = recv_session_ready(&mut client, session_id).await;
+        assert_eq!(
+            assert_relayed(session_id, &mut host, &mut client, client_id).await,
+            host_id
+        );
+    }
+
+    #[tokio::test]
+    async fn test_all_queued_clients_are_connected_once_host_joins() {
+        let (_server_state, address) = spawn_server();
+        let session_id = SessionId::new(1);
+        let mut clients = Vec::new();
+        for _ in 0..3 {
+            let mut client = Client::connect(address, "one-to-many").await;
+            client
+                .send(&SignalMessage::SessionJoin(session_id, false))
+                .await;
+            clients.push(client);
+        }
+        for client in &mut clients {
+            client.assert_no_message().await;
+        }
+
+        let mut host = Client::connect(address, "one-to-many").await;
+        host.send(&SignalMessage::SessionJoin(session_id, true))
+            .await;
+        let mut client_ids = Vec::new();
+        for _ in &clients {
+            client_ids.push(recv_session_ready(&mut host, session_id).await);
+        }
+        for (client, client_id) in clients.iter_mut().zip(client_ids) {
+            let host_id = recv_session_ready(client, session_id).await;
+            assert_eq!(
+                assert_relayed(session_id, &mut host, client, client_id).await,
+                host_id
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_clients_are_queued_again_once_host_leaves() {
+        let (_server_state, address) = spawn_server();
+        let session_id = SessionId::new(1);
+        let mut host = Client::connect(address, "one-to-many").await;
+        host.send(&SignalMessage::SessionJoin(session_id, true))
+            .await;
+        let mut client = Client::connect(address, "one-to-many").await;
+        client
+            .send(&SignalMessage::SessionJoin(session_id, false))
+            .await;
+        let client_id = recv_session_ready(&mut host, session_id).await;
+        let host_id = recv_session_ready(&mut client, session_id).await;
+        host.close().await;
+        assert_eq!(
+            client.recv::<SignalMessage>().await,
+            SignalMessage::SessionLeave(session_id, host_id)
+        );
+
+        let mut new_host = Client::connect(address, "one-to-many").await;
+        new_host
+            .send(&SignalMessage::SessionJoin(session_id, true))
+            .await;
+        assert_eq!(
+            recv_session_ready(&mut new_host, session_id).await,
+            client_id
+        );
+        let new_host_id = recv_session_ready(&mut client, session_id).await;
+        assert_eq!(
+            assert_relayed(session_id, &mut new_host, &mut client, client_id).await,
+            new_host_id
+        );
+    }
+
+    #[tokio::test]
+    async fn test_host_is_told_about_leaving_client() {
+        let (_server_state, address) = spawn_server();
+        let session_id = SessionId::new(1);
+        let mut host = Client::connect(address, "one-to-many").await;
+        host.send(&SignalMessage::SessionJoin(session_id, true))
+            .await;
+        let mut clients = Vec::new();
+        for _ in 0..2 {
+            let mut client = Client::connect(address, "one-to-many").await;
+            client
+                .send(&SignalMessage::SessionJoin(session_id, false))
+                .await;
+            let client_id = recv_session_ready(&mut host, session_id).await;
+            recv_session_ready(&mut client, session_id).await;
+            clients.push((client, client_id));
+        }
+
+        let (leaving_client, leaving_client_id) = clients.remove(0);
+        leaving_client.close().await;
+        assert_eq!(
+            host.recv::<SignalMessage>().await,
+            SignalMessage::SessionLeave(session_id, leaving_client_id)
+        );
+        // other clients aren't connected with the leaving one
+        for (mut client, _client_id) in clients {
+            client.assert_no_message().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_clients_are_turned_away_once_host_reports_full_session() {
+        let (server_state, address) = spawn_server();
+        let session_id = SessionId::new(1);
+        let mut host = Client::connect(address, "one-to-many").await;
+        host.send(&SignalMessage::SessionJoin(session_id, true))
+            .await;
+        let mut accepted = Client::connect(address, "one-to-many").await;
+        accepted
+            .send(&SignalMessage::SessionJoin(session_id, false))
+            .await;
+        let accepted_id = recv_session_ready(&mut host, session_id).await;
+        recv_session_ready(&mut accepted, session_id).await;
+
+        // only the host can report its session as full
+        accepted
+            .send(&SignalMessage::SessionFullNotice(session_id, accepted_id))
+            .await;
+        accepted.assert_no_message().await;
+
+        let mut turned_away = Client::connect(address, "one-to-many").await;
+        turned_away
+            .send(&SignalMessage::SessionJoin(session_id, false))
+            .await;
+        let turned_away_id = recv_session_ready(&mut host, session_id).await;
+        recv_session_ready(&mut turned_away, session_id).await;
+        host.send(&SignalMessage::SessionFullNotice(
+            session_id,
+            turned_away_id,
+        ))
+        .await;
+        assert_eq!(
+            turned_away.recv::<SignalMessage>().await,
+            SignalMessage::Error(session_id, turned_away_id, "session is full".to_owned())
+        );
+        assert!(
+            !server_state.one_to_many_sessions().read().await[&session_id]
+                .users
+                .contains(&turned_away_id)
+        );
+
+        // further clients are turned away without reaching the host
+        let mut late = Client::connect(address, "one-to-many").await;
+        late.send(&SignalMessage::SessionJoin(session_id, false))
+            .await;
+        assert!(matches!(
+            late.recv().await,
+            SignalMessage::Error(error_session_id, _, error)
+                if error_session_id == session_id && error == "session is full"
+        ));
+        host.assert_no_message().await;
+
+        // place is freed once a client leaves
+        accepted.close().await;
+        assert_eq!(
+            host.recv::<SignalMessage>().await,
+            SignalMessage::SessionLeave(session_id, accepted_id)
+        );
+        late.send(&SignalMessage::SessionJoin(session_id, false))
+            .await;
+        recv_session_ready(&mut host, session_id).await;
+        recv_session_ready(&mut late, session_id).await;
+    }
+
+    #[tokio::test]
+    async fn test_client_leaves_every_session_of_its_connection() {
+        let (server_state, address) = spawn_server();
+        let mut client = Client::connect(address, "one-to-many").await;
+        let mut hosts = Vec::new();
+        for session_id in [SessionId::new(1), SessionId::new(2)] {
+            let mut host = Client::connect(address, "one-to-many").await;
+            host.send(&SignalMessage::SessionJoin(session_id, true))
+                .await;
+            client
+                .send(&SignalMessage::SessionJoin(session_id, false))
+                .await;
+            let client_id = recv_session_ready(&mut host, session_id).await;
+            recv_session_ready(&mut client, session_id).await;
+            hosts.push((host, session_id, client_id));
+        }
+
+        client.close().await;
+        for (host, session_id, client_id) in &mut hosts {
+            assert_eq!(
+                host.recv::<SignalMessage>().await,
+                SignalMessage::SessionLeave(*session_id, *client_id)
+            );
+        }
+        for (host, _session_id, _client_id) in hosts {
+            host.close().await;
+        }
+        eventually(|| async { server_state.one_to_many_sessions().read().await.is_empty() }).await;
+    }
+
+    #[tokio::test]
+    async fn test_host_is_told_stable_id_of_rejoining_client() {
+        let (_server_state, address) = spawn_server();
+        let session_id = SessionId::new(1);
+        let stable_id = StableId::new(7);
+        let mut host = Client::connect(address, "one-to-many").await;
+        host.send(&SignalMessage::SessionJoin(session_id, true))
+            .await;
+        let mut client = Client::connect(address, "one-to-many").await;
+        client
+            .send(&SignalMessage::SessionJoinAs(session_id, stable_id))
+            .await;
+        let SignalMessage::ClientIdentity(_, client_id, identity) = host.recv().await else {
+            panic!("expected ClientIdentity");
+        };
+        assert_eq!(identity, stable_id);
+        assert_eq!(recv_session_ready(&mut host, session_id).await, client_id);
+        recv_session_ready(&mut client, session_id).await;
+        client.close().await;
+        assert_eq!(
+            host.recv::<SignalMessage>().await,
+            SignalMessage::SessionLeave(session_id, client_id)
+        );
+
+        let mut rejoined = Client::connect(address, "one-to-many").await;
+        rejoined
+            .send(&SignalMessage::SessionJoinAs(session_id, stable_id))
+            .await;
+        let SignalMessage::ClientIdentity(_, rejoined_id, identity) = host.recv().await else {
+            panic!("expected ClientIdentity");
+        };
+        assert_eq!(identity, stable_id);
+        assert_ne!(rejoined_id, client_id);
+        assert_eq!(recv_session_ready(&mut host, session_id).await, rejoined_id);
+    }
+
+    #[tokio::test]
+    async fn test_stable_id_of_connected_client_is_rejected() {
+        let (_server_state, address) = spawn_server();
+        let session_id = SessionId::new(1);
+        let stable_id = StableId::new(7);
+        let mut client = Client::connect(address, "one-to-many").await;
+        client
+            .send(&SignalMessage::SessionJoinAs(session_id, stable_id))
+            .await;
+        let mut impostor = Client::connect(address, "one-to-many").await;
+        impostor
+            .send(&SignalMessage::SessionJoinAs(session_id, stable_id))
+            .await;
+        assert!(matches!(
+            impostor.recv().await,
+            SignalMessage::Error(error_session_id, _, error)
+                if error_session_id == session_id && error == "stable id is used by another client"
+        ));
+        client.assert_no_message().await;
+    }
+
+    #[tokio::test]
+    async fn test_session_is_removed_once_everyone_disconnects() {
+        let (server_state, address) = spawn_server();
+        let session_id = SessionId::new(1);
+        let mut host = Client::connect(address, "one-to-many").await;
+        host.send(&SignalMessage::SessionJoin(session_id, true))
+            .await;
+        let mut client = Client::connect(address, "one-to-many").await;
+        client
+            .send(&SignalMessage::SessionJoin(session_id, false))
+            .await;
+        recv_session_ready(&mut host, session_id).await;
+
+        host.close().await;
+        eventually(|| async {
+            server_state
+                .one_to_many_sessions()
+                .read()
+                .await
+                .get(&session_id)
+                .is_some_and(|session| session.hosts.is_empty() && session.users.len() == 1)
+        })
+        .await;
+
+        client.close().await;
+        eventually(|| async { server_state.one_to_many_sessions().read().await.is_empty() }).await;
+    }
+}
+
+mod many_to_many_topology {
+    use one_to_many::SignalMessage;
+
+    use super::*;
+
+    /// Joins a new user into session, returns ids of already present users it was told about.
+    async fn join(
+        address: SocketAddr,
+        session_id: SessionId,
+        present: usize,
+    ) -> (Client, Vec<UserId>) {
+        let (client, _user_id, peer_ids) = join_with_members(address, session_id, present).await;
+        (client, peer_ids)
+    }
+
+    /// Same as [`join`], also returns the user's own id and all members of session it was told about.
+    async fn join_with_members(
+        address: SocketAddr,
+        session_id: SessionId,
+        present: usize,
+    ) -> (Client, UserId, Vec<UserId>) {
+        let mut client = Client::connect(address, "many-to-many").await;
+        client
+            .send(&SignalMessage::SessionJoin(session_id, false))
+            .await;
+        let SignalMessage::SessionMembers(members_session_id, user_id, members) =
+            client.recv().await
+        else {
+            panic!("expected SessionMembers");
+        };
+        assert_eq!(members_session_id, session_id);
+        assert_eq!(members.len(), present + 1);
+        assert!(members.contains(&user_id));
+        assert!(members.windows(2).all(|pair| pair[0] < pair[1]));
+        let mut peer_ids = Vec::new();
+        for _ in 0..present {
+            let SignalMessage::SessionReady(ready_session_id, peer_id) = client.recv().await else {
+                panic!("expected SessionReady");
+            };
+            assert_eq!(ready_session_id, session_id);
+            peer_ids.push(peer_id);
+        }
+        client.assert_no_message().await;
+        for peer_id in &peer_ids {
+            assert!(members.contains(peer_id));
+        }
+        (client, user_id, peer_ids)
+    }
+
+    #[tokio::test]
+    async fn test_session_ready_is_sent_for_each_present_user() {
+        let (server_state, address) = spawn_server();
+        let session_id = SessionId::new(1);
+        let (mut first, first_peers) = join(address, session_id, 0).await;
+        let (mut second, second_peers) = join(address, session_id, 1).await;
+        let (third, mut third_peers) = join(address, session_id, 2).await;
+        assert!(first_peers.is_empty());
+        let first_id = second_peers[0];
+        third_peers.sort_unstable_by_key(|peer_id| peer_id.into_inner());
+        third_peers.dedup();
+        assert_eq!(third_peers.len(), 2);
+        assert!(third_peers.contains(&first_id));
+        let users = server_state.many_to_many_sessions().read().await[&session_id]
+            .users
+            .clone();
+        assert_eq!(users.len(), 3);
+        assert!(third_peers.iter().all(|peer_id| users.contains(peer_id)));
+        // already present users are only told about newcomers by the newcomers themselves
+        first.assert_no_message().await;
+        second.assert_no_message().await;
+
+        third.close().await;
+        second.close().await;
+        first.close().await;
+        eventually(|| async { server_state.many_to_many_sessions().read().await.is_empty() }).await;
+    }
+
+    #[tokio::test]
+    async fn test_newcomers_are_told_ordered_members_including_themselves() {
+        let (_server_state, address) = spawn_server();
+        let session_id = SessionId::new(1);
+        let (_first, first_id, _peers) = join_with_members(address, session_id, 0).await;
+        let (_second, second_id, _peers) = join_with_members(address, session_id, 1).await;
+        let mut third = Client::connect(address, "many-to-many").await;
+        third
+            .send(&SignalMessage::SessionJoin(session_id, false))
+            .await;
+        let SignalMessage::SessionMembers(_, third_id, members) = third.recv().await else {
+            panic!("expected SessionMembers");
+        };
+        let mut expected = vec![first_id, second_id, third_id];
+        expected.sort_unstable();
+        assert_eq!(members, expected);
+    }
+
+    #[tokio::test]
+    async fn test_creating_existing_session_is_refused() {
+        let (_server_state, address) = spawn_server();
+        let session_id = SessionId::new(1);
+        let (mut first, _peers) = join(address, session_id, 0).await;
+        let mut second = Client::connect(address, "many-to-many").await;
+        second
+            .send(&SignalMessage::SessionJoinWithIntent(
+                session_id,
+                false,
+                JoinIntent::Create,
+            ))
+            .await;
+        assert_eq!(
+            second.recv::<SignalMessage>().await,
+            SignalMessage::SessionJoinError(session_id, JoinError::AlreadyExists)
+        );
+        second.assert_no_message().await;
+        first.assert_no_message().await;
+    }
+
+    #[tokio::test]
+    async fn test_disconnect_removes_every_emptied_session() {
+        let (server_state, address) = spawn_server();
+        let mut shared = Client::connect(address, "many-to-many").await;
+        for session_id in 1..=3 {
+            shared
+                .send(&SignalMessage::SessionJoin(
+                    SessionId::new(session_id),
+                    false,
+                ))
+                .await;
+        }
+        eventually(|| async { server_state.many_to_many_sessions().read().await.len() == 3 }).await;
+        let (other, _peers) = join(address, SessionId::new(2), 1).await;
+
+        shared.close().await;
+        eventually(|| async {
+            let sessions = server_state.many_to_many_sessions().read().await;
+            sessions.len() == 1 && sessions[&SessionId::new(2)].users.len() == 1
+        })
+        .await;
+        other.close().await;
+        eventually(|| async { server_state.many_to_many_sessions().read().await.is_empty() }).await;
+    }
+
+    #[tokio::test]
+    async fn test_session_list_reports_only_mesh_sessions() {
+        let (_server_state, address) = spawn_server();
+        let (_first, _peers) = join(address, SessionId::new(1), 0).await;
+        let (_second, _peers) = join(address, SessionId::new(1), 1).await;
+        let mut one_to_many_host = Client::connect(address, "one-to-many").await;
+        one_to_many_host
+            .send(&SignalMessage::SessionJoin(SessionId::new(2), true))
+            .await;
+        one_to_many_host.assert_no_message().await;
+
+        let mut lobby = Client::connect(address, "many-to-many").await;
+        lobby.send(&SignalMessage::SessionListRequest).await;
+        assert_eq!(
+            lobby.recv::<SignalMessage>().await,
+            SignalMessage::SessionListResponse(vec![SessionInfo {
+                session_id: SessionId::new(1),
+                peer_count: 2,
+                topology: TopologyType::ManyToMany,
+            }])
+        );
+    }
+}
+
+mod embedding {
+    use axum::http::StatusCode;
+    use axum::Router;
+    use one_to_one::SignalMessage;
+    use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+    use wasm_peers_signaling_server::hub::SignalingHub;
+    use wasm_peers_signaling_server::one_to_one::{Policy, Sessions};
+    use wasm_peers_signaling_server::router::RouterConfig;
+
+    use super::*;
+
+    /// Serves signaling routes nested under `/api` next to another route of the app.
+    fn spawn_app(server_state: ServerState, config: RouterConfig) -> SocketAddr {
+        let app = Router::new()
+            .route("/api/status", axum::routing::get(|| async { "app" }))
+            .nest("/api", router::create_with_config(server_state, config));
+        let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind listener");
+        let address = listener.local_addr().expect("listener has no address");
+        let server = axum::Server::from_tcp(listener)
+            .expect("failed to create server")
+            .serve(app.into_make_service());
+        tokio::spawn(server);
+        address
+    }
+
+    async fn get_status(address: SocketAddr, path: &str) -> (StatusCode, String) {
+        let response = http_get(address, path).await;
+        let status = response
+            .split(' ')
+            .nth(1)
+            .and_then(|status| status.parse::<u16>().ok())
+            .and_then(|status| StatusCode::from_u16(status).ok())
+            .expect("malformed response");
+        let body = response
+            .split_once("\r\n\r\n")
+            .map(|(_, body)| body.to_owned())
+            .unwrap_or_default();
+        (status, body)
+    }
+
+    #[tokio::test]
+    async fn test_websockets_are_upgraded_through_nested_prefix() {
+        let sessions = Sessions::default();
+        let server_state = ServerState::default()
+            .with_one_to_one_hub(SignalingHub::new(Policy::with_sessions(sessions.clone())));
+        let config = RouterConfig {
+            path_prefix: "/signal/".to_owned(),
+            ..RouterConfig::default()
+        };
+        let address = spawn_app(server_state, config);
+        let session_id = SessionId::new(1);
+
+        let mut first = Client::connect(address, "api/signal/one-to-one").await;
+        let mut second = Client::connect(address, "api/signal/one-to-one").await;
+        first.send(&SignalMessage::SessionJoin(session_id)).await;
+        first.assert_no_message().await;
+        second.send(&SignalMessage::SessionJoin(session_id)).await;
+        assert!(matches!(
+            first.recv().await,
+            SignalMessage::SessionReady(ready_session_id, _) if ready_session_id == session_id
+        ));
+        assert!(matches!(
+            second.recv().await,
+            SignalMessage::SessionReady(ready_session_id, _) if ready_session_id == session_id
+        ));
+
+        let sessions = sessions.read().await;
+        let session = sessions.get(&session_id).expect("session isn't observable");
+        assert!(session.first.is_some() && session.second.is_some());
+        assert_eq!(
+            get_status(address, "/api/status").await,
+            (StatusCode::OK, "app".to_owned())
+        );
+        assert_eq!(
+            get_status(address, "/api/signal/health").await.0,
+            StatusCode::OK
+        );
+    }
+
+    #[tokio::test]
+    async fn test_users_are_disconnected_on_shutdown() {
+        let (server_state, address) = spawn_server();
+        let session_id = SessionId::new(1);
+        let mut one_to_one_user = Client::connect(address, "one-to-one").await;
+        one_to_one_user
+            .send(&SignalMessage::SessionJoin(session_id))
+            .await;
+        let many_to_many_user = Client::connect(address, "many-to-many").await;
+        eventually(|| async { !server_state.one_to_one_sessions().read().await.is_empty() }).await;
+
+        let closing = tokio::spawn({
+            let server_state = server_state.clone();
+            async move { server_state.close_connections(TIMEOUT).await }
+        });
+        for mut user in [one_to_one_user, many_to_many_user] {
+            let message = tokio::time::timeout(TIMEOUT, user.ws.next())
+                .await
+                .expect("connection wasn't closed in time")
+                .expect("connection closed without close frame")
+                .expect("websocket error");
+            assert!(
+                matches!(
+                    message,
+                    Message::Close(Some(ref frame))
+                        if frame.code == CloseCode::Away && frame.reason == router::SHUTDOWN_CLOSE_REASON
+                ),
+                "unexpected message: {message:?}"
+            );
+            // reading further replies to the close frame
+            while user.ws.next().await.is_some() {}
+        }
+
+        tokio::time::timeout(TIMEOUT, closing)
+            .await
+            .expect("server didn't notice disconnects in time")
+            .expect("closing connections panicked");
+        assert!(server_state.one_to_one_sessions().read().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_served_router_stops_once_users_disconnect() {
+        let server_state = ServerState::default();
+        let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind listener");
+        let address = listener.local_addr().expect("listener has no address");
+        let server = tokio::spawn(router::serve(
+            listener,
+            server_state.clone(),
+            RouterConfig::default(),
+            Duration::from_secs(60),
+        ));
+        let mut user = Client::connect(address, "one-to-one").await;
+        user.send(&SignalMessage::SessionJoin(SessionId::new(1)))
+            .await;
+        eventually(|| async { !server_state.one_to_one_sessions().read().await.is_empty() }).await;
+
+        server_state.request_shutdown();
+        let message = tokio::time::timeout(TIMEOUT, user.ws.next())
+            .await
+            .expect("connection wasn't closed in time")
+            .expect("connection closed without close frame")
+            .expect("websocket error");
+        assert!(
+            matches!(message, Message::Close(Some(ref frame)) if frame.code == CloseCode::Away),
+            "unexpected message: {message:?}"
+        );
+        while user.ws.next().await.is_some() {}
+
+        tokio::time::timeout(TIMEOUT, server)
+            .await
+            .expect("server didn't stop once users disconnected")
+            .expect("server panicked")
+            .expect("server failed");
+    }
+
+    #[tokio::test]
+    async fn test_health_and_metrics_are_configurable() {
+        let config = RouterConfig {
+            enable_health: false,
+            enable_metrics: true,
+            ..RouterConfig::default()
+        };
+        let address = spawn_app(ServerState::default(), config);
+        let mut client = Client::connect(address, "api/one-to-one").await;
+        client
+            .send(&SignalMessage::SessionJoin(SessionId::new(1)))
+            .await;
+        client.assert_no_message().await;
+
+        assert_eq!(
+            get_status(address, "/api/health").await.0,
+            StatusCode::NOT_FOUND
+        );
+        let (status, metrics) = get_status(address, "/api/metrics").await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(metrics.contains("wasm_peers_connected_users{topology=\"one-to-one\"} 1\n"));
+        assert!(metrics.contains("wasm_peers_sessions{topology=\"one-to-one\"} 1\n"));
+        assert!(metrics.contains("wasm_peers_sessions{topology=\"many-to-many\"} 0\n"));
+    }
+}
+
+mod server_config {
+    use one_to_one::SignalMessage;
+    use wasm_peers_signaling_server::config::{
+        AllowedOrigins, PayloadLimits, ServerConfig, SessionLimits,
+    };
+
+    use super::*;
+
+    fn spawn_configured_server(config: &ServerConfig) -> SocketAddr {
+        let server_state = ServerState::from_config(config).expect("failed to create server state");
+        let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind listener");
+        let address = listener.local_addr().expect("listener has no address");
+        let server = axum::Server::from_tcp(listener)
+            .expect("failed to create server")
+            .serve(router::create(server_state).into_make_service());
+        tokio::spawn(server);
+        address
+    }
+
+    #[tokio::test]
+    async fn test_users_without_auth_secret_are_rejected() {
+        let address = spawn_configured_server(&ServerConfig {
+            auth_secret: Some("secret".to_owned()),
+            ..ServerConfig::default()
+        });
+
+        for endpoint in ["one-to-one", "one-to-one?token=wrong"] {
+            let result =
+                tokio_tungstenite::connect_async(format!("ws://{address}/{endpoint}")).await;
+            assert!(
+                matches!(
+                    result,
+                    Err(tokio_tungstenite::tungstenite::Error::Http(ref response))
+                        if response.status() == 401
+                ),
+                "unexpected result for {endpoint}: {result:?}"
+            );
+        }
+        let mut client = Client::connect(address, "one-to-one?token=secret").await;
+        client
+            .send(&SignalMessage::SessionJoin(SessionId::new(1)))
+            .await;
+        client.assert_no_message().await;
+    }
+
+    /// Connects to one-to-one endpoint like a browser on a page with `origin` would.
+    async fn connect_from_origin(
+        address: SocketAddr,
+        origin: &str,
+    ) -> Result<Client, tokio_tungstenite::tungstenite::Error> {
+        use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+
+        let mut request = format!("ws://{address}/one-to-one")
+            .into_client_request()
+            .expect("invalid request");
+        request
+            .headers_mut()
+            .insert("Origin", origin.parse().expect("invalid header"));
+        let (ws, _response) = tokio_tungstenite::connect_async(request).await?;
+        Ok(Client { ws })
+    }
+
+    #[tokio::test]
+    async fn test_users_from_other_origins_are_rejected() {
+        let address = spawn_configured_server(&ServerConfig {
+            allowed_origins: AllowedOrigins {
+                patterns: vec!["https://*.example.com".to_owned()],
+            },
+            ..ServerConfig::default()
+        });
+
+        for origin in ["https://app.example.com", "https://other.example.com"] {
+            let result = connect_from_origin(address, origin).await.map(|_client| ());
+            assert!(result.is_ok(), "unexpected result for {origin}: {result:?}");
+        }
+        for origin in ["https://evil.com", "http://app.example.com", "null"] {
+            let result = connect_from_origin(address, origin).await.map(|_client| ());
+            assert!(
+                matches!(
+                    result,
+                    Err(tokio_tungstenite::tungstenite::Error::Http(ref response))
+                        if response.status() == 403
+                ),
+                "unexpected result for {origin}: {result:?}"
+            );
+        }
+        // clients other than browsers don't send origin
+        let mut client = Client::connect(address, "one-to-one").await;
+        client
+            .send(&SignalMessage::SessionJoin(SessionId::new(1)))
+            .await;
+        client.assert_no_message().await;
+    }
+
+    #[tokio::test]
+    async fn test_sessions_over_limit_are_rejected() {
+        let address = spawn_configured_server(&ServerConfig {
+            session_limits: SessionLimits {
+                max_sessions: Some(1),
+                max_users_per_session: None,
+            },
+            ..ServerConfig::default()
+        });
+        let mut first = Client::connect(address, "one-to-one").await;
+        first
+            .send(&SignalMessage::SessionJoin(SessionId::new(1)))
+            .await;
+        first.assert_no_message().await;
+
+        let mut second = Client::connect(address, "one-to-one").await;
+        second
+            .send(&SignalMessage::SessionJoin(SessionId::new(2)))
+            .await;
+        assert_eq!(
+            second.recv::<SignalMessage>().await,
+            SignalMessage::Error(SessionId::new(2), "too many sessions".to_owned())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_users_over_session_limit_are_rejected() {
+        let address = spawn_configured_server(&ServerConfig {
+            session_limits: SessionLimits {
+                max_sessions: None,
+                max_users_per_session: Some(2),
+            },
+            ..ServerConfig::default()
+        });
+        let session_id = SessionId::new(1);
+        let mut users = Vec::new();
+        for _ in 0..2 {
+            let mut user = Client::connect(address, "many-to-many").await;
+            user.send(&one_to_many::SignalMessage::SessionJoin(session_id, false))
+                .await;
+            users.push(user);
+        }
+        // second user is told about the first one
+        let _ready: one_to_many::SignalMessage = users[1].recv().await;
+
+        let mut third = Client::connect(address, "many-to-many").await;
+        third
+            .send(&one_to_many::SignalMessage::SessionJoin(session_id, false))
+            .await;
+        assert!(matches!(
+            third.recv().await,
+            one_to_many::SignalMessage::Error(error_session_id, _, error)
+                if error_session_id == session_id && error == "session is full"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_payloads_over_configured_limits_are_rejected() {
+        let address = spawn_configured_server(&ServerConfig {
+            payload_limits: PayloadLimits {
+                max_sdp_lines: 2,
+                ..PayloadLimits::default()
+            },
+            ..ServerConfig::default()
+        });
+        let session_id = SessionId::new(1);
+        let mut host = Client::connect(address, "one-to-many").await;
+        host.send(&one_to_many::SignalMessage::SessionJoin(session_id, true))
+            .await;
+        let mut client = Client::connect(address, "one-to-many").await;
+        client
+            .send(&one_to_many::SignalMessage::SessionJoin(session_id, false))
+            .await;
+        let one_to_many::SignalMessage::SessionReady(_, client_id) = host.recv().await else {
+            panic!("expected SessionReady");
+        };
+        let _ready: one_to_many::SignalMessage = client.recv().await;
+
+        host.send(&one_to_many::SignalMessage::SdpOffer(
+            session_id,
+            client_id,
+            OFFER.to_owned(),
+        ))
+        .await;
+        assert!(matches!(
+            host.recv().await,
+            one_to_many::SignalMessage::Error(error_session_id, _, error)
+                if error_session_id == session_id && error == "SdpOffer rejected: SDP has too many lines"
+        ));
+        client.assert_no_message().await;
+    }
+
+    fn config_with_session_store(name: &str) -> ServerConfig {
+        let session_store_dir =
+            std::env::temp_dir().join(format!("wasm-peers-{}-{}", name, std::process::id()));
+        ServerConfig {
+            session_store_dir: Some(session_store_dir),
+            ..ServerConfig::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_one_to_one_session_is_restored_after_restart() {
+        let config = config_with_session_store("one-to-one-restart");
+        let address = spawn_configured_server(&config);
+        let session_id = SessionId::new(1);
+        // users of the server that went down stay connected to it, so it doesn't remove them
+        let mut users = Vec::new();
+        for _ in 0..2 {
+            let mut user = Client::connect(address, "one-to-one").await;
+            user.send(&SignalMessage::SessionJoin(session_id)).await;
+            users.push(user);
+        }
+        for user in &mut users {
+            let _ready: SignalMessage = user.recv().await;
+        }
+
+        // and reconnect to the restarted one
+        let restarted = spawn_configured_server(&config);
+        let mut first = Client::connect(restarted, "one-to-one").await;
+        first.send(&SignalMessage::SessionJoin(session_id)).await;
+        first.assert_no_message().await;
+        let mut second = Client::connect(restarted, "one-to-one").await;
+        second.send(&SignalMessage::SessionJoin(session_id)).await;
+        for user in [&mut first, &mut second] {
+            assert!(matches!(
+                user.recv().await,
+                SignalMessage::SessionReady(ready_session_id, _) if ready_session_id == session_id
+            ));
+        }
+
+        let mut third = Client::connect(restarted, "one-to-one").await;
+        third.send(&SignalMessage::SessionJoin(session_id)).await;
+        assert_eq!(
+            third.recv::<SignalMessage>().await,
+            SignalMessage::Error(session_id, "session is full".to_owned())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_clients_of_restored_session_wait_for_host_to_rejoin() {
+        let config = config_with_session_store("one-to-many-restart");
+        let address = spawn_configured_server(&config);
+        let session_id = SessionId::new(1);
+        // users of the server that went down stay connected to it, so it doesn't remove them
+        let mut users = Vec::new();
+        for is_host in [true, false] {
+            let mut user = Client::connect(address, "one-to-many").await;
+            user.send(&one_to_many::SignalMessage::SessionJoin(
+                session_id, is_host,
+            ))
+            .await;
+            users.push(user);
+        }
+        for user in &mut users {
+            let _ready: one_to_many::SignalMessage = user.recv().await;
+        }
+
+        let restarted = spawn_configured_server(&config);
+        let mut client = Client::connect(restarted, "one-to-many").await;
+        client
+            .send(&one_to_many::SignalMessage::SessionJoin(session_id, false))
+            .await;
+        client.assert_no_message().await;
+        let mut host = Client::connect(restarted, "one-to-many").await;
+        host.send(&one_to_many::SignalMessage::SessionJoin(session_id, true))
+            .await;
+        for user in [&mut client, &mut host] {
+            assert!(matches!(
+                user.recv().await,
+                one_to_many::SignalMessage::SessionReady(ready_session_id, _)
+                    if ready_session_id == session_id
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_client_rejoining_restored_session_replaces_its_previous_connection() {
+        let config = config_with_session_store("stable-id-restart");
+        let address = spawn_configured_server(&config);
+        let session_id = SessionId::new(1);
+        let stable_id = StableId::new(7);
+        let mut host = Client::connect(address, "one-to-many").await;
+        host.send(&one_to_many::SignalMessage::SessionJoin(session_id, true))
+            .await;
+        let mut client = Client::connect(address, "one-to-many").await;
+        client
+            .send(&one_to_many::SignalMessage::SessionJoinAs(
+                session_id, stable_id,
+            ))
+            .await;
+        let one_to_many::SignalMessage::ClientIdentity(_, client_id, _) = host.recv().await else {
+            panic!("expected ClientIdentity");
+        };
+
+        let restarted = spawn_configured_server(&config);
+        let mut new_host = Client::connect(restarted, "one-to-many").await;
+        new_host
+            .send(&one_to_many::SignalMessage::SessionJoin(session_id, true))
+            .await;
+        new_host.assert_no_message().await;
+        let mut new_client = Client::connect(restarted, "one-to-many").await;
+        new_client
+            .send(&one_to_many::SignalMessage::SessionJoinAs(
+                session_id, stable_id,
+            ))
+            .await;
+        // host may still be connected with the client from before the restart
+        assert_eq!(
+            new_host.recv::<one_to_many::SignalMessage>().await,
+            one_to_many::SignalMessage::SessionLeave(session_id, client_id)
+        );
+        assert!(matches!(
+            new_host.recv().await,
+            one_to_many::SignalMessage::ClientIdentity(_, _, identity) if identity == stable_id
+        ));
+    }
+}
+
+mod custom_policy {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use anyhow::anyhow;
+    use axum::async_trait;
+    use axum::Router;
+    use one_to_one::SignalMessage;
+    use wasm_peers_signaling_server::hub::{Outgoing, SessionPolicy, SignalingHub};
+
+    use super::*;
+
+    /// One-to-one policy turning away users once `max_users` are connected.
+    struct Capped {
+        inner: wasm_peers_signaling_server::one_to_one::Policy,
+        connected: AtomicUsize,
+        max_users: usize,
+    }
+
+    #[async_trait]
+    impl SessionPolicy for Capped {
+        type Message = SignalMessage;
+
+        const TOPOLOGY: &'static str = "capped";
+
+        async fn on_join(&self, _user_id: UserId) -> wasm_peers_signaling_server::Result<()> {
+            if self.connected.fetch_add(1, Ordering::SeqCst) >= self.max_users {
+                self.connected.fetch_sub(1, Ordering::SeqCst);
+                return Err(anyhow!("server is full"));
+            }
+            Ok(())
+        }
+
+        async fn route(
+            &self,
+            sender_id: UserId,
+            message: SignalMessage,
+        ) -> wasm_peers_signaling_server::Result<Outgoing<SignalMessage>> {
+            self.inner.route(sender_id, message).await
+        }
+
+        async fn on_disconnect(&self, user_id: UserId) -> Outgoing<SignalMessage> {
+            self.connected.fetch_sub(1, Ordering::SeqCst);
+            self.inner.on_disconnect(user_id).await
+        }
+    }
+
+    /// Starts a custom app with the capped hub mounted under `/signaling`.
+    fn spawn_app(max_users: usize) -> SocketAddr {
+        let hub = SignalingHub::new(Capped {
+            inner: wasm_peers_signaling_server::one_to_one::Policy::default(),
+            connected: AtomicUsize::new(0),
+            max_users,
+        });
+        spawn_router(Router::new().route("/signaling", hub.method_router()))
+    }
+
+    fn spawn_router(app: Router) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind listener");
+        let address = listener.local_addr().expect("listener has no address");
+        let server = axum::Server::from_tcp(listener)
+            .expect("failed to create server")
+            .serve(app.into_make_service());
+        tokio::spawn(server);
+        address
+    }
+
+    /// Policy failing on the first message, as if it hit a bug.
+    struct Panicking;
+
+    #[async_trait]
+    impl SessionPolicy for Panicking {
+        type Message = SignalMessage;
+
+        const TOPOLOGY: &'static str = "panicking";
+
+        async fn route(
+            &self,
+            _sender_id: UserId,
+            _message: SignalMessage,
+        ) -> wasm_peers_signaling_server::Result<Outgoing<SignalMessage>> {
+            panic!("bug in policy");
+        }
+
+        async fn on_disconnect(&self, _user_id: UserId) -> Outgoing<SignalMessage> {
+            Vec::new()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_custom_policy_turns_away_users_over_capacity() {
+        let address = spawn_app(2);
+        let session_id = SessionId::new(1);
+        let mut first = Client::connect(address, "signaling").await;
+        first.send(&SignalMessage::SessionJoin(session_id)).await;
+        first.assert_no_message().await;
+        let mut second = Client::connect(address, "signaling").await;
+        second.send(&SignalMessage::SessionJoin(session_id)).await;
+        assert!(matches!(
+            first.recv().await,
+            SignalMessage::SessionReady(ready_session_id, _) if ready_session_id == session_id
+        ));
+        assert!(matches!(
+            second.recv().await,
+            SignalMessage::SessionReady(ready_session_id, _) if ready_session_id == session_id
+        ));
+
+        let mut third = Client::connect(address, "signaling").await;
+        let closed = tokio::time::timeout(TIMEOUT, third.ws.next())
+            .await
+            .expect("connection wasn't closed in time");
+        assert!(
+            !matches!(closed, Some(Ok(Message::Binary(_)))),
+            "unexpected message: {closed:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_hub_is_unhealthy_once_connection_handler_panics() {
+        let hub = SignalingHub::new(Panicking);
+        let address = spawn_router(Router::new().route("/signaling", hub.clone().method_router()));
+        assert!(hub.is_healthy());
+
+        let mut user = Client::connect(address, "signaling").await;
+        user.send(&SignalMessage::SessionJoin(SessionId::new(1)))
+            .await;
+        eventually(|| async { !hub.is_healthy() }).await;
+    }
+}
+
+mod relay {
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use anyhow::anyhow;
+    use axum::async_trait;
+    use axum::extract::ws;
+    use axum::Router;
+    use one_to_one::SignalMessage;
+    use tokio::sync::mpsc;
+    use wasm_peers_signaling_server::hub::SignalingHub;
+    use wasm_peers_signaling_server::one_to_one::{Policy, Sessions};
+    use wasm_peers_signaling_server::relay::Relay;
+
+    use super::*;
+
+    /// Relay shared by instances running in the same process, standing in for e.g. Redis pub/sub.
+    #[derive(Default)]
+    struct InProcessRelay {
+        next_user_id: AtomicU64,
+        subscribers: Mutex<HashMap<UserId, mpsc::UnboundedSender<ws::Message>>>,
+    }
+
+    #[async_trait]
+    impl Relay for InProcessRelay {
+        async fn next_user_id(&self) -> wasm_peers_signaling_server::Result<UserId> {
+            Ok(UserId::new(
+                self.next_user_id.fetch_add(1, Ordering::SeqCst) + 1,
+            ))
+        }
+
+        async fn subscribe(
+            &self,
+            user_id: UserId,
+            sender: mpsc::UnboundedSender<ws::Message>,
+        ) -> wasm_peers_signaling_server::Result<()> {
+            self.subscribers
+                .lock()
+                .expect("lock poisoned")
+                .insert(user_id, sender);
+            Ok(())
+        }
+
+        async fn unsubscribe(&self, user_id: UserId) {
+            self.subscribers
+                .lock()
+                .expect("lock poisoned")
+                .remove(&user_id);
+        }
+
+        async fn forward(
+            &self,
+            recipient_id: UserId,
+            message: Vec<u8>,
+        ) -> wasm_peers_signaling_server::Result<()> {
+            let subscribers = self.subscribers.lock().expect("lock poisoned");
+            let sender = subscribers
+                .get(&recipient_id)
+                .ok_or_else(|| anyhow!("no instance has user {recipient_id:?}"))?;
+            sender.send(ws::Message::Binary(message))?;
+            Ok(())
+        }
+    }
+
+    /// Starts an instance of the server sharing one-to-one sessions and relay with other ones.
+    fn spawn_instance(sessions: &Sessions, relay: &Arc<InProcessRelay>) -> SocketAddr {
+        let hub = SignalingHub::new(Policy::with_sessions(sessions.clone()))
+            .with_relay(Arc::clone(relay) as Arc<dyn Relay>);
+        let app: Router = Router::new().route("/one-to-one", hub.method_router());
+        let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind listener");
+        let address = listener.local_addr().expect("listener has no address");
+        let server = axum::Server::from_tcp(listener)
+            .expect("failed to create server")
+            .serve(app.into_make_service());
+        tokio::spawn(server);
+        address
+    }
+
+    #[tokio::test]
+    async fn test_session_spans_instances() {
+        let sessions = Sessions::default();
+        let relay = Arc::new(InProcessRelay::default());
+        let first_address = spawn_instance(&sessions, &relay);
+        let second_address = spawn_instance(&sessions, &relay);
+        let session_id = SessionId::new(1);
+
+        let mut first = Client::connect(first_address, "one-to-one").await;
+        let mut second = Client::connect(second_address, "one-to-one").await;
+        first.send(&SignalMessage::SessionJoin(session_id)).await;
+        first.assert_no_message().await;
+        second.send(&SignalMessage::SessionJoin(session_id)).await;
+        for user in [&mut first, &mut second] {
+            assert!(matches!(
+                user.recv().await,
+                SignalMessage::SessionReady(id, _) if id == session_id
+            ));
+        }
+
+        first
+            .send(&SignalMessage::SdpOffer(session_id, OFFER.to_owned()))
+            .await;
+        assert_eq!(
+            second.recv::<SignalMessage>().await,
+            SignalMessage::SdpOffer(session_id, OFFER.to_owned())
+        );
+        second
+            .send(&SignalMessage::SdpAnswer(session_id, ANSWER.to_owned()))
+            .await;
+        assert_eq!(
+            first.recv::<SignalMessage>().await,
+            SignalMessage::SdpAnswer(session_id, ANSWER.to_owned())
+        );
+    }
+}
+
+mod compression {
+    use std::io;
+    use std::pin::Pin;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::{Context, Poll};
+
+    use futures_util::io::{AsyncRead, AsyncWrite};
+    use one_to_one::SignalMessage;
+    use soketto::connection::{CloseReason, Receiver, Sender};
+    use soketto::extension::deflate::Deflate;
+    use soketto::handshake::{self, ServerResponse};
+    use soketto::{Incoming, Mode};
+    use tokio_util::compat::{Compat, TokioAsyncReadCompatExt};
+
+    use super::*;
+
+    /// Offer of a Chrome data channel with its candidates gathered, as sent by peers without trickle ICE.
+    const CHROME_OFFER: &str = "v=0\r\n\
+        o=- 4611731400430051336 2 IN IP4 127.0.0.1\r\n\
+        s=-\r\n\
+        t=0 0\r\n\
+        a=group:BUNDLE 0\r\n\
+        a=extmap-allow-mixed\r\n\
+        a=msid-semantic: WMS\r\n\
+        m=application 46243 UDP/DTLS/SCTP webrtc-datachannel\r\n\
+        c=IN IP4 203.0.113.7\r\n\
+        a=candidate:1467250027 1 udp 2122260223 192.168.0.196 46243 typ host generation 0 network-id 1 network-cost 10\r\n\
+        a=candidate:3348134513 1 udp 2122194687 10.8.0.2 51468 typ host generation 0 network-id 2 network-cost 50\r\n\
+        a=candidate:2999745851 1 udp 2122129151 fd00::1c4a:e3ff:fe2b:9a10 39825 typ host generation 0 network-id 3 network-cost 10\r\n\
+        a=candidate:435653019 1 tcp 1518280447 192.168.0.196 9 typ host tcptype active generation 0 network-id 1 network-cost 10\r\n\
+        a=candidate:2214745891 1 tcp 1518214911 10.8.0.2 9 typ host tcptype active generation 0 network-id 2 network-cost 50\r\n\
+        a=candidate:842163049 1 udp 1686052607 203.0.113.7 46243 typ srflx raddr 192.168.0.196 rport 46243 generation 0 network-id 1 network-cost 10\r\n\
+        a=ice-ufrag:Ld5e\r\n\
+        a=ice-pwd:pUdPzRnvM1mYJk5Tsr5PA2/g\r\n\
+        a=ice-options:trickle\r\n\
+        a=fingerprint:sha-256 E5:1B:58:0C:9A:47:3F:D2:11:8E:6C:B4:70:2D:95:A3:C8:5F:0E:7B:44:D9:21:6A:B3:8F:12:E7:5C:90:3D:A6\r\n\
+        a=setup:actpass\r\n\
+        a=mid:0\r\n\
+        a=sctp-port:5000\r\n\
+        a=max-message-size:262144\r\n";
+
+    /// Connection counting bytes received from the server.
+    struct Counted {
+        inner: Compat<TcpStream>,
+        received: Arc<AtomicUsize>,
+    }
+
+    impl AsyncRead for Counted {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut [u8],
+        ) -> Poll<io::Result<usize>> {
+            let result = Pin::new(&mut self.inner).poll_read(cx, buf);
+            if let Poll::Ready(Ok(read)) = result {
+                self.received.fetch_add(read, Ordering::SeqCst);
+            }
+            result
+        }
+    }
+
+    impl AsyncWrite for Counted {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            Pin::new(&mut self.inner).poll_write(cx, buf)
+        }
+
+        fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.inner).poll_flush(cx)
+        }
+
+        fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.inner).poll_close(cx)
+        }
+    }
+
+    /// Client offering `permessage-deflate` like browsers do, unless `deflate` is `false`.
+    struct SokettoClient {
+        sender: Sender<Counted>,
+        receiver: Receiver<Counted>,
+        received: Arc<AtomicUsize>,
+    }
+
+    impl SokettoClient {
+        async fn connect(address: SocketAddr, endpoint: &str, deflate: bool) -> Self {
+            let received = Arc::default();
+            let socket = Counted {
+                inner: TcpStream::connect(address)
+                    .await
+                    .expect("failed to connect to signaling server")
+                    .compat(),
+                received: Arc::clone(&received),
+            };
+            let host = address.to_string();
+            let resource = format!("/{endpoint}");
+            let mut client = handshake::Client::new(socket, &host, &resource);
+            if deflate {
+                client.add_extension(Box::new(Deflate::new(Mode::Client)));
+            }
+            let response = client.handshake().await.expect("handshake failed");
+            assert!(matches!(response, ServerResponse::Accepted { .. }));
+            let (sender, receiver) = client.into_builder().finish();
+            Self {
+                sender,
+                receiver,
+                received,
+            }
+        }
+
+        async fn send(&mut self, message: &impl Serialize) {
+            let message = rmp_serde::to_vec(message).expect("failed to serialize message");
+            self.sender
+                .send_binary_mut(message)
+                .await
+                .expect("failed to send message");
+            self.sender.flush().await.expect("failed to send message");
+        }
+
+        async fn recv<M: DeserializeOwned>(&mut self) -> M {
+            let mut message = Vec::new();
+            tokio::time::timeout(TIMEOUT, self.receiver.receive_data(&mut message))
+                .await
+                .expect("no message received in time")
+                .expect("websocket error");
+            rmp_serde::from_slice(&message).expect("failed to deserialize message")
+        }
+
+        /// Bytes received from the server so far.
+        fn received(&self) -> usize {
+            self.received.load(Ordering::SeqCst)
+        }
+    }
+
+    /// Relays [`CHROME_OFFER`] to a client, returning number of bytes it was received in.
+    async fn bytes_of_relayed_offer(deflate: bool) -> usize {
+        let (_server_state, address) = spawn_server();
+        let session_id = SessionId::new(1);
+        let mut sender = Client::connect(address, "one-to-one").await;
+        let mut recipient = SokettoClient::connect(address, "one-to-one", deflate).await;
+        sender.send(&SignalMessage::SessionJoin(session_id)).await;
+        sender.assert_no_message().await;
+        recipient
+            .send(&SignalMessage::SessionJoin(session_id))
+            .await;
+        let _ready: SignalMessage = sender.recv().await;
+        let _ready: SignalMessage = recipient.recv().await;
+
+        let received = recipient.received();
+        sender
+            .send(&SignalMessage::SdpOffer(
+                session_id,
+                CHROME_OFFER.to_owned(),
+            ))
+            .await;
+        assert_eq!(
+            recipient.recv::<SignalMessage>().await,
+            SignalMessage::SdpOffer(session_id, CHROME_OFFER.to_owned())
+        );
+        recipient.received() - received
+    }
+
+    #[tokio::test]
+    async fn test_offer_is_compressed_for_clients_offering_deflate() {
+        let uncompressed = bytes_of_relayed_offer(false).await;
+        let compressed = bytes_of_relayed_offer(true).await;
+        assert!(uncompressed > CHROME_OFFER.len());
+        assert!(
+            compressed * 10 < uncompressed * 7,
+            "offer of {uncompressed} bytes compressed only to {compressed} bytes"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_compressed_connections_are_closed_on_shutdown() {
+        let (server_state, address) = spawn_server();
+        let mut user = SokettoClient::connect(address, "one-to-one", true).await;
+        user.send(&SignalMessage::SessionJoin(SessionId::new(1)))
+            .await;
+        eventually(|| async { !server_state.one_to_one_sessions().read().await.is_empty() }).await;
+
+        let closing = tokio::spawn({
+            let server_state = server_state.clone();
+            async move { server_state.close_connections(TIMEOUT).await }
+        });
+        let mut message = Vec::new();
+        let incoming = tokio::time::timeout(TIMEOUT, user.receiver.receive(&mut message))
+            .await
+            .expect("connection wasn't closed in time")
+            .expect("websocket error");
+        // soketto closes connections with `Normal Closure` only
+        let Incoming::Closed(CloseReason { code, .. }) = incoming else {
+            panic!("expected close frame");
+        };
+        assert_eq!(code, 1000);
+
+        tokio::time::timeout(TIMEOUT, closing)
+            .await
+            .expect("server didn't notice disconnect in time")
+            .expect("closing connections panicked");
+        assert!(server_state.one_to_one_sessions().read().await.is_empty());
+    }
+}
+
+mod tls {
+    use std::sync::Arc;
+
+    use one_to_one::SignalMessage;
+    use std::fs;
+    use std::path::PathBuf;
+
+    use tokio::task::JoinHandle;
+    use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+    use tokio_tungstenite::Connector;
+    use wasm_peers_signaling_server::router::RouterConfig;
+
+    use super::*;
+
+    /// Writes PEM files to a temporary directory, returning their paths.
+    fn write_pem_files(name: &str, cert: &str, key: &str) -> (PathBuf, PathBuf) {
+        let directory =
+            std::env::temp_dir().join(format!("wasm-peers-tls-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&directory).expect("failed to create directory");
+        let cert_path = directory.join("cert.pem");
+        let key_path = directory.join("key.pem");
+        fs::write(&cert_path, cert).expect("failed to write certificate");
+        fs::write(&key_path, key).expect("failed to write key");
+        (cert_path, key_path)
+    }
+
+    /// Starts the server on an ephemeral port with a self-signed certificate for `localhost`,
+    /// read from PEM files like the binary does. Certificate is returned for clients to trust,
+    /// along with the task serving until shutdown of `server_state` is requested.
+    async fn spawn_tls_server(
+        name: &str,
+        server_state: ServerState,
+        shutdown_timeout: Duration,
+    ) -> (
+        SocketAddr,
+        rustls::Certificate,
+        JoinHandle<wasm_peers_signaling_server::Result<()>>,
+    ) {
+        let certificate = rcgen::generate_simple_self_signed(vec!["localhost".to_owned()])
+            .expect("failed to generate certificate");
+        let (cert_path, key_path) = write_pem_files(
+            name,
+            &certificate
+                .serialize_pem()
+                .expect("failed to serialize certificate"),
+            &certificate.serialize_private_key_pem(),
+        );
+        let tls_config = router::load_tls_config(&cert_path, &key_path)
+            .await
+            .expect("failed to load TLS config");
+        let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind listener");
+        let address = listener.local_addr().expect("listener has no address");
+        let server = tokio::spawn(router::serve_with_tls(
+            listener,
+            server_state,
+            RouterConfig::default(),
+            tls_config,
+            shutdown_timeout,
+        ));
+        let der = certificate
+            .serialize_der()
+            .expect("failed to serialize certificate");
+        (address, rustls::Certificate(der), server)
+    }
+
+    async fn connect(address: SocketAddr, certificate: &rustls::Certificate) -> Client {
+        let mut roots = rustls::RootCertStore::empty();
+        roots.add(certificate).expect("invalid certificate");
+        let client_config = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let (ws, _response) = tokio_tungstenite::connect_async_tls_with_config(
+            format!("wss://localhost:{}/one-to-one", address.port()),
+            None,
+            false,
+            Some(Connector::Rustls(Arc::new(client_config))),
+        )
+        .await
+        .expect("failed to connect to signaling server");
+        Client { ws }
+    }
+
+    #[tokio::test]
+    async fn test_session_is_joined_over_wss() {
+        let (address, certificate, _server) =
+            spawn_tls_server("join", ServerState::default(), TIMEOUT).await;
+        let session_id = SessionId::new(1);
+        let mut first = connect(address, &certificate).await;
+        let mut second = connect(address, &certificate).await;
+
+        first.send(&SignalMessage::SessionJoin(session_id)).await;
+        first.assert_no_message().await;
+        second.send(&SignalMessage::SessionJoin(session_id)).await;
+
+        for user in [&mut first, &mut second] {
+            assert!(matches!(
+                user.recv().await,
+                SignalMessage::SessionReady(id, _) if id == session_id
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_users_are_disconnected_as_soon_as_shutdown_is_requested() {
+        let server_state = ServerState::default();
+        // much longer than the test waits, so close frames can't wait for it to pass
+        let shutdown_timeout = Duration::from_secs(60);
+        let (address, certificate, server) =
+            spawn_tls_server("shutdown", server_state.clone(), shutdown_timeout).await;
+        let session_id = SessionId::new(1);
+        let mut user = connect(address, &certificate).await;
+        user.send(&SignalMessage::SessionJoin(session_id)).await;
+        eventually(|| async { !server_state.one_to_one_sessions().read().await.is_empty() }).await;
+
+        server_state.request_shutdown();
+        let message = tokio::time::timeout(TIMEOUT, user.ws.next())
+            .await
+            .expect("connection wasn't closed in time")
+            .expect("connection closed without close frame")
+            .expect("websocket error");
+        assert!(
+            matches!(
+                message,
+                Message::Close(Some(ref frame))
+                    if frame.code == CloseCode::Away && frame.reason == router::SHUTDOWN_CLOSE_REASON
+            ),
+            "unexpected message: {message:?}"
+        );
+        // reading further replies to the close frame
+        while user.ws.next().await.is_some() {}
+
+        tokio::time::timeout(TIMEOUT, server)
+            .await
+            .expect("server didn't stop once users disconnected")
+            .expect("server panicked")
+            .expect("server failed");
+    }
+
+    #[tokio::test]
+    async fn test_server_stops_after_shutdown_timeout() {
+        let server_state = ServerState::default();
+        let (address, certificate, server) =
+            spawn_tls_server("timeout", server_state.clone(), Duration::from_millis(200)).await;
+        // never reads the close frame, so it doesn't disconnect on its own
+        let mut user = connect(address, &certificate).await;
+        user.send(&SignalMessage::SessionJoin(SessionId::new(1)))
+            .await;
+        eventually(|| async { !server_state.one_to_one_sessions().read().await.is_empty() }).await;
+
+        server_state.request_shutdown();
+        tokio::time::timeout(TIMEOUT, server)
+            .await
+            .expect("server didn't stop after its shutdown timeout")
+            .expect("server panicked")
+            .expect("server failed");
+    }
+
+    #[tokio::test]
+    async fn test_plain_websocket_is_rejected() {
+        let (address, _certificate, _server) =
+            spawn_tls_server("plain", ServerState::default(), TIMEOUT).await;
+
+        let result = tokio_tungstenite::connect_async(format!("ws://{address}/one-to-one")).await;
+        assert!(result.is_err(), "plain connection should fail");
+    }
+
+    #[tokio::test]
+    async fn test_pem_errors_name_the_file() {
+        let certificate = rcgen::generate_simple_self_signed(vec!["localhost".to_owned()])
+            .expect("failed to generate certificate");
+        let cert = certificate
+            .serialize_pem()
+            .expect("failed to serialize certificate");
+        // certificate given in place of the key
+        let (cert_path, key_path) = write_pem_files("swapped", &cert, &cert);
+
+        let err = router::load_tls_config(&cert_path, &key_path)
+            .await
+            .expect_err("certificate is not a key");
+        assert!(
+            err.to_string()
+                .starts_with(&format!("no private key found in {}", key_path.display())),
+            "unexpected error: {err}"
+        );
+
+        let missing = cert_path.with_file_name("missing.pem");
+        let err = router::load_tls_config(&missing, &key_path)
+            .await
+            .expect_err("certificate is missing");
+        assert!(
+            err.to_string().contains(&missing.display().to_string()),
+            "unexpected error: {err}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_plain_http_is_redirected_to_https() {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind listener");
+        let address = listener.local_addr().expect("listener has no address");
+        let server = axum::Server::from_tcp(listener)
+            .expect("failed to create server")
+            .serve(router::https_redirect(8443).into_make_service());
+        tokio::spawn(server);
+
+        let response = http_get(address, "/one-to-one?token=secret").await;
+        assert!(
+            response.starts_with("HTTP/1.1 308"),
+            "unexpected response: {response}"
+        );
+        assert!(
+            response.to_lowercase().contains(&format!(
+                "location: https://{}:8443/one-to-one?token=secret",
+                address.ip()
+            )),
+            "unexpected response: {response}"
+        );
+    }
+}
+
+mod admin {
+    use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+    use wasm_peers_signaling_server::admin::{KICKED_REASON, SESSION_CLOSED_REASON};
+
+    use super::*;
+
+    const ADMIN_TOKEN: &str = "admin-secret";
+
+    fn spawn_admin_server() -> (ServerState, SocketAddr) {
+        let server_state = ServerState::with_admin_token(ADMIN_TOKEN);
+        let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind listener");
+        let address = listener.local_addr().expect("listener has no address");
+        let server = axum::Server::from_tcp(listener)
+            .expect("failed to create server")
+            .serve(router::create(server_state.clone()).into_make_service());
+        tokio::spawn(server);
+        (server_state, address)
+    }
+
+    /// Sends a plain HTTP request with `token` in `Authorization` header, returning the status line.
+    async fn admin_request(
+        address: SocketAddr,
+        method: &str,
+        path: &str,
+        token: Option<&str>,
+    ) -> String {
+        admin_response(address, method, path, token)
+            .await
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .to_owned()
+    }
+
+    /// Same as [`admin_request`], but returning the whole response.
+    async fn admin_response(
+        address: SocketAddr,
+        method: &str,
+        path: &str,
+        token: Option<&str>,
+    ) -> String {
+        let mut stream = TcpStream::connect(address)
+            .await
+            .expect("failed to connect to server");
+        let authorization = token.map_or_else(String::new, |token| {
+            format!("Authorization: Bearer {token}\r\n")
+        });
+        let request = format!(
+            "{method} {path} HTTP/1.1\r\nHost: {address}\r\n{authorization}\
+             Content-Length: 0\r\nConnection: close\r\n\r\n"
+        );
+        tokio::io::AsyncWriteExt::write_all(&mut stream, request.as_bytes())
+            .await
+            .expect("failed to send request");
+        let mut response = String::new();
+        tokio::io::AsyncReadExt::read_to_string(&mut stream, &mut response)
+            .await
+            .expect("failed to read response");
+        response
+    }
+
+    /// Types of events of session 1, as listed by the admin endpoint.
+    async fn session_event_types(address: SocketAddr) -> Vec<String> {
+        let response = admin_response(
+            address,
+            "GET",
+            "/admin/sessions/1/events",
+            Some(ADMIN_TOKEN),
+        )
+        .await;
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+        let (_headers, body) = response
+            .split_once("\r\n\r\n")
+            .expect("response without body");
+        let events: Vec<serde_json::Value> =
+            serde_json::from_str(body).expect("failed to parse events");
+        events
+            .iter()
+            .map(|event| event["event_type"].as_str().unwrap_or_default().to_owned())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_requests_without_admin_token_are_unauthorized() {
+        let (_server_state, address) = spawn_admin_server();
+        for (method, path) in [
+            ("DELETE", "/admin/sessions/1"),
+            ("POST", "/admin/sessions/1/kick/1"),
+            ("GET", "/admin/redirect?to=wss://new.server.com"),
+            ("GET", "/admin/sessions/1/events"),
+        ] {
+            for token in [None, Some("wrong")] {
+                assert_eq!(
+                    admin_request(address, method, path, token).await,
+                    "HTTP/1.1 401 Unauthorized",
+                    "unexpected response to {method} {path} with {token:?}"
+                );
+            }
+        }
+
+        let (_server_state, address) = spawn_server();
+        assert_eq!(
+            admin_request(address, "DELETE", "/admin/sessions/1", Some("")).await,
+            "HTTP/1.1 401 Unauthorized"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_closed_session_is_removed_and_its_users_are_told() {
+        use one_to_one::SignalMessage;
+
+        let (server_state, address) = spawn_admin_server();
+        let session_id = SessionId::new(1);
+        let mut first = Client::connect(address, "one-to-one").await;
+        let mut second = Client::connect(address, "one-to-one").await;
+        first.send(&SignalMessage::SessionJoin(session_id)).await;
+        first.assert_no_message().await;
+        second.send(&SignalMessage::SessionJoin(session_id)).await;
+        for user in [&mut first, &mut second] {
+            assert!(matches!(
+                user.recv().await,
+                SignalMessage::SessionReady(ready_session_id, _) if ready_session_id == session_id
+            ));
+        }
+
+        assert_eq!(
+            admin_request(address, "DELETE", "/admin/sessions/1", Some(ADMIN_TOKEN)).await,
+            "HTTP/1.1 204 No Content"
+        );
+        for user in [&mut first, &mut second] {
+            assert_eq!(
+                user.recv::<SignalMessage>().await,
+                SignalMessage::Error(session_id, SESSION_CLOSED_REASON.to_owned())
+            );
+        }
+        assert!(server_state.one_to_one_sessions().read().await.is_empty());
+        assert_eq!(
+            admin_request(address, "DELETE", "/admin/sessions/1", Some(ADMIN_TOKEN)).await,
+            "HTTP/1.1 404 Not Found"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_kicked_user_is_disconnected() {
+        use one_to_many::SignalMessage;
+
+        let (_server_state, address) = spawn_admin_server();
+        let session_id = SessionId::new(1);
+        let mut host = Client::connect(address, "one-to-many").await;
+        let mut client = Client::connect(address, "one-to-many").await;
+        host.send(&SignalMessage::SessionJoin(session_id, true))
+            .await;
+        client
+            .send(&SignalMessage::SessionJoin(session_id, false))
+            .await;
+        let SignalMessage::SessionReady(_, client_id) = host.recv().await else {
+            panic!("expected SessionReady");
+        };
+        let _host_ready: SignalMessage = client.recv().await;
+
+        let path = format!("/admin/sessions/1/kick/{}", client_id.into_inner());
+        assert_eq!(
+            admin_request(address, "POST", &path, Some(ADMIN_TOKEN)).await,
+            "HTTP/1.1 204 No Content"
+        );
+        assert_eq!(
+            client.recv::<SignalMessage>().await,
+            SignalMessage::Error(session_id, client_id, KICKED_REASON.to_owned())
+        );
+        let message = tokio::time::timeout(TIMEOUT, client.ws.next())
+            .await
+            .expect("connection wasn't closed in time")
+            .expect("connection closed without close frame")
+            .expect("websocket error");
+        assert!(
+            matches!(
+                message,
+                Message::Close(Some(ref frame))
+                    if frame.code == CloseCode::Policy && frame.reason == KICKED_REASON
+            ),
+            "unexpected message: {message:?}"
+        );
+        // reading further replies to the close frame
+        while client.ws.next().await.is_some() {}
+        assert_eq!(
+            host.recv::<SignalMessage>().await,
+            SignalMessage::SessionLeave(session_id, client_id)
+        );
+        assert_eq!(
+            admin_request(address, "POST", &path, Some(ADMIN_TOKEN)).await,
+            "HTTP/1.1 404 Not Found"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_session_events_are_listed() {
+        use one_to_one::SignalMessage;
+
+        let (_server_state, address) = spawn_admin_server();
+        let session_id = SessionId::new(1);
+        assert!(session_event_types(address).await.is_empty());
+
+        let mut first = Client::connect(address, "one-to-one").await;
+        let mut second = Client::connect(address, "one-to-one").await;
+        first.send(&SignalMessage::SessionJoin(session_id)).await;
+        first.assert_no_message().await;
+        second.send(&SignalMessage::SessionJoin(session_id)).await;
+        for user in [&mut first, &mut second] {
+            let _ready: SignalMessage = user.recv().await;
+        }
+        first
+            .send(&SignalMessage::SdpOffer(session_id, OFFER.to_owned()))
+            .await;
+        let _offer: SignalMessage = second.recv().await;
+        assert_eq!(
+            session_event_types(address).await,
+            ["join", "join", "offer"]
+        );
+
+        first.close().await;
+        eventually(|| async {
+            session_event_types(address)
+                .await
+                .last()
+                .map(String::as_str)
+                == Some("leave")
+        })
+        .await;
+
+        assert_eq!(
+            admin_request(
+                address,
+                "GET",
+                "/admin/sessions/not-a-number/events",
+                Some(ADMIN_TOKEN)
+            )
+            .await,
+            "HTTP/1.1 400 Bad Request"
+        );
+    }
+}
+
+mod protocol_version {
+    use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+    use wasm_peers_protocol::{PROTOCOL_MAJOR_VERSION, PROTOCOL_MINOR_VERSION};
+    use wasm_peers_signaling_server::version::INCOMPATIBLE_VERSION_REASON;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_compatible_version_is_acknowledged() {
+        use one_to_one::SignalMessage;
+
+        let (_server_state, address) = spawn_server();
+        let mut user = Client::connect(address, "one-to-one").await;
+        user.send(&SignalMessage::ProtocolVersion {
+            major: PROTOCOL_MAJOR_VERSION,
+            minor: PROTOCOL_MINOR_VERSION.saturating_add(1),
+        })
+        .await;
+        assert_eq!(
+            user.recv::<SignalMessage>().await,
+            SignalMessage::ProtocolVersion {
+                major: PROTOCOL_MAJOR_VERSION,
+                minor: PROTOCOL_MINOR_VERSION,
+            }
+        );
+
+        user.send(&SignalMessage::SessionListRequest).await;
+        assert_eq!(
+            user.recv::<SignalMessage>().await,
+            SignalMessage::SessionListResponse(Vec::new())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_incompatible_version_is_refused() {
+        use one_to_many::SignalMessage;
+
+        let (_server_state, address) = spawn_server();
+        let mut user = Client::connect(address, "one-to-many").await;
+        user.send(&SignalMessage::ProtocolVersion {
+            major: PROTOCOL_MAJOR_VERSION.wrapping_add(1),
+            minor: 0,
+        })
+        .await;
+        assert_eq!(
+            user.recv::<SignalMessage>().await,
+            SignalMessage::ProtocolVersionMismatch {
+                server_major: PROTOCOL_MAJOR_VERSION,
+                server_minor: PROTOCOL_MINOR_VERSION,
+            }
+        );
+        let message = tokio::time::timeout(TIMEOUT, user.ws.next())
+            .await
+            .expect("connection wasn't closed in time")
+            .expect("connection closed without close frame")
+            .expect("websocket error");
+        assert!(
+            matches!(
+                message,
+                Message::Close(Some(ref frame))
+                    if frame.code == CloseCode::Policy && frame.reason == INCOMPATIBLE_VERSION_REASON
+            ),
+            "unexpected message: {message:?}"
+        );
+    }
+}
@@ -0,0 +1,224 @@
+//! Instances of the server sharing users and sessions through Redis, which is started in a container,
+//! so Docker has to be running.
+#![cfg(feature = "redis")]
+
+use std::net::{SocketAddr, TcpListener};
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::Router;
+use futures_util::{SinkExt, StreamExt};
+use redis::Commands;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use testcontainers::clients::Cli;
+use testcontainers::Container;
+use testcontainers_modules::redis::{Redis, REDIS_PORT};
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+use wasm_peers_protocol::{one_to_many, one_to_one, SessionId};
+use wasm_peers_signaling_server::config::ServerConfig;
+use wasm_peers_signaling_server::hub::{SessionPolicy, SignalingHub};
+use wasm_peers_signaling_server::redis::{self as shared, RedisRelay, RedisStore};
+use wasm_peers_signaling_server::router::{self, ServerState};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+const OFFER: &str = "v=0\r\no=- 1 1 IN IP4 127.0.0.1\r\ns=-\r\nt=0 0\r\n";
+const ANSWER: &str = "v=0\r\no=- 2 1 IN IP4 127.0.0.1\r\ns=-\r\nt=0 0\r\n";
+
+/// Starts Redis in a container, returning it along with its URL.
+fn start_redis(docker: &Cli) -> (Container<'_, Redis>, String) {
+    let container = docker.run(Redis);
+    let url = format!(
+        "redis://127.0.0.1:{}",
+        container.get_host_port_ipv4(REDIS_PORT)
+    );
+    (container, url)
+}
+
+fn serve(app: Router) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind listener");
+    let address = listener.local_addr().expect("listener has no address");
+    let server = axum::Server::from_tcp(listener)
+        .expect("failed to create server")
+        .serve(app.into_make_service());
+    tokio::spawn(server);
+    address
+}
+
+/// Starts an instance of the server on an ephemeral port, configured to use Redis at `redis_url`.
+fn spawn_instance(redis_url: &str) -> SocketAddr {
+    let config = ServerConfig {
+        redis_url: Some(redis_url.to_owned()),
+        ..ServerConfig::default()
+    };
+    let server_state = ServerState::from_config(&config).expect("failed to create server state");
+    serve(router::create(server_state))
+}
+
+struct Client {
+    ws: WebSocketStream<MaybeTlsStream<TcpStream>>,
+}
+
+impl Client {
+    async fn connect(address: SocketAddr, endpoint: &str) -> Self {
+        let (ws, _response) =
+            tokio_tungstenite::connect_async(format!("ws://{address}/{endpoint}"))
+                .await
+                .expect("failed to connect to signaling server");
+        Self { ws }
+    }
+
+    async fn send(&mut self, message: &impl Serialize) {
+        let message = rmp_serde::to_vec(message).expect("failed to serialize message");
+        self.ws
+            .send(Message::Binary(message))
+            .await
+            .expect("failed to send message");
+    }
+
+    async fn recv<M: DeserializeOwned>(&mut self) -> M {
+        let message = tokio::time::timeout(TIMEOUT, self.ws.next())
+            .await
+            .expect("no message received in time")
+            .expect("connection closed")
+            .expect("websocket error");
+        rmp_serde::from_slice(&message.into_data()).expect("failed to deserialize message")
+    }
+
+    async fn assert_no_message(&mut self) {
+        let message = tokio::time::timeout(Duration::from_millis(200), self.ws.next()).await;
+        assert!(message.is_err(), "unexpected message: {message:?}");
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_one_to_one_session_spans_instances() {
+    use one_to_one::SignalMessage;
+
+    let docker = Cli::default();
+    let (_redis, redis_url) = start_redis(&docker);
+    let first_address = spawn_instance(&redis_url);
+    let second_address = spawn_instance(&redis_url);
+    let session_id = SessionId::new(1);
+
+    let mut first = Client::connect(first_address, "one-to-one").await;
+    let mut second = Client::connect(second_address, "one-to-one").await;
+    first.send(&SignalMessage::SessionJoin(session_id)).await;
+    first.assert_no_message().await;
+    second.send(&SignalMessage::SessionJoin(session_id)).await;
+    let mut hosts = 0;
+    for user in [&mut first, &mut second] {
+        let SignalMessage::SessionReady(ready_session_id, is_host) = user.recv().await else {
+            panic!("expected SessionReady");
+        };
+        assert_eq!(ready_session_id, session_id);
+        hosts += usize::from(is_host);
+    }
+    assert_eq!(hosts, 1, "exactly one user should be host");
+
+    first
+        .send(&SignalMessage::SdpOffer(session_id, OFFER.to_owned()))
+        .await;
+    assert_eq!(
+        second.recv::<SignalMessage>().await,
+        SignalMessage::SdpOffer(session_id, OFFER.to_owned())
+    );
+    second
+        .send(&SignalMessage::SdpAnswer(session_id, ANSWER.to_owned()))
+        .await;
+    assert_eq!(
+        first.recv::<SignalMessage>().await,
+        SignalMessage::SdpAnswer(session_id, ANSWER.to_owned())
+    );
+
+    // session is full on every instance
+    let mut third = Client::connect(first_address, "one-to-one").await;
+    third.send(&SignalMessage::SessionJoin(session_id)).await;
+    assert!(matches!(
+        third.recv::<SignalMessage>().await,
+        SignalMessage::Error(error_session_id, _) if error_session_id == session_id
+    ));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_one_to_many_clients_of_host_on_other_instance() {
+    use one_to_many::SignalMessage;
+
+    let docker = Cli::default();
+    let (_redis, redis_url) = start_redis(&docker);
+    let first_address = spawn_instance(&redis_url);
+    let second_address = spawn_instance(&redis_url);
+    let session_id = SessionId::new(1);
+
+    let mut host = Client::connect(first_address, "one-to-many").await;
+    host.send(&SignalMessage::SessionJoin(session_id, true))
+        .await;
+    host.assert_no_message().await;
+
+    let mut client_ids = Vec::new();
+    for address in [first_address, second_address] {
+        let mut client = Client::connect(address, "one-to-many").await;
+        client
+            .send(&SignalMessage::SessionJoin(session_id, false))
+            .await;
+        let SignalMessage::SessionReady(_, client_id) = host.recv().await else {
+            panic!("expected SessionReady for host");
+        };
+        let SignalMessage::SessionReady(_, host_id) = client.recv().await else {
+            panic!("expected SessionReady for client");
+        };
+        assert_ne!(client_id, host_id);
+
+        host.send(&SignalMessage::SdpOffer(
+            session_id,
+            client_id,
+            OFFER.to_owned(),
+        ))
+        .await;
+        assert_eq!(
+            client.recv::<SignalMessage>().await,
+            SignalMessage::SdpOffer(session_id, host_id, OFFER.to_owned())
+        );
+        client_ids.push(client_id);
+    }
+    // user ids are allocated by all instances together
+    assert_ne!(client_ids[0], client_ids[1]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_session_expires_after_its_ttl() {
+    use one_to_one::SignalMessage;
+    use wasm_peers_signaling_server::one_to_one::Policy;
+
+    let docker = Cli::default();
+    let (_redis, redis_url) = start_redis(&docker);
+    let prefix = shared::key_prefix(Policy::TOPOLOGY);
+    let store = RedisStore::new(&redis_url, prefix.clone())
+        .expect("invalid Redis URL")
+        .with_ttl(Duration::from_secs(1));
+    let policy = Policy::default()
+        .with_store(Arc::new(store))
+        .expect("failed to use store");
+    let relay = RedisRelay::new(&redis_url, prefix.clone()).expect("invalid Redis URL");
+    let hub = SignalingHub::new(policy).with_relay(Arc::new(relay));
+    let address = serve(Router::new().route("/one-to-one", hub.method_router()));
+    let session_id = SessionId::new(1);
+
+    let mut user = Client::connect(address, "one-to-one").await;
+    user.send(&SignalMessage::SessionJoin(session_id)).await;
+    user.assert_no_message().await;
+
+    let mut redis = redis::Client::open(redis_url.as_str())
+        .expect("invalid Redis URL")
+        .get_connection()
+        .expect("failed to connect to Redis");
+    let key = format!("{prefix}:session:{}", session_id.inner());
+    let exists: bool = redis.exists(&key).expect("failed to read session");
+    assert!(exists, "session should be saved in Redis");
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    let exists: bool = redis.exists(&key).expect("failed to read session");
+    assert!(!exists, "session should expire");
+}
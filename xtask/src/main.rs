@@ -18,16 +18,47 @@ enum Command {
     Fmt,
     Check,
     Clippy,
-    Run,
+    /// Runs signaling server, passing on arguments after `--`, e.g. `cargo xtask run -- --port 9002`
+    Run {
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
     Test,
+    /// Runs ignored throughput and latency benchmarks of the library in headless Chrome
+    Bench,
+    /// Measures WASM size of the library for each combination of features in `SIZE_FEATURES`,
+    /// writing them into crate documentation
+    Size {
+        /// Fails instead of writing sizes if the documented ones are off by more than `SIZE_TOLERANCE_PERCENT`
+        #[arg(long)]
+        check: bool,
+    },
     Doc,
     PreCommit,
-    PublishDocker { tag: String },
-    PublishAws { tag: String },
+    PublishDocker {
+        tag: String,
+    },
+    PublishAws {
+        tag: String,
+    },
 }
 
 const AWS_PUBLIC_ECR_ACCOUNT_URI: &str = "public.ecr.aws/2j7p7g8d";
 
+/// Features of the library measured by `size` command, each built with `--no-default-features`
+const SIZE_FEATURES: [&str; 6] = [
+    "one-to-one",
+    "one-to-one,quiet",
+    "one-to-many",
+    "many-to-many",
+    "one-to-one,one-to-many,many-to-many,session-bootstrap",
+    "default",
+];
+
+/// Documented sizes are stale once measured ones differ by more than this, smaller differences
+/// come with updates of dependencies and of the compiler, as `Cargo.lock` isn't committed
+const SIZE_TOLERANCE_PERCENT: u64 = 5;
+
 fn main() -> Result<()> {
     color_eyre::install()?;
     let cli = Cli::parse();
@@ -37,8 +68,10 @@ fn main() -> Result<()> {
         Command::Fmt => fmt(&sh)?,
         Command::Check => check(&sh)?,
         Command::Clippy => clippy(&sh)?,
-        Command::Run => run(&sh)?,
+        Command::Run { args } => run(&sh, args)?,
         Command::Test => test(&sh)?,
+        Command::Bench => bench(&sh)?,
+        Command::Size { check } => size(&sh, *check)?,
         Command::Doc => doc(&sh)?,
         Command::PreCommit => pre_commit(&sh)?,
         Command::PublishDocker { tag } => publish_docker(&sh, tag)?,
@@ -60,8 +93,12 @@ fn clippy(sh: &Shell) -> Result<()> {
     Ok(cmd!(sh, "cargo clippy --all-targets --all-features --workspace").run()?)
 }
 
-fn run(sh: &Shell) -> Result<()> {
-    Ok(cmd!(sh, "cargo run --package wasm-peers-signaling-server").run()?)
+fn run(sh: &Shell, args: &[String]) -> Result<()> {
+    Ok(cmd!(
+        sh,
+        "cargo run --package wasm-peers-signaling-server -- {args...}"
+    )
+    .run()?)
 }
 
 fn test(sh: &Shell) -> Result<()> {
@@ -69,21 +106,137 @@ fn test(sh: &Shell) -> Result<()> {
     let mut server = process::Command::new("./target/debug/wasm-peers-signaling-server")
         .current_dir(project_root::get_project_root()?)
         .spawn()?;
+    // peer for browser tests checking interoperability with native peers
+    cmd!(
+        sh,
+        "cargo build --package wasm-peers --example native_pong --features native"
+    )
+    .run()?;
+    let mut native_peer = process::Command::new("./target/debug/examples/native_pong")
+        .current_dir(project_root::get_project_root()?)
+        .spawn()?;
 
     let result = || -> Result<()> {
         let current_dir = sh.current_dir();
         sh.change_dir(project_root::get_project_root()?.join("library/"));
         cmd!(sh, "wasm-pack test --headless --firefox").run()?;
         cmd!(sh, "wasm-pack test --headless --chrome").run()?;
+        cmd!(
+            sh,
+            "wasm-pack test --headless --firefox -- --features json-messages"
+        )
+        .run()?;
+        cmd!(
+            sh,
+            "wasm-pack test --headless --firefox -- --features netem --test netem"
+        )
+        .run()?;
+        cmd!(
+            sh,
+            "wasm-pack test --headless --firefox -- --features session-bootstrap --test session_bootstrap"
+        )
+        .run()?;
         sh.change_dir(current_dir);
+        cmd!(
+            sh,
+            "cargo test --package wasm-peers --features native --test native"
+        )
+        .run()?;
+        cmd!(sh, "cargo test --package wasm-peers-signaling-server").run()?;
+        // starts Redis with Docker
+        cmd!(
+            sh,
+            "cargo test --package wasm-peers-signaling-server --features redis --test redis"
+        )
+        .run()?;
         Ok(())
     }();
 
+    native_peer.kill()?;
     server.kill()?;
 
     result
 }
 
+fn bench(sh: &Shell) -> Result<()> {
+    sh.change_dir(project_root::get_project_root()?.join("library/"));
+    Ok(cmd!(
+        sh,
+        "wasm-pack test --headless --chrome --release -- --test bench -- --include-ignored"
+    )
+    .run()?)
+}
+
+fn size(sh: &Shell, check: bool) -> Result<()> {
+    let root = project_root::get_project_root()?;
+    let wasm = root.join("target/wasm32-unknown-unknown/release/wasm_peers.wasm");
+    let sizes_path = root.join("library/src/sizes.md");
+    let twiggy = cmd!(sh, "twiggy --version")
+        .quiet()
+        .ignore_stdout()
+        .run()
+        .is_ok();
+
+    let mut measured = Vec::new();
+    let mut table = String::from(
+        "Sizes of the library built in release mode for `wasm32-unknown-unknown` with `--no-default-features`,\n\
+         before `wasm-opt` and compression, generated with `cargo xtask size`.\n\n\
+         | Features | Size |\n\
+         |---|---|\n",
+    );
+    for features in SIZE_FEATURES {
+        cmd!(
+            sh,
+            "cargo build --package wasm-peers --lib --release --target wasm32-unknown-unknown --no-default-features --features {features}"
+        )
+        .run()?;
+        let bytes = std::fs::metadata(&wasm)?.len();
+        let kib = (bytes + 512) / 1024;
+        table.push_str(&format!("| `{features}` | {kib} KiB |\n"));
+        measured.push((features.to_owned(), kib));
+        if twiggy {
+            cmd!(sh, "twiggy top -n 20 {wasm}").run()?;
+        }
+    }
+
+    println!("{table}");
+    if check {
+        let documented = documented_sizes(&sh.read_file(&sizes_path)?);
+        let stale = documented.len() != measured.len()
+            || documented.iter().zip(&measured).any(
+                |(&(ref documented_features, documented_kib), &(ref features, kib))| {
+                    documented_features != features
+                        || documented_kib.abs_diff(kib) * 100
+                            > documented_kib * SIZE_TOLERANCE_PERCENT
+                },
+            );
+        if stale {
+            return Err(color_eyre::eyre::eyre!(
+                "sizes in library/src/sizes.md are stale, run `cargo xtask size` and commit them"
+            ));
+        }
+        return Ok(());
+    }
+    sh.write_file(sizes_path, table)?;
+    Ok(())
+}
+
+/// Features and sizes in KiB from rows of the table written by `size` command.
+fn documented_sizes(sizes: &str) -> Vec<(String, u64)> {
+    sizes
+        .lines()
+        .filter_map(|line| {
+            let mut cells = line
+                .split('|')
+                .map(str::trim)
+                .filter(|cell| !cell.is_empty());
+            let features = cells.next()?.strip_prefix('`')?.strip_suffix('`')?;
+            let kib = cells.next()?.strip_suffix(" KiB")?.parse().ok()?;
+            Some((features.to_owned(), kib))
+        })
+        .collect()
+}
+
 fn doc(sh: &Shell) -> Result<()> {
     Ok(cmd!(sh, "cargo doc --no-deps --all-features").run()?)
 }